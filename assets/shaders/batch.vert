@@ -0,0 +1,33 @@
+#version 450
+
+// world positions of the vertices, already transformed by the CPU
+layout(location = 0) in vec2 world_pos;
+layout(location = 1) in vec2 tex_coords;
+layout(location = 2) in vec4 color;
+
+// color and texture coordinates for the fragment shader
+layout(location = 0) out vec4 frag_color;
+layout(location = 1) out vec2 frag_tex_coords;
+
+// Data passed by the Graphics Handler
+layout(set = 0, binding = 1) uniform readonly GlobalData {
+    uvec4 window_size;
+    vec4 camera_position;
+    vec4 camera_scale;
+    vec4 camera_rotation; // cosine and sine of the camera rotation
+} global_data;
+
+
+void main() {
+    frag_color = color;
+    frag_tex_coords = tex_coords;
+
+    // the world turns the opposite way of the camera
+    vec2 offset = world_pos - global_data.camera_position.xy;
+    vec2 cam_rot = global_data.camera_rotation.xy;
+    vec2 view_position = vec2(offset.x * cam_rot.x + offset.y * cam_rot.y, offset.y * cam_rot.x - offset.x * cam_rot.y);
+
+    vec2 rel_position = view_position / (global_data.window_size.xy * global_data.camera_scale.xy);
+
+    gl_Position = vec4(rel_position, 0.0, 1.0);
+}
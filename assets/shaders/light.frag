@@ -0,0 +1,80 @@
+#version 450
+
+// same limits as the Light Layer object
+#define MAX_LIGHTS 32
+#define MAX_SEGMENTS 256
+
+layout(location = 0) in vec2 world_pos;
+
+layout(location = 0) out vec4 f_color;
+
+struct LightData {
+    vec4 position_radius; // position, radius and falloff exponent
+    vec4 color; // color times intensity, alpha is 1.0 for lights casting shadows
+    vec4 cone; // direction, cosines of the outer and inner edges (-2.0 and -1.0 for point lights)
+};
+
+// Data passed by the Light Layer object
+layout(set = 0, binding = 0) uniform readonly LightingData {
+    vec4 ambient;
+    uvec4 counts; // lights and occluder segments
+    LightData lights[MAX_LIGHTS];
+    vec4 segments[MAX_SEGMENTS]; // start and end of the occluder edges
+} lighting;
+
+
+float cross2(vec2 a, vec2 b) {
+    return a.x * b.y - a.y * b.x;
+}
+
+// true if an occluder edge crosses the way from the light to the fragment
+bool blocked(vec2 from, vec2 to) {
+    vec2 ray = to - from;
+
+    for (uint i = 0; i < lighting.counts.y; i++) {
+        vec2 start = lighting.segments[i].xy;
+        vec2 edge = lighting.segments[i].zw - start;
+
+        float denominator = cross2(ray, edge);
+        if (abs(denominator) < 1e-6) {
+            continue;
+        }
+
+        vec2 offset = start - from;
+        float t = cross2(offset, edge) / denominator;
+        float u = cross2(offset, ray) / denominator;
+        if (t > 0.0 && t < 1.0 && u >= 0.0 && u <= 1.0) {
+            return true;
+        }
+    }
+
+    return false;
+}
+
+
+void main() {
+    vec3 light = lighting.ambient.rgb;
+
+    for (uint i = 0; i < lighting.counts.x; i++) {
+        LightData data = lighting.lights[i];
+
+        vec2 to_fragment = world_pos - data.position_radius.xy;
+        float dist = length(to_fragment);
+        float radius = data.position_radius.z;
+        if (dist >= radius) {
+            continue;
+        }
+
+        float attenuation = pow(1.0 - dist / radius, data.position_radius.w);
+        float cosine = dist > 0.0 ? dot(to_fragment / dist, data.cone.xy) : 1.0;
+        attenuation *= smoothstep(data.cone.z, data.cone.w, cosine);
+
+        if (attenuation <= 0.0 || (data.color.a > 0.5 && blocked(data.position_radius.xy, world_pos))) {
+            continue;
+        }
+        light += data.color.rgb * attenuation;
+    }
+
+    // multiplied with the scene colors by the blending
+    f_color = vec4(min(light, vec3(1.0)), 1.0);
+}
@@ -0,0 +1,25 @@
+#version 450
+
+// corners of the screen, in normalized device coordinates
+layout(location = 0) in vec2 vert_pos;
+
+// world position under the fragment
+layout(location = 0) out vec2 world_pos;
+
+// Data passed by the Graphics Handler
+layout(set = 0, binding = 1) uniform readonly GlobalData {
+    uvec4 window_size;
+    vec4 camera_position;
+    vec4 camera_scale;
+    vec4 camera_rotation; // cosine and sine of the camera rotation
+} global_data;
+
+
+void main() {
+    // inverse of the world to screen transform of the other shaders
+    vec2 view_position = vert_pos * (global_data.window_size.xy * global_data.camera_scale.xy);
+    vec2 cam_rot = global_data.camera_rotation.xy;
+    world_pos = global_data.camera_position.xy + vec2(view_position.x * cam_rot.x - view_position.y * cam_rot.y, view_position.x * cam_rot.y + view_position.y * cam_rot.x);
+
+    gl_Position = vec4(vert_pos, 0.0, 1.0);
+}
@@ -18,6 +18,7 @@ layout(set = 0, binding = 1) uniform readonly GlobalData {
     uvec4 window_size;
     vec4 camera_position;
     vec4 camera_scale;
+    vec4 camera_rotation; // cosine and sine of the camera rotation
 } global_data;
 
 
@@ -26,7 +27,12 @@ void main() {
 
     vec4 vertex_global_position = primitive_data.global_position + (vec4(vert_pos, 0.0, 0.0) * primitive_data.scale);
 
-    vec2 rel_position = (vertex_global_position.xy - global_data.camera_position.xy) / (global_data.window_size.xy * global_data.camera_scale.xy);
+    // the world turns the opposite way of the camera
+    vec2 offset = vertex_global_position.xy - global_data.camera_position.xy;
+    vec2 cam_rot = global_data.camera_rotation.xy;
+    vec2 view_position = vec2(offset.x * cam_rot.x + offset.y * cam_rot.y, offset.y * cam_rot.x - offset.x * cam_rot.y);
+
+    vec2 rel_position = view_position / (global_data.window_size.xy * global_data.camera_scale.xy);
 
     gl_Position = vec4(rel_position, 0.0, 1.0);
 }
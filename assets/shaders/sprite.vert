@@ -13,6 +13,7 @@ layout(set = 0, binding = 1) uniform readonly SpriteData {
     vec4 global_position;
     vec4 scale;
     uvec4 image_dimensions;
+    vec4 tex_region; // min and max texture coordinates of the region drawn
 } sprite_data;
 
 // Data passed by the Graphics Handler
@@ -20,16 +21,22 @@ layout(set = 0, binding = 2) uniform readonly GlobalData {
     uvec4 window_size;
     vec4 camera_position;
     vec4 camera_scale;
+    vec4 camera_rotation; // cosine and sine of the camera rotation
 } global_data;
 
 
 void main() {
     frag_color = sprite_data.color; // pass the sprite color to the fragment shader
-    tex_coords = clamp(vert_pos, 0.0, 1.0); // texture coordinates can't be negative
+    tex_coords = mix(sprite_data.tex_region.xy, sprite_data.tex_region.zw, clamp(vert_pos, 0.0, 1.0)); // texture coordinates can't be negative
 
     vec4 vertex_global_position = sprite_data.global_position + (sprite_data.image_dimensions * vec4(vert_pos, 0.0, 0.0) * sprite_data.scale);
 
-    vec2 rel_position = (vertex_global_position.xy - global_data.camera_position.xy) / (global_data.window_size.xy * global_data.camera_scale.xy);
+    // the world turns the opposite way of the camera
+    vec2 offset = vertex_global_position.xy - global_data.camera_position.xy;
+    vec2 cam_rot = global_data.camera_rotation.xy;
+    vec2 view_position = vec2(offset.x * cam_rot.x + offset.y * cam_rot.y, offset.y * cam_rot.x - offset.x * cam_rot.y);
+
+    vec2 rel_position = view_position / (global_data.window_size.xy * global_data.camera_scale.xy);
 
     gl_Position = vec4(rel_position, 0.0, 1.0);
 }
@@ -0,0 +1,100 @@
+//! Asset baking tool: packs an asset directory into a single archive read by the engine
+//!
+//! ```text
+//! magma-pack <asset directory> <archive> [--level 0-9]
+//! magma-pack --list <archive>
+//! ```
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use pholidota::engine::pack::{self, PackArchive};
+
+const USAGE: &str = "Usage:
+    magma-pack <asset directory> <archive> [--level 0-9]
+    magma-pack --list <archive>";
+
+/// Default deflate level, favouring size over baking speed
+const DEFAULT_LEVEL: u32 = 9;
+
+fn bake(args: &[String]) -> Result<(), String> {
+    let mut paths = Vec::new();
+    let mut level = DEFAULT_LEVEL;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--level" => {
+                level = args
+                    .next()
+                    .and_then(|level| level.parse::<u32>().ok())
+                    .filter(|level| *level <= 9)
+                    .ok_or("The level must be a number from 0 to 9")?;
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    if paths.len() != 2 {
+        return Err(USAGE.to_string());
+    }
+    let (directory, output) = (Path::new(paths[0]), Path::new(paths[1]));
+    if !directory.is_dir() {
+        return Err(format!("'{}' isn't a directory", directory.display()));
+    }
+
+    let report = pack::bake(directory, output, level)?;
+    let ratio = if report.size > 0 {
+        report.packed_size as f64 / report.size as f64 * 100.0
+    } else {
+        100.0
+    };
+    println!(
+        "Packed {} files ({} duplicates) into '{}': {} -> {} bytes ({:.1}%)",
+        report.files,
+        report.duplicates,
+        output.display(),
+        report.size,
+        report.packed_size,
+        ratio
+    );
+    Ok(())
+}
+
+fn list(archive: &Path) -> Result<(), String> {
+    let archive = PackArchive::open(archive)?;
+
+    let mut entries: Vec<_> = archive.entries().collect();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    for entry in entries {
+        println!(
+            "{:>10} {:>10} {} {}{}",
+            entry.size,
+            entry.packed_size,
+            entry.hash,
+            entry.path,
+            if entry.compressed { "" } else { " (stored)" }
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.first().map(|arg| arg.as_str()) {
+        Some("--list") if args.len() == 2 => list(Path::new(&args[1])),
+        Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(_) => bake(&args),
+        None => Err(USAGE.to_string()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
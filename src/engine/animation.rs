@@ -0,0 +1,480 @@
+//! Animation timelines shared by the animation systems
+
+// standard imports
+use std::collections::HashMap;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+// import the event bus
+use super::events::{EngineEvent, EventBus};
+
+// import the engine modules
+use super::ctx::draw_objects::SpriteObject;
+use super::ecs::World;
+use super::geometry::Rect;
+use super::pack;
+
+/// Named marker placed on a frame of an animation timeline
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnimationMarker {
+    pub frame: usize,
+    pub name: String,
+}
+
+/// Markers of an animation firing named events (footsteps, hit frames, VFX spawns...)
+#[derive(Clone, Debug, Default)]
+pub struct AnimationEventTrack {
+    /// Always sorted by frame
+    markers: Vec<AnimationMarker>,
+}
+
+impl AnimationEventTrack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a track from text, one `<frame> <event name>` marker per line (`#` starts a comment)
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut track = Self::new();
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let frame = parts
+                .next()
+                .and_then(|f| f.parse::<usize>().ok())
+                .ok_or_else(|| format!("Invalid frame on line {}", number + 1))?;
+            let name = parts
+                .next()
+                .map(str::trim)
+                .filter(|n| !n.is_empty())
+                .ok_or_else(|| format!("Missing event name on line {}", number + 1))?;
+
+            track.add_marker(frame, name);
+        }
+
+        Ok(track)
+    }
+
+    /// Load a track from a file in the format read by `parse`
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let source = pack::read_to_string(path)?;
+
+        Self::parse(&source).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn add_marker(&mut self, frame: usize, name: &str) {
+        let index = self.markers.partition_point(|m| m.frame <= frame);
+        self.markers.insert(
+            index,
+            AnimationMarker {
+                frame,
+                name: name.to_string(),
+            },
+        );
+    }
+
+    pub fn markers(&self) -> &[AnimationMarker] {
+        &self.markers
+    }
+
+    /// Markers reached going from `previous` (already fired) to `current`, in order.
+    /// `loops` is how many times the animation wrapped around its `frame_count` meanwhile.
+    pub fn crossed(
+        &self,
+        previous: usize,
+        current: usize,
+        loops: usize,
+        frame_count: usize,
+    ) -> Vec<&AnimationMarker> {
+        if loops == 0 {
+            return self
+                .markers
+                .iter()
+                .filter(|m| m.frame > previous && m.frame <= current)
+                .collect();
+        }
+
+        // End of the current loop, every full loop in between, then the start of the last one
+        let mut crossed: Vec<&AnimationMarker> = self
+            .markers
+            .iter()
+            .filter(|m| m.frame > previous && m.frame < frame_count)
+            .collect();
+        for _ in 1..loops {
+            crossed.extend(self.markers.iter().filter(|m| m.frame < frame_count));
+        }
+        crossed.extend(self.markers.iter().filter(|m| m.frame <= current));
+
+        crossed
+    }
+
+    /// Send the crossed markers through the event bus as `AnimationEvent`s of the given source
+    pub fn fire(
+        &self,
+        bus: &mut EventBus,
+        source: u64,
+        previous: usize,
+        current: usize,
+        loops: usize,
+        frame_count: usize,
+    ) {
+        for marker in self.crossed(previous, current, loops, frame_count) {
+            bus.push(EngineEvent::AnimationEvent {
+                source,
+                name: marker.name.clone(),
+                frame: marker.frame,
+            });
+        }
+    }
+}
+
+/// Timing of an animation played at a fixed frame rate, firing the markers of its track as it goes
+#[derive(Clone, Debug)]
+pub struct AnimationPlayback {
+    /// Identifier reported in the fired events
+    pub source: u64,
+    pub track: AnimationEventTrack,
+    pub frame_count: usize,
+    pub frame_duration: Duration,
+    pub looping: bool,
+    elapsed: Duration,
+    frame: usize,
+}
+
+impl AnimationPlayback {
+    pub fn new(source: u64, frame_count: usize, frame_duration: Duration, looping: bool) -> Self {
+        Self {
+            source,
+            track: AnimationEventTrack::new(),
+            frame_count: frame_count.max(1),
+            frame_duration,
+            looping,
+            elapsed: Duration::from_secs(0),
+            frame: 0,
+        }
+    }
+
+    pub fn with_track(mut self, track: AnimationEventTrack) -> Self {
+        self.track = track;
+        self
+    }
+
+    /// Go back to the first frame, firing its markers
+    pub fn restart(&mut self, bus: &mut EventBus) {
+        self.elapsed = Duration::from_secs(0);
+        self.frame = 0;
+
+        for marker in self.track.markers().iter().filter(|m| m.frame == 0) {
+            bus.push(EngineEvent::AnimationEvent {
+                source: self.source,
+                name: marker.name.clone(),
+                frame: 0,
+            });
+        }
+    }
+
+    /// Move the animation forward, every marker crossed fires even if its frame was skipped
+    pub fn advance(&mut self, delta: Duration, bus: &mut EventBus) {
+        if self.is_finished() || self.frame_duration.as_nanos() == 0 {
+            return;
+        }
+
+        self.elapsed += delta;
+        let total = (self.elapsed.as_nanos() / self.frame_duration.as_nanos()) as usize;
+
+        let (frame, loops) = if self.looping {
+            (total % self.frame_count, total / self.frame_count)
+        } else {
+            (total.min(self.frame_count - 1), 0)
+        };
+
+        // Keep the elapsed time within the current loop
+        if loops > 0 {
+            self.elapsed -= self.frame_duration * (self.frame_count * loops) as u32;
+        }
+
+        if frame != self.frame || loops > 0 {
+            self.track
+                .fire(bus, self.source, self.frame, frame, loops, self.frame_count);
+            self.frame = frame;
+        }
+    }
+
+    pub fn get_frame(&self) -> usize {
+        self.frame
+    }
+
+    /// A non-looping animation stays on its last frame once finished
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.frame + 1 >= self.frame_count
+    }
+}
+
+/// Frame of a sprite animation: region of the atlas shown and for how long
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteFrame {
+    /// In pixels of the atlas texture
+    pub region: Rect,
+    pub duration: Duration,
+}
+
+/// Sequence of atlas regions shown one after the other, firing the markers of its track
+#[derive(Clone, Debug)]
+pub struct SpriteAnimation {
+    pub name: String,
+    pub frames: Vec<SpriteFrame>,
+    pub looping: bool,
+    pub track: AnimationEventTrack,
+}
+
+impl SpriteAnimation {
+    pub fn new(name: &str, looping: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            frames: Vec::new(),
+            looping,
+            track: AnimationEventTrack::new(),
+        }
+    }
+
+    /// Animation of the cells of an atlas grid, read row by row (`cells` counts from the top-left cell)
+    pub fn from_grid(
+        name: &str,
+        cell_size: (u32, u32),
+        columns: u32,
+        cells: Range<u32>,
+        frame_duration: Duration,
+        looping: bool,
+    ) -> Self {
+        let columns = columns.max(1);
+        let frames = cells
+            .map(|cell| SpriteFrame {
+                region: Rect::new(
+                    ((cell % columns) * cell_size.0) as f32,
+                    ((cell / columns) * cell_size.1) as f32,
+                    cell_size.0 as f32,
+                    cell_size.1 as f32,
+                ),
+                duration: frame_duration,
+            })
+            .collect();
+
+        Self {
+            frames,
+            ..Self::new(name, looping)
+        }
+    }
+
+    pub fn with_frame(mut self, region: Rect, duration: Duration) -> Self {
+        self.frames.push(SpriteFrame { region, duration });
+        self
+    }
+
+    pub fn with_track(mut self, track: AnimationEventTrack) -> Self {
+        self.track = track;
+        self
+    }
+
+    /// Length of a single play of the animation
+    pub fn duration(&self) -> Duration {
+        self.frames.iter().map(|frame| frame.duration).sum()
+    }
+}
+
+/// Component playing the animations of the `SpriteObject` of its entity.
+/// The engine advances it at every update and sets the region of the sprite
+#[derive(Clone, Debug)]
+pub struct SpriteAnimator {
+    /// Identifier reported in the fired events
+    pub source: u64,
+    animations: HashMap<String, SpriteAnimation>,
+    current: Option<String>,
+    frame: usize,
+    /// Time spent on the current frame
+    elapsed: Duration,
+    playing: bool,
+    finished: bool,
+    /// Markers of the first frame fire at the next advance
+    started: bool,
+    speed: f32,
+}
+
+impl SpriteAnimator {
+    pub fn new(source: u64) -> Self {
+        Self {
+            source,
+            animations: HashMap::new(),
+            current: None,
+            frame: 0,
+            elapsed: Duration::from_secs(0),
+            playing: false,
+            finished: false,
+            started: false,
+            speed: 1.0,
+        }
+    }
+
+    pub fn with_animation(mut self, animation: SpriteAnimation) -> Self {
+        self.add_animation(animation);
+        self
+    }
+
+    /// Add an animation, replacing the one with the same name
+    pub fn add_animation(&mut self, animation: SpriteAnimation) {
+        self.animations.insert(animation.name.clone(), animation);
+    }
+
+    /// Play an animation from its start, unless it's already playing
+    pub fn play(&mut self, name: &str) -> Result<(), String> {
+        if self.current.as_deref() == Some(name) && self.playing && !self.finished {
+            return Ok(());
+        }
+
+        self.set_animation(name)?;
+        self.restart();
+        Ok(())
+    }
+
+    /// Switch to another animation keeping the current frame and time (e.g. from walking to walking
+    /// while shooting), the first frame is used if the new animation is shorter
+    pub fn set_animation(&mut self, name: &str) -> Result<(), String> {
+        let animation = self
+            .animations
+            .get(name)
+            .ok_or_else(|| format!("Unknown sprite animation '{}'", name))?;
+
+        if self.frame >= animation.frames.len() {
+            self.frame = 0;
+            self.elapsed = Duration::from_secs(0);
+        }
+        self.current = Some(name.to_string());
+        self.playing = true;
+        self.finished = false;
+        Ok(())
+    }
+
+    /// Go back to the first frame of the current animation
+    pub fn restart(&mut self) {
+        self.frame = 0;
+        self.elapsed = Duration::from_secs(0);
+        self.finished = false;
+        self.started = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn resume(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing && !self.finished
+    }
+
+    /// An animation which doesn't loop stays on its last frame once finished
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Playback speed multiplier, 1.0 by default
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn current_animation(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    pub fn get_frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Atlas region of the frame shown
+    pub fn current_region(&self) -> Option<Rect> {
+        let animation = self.animations.get(self.current.as_ref()?)?;
+        animation.frames.get(self.frame).map(|frame| frame.region)
+    }
+
+    /// Move the animation forward, firing its markers and `AnimationFinished` at the end
+    pub fn advance(&mut self, delta: Duration, bus: &mut EventBus) {
+        let animations = &self.animations;
+        let animation = match self.current.as_ref().and_then(|name| animations.get(name)) {
+            Some(animation) => animation,
+            None => return,
+        };
+        if !self.playing || self.finished || animation.duration().as_nanos() == 0 {
+            return;
+        }
+
+        let frame_count = animation.frames.len();
+        if self.started {
+            self.started = false;
+            for marker in animation.track.markers().iter().filter(|m| m.frame == 0) {
+                bus.push(EngineEvent::AnimationEvent {
+                    source: self.source,
+                    name: marker.name.clone(),
+                    frame: 0,
+                });
+            }
+        }
+
+        let previous = self.frame;
+        let mut loops = 0;
+        self.elapsed += delta.mul_f32(self.speed);
+
+        while self.elapsed >= animation.frames[self.frame].duration {
+            self.elapsed -= animation.frames[self.frame].duration;
+
+            if self.frame + 1 < frame_count {
+                self.frame += 1;
+            } else if animation.looping {
+                self.frame = 0;
+                loops += 1;
+            } else {
+                self.elapsed = Duration::from_secs(0);
+                self.finished = true;
+                break;
+            }
+        }
+
+        if self.frame != previous || loops > 0 {
+            animation
+                .track
+                .fire(bus, self.source, previous, self.frame, loops, frame_count);
+        }
+        if self.finished {
+            bus.push(EngineEvent::AnimationFinished {
+                source: self.source,
+                animation: animation.name.clone(),
+            });
+        }
+    }
+}
+
+/// Advance the `SpriteAnimator` components and show their frame on the `SpriteObject`
+/// of the same entity (called by the engine at every update)
+pub(crate) fn advance_animators(world: &World, delta: f32, bus: &mut EventBus) {
+    let mut animators = match world.borrow_mut::<SpriteAnimator>() {
+        Some(animators) => animators,
+        None => return,
+    };
+    let sprites = world.borrow::<SpriteObject>();
+
+    for (entity, animator) in animators.iter_mut() {
+        animator.advance(Duration::from_secs_f32(delta.max(0.0)), bus);
+
+        let sprite = sprites.as_ref().and_then(|sprites| sprites.get(entity));
+        if let (Some(sprite), Some(region)) = (sprite, animator.current_region()) {
+            sprite.get_mut().set_region(region);
+        }
+    }
+}
@@ -0,0 +1,357 @@
+//! Central asset manager, loading every kind of resource by path through typed handles
+//!
+//! Loading the same path twice returns the same resource while a handle to it is alive,
+//! the resource is freed together with its last handle. Reloaded resources (see `hot_reload`)
+//! replace the old ones for every handle calling `Handle::refresh`.
+
+// standard imports
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+
+// import the ctx module
+use super::ctx::vulkan::Texture;
+use super::ctx::SoundEffect;
+use super::memory::{self, MemoryTag};
+use super::pack;
+#[cfg(feature = "serialization")]
+use super::prefab::Prefab;
+
+// other imports
+use cgmath::Vector2;
+
+/// Latest version of an asset, shared by all its handles
+struct AssetSlot<T> {
+    latest: RefCell<Rc<T>>,
+    version: Cell<u64>,
+}
+
+/// Shared reference to a loaded asset
+pub struct Handle<T> {
+    asset: Rc<T>,
+    /// Version of the asset this handle points to
+    version: u64,
+    slot: Rc<AssetSlot<T>>,
+    path: Rc<PathBuf>,
+}
+
+impl<T> Handle<T> {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Check if two handles refer to the same loaded asset, reloaded versions included
+    pub fn ptr_eq(&self, other: &Handle<T>) -> bool {
+        Rc::ptr_eq(&self.slot, &other.slot)
+    }
+
+    /// Check if the asset was reloaded since this handle was updated
+    pub fn is_stale(&self) -> bool {
+        self.version != self.slot.version.get()
+    }
+
+    /// Point to the latest version of the asset, returns true if it changed
+    pub fn refresh(&mut self) -> bool {
+        if !self.is_stale() {
+            return false;
+        }
+
+        self.asset = self.slot.latest.borrow().clone();
+        self.version = self.slot.version.get();
+        true
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            asset: self.asset.clone(),
+            version: self.version,
+            slot: self.slot.clone(),
+            path: self.path.clone(),
+        }
+    }
+}
+
+impl<T> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.asset
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({})", self.path.display())
+    }
+}
+
+/// Loaded assets of a single type, by path
+pub struct AssetCache<T> {
+    entries: HashMap<PathBuf, (Weak<AssetSlot<T>>, Rc<PathBuf>)>,
+}
+
+impl<T> AssetCache<T> {
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Handle to the asset if it's still loaded
+    pub fn get(&self, path: &Path) -> Option<Handle<T>> {
+        let (slot, path) = self.entries.get(path)?;
+        let slot = slot.upgrade()?;
+
+        let asset = slot.latest.borrow().clone();
+        Some(Handle {
+            asset,
+            version: slot.version.get(),
+            slot,
+            path: path.clone(),
+        })
+    }
+
+    /// Handle to the asset, loading it with `loader` if it isn't loaded yet
+    pub fn get_or_load<F>(&mut self, path: &Path, loader: F) -> Result<Handle<T>, String>
+    where
+        F: FnOnce(&Path) -> Result<T, String>,
+    {
+        if let Some(handle) = self.get(path) {
+            return Ok(handle);
+        }
+
+        let asset = Rc::new(loader(path)?);
+        let slot = Rc::new(AssetSlot {
+            latest: RefCell::new(asset.clone()),
+            version: Cell::new(0),
+        });
+        let path = Rc::new(path.to_path_buf());
+
+        self.collect();
+        self.entries
+            .insert(path.to_path_buf(), (Rc::downgrade(&slot), path.clone()));
+
+        Ok(Handle {
+            asset,
+            version: 0,
+            slot,
+            path,
+        })
+    }
+
+    /// Replace a loaded asset with a new version, the handles get it with `Handle::refresh`.
+    /// Returns false if the asset isn't loaded anymore
+    pub fn replace(&mut self, path: &Path, asset: T) -> bool {
+        let slot = match self.entries.get(path).and_then(|(slot, _)| slot.upgrade()) {
+            Some(slot) => slot,
+            None => return false,
+        };
+
+        slot.latest.replace(Rc::new(asset));
+        slot.version.set(slot.version.get() + 1);
+        true
+    }
+
+    pub fn is_loaded(&self, path: &Path) -> bool {
+        self.get(path).is_some()
+    }
+
+    /// Paths of the assets still loaded
+    pub fn loaded_paths(&self) -> Vec<PathBuf> {
+        self.entries
+            .iter()
+            .filter(|(_, (slot, _))| slot.strong_count() > 0)
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Forget the assets whose handles were all dropped
+    pub fn collect(&mut self) {
+        self.entries.retain(|_, (slot, _)| slot.strong_count() > 0);
+    }
+}
+
+impl<T> Default for AssetCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Texture on the GPU, ready to be used by sprites
+#[derive(Clone)]
+pub struct TextureAsset {
+    pub(crate) texture: Texture,
+    pub dimensions: Vector2<u32>,
+}
+
+/// Source code of a shader
+#[derive(Clone, Debug)]
+pub struct ShaderSource {
+    pub code: String,
+}
+
+/// Source code of a script (see `scripting`)
+#[derive(Clone, Debug)]
+pub struct ScriptSource {
+    pub code: String,
+}
+
+/// Raw font file (TTF/OTF), parsed by the text renderer
+#[derive(Clone, Debug)]
+pub struct FontData {
+    pub bytes: Vec<u8>,
+}
+
+/// 2D mesh read from a Wavefront OBJ file (z coordinates are ignored)
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// Parse the vertices and triangulated faces of an OBJ file
+    pub fn parse_obj(source: &str) -> Result<Mesh, String> {
+        let mut mesh = Mesh::default();
+
+        for (number, line) in source.lines().enumerate() {
+            let mut parts = line.split_whitespace();
+
+            match parts.next() {
+                Some("v") => {
+                    let coords: Vec<f32> = parts
+                        .take(2)
+                        .map(|c| c.parse::<f32>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|e| format!("Invalid vertex on line {}: {}", number + 1, e))?;
+                    if coords.len() < 2 {
+                        return Err(format!("Missing vertex coordinates on line {}", number + 1));
+                    }
+                    mesh.vertices.push([coords[0], coords[1]]);
+                }
+                Some("f") => {
+                    // Only the vertex index of "v/vt/vn" matters, polygons are split as fans
+                    let face: Vec<u32> = parts
+                        .map(|v| v.split('/').next().unwrap_or("").parse::<u32>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|e| format!("Invalid face on line {}: {}", number + 1, e))?;
+                    if face.len() < 3 || face.contains(&0) {
+                        return Err(format!("Invalid face on line {}", number + 1));
+                    }
+                    for i in 1..face.len() - 1 {
+                        mesh.indices
+                            .extend_from_slice(&[face[0] - 1, face[i] - 1, face[i + 1] - 1]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if mesh.indices.iter().any(|&i| i as usize >= mesh.vertices.len()) {
+            return Err(String::from("Face referencing a missing vertex"));
+        }
+
+        Ok(mesh)
+    }
+}
+
+pub(crate) fn read_shader(path: &Path) -> Result<ShaderSource, String> {
+    let _scope = memory::scope(MemoryTag::Assets);
+    pack::read_to_string(path)
+        .map(|code| ShaderSource { code })
+        .map_err(|e| format!("Couldn't load shader '{}': {}", path.display(), e))
+}
+
+pub(crate) fn read_script(path: &Path) -> Result<ScriptSource, String> {
+    let _scope = memory::scope(MemoryTag::Assets);
+    pack::read_to_string(path)
+        .map(|code| ScriptSource { code })
+        .map_err(|e| format!("Couldn't load script '{}': {}", path.display(), e))
+}
+
+/// Prefab in RON with the `.ron` extension, in JSON otherwise
+#[cfg(feature = "serialization")]
+pub(crate) fn read_prefab(path: &Path) -> Result<Prefab, String> {
+    let _scope = memory::scope(MemoryTag::Assets);
+    let source = pack::read_to_string(path)
+        .map_err(|e| format!("Couldn't load prefab '{}': {}", path.display(), e))?;
+    let prefab = match path.extension().and_then(|extension| extension.to_str()) {
+        #[cfg(feature = "ron-prefabs")]
+        Some("ron") => Prefab::parse_ron(&source),
+        #[cfg(not(feature = "ron-prefabs"))]
+        Some("ron") => Err(String::from("RON prefabs need the ron-prefabs feature")),
+        _ => Prefab::parse_json(&source),
+    };
+    prefab.map_err(|e| format!("Couldn't parse prefab '{}': {}", path.display(), e))
+}
+
+pub(crate) fn read_font(path: &Path) -> Result<FontData, String> {
+    let _scope = memory::scope(MemoryTag::Assets);
+    pack::read(path)
+        .map(|bytes| FontData { bytes })
+        .map_err(|e| format!("Couldn't load font '{}': {}", path.display(), e))
+}
+
+pub(crate) fn read_mesh(path: &Path) -> Result<Mesh, String> {
+    let _scope = memory::scope(MemoryTag::Assets);
+    let source = pack::read_to_string(path)
+        .map_err(|e| format!("Couldn't load mesh '{}': {}", path.display(), e))?;
+    Mesh::parse_obj(&source).map_err(|e| format!("Couldn't parse mesh '{}': {}", path.display(), e))
+}
+
+/// Caches of every kind of asset, owned by the Context
+#[derive(Default)]
+pub struct Assets {
+    pub textures: AssetCache<TextureAsset>,
+    pub sounds: AssetCache<SoundEffect>,
+    pub shaders: AssetCache<ShaderSource>,
+    pub fonts: AssetCache<FontData>,
+    pub meshes: AssetCache<Mesh>,
+    pub scripts: AssetCache<ScriptSource>,
+    #[cfg(feature = "serialization")]
+    pub prefabs: AssetCache<Prefab>,
+}
+
+impl Assets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load_shader(&mut self, path: &Path) -> Result<Handle<ShaderSource>, String> {
+        self.shaders.get_or_load(path, read_shader)
+    }
+
+    pub fn load_script(&mut self, path: &Path) -> Result<Handle<ScriptSource>, String> {
+        self.scripts.get_or_load(path, read_script)
+    }
+
+    #[cfg(feature = "serialization")]
+    pub fn load_prefab(&mut self, path: &Path) -> Result<Handle<Prefab>, String> {
+        self.prefabs.get_or_load(path, read_prefab)
+    }
+
+    pub fn load_font(&mut self, path: &Path) -> Result<Handle<FontData>, String> {
+        self.fonts.get_or_load(path, read_font)
+    }
+
+    pub fn load_mesh(&mut self, path: &Path) -> Result<Handle<Mesh>, String> {
+        self.meshes.get_or_load(path, read_mesh)
+    }
+
+    /// Forget every asset no longer in use
+    pub fn collect(&mut self) {
+        self.textures.collect();
+        self.sounds.collect();
+        self.shaders.collect();
+        self.fonts.collect();
+        self.meshes.collect();
+        self.scripts.collect();
+        #[cfg(feature = "serialization")]
+        self.prefabs.collect();
+    }
+}
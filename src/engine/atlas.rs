@@ -0,0 +1,337 @@
+//! Texture atlases packed at load time from separate images
+//!
+//! Artists keep one file per sprite, the `AtlasBuilder` bakes them into as few textures as
+//! possible: sprites sharing a texture are merged in the same batched draws.
+
+// standard imports
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::Path;
+
+// import the engine modules
+use super::ctx::draw_objects::SpriteObject;
+use super::ctx::vulkan::{GraphicsHandler, Texture};
+use super::ctx::{decode_png_rgba, VideoHandler};
+use super::error::MagmaError;
+use super::geometry::Rect;
+use super::pack;
+
+// other imports
+use cgmath::Vector2;
+
+/// Image waiting to be packed
+struct SourceImage {
+    name: String,
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+/// Place of an image in an atlas
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasRegion {
+    /// Index of the page (texture) holding the image
+    pub page: usize,
+    /// In pixels of the page
+    pub rect: Rect,
+}
+
+/// RGBA8 pixels of an atlas texture
+#[derive(Clone, Debug)]
+pub struct AtlasPage {
+    pub pixels: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Atlas packed on the CPU, not uploaded yet
+#[derive(Clone, Debug)]
+pub struct PackedAtlas {
+    pub pages: Vec<AtlasPage>,
+    pub regions: HashMap<String, AtlasRegion>,
+}
+
+/// Collect images, then pack them in atlas pages of at most `max_size` pixels per side
+pub struct AtlasBuilder {
+    label: String,
+    max_size: u32,
+    padding: u32,
+    images: Vec<SourceImage>,
+}
+
+impl AtlasBuilder {
+    /// `label` names the atlas textures in the caches and leak reports
+    pub fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            max_size: 2048,
+            padding: 1,
+            images: Vec::new(),
+        }
+    }
+
+    /// Largest side of a page, 2048 by default
+    pub fn max_size(mut self, max_size: u32) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Transparent pixels around every image, so filtering doesn't bleed the neighbours in (1 by default)
+    pub fn padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Add decoded RGBA8 pixels, replacing the image with the same name
+    pub fn add_image(
+        &mut self,
+        name: &str,
+        pixels: Vec<u8>,
+        width: u32,
+        height: u32,
+    ) -> Result<(), String> {
+        if pixels.len() != (width * height * 4) as usize {
+            return Err(format!(
+                "Image '{}' has {} bytes instead of {} for {}x{} RGBA8 pixels",
+                name,
+                pixels.len(),
+                width * height * 4,
+                width,
+                height
+            ));
+        }
+
+        self.images.retain(|image| image.name != name);
+        self.images.push(SourceImage {
+            name: name.to_string(),
+            pixels,
+            width,
+            height,
+        });
+        Ok(())
+    }
+
+    /// Add a PNG file, named by its path
+    pub fn add_file(&mut self, path: &Path) -> Result<(), String> {
+        let bytes = pack::read(path)
+            .map_err(|e| format!("Couldn't open image '{}': {}", path.display(), e))?;
+        let (pixels, width, height) = decode_png_rgba(Cursor::new(bytes))
+            .map_err(|e| format!("Couldn't decode image '{}': {}", path.display(), e))?;
+
+        self.add_image(&path.to_string_lossy(), pixels, width, height)
+    }
+
+    /// Add every PNG file of a directory (not recursively), named by their path
+    pub fn add_directory(&mut self, dir: &Path) -> Result<(), String> {
+        let entries = dir
+            .read_dir()
+            .map_err(|e| format!("Couldn't read directory '{}': {}", dir.display(), e))?;
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .map_or(false, |ext| ext.eq_ignore_ascii_case("png"))
+            })
+            .collect();
+        // Same packing on every platform
+        paths.sort();
+
+        for path in paths {
+            self.add_file(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Pack the images in pages, tallest first on shelves filled left to right
+    pub fn pack(self) -> Result<PackedAtlas, String> {
+        let padding = self.padding;
+        let mut images = self.images;
+        images.sort_by(|a, b| b.height.cmp(&a.height).then(b.width.cmp(&a.width)));
+
+        let mut packer = ShelfPacker::new(self.max_size);
+        let mut placed = Vec::with_capacity(images.len());
+        for image in &images {
+            let (width, height) = (image.width + padding * 2, image.height + padding * 2);
+            if width > self.max_size || height > self.max_size {
+                return Err(format!(
+                    "Image '{}' ({}x{}) doesn't fit in atlas pages of {} pixels",
+                    image.name, image.width, image.height, self.max_size
+                ));
+            }
+
+            placed.push(packer.insert(width, height));
+        }
+
+        // Pages trimmed to the space used
+        let mut pages: Vec<AtlasPage> = packer
+            .used
+            .iter()
+            .map(|&(width, height)| AtlasPage {
+                pixels: vec![0; (width * height * 4) as usize],
+                width,
+                height,
+            })
+            .collect();
+
+        let mut regions = HashMap::with_capacity(images.len());
+        for (image, (page_index, x, y)) in images.into_iter().zip(placed) {
+            let (x, y) = (x + padding, y + padding);
+            let page = &mut pages[page_index];
+
+            let row_bytes = (image.width * 4) as usize;
+            for row in 0..image.height {
+                let source = (row * image.width * 4) as usize;
+                let target = (((y + row) * page.width + x) * 4) as usize;
+                page.pixels[target..target + row_bytes]
+                    .copy_from_slice(&image.pixels[source..source + row_bytes]);
+            }
+
+            regions.insert(
+                image.name,
+                AtlasRegion {
+                    page: page_index,
+                    rect: Rect::new(x as f32, y as f32, image.width as f32, image.height as f32),
+                },
+            );
+        }
+
+        Ok(PackedAtlas { pages, regions })
+    }
+
+    /// Pack the images and upload the pages as textures
+    pub fn build(self, gl_handler: &GraphicsHandler) -> Result<TextureAtlas, String> {
+        let label = self.label.clone();
+        let packed = self.pack()?;
+
+        let mut pages = Vec::with_capacity(packed.pages.len());
+        for (index, page) in packed.pages.iter().enumerate() {
+            let path = format!("<atlas {}>/{}", label, index);
+            pages.push(gl_handler.create_texture(
+                Path::new(&path),
+                &page.pixels,
+                page.width,
+                page.height,
+            )?);
+        }
+
+        Ok(TextureAtlas {
+            label,
+            pages,
+            regions: packed.regions,
+        })
+    }
+}
+
+/// Shelves of rows filled left to right, a new page once the current one is full
+struct ShelfPacker {
+    max_size: u32,
+    /// Top, height and used width of the shelves of the current page
+    shelves: Vec<(u32, u32, u32)>,
+    /// Width and height used on every page
+    used: Vec<(u32, u32)>,
+}
+
+impl ShelfPacker {
+    fn new(max_size: u32) -> Self {
+        Self {
+            max_size,
+            shelves: Vec::new(),
+            used: Vec::new(),
+        }
+    }
+
+    /// Page and top-left corner of a new rectangle
+    fn insert(&mut self, width: u32, height: u32) -> (usize, u32, u32) {
+        if self.used.is_empty() {
+            self.used.push((0, 0));
+        }
+
+        // Images come tallest first, the first shelf with room is good enough
+        let max_size = self.max_size;
+        let slot = self
+            .shelves
+            .iter_mut()
+            .find(|(_, shelf_height, used)| height <= *shelf_height && used + width <= max_size);
+
+        let (x, y) = match slot {
+            Some((top, _, used)) => {
+                *used += width;
+                (*used - width, *top)
+            }
+            None => {
+                let top = self
+                    .shelves
+                    .last()
+                    .map_or(0, |(top, shelf_height, _)| top + shelf_height);
+
+                if top + height > self.max_size {
+                    // Current page full
+                    self.shelves.clear();
+                    self.used.push((0, 0));
+                    self.shelves.push((0, height, width));
+                    (0, 0)
+                } else {
+                    self.shelves.push((top, height, width));
+                    (0, top)
+                }
+            }
+        };
+
+        let page = self.used.len() - 1;
+        let used = &mut self.used[page];
+        used.0 = used.0.max(x + width);
+        used.1 = used.1.max(y + height);
+
+        (page, x, y)
+    }
+}
+
+/// Atlas textures uploaded to the GPU, with the region of every image
+pub struct TextureAtlas {
+    label: String,
+    pages: Vec<(Texture, Vector2<u32>)>,
+    regions: HashMap<String, AtlasRegion>,
+}
+
+impl TextureAtlas {
+    /// Region of an image, by the name it was added with
+    pub fn region(&self, name: &str) -> Option<AtlasRegion> {
+        self.regions.get(name).copied()
+    }
+
+    /// Names of every image of the atlas
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.regions.keys().map(String::as_str)
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Texture and size of a page
+    pub fn page(&self, index: usize) -> Option<&(Texture, Vector2<u32>)> {
+        self.pages.get(index)
+    }
+
+    /// Sprite showing one of the images, failing with `MagmaError::Asset` if the atlas doesn't have it
+    pub fn new_sprite(
+        &self,
+        video: &mut VideoHandler,
+        name: &str,
+        z_index: u8,
+    ) -> Result<SpriteObject, MagmaError> {
+        let region = self.region(name).ok_or_else(|| {
+            MagmaError::Asset(format!("No image '{}' in atlas {}", name, self.label))
+        })?;
+        let (texture, dimensions) = self.pages[region.page].clone();
+
+        let label = format!("{} in atlas {}", name, self.label);
+        let sprite = video
+            .gl_handler
+            .new_sprite_from_texture(texture, dimensions, &label, z_index)?;
+        sprite.get_mut().set_region(region.rect);
+
+        Ok(sprite)
+    }
+}
@@ -0,0 +1,419 @@
+//! Generated stress scenes, to benchmark the hardware and engine changes with consistent workloads
+//!
+//! Run a `BenchmarkScene` with `Engine::run` (or `Engine::run_benchmark`), then read its report:
+//! objects are placed by a seeded generator so every run draws the same scene.
+//! Headless engines only run the updates, the workloads are then measured without drawing them.
+//! Frames are timed by the engine around the updates and the rendering, the framerate limit isn't.
+
+// standard imports
+use std::f32::consts::PI;
+use std::fmt;
+use std::path::PathBuf;
+
+// import the engine modules
+use super::assets::Mesh;
+use super::color::Color;
+use super::crash;
+use super::ctx::draw_objects::{PrimitiveObject, SpriteObject};
+use super::ctx::vulkan::RenderCounters;
+use super::ecs::Entity;
+use super::game::{Context, Frame, Game};
+use super::lighting::{Light, LightLayerObject};
+use super::lod::{LodLevel, LodMesh, LodMeshObject};
+use super::stats::FrameStats;
+use super::time::Time;
+
+// other imports
+use cgmath::{Vector2, Vector4};
+#[cfg(feature = "serialization")]
+use serde::Serialize;
+
+/// Area the objects move in without window
+const HEADLESS_AREA: Vector2<f32> = Vector2::new(1280.0, 720.0);
+
+/// Workload of a benchmark scene
+#[derive(Clone, Debug, PartialEq)]
+pub enum BenchmarkKind {
+    /// Moving sprites all using the same texture, loaded from the asset root
+    Sprites { count: usize, texture: PathBuf },
+    /// Moving colored rectangles
+    Rectangles { count: usize },
+    /// ECS entities bouncing in the window, each drawn as a rectangle
+    Bodies { count: usize },
+    /// Moving colored polygons with many sides, drawn as meshes
+    Meshes { count: usize, sides: usize },
+    /// Moving point lights, each over a moving rectangle, in a light layer drawn over them.
+    /// Only the first `lighting::MAX_LIGHTS` lights reach the light map
+    Lights { count: usize },
+}
+
+impl BenchmarkKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BenchmarkKind::Sprites { .. } => "sprites",
+            BenchmarkKind::Rectangles { .. } => "rectangles",
+            BenchmarkKind::Bodies { .. } => "bodies",
+            BenchmarkKind::Meshes { .. } => "meshes",
+            BenchmarkKind::Lights { .. } => "lights",
+        }
+    }
+}
+
+/// Frame timings measured by a benchmark
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+pub struct BenchmarkReport {
+    /// Name of the workload (see `BenchmarkKind::name`) and its number of objects
+    pub scene: String,
+    pub objects: usize,
+    /// Only the updates were measured
+    pub headless: bool,
+    pub frames: usize,
+    /// Time spent updating and rendering the frames in milliseconds, without the framerate limit
+    pub average: f32,
+    pub min: f32,
+    pub max: f32,
+    /// Frame time of the slowest 1% of the frames
+    pub percentile_99: f32,
+    /// Draw calls and vertices of the last measured frame
+    pub draw_calls: u32,
+    pub vertices: u32,
+}
+
+impl BenchmarkReport {
+    fn from_stats(stats: &FrameStats) -> Self {
+        Self {
+            frames: stats.frame_count() as usize,
+            average: stats.average() * 1000.0,
+            min: stats.min() * 1000.0,
+            max: stats.max() * 1000.0,
+            percentile_99: stats.percentile(0.99) * 1000.0,
+            ..Self::default()
+        }
+    }
+
+    /// Machine readable report, to compare runs between engine versions
+    #[cfg(feature = "serialization")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {} frames: avg {:.2}ms, min {:.2}ms, max {:.2}ms, 99th percentile {:.2}ms",
+            self.objects,
+            self.scene,
+            self.frames,
+            self.average,
+            self.min,
+            self.max,
+            self.percentile_99
+        )
+    }
+}
+
+/// Position and velocity of a benchmark body, also used as ECS component
+#[derive(Clone, Copy, Debug)]
+pub struct BenchmarkBody {
+    pub position: Vector2<f32>,
+    pub velocity: Vector2<f32>,
+}
+
+impl BenchmarkBody {
+    /// Move the body, bouncing on the borders of the area
+    fn step(&mut self, dt: f32, area: Vector2<f32>) {
+        self.position += self.velocity * dt;
+
+        if self.position.x < 0.0 || self.position.x > area.x {
+            self.velocity.x = -self.velocity.x;
+        }
+        if self.position.y < 0.0 || self.position.y > area.y {
+            self.velocity.y = -self.velocity.y;
+        }
+    }
+}
+
+/// Small deterministic generator, every run places the objects the same way
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> f32 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+fn window_area(ctx: &Context) -> Vector2<f32> {
+    match ctx.main_video() {
+        Some(video) => {
+            let (width, height) = video.get_window_size();
+            Vector2::new(width as f32, height as f32)
+        }
+        None => HEADLESS_AREA,
+    }
+}
+
+/// Regular polygon centered on the origin, with a radius of 1
+fn polygon(sides: usize) -> Mesh {
+    let sides = sides.max(3);
+    let mut mesh = Mesh::default();
+
+    mesh.vertices.push([0.0, 0.0]);
+    for side in 0..sides {
+        let angle = side as f32 / sides as f32 * 2.0 * PI;
+        mesh.vertices.push([angle.cos(), angle.sin()]);
+    }
+    for side in 0..sides as u32 {
+        mesh.indices
+            .extend_from_slice(&[0, side + 1, (side + 1) % sides as u32 + 1]);
+    }
+
+    mesh
+}
+
+/// Game running a stress scene for a given time or number of frames, then stopping the engine
+pub struct BenchmarkScene {
+    pub kind: BenchmarkKind,
+    /// Seconds measured, after the warmup
+    pub duration: f32,
+    /// Frames measured after the warmup, used instead of the duration
+    pub frames: Option<usize>,
+    /// Seconds ignored at the start, while caches fill up
+    pub warmup: f32,
+    sprites: Vec<SpriteObject>,
+    rectangles: Vec<PrimitiveObject>,
+    meshes: Vec<LodMeshObject>,
+    light_layer: Option<LightLayerObject>,
+    headless: bool,
+    /// Bodies moved by the scene itself, or copied from the ECS entities
+    bodies: Vec<BenchmarkBody>,
+    entities: Vec<Entity>,
+    /// Work time of every measured frame
+    stats: FrameStats,
+    elapsed: f32,
+    report: Option<BenchmarkReport>,
+}
+
+impl BenchmarkScene {
+    pub fn new(kind: BenchmarkKind, duration: f32) -> Self {
+        Self {
+            kind,
+            duration,
+            frames: None,
+            warmup: 1.0,
+            sprites: Vec::new(),
+            rectangles: Vec::new(),
+            meshes: Vec::new(),
+            light_layer: None,
+            headless: false,
+            bodies: Vec::new(),
+            entities: Vec::new(),
+            stats: FrameStats::with_window(usize::MAX),
+            elapsed: 0.0,
+            report: None,
+        }
+    }
+
+    /// Scene measuring a fixed number of frames, whatever time they take
+    pub fn with_frames(kind: BenchmarkKind, frames: usize) -> Self {
+        Self {
+            frames: Some(frames),
+            ..Self::new(kind, 0.0)
+        }
+    }
+
+    /// Timings of the benchmark, available once it's finished
+    pub fn get_report(&self) -> Option<&BenchmarkReport> {
+        self.report.as_ref()
+    }
+
+    fn count(&self) -> usize {
+        match &self.kind {
+            BenchmarkKind::Sprites { count, .. } => *count,
+            BenchmarkKind::Rectangles { count } => *count,
+            BenchmarkKind::Bodies { count } => *count,
+            BenchmarkKind::Meshes { count, .. } => *count,
+            BenchmarkKind::Lights { count } => *count,
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        match self.frames {
+            Some(frames) => self.stats.frame_count() as usize >= frames,
+            None => self.elapsed >= self.warmup + self.duration,
+        }
+    }
+}
+
+impl Game for BenchmarkScene {
+    fn init(&mut self, ctx: &mut Context) {
+        let area = window_area(ctx);
+        let mut rng = Lcg(0x5eed);
+        self.headless = ctx.is_headless();
+        let mesh = match &self.kind {
+            BenchmarkKind::Meshes { sides, .. } => Some(polygon(*sides)),
+            _ => None,
+        };
+
+        // Uploaded once through the asset cache, shared by every sprite
+        let texture = match &self.kind {
+            BenchmarkKind::Sprites { texture, .. } if !self.headless => ctx
+                .load_texture(texture)
+                .map_err(|e| crash::log(&format!("Benchmark texture not loaded: {}", e)))
+                .ok(),
+            _ => None,
+        };
+
+        if let (BenchmarkKind::Lights { .. }, Some(video)) = (&self.kind, ctx.try_video()) {
+            match video.new_light_layer(8) {
+                Ok(layer) => {
+                    layer.get_mut().set_ambient(Color::rgb(0.1, 0.1, 0.1));
+                    self.light_layer = Some(layer);
+                }
+                Err(e) => crash::log(&format!("Benchmark light layer not created: {}", e)),
+            }
+        }
+
+        for index in 0..self.count() {
+            let body = BenchmarkBody {
+                position: Vector2::new(rng.next() * area.x, rng.next() * area.y),
+                velocity: Vector2::new(rng.next() - 0.5, rng.next() - 0.5) * 400.0,
+            };
+            let color = Vector4::new(rng.next(), rng.next(), rng.next(), 1.0);
+            let z_index = (index % 8) as u8;
+            self.bodies.push(body);
+
+            if let BenchmarkKind::Bodies { .. } = self.kind {
+                self.entities.push(ctx.world.build().with(body).finish());
+            }
+
+            // Nothing to draw without window
+            let video = match ctx.try_video() {
+                Some(video) => video,
+                None => continue,
+            };
+
+            match &self.kind {
+                BenchmarkKind::Sprites { .. } => {
+                    if let Some(texture) = &texture {
+                        match video.new_sprite_from_asset(texture, z_index) {
+                            Ok(sprite) => {
+                                sprite.get_mut().global_position = body.position;
+                                self.sprites.push(sprite);
+                            }
+                            Err(e) => crash::log(&format!("Benchmark sprite not created: {}", e)),
+                        }
+                    }
+                }
+                BenchmarkKind::Rectangles { .. }
+                | BenchmarkKind::Bodies { .. }
+                | BenchmarkKind::Lights { .. } => {
+                    match video.new_rectangle(Vector2::new(4.0, 4.0), color, body.position, z_index)
+                    {
+                        Ok(rectangle) => self.rectangles.push(rectangle),
+                        Err(e) => crash::log(&format!("Benchmark rectangle not created: {}", e)),
+                    }
+                }
+                BenchmarkKind::Meshes { .. } => {
+                    let level = LodLevel::new(mesh.clone().unwrap_or_default(), 0.0);
+                    let scale = Vector2::new(6.0, 6.0);
+                    self.meshes.push(LodMesh::create(
+                        video,
+                        vec![level],
+                        color,
+                        body.position,
+                        scale,
+                        z_index,
+                    ));
+                }
+            }
+        }
+
+        if let Some(layer) = &self.light_layer {
+            let mut layer = layer.get_mut();
+            for body in &self.bodies {
+                let color = Color::rgb(rng.next(), rng.next(), rng.next());
+                layer.add_light(Light::point(body.position, 96.0).color(color));
+            }
+        }
+
+        if !self.entities.is_empty() {
+            ctx.systems.add_system("benchmark_bodies", |time: &Time, ctx: &mut Context| {
+                let area = window_area(ctx);
+                if let Some(mut bodies) = ctx.world.borrow_mut::<BenchmarkBody>() {
+                    for (_, body) in bodies.iter_mut() {
+                        body.step(time.delta(), area);
+                    }
+                }
+            });
+        }
+    }
+
+    fn update(&mut self, time: &Time, ctx: &mut Context) {
+        let dt = time.delta();
+
+        // The systems already ran, only read back where the entities are
+        if self.entities.is_empty() {
+            let area = window_area(ctx);
+            for body in self.bodies.iter_mut() {
+                body.step(dt, area);
+            }
+        } else {
+            for (body, entity) in self.bodies.iter_mut().zip(self.entities.iter()) {
+                if let Some(moved) = ctx.world.get::<BenchmarkBody>(*entity) {
+                    *body = *moved;
+                }
+            }
+        }
+
+        // Wall time of the last frame, the waits of the framerate limit aren't part of the workload
+        self.elapsed += dt;
+        if self.elapsed > self.warmup {
+            self.stats
+                .record(ctx.frame_stats.work_time(), RenderCounters::default());
+        }
+
+        if self.is_finished() && self.report.is_none() {
+            self.report = Some(BenchmarkReport {
+                scene: self.kind.name().to_string(),
+                objects: self.count(),
+                headless: self.headless,
+                draw_calls: ctx.frame_stats.draw_calls(),
+                vertices: ctx.frame_stats.vertices(),
+                ..BenchmarkReport::from_stats(&self.stats)
+            });
+            ctx.set_break_signal();
+        }
+    }
+
+    fn render(&mut self, _frame: &mut Frame) {
+        for (sprite, body) in self.sprites.iter().zip(self.bodies.iter()) {
+            sprite.get_mut().global_position = body.position;
+        }
+        for (rectangle, body) in self.rectangles.iter().zip(self.bodies.iter()) {
+            rectangle.get_mut().global_position = body.position;
+        }
+        for (mesh, body) in self.meshes.iter().zip(self.bodies.iter()) {
+            mesh.get_mut().global_position = body.position;
+        }
+        if let Some(layer) = &self.light_layer {
+            let mut layer = layer.get_mut();
+            for (light, body) in layer.lights.iter_mut().zip(self.bodies.iter()) {
+                light.position = body.position;
+            }
+        }
+    }
+
+    fn shutdown(&mut self, ctx: &mut Context) {
+        ctx.systems.remove_system("benchmark_bodies");
+        for entity in self.entities.drain(..) {
+            ctx.world.despawn(entity);
+        }
+    }
+}
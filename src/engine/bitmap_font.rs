@@ -0,0 +1,575 @@
+//! Bitmap fonts made with AngelCode BMFont (or compatible tools), loaded from `.fnt` files
+//!
+//! The text and XML descriptor formats are supported, with kerning pairs and multiple pages.
+//! Glyphs are copied from the pre-baked PNG pages without any rasterization, which keeps pixel
+//! art fonts crisp at whole scales. Text is drawn through the sprite batch.
+
+// standard imports
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// vulkan imports
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+
+// import the engine modules
+use super::color::Color;
+use super::ctx::draw_objects::{
+    BatchQuad, Draw, DrawFlags, DrawState, GraphicObject, WORLD_UNITS_PER_PIXEL,
+};
+use super::ctx::vulkan::{DeviceRecovery, FrameError, GraphicsHandler, Texture};
+use super::ctx::VideoHandler;
+use super::geometry::{Aabb, Rect};
+use super::leaks::{ResourceKind, ResourceTracker};
+use super::math::{Vec2, Vec4};
+use super::pack;
+
+// other imports
+use cgmath::Vector2;
+#[cfg(feature = "xml")]
+use roxmltree::Document;
+
+/// Character of a font page
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Glyph {
+    /// Area of the page, in pixels
+    pub region: Rect,
+    /// Offset from the pen position to the top-left corner of the region
+    pub offset: Vec2,
+    /// Distance the pen moves after the glyph
+    pub advance: f32,
+    pub page: usize,
+}
+
+/// Horizontal alignment of the lines of a text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+/// Glyph placed by the text layout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlacedGlyph {
+    pub glyph: Glyph,
+    /// Top-left corner, in pixels from the top-left corner of the text
+    pub position: Vec2,
+}
+
+/// Font descriptor, the page textures are loaded when drawing text
+#[derive(Clone, Debug)]
+pub struct BitmapFont {
+    pub face: String,
+    /// Size the font was rendered at, in pixels
+    pub size: f32,
+    /// Distance between two lines, in pixels
+    pub line_height: f32,
+    /// Distance from the top of a line to the baseline
+    pub base: f32,
+    /// PNG files of the pages, by page id
+    pub pages: Vec<PathBuf>,
+    glyphs: HashMap<char, Glyph>,
+    kernings: HashMap<(char, char), f32>,
+}
+
+impl BitmapFont {
+    /// Load a font descriptor, its pages are found next to it
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes = pack::read(path)
+            .map_err(|e| format!("Couldn't open font '{}': {}", path.display(), e))?;
+        if bytes.starts_with(b"BMF") {
+            return Err(format!(
+                "Font '{}' uses the binary format, export it as text or XML",
+                path.display()
+            ));
+        }
+
+        let source = String::from_utf8(bytes)
+            .map_err(|e| format!("Couldn't read font '{}': {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        Self::parse(&source, dir).map_err(|e| format!("Invalid font '{}': {}", path.display(), e))
+    }
+
+    /// Parse a text or XML descriptor, `dir` being the directory of the pages
+    pub fn parse(source: &str, dir: &Path) -> Result<Self, String> {
+        let tags = if source.trim_start().starts_with('<') {
+            parse_xml_tags(source)?
+        } else {
+            parse_text_tags(source)
+        };
+
+        let mut font = Self {
+            face: String::new(),
+            size: 0.0,
+            line_height: 0.0,
+            base: 0.0,
+            pages: Vec::new(),
+            glyphs: HashMap::new(),
+            kernings: HashMap::new(),
+        };
+
+        let mut pages = HashMap::new();
+        for (tag, values) in &tags {
+            match tag.as_str() {
+                "info" => {
+                    font.face = values.get("face").cloned().unwrap_or_default();
+                    // Negative sizes mean the size matches the character height
+                    font.size = number(values, "size").unwrap_or(0.0).abs();
+                }
+                "common" => {
+                    font.line_height = required(values, "common", "lineHeight")?;
+                    font.base = required(values, "common", "base")?;
+                }
+                "page" => {
+                    let id = required::<f32>(values, "page", "id")? as usize;
+                    let file = values
+                        .get("file")
+                        .ok_or_else(|| "Missing file of a page".to_string())?;
+                    pages.insert(id, dir.join(file));
+                }
+                "char" => {
+                    // Tools write -1 for the glyph of missing characters
+                    let id: f32 = required(values, "char", "id")?;
+                    let character = match std::char::from_u32(id as u32) {
+                        Some(character) if id >= 0.0 => character,
+                        _ => continue,
+                    };
+
+                    font.glyphs.insert(
+                        character,
+                        Glyph {
+                            region: Rect::new(
+                                required(values, "char", "x")?,
+                                required(values, "char", "y")?,
+                                required(values, "char", "width")?,
+                                required(values, "char", "height")?,
+                            ),
+                            offset: Vec2::new(
+                                number(values, "xoffset").unwrap_or(0.0),
+                                number(values, "yoffset").unwrap_or(0.0),
+                            ),
+                            advance: number(values, "xadvance").unwrap_or(0.0),
+                            page: number(values, "page").unwrap_or(0.0) as usize,
+                        },
+                    );
+                }
+                "kerning" => {
+                    let first = required::<f32>(values, "kerning", "first")? as u32;
+                    let second = required::<f32>(values, "kerning", "second")? as u32;
+                    if let (Some(first), Some(second)) =
+                        (std::char::from_u32(first), std::char::from_u32(second))
+                    {
+                        font.kernings
+                            .insert((first, second), required(values, "kerning", "amount")?);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Pages ids go from 0 to count - 1
+        for id in 0..pages.len() {
+            let page = pages
+                .remove(&id)
+                .ok_or_else(|| format!("Missing page {}", id))?;
+            font.pages.push(page);
+        }
+        if let Some(glyph) = font
+            .glyphs
+            .values()
+            .find(|glyph| glyph.page >= font.pages.len())
+        {
+            return Err(format!("Glyph on the missing page {}", glyph.page));
+        }
+
+        Ok(font)
+    }
+
+    pub fn glyph(&self, character: char) -> Option<&Glyph> {
+        self.glyphs.get(&character)
+    }
+
+    /// Adjustment of the advance between two characters, 0.0 without kerning pair
+    pub fn kerning(&self, first: char, second: char) -> f32 {
+        self.kernings.get(&(first, second)).copied().unwrap_or(0.0)
+    }
+
+    /// Place the glyphs of a text, lines split on '\n'.
+    /// Characters missing from the font are skipped
+    pub fn layout(&self, text: &str, align: TextAlign) -> Vec<PlacedGlyph> {
+        let lines: Vec<(Vec<PlacedGlyph>, f32)> = text
+            .split('\n')
+            .enumerate()
+            .map(|(index, line)| self.layout_line(line, index as f32 * self.line_height))
+            .collect();
+        let width = lines.iter().map(|(_, width)| *width).fold(0.0, f32::max);
+
+        let mut placed = Vec::new();
+        for (glyphs, line_width) in lines {
+            let shift = match align {
+                TextAlign::Left => 0.0,
+                TextAlign::Center => ((width - line_width) / 2.0).floor(),
+                TextAlign::Right => width - line_width,
+            };
+
+            placed.extend(glyphs.into_iter().map(|mut glyph| {
+                glyph.position.x += shift;
+                glyph
+            }));
+        }
+        placed
+    }
+
+    /// Size of a text, in pixels
+    pub fn measure(&self, text: &str) -> Vec2 {
+        let lines = text.split('\n');
+        let count = lines.clone().count();
+        let width = lines
+            .map(|line| self.layout_line(line, 0.0).1)
+            .fold(0.0, f32::max);
+
+        Vec2::new(width, count as f32 * self.line_height)
+    }
+
+    /// Load the textures of the pages, shared with the other users of the same files
+    pub fn load_pages(
+        &self,
+        gl_handler: &GraphicsHandler,
+    ) -> Result<Vec<(Texture, Vector2<u32>)>, String> {
+        self.pages
+            .iter()
+            .map(|page| gl_handler.load_texture(&page.to_string_lossy()))
+            .collect()
+    }
+
+    /// Text on screen, with the pages loaded from their files
+    pub fn new_text(
+        self: &Arc<Self>,
+        video: &mut VideoHandler,
+        text: &str,
+        z_index: u8,
+    ) -> Result<BitmapTextObject, String> {
+        let pages = Arc::new(self.load_pages(&video.gl_handler)?);
+        let draw = BitmapText::new(self.clone(), pages, text, z_index);
+
+        Ok(video.gl_handler.add_draw_object(draw))
+    }
+
+    /// Glyphs of a line and its width
+    fn layout_line(&self, line: &str, top: f32) -> (Vec<PlacedGlyph>, f32) {
+        let mut glyphs = Vec::new();
+        let mut pen = 0.0;
+        let mut previous = None;
+
+        for character in line.chars() {
+            let glyph = match self.glyphs.get(&character) {
+                Some(glyph) => *glyph,
+                None => continue,
+            };
+
+            if let Some(previous) = previous {
+                pen += self.kerning(previous, character);
+            }
+            glyphs.push(PlacedGlyph {
+                glyph,
+                position: Vec2::new(pen + glyph.offset.x, top + glyph.offset.y),
+            });
+
+            pen += glyph.advance;
+            previous = Some(character);
+        }
+
+        (glyphs, pen)
+    }
+}
+
+/// Split the lines of the text format in tags and their `key=value` pairs
+fn parse_text_tags(source: &str) -> Vec<(String, HashMap<String, String>)> {
+    let mut tags = Vec::new();
+
+    for line in source.lines() {
+        let mut rest = line.trim();
+        let tag = match rest.split_whitespace().next() {
+            Some(tag) => tag.to_string(),
+            None => continue,
+        };
+        rest = rest[tag.len()..].trim_start();
+
+        let mut values = HashMap::new();
+        while let Some(equal) = rest.find('=') {
+            let key = rest[..equal].trim().to_string();
+            rest = &rest[equal + 1..];
+
+            // Quoted values may hold spaces (e.g. font faces and file names)
+            let value = if let Some(quoted) = rest.strip_prefix('"') {
+                let end = quoted.find('"').unwrap_or_else(|| quoted.len());
+                rest = quoted.get(end + 1..).unwrap_or("");
+                &quoted[..end]
+            } else {
+                let end = rest.find(char::is_whitespace).unwrap_or_else(|| rest.len());
+                let value = &rest[..end];
+                rest = &rest[end..];
+                value
+            };
+
+            values.insert(key, value.to_string());
+            rest = rest.trim_start();
+        }
+
+        tags.push((tag, values));
+    }
+
+    tags
+}
+
+/// Elements of the XML format with their attributes, same names as the text format
+#[cfg(feature = "xml")]
+fn parse_xml_tags(source: &str) -> Result<Vec<(String, HashMap<String, String>)>, String> {
+    let document = Document::parse(source).map_err(|e| e.to_string())?;
+
+    Ok(document
+        .descendants()
+        .filter(|node| node.is_element())
+        .map(|node| {
+            let values = node
+                .attributes()
+                .iter()
+                .map(|attribute| (attribute.name().to_string(), attribute.value().to_string()))
+                .collect();
+            (node.tag_name().name().to_string(), values)
+        })
+        .collect())
+}
+
+#[cfg(not(feature = "xml"))]
+fn parse_xml_tags(_source: &str) -> Result<Vec<(String, HashMap<String, String>)>, String> {
+    Err(String::from("XML fonts need the xml feature"))
+}
+
+fn number(values: &HashMap<String, String>, key: &str) -> Option<f32> {
+    values.get(key).and_then(|value| value.parse().ok())
+}
+
+fn required<T: std::str::FromStr>(
+    values: &HashMap<String, String>,
+    tag: &str,
+    key: &str,
+) -> Result<T, String> {
+    let value = values
+        .get(key)
+        .ok_or_else(|| format!("Missing {} of '{}'", key, tag))?;
+
+    value
+        .parse()
+        .map_err(|_| format!("Invalid {}={} of '{}'", key, value, tag))
+}
+
+pub type BitmapTextObject = GraphicObject<BitmapText>;
+
+/// Text drawn with a bitmap font
+pub struct BitmapText {
+    font: Arc<BitmapFont>,
+    pages: Arc<Vec<(Texture, Vector2<u32>)>>,
+    text: String,
+    align: TextAlign,
+    glyphs: Vec<PlacedGlyph>,
+
+    z_index: u8,
+    draw_flags: DrawFlags,
+
+    pub color: Vec4,
+    /// Top-left corner of the text, in world units
+    pub global_position: Vec2,
+    pub scale: Vec2,
+
+    _tracker: ResourceTracker,
+}
+
+impl BitmapText {
+    pub fn new(
+        font: Arc<BitmapFont>,
+        pages: Arc<Vec<(Texture, Vector2<u32>)>>,
+        text: &str,
+        z_index: u8,
+    ) -> Self {
+        let label = format!("Bitmap text with font '{}'", font.face);
+
+        let glyphs = font.layout(text, TextAlign::Left);
+
+        Self {
+            font,
+            pages,
+            text: text.to_string(),
+            align: TextAlign::Left,
+            glyphs,
+            z_index,
+            draw_flags: DrawFlags::USED | DrawFlags::VISIBLE,
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            global_position: Vec2::new(0.0, 0.0),
+            scale: Vec2::new(1.0, 1.0),
+            _tracker: ResourceTracker::new(ResourceKind::DrawObject, &label),
+        }
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        if self.text != text {
+            self.text = text.to_string();
+            self.glyphs = self.font.layout(text, self.align);
+        }
+    }
+
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn set_align(&mut self, align: TextAlign) {
+        self.align = align;
+        self.glyphs = self.font.layout(&self.text, align);
+    }
+
+    pub fn get_align(&self) -> TextAlign {
+        self.align
+    }
+
+    pub fn font(&self) -> &Arc<BitmapFont> {
+        &self.font
+    }
+
+    pub fn set_color(&mut self, color: impl Into<Color>) {
+        self.color = color.into().into();
+    }
+
+    pub fn get_color(&self) -> Color {
+        self.color.into()
+    }
+
+    /// Area covered by the text, in world units
+    pub fn bounds(&self) -> Aabb {
+        let size = self.font.measure(&self.text);
+        let size = Vec2::new(
+            size.x * self.scale.x * WORLD_UNITS_PER_PIXEL,
+            size.y * self.scale.y * WORLD_UNITS_PER_PIXEL,
+        );
+
+        Aabb::new(self.global_position, self.global_position + size)
+    }
+
+    fn push_quads(&self, view: Option<&Aabb>, quads: &mut Vec<BatchQuad>) {
+        let pixel = Vec2::new(
+            self.scale.x * WORLD_UNITS_PER_PIXEL,
+            self.scale.y * WORLD_UNITS_PER_PIXEL,
+        );
+
+        for placed in &self.glyphs {
+            let glyph = &placed.glyph;
+            if glyph.region.width <= 0.0 || glyph.region.height <= 0.0 {
+                continue;
+            }
+
+            let min = self.global_position
+                + Vec2::new(placed.position.x * pixel.x, placed.position.y * pixel.y);
+            let size = Vec2::new(glyph.region.width * pixel.x, glyph.region.height * pixel.y);
+            if let Some(view) = view {
+                if !view.intersects(&Aabb::new(min, min + size)) {
+                    continue;
+                }
+            }
+
+            let (texture, dimensions) = &self.pages[glyph.page];
+            let (width, height) = (dimensions.x.max(1) as f32, dimensions.y.max(1) as f32);
+            let tex_min = [glyph.region.x / width, glyph.region.y / height];
+            let tex_max = [
+                (glyph.region.x + glyph.region.width) / width,
+                (glyph.region.y + glyph.region.height) / height,
+            ];
+
+            let quad = BatchQuad::new(
+                Some(texture.clone()),
+                min + size / 2.0,
+                size / 2.0,
+                self.color,
+            );
+            quads.push(quad.with_tex_region(tex_min, tex_max));
+        }
+    }
+}
+
+impl Draw for BitmapText {
+    fn draw(
+        &self,
+        gl_handler: &mut GraphicsHandler,
+        command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), FrameError> {
+        // Recorded Command Buffers may be resubmitted after the camera moves, keep every glyph
+        let mut quads = Vec::new();
+        self.push_quads(None, &mut quads);
+
+        gl_handler.draw_quads(quads, command_buffer)
+    }
+
+    fn get_z_index(&self) -> u8 {
+        self.z_index
+    }
+
+    fn flush_data(&self) {}
+
+    fn write_flags(&mut self) -> &mut DrawFlags {
+        &mut self.draw_flags
+    }
+
+    fn read_flags(&self) -> DrawFlags {
+        self.draw_flags
+    }
+
+    fn set_dead(&mut self) {
+        self.draw_flags.remove(DrawFlags::USED);
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        self.draw_flags.set(DrawFlags::VISIBLE, visible);
+    }
+
+    fn get_state(&self) -> DrawState {
+        DrawState {
+            color: self.color,
+            global_position: self.global_position,
+            scale: self.scale,
+            flags: self.draw_flags,
+        }
+    }
+
+    fn set_state(&mut self, state: &DrawState) {
+        self.color = state.color;
+        self.global_position = state.global_position;
+        self.scale = state.scale;
+        self.draw_flags = state.flags;
+    }
+
+    fn recreate(
+        &mut self,
+        _gl_handler: &GraphicsHandler,
+        recovery: &DeviceRecovery,
+    ) -> Result<(), String> {
+        let pages = self
+            .pages
+            .iter()
+            .map(|(texture, dimensions)| Ok((recovery.texture(texture)?, *dimensions)))
+            .collect::<Result<Vec<_>, String>>()?;
+        self.pages = Arc::new(pages);
+
+        Ok(())
+    }
+
+    fn batch_quads(&self, view: &Aabb, quads: &mut Vec<BatchQuad>) -> bool {
+        self.push_quads(Some(view), quads);
+        true
+    }
+
+    fn batch_key(&self) -> usize {
+        self.pages
+            .first()
+            .map_or(0, |(texture, _)| Arc::as_ptr(texture) as usize)
+    }
+}
@@ -0,0 +1,381 @@
+//! 2D camera of the main window, with zoom, rotation, bounds and target following
+//!
+//! The engine updates `ctx.camera` after the updates of every frame and gives its view to the
+//! next frame drawn, so objects placed in world units move with the view.
+//! The camera also runs the screen effects: trauma-based shake, color flashes and fades.
+
+// import the engine modules
+use super::color::Color;
+use super::ctx::vulkan::CameraView;
+use super::ecs::{Entity, World};
+use super::geometry::Aabb;
+use super::math::{Rad, Vec2};
+use super::transform::GlobalTransform;
+
+/// Screen shake strength, scaled by the square of the trauma
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraShake {
+    /// Largest offset of the view, in world units
+    pub max_offset: Vec2,
+    pub max_rotation: Rad<f32>,
+    /// Speed of the shaking
+    pub frequency: f32,
+    /// Trauma lost every second
+    pub decay: f32,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self {
+            max_offset: Vec2::new(20.0, 20.0),
+            max_rotation: Rad(0.05),
+            frequency: 15.0,
+            decay: 1.0,
+        }
+    }
+}
+
+/// Color covering the screen, fading away
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Flash {
+    color: Color,
+    duration: f32,
+    elapsed: f32,
+}
+
+/// Change of the fade level over time
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Fade {
+    from: f32,
+    to: f32,
+    duration: f32,
+    elapsed: f32,
+}
+
+/// What the camera follows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraTarget {
+    Point(Vec2),
+    /// Entity with a `GlobalTransform`, the camera stays put once it's gone
+    Entity(Entity),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Camera2D {
+    /// Point of the world at the center of the view
+    pub position: Vec2,
+    /// Magnification, 2.0 shows everything twice as big
+    pub zoom: f32,
+    /// Size of the view on each axis before the zoom, 0.5 on Y shows half as much vertically.
+    /// A negative dimension flips the view on it
+    pub stretch: Vec2,
+    /// Counterclockwise rotation of the view
+    pub rotation: Rad<f32>,
+    /// Area the view is kept inside of, None to move freely
+    pub bounds: Option<Aabb>,
+    target: Option<CameraTarget>,
+    /// Time in seconds to cover about two thirds of the distance to the target, 0.0 snaps to it
+    pub follow_smoothing: f32,
+    /// Offset from the target to the center of the view (e.g. to look ahead of the player)
+    pub follow_offset: Vec2,
+    /// Size of the area around the center the target moves in without moving the camera
+    pub dead_zone: Vec2,
+    pub shake: CameraShake,
+    /// From 0.0 to 1.0, the shaking grows with its square
+    trauma: f32,
+    shake_time: f32,
+    flash: Option<Flash>,
+    fade: Option<Fade>,
+    fade_color: Color,
+    /// Opacity of the fade color over the screen
+    fade_level: f32,
+}
+
+impl Camera2D {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start following a target, smoothed by `follow_smoothing`
+    pub fn follow(&mut self, target: CameraTarget) {
+        self.target = Some(target);
+    }
+
+    pub fn stop_following(&mut self) {
+        self.target = None;
+    }
+
+    pub fn target(&self) -> Option<CameraTarget> {
+        self.target
+    }
+
+    /// Jump to the target right away, skipping the smoothing (e.g. after a level change)
+    pub fn snap_to_target(&mut self, world: &World) {
+        if let Some(target) = self.target_position(world) {
+            self.position = target;
+        }
+    }
+
+    /// Zoom by a factor, keeping a world point (e.g. under the mouse) at the same place on screen
+    pub fn zoom_at(&mut self, factor: f32, point: Vec2) {
+        if factor <= 0.0 {
+            return;
+        }
+
+        self.position = point + (self.position - point) / factor;
+        self.zoom *= factor;
+    }
+
+    /// Shake the screen, trauma adds up to 1.0 and fades by `shake.decay` every second
+    pub fn add_trauma(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).max(0.0).min(1.0);
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    /// Offset and rotation added to the view by the shaking
+    pub fn shake_offset(&self) -> (Vec2, Rad<f32>) {
+        let amount = self.trauma * self.trauma;
+        if amount <= 0.0 {
+            return (Vec2::new(0.0, 0.0), Rad(0.0));
+        }
+
+        let time = self.shake_time * self.shake.frequency;
+        let offset = Vec2::new(
+            self.shake.max_offset.x * amount * shake_noise(time, 0.0),
+            self.shake.max_offset.y * amount * shake_noise(time, 1.0),
+        );
+
+        (
+            offset,
+            Rad(self.shake.max_rotation.0 * amount * shake_noise(time, 2.0)),
+        )
+    }
+
+    /// Cover the screen with a color fading away in `duration` seconds (e.g. white when hit)
+    pub fn flash(&mut self, color: impl Into<Color>, duration: f32) {
+        self.flash = Some(Flash {
+            color: color.into(),
+            duration,
+            elapsed: 0.0,
+        });
+    }
+
+    /// Cover the screen with a color over `duration` seconds, staying until `fade_in`
+    pub fn fade_out(&mut self, color: impl Into<Color>, duration: f32) {
+        self.fade_color = color.into();
+        self.fade_to(1.0, duration);
+    }
+
+    /// Uncover the screen over `duration` seconds
+    pub fn fade_in(&mut self, duration: f32) {
+        self.fade_to(0.0, duration);
+    }
+
+    pub fn is_fading(&self) -> bool {
+        self.fade.is_some()
+    }
+
+    /// Opacity of the fade, 1.0 once the screen is covered
+    pub fn fade_level(&self) -> f32 {
+        self.fade_level
+    }
+
+    /// Color covering the screen, the flash over the fade
+    pub fn overlay(&self) -> Color {
+        let fade = self
+            .fade_color
+            .with_alpha(self.fade_color.a * self.fade_level);
+
+        match self.flash {
+            Some(flash) => {
+                let left = 1.0 - flash.elapsed / flash.duration.max(f32::EPSILON);
+                let flash = flash.color.with_alpha(flash.color.a * left.max(0.0));
+
+                blend_over(flash, fade)
+            }
+            None => fade,
+        }
+    }
+
+    /// Move towards the target and run the effects by `delta` seconds
+    pub fn update(&mut self, delta: f32, world: &World) {
+        self.update_effects(delta);
+
+        let target = match self.target_position(world) {
+            Some(target) => target,
+            None => return,
+        };
+
+        // Only the part of the distance out of the dead zone is followed
+        let half_zone = self.dead_zone / 2.0;
+        let offset = target - self.position;
+        let outside = Vec2::new(
+            offset.x - offset.x.max(-half_zone.x).min(half_zone.x),
+            offset.y - offset.y.max(-half_zone.y).min(half_zone.y),
+        );
+
+        if self.follow_smoothing <= 0.0 {
+            self.position += outside;
+        } else {
+            self.position += outside * (1.0 - (-delta / self.follow_smoothing).exp());
+        }
+    }
+
+    /// View of a renderer drawing a window of the given logical size, clamping the camera
+    /// position to its bounds
+    pub fn view(&mut self, window_size: (u32, u32)) -> CameraView {
+        let zoom = if self.zoom > 0.0 { self.zoom } else { 1.0 };
+
+        let scale = self.stretch / zoom;
+
+        if let Some(bounds) = self.bounds {
+            let (width, height) = window_size;
+            self.position = self.clamped_position(
+                &bounds,
+                Vec2::new(width as f32 * scale.x.abs(), height as f32 * scale.y.abs()),
+            );
+        }
+
+        let (shake_offset, shake_rotation) = self.shake_offset();
+        CameraView {
+            position: self.position + shake_offset,
+            scale,
+            rotation: self.rotation + shake_rotation,
+            overlay: self.overlay(),
+        }
+    }
+
+    fn fade_to(&mut self, level: f32, duration: f32) {
+        self.fade = Some(Fade {
+            from: self.fade_level,
+            to: level,
+            duration,
+            elapsed: 0.0,
+        });
+    }
+
+    fn update_effects(&mut self, delta: f32) {
+        self.trauma = (self.trauma - self.shake.decay * delta).max(0.0);
+        // Only needs to move while shaking, kept small for precision
+        self.shake_time = if self.trauma > 0.0 {
+            self.shake_time + delta
+        } else {
+            0.0
+        };
+
+        if let Some(flash) = &mut self.flash {
+            flash.elapsed += delta;
+            if flash.elapsed >= flash.duration {
+                self.flash = None;
+            }
+        }
+
+        if let Some(fade) = &mut self.fade {
+            fade.elapsed += delta;
+            let t = if fade.duration > 0.0 {
+                (fade.elapsed / fade.duration).min(1.0)
+            } else {
+                1.0
+            };
+
+            self.fade_level = fade.from + (fade.to - fade.from) * t;
+            if t >= 1.0 {
+                self.fade = None;
+            }
+        }
+    }
+
+    fn target_position(&self, world: &World) -> Option<Vec2> {
+        let target = match self.target? {
+            CameraTarget::Point(point) => point,
+            CameraTarget::Entity(entity) => world.get::<GlobalTransform>(entity)?.translation(),
+        };
+
+        Some(target + self.follow_offset)
+    }
+
+    /// Position keeping the whole view (of the given half extents) inside the bounds,
+    /// centered on them if they are smaller than the view
+    fn clamped_position(&self, bounds: &Aabb, half_view: Vec2) -> Vec2 {
+        // World extents of the rotated view
+        let (sin, cos) = self.rotation.0.sin_cos();
+        let half_view = Vec2::new(
+            half_view.x * cos.abs() + half_view.y * sin.abs(),
+            half_view.x * sin.abs() + half_view.y * cos.abs(),
+        );
+
+        let clamp = |value: f32, min: f32, max: f32| {
+            if min > max {
+                (min + max) / 2.0
+            } else {
+                value.max(min).min(max)
+            }
+        };
+
+        Vec2::new(
+            clamp(
+                self.position.x,
+                bounds.min.x + half_view.x,
+                bounds.max.x - half_view.x,
+            ),
+            clamp(
+                self.position.y,
+                bounds.min.y + half_view.y,
+                bounds.max.y - half_view.y,
+            ),
+        )
+    }
+}
+
+impl Default for Camera2D {
+    fn default() -> Self {
+        Self {
+            position: Vec2::new(0.0, 0.0),
+            zoom: 1.0,
+            stretch: Vec2::new(1.0, 1.0),
+            rotation: Rad(0.0),
+            bounds: None,
+            target: None,
+            follow_smoothing: 0.2,
+            follow_offset: Vec2::new(0.0, 0.0),
+            dead_zone: Vec2::new(0.0, 0.0),
+            shake: CameraShake::default(),
+            trauma: 0.0,
+            shake_time: 0.0,
+            flash: None,
+            fade: None,
+            fade_color: Color::BLACK,
+            fade_level: 0.0,
+        }
+    }
+}
+
+/// Smooth wave from -1.0 to 1.0, the same at the same time so replays shake the same way
+fn shake_noise(time: f32, seed: f32) -> f32 {
+    (time + seed * 13.7).sin() * 0.5
+        + (time * 2.3 + seed * 7.1).sin() * 0.3
+        + (time * 4.7 + seed * 3.3).sin() * 0.2
+}
+
+/// Color of `top` drawn over `bottom`
+fn blend_over(top: Color, bottom: Color) -> Color {
+    let alpha = top.a + bottom.a * (1.0 - top.a);
+    if alpha <= 0.0 {
+        return Color::TRANSPARENT;
+    }
+
+    let bottom_weight = bottom.a * (1.0 - top.a);
+    let mix = |top_value: f32, bottom_value: f32| {
+        (top_value * top.a + bottom_value * bottom_weight) / alpha
+    };
+
+    Color::rgba(
+        mix(top.r, bottom.r),
+        mix(top.g, bottom.g),
+        mix(top.b, bottom.b),
+        alpha,
+    )
+}
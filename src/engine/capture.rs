@@ -0,0 +1,506 @@
+//! Gameplay capture to short GIF clips (MP4 with the `video` feature), to share bugs and for
+//! marketing material
+//!
+//! The `CapturePlugin` records the presented frames while toggled by a key (F9), or through the
+//! `ClipRecorder` it adds to the world as a resource. Every captured frame is copied back from
+//! the swapchain and downsampled, the clip is encoded on a background thread once the recording
+//! stops and saved in the `captures` directory next to the logs.
+//!
+//! Copying a frame back waits for the GPU: frames are captured at a low rate, 15 per second by
+//! default.
+
+// standard imports
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// SDL2 imports
+use sdl2::keyboard::Keycode;
+
+// import the engine modules
+use super::crash;
+use super::events::{EngineEvent, InputEvent};
+use super::game::Context;
+use super::paths::DirKind;
+use super::plugin::EnginePlugin;
+use super::time::Time;
+
+// other imports
+#[cfg(feature = "video")]
+use ffmpeg_next as ffmpeg;
+
+/// Container and codec of the clips
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureFormat {
+    /// Looping GIF, playable everywhere but with 256 colors per frame
+    Gif,
+    /// H.264 video in an MP4 container, encoded by FFmpeg
+    #[cfg(feature = "video")]
+    Mp4,
+}
+
+impl CaptureFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            CaptureFormat::Gif => "gif",
+            #[cfg(feature = "video")]
+            CaptureFormat::Mp4 => "mp4",
+        }
+    }
+}
+
+/// Downsampled frame, tightly packed RGBA8
+struct ClipFrame {
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+/// Average blocks of `factor` by `factor` pixels, opaque since GIF treats alpha as transparency
+fn downsample(pixels: &[u8], width: u32, height: u32, factor: u32) -> ClipFrame {
+    let (clip_width, clip_height) = ((width / factor).max(1), (height / factor).max(1));
+    let mut clip = Vec::with_capacity((clip_width * clip_height * 4) as usize);
+
+    for y in 0..clip_height {
+        for x in 0..clip_width {
+            let mut sum = [0u32; 3];
+            let mut count = 0;
+            for source_y in (y * factor)..((y + 1) * factor).min(height) {
+                for source_x in (x * factor)..((x + 1) * factor).min(width) {
+                    let index = ((source_y * width + source_x) * 4) as usize;
+                    for (channel, total) in sum.iter_mut().enumerate() {
+                        *total += u32::from(pixels[index + channel]);
+                    }
+                    count += 1;
+                }
+            }
+            for total in sum.iter() {
+                clip.push((total / count.max(1)) as u8);
+            }
+            clip.push(255);
+        }
+    }
+
+    ClipFrame {
+        pixels: clip,
+        width: clip_width,
+        height: clip_height,
+    }
+}
+
+fn encode_gif(frames: &[ClipFrame], fps: u32, path: &Path) -> Result<(), String> {
+    let error = |e: gif::EncodingError| format!("Couldn't encode '{}': {}", path.display(), e);
+    let first = frames.first().ok_or("No frame captured")?;
+
+    let file =
+        File::create(path).map_err(|e| format!("Couldn't create '{}': {}", path.display(), e))?;
+    let mut encoder = gif::Encoder::new(
+        BufWriter::new(file),
+        first.width as u16,
+        first.height as u16,
+        &[],
+    )
+    .map_err(error)?;
+    encoder.set_repeat(gif::Repeat::Infinite).map_err(error)?;
+
+    // In hundredths of second, browsers slow down shorter delays
+    let delay = (100 / fps.max(1)).max(2) as u16;
+    for clip_frame in frames {
+        let mut pixels = clip_frame.pixels.clone();
+        let mut frame = gif::Frame::from_rgba_speed(
+            clip_frame.width as u16,
+            clip_frame.height as u16,
+            &mut pixels,
+            10,
+        );
+        frame.delay = delay;
+        encoder.write_frame(&frame).map_err(error)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "video")]
+fn encode_mp4(frames: &[ClipFrame], fps: u32, path: &Path) -> Result<(), String> {
+    let error = |e: ffmpeg::Error| format!("Couldn't encode '{}': {}", path.display(), e);
+    let first = frames.first().ok_or("No frame captured")?;
+    // 4:2:0 chroma needs even sizes
+    let (width, height) = (first.width & !1, first.height & !1);
+
+    ffmpeg::init().map_err(error)?;
+    let mut output = ffmpeg::format::output(&path).map_err(error)?;
+    let codec = ffmpeg::encoder::find(ffmpeg::codec::Id::H264)
+        .ok_or_else(|| String::from("FFmpeg was built without H.264 encoder"))?;
+    let global_header = output
+        .format()
+        .flags()
+        .contains(ffmpeg::format::Flags::GLOBAL_HEADER);
+
+    let mut stream = output.add_stream(codec).map_err(error)?;
+    let mut encoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())
+        .map_err(error)?
+        .encoder()
+        .video()
+        .map_err(error)?;
+    let time_base = ffmpeg::Rational::new(1, fps as i32);
+    encoder.set_width(width);
+    encoder.set_height(height);
+    encoder.set_format(ffmpeg::format::Pixel::YUV420P);
+    encoder.set_time_base(time_base);
+    encoder.set_frame_rate(Some(ffmpeg::Rational::new(fps as i32, 1)));
+    if global_header {
+        encoder.set_flags(ffmpeg::codec::Flags::GLOBAL_HEADER);
+    }
+    let mut encoder = encoder.open_as(codec).map_err(error)?;
+    stream.set_parameters(&encoder);
+
+    output.write_header().map_err(error)?;
+    let stream_time_base = output
+        .stream(0)
+        .map(|stream| stream.time_base())
+        .unwrap_or(time_base);
+
+    let mut scaler = ffmpeg::software::scaling::Context::get(
+        ffmpeg::format::Pixel::RGBA,
+        width,
+        height,
+        ffmpeg::format::Pixel::YUV420P,
+        width,
+        height,
+        ffmpeg::software::scaling::Flags::BILINEAR,
+    )
+    .map_err(error)?;
+
+    for (index, clip_frame) in frames.iter().enumerate() {
+        let mut rgba = ffmpeg::frame::Video::new(ffmpeg::format::Pixel::RGBA, width, height);
+        let stride = rgba.stride(0);
+        let row = width as usize * 4;
+        for y in 0..height as usize {
+            let source = y * clip_frame.width as usize * 4;
+            rgba.data_mut(0)[y * stride..y * stride + row]
+                .copy_from_slice(&clip_frame.pixels[source..source + row]);
+        }
+
+        let mut yuv = ffmpeg::frame::Video::empty();
+        scaler.run(&rgba, &mut yuv).map_err(error)?;
+        yuv.set_pts(Some(index as i64));
+
+        encoder.send_frame(&yuv).map_err(error)?;
+        write_packets(&mut encoder, &mut output, time_base, stream_time_base).map_err(error)?;
+    }
+
+    encoder.send_eof().map_err(error)?;
+    write_packets(&mut encoder, &mut output, time_base, stream_time_base).map_err(error)?;
+    output.write_trailer().map_err(error)
+}
+
+/// Write the packets the encoder has ready
+#[cfg(feature = "video")]
+fn write_packets(
+    encoder: &mut ffmpeg::encoder::Video,
+    output: &mut ffmpeg::format::context::Output,
+    time_base: ffmpeg::Rational,
+    stream_time_base: ffmpeg::Rational,
+) -> Result<(), ffmpeg::Error> {
+    let mut packet = ffmpeg::Packet::empty();
+    while encoder.receive_packet(&mut packet).is_ok() {
+        packet.set_stream(0);
+        packet.rescale_ts(time_base, stream_time_base);
+        packet.write_interleaved(output)?;
+    }
+
+    Ok(())
+}
+
+/// Records the presented frames and encodes them into clips, a world resource added by the
+/// `CapturePlugin`
+pub struct ClipRecorder {
+    format: CaptureFormat,
+    fps: u32,
+    /// Frames wider are downsampled by a whole factor
+    max_width: u32,
+    max_seconds: f32,
+    directory: PathBuf,
+
+    recording: bool,
+    since_capture: f32,
+    frames: Vec<ClipFrame>,
+    /// Clips being encoded, the result is logged when they finish
+    encodings: Vec<JoinHandle<Result<PathBuf, String>>>,
+    last_clip: Option<PathBuf>,
+}
+
+impl ClipRecorder {
+    fn new() -> Self {
+        Self {
+            format: CaptureFormat::Gif,
+            fps: 15,
+            max_width: 480,
+            max_seconds: 10.0,
+            directory: PathBuf::from("captures"),
+            recording: false,
+            since_capture: 0.0,
+            frames: Vec::new(),
+            encodings: Vec::new(),
+            last_clip: None,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Start a clip, the frame presented before the next update is the first one
+    pub fn start(&mut self) {
+        if self.recording {
+            return;
+        }
+
+        self.recording = true;
+        self.frames.clear();
+        // Captured right away
+        self.since_capture = 1.0 / self.fps as f32;
+    }
+
+    /// Stop recording and encode the clip in the background
+    pub fn stop(&mut self) {
+        if !self.recording {
+            return;
+        }
+        self.recording = false;
+
+        if self.frames.is_empty() {
+            return;
+        }
+
+        let frames = std::mem::take(&mut self.frames);
+        let (format, fps) = (self.format, self.fps);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis());
+        let directory = self.directory.clone();
+        let path = directory.join(format!("capture-{}.{}", timestamp, format.extension()));
+
+        let encoding = thread::Builder::new()
+            .name(String::from("clip encoder"))
+            .spawn(move || {
+                fs::create_dir_all(&directory).map_err(|e| {
+                    format!("Couldn't create directory '{}': {}", directory.display(), e)
+                })?;
+                match format {
+                    CaptureFormat::Gif => encode_gif(&frames, fps, &path)?,
+                    #[cfg(feature = "video")]
+                    CaptureFormat::Mp4 => encode_mp4(&frames, fps, &path)?,
+                }
+                Ok(path)
+            });
+
+        match encoding {
+            Ok(encoding) => self.encodings.push(encoding),
+            Err(e) => crash::log(&format!("Couldn't start encoding the clip: {}", e)),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        if self.recording {
+            self.stop();
+        } else {
+            self.start();
+        }
+    }
+
+    /// Check if clips are still being encoded
+    pub fn is_encoding(&self) -> bool {
+        !self.encodings.is_empty()
+    }
+
+    /// Path of the last clip saved
+    pub fn last_clip(&self) -> Option<&Path> {
+        self.last_clip.as_deref()
+    }
+
+    /// Advance the time of the recording, returns true if a frame should be captured
+    fn advance(&mut self, delta: f32) -> bool {
+        self.poll_encodings(false);
+
+        if !self.recording {
+            return false;
+        }
+
+        self.since_capture += delta;
+        if self.since_capture < 1.0 / self.fps as f32 {
+            return false;
+        }
+        self.since_capture = 0.0;
+
+        true
+    }
+
+    /// Add a frame read back from the swapchain, the clip ends at its maximum length
+    fn push_frame(&mut self, pixels: &[u8], width: u32, height: u32) {
+        let factor = ((width + self.max_width - 1) / self.max_width).max(1);
+        let frame = downsample(pixels, width, height, factor);
+
+        // Frames of another size are dropped until the recording ends, after a resize
+        let same_size = self.frames.first().map_or(true, |first| {
+            first.width == frame.width && first.height == frame.height
+        });
+        if same_size {
+            self.frames.push(frame);
+        }
+
+        if self.frames.len() as f32 >= self.max_seconds * self.fps as f32 {
+            self.stop();
+        }
+    }
+
+    /// Log the clips encoded, waiting for the ones in progress if `wait` is set
+    fn poll_encodings(&mut self, wait: bool) {
+        let mut index = 0;
+        while index < self.encodings.len() {
+            if !wait && !self.encodings[index].is_finished() {
+                index += 1;
+                continue;
+            }
+
+            match self.encodings.swap_remove(index).join() {
+                Ok(Ok(path)) => {
+                    crash::log(&format!("Saved clip '{}'", path.display()));
+                    self.last_clip = Some(path);
+                }
+                Ok(Err(e)) => crash::log(&e),
+                Err(_) => crash::log("The clip encoder panicked"),
+            }
+        }
+    }
+}
+
+/// Clip recorder toggled by a key, added with `Engine::add_plugin`
+pub struct CapturePlugin {
+    toggle_key: Keycode,
+    /// Moved to the world when the plugin is added
+    recorder: Option<ClipRecorder>,
+    /// A copy of the frame drawn after the last update was asked for
+    frame_kept: bool,
+}
+
+impl CapturePlugin {
+    /// Records GIF clips of up to 10 seconds, 480 pixels wide at 15 frames per second, with F9
+    pub fn new() -> Self {
+        Self {
+            toggle_key: Keycode::F9,
+            recorder: Some(ClipRecorder::new()),
+            frame_kept: false,
+        }
+    }
+
+    pub fn with_toggle_key(mut self, key: Keycode) -> Self {
+        self.toggle_key = key;
+        self
+    }
+
+    pub fn with_format(mut self, format: CaptureFormat) -> Self {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.format = format;
+        }
+        self
+    }
+
+    /// Frames captured per second, each one waits for the GPU
+    pub fn with_fps(mut self, fps: u32) -> Self {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.fps = fps.max(1);
+        }
+        self
+    }
+
+    /// Width in pixels the frames are downsampled under
+    pub fn with_max_width(mut self, width: u32) -> Self {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.max_width = width.max(1);
+        }
+        self
+    }
+
+    /// Length in seconds a recording stops at
+    pub fn with_max_seconds(mut self, seconds: f32) -> Self {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.max_seconds = seconds;
+        }
+        self
+    }
+}
+
+impl Default for CapturePlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EnginePlugin for CapturePlugin {
+    fn name(&self) -> &str {
+        "capture"
+    }
+
+    fn build(&mut self, ctx: &mut Context) {
+        if let Some(mut recorder) = self.recorder.take() {
+            recorder.directory = ctx.paths.resolve(DirKind::Logs).join("captures");
+            ctx.world.insert_resource(recorder);
+        }
+    }
+
+    fn on_update(&mut self, time: &Time, ctx: &mut Context) {
+        // The frames are only copied when captured, the copy asked for the last time is read now
+        if self.frame_kept {
+            self.frame_kept = false;
+            let captured = match ctx.try_video() {
+                Some(video) => video.gl_handler.capture_last_frame(),
+                None => return,
+            };
+            if let Some(mut recorder) = ctx.world.resource_mut::<ClipRecorder>() {
+                match captured {
+                    Ok(Some((pixels, width, height))) if recorder.is_recording() => {
+                        recorder.push_frame(&pixels, width, height)
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        crash::log(&format!("Couldn't capture the frame: {}", e));
+                        recorder.stop();
+                    }
+                }
+            }
+        }
+
+        let due = match ctx.world.resource_mut::<ClipRecorder>() {
+            Some(mut recorder) => recorder.advance(time.delta()),
+            None => return,
+        };
+        if let Some(video) = ctx.try_video().filter(|_| due) {
+            video.keep_next_frame();
+            self.frame_kept = true;
+        }
+    }
+
+    fn on_event(&mut self, event: &EngineEvent, ctx: &mut Context) {
+        if let EngineEvent::Input(InputEvent::KeyDown {
+            key, repeat: false, ..
+        }) = event
+        {
+            if *key == self.toggle_key {
+                if let Some(mut recorder) = ctx.world.resource_mut::<ClipRecorder>() {
+                    recorder.toggle();
+                }
+            }
+        }
+    }
+
+    fn shutdown(&mut self, ctx: &mut Context) {
+        // The clip being recorded is saved before the game exits
+        if let Some(mut recorder) = ctx.world.resource_mut::<ClipRecorder>() {
+            recorder.stop();
+            recorder.poll_encodings(true);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Kinematic character controller for side-view games
+//!
+//! The ground is found through the `GroundQuery` trait shared with the vehicles.
+//! Coordinates follow the screen, with the y axis pointing down.
+
+// import the engine modules
+use super::vehicle::GroundQuery;
+
+// other imports
+use cgmath::Vector2;
+
+/// How the character is currently moving
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovementMode {
+    Grounded,
+    Airborne,
+    Swimming,
+}
+
+/// Inputs given to the character every update
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CharacterInput {
+    /// Horizontal movement, in -1.0..=1.0
+    pub move_x: f32,
+    /// Vertical movement while swimming, in -1.0..=1.0 (negative goes up)
+    pub move_y: f32,
+    pub jump: bool,
+}
+
+/// Tuning of the character movement
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CharacterParams {
+    pub gravity: f32,
+    pub max_fall_speed: f32,
+    pub move_speed: f32,
+    /// Horizontal acceleration on the ground, in the air it's halved
+    pub acceleration: f32,
+    pub jump_speed: f32,
+    pub swim_speed: f32,
+    pub swim_acceleration: f32,
+}
+
+impl Default for CharacterParams {
+    fn default() -> Self {
+        Self {
+            gravity: 980.0,
+            max_fall_speed: 900.0,
+            move_speed: 250.0,
+            acceleration: 2000.0,
+            jump_speed: 450.0,
+            swim_speed: 150.0,
+            swim_acceleration: 600.0,
+        }
+    }
+}
+
+/// Fluid surrounding the character, changing its movement model to swimming
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Medium {
+    /// Fraction of the gravity cancelled (above 1.0 the character floats up)
+    pub buoyancy: f32,
+    /// Velocity lost every second
+    pub drag: f32,
+    /// Flow pushing the character
+    pub current: Vector2<f32>,
+    /// Height of the surface, where the character can jump out
+    pub surface: f32,
+}
+
+/// Character moved by its inputs, gravity and the surrounding medium
+#[derive(Clone, Debug)]
+pub struct CharacterController {
+    pub params: CharacterParams,
+    pub position: Vector2<f32>,
+    pub velocity: Vector2<f32>,
+    pub half_extents: Vector2<f32>,
+    mode: MovementMode,
+    medium: Option<Medium>,
+}
+
+impl CharacterController {
+    pub fn new(params: CharacterParams, position: Vector2<f32>, half_extents: Vector2<f32>) -> Self {
+        Self {
+            params,
+            position,
+            velocity: Vector2::new(0.0, 0.0),
+            half_extents,
+            mode: MovementMode::Airborne,
+            medium: None,
+        }
+    }
+
+    pub fn get_mode(&self) -> MovementMode {
+        self.mode
+    }
+
+    pub fn get_medium(&self) -> Option<Medium> {
+        self.medium
+    }
+
+    /// Enter or leave a fluid, switching between swimming and the usual movement
+    pub fn set_medium(&mut self, medium: Option<Medium>) {
+        self.medium = medium;
+
+        self.mode = match (medium, self.mode) {
+            (Some(_), _) => MovementMode::Swimming,
+            (None, MovementMode::Swimming) => MovementMode::Airborne,
+            (None, mode) => mode,
+        };
+    }
+
+    /// Height of the top of the character
+    pub fn head(&self) -> f32 {
+        self.position.y - self.half_extents.y
+    }
+
+    /// Height of the bottom of the character
+    pub fn feet(&self) -> f32 {
+        self.position.y + self.half_extents.y
+    }
+
+    fn approach(current: f32, target: f32, step: f32) -> f32 {
+        if current < target {
+            (current + step).min(target)
+        } else {
+            (current - step).max(target)
+        }
+    }
+
+    /// Advance the character by `dt` seconds
+    pub fn update(&mut self, dt: f32, input: CharacterInput, ground: &dyn GroundQuery) {
+        let params = self.params;
+
+        match (self.mode, self.medium) {
+            (MovementMode::Swimming, Some(medium)) => {
+                let target = Vector2::new(input.move_x, input.move_y) * params.swim_speed;
+                let step = params.swim_acceleration * dt;
+
+                self.velocity.x = Self::approach(self.velocity.x, target.x, step);
+                self.velocity.y = Self::approach(self.velocity.y, target.y, step);
+                self.velocity.y += params.gravity * (1.0 - medium.buoyancy) * dt;
+                self.velocity *= 1.0 / (1.0 + medium.drag * dt);
+
+                // Jump out when the head reaches the surface
+                if input.jump && self.head() <= medium.surface {
+                    self.velocity.y = -params.jump_speed;
+                }
+
+                self.position += (self.velocity + medium.current) * dt;
+            }
+            (mode, _) => {
+                let acceleration = if mode == MovementMode::Grounded {
+                    params.acceleration
+                } else {
+                    params.acceleration / 2.0
+                };
+                self.velocity.x = Self::approach(
+                    self.velocity.x,
+                    input.move_x * params.move_speed,
+                    acceleration * dt,
+                );
+
+                if input.jump && mode == MovementMode::Grounded {
+                    self.velocity.y = -params.jump_speed;
+                    self.mode = MovementMode::Airborne;
+                }
+
+                self.velocity.y = (self.velocity.y + params.gravity * dt).min(params.max_fall_speed);
+                self.position.x += self.velocity.x * dt;
+                self.position.y += self.velocity.y * dt;
+            }
+        }
+
+        self.resolve_ground(ground);
+    }
+
+    /// Stand on the ground below the feet, if touching it
+    fn resolve_ground(&mut self, ground: &dyn GroundQuery) {
+        if self.velocity.y < 0.0 {
+            if self.mode == MovementMode::Grounded {
+                self.mode = MovementMode::Airborne;
+            }
+            return;
+        }
+
+        // Probe a bit below the feet, to stay grounded on slopes going down
+        let probe = 2.0;
+        let hit = ground.raycast(self.position, Vector2::new(0.0, 1.0), self.half_extents.y + probe);
+
+        match hit {
+            Some(hit) => {
+                self.position.y += hit.distance - self.half_extents.y;
+                self.velocity.y = 0.0;
+                if self.mode != MovementMode::Swimming {
+                    self.mode = MovementMode::Grounded;
+                }
+            }
+            None if self.mode == MovementMode::Grounded => self.mode = MovementMode::Airborne,
+            None => {}
+        }
+    }
+}
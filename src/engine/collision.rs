@@ -0,0 +1,987 @@
+//! Lightweight 2D collision detection: shape tests, swept shapes, ray casts and tile grids
+//!
+//! Enough for platformers and top-down games without a physics engine: the tests return
+//! the normal and depth needed to push shapes apart, the game decides how to respond.
+//! The world queries (`raycast`, `shape_cast`) look through the `Collider` components of
+//! the entities and the `TileCollider` resource of the world.
+
+// import the engine modules
+use super::ecs::{Entity, World};
+use super::geometry::{Aabb, Circle};
+use super::math::Vec2;
+#[cfg(feature = "xml")]
+use super::tilemap::TileMap;
+use super::transform::{GlobalTransform, Transform};
+
+// other imports
+use cgmath::InnerSpace;
+
+/// Margin keeping boxes resting on a tile from touching the tiles next to it
+const EPSILON: f32 = 1e-4;
+
+/// Overlap of two shapes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contact {
+    /// Unit vector from the first shape to the second one: moving the second shape by
+    /// `normal * depth` (or the first one by the opposite) separates them
+    pub normal: Vec2,
+    pub depth: f32,
+    /// Point in the middle of the overlap
+    pub point: Vec2,
+}
+
+impl Contact {
+    /// Same contact seen from the other shape
+    pub fn flipped(self) -> Self {
+        Self {
+            normal: -self.normal,
+            ..self
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    Aabb(Aabb),
+    Circle(Circle),
+}
+
+impl Shape {
+    pub fn bounds(&self) -> Aabb {
+        match self {
+            Shape::Aabb(aabb) => *aabb,
+            Shape::Circle(circle) => circle.bounds(),
+        }
+    }
+
+    pub fn center(&self) -> Vec2 {
+        match self {
+            Shape::Aabb(aabb) => aabb.center(),
+            Shape::Circle(circle) => circle.center,
+        }
+    }
+
+    pub fn translated(&self, offset: Vec2) -> Shape {
+        match self {
+            Shape::Aabb(aabb) => Shape::Aabb(Aabb::new(aabb.min + offset, aabb.max + offset)),
+            Shape::Circle(circle) => {
+                Shape::Circle(Circle::new(circle.center + offset, circle.radius))
+            }
+        }
+    }
+
+    pub fn collide(&self, other: &Shape) -> Option<Contact> {
+        match (self, other) {
+            (Shape::Aabb(a), Shape::Aabb(b)) => aabb_aabb(a, b),
+            (Shape::Aabb(a), Shape::Circle(b)) => aabb_circle(a, b),
+            (Shape::Circle(a), Shape::Aabb(b)) => aabb_circle(b, a).map(Contact::flipped),
+            (Shape::Circle(a), Shape::Circle(b)) => circle_circle(a, b),
+        }
+    }
+
+    /// First hit of a ray going from `origin` in `direction` for at most `max_distance`
+    pub fn raycast(&self, origin: Vec2, direction: Vec2, max_distance: f32) -> Option<RayHit> {
+        match self {
+            Shape::Aabb(aabb) => raycast_aabb(origin, direction, max_distance, aabb),
+            Shape::Circle(circle) => raycast_circle(origin, direction, max_distance, circle),
+        }
+    }
+
+    /// Point of the shape nearest to another one
+    fn closest_point(&self, point: Vec2) -> Vec2 {
+        match self {
+            Shape::Aabb(aabb) => aabb.closest_point(point),
+            Shape::Circle(circle) => {
+                let direction = normalized(point - circle.center).unwrap_or(Vec2::new(0.0, 0.0));
+                circle.center + direction * circle.radius
+            }
+        }
+    }
+}
+
+impl From<Aabb> for Shape {
+    fn from(aabb: Aabb) -> Self {
+        Shape::Aabb(aabb)
+    }
+}
+
+impl From<Circle> for Shape {
+    fn from(circle: Circle) -> Self {
+        Shape::Circle(circle)
+    }
+}
+
+/// Contact of two boxes, separated along the axis of least penetration
+pub fn aabb_aabb(a: &Aabb, b: &Aabb) -> Option<Contact> {
+    let overlap = a.overlap(b)?;
+    let size = overlap.size();
+    if size.x <= 0.0 || size.y <= 0.0 {
+        return None;
+    }
+
+    let direction = b.center() - a.center();
+    let (normal, depth) = if size.x < size.y {
+        (Vec2::new(1f32.copysign(direction.x), 0.0), size.x)
+    } else {
+        (Vec2::new(0.0, 1f32.copysign(direction.y)), size.y)
+    };
+
+    Some(Contact {
+        normal,
+        depth,
+        point: overlap.center(),
+    })
+}
+
+pub fn circle_circle(a: &Circle, b: &Circle) -> Option<Contact> {
+    let offset = b.center - a.center;
+    let distance = offset.magnitude();
+    let depth = a.radius + b.radius - distance;
+    if depth <= 0.0 {
+        return None;
+    }
+
+    // Same centers, any direction works
+    let normal = if distance > 0.0 {
+        offset / distance
+    } else {
+        Vec2::new(1.0, 0.0)
+    };
+
+    Some(Contact {
+        normal,
+        depth,
+        point: a.center + normal * (a.radius - depth / 2.0),
+    })
+}
+
+pub fn aabb_circle(aabb: &Aabb, circle: &Circle) -> Option<Contact> {
+    let closest = aabb.closest_point(circle.center);
+    let offset = circle.center - closest;
+    let distance2 = offset.magnitude2();
+
+    if distance2 > 0.0 {
+        if distance2 >= circle.radius * circle.radius {
+            return None;
+        }
+
+        let distance = distance2.sqrt();
+        return Some(Contact {
+            normal: offset / distance,
+            depth: circle.radius - distance,
+            point: closest,
+        });
+    }
+
+    // Center inside the box, pushed out through the nearest face
+    let faces = [
+        (circle.center.x - aabb.min.x, Vec2::new(-1.0, 0.0)),
+        (aabb.max.x - circle.center.x, Vec2::new(1.0, 0.0)),
+        (circle.center.y - aabb.min.y, Vec2::new(0.0, -1.0)),
+        (aabb.max.y - circle.center.y, Vec2::new(0.0, 1.0)),
+    ];
+    let (distance, normal) =
+        faces.iter().copied().fold(
+            faces[0],
+            |best, face| if face.0 < best.0 { face } else { best },
+        );
+
+    Some(Contact {
+        normal,
+        depth: circle.radius + distance,
+        point: circle.center + normal * distance,
+    })
+}
+
+/// First hit of a moving box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SweepHit {
+    /// Fraction of the motion done before the hit, 0.0 if the boxes already overlap
+    pub time: f32,
+    /// Normal of the surface hit, facing the moving box
+    pub normal: Vec2,
+    /// Center of the moving box when it hits
+    pub center: Vec2,
+}
+
+/// Move a box by `motion` against a static one, finding the first time they touch.
+/// Fast movers can't tunnel through thin obstacles like with overlap tests at the end position
+pub fn sweep_aabb(moving: &Aabb, motion: Vec2, target: &Aabb) -> Option<SweepHit> {
+    if let Some(contact) = aabb_aabb(target, moving) {
+        return Some(SweepHit {
+            time: 0.0,
+            normal: contact.normal,
+            center: moving.center(),
+        });
+    }
+
+    // Ray of the center against the target grown by the moving box
+    let half = moving.half_extents();
+    let grown = Aabb::new(target.min - half, target.max + half);
+    let origin = moving.center();
+
+    let slab = |origin: f32, motion: f32, min: f32, max: f32| -> Option<(f32, f32)> {
+        if motion == 0.0 {
+            if origin <= min || origin >= max {
+                return None;
+            }
+            return Some((f32::NEG_INFINITY, f32::INFINITY));
+        }
+
+        let (t1, t2) = ((min - origin) / motion, (max - origin) / motion);
+        Some((t1.min(t2), t1.max(t2)))
+    };
+
+    let (entry_x, exit_x) = slab(origin.x, motion.x, grown.min.x, grown.max.x)?;
+    let (entry_y, exit_y) = slab(origin.y, motion.y, grown.min.y, grown.max.y)?;
+
+    let entry = entry_x.max(entry_y);
+    let exit = exit_x.min(exit_y);
+    if entry >= exit || entry < 0.0 || entry > 1.0 {
+        return None;
+    }
+
+    let normal = if entry_x > entry_y {
+        Vec2::new(-1f32.copysign(motion.x), 0.0)
+    } else {
+        Vec2::new(0.0, -1f32.copysign(motion.y))
+    };
+
+    Some(SweepHit {
+        time: entry,
+        normal,
+        center: origin + motion * entry,
+    })
+}
+
+/// Move a shape by `motion` against a static one, finding the first time they touch
+pub fn sweep(moving: &Shape, motion: Vec2, target: &Shape) -> Option<SweepHit> {
+    match (moving, target) {
+        (Shape::Aabb(a), Shape::Aabb(b)) => sweep_aabb(a, motion, b),
+        (Shape::Circle(a), Shape::Circle(b)) => {
+            if let Some(contact) = circle_circle(b, a) {
+                return Some(SweepHit {
+                    time: 0.0,
+                    normal: contact.normal,
+                    center: a.center,
+                });
+            }
+
+            // Ray of the center against the target grown by the moving circle
+            let grown = Circle::new(b.center, a.radius + b.radius);
+            sweep_center(a.center, motion, &Shape::Circle(grown))
+        }
+        (Shape::Circle(a), Shape::Aabb(b)) => sweep_circle_aabb(a, motion, b),
+        (Shape::Aabb(a), Shape::Circle(b)) => {
+            // The circle moving the other way hits the box at the same time
+            let hit = sweep_circle_aabb(b, -motion, a)?;
+            Some(SweepHit {
+                time: hit.time,
+                normal: -hit.normal,
+                center: a.center() + motion * hit.time,
+            })
+        }
+    }
+}
+
+fn sweep_circle_aabb(moving: &Circle, motion: Vec2, target: &Aabb) -> Option<SweepHit> {
+    if let Some(contact) = aabb_circle(target, moving) {
+        return Some(SweepHit {
+            time: 0.0,
+            normal: contact.normal,
+            center: moving.center,
+        });
+    }
+
+    // The center hits the box grown by the radius, with rounded corners
+    let radius = Vec2::new(moving.radius, moving.radius);
+    let grown = Aabb::new(target.min - radius, target.max + radius);
+    let hit = sweep_center(moving.center, motion, &Shape::Aabb(grown))?;
+
+    let in_corner = (hit.center.x < target.min.x || hit.center.x > target.max.x)
+        && (hit.center.y < target.min.y || hit.center.y > target.max.y);
+    if !in_corner {
+        return Some(hit);
+    }
+
+    let corner = Circle::new(target.closest_point(hit.center), moving.radius);
+    sweep_center(moving.center, motion, &Shape::Circle(corner))
+}
+
+/// Sweep of a point, as a ray along the motion
+fn sweep_center(center: Vec2, motion: Vec2, target: &Shape) -> Option<SweepHit> {
+    let length = motion.magnitude();
+    let hit = target.raycast(center, motion, length)?;
+
+    Some(SweepHit {
+        time: hit.distance / length,
+        normal: hit.normal,
+        center: hit.point,
+    })
+}
+
+/// First hit of a ray
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    /// Entity of the collider hit, None for tiles and shapes outside of the world
+    pub entity: Option<Entity>,
+    pub point: Vec2,
+    /// Normal of the surface hit, facing the origin (opposite to the ray if it starts inside)
+    pub normal: Vec2,
+    /// From the origin of the ray to the point
+    pub distance: f32,
+}
+
+impl RayHit {
+    fn inside(origin: Vec2, direction: Vec2) -> Self {
+        Self {
+            entity: None,
+            point: origin,
+            normal: -direction,
+            distance: 0.0,
+        }
+    }
+}
+
+pub fn raycast_aabb(
+    origin: Vec2,
+    direction: Vec2,
+    max_distance: f32,
+    aabb: &Aabb,
+) -> Option<RayHit> {
+    let direction = normalized(direction)?;
+    if aabb.contains(origin) {
+        return Some(RayHit::inside(origin, direction));
+    }
+
+    let mut entry = f32::NEG_INFINITY;
+    let mut exit = f32::INFINITY;
+    let mut normal = Vec2::new(0.0, 0.0);
+    for axis in 0..2 {
+        if direction[axis] == 0.0 {
+            if origin[axis] < aabb.min[axis] || origin[axis] > aabb.max[axis] {
+                return None;
+            }
+            continue;
+        }
+
+        let t1 = (aabb.min[axis] - origin[axis]) / direction[axis];
+        let t2 = (aabb.max[axis] - origin[axis]) / direction[axis];
+        if t1.min(t2) > entry {
+            entry = t1.min(t2);
+            normal = Vec2::new(0.0, 0.0);
+            normal[axis] = -1f32.copysign(direction[axis]);
+        }
+        exit = exit.min(t1.max(t2));
+    }
+
+    if entry > exit || entry < 0.0 || entry > max_distance {
+        return None;
+    }
+
+    Some(RayHit {
+        entity: None,
+        point: origin + direction * entry,
+        normal,
+        distance: entry,
+    })
+}
+
+pub fn raycast_circle(
+    origin: Vec2,
+    direction: Vec2,
+    max_distance: f32,
+    circle: &Circle,
+) -> Option<RayHit> {
+    let direction = normalized(direction)?;
+    let offset = origin - circle.center;
+    let outside = offset.magnitude2() - circle.radius * circle.radius;
+    if outside <= 0.0 {
+        return Some(RayHit::inside(origin, direction));
+    }
+
+    // Nearest root of |offset + direction * t| = radius, if the ray goes towards the circle
+    let along = offset.dot(direction);
+    let discriminant = along * along - outside;
+    if along > 0.0 || discriminant < 0.0 {
+        return None;
+    }
+
+    let distance = -along - discriminant.sqrt();
+    if distance > max_distance {
+        return None;
+    }
+
+    let point = origin + direction * distance;
+    Some(RayHit {
+        entity: None,
+        point,
+        normal: normalized(point - circle.center).unwrap_or(-direction),
+        distance,
+    })
+}
+
+fn normalized(vector: Vec2) -> Option<Vec2> {
+    let length = vector.magnitude();
+    if length > 0.0 {
+        Some(vector / length)
+    } else {
+        None
+    }
+}
+
+/// Result of a box moved through a tile grid
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileMove {
+    /// Box at its new position
+    pub aabb: Aabb,
+    /// Motion actually done
+    pub motion: Vec2,
+    /// Normals of the tiles hit, (0, -1) when landing on the ground (Y goes down)
+    pub normal: Vec2,
+}
+
+impl TileMove {
+    pub fn hit_x(&self) -> bool {
+        self.normal.x != 0.0
+    }
+
+    pub fn hit_y(&self) -> bool {
+        self.normal.y != 0.0
+    }
+}
+
+/// Grid of solid and empty cells, e.g. the tiles of a map with a collision property
+#[derive(Clone, Debug)]
+pub struct TileCollider {
+    width: u32,
+    height: u32,
+    solid: Vec<bool>,
+    /// Top-left corner of the grid
+    pub origin: Vec2,
+    pub tile_size: Vec2,
+}
+
+impl TileCollider {
+    /// Grid of a layer of a map, in map pixels: tiles are solid if they have the property,
+    /// unless it's false. None if the layer doesn't exist
+    #[cfg(feature = "xml")]
+    pub fn from_tilemap(map: &TileMap, layer: &str, property: &str) -> Option<Self> {
+        let tiles = map.tile_layer(layer)?;
+
+        let mut collider = Self::new(
+            tiles.width,
+            tiles.height,
+            Vec2::new(map.tile_width as f32, map.tile_height as f32),
+        );
+        collider.origin = tiles.offset;
+        for (x, y) in map.tiles_with_property(layer, property) {
+            collider.set_solid(x as i32, y as i32, true);
+        }
+
+        Some(collider)
+    }
+
+    /// Empty grid
+    pub fn new(width: u32, height: u32, tile_size: Vec2) -> Self {
+        Self {
+            width,
+            height,
+            solid: vec![false; (width * height) as usize],
+            origin: Vec2::new(0.0, 0.0),
+            tile_size,
+        }
+    }
+
+    /// Place the grid in the world, e.g. from the `tile_bounds(0, 0)` of a `TileMapObject`
+    pub fn placed(mut self, origin: Vec2, tile_size: Vec2) -> Self {
+        self.origin = origin;
+        self.tile_size = tile_size;
+        self
+    }
+
+    pub fn set_solid(&mut self, x: i32, y: i32, solid: bool) {
+        if let Some(index) = self.index(x, y) {
+            self.solid[index] = solid;
+        }
+    }
+
+    /// Cells out of the grid are empty
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).map_or(false, |index| self.solid[index])
+    }
+
+    pub fn is_solid_at(&self, point: Vec2) -> bool {
+        let (x, y) = self.cell_at(point);
+        self.is_solid(x, y)
+    }
+
+    pub fn cell_at(&self, point: Vec2) -> (i32, i32) {
+        let local = point - self.origin;
+        (
+            (local.x / self.tile_size.x).floor() as i32,
+            (local.y / self.tile_size.y).floor() as i32,
+        )
+    }
+
+    pub fn cell_bounds(&self, x: i32, y: i32) -> Aabb {
+        let min = self.origin + Vec2::new(x as f32 * self.tile_size.x, y as f32 * self.tile_size.y);
+        Aabb::new(min, min + self.tile_size)
+    }
+
+    /// Solid cells overlapping a box
+    pub fn solid_cells(&self, aabb: &Aabb) -> Vec<(i32, i32)> {
+        let (min_x, min_y) = self.cell_at(aabb.min);
+        let (max_x, max_y) = self.cell_at(aabb.max);
+
+        let mut cells = Vec::new();
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                if self.is_solid(x, y) {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    /// Contacts of a shape with the solid cells, normals going from the cells to the shape
+    pub fn collide(&self, shape: &Shape) -> Vec<Contact> {
+        self.solid_cells(&shape.bounds())
+            .into_iter()
+            .filter_map(|(x, y)| Shape::Aabb(self.cell_bounds(x, y)).collide(shape))
+            .collect()
+    }
+
+    /// First solid cell hit by a ray, walking the cells it crosses
+    pub fn raycast(&self, origin: Vec2, direction: Vec2, max_distance: f32) -> Option<RayHit> {
+        let direction = normalized(direction)?;
+        let (mut x, mut y) = self.cell_at(origin);
+        if self.is_solid(x, y) {
+            return Some(RayHit::inside(origin, direction));
+        }
+
+        // Distances along the ray to the next cell borders, and between two borders
+        let cell = self.cell_bounds(x, y);
+        let border = |axis: usize| -> (f32, f32) {
+            if direction[axis] > 0.0 {
+                (
+                    (cell.max[axis] - origin[axis]) / direction[axis],
+                    self.tile_size[axis] / direction[axis],
+                )
+            } else if direction[axis] < 0.0 {
+                (
+                    (cell.min[axis] - origin[axis]) / direction[axis],
+                    -self.tile_size[axis] / direction[axis],
+                )
+            } else {
+                (f32::INFINITY, f32::INFINITY)
+            }
+        };
+        let (mut next_x, delta_x) = border(0);
+        let (mut next_y, delta_y) = border(1);
+        let step_x = if direction.x > 0.0 { 1 } else { -1 };
+        let step_y = if direction.y > 0.0 { 1 } else { -1 };
+
+        loop {
+            let (distance, normal) = if next_x < next_y {
+                x += step_x;
+                next_x += delta_x;
+                (next_x - delta_x, Vec2::new(-step_x as f32, 0.0))
+            } else {
+                y += step_y;
+                next_y += delta_y;
+                (next_y - delta_y, Vec2::new(0.0, -step_y as f32))
+            };
+            if distance > max_distance {
+                return None;
+            }
+
+            if self.is_solid(x, y) {
+                return Some(RayHit {
+                    entity: None,
+                    point: origin + direction * distance,
+                    normal,
+                    distance,
+                });
+            }
+
+            // Out of the grid and going away from it
+            let leaving_x =
+                (x < 0 && direction.x <= 0.0) || (x >= self.width as i32 && direction.x >= 0.0);
+            let leaving_y =
+                (y < 0 && direction.y <= 0.0) || (y >= self.height as i32 && direction.y >= 0.0);
+            if leaving_x || leaving_y {
+                return None;
+            }
+        }
+    }
+
+    /// First solid cell hit by a shape moved by `motion`
+    pub fn sweep(&self, shape: &Shape, motion: Vec2) -> Option<(SweepHit, Aabb)> {
+        let start = shape.bounds();
+        let swept = start.union(&shape.translated(motion).bounds());
+
+        self.solid_cells(&swept)
+            .into_iter()
+            .filter_map(|(x, y)| {
+                let cell = self.cell_bounds(x, y);
+                Some((sweep(shape, motion, &Shape::Aabb(cell))?, cell))
+            })
+            .min_by(|(a, _), (b, _)| a.time.partial_cmp(&b.time).unwrap())
+    }
+
+    /// Move a box, stopping it against the solid cells one axis at a time (X first).
+    /// Boxes already inside a cell can move out of it
+    pub fn move_aabb(&self, aabb: &Aabb, motion: Vec2) -> TileMove {
+        let mut moved = *aabb;
+        let mut done = Vec2::new(0.0, 0.0);
+        let mut normal = Vec2::new(0.0, 0.0);
+
+        for axis in 0..2 {
+            let (amount, hit) = self.move_axis(&moved, motion[axis], axis);
+
+            let mut offset = Vec2::new(0.0, 0.0);
+            offset[axis] = amount;
+            moved = Aabb::new(moved.min + offset, moved.max + offset);
+            done[axis] = amount;
+            if hit {
+                normal[axis] = -1f32.copysign(motion[axis]);
+            }
+        }
+
+        TileMove {
+            aabb: moved,
+            motion: done,
+            normal,
+        }
+    }
+
+    /// Distance a box can move along an axis, and whether a cell stopped it
+    fn move_axis(&self, aabb: &Aabb, amount: f32, axis: usize) -> (f32, bool) {
+        if amount == 0.0 {
+            return (0.0, false);
+        }
+
+        // Cells crossed by the move, without the ones only touched on the other axis
+        let other = 1 - axis;
+        let mut swept = *aabb;
+        if amount > 0.0 {
+            swept.max[axis] += amount;
+        } else {
+            swept.min[axis] += amount;
+        }
+        swept.min[other] += EPSILON;
+        swept.max[other] -= EPSILON;
+
+        let mut allowed = amount;
+        let mut hit = false;
+        for (x, y) in self.solid_cells(&swept) {
+            let cell = self.cell_bounds(x, y);
+
+            if amount > 0.0 {
+                let gap = cell.min[axis] - aabb.max[axis];
+                if gap >= -EPSILON && gap < allowed {
+                    allowed = gap.max(0.0);
+                    hit = true;
+                }
+            } else {
+                let gap = cell.max[axis] - aabb.min[axis];
+                if gap <= EPSILON && gap > allowed {
+                    allowed = gap.min(0.0);
+                    hit = true;
+                }
+            }
+        }
+
+        (allowed, hit)
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return None;
+        }
+
+        Some((y as u32 * self.width + x as u32) as usize)
+    }
+}
+
+/// Shape of an entity for the world queries, relative to its position (rotations and
+/// scales are ignored)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Collider {
+    pub shape: Shape,
+    /// Bits of the layers of the collider, matched with the masks of the queries
+    pub layers: u32,
+}
+
+impl Collider {
+    pub fn new(shape: impl Into<Shape>) -> Self {
+        Self {
+            shape: shape.into(),
+            layers: 1,
+        }
+    }
+
+    pub fn with_layers(mut self, layers: u32) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    /// Shape in the world for an entity at `position`
+    pub fn placed(&self, position: Vec2) -> Shape {
+        self.shape.translated(position)
+    }
+}
+
+/// Colliders a world query can hit
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryFilter {
+    /// Layers hit, every one by default
+    pub mask: u32,
+    /// Entities ignored, e.g. the one casting the ray
+    pub exclude: Vec<Entity>,
+    /// Hit the `TileCollider` resource of the world
+    pub tiles: bool,
+}
+
+impl QueryFilter {
+    pub fn new() -> Self {
+        Self {
+            mask: u32::MAX,
+            exclude: Vec::new(),
+            tiles: true,
+        }
+    }
+
+    pub fn mask(mut self, mask: u32) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    pub fn exclude(mut self, entity: Entity) -> Self {
+        self.exclude.push(entity);
+        self
+    }
+
+    pub fn without_tiles(mut self) -> Self {
+        self.tiles = false;
+        self
+    }
+
+    pub fn accepts(&self, entity: Entity, layers: u32) -> bool {
+        layers & self.mask != 0 && !self.exclude.contains(&entity)
+    }
+}
+
+impl Default for QueryFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// First hit of a shape moved through the world
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShapeHit {
+    /// Entity of the collider hit, None for tiles
+    pub entity: Option<Entity>,
+    /// Fraction of the motion done before the hit, 0.0 if the shape already overlaps
+    pub time: f32,
+    /// Point of the surface hit nearest to the shape
+    pub point: Vec2,
+    /// Normal of the surface hit, facing the shape
+    pub normal: Vec2,
+    /// Center of the shape when it hits
+    pub center: Vec2,
+}
+
+/// Collision of a level, as seen by the movement helpers
+pub trait CollisionQuery {
+    /// First hit of a ray going from `origin` in `direction` for at most `max_distance`
+    fn raycast(
+        &self,
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+        filter: &QueryFilter,
+    ) -> Option<RayHit>;
+
+    /// First hit of a shape moved by `motion`
+    fn shape_cast(&self, shape: &Shape, motion: Vec2, filter: &QueryFilter) -> Option<ShapeHit>;
+}
+
+/// Colliders of the entities and tiles of the world
+impl CollisionQuery for World {
+    fn raycast(
+        &self,
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+        filter: &QueryFilter,
+    ) -> Option<RayHit> {
+        raycast(self, origin, direction, max_distance, filter)
+    }
+
+    fn shape_cast(&self, shape: &Shape, motion: Vec2, filter: &QueryFilter) -> Option<ShapeHit> {
+        shape_cast(self, shape, motion, filter)
+    }
+}
+
+/// Solid cells only, the filter is ignored
+impl CollisionQuery for TileCollider {
+    fn raycast(
+        &self,
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+        _filter: &QueryFilter,
+    ) -> Option<RayHit> {
+        TileCollider::raycast(self, origin, direction, max_distance)
+    }
+
+    fn shape_cast(&self, shape: &Shape, motion: Vec2, _filter: &QueryFilter) -> Option<ShapeHit> {
+        let (hit, cell) = self.sweep(shape, motion)?;
+
+        Some(ShapeHit {
+            entity: None,
+            time: hit.time,
+            point: cell.closest_point(hit.center),
+            normal: hit.normal,
+            center: hit.center,
+        })
+    }
+}
+
+/// Shapes of the entity colliders of a world accepted by a filter, placed at the
+/// `GlobalTransform` of the entities (or the `Transform` without one)
+pub fn world_colliders(world: &World, filter: &QueryFilter) -> Vec<(Entity, Shape)> {
+    let colliders = match world.borrow::<Collider>() {
+        Some(colliders) => colliders,
+        None => return Vec::new(),
+    };
+    let globals = world.borrow::<GlobalTransform>();
+    let transforms = world.borrow::<Transform>();
+
+    colliders
+        .iter()
+        .filter(|(entity, collider)| filter.accepts(*entity, collider.layers))
+        .map(|(entity, collider)| {
+            let global = globals
+                .as_ref()
+                .and_then(|globals| globals.get(entity))
+                .map(GlobalTransform::translation);
+            let local = transforms
+                .as_ref()
+                .and_then(|transforms| transforms.get(entity))
+                .map(|transform| transform.translation);
+            let position = global.or(local).unwrap_or(Vec2::new(0.0, 0.0));
+
+            (entity, collider.placed(position))
+        })
+        .collect()
+}
+
+/// First collider of the world hit by a ray going from `origin` in `direction` for at most
+/// `max_distance`, e.g. for line of sight, hitscan weapons and ground checks
+pub fn raycast(
+    world: &World,
+    origin: Vec2,
+    direction: Vec2,
+    max_distance: f32,
+    filter: &QueryFilter,
+) -> Option<RayHit> {
+    let mut nearest = world_colliders(world, filter)
+        .into_iter()
+        .filter_map(|(entity, shape)| {
+            let hit = shape.raycast(origin, direction, max_distance)?;
+            Some(RayHit {
+                entity: Some(entity),
+                ..hit
+            })
+        })
+        .min_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap());
+
+    if filter.tiles {
+        if let Some(tiles) = world.resource::<TileCollider>() {
+            let max_distance = nearest.map_or(max_distance, |hit| hit.distance);
+            if let Some(hit) = tiles.raycast(origin, direction, max_distance) {
+                if nearest.map_or(true, |nearest| hit.distance < nearest.distance) {
+                    nearest = Some(hit);
+                }
+            }
+        }
+    }
+
+    nearest
+}
+
+/// Every collider of the world hit by a ray, nearest first (the tiles stop the ray)
+pub fn raycast_all(
+    world: &World,
+    origin: Vec2,
+    direction: Vec2,
+    max_distance: f32,
+    filter: &QueryFilter,
+) -> Vec<RayHit> {
+    let mut max_distance = max_distance;
+    let mut hits = Vec::new();
+    if filter.tiles {
+        if let Some(tiles) = world.resource::<TileCollider>() {
+            if let Some(hit) = tiles.raycast(origin, direction, max_distance) {
+                max_distance = hit.distance;
+                hits.push(hit);
+            }
+        }
+    }
+
+    hits.extend(
+        world_colliders(world, filter)
+            .into_iter()
+            .filter_map(|(entity, shape)| {
+                let hit = shape.raycast(origin, direction, max_distance)?;
+                Some(RayHit {
+                    entity: Some(entity),
+                    ..hit
+                })
+            }),
+    );
+    hits.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap());
+    hits
+}
+
+/// First collider of the world hit by a shape moved by `motion`, e.g. to check where a
+/// thrown object lands or whether a character fits through a gap
+pub fn shape_cast(
+    world: &World,
+    shape: &Shape,
+    motion: Vec2,
+    filter: &QueryFilter,
+) -> Option<ShapeHit> {
+    let shape_hit = |entity: Option<Entity>, hit: SweepHit, target: &Shape| ShapeHit {
+        entity,
+        time: hit.time,
+        point: target.closest_point(hit.center),
+        normal: hit.normal,
+        center: hit.center,
+    };
+
+    let mut nearest = world_colliders(world, filter)
+        .into_iter()
+        .filter_map(|(entity, target)| {
+            let hit = sweep(shape, motion, &target)?;
+            Some(shape_hit(Some(entity), hit, &target))
+        })
+        .min_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+
+    if filter.tiles {
+        if let Some(tiles) = world.resource::<TileCollider>() {
+            if let Some((hit, cell)) = tiles.sweep(shape, motion) {
+                if nearest.map_or(true, |nearest| hit.time < nearest.time) {
+                    nearest = Some(shape_hit(None, hit, &Shape::Aabb(cell)));
+                }
+            }
+        }
+    }
+
+    nearest
+}
@@ -0,0 +1,211 @@
+//! RGBA colors, accepted by every engine API taking a color
+//!
+//! Components go from 0.0 to 1.0 and are in sRGB space, like the colors of image editors
+//! and CSS. `to_linear` gives the values to use for lighting and blending math.
+
+// standard imports
+use std::fmt;
+
+// other imports
+use cgmath::Vector4;
+
+/// Color with straight (not premultiplied) alpha
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+/// sRGB transfer function of a single component
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl Color {
+    pub const WHITE: Color = Color::rgb(1.0, 1.0, 1.0);
+    pub const BLACK: Color = Color::rgb(0.0, 0.0, 0.0);
+    pub const TRANSPARENT: Color = Color::rgba(0.0, 0.0, 0.0, 0.0);
+    pub const GRAY: Color = Color::rgb(0.5, 0.5, 0.5);
+    pub const RED: Color = Color::rgb(1.0, 0.0, 0.0);
+    pub const GREEN: Color = Color::rgb(0.0, 1.0, 0.0);
+    pub const BLUE: Color = Color::rgb(0.0, 0.0, 1.0);
+    pub const YELLOW: Color = Color::rgb(1.0, 1.0, 0.0);
+    pub const CYAN: Color = Color::rgb(0.0, 1.0, 1.0);
+    pub const MAGENTA: Color = Color::rgb(1.0, 0.0, 1.0);
+    pub const ORANGE: Color = Color::rgb(1.0, 0.5, 0.0);
+    pub const PURPLE: Color = Color::rgb(0.5, 0.0, 0.5);
+
+    pub const fn rgb(r: f32, g: f32, b: f32) -> Self {
+        Self { r, g, b, a: 1.0 }
+    }
+
+    pub const fn rgba(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Color from 8 bit components
+    pub fn rgba8(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self::rgba(
+            r as f32 / 255.0,
+            g as f32 / 255.0,
+            b as f32 / 255.0,
+            a as f32 / 255.0,
+        )
+    }
+
+    /// Parse `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa` (the `#` is optional)
+    pub fn from_hex(hex: &str) -> Result<Self, String> {
+        let digits = hex.trim().trim_start_matches('#');
+        let error = || format!("Invalid hex color '{}'", hex);
+
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(error());
+        }
+
+        let nibble = |index: usize| u8::from_str_radix(&digits[index..=index], 16).unwrap() * 17;
+        let byte = |index: usize| u8::from_str_radix(&digits[index..index + 2], 16).unwrap();
+
+        match digits.len() {
+            3 => Ok(Self::rgba8(nibble(0), nibble(1), nibble(2), 255)),
+            4 => Ok(Self::rgba8(nibble(0), nibble(1), nibble(2), nibble(3))),
+            6 => Ok(Self::rgba8(byte(0), byte(2), byte(4), 255)),
+            8 => Ok(Self::rgba8(byte(0), byte(2), byte(4), byte(6))),
+            _ => Err(error()),
+        }
+    }
+
+    /// `#rrggbbaa` form of the color
+    pub fn to_hex(&self) -> String {
+        let [r, g, b, a] = self.to_rgba8();
+        format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+    }
+
+    pub fn to_rgba8(&self) -> [u8; 4] {
+        let byte = |value: f32| (value.max(0.0).min(1.0) * 255.0).round() as u8;
+        [byte(self.r), byte(self.g), byte(self.b), byte(self.a)]
+    }
+
+    /// Color from hue (degrees), saturation and value (0.0 to 1.0)
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Self {
+        let hue = hue.rem_euclid(360.0) / 60.0;
+        let chroma = value * saturation;
+        let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+        let m = value - chroma;
+
+        let (r, g, b) = match hue as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+
+        Self::rgb(r + m, g + m, b + m)
+    }
+
+    /// Hue (degrees), saturation and value (0.0 to 1.0) of the color
+    pub fn to_hsv(&self) -> (f32, f32, f32) {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        let chroma = max - min;
+
+        let hue = if chroma == 0.0 {
+            0.0
+        } else if max == self.r {
+            60.0 * ((self.g - self.b) / chroma).rem_euclid(6.0)
+        } else if max == self.g {
+            60.0 * ((self.b - self.r) / chroma + 2.0)
+        } else {
+            60.0 * ((self.r - self.g) / chroma + 4.0)
+        };
+        let saturation = if max == 0.0 { 0.0 } else { chroma / max };
+
+        (hue, saturation, max)
+    }
+
+    /// Components in linear space (alpha unchanged)
+    pub fn to_linear(&self) -> Self {
+        Self::rgba(
+            srgb_to_linear(self.r),
+            srgb_to_linear(self.g),
+            srgb_to_linear(self.b),
+            self.a,
+        )
+    }
+
+    /// Color from components in linear space
+    pub fn from_linear(linear: Color) -> Self {
+        Self::rgba(
+            linear_to_srgb(linear.r),
+            linear_to_srgb(linear.g),
+            linear_to_srgb(linear.b),
+            linear.a,
+        )
+    }
+
+    pub fn with_alpha(mut self, alpha: f32) -> Self {
+        self.a = alpha;
+        self
+    }
+
+    /// Mix of two colors, `t` going from 0.0 (self) to 1.0 (other)
+    pub fn lerp(&self, other: Color, t: f32) -> Self {
+        Self::rgba(
+            self.r + (other.r - self.r) * t,
+            self.g + (other.g - self.g) * t,
+            self.b + (other.b - self.b) * t,
+            self.a + (other.a - self.a) * t,
+        )
+    }
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Color::WHITE
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl From<[f32; 4]> for Color {
+    fn from(c: [f32; 4]) -> Self {
+        Self::rgba(c[0], c[1], c[2], c[3])
+    }
+}
+
+impl From<Color> for [f32; 4] {
+    fn from(c: Color) -> Self {
+        [c.r, c.g, c.b, c.a]
+    }
+}
+
+impl From<Vector4<f32>> for Color {
+    fn from(c: Vector4<f32>) -> Self {
+        Self::rgba(c.x, c.y, c.z, c.w)
+    }
+}
+
+impl From<Color> for Vector4<f32> {
+    fn from(c: Color) -> Self {
+        Vector4::new(c.r, c.g, c.b, c.a)
+    }
+}
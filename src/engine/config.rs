@@ -0,0 +1,607 @@
+//! Configuration used at engine creation
+
+// standard imports
+use std::collections::BTreeMap;
+#[cfg(feature = "config-files")]
+use std::fs;
+#[cfg(feature = "config-files")]
+use std::io;
+#[cfg(feature = "config-files")]
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// SDL2 imports
+use sdl2::keyboard::Keycode;
+
+// import the ctx module
+use super::crash::CrashReports;
+use super::ctx::SwapInterval;
+use super::paths::Paths;
+use super::platform;
+
+// other imports
+#[cfg(feature = "config-files")]
+use serde::{Deserialize, Serialize};
+
+/// Name of the player settings file, in the config directory of the project
+#[cfg(feature = "config-files")]
+pub const SETTINGS_FILE_NAME: &str = "settings.toml";
+
+/// Where to place the window on its display
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowPlacement {
+    Centered,
+    /// Coordinates relative to the top-left corner of the display
+    At(i32, i32),
+}
+
+/// Settings used to build the main window
+#[derive(Clone, Debug)]
+pub struct WindowConfig {
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+    pub resizable: bool,
+    pub fullscreen: bool,
+    pub borderless: bool,
+    /// Render at the display's native resolution on Retina/4K displays
+    pub allow_highdpi: bool,
+    pub always_on_top: bool,
+    pub minimum_size: Option<(u32, u32)>,
+    pub maximum_size: Option<(u32, u32)>,
+    /// How long the window size must stay the same before rebuilding the swapchain
+    pub resize_debounce: Duration,
+    /// Index of the display to open the window on (the primary one if None)
+    pub display: Option<usize>,
+    pub placement: WindowPlacement,
+}
+
+impl WindowConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn resizable(mut self, resizable: bool) -> Self {
+        self.resizable = resizable;
+        self
+    }
+
+    pub fn fullscreen(mut self, fullscreen: bool) -> Self {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    pub fn borderless(mut self, borderless: bool) -> Self {
+        self.borderless = borderless;
+        self
+    }
+
+    pub fn allow_highdpi(mut self, allow_highdpi: bool) -> Self {
+        self.allow_highdpi = allow_highdpi;
+        self
+    }
+
+    pub fn always_on_top(mut self, always_on_top: bool) -> Self {
+        self.always_on_top = always_on_top;
+        self
+    }
+
+    pub fn minimum_size(mut self, width: u32, height: u32) -> Self {
+        self.minimum_size = Some((width, height));
+        self
+    }
+
+    pub fn maximum_size(mut self, width: u32, height: u32) -> Self {
+        self.maximum_size = Some((width, height));
+        self
+    }
+
+    pub fn display(mut self, display: usize) -> Self {
+        self.display = Some(display);
+        self
+    }
+
+    pub fn placement(mut self, placement: WindowPlacement) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    pub fn resize_debounce(mut self, debounce: Duration) -> Self {
+        self.resize_debounce = debounce;
+        self
+    }
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            title: String::from("Magma"),
+            width: 800,
+            height: 600,
+            resizable: true,
+            fullscreen: false,
+            borderless: false,
+            allow_highdpi: true,
+            always_on_top: false,
+            minimum_size: None,
+            maximum_size: None,
+            resize_debounce: Duration::from_millis(50),
+            display: None,
+            placement: WindowPlacement::Centered,
+        }
+    }
+}
+
+/// What to do when a frame fails to render (a lost device is always recreated)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameErrorPolicy {
+    /// Drop the frame and try again on the next one
+    SkipFrame,
+    /// Drop the pending GPU work and rebuild the swapchain
+    ResetRenderer,
+    /// Stop the engine, `Engine::run` returns the error
+    Abort,
+}
+
+impl Default for FrameErrorPolicy {
+    fn default() -> Self {
+        FrameErrorPolicy::SkipFrame
+    }
+}
+
+/// How `Engine::run` schedules the game updates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoopMode {
+    /// One update per rendered frame, with the time of the previous frame
+    Variable,
+    /// Updates at a fixed rate (in Hz) catching up with the rendered frames, for physics and deterministic gameplay
+    Fixed(f32),
+}
+
+impl Default for LoopMode {
+    fn default() -> Self {
+        LoopMode::Variable
+    }
+}
+
+/// How the engine runs while the window is in the background
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BackgroundBehavior {
+    /// Framerate limit, lower than the target one (None keeps the target one)
+    pub max_fps: Option<u32>,
+    /// Render the frames (never while minimized), the game still updates otherwise
+    pub render: bool,
+}
+
+impl BackgroundBehavior {
+    /// Keep running as in the foreground
+    pub fn full_speed() -> Self {
+        Self {
+            max_fps: None,
+            render: true,
+        }
+    }
+
+    pub fn throttled(max_fps: u32) -> Self {
+        Self {
+            max_fps: Some(max_fps),
+            render: true,
+        }
+    }
+
+    /// Don't render anything, polling the events at `max_fps`
+    pub fn suspended(max_fps: u32) -> Self {
+        Self {
+            max_fps: Some(max_fps),
+            render: false,
+        }
+    }
+}
+
+/// Throttling applied when the window loses the focus or is minimized
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BackgroundPolicy {
+    pub unfocused: BackgroundBehavior,
+    pub minimized: BackgroundBehavior,
+    /// Pause the music and sound effects while unfocused or minimized
+    pub pause_audio: bool,
+}
+
+impl Default for BackgroundPolicy {
+    fn default() -> Self {
+        Self {
+            unfocused: BackgroundBehavior::full_speed(),
+            minimized: BackgroundBehavior::suspended(10),
+            pause_audio: false,
+        }
+    }
+}
+
+/// Optional subsystems started with the engine, by default the ones built with their cargo feature.
+/// Asking for one built without its feature logs an error and runs without it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Subsystems {
+    pub audio: bool,
+    /// Game controllers and joysticks
+    pub gamepads: bool,
+}
+
+impl Default for Subsystems {
+    fn default() -> Self {
+        Self {
+            audio: cfg!(feature = "audio"),
+            gamepads: cfg!(feature = "gamepads"),
+        }
+    }
+}
+
+/// Emulation between the touch screen and the mouse, done by SDL2.
+/// Touch input comes first on Android and iOS: touches don't move the mouse there by default
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TouchConfig {
+    /// Touches also move and click the mouse, for games only reading the mouse
+    pub mouse_from_touch: bool,
+    /// Mouse clicks also send touches, to try the touch controls on desktop
+    pub touch_from_mouse: bool,
+}
+
+impl Default for TouchConfig {
+    fn default() -> Self {
+        Self {
+            mouse_from_touch: !platform::IS_MOBILE,
+            touch_from_mouse: false,
+        }
+    }
+}
+
+/// Players of the local game
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputConfig {
+    /// Controller slots, the controllers connected once they're all taken get no player
+    pub max_players: usize,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self { max_players: 4 }
+    }
+}
+
+/// Volumes of the mixer, from 0 to 128
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AudioConfig {
+    pub music_volume: i32,
+    pub sfx_volume: i32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            music_volume: 30,
+            sfx_volume: 30,
+        }
+    }
+}
+
+/// Settings for the whole engine, passed to `Engine::new`
+#[derive(Clone, Debug, Default)]
+pub struct EngineConfig {
+    pub window: WindowConfig,
+    pub frame_error_policy: FrameErrorPolicy,
+    pub loop_mode: LoopMode,
+    /// Resubmit the same Command Buffers while the draw list doesn't change (good for static scenes)
+    pub reuse_command_buffers: bool,
+    /// Record and present the frames of the main window on a render thread, while the next update runs
+    pub render_thread: bool,
+    /// File holding the assets used by the previous run, read for the startup warmup and rewritten at the end.
+    /// Relative paths are kept in the cache directory
+    pub warmup_profile: Option<PathBuf>,
+    /// File holding the Vulkan pipeline cache, read when creating the renderer and rewritten at the end.
+    /// Relative paths are kept in the cache directory
+    pub pipeline_cache: Option<PathBuf>,
+    /// Name of the directories holding config, saves, cache and logs, the window title if missing
+    pub project_name: Option<String>,
+    pub swap_interval: SwapInterval,
+    pub audio: AudioConfig,
+    /// Directory the relative asset paths are loaded from. A relative root is found in the
+    /// app bundle on iOS, and in the APK on Android when the files aren't on the file system
+    pub asset_root: PathBuf,
+    /// Packed archives (see `pack`) mounted on the asset root, read before the loose files.
+    /// The engine doesn't start if one of them can't be opened
+    pub asset_archives: Vec<PathBuf>,
+    /// Key of every input action, by action name
+    pub key_bindings: BTreeMap<String, Keycode>,
+    pub background_policy: BackgroundPolicy,
+    pub subsystems: Subsystems,
+    pub touch: TouchConfig,
+    pub input: InputConfig,
+    /// Run without window, renderer, audio and controllers (dedicated servers), `Game::render` is never called
+    pub headless: bool,
+    /// Seed of `ctx.rng`, taken from the current time if None
+    pub rng_seed: Option<u64>,
+    pub crash_reports: CrashReports,
+}
+
+impl EngineConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn window(mut self, window: WindowConfig) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn frame_error_policy(mut self, policy: FrameErrorPolicy) -> Self {
+        self.frame_error_policy = policy;
+        self
+    }
+
+    pub fn loop_mode(mut self, mode: LoopMode) -> Self {
+        self.loop_mode = mode;
+        self
+    }
+
+    pub fn reuse_command_buffers(mut self, reuse: bool) -> Self {
+        self.reuse_command_buffers = reuse;
+        self
+    }
+
+    pub fn render_thread(mut self, render_thread: bool) -> Self {
+        self.render_thread = render_thread;
+        self
+    }
+
+    pub fn warmup_profile(mut self, path: PathBuf) -> Self {
+        self.warmup_profile = Some(path);
+        self
+    }
+
+    pub fn pipeline_cache(mut self, path: PathBuf) -> Self {
+        self.pipeline_cache = Some(path);
+        self
+    }
+
+    pub fn project_name(mut self, name: &str) -> Self {
+        self.project_name = Some(name.to_string());
+        self
+    }
+
+    pub fn swap_interval(mut self, interval: SwapInterval) -> Self {
+        self.swap_interval = interval;
+        self
+    }
+
+    pub fn audio(mut self, audio: AudioConfig) -> Self {
+        self.audio = audio;
+        self
+    }
+
+    pub fn asset_root(mut self, root: PathBuf) -> Self {
+        self.asset_root = root;
+        self
+    }
+
+    pub fn asset_archive(mut self, archive: PathBuf) -> Self {
+        self.asset_archives.push(archive);
+        self
+    }
+
+    pub fn key_binding(mut self, action: &str, key: Keycode) -> Self {
+        self.key_bindings.insert(action.to_string(), key);
+        self
+    }
+
+    pub fn background_policy(mut self, policy: BackgroundPolicy) -> Self {
+        self.background_policy = policy;
+        self
+    }
+
+    pub fn subsystems(mut self, subsystems: Subsystems) -> Self {
+        self.subsystems = subsystems;
+        self
+    }
+
+    pub fn touch(mut self, touch: TouchConfig) -> Self {
+        self.touch = touch;
+        self
+    }
+
+    pub fn input(mut self, input: InputConfig) -> Self {
+        self.input = input;
+        self
+    }
+
+    pub fn headless(mut self, headless: bool) -> Self {
+        self.headless = headless;
+        self
+    }
+
+    pub fn rng_seed(mut self, seed: u64) -> Self {
+        self.rng_seed = Some(seed);
+        self
+    }
+
+    pub fn crash_reports(mut self, crash_reports: CrashReports) -> Self {
+        self.crash_reports = crash_reports;
+        self
+    }
+
+    /// Directories of the project, named after `project_name` or the window title
+    pub fn resolve_paths(&self) -> Paths {
+        Paths::new(self.project_name.as_ref().unwrap_or(&self.window.title))
+    }
+}
+
+/// Config files, with the `config-files` feature
+#[cfg(feature = "config-files")]
+impl EngineConfig {
+    /// Default settings overridden by the ones in a config file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Self::default().with_file(path)
+    }
+
+    /// Override these settings with the ones saved by `save_default`, in the config directory of the project
+    pub fn load_default(self) -> Result<Self, String> {
+        let path = self.resolve_paths().config()?.join(SETTINGS_FILE_NAME);
+        self.with_file(&path)
+    }
+
+    /// Write the settings players can change to the settings file in the config directory of the project
+    pub fn save_default(&self) -> Result<(), String> {
+        let path = self.resolve_paths().config()?.join(SETTINGS_FILE_NAME);
+        self.save(&path)
+    }
+
+    /// Override these settings with the ones in a config file (TOML), if it exists.
+    /// Only the settings players can change are read: window size and mode, vsync,
+    /// audio volumes, asset root and key bindings
+    pub fn with_file(mut self, path: &Path) -> Result<Self, String> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(self),
+            Err(e) => return Err(format!("Couldn't read config '{}': {}", path.display(), e)),
+        };
+
+        let file: ConfigFile = toml::from_str(&source)
+            .map_err(|e| format!("Invalid config '{}': {}", path.display(), e))?;
+        let error = |entry: &str, expected: &str| {
+            format!(
+                "Invalid value for '{}' in '{}', expected {}",
+                entry,
+                path.display(),
+                expected
+            )
+        };
+
+        let window = file.window;
+        self.window.width = window.width.unwrap_or(self.window.width);
+        self.window.height = window.height.unwrap_or(self.window.height);
+        self.window.fullscreen = window.fullscreen.unwrap_or(self.window.fullscreen);
+        self.window.borderless = window.borderless.unwrap_or(self.window.borderless);
+        self.window.display = window.display.or(self.window.display);
+        self.swap_interval = file.video.vsync.unwrap_or(self.swap_interval);
+
+        let volume = |entry: &str, volume: Option<i32>, current: i32| match volume {
+            Some(volume) if !(0..=128).contains(&volume) => {
+                Err(error(entry, "a volume from 0 to 128"))
+            }
+            volume => Ok(volume.unwrap_or(current)),
+        };
+        self.audio.music_volume = volume(
+            "audio.music_volume",
+            file.audio.music_volume,
+            self.audio.music_volume,
+        )?;
+        self.audio.sfx_volume = volume(
+            "audio.sfx_volume",
+            file.audio.sfx_volume,
+            self.audio.sfx_volume,
+        )?;
+
+        if let Some(root) = file.assets.root {
+            self.asset_root = root;
+        }
+        for (action, name) in file.bindings {
+            let key = Keycode::from_name(&name)
+                .ok_or_else(|| error(&format!("bindings.{}", action), "a key name"))?;
+            self.key_bindings.insert(action, key);
+        }
+
+        Ok(self)
+    }
+
+    /// Write the settings players can change to a config file (TOML)
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let file = ConfigFile {
+            window: WindowSettings {
+                width: Some(self.window.width),
+                height: Some(self.window.height),
+                fullscreen: Some(self.window.fullscreen),
+                borderless: Some(self.window.borderless),
+                display: self.window.display,
+            },
+            video: VideoSettings {
+                vsync: Some(self.swap_interval),
+            },
+            audio: AudioSettings {
+                music_volume: Some(self.audio.music_volume),
+                sfx_volume: Some(self.audio.sfx_volume),
+            },
+            assets: AssetSettings {
+                root: Some(self.asset_root.clone()),
+            },
+            bindings: self
+                .key_bindings
+                .iter()
+                .map(|(action, key)| (action.clone(), key.name()))
+                .collect(),
+        };
+        let out = toml::to_string(&file).map_err(|e| e.to_string())?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        fs::write(path, out)
+            .map_err(|e| format!("Couldn't save config '{}': {}", path.display(), e))
+    }
+}
+
+/// Settings players can change, as stored in config files.
+/// Missing entries keep the current settings, unknown ones (of newer versions) are ignored
+#[cfg(feature = "config-files")]
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct ConfigFile {
+    window: WindowSettings,
+    video: VideoSettings,
+    audio: AudioSettings,
+    assets: AssetSettings,
+    bindings: BTreeMap<String, String>,
+}
+
+#[cfg(feature = "config-files")]
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct WindowSettings {
+    width: Option<u32>,
+    height: Option<u32>,
+    fullscreen: Option<bool>,
+    borderless: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    display: Option<usize>,
+}
+
+#[cfg(feature = "config-files")]
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct VideoSettings {
+    vsync: Option<SwapInterval>,
+}
+
+#[cfg(feature = "config-files")]
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct AudioSettings {
+    music_volume: Option<i32>,
+    sfx_volume: Option<i32>,
+}
+
+#[cfg(feature = "config-files")]
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct AssetSettings {
+    root: Option<PathBuf>,
+}
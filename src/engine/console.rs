@@ -0,0 +1,703 @@
+//! Developer console dropping down over the game, toggled by a key
+//!
+//! The `Console` resource of the world holds the commands, the console variables (cvars),
+//! the output and the history, so the game registers and reads them from anywhere. The
+//! `ConsolePlugin` feeds it the keyboard, follows the engine log and draws it over the game.
+//! The game still gets the input events while the console is open, check
+//! `Console::is_open` to ignore them.
+
+// standard imports
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+// SDL2 imports
+use sdl2::keyboard::Keycode;
+
+// import the engine modules
+use super::bitmap_font::{BitmapFont, BitmapTextObject};
+use super::color::Color;
+use super::crash;
+use super::ctx::draw_objects::{Draw, PrimitiveObject, WORLD_UNITS_PER_PIXEL};
+use super::events::{EngineEvent, InputEvent};
+use super::game::{Context, Frame};
+use super::math::Vec2;
+use super::plugin::EnginePlugin;
+use super::time::Time;
+
+/// Output lines kept, the oldest ones are dropped
+const MAX_LINES: usize = 500;
+/// Commands kept in the history
+const MAX_HISTORY: usize = 100;
+/// Seconds to fully open or close the console
+const SLIDE_DURATION: f32 = 0.15;
+/// Drawn over everything else
+const CONSOLE_Z_INDEX: u8 = 255;
+
+/// Value of a console variable, its type is kept when set from the console
+#[derive(Clone, Debug, PartialEq)]
+pub enum CVarValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl CVarValue {
+    /// Parse a value of the same type as this one
+    fn parse_same(&self, text: &str) -> Result<CVarValue, String> {
+        let invalid = |kind: &str| format!("'{}' isn't a valid {}", text, kind);
+
+        match self {
+            CVarValue::Bool(_) => match text {
+                "1" | "true" | "on" => Ok(CVarValue::Bool(true)),
+                "0" | "false" | "off" => Ok(CVarValue::Bool(false)),
+                _ => Err(invalid("boolean")),
+            },
+            CVarValue::Int(_) => text
+                .parse()
+                .map(CVarValue::Int)
+                .map_err(|_| invalid("integer")),
+            CVarValue::Float(_) => text
+                .parse()
+                .map(CVarValue::Float)
+                .map_err(|_| invalid("number")),
+            CVarValue::Text(_) => Ok(CVarValue::Text(text.to_string())),
+        }
+    }
+
+    fn same_type(&self, other: &CVarValue) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl fmt::Display for CVarValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CVarValue::Bool(value) => write!(f, "{}", value),
+            CVarValue::Int(value) => write!(f, "{}", value),
+            CVarValue::Float(value) => write!(f, "{}", value),
+            CVarValue::Text(value) => write!(f, "\"{}\"", value),
+        }
+    }
+}
+
+struct CVar {
+    value: CVarValue,
+    default: CVarValue,
+    help: String,
+}
+
+/// Command of the console, gets the words after its name and returns the text to print
+pub type CommandFn = Box<dyn FnMut(&[&str], &mut Context) -> Result<String, String>>;
+
+struct Command {
+    help: String,
+    /// Taken out while the command runs, it gets the whole context
+    run: Option<CommandFn>,
+}
+
+/// Commands, variables, output and input line of the console
+#[derive(Default)]
+pub struct Console {
+    commands: BTreeMap<String, Command>,
+    cvars: BTreeMap<String, CVar>,
+    lines: VecDeque<String>,
+    history: Vec<String>,
+    /// Entry of the history shown in the input line while browsing it
+    history_position: Option<usize>,
+    input: String,
+    open: bool,
+    /// Lines of the engine log already printed
+    log_seen: u64,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a command, replacing the one with the same name
+    pub fn register_command<F>(&mut self, name: &str, help: &str, command: F)
+    where
+        F: FnMut(&[&str], &mut Context) -> Result<String, String> + 'static,
+    {
+        self.commands.insert(
+            name.to_string(),
+            Command {
+                help: help.to_string(),
+                run: Some(Box::new(command)),
+            },
+        );
+    }
+
+    pub fn unregister_command(&mut self, name: &str) -> bool {
+        self.commands.remove(name).is_some()
+    }
+
+    /// Add a variable, keeping its value if it already exists with the same type
+    pub fn register_cvar(&mut self, name: &str, default: CVarValue, help: &str) {
+        let value = match self.cvars.get(name) {
+            Some(cvar) if cvar.value.same_type(&default) => cvar.value.clone(),
+            _ => default.clone(),
+        };
+
+        self.cvars.insert(
+            name.to_string(),
+            CVar {
+                value,
+                default,
+                help: help.to_string(),
+            },
+        );
+    }
+
+    /// Every console variable with its value, by name
+    pub fn cvars(&self) -> impl Iterator<Item = (&str, &CVarValue)> {
+        self.cvars
+            .iter()
+            .map(|(name, cvar)| (name.as_str(), &cvar.value))
+    }
+
+    pub fn cvar(&self, name: &str) -> Option<&CVarValue> {
+        self.cvars.get(name).map(|cvar| &cvar.value)
+    }
+
+    pub fn cvar_bool(&self, name: &str) -> Option<bool> {
+        match self.cvar(name)? {
+            CVarValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn cvar_int(&self, name: &str) -> Option<i64> {
+        match self.cvar(name)? {
+            CVarValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Value of a number variable, integers included
+    pub fn cvar_float(&self, name: &str) -> Option<f64> {
+        match self.cvar(name)? {
+            CVarValue::Float(value) => Some(*value),
+            CVarValue::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn cvar_text(&self, name: &str) -> Option<&str> {
+        match self.cvar(name)? {
+            CVarValue::Text(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Change a registered variable, the value must have the same type
+    pub fn set_cvar(&mut self, name: &str, value: CVarValue) -> Result<(), String> {
+        let cvar = self
+            .cvars
+            .get_mut(name)
+            .ok_or_else(|| format!("Unknown variable '{}'", name))?;
+        if !cvar.value.same_type(&value) {
+            return Err(format!("Wrong type for '{}': {}", name, value));
+        }
+
+        cvar.value = value;
+        Ok(())
+    }
+
+    /// Add a line to the output
+    pub fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            if self.lines.len() == MAX_LINES {
+                self.lines.pop_front();
+            }
+            self.lines.push_back(line.to_string());
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    /// Output, oldest line first
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(|line| line.as_str())
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn set_open(&mut self, open: bool) {
+        self.open = open;
+    }
+
+    /// Text being typed
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// Commands typed before, oldest first
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Names of the commands and variables starting with a prefix
+    pub fn completions(&self, prefix: &str) -> Vec<&str> {
+        let mut names: Vec<&str> = BUILTIN_COMMANDS
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(self.commands.keys().map(|name| name.as_str()))
+            .chain(self.cvars.keys().map(|name| name.as_str()))
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Complete the first word of the input line as far as it's not ambiguous,
+    /// the candidates are printed otherwise
+    fn complete(&mut self) {
+        if self.input.contains(char::is_whitespace) {
+            return;
+        }
+
+        let candidates: Vec<String> = self
+            .completions(&self.input)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        match candidates.len() {
+            0 => {}
+            1 => self.input = format!("{} ", candidates[0]),
+            _ => {
+                // Longest prefix shared by every candidate
+                let mut common = candidates[0].clone();
+                for candidate in candidates.iter().skip(1) {
+                    let length = common
+                        .chars()
+                        .zip(candidate.chars())
+                        .take_while(|(a, b)| a == b)
+                        .map(|(a, _)| a.len_utf8())
+                        .sum();
+                    common.truncate(length);
+                }
+
+                self.print(&candidates.join("  "));
+                self.input = common;
+            }
+        }
+    }
+
+    fn browse_history(&mut self, older: bool) {
+        if self.history.is_empty() {
+            return;
+        }
+
+        let position = match (self.history_position, older) {
+            (None, true) => Some(self.history.len() - 1),
+            (None, false) => None,
+            (Some(position), true) => Some(position.saturating_sub(1)),
+            (Some(position), false) if position + 1 < self.history.len() => Some(position + 1),
+            (Some(_), false) => None,
+        };
+
+        self.history_position = position;
+        self.input = position.map_or_else(String::new, |p| self.history[p].clone());
+    }
+
+    fn push_history(&mut self, line: &str) {
+        if self.history.last().map(|last| last.as_str()) != Some(line) {
+            if self.history.len() == MAX_HISTORY {
+                self.history.remove(0);
+            }
+            self.history.push(line.to_string());
+        }
+        self.history_position = None;
+    }
+
+    fn help(&self) -> String {
+        let mut help = String::from("Commands:");
+        for (name, text) in BUILTIN_COMMANDS.iter() {
+            help += &format!("\n  {} - {}", name, text);
+        }
+        for (name, command) in self.commands.iter() {
+            help += &format!("\n  {} - {}", name, command.help);
+        }
+
+        if !self.cvars.is_empty() {
+            help += "\nVariables:";
+            for (name, cvar) in self.cvars.iter() {
+                help += &format!("\n  {} = {} - {}", name, cvar.value, cvar.help);
+            }
+        }
+        help
+    }
+
+    /// Run a line for the variables and the builtin commands, returns the name and arguments of
+    /// a user command to run with the context
+    fn run_local(&mut self, words: &[&str]) -> Option<String> {
+        let (name, arguments) = words.split_first()?;
+
+        match *name {
+            "help" => {
+                let help = self.help();
+                self.print(&help);
+            }
+            "clear" => self.clear(),
+            "reset" => match arguments.first() {
+                Some(cvar) => match self.cvars.get_mut(*cvar) {
+                    Some(cvar) => cvar.value = cvar.default.clone(),
+                    None => self.print(&format!("Unknown variable '{}'", cvar)),
+                },
+                None => self.print("Usage: reset <variable>"),
+            },
+            _ if self.cvars.contains_key(*name) => {
+                let cvar = &self.cvars[*name];
+                let line = match arguments.first() {
+                    None => format!(
+                        "{} = {} (default {}) - {}",
+                        name, cvar.value, cvar.default, cvar.help
+                    ),
+                    Some(text) => match cvar.value.parse_same(text) {
+                        Ok(value) => {
+                            let line = format!("{} = {}", name, value);
+                            self.cvars.get_mut(*name).unwrap().value = value;
+                            line
+                        }
+                        Err(e) => e,
+                    },
+                };
+                self.print(&line);
+            }
+            _ if self.commands.contains_key(*name) => return Some(name.to_string()),
+            _ => self.print(&format!("Unknown command '{}', try 'help'", name)),
+        }
+
+        None
+    }
+}
+
+/// Commands every console has, handled before the registered ones
+const BUILTIN_COMMANDS: [(&str, &str); 3] = [
+    ("help", "list the commands and variables"),
+    ("clear", "clear the output"),
+    ("reset", "set a variable back to its default value"),
+];
+
+/// Split a line in words, quotes keep spaces in a word
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut in_word = false;
+
+    for character in line.chars() {
+        match character {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+
+    words
+}
+
+/// Run a line of the console (echoed in its output), as if typed by the player
+pub fn execute(line: &str, ctx: &mut Context) {
+    let words = split_words(line);
+    let words: Vec<&str> = words.iter().map(|word| word.as_str()).collect();
+
+    let (name, mut command) = {
+        let mut console = match ctx.world.resource_mut::<Console>() {
+            Some(console) => console,
+            None => return,
+        };
+        console.print(&format!("> {}", line));
+
+        let name = match console.run_local(&words) {
+            Some(name) => name,
+            None => return,
+        };
+        let command = console
+            .commands
+            .get_mut(&name)
+            .and_then(|command| command.run.take());
+        match command {
+            Some(command) => (name, command),
+            // Running already, the command typed itself
+            None => {
+                console.print(&format!("'{}' can't run recursively", name));
+                return;
+            }
+        }
+    };
+
+    let result = command(&words[1..], ctx);
+
+    if let Some(mut console) = ctx.world.resource_mut::<Console>() {
+        match result {
+            Ok(output) => console.print(&output),
+            Err(e) => console.print(&format!("Error: {}", e)),
+        }
+        // The command may have been replaced or removed while running
+        if let Some(registered) = console.commands.get_mut(&name) {
+            if registered.run.is_none() {
+                registered.run = Some(command);
+            }
+        }
+    }
+}
+
+/// Keyboard handling and drawing of the `Console`, added with `Engine::add_plugin`
+pub struct ConsolePlugin {
+    font: Arc<BitmapFont>,
+    toggle_key: Keycode,
+    /// Part of the view covered when open
+    height: f32,
+    background_color: Color,
+    text_color: Color,
+    /// 0.0 closed to 1.0 open
+    slide: f32,
+    /// Output and input lines shown, built by `on_update`
+    content: String,
+    /// Lines fitting in the panel, measured by the last render
+    visible_lines: usize,
+    background: Option<PrimitiveObject>,
+    text: Option<BitmapTextObject>,
+}
+
+impl ConsolePlugin {
+    /// Console drawn with a bitmap font, opened with the backquote key
+    pub fn new(font: Arc<BitmapFont>) -> Self {
+        Self {
+            font,
+            toggle_key: Keycode::Backquote,
+            height: 0.5,
+            background_color: Color::rgba(0.05, 0.05, 0.1, 0.85),
+            text_color: Color::WHITE,
+            slide: 0.0,
+            content: String::new(),
+            visible_lines: 20,
+            background: None,
+            text: None,
+        }
+    }
+
+    pub fn with_toggle_key(mut self, key: Keycode) -> Self {
+        self.toggle_key = key;
+        self
+    }
+
+    /// Part of the view covered when open, 0.0 to 1.0
+    pub fn with_height(mut self, height: f32) -> Self {
+        self.height = height.max(0.05).min(1.0);
+        self
+    }
+
+    pub fn with_colors(mut self, background: Color, text: Color) -> Self {
+        self.background_color = background;
+        self.text_color = text;
+        self
+    }
+
+    fn on_key(&mut self, key: Keycode, ctx: &mut Context) {
+        let line = {
+            let mut console = match ctx.world.resource_mut::<Console>() {
+                Some(console) => console,
+                None => return,
+            };
+
+            if key == self.toggle_key {
+                let open = !console.is_open();
+                console.set_open(open);
+                return;
+            }
+            if !console.is_open() {
+                return;
+            }
+
+            match key {
+                Keycode::Return | Keycode::KpEnter => {
+                    let line = std::mem::take(&mut console.input);
+                    let line = line.trim().to_string();
+                    if !line.is_empty() {
+                        console.push_history(&line);
+                    }
+                    line
+                }
+                Keycode::Backspace => {
+                    console.input.pop();
+                    return;
+                }
+                Keycode::Tab => {
+                    console.complete();
+                    return;
+                }
+                Keycode::Up => {
+                    console.browse_history(true);
+                    return;
+                }
+                Keycode::Down => {
+                    console.browse_history(false);
+                    return;
+                }
+                Keycode::Escape => {
+                    console.set_open(false);
+                    return;
+                }
+                _ => return,
+            }
+        };
+
+        if !line.is_empty() {
+            execute(&line, ctx);
+        }
+    }
+}
+
+impl EnginePlugin for ConsolePlugin {
+    fn name(&self) -> &str {
+        "console"
+    }
+
+    fn build(&mut self, ctx: &mut Context) {
+        if ctx.world.resource::<Console>().is_none() {
+            ctx.world.insert_resource(Console::new());
+        }
+    }
+
+    fn on_update(&mut self, time: &Time, ctx: &mut Context) {
+        let mut console = match ctx.world.resource_mut::<Console>() {
+            Some(console) => console,
+            None => return,
+        };
+
+        let (lines, seen) = crash::lines_since(console.log_seen);
+        console.log_seen = seen;
+        for line in lines {
+            console.print(&line);
+        }
+
+        let step = time.delta() / SLIDE_DURATION;
+        self.slide = if console.is_open() {
+            (self.slide + step).min(1.0)
+        } else {
+            (self.slide - step).max(0.0)
+        };
+
+        if self.slide > 0.0 {
+            // The last output lines, then the input line with its cursor
+            let shown = self.visible_lines.saturating_sub(1);
+            let skipped = console.lines.len().saturating_sub(shown);
+            self.content.clear();
+            for line in console.lines.iter().skip(skipped) {
+                self.content += line;
+                self.content.push('\n');
+            }
+            self.content += &format!("> {}_", console.input);
+        }
+    }
+
+    fn on_event(&mut self, event: &EngineEvent, ctx: &mut Context) {
+        match event {
+            EngineEvent::Input(InputEvent::KeyDown { key, .. }) => self.on_key(*key, ctx),
+            EngineEvent::TextInput(text) => {
+                // The toggle key types its character too
+                if *text == self.toggle_key.name() {
+                    return;
+                }
+                if let Some(mut console) = ctx.world.resource_mut::<Console>() {
+                    if console.is_open() {
+                        console.input += text;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_render(&mut self, frame: &mut Frame) {
+        let video = &mut *frame.video;
+
+        if self.slide <= 0.0 {
+            if let Some(background) = &self.background {
+                background.get_mut().set_visible(false);
+            }
+            if let Some(text) = &self.text {
+                text.get_mut().set_visible(false);
+            }
+            return;
+        }
+
+        let view = video.gl_handler.view_bounds();
+        let view_size = view.max - view.min;
+        let (window_width, _) = video.get_window_size();
+        // The text keeps its size in pixels whatever the camera zoom
+        let units_per_pixel = view_size.x / window_width.max(1) as f32;
+
+        let panel_height = view_size.y * self.height;
+        let top = view.min.y - panel_height * (1.0 - self.slide);
+        let padding = 4.0 * units_per_pixel;
+
+        let line_height = self.font.line_height.max(1.0);
+        self.visible_lines =
+            ((panel_height / units_per_pixel - 8.0) / line_height).max(1.0) as usize;
+
+        if self.background.is_none() {
+            match video.new_rectangle(
+                Vec2::new(1.0, 1.0),
+                self.background_color,
+                Vec2::new(0.0, 0.0),
+                CONSOLE_Z_INDEX,
+            ) {
+                Ok(background) => self.background = Some(background),
+                Err(e) => crash::log(&format!("Couldn't create the console background: {}", e)),
+            }
+        }
+        if self.text.is_none() {
+            match self.font.new_text(video, "", CONSOLE_Z_INDEX) {
+                Ok(text) => self.text = Some(text),
+                Err(e) => {
+                    crash::log(&format!("Couldn't create the console text: {}", e));
+                    return;
+                }
+            }
+        }
+
+        if let Some(background) = &self.background {
+            let mut background = background.get_mut();
+            background.global_position =
+                Vec2::new((view.min.x + view.max.x) / 2.0, top + panel_height / 2.0);
+            background.scale = Vec2::new(view_size.x / 2.0, panel_height / 2.0);
+            background.set_color(self.background_color);
+            background.set_visible(true);
+        }
+
+        if let Some(text) = &self.text {
+            let mut text = text.get_mut();
+            text.global_position = Vec2::new(view.min.x + padding, top + padding);
+            text.scale = Vec2::new(units_per_pixel, units_per_pixel) / WORLD_UNITS_PER_PIXEL;
+            text.set_color(self.text_color);
+            text.set_text(&self.content);
+            text.set_visible(true);
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! Crash reports written by the engine panic hook
+//!
+//! The report holds the panic message and backtrace, the engine, OS and GPU information
+//! and the last lines logged with `crash::log`. It's saved in the logs directory of the game,
+//! then the previous panic hook runs and, if enabled, a message box tells the player where it is.
+//! Every logged line is also appended to the log file of the run, in the same directory.
+
+// standard imports
+use std::backtrace::Backtrace;
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::panic::{self, PanicInfo};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// SDL2 imports
+use sdl2::messagebox::{self, MessageBoxFlag};
+
+/// Log lines kept for the crash reports
+const LOG_CAPACITY: usize = 100;
+/// Log file of the current run, replaced by the next run
+const LOG_FILE_NAME: &str = "latest.log";
+
+/// What the panic hook does
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CrashReports {
+    /// Write a report on panic
+    pub enabled: bool,
+    /// Show an error message box pointing to the report before exiting
+    pub message_box: bool,
+}
+
+impl Default for CrashReports {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            message_box: false,
+        }
+    }
+}
+
+/// Settings of the installed hook, updated by every new engine
+struct HookState {
+    settings: CrashReports,
+    project_name: String,
+    directory: PathBuf,
+}
+
+static HOOK: Mutex<Option<HookState>> = Mutex::new(None);
+static LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+/// Lines logged since the start, kept or not
+static LOG_COUNT: AtomicU64 = AtomicU64::new(0);
+/// Log file opened by `install`, None when it couldn't be created
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+/// Engine, GPU and driver information, by name in order of addition
+static INFO: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// Print a line on the standard error and in the log file, keeping it for the crash reports
+pub fn log(line: &str) {
+    eprintln!("{}", line);
+
+    if let Ok(mut file) = LOG_FILE.lock() {
+        if let Some(file) = file.as_mut() {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+
+    if let Ok(mut log) = LOG.lock() {
+        if log.len() == LOG_CAPACITY {
+            log.pop_front();
+        }
+        log.push_back(line.to_string());
+        LOG_COUNT.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Lines logged after the first `seen` ones (only the last ones are kept) and the number of
+/// lines logged, to follow the log from elsewhere (e.g. the console)
+pub fn lines_since(seen: u64) -> (Vec<String>, u64) {
+    match LOG.lock() {
+        Ok(log) => {
+            let count = LOG_COUNT.load(Ordering::SeqCst);
+            let new = (count.saturating_sub(seen) as usize).min(log.len());
+            (log.iter().skip(log.len() - new).cloned().collect(), count)
+        }
+        Err(_) => (Vec::new(), seen),
+    }
+}
+
+/// Add (or replace) an entry of the system information written in the reports
+pub fn set_info(name: &str, value: &str) {
+    if let Ok(mut info) = INFO.lock() {
+        match info.iter_mut().find(|(entry, _)| entry == name) {
+            Some(entry) => entry.1 = value.to_string(),
+            None => info.push((name.to_string(), value.to_string())),
+        }
+    }
+}
+
+/// Install the panic hook, reports and the log file are written in `directory`.
+/// Calling it again (e.g. after a restart) only changes the settings, and the log file
+/// if the directory changed
+pub fn install(settings: CrashReports, project_name: &str, directory: PathBuf) {
+    let mut hook = match HOOK.lock() {
+        Ok(hook) => hook,
+        Err(_) => return,
+    };
+
+    if hook
+        .as_ref()
+        .map_or(true, |state| state.directory != directory)
+    {
+        open_log_file(&directory);
+    }
+
+    if hook.is_none() {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            // try_lock: the panic may come from a thread holding a lock
+            let state = HOOK.try_lock().ok().and_then(|hook| {
+                hook.as_ref().map(|state| {
+                    (
+                        state.settings,
+                        state.project_name.clone(),
+                        state.directory.clone(),
+                    )
+                })
+            });
+
+            let report = match &state {
+                Some((settings, name, directory)) if settings.enabled => {
+                    Some(write_report(info, name, directory))
+                }
+                _ => None,
+            };
+
+            previous(info);
+
+            if let (Some(report), Some((settings, name, _))) = (report, state) {
+                let message = match report {
+                    Ok(path) => format!(
+                        "{} crashed.\n\nA crash report was saved to:\n{}",
+                        name,
+                        path.display()
+                    ),
+                    Err(e) => {
+                        eprintln!("Couldn't write crash report: {}", e);
+                        format!("{} crashed.\n\n{}", name, panic_message(info))
+                    }
+                };
+
+                if settings.message_box {
+                    let _ = messagebox::show_simple_message_box(
+                        MessageBoxFlag::ERROR,
+                        &format!("{} crashed", name),
+                        &message,
+                        None,
+                    );
+                }
+            }
+        }));
+    }
+
+    *hook = Some(HookState {
+        settings,
+        project_name: project_name.to_string(),
+        directory,
+    });
+}
+
+/// Start a new log file in `directory`, the lines already logged are written first
+fn open_log_file(directory: &Path) {
+    let path = directory.join(LOG_FILE_NAME);
+    let file = fs::create_dir_all(directory).and_then(|_| File::create(&path));
+
+    let file = match file {
+        Ok(mut file) => {
+            if let Ok(log) = LOG.lock() {
+                for line in log.iter() {
+                    let _ = writeln!(file, "{}", line);
+                }
+            }
+            Some(file)
+        }
+        Err(e) => {
+            eprintln!("Couldn't create log file '{}': {}", path.display(), e);
+            None
+        }
+    };
+
+    if let Ok(mut log_file) = LOG_FILE.lock() {
+        *log_file = file;
+    }
+}
+
+fn panic_message(info: &PanicInfo) -> String {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| String::from("Box<dyn Any>"));
+
+    match info.location() {
+        Some(location) => format!("{} at {}:{}", message, location.file(), location.line()),
+        None => message,
+    }
+}
+
+/// Full text of a report
+fn build_report(info: &PanicInfo, project_name: &str, timestamp: u64) -> String {
+    let mut report = format!("{} crashed\n\n", project_name);
+
+    report += &format!("Time: {} (Unix seconds)\n", timestamp);
+    report += &format!(
+        "Engine: {} {}\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+    report += &format!("OS: {} {}\n", env::consts::OS, env::consts::ARCH);
+    report += &format!(
+        "Thread: {}\n",
+        thread::current().name().unwrap_or("<unnamed>")
+    );
+    report += &format!("Panic: {}\n", panic_message(info));
+
+    report += "\n[System]\n";
+    if let Ok(info) = INFO.try_lock() {
+        for (name, value) in info.iter() {
+            report += &format!("{}: {}\n", name, value);
+        }
+    }
+
+    report += "\n[Recent log]\n";
+    if let Ok(log) = LOG.try_lock() {
+        for line in log.iter() {
+            report += line;
+            report += "\n";
+        }
+    }
+
+    report += &format!("\n[Backtrace]\n{}\n", Backtrace::force_capture());
+
+    report
+}
+
+fn write_report(info: &PanicInfo, project_name: &str, directory: &Path) -> Result<PathBuf, String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let path = directory.join(format!("crash-{}.txt", timestamp));
+
+    fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+    fs::write(&path, build_report(info, project_name, timestamp)).map_err(|e| e.to_string())?;
+
+    Ok(path)
+}
@@ -1,41 +1,89 @@
 // std imports
+use std::cell::{Cell, RefCell};
 use std::path::Path;
 
 // SDL2 imports
-use sdl2::mixer::{self, Channel, Chunk, Music};
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::mixer::{self, Channel, Chunk, LoaderRWops, Music};
+use sdl2::rwops::RWops;
+use sdl2::{AudioSubsystem, Sdl};
+
+// other imports
+use crate::engine::config::AudioConfig;
+use crate::engine::error::MagmaError;
+use crate::engine::memory::{self, MemoryTag};
+use crate::engine::warmup::{self, AssetKind};
 
 
 /// Component of the CtxHandler to handle all calls to SDL_Mixer's API
 pub struct AudioHandler {
-    mix_context: mixer::Sdl2MixerContext,
+    /// None when the audio is disabled, every call is then ignored
+    mix_context: Option<mixer::Sdl2MixerContext>,
+    /// Opens the streams played next to the mixer, None when the audio is disabled
+    subsystem: Option<AudioSubsystem>,
     music: Option<Box<Music<'static>>>,
     general_channel: Channel,
+    /// Music and channels paused by `pause_all`, the only ones resumed by `resume_all`
+    paused_music: Cell<bool>,
+    paused_channels: RefCell<Vec<Channel>>,
+    /// Volume given to the loaded music
+    music_volume: i32,
+    sfx_volume: i32,
 }
 
 impl AudioHandler {
-    pub fn new() -> AudioHandler{
+    /// Open the audio device, failing if there's none (e.g. on a server)
+    pub fn new(ctx: &Sdl, config: &AudioConfig) -> Result<AudioHandler, MagmaError> {
+        let subsystem = ctx
+            .audio()
+            .map_err(|e| MagmaError::Audio(format!("Couldn't obtain SDL2 Audio Subsystem: {}", e)))?;
+
         let mut init_flags = mixer::InitFlag::empty();
         init_flags.set(mixer::InitFlag::OGG, true);
 
-        let mix_context = mixer::init(init_flags).expect("Couldn't init SDL2 Mixer context");
+        let mix_context = mixer::init(init_flags)
+            .map_err(|e| MagmaError::Audio(format!("Couldn't init SDL2 Mixer: {}", e)))?;
 
         mixer::allocate_channels(5);
 
-        mixer::open_audio(44100, mixer::AUDIO_U16, 2, 1024).expect("Couldn't open audio on SDL2 Mixer Context");
+        mixer::open_audio(44100, mixer::AUDIO_U16, 2, 1024)
+            .map_err(|e| MagmaError::Audio(format!("Couldn't open the audio device: {}", e)))?;
 
-        let general_channel = Channel::all();
+        Ok(AudioHandler {
+            mix_context: Some(mix_context),
+            subsystem: Some(subsystem),
+            ..Self::disabled(config)
+        })
+    }
 
+    /// Handler without audio device: sounds don't load and nothing plays
+    pub fn disabled(config: &AudioConfig) -> AudioHandler {
         AudioHandler {
-            mix_context,
+            mix_context: None,
+            subsystem: None,
             music: None,
-            general_channel,
+            general_channel: Channel::all(),
+            paused_music: Cell::new(false),
+            paused_channels: RefCell::new(Vec::new()),
+            music_volume: config.music_volume,
+            sfx_volume: config.sfx_volume,
         }
     }
 
+    pub fn is_enabled(&self) -> bool {
+        self.mix_context.is_some()
+    }
+
     //----------------
     // SOUND EFFECTS
     //----------------
     pub fn sfx_from_file(&mut self, path: &Path) -> SoundEffect {
+        if !self.is_enabled() {
+            return SoundEffect {data: None, volume: 30,};
+        }
+
+        let _scope = memory::scope(MemoryTag::Audio);
+        warmup::record(AssetKind::Sound, path);
         let new_chunk = match Chunk::from_file(path) {
             Ok(chunk) => {
                 Some(Box::new(chunk))
@@ -49,11 +97,35 @@ impl AudioHandler {
         SoundEffect {data: new_chunk, volume: 30,}
     }
 
+    /// Decode a sound effect already read in memory (WAV, OGG...), `label` names it in the errors
+    pub fn sfx_from_bytes(&mut self, bytes: &[u8], label: &str) -> SoundEffect {
+        if !self.is_enabled() {
+            return SoundEffect {data: None, volume: 30,};
+        }
+
+        let _scope = memory::scope(MemoryTag::Audio);
+        let new_chunk = match RWops::from_bytes(bytes).and_then(|rwops| rwops.load_wav()) {
+            Ok(chunk) => {
+                Some(Box::new(chunk))
+            },
+            Err(e) => {
+                eprintln!("Couldn't load SFX \'{}\': {}", label, e);
+                None
+            },
+        };
+
+        SoundEffect {data: new_chunk, volume: 30,}
+    }
+
     pub fn sfx_play(&self, sfx: &SoundEffect) -> Option<Channel> {
+        if !self.is_enabled() {
+            return None;
+        }
+
         if let Some(chunk_box) = &sfx.data {
             match self.general_channel.play(chunk_box.as_ref(), 0) {
                 Ok(c) => {
-                    c.set_volume(30);
+                    c.set_volume(self.sfx_volume);
                     Some(c)
                 },
                 Err(e) => {
@@ -68,14 +140,29 @@ impl AudioHandler {
         }
     }
 
+    pub fn sfx_get_volume(&self) -> i32 {
+        self.sfx_volume
+    }
+
+    /// Volume of the sound effects played from now on
+    pub fn sfx_set_volume(&mut self, volume: i32) {
+        self.sfx_volume = volume;
+    }
+
     //--------
     // MUSIC
     //--------
     pub fn music_from_file(&mut self, path: &Path) -> Result<(), ()> {
+        if !self.is_enabled() {
+            return Err(());
+        }
+
+        let _scope = memory::scope(MemoryTag::Audio);
+        warmup::record(AssetKind::Music, path);
         match Music::from_file(path) {
             Ok(music) => {
                 self.music = Some(Box::new(music));
-                self.music_set_volume(30);
+                self.music_set_volume(self.music_volume);
                 Ok(())
             },
             Err(e) => {
@@ -109,6 +196,39 @@ impl AudioHandler {
         Music::halt();
     }
 
+    /// Pause the music and every sound effect playing.
+    /// What the game paused itself stays paused on `resume_all`
+    pub fn pause_all(&self) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        // Playing also means paused for SDL2 Mixer
+        if Music::is_playing() && !Music::is_paused() {
+            Music::pause();
+            self.paused_music.set(true);
+        }
+
+        let mut paused = self.paused_channels.borrow_mut();
+        for index in 0..mixer::allocate_channels(-1) {
+            let channel = Channel(index);
+            if channel.is_playing() && !channel.is_paused() {
+                channel.pause();
+                paused.push(channel);
+            }
+        }
+    }
+
+    /// Resume what `pause_all` paused, and only that
+    pub fn resume_all(&self) {
+        if self.paused_music.replace(false) {
+            Music::resume();
+        }
+        for channel in self.paused_channels.borrow_mut().drain(..) {
+            channel.resume();
+        }
+    }
+
     pub fn music_get_volume(&self) -> i32 {
         Music::get_volume()
     }
@@ -116,9 +236,78 @@ impl AudioHandler {
     pub fn music_set_volume(&self, volume: i32) {
         Music::set_volume(volume);
     }
+
+    //----------
+    // STREAMS
+    //----------
+    /// Open a stream of 16-bit samples queued by the game (e.g. the soundtrack of a video),
+    /// played next to the mixer on the default device. It starts paused
+    pub fn open_stream(&self, frequency: i32, channels: u8) -> Result<AudioStream, String> {
+        let subsystem = self.subsystem.as_ref().ok_or("The audio is disabled")?;
+
+        let spec = AudioSpecDesired {
+            freq: Some(frequency),
+            channels: Some(channels),
+            samples: None,
+        };
+        let queue = subsystem
+            .open_queue::<i16, _>(None, &spec)
+            .map_err(|e| format!("Couldn't open audio stream: {}", e))?;
+
+        Ok(AudioStream { queue })
+    }
+}
+
+/// Samples played in the order they're queued, see `AudioHandler::open_stream`
+pub struct AudioStream {
+    queue: AudioQueue<i16>,
+}
+
+impl AudioStream {
+    /// Queue interleaved samples, scaled by a volume from 0 to 128 like the mixer ones
+    pub fn queue(&self, samples: &[i16], volume: i32) -> Result<(), String> {
+        let volume = volume.max(0).min(mixer::MAX_VOLUME);
+        let scaled: Vec<i16> = samples
+            .iter()
+            .map(|&sample| (sample as i32 * volume / mixer::MAX_VOLUME) as i16)
+            .collect();
+
+        if self.queue.queue(&scaled) {
+            Ok(())
+        } else {
+            Err(sdl2::get_error())
+        }
+    }
+
+    pub fn play(&self) {
+        self.queue.resume();
+    }
+
+    pub fn pause(&self) {
+        self.queue.pause();
+    }
+
+    /// Drop the samples not played yet
+    pub fn clear(&self) {
+        self.queue.clear();
+    }
+
+    /// Seconds of samples queued and not played yet
+    pub fn queued_seconds(&self) -> f32 {
+        let spec = self.queue.spec();
+        let bytes_per_second = spec.freq as f32 * spec.channels as f32 * 2.0;
+        self.queue.size() as f32 / bytes_per_second.max(1.0)
+    }
 }
 
 pub struct SoundEffect {
     data: Option<Box<Chunk>>,
     volume: i32,
 }
+
+impl SoundEffect {
+    /// Check if the file was loaded correctly
+    pub fn is_loaded(&self) -> bool {
+        self.data.is_some()
+    }
+}
@@ -1,68 +1,1104 @@
+// standard imports
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
 // SDL2 imports
-use sdl2::event::{Event, WindowEvent};
+use sdl2::event::{Event, WindowEvent as SdlWindowEvent};
+use sdl2::keyboard::{Keycode, Scancode};
+use sdl2::mouse::Cursor;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::surface::Surface;
 use sdl2::EventPump;
 use sdl2::Sdl;
 
+pub use sdl2::mouse::SystemCursor;
+
 // imports from the module
-use super::audio::AudioHandler;
-use super::video::VideoHandler;
-use super::FPSHandler;
+use super::gamepad::GamepadHandler;
+use super::video::{decode_png_rgba, VideoHandler};
+use super::pipeline_cache;
+use super::vulkan::FrameError;
+use super::{AudioHandler, FPSHandler, SoundEffect};
+
+// other imports
+use crate::engine::assets::{read_font, read_mesh, read_script, read_shader, Assets, Handle, TextureAsset};
+#[cfg(feature = "serialization")]
+use crate::engine::assets::read_prefab;
+use crate::engine::camera::Camera2D;
+use crate::engine::config::{
+    BackgroundBehavior, BackgroundPolicy, EngineConfig, FrameErrorPolicy, WindowConfig,
+};
+use crate::engine::crash;
+use crate::engine::debug_server::DebugServer;
+use crate::engine::ecs::{Schedule, World};
+use crate::engine::error::MagmaError;
+use crate::engine::events::{
+    EngineEvent, EventBus, InputEvent, LifecycleEvent, WindowEvent, WindowId,
+};
+use crate::engine::exit::ExitReason;
+use crate::engine::hot_reload::{AssetWatcher, WatchKind};
+use crate::engine::jobs::JobPool;
+use crate::engine::loading::{AssetLoader, BatchId, DecodedAsset, LoadKind};
+use crate::engine::memory::{self, MemoryTag};
+use crate::engine::pack;
+use crate::engine::paths::{DirKind, Paths};
+use crate::engine::platform;
+#[cfg(feature = "serialization")]
+use crate::engine::prefab::Prefab;
+use crate::engine::random::Rng;
+#[cfg(feature = "serialization")]
+use crate::engine::save::SaveManager;
+use crate::engine::stats::FrameStats;
+use crate::engine::transform;
+use crate::engine::tween::TweenManager;
+
+/// Assets uploaded at most every frame by the background loading, to avoid hitches
+const MAX_ASSETS_FINALIZED_PER_FRAME: usize = 8;
 
 /// Main handler to manage calls to the SDL2 API
 pub struct CtxHandler {
     ctx: Sdl,
     event_pump: EventPump,
-    pub video: VideoHandler,
+    /// Main window renderer, None in headless mode
+    video: Option<VideoHandler>,
+    /// Additional windows, each one with its own renderer
+    windows: HashMap<WindowId, VideoHandler>,
     pub fps_manager: FPSHandler,
+    /// Frame times and renderer counters of the last frames
+    pub frame_stats: FrameStats,
+    /// SDL2 events of the frame, kept to not allocate them again every frame
+    polled_events: Vec<Event>,
+    /// Worker threads for the parallel work of the game
+    pub jobs: JobPool,
     pub audio: AudioHandler,
+    pub gamepads: GamepadHandler,
+    pub events: EventBus,
+    /// Cache of the loaded textures, sounds, shaders, fonts, meshes and scripts
+    pub assets: Assets,
+    /// Batches of assets loading in the background
+    pub loader: AssetLoader,
+    /// Files reloaded when changed on disk, None unless hot-reloading is enabled
+    pub watcher: Option<AssetWatcher>,
+    /// HTTP server exposing the engine state, None unless enabled
+    pub debug_server: Option<DebugServer>,
+    /// Entities and components of the game
+    pub world: World,
+    /// Systems run on the world at every update
+    pub systems: Schedule,
+    /// Config, saves, cache and logs directories of the game
+    pub paths: Paths,
+    /// Save slots of the game state
+    #[cfg(feature = "serialization")]
+    pub saves: SaveManager,
+    /// Random numbers of the gameplay, reseeded by replays
+    pub rng: Rng,
+    /// Tweens advanced after every update
+    pub tweens: TweenManager,
+    /// View of the main window, applied before every render
+    pub camera: Camera2D,
+    /// Directory the relative asset paths are loaded from
+    pub asset_root: PathBuf,
+    /// Key of every input action, by action name
+    pub key_bindings: BTreeMap<String, Keycode>,
 
-    must_break: bool,
+    pub frame_error_policy: FrameErrorPolicy,
+    /// Custom cursor in use, SDL2 needs it alive while it's set
+    cursor: Option<Cursor>,
+    /// Set once the program must stop, at the end of the current frame
+    exit_reason: Option<ExitReason>,
+    focused: bool,
+    minimized: bool,
+    /// The app is in the background on a mobile OS, its window can't be drawn
+    suspended: bool,
+    /// Throttling applied while the window is in the background
+    pub background_policy: BackgroundPolicy,
+    /// The audio was paused by the background policy
+    audio_paused: bool,
 }
 
 impl CtxHandler {
     /// Generate a new handler with a new context, window, graphics handler, event pump, audio mixer
-    pub fn new() -> CtxHandler {
-        let ctx = sdl2::init().expect("Couldn't init SDL2 context");
+    pub fn new(config: &EngineConfig) -> Result<CtxHandler, MagmaError> {
+        let flag = |enabled: bool| if enabled { "1" } else { "0" };
+        sdl2::hint::set("SDL_TOUCH_MOUSE_EVENTS", flag(config.touch.mouse_from_touch));
+        sdl2::hint::set("SDL_MOUSE_TOUCH_EVENTS", flag(config.touch.touch_from_mouse));
 
-        let event_pump = ctx
-            .event_pump()
-            .expect("Couldn't obtain Event Pump from SDL2 context");
+        let ctx = sdl2::init()
+            .map_err(|e| MagmaError::Sdl(format!("Couldn't init SDL2 context: {}", e)))?;
 
-        let video = VideoHandler::new(&ctx);
-        let audio = AudioHandler::new();
+        let event_pump = ctx.event_pump().map_err(|e| {
+            MagmaError::Sdl(format!("Couldn't obtain Event Pump from SDL2 context: {}", e))
+        })?;
+
+        let paths = config.resolve_paths();
+
+        // Read by the renderers created from now on, restarted engines choose it again
+        let cache_file = config.pipeline_cache.as_ref().and_then(|path| {
+            paths
+                .file(DirKind::Cache, path)
+                .map_err(|e| crash::log(&format!("Pipeline cache disabled: {}", e)))
+                .ok()
+        });
+        pipeline_cache::set_file(cache_file);
+
+        // Dedicated servers skip the SDL2 video subsystem and the renderer entirely
+        let video = if config.headless {
+            None
+        } else {
+            let mut video = {
+                let _scope = memory::scope(MemoryTag::Graphics);
+                VideoHandler::new(&ctx, &config.window)?
+            };
+            video.gl_handler.reuse_command_buffers = config.reuse_command_buffers;
+            if let Err(e) = video.gl_handler.set_threaded(config.render_thread) {
+                crash::log(&format!("{}, rendering on the main thread", e));
+            }
+            if let Err(e) = video.gl_set_swap_interval(config.swap_interval) {
+                crash::log(&e);
+            }
+            Some(video)
+        };
+        // Optional subsystems failing to start are disabled instead of stopping the engine
+        let audio = if config.subsystems.audio && !config.headless {
+            let _scope = memory::scope(MemoryTag::Audio);
+            AudioHandler::new(&ctx, &config.audio).unwrap_or_else(|e| {
+                crash::log(&format!("Couldn't init audio, running without it: {}", e));
+                AudioHandler::disabled(&config.audio)
+            })
+        } else {
+            AudioHandler::disabled(&config.audio)
+        };
+        let gamepads = if config.subsystems.gamepads && !config.headless {
+            GamepadHandler::new(&ctx, config.input.max_players).unwrap_or_else(|e| {
+                crash::log(&format!(
+                    "Couldn't init game controllers, running without them: {}",
+                    e
+                ));
+                GamepadHandler::disabled(config.input.max_players)
+            })
+        } else {
+            GamepadHandler::disabled(config.input.max_players)
+        };
+
+        // Relative paths of the files shipped with the game are in the app bundle on iOS
+        let bundled = |path: &Path| match platform::bundle_dir() {
+            Some(bundle) if path.is_relative() => bundle.join(path),
+            _ => path.to_path_buf(),
+        };
+        let asset_root = bundled(&config.asset_root);
+
+        // Mounted again by the restarted engines
+        for archive in config.asset_archives.iter() {
+            let archive = bundled(archive);
+            pack::unmount(&archive);
+            pack::mount(&archive, &asset_root).map_err(MagmaError::Asset)?;
+        }
 
         let fps_manager = FPSHandler::new(60);
 
-        CtxHandler {
+        let mut world = World::new();
+        transform::register_snapshot(&mut world);
+
+        Ok(CtxHandler {
             ctx,
             event_pump,
             video,
+            windows: HashMap::new(),
             fps_manager,
+            frame_stats: FrameStats::new(),
+            polled_events: Vec::new(),
+            jobs: JobPool::with_default_threads(),
             audio,
+            gamepads,
+            events: EventBus::new(),
+            assets: Assets::new(),
+            loader: AssetLoader::new(),
+            watcher: None,
+            debug_server: None,
+            world,
+            systems: Schedule::new(),
+            paths: paths.clone(),
+            #[cfg(feature = "serialization")]
+            saves: SaveManager::new(paths),
+            rng: config.rng_seed.map_or_else(Rng::from_time, Rng::new),
+            tweens: TweenManager::new(),
+            camera: Camera2D::new(),
+            asset_root,
+            key_bindings: config.key_bindings.clone(),
 
-            must_break: false,
-        }
+            frame_error_policy: config.frame_error_policy,
+            cursor: None,
+            exit_reason: None,
+            focused: true,
+            minimized: false,
+            suspended: false,
+            background_policy: config.background_policy,
+            audio_paused: false,
+        })
     }
 
     /// Check all SDL2 and SDL_Window events
     pub fn check_events(&mut self) {
-        for event in self.event_pump.poll_iter() {
+        // Collected first, handling them needs the whole handler
+        let mut events = mem::take(&mut self.polled_events);
+        events.extend(self.event_pump.poll_iter());
+
+        for event in events.drain(..) {
+            self.gamepads.handle_event(&event);
+
             match event {
-                Event::Quit { .. } => self.must_break = true,
+                Event::Quit { .. } => self.set_break_signal(),
                 Event::Window {
-                    win_event: WindowEvent::Resized(_, _),
+                    window_id,
+                    win_event: SdlWindowEvent::SizeChanged(width, height),
                     ..
                 } => {
-                    self.video.set_window_resized(true);
+                    if let Some(video) = self.get_window(window_id) {
+                        video.notify_resize(width as u32, height as u32);
+                    }
+                }
+                Event::Window {
+                    window_id,
+                    win_event,
+                    ..
+                } => {
+                    if let Some(window_event) = self.handle_window_event(window_id, win_event) {
+                        self.events
+                            .push(EngineEvent::Window(window_id, window_event));
+                    }
+                }
+                Event::TextInput { text, .. } => self.events.push(EngineEvent::TextInput(text)),
+                Event::AppTerminating { .. } => self.handle_lifecycle(LifecycleEvent::Terminating),
+                Event::AppLowMemory { .. } => self.handle_lifecycle(LifecycleEvent::LowMemory),
+                Event::AppWillEnterBackground { .. } => {
+                    self.handle_lifecycle(LifecycleEvent::WillEnterBackground)
+                }
+                Event::AppDidEnterBackground { .. } => {
+                    self.handle_lifecycle(LifecycleEvent::DidEnterBackground)
+                }
+                Event::AppWillEnterForeground { .. } => {
+                    self.handle_lifecycle(LifecycleEvent::WillEnterForeground)
+                }
+                Event::AppDidEnterForeground { .. } => {
+                    self.handle_lifecycle(LifecycleEvent::DidEnterForeground)
+                }
+                event => {
+                    if let Some(input) = self.translate_input_event(&event) {
+                        self.events.push(EngineEvent::Input(input));
+                    }
+                }
+            }
+        }
+        self.polled_events = events;
+    }
+
+    /// Input of the player reported to the game, controllers identified by player slot
+    fn translate_input_event(&self, event: &Event) -> Option<InputEvent> {
+        let input = match *event {
+            Event::KeyDown {
+                window_id,
+                keycode: Some(key),
+                repeat,
+                ..
+            } => InputEvent::KeyDown {
+                window: window_id,
+                key,
+                repeat,
+            },
+            Event::KeyUp {
+                window_id,
+                keycode: Some(key),
+                ..
+            } => InputEvent::KeyUp {
+                window: window_id,
+                key,
+            },
+            Event::MouseMotion {
+                window_id, x, y, ..
+            } => InputEvent::MouseMoved {
+                window: window_id,
+                x,
+                y,
+            },
+            Event::MouseButtonDown {
+                window_id,
+                mouse_btn,
+                x,
+                y,
+                ..
+            } => InputEvent::MouseButtonDown {
+                window: window_id,
+                button: mouse_btn,
+                x,
+                y,
+            },
+            Event::MouseButtonUp {
+                window_id,
+                mouse_btn,
+                x,
+                y,
+                ..
+            } => InputEvent::MouseButtonUp {
+                window: window_id,
+                button: mouse_btn,
+                x,
+                y,
+            },
+            Event::MouseWheel {
+                window_id, x, y, ..
+            } => InputEvent::MouseWheel {
+                window: window_id,
+                x,
+                y,
+            },
+            Event::ControllerButtonDown { which, button, .. } => InputEvent::GamepadButton {
+                player: self.gamepads.get_player(which)?,
+                button,
+                pressed: true,
+            },
+            Event::ControllerButtonUp { which, button, .. } => InputEvent::GamepadButton {
+                player: self.gamepads.get_player(which)?,
+                button,
+                pressed: false,
+            },
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => InputEvent::GamepadAxis {
+                player: self.gamepads.get_player(which)?,
+                axis,
+                value: (value as f32 / i16::MAX as f32).max(-1.0),
+            },
+            Event::FingerDown {
+                finger_id, x, y, ..
+            } => {
+                let (window, x, y) = self.touch_position(x, y);
+                InputEvent::TouchDown {
+                    window,
+                    finger: finger_id,
+                    x,
+                    y,
+                }
+            }
+            Event::FingerMotion {
+                finger_id, x, y, ..
+            } => {
+                let (window, x, y) = self.touch_position(x, y);
+                InputEvent::TouchMoved {
+                    window,
+                    finger: finger_id,
+                    x,
+                    y,
+                }
+            }
+            Event::FingerUp {
+                finger_id, x, y, ..
+            } => {
+                let (window, x, y) = self.touch_position(x, y);
+                InputEvent::TouchUp {
+                    window,
+                    finger: finger_id,
+                    x,
+                    y,
+                }
+            }
+            _ => return None,
+        };
+
+        Some(input)
+    }
+
+    /// Window owning a touch and its pixels there, SDL2 gives them from 0 to 1 over that window.
+    /// SDL2 sends the touches to the window with the mouse focus, the main one when none has it
+    fn touch_position(&self, x: f32, y: f32) -> (WindowId, f32, f32) {
+        let window = self
+            .ctx
+            .mouse()
+            .focused_window_id()
+            .filter(|id| *id == self.main_window_id() || self.windows.contains_key(id))
+            .unwrap_or_else(|| self.main_window_id());
+        let video = if window == self.main_window_id() {
+            self.main_video()
+        } else {
+            self.windows.get(&window)
+        };
+        let (width, height) = video.map_or((1, 1), |video| video.get_window_size());
+
+        (window, x * width as f32, y * height as f32)
+    }
+
+    /// Suspend the rendering and the audio while the app is in the background, then report the event.
+    /// The surface of the window is destroyed in the background on Android, the renderer starts again on the new one
+    fn handle_lifecycle(&mut self, event: LifecycleEvent) {
+        match event {
+            LifecycleEvent::Terminating => self.set_break_signal(),
+            LifecycleEvent::LowMemory => self.assets.collect(),
+            LifecycleEvent::WillEnterBackground => self.suspended = true,
+            LifecycleEvent::DidEnterForeground => {
+                self.suspended = false;
+                if cfg!(target_os = "android") && !self.is_headless() {
+                    crash::log("Back from the background, recreating the window surface");
+                    self.recover_device(self.main_window_id());
+                }
+            }
+            LifecycleEvent::DidEnterBackground | LifecycleEvent::WillEnterForeground => {}
+        }
+        self.update_background_audio();
+
+        self.events.push(EngineEvent::Lifecycle(event));
+    }
+
+    /// Keep track of the window state, returning the engine event to report
+    fn handle_window_event(
+        &mut self,
+        window_id: WindowId,
+        win_event: SdlWindowEvent,
+    ) -> Option<WindowEvent> {
+        // The engine state follows the main window only
+        if window_id != self.main_window_id() {
+            if let SdlWindowEvent::Resized(width, height) = win_event {
+                if let Some(video) = self.windows.get_mut(&window_id) {
+                    video.notify_resize(width as u32, height as u32);
+                }
+            }
+
+            return Self::translate_window_event(win_event);
+        }
+
+        match win_event {
+            SdlWindowEvent::Resized(width, height) => {
+                if let Some(video) = self.video.as_mut() {
+                    video.notify_resize(width as u32, height as u32)
+                }
+            }
+            SdlWindowEvent::Minimized => self.minimized = true,
+            SdlWindowEvent::Restored => self.minimized = false,
+            SdlWindowEvent::FocusGained => self.focused = true,
+            SdlWindowEvent::FocusLost => self.focused = false,
+            _ => {}
+        }
+        self.update_background_audio();
+
+        Self::translate_window_event(win_event)
+    }
+
+    fn translate_window_event(win_event: SdlWindowEvent) -> Option<WindowEvent> {
+        let window_event = match win_event {
+            SdlWindowEvent::Shown => WindowEvent::Shown,
+            SdlWindowEvent::Hidden => WindowEvent::Hidden,
+            SdlWindowEvent::Exposed => WindowEvent::Exposed,
+            SdlWindowEvent::Moved(x, y) => WindowEvent::Moved(x, y),
+            SdlWindowEvent::Resized(width, height) => WindowEvent::Resized(width as u32, height as u32),
+            SdlWindowEvent::Minimized => WindowEvent::Minimized,
+            SdlWindowEvent::Maximized => WindowEvent::Maximized,
+            SdlWindowEvent::Restored => WindowEvent::Restored,
+            SdlWindowEvent::FocusGained => WindowEvent::FocusGained,
+            SdlWindowEvent::FocusLost => WindowEvent::FocusLost,
+            SdlWindowEvent::Enter => WindowEvent::MouseEntered,
+            SdlWindowEvent::Leave => WindowEvent::MouseLeft,
+            SdlWindowEvent::Close => WindowEvent::CloseRequested,
+            _ => return None,
+        };
+
+        Some(window_event)
+    }
+
+    /// Open an additional window with its own renderer, on the GPU device of the main window
+    pub fn create_window(&mut self, config: &WindowConfig) -> Result<WindowId, MagmaError> {
+        let main = self
+            .video
+            .as_ref()
+            .ok_or_else(|| MagmaError::Config(String::from("Can't open windows in headless mode")))?;
+
+        let video = {
+            let _scope = memory::scope(MemoryTag::Graphics);
+            VideoHandler::new_sharing(&self.ctx, config, main)?
+        };
+        let id = video.get_window_id();
+
+        self.windows.insert(id, video);
+
+        Ok(id)
+    }
+
+    /// Close an additional window (the main one can't be closed this way)
+    pub fn close_window(&mut self, id: WindowId) -> bool {
+        self.windows.remove(&id).is_some()
+    }
+
+    /// Identifier of the main window, 0 in headless mode (never used by SDL2)
+    pub fn main_window_id(&self) -> WindowId {
+        self.video.as_ref().map_or(0, |video| video.get_window_id())
+    }
+
+    /// Renderer of the main window, to create draw objects and move the camera.
+    /// Panics in headless mode, use `try_video` in code shared with dedicated servers
+    pub fn video(&mut self) -> &mut VideoHandler {
+        self.video
+            .as_mut()
+            .expect("No video in headless mode, check is_headless or use try_video")
+    }
+
+    /// Renderer of the main window, None in headless mode
+    pub fn try_video(&mut self) -> Option<&mut VideoHandler> {
+        self.video.as_mut()
+    }
+
+    pub(crate) fn main_video(&self) -> Option<&VideoHandler> {
+        self.video.as_ref()
+    }
+
+    /// Check if the engine runs without window, renderer and audio (dedicated servers)
+    pub fn is_headless(&self) -> bool {
+        self.video.is_none()
+    }
+
+    /// Get the handler of any window, the main one included
+    pub fn get_window(&mut self, id: WindowId) -> Option<&mut VideoHandler> {
+        if self.video.is_some() && id == self.main_window_id() {
+            self.video.as_mut()
+        } else {
+            self.windows.get_mut(&id)
+        }
+    }
+
+    /// Check if the window has the keyboard focus
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Check if the window is minimized
+    pub fn is_minimized(&self) -> bool {
+        self.minimized
+    }
+
+    /// Check if the app is in the background on Android or iOS, nothing is rendered meanwhile
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    /// Behavior of the background policy in use, None while the window is in the foreground.
+    /// A suspended app behaves as minimized
+    pub fn get_background_behavior(&self) -> Option<BackgroundBehavior> {
+        if self.minimized || self.suspended {
+            Some(self.background_policy.minimized)
+        } else if !self.focused {
+            Some(self.background_policy.unfocused)
+        } else {
+            None
+        }
+    }
+
+    /// Pause or resume the audio as the window goes to the background or comes back.
+    /// A suspended app is always silent, whatever the policy
+    fn update_background_audio(&mut self) {
+        let background = self.get_background_behavior().is_some();
+        let pause = self.background_policy.pause_audio || self.suspended;
+
+        if background && pause && !self.audio_paused {
+            self.audio.pause_all();
+            self.audio_paused = true;
+        } else if !background && self.audio_paused {
+            self.audio.resume_all();
+            self.audio_paused = false;
+        }
+    }
+
+    /// Render the frame, handling the errors as the frame error policy says.
+    /// A lost device is always recreated, whatever the policy
+    pub fn update_video(&mut self) {
+        // Nothing would be visible, or the policy says so: don't waste the GPU time
+        if self.minimized
+            || self.suspended
+            || self.get_background_behavior().map_or(false, |b| !b.render)
+        {
+            return;
+        }
+
+        let video = match self.video.as_mut() {
+            Some(video) => video,
+            None => return,
+        };
+
+        let mut lost_windows = Vec::new();
+        let mut abort = None;
+        if let Err(e) = video.update() {
+            if matches!(e, FrameError::DeviceLost) {
+                lost_windows.push(video.get_window_id());
+            } else {
+                match self.frame_error_policy {
+                    FrameErrorPolicy::SkipFrame => {}
+                    FrameErrorPolicy::ResetRenderer => video.gl_handler.reset(),
+                    FrameErrorPolicy::Abort => abort = Some(e.to_string()),
+                }
+            }
+
+            self.events.push(EngineEvent::FrameError(e));
+        }
+
+        let mut errors = Vec::new();
+        for (id, video) in self.windows.iter_mut() {
+            if let Err(e) = video.update() {
+                if matches!(e, FrameError::DeviceLost) {
+                    lost_windows.push(*id);
+                } else if self.frame_error_policy == FrameErrorPolicy::ResetRenderer {
+                    video.gl_handler.reset();
+                }
+                errors.push(e);
+            }
+        }
+        for e in errors {
+            if self.frame_error_policy == FrameErrorPolicy::Abort
+                && !matches!(e, FrameError::DeviceLost)
+                && abort.is_none()
+            {
+                abort = Some(e.to_string());
+            }
+            self.events.push(EngineEvent::FrameError(e));
+        }
+
+        for id in lost_windows {
+            crash::log(&format!("Vulkan Device of window {} lost, recreating it", id));
+            self.recover_device(id);
+        }
+
+        // The frame error policy says to stop, `Engine::run` returns the error at the end of the frame
+        if let Some(e) = abort {
+            let message = format!("Unrecoverable frame error: {}", e);
+            crash::log(&message);
+            self.exit(ExitReason::Error(MagmaError::Graphics(message)));
+        }
+    }
+
+    /// Start the renderer of a window again on a new device and surface. The textures of the asset manager,
+    /// uploaded with the main window, are replaced by the new ones. Stops the engine if no device works
+    fn recover_device(&mut self, id: WindowId) {
+        let is_main = id == self.main_window_id();
+        let video = match self.get_window(id) {
+            Some(video) => video,
+            None => return,
+        };
+        let recovery = match video.recreate_device() {
+            Ok(recovery) => recovery,
+            Err(e) => {
+                crash::log(&format!("Couldn't start the renderer again: {}", e));
+                self.exit(ExitReason::Error(e));
+                return;
+            }
+        };
+
+        if is_main {
+            for path in self.assets.textures.loaded_paths() {
+                let asset = match self.assets.textures.get(&path) {
+                    Some(asset) => asset,
+                    None => continue,
+                };
+                match recovery.texture(&asset.texture) {
+                    Ok(texture) => {
+                        let dimensions = asset.dimensions;
+                        self.assets.textures.replace(&path, TextureAsset { texture, dimensions });
+                    }
+                    Err(e) => crash::log(&format!("Couldn't recreate texture '{}': {}", path.display(), e)),
                 }
-                _ => {}
             }
         }
+
+        self.events.push(EngineEvent::DeviceRecovered {
+            window: id,
+            lost_objects: recovery.lost_objects,
+        });
     }
 
     /// Fetch the flag to stop the program
     pub fn get_break_signal(&self) -> bool {
-        self.must_break
+        self.exit_reason.is_some()
+    }
+
+    /// Why the program is stopping, if it is
+    pub fn get_exit_reason(&self) -> Option<&ExitReason> {
+        self.exit_reason.as_ref()
+    }
+
+    pub(crate) fn take_exit_reason(&mut self) -> Option<ExitReason> {
+        self.exit_reason.take()
+    }
+
+    /// Path of an asset, relative paths are found in the asset root
+    pub fn asset_path(&self, path: &Path) -> PathBuf {
+        self.asset_root.join(path)
+    }
+
+    /// Action bound to a key, if any
+    pub fn action_for_key(&self, key: Keycode) -> Option<&str> {
+        self.key_bindings
+            .iter()
+            .find(|(_, bound)| **bound == key)
+            .map(|(action, _)| action.as_str())
+    }
+
+    pub fn key_for_action(&self, action: &str) -> Option<Keycode> {
+        self.key_bindings.get(action).copied()
+    }
+
+    /// Current state of a key, for polling instead of following the input events
+    pub fn is_key_down(&self, key: Keycode) -> bool {
+        Scancode::from_keycode(key).map_or(false, |scancode| {
+            self.event_pump
+                .keyboard_state()
+                .is_scancode_pressed(scancode)
+        })
+    }
+
+    /// Whether the key bound to an action is held
+    pub fn is_action_down(&self, action: &str) -> bool {
+        self.key_for_action(action)
+            .map_or(false, |key| self.is_key_down(key))
+    }
+
+    /// Load a texture through the asset cache
+    pub fn load_texture(&mut self, path: &Path) -> Result<Handle<TextureAsset>, String> {
+        let path = self.asset_path(path);
+        let gl_handler = &self
+            .video
+            .as_ref()
+            .ok_or("Can't load textures in headless mode")?
+            .gl_handler;
+
+        self.assets.textures.get_or_load(&path, |path| {
+            let _scope = memory::scope(MemoryTag::Assets);
+            let (texture, dimensions) = if pack::is_packed(path) {
+                let bytes = pack::read(path).map_err(|e| e.to_string())?;
+                let (pixels, width, height) = decode_png_rgba(io::Cursor::new(bytes))?;
+                gl_handler.create_texture(path, &pixels, width, height)?
+            } else {
+                gl_handler.load_texture(path.to_str().ok_or("Texture path isn't valid UTF-8")?)?
+            };
+            Ok(TextureAsset { texture, dimensions })
+        })
+    }
+
+    /// Load a sound effect through the asset cache
+    pub fn load_sound(&mut self, path: &Path) -> Result<Handle<SoundEffect>, String> {
+        let path = self.asset_path(path);
+        let audio = &mut self.audio;
+
+        self.assets.sounds.get_or_load(&path, |path| {
+            let sound = if pack::is_packed(path) {
+                let bytes = pack::read(path).map_err(|e| e.to_string())?;
+                audio.sfx_from_bytes(&bytes, &path.display().to_string())
+            } else {
+                audio.sfx_from_file(path)
+            };
+            if sound.is_loaded() {
+                Ok(sound)
+            } else {
+                Err(format!("Couldn't load sound '{}'", path.display()))
+            }
+        })
+    }
+
+    /// Load an entity definition through the asset cache, spawned with `Prefabs::spawn`
+    #[cfg(feature = "serialization")]
+    pub fn load_prefab(&mut self, path: &Path) -> Result<Handle<Prefab>, String> {
+        let path = self.asset_path(path);
+        self.assets.load_prefab(&path)
+    }
+
+    /// Queue an input event as if it came from the OS, handled by the next `check_events`
+    pub fn simulate_event(&self, event: Event) -> Result<(), String> {
+        self.ctx.event()?.push_event(event)
+    }
+
+    /// Load assets in the background, follow the progress with `loader.progress` and take
+    /// the handles with `loader.finish` once `EngineEvent::AssetsLoaded` is received
+    pub fn load_batch(&mut self, requests: Vec<(LoadKind, PathBuf)>) -> BatchId {
+        let requests = requests
+            .into_iter()
+            .map(|(kind, path)| (kind, self.asset_path(&path)))
+            .collect();
+
+        self.loader.start(requests)
+    }
+
+    /// Reload the loaded assets when they change on disk, checking them every `interval`
+    pub fn enable_hot_reload(&mut self, interval: Duration) {
+        self.watcher = Some(AssetWatcher::new(interval));
+    }
+
+    pub fn disable_hot_reload(&mut self) {
+        self.watcher = None;
+    }
+
+    /// Apply the runtime settings of a config file again whenever it changes (needs hot-reloading)
+    #[cfg(feature = "config-files")]
+    pub fn watch_config(&mut self, path: &Path) {
+        if let Some(watcher) = self.watcher.as_mut() {
+            watcher.watch(path, WatchKind::Config);
+        }
+    }
+
+    /// Start the HTTP debug server on the given address, see `DebugServer::bind`
+    pub fn enable_debug_server(&mut self, address: SocketAddr) -> io::Result<()> {
+        self.debug_server = Some(DebugServer::bind(address)?);
+
+        Ok(())
+    }
+
+    pub fn disable_debug_server(&mut self) {
+        self.debug_server = None;
+    }
+
+    /// Follow the camera target and set the view of the main window (called by the engine every frame)
+    pub fn update_camera(&mut self, delta: f32) {
+        self.camera.update(delta, &self.world);
+
+        // Given to the next frame, the renderer may still be drawing the last one
+        if let Some(video) = &mut self.video {
+            let view = self.camera.view(video.get_window_size());
+            video.set_camera_view(view);
+        }
+    }
+
+    /// Reload the watched files changed on disk (called by the engine every frame)
+    pub fn update_hot_reload(&mut self) {
+        let mut watcher = match self.watcher.take() {
+            Some(watcher) if watcher.is_due() => watcher,
+            watcher => {
+                self.watcher = watcher;
+                return;
+            }
+        };
+
+        // Follow what's loaded right now, unloaded assets don't need to be watched anymore.
+        // Textures loaded by path without the asset cache (e.g. `new_sprite`) are reloaded too
+        let mut textures = self.assets.textures.loaded_paths();
+        if let Some(video) = &self.video {
+            textures.extend(video.gl_handler.texture_files());
+        }
+        textures.sort();
+        textures.dedup();
+        watcher.sync(WatchKind::Texture, &textures);
+        watcher.sync(WatchKind::Sound, &self.assets.sounds.loaded_paths());
+        watcher.sync(WatchKind::Shader, &self.assets.shaders.loaded_paths());
+        watcher.sync(WatchKind::Font, &self.assets.fonts.loaded_paths());
+        watcher.sync(WatchKind::Mesh, &self.assets.meshes.loaded_paths());
+        watcher.sync(WatchKind::Script, &self.assets.scripts.loaded_paths());
+        #[cfg(feature = "serialization")]
+        watcher.sync(WatchKind::Prefab, &self.assets.prefabs.loaded_paths());
+
+        for (path, kind) in watcher.poll() {
+            match self.reload(&path, kind) {
+                Ok(()) => self.events.push(EngineEvent::AssetReloaded { path, kind }),
+                Err(e) => crash::log(&format!("Couldn't reload '{}': {}", path.display(), e)),
+            }
+        }
+
+        self.watcher = Some(watcher);
+    }
+
+    fn reload(&mut self, path: &Path, kind: WatchKind) -> Result<(), String> {
+        match kind {
+            WatchKind::Texture => {
+                let gl_handler = &self
+                    .video
+                    .as_ref()
+                    .ok_or("Can't load textures in headless mode")?
+                    .gl_handler;
+                let file = File::open(path).map_err(|e| e.to_string())?;
+                let (pixels, width, height) = decode_png_rgba(file)?;
+                let (texture, dimensions) = gl_handler.reload_texture(path, &pixels, width, height)?;
+                self.assets
+                    .textures
+                    .replace(path, TextureAsset { texture, dimensions });
+            }
+            WatchKind::Sound => {
+                let sound = self.audio.sfx_from_file(path);
+                if !sound.is_loaded() {
+                    return Err(String::from("Couldn't decode sound"));
+                }
+                self.assets.sounds.replace(path, sound);
+            }
+            WatchKind::Shader => {
+                let shader = read_shader(path)?;
+                self.assets.shaders.replace(path, shader);
+            }
+            WatchKind::Font => {
+                let font = read_font(path)?;
+                self.assets.fonts.replace(path, font);
+            }
+            WatchKind::Mesh => {
+                let mesh = read_mesh(path)?;
+                self.assets.meshes.replace(path, mesh);
+            }
+            WatchKind::Script => {
+                let script = read_script(path)?;
+                self.assets.scripts.replace(path, script);
+            }
+            #[cfg(feature = "serialization")]
+            WatchKind::Prefab => {
+                let prefab = read_prefab(path)?;
+                self.assets.prefabs.replace(path, prefab);
+            }
+            #[cfg(not(feature = "serialization"))]
+            WatchKind::Prefab => return Err(String::from("Needs the serialization feature")),
+            #[cfg(feature = "config-files")]
+            WatchKind::Config => {
+                let config = EngineConfig::load(path)?;
+                self.audio.sfx_set_volume(config.audio.sfx_volume);
+                self.audio.music_set_volume(config.audio.music_volume);
+                self.key_bindings = config.key_bindings;
+                if let Some(video) = self.video.as_mut() {
+                    video.gl_set_swap_interval(config.swap_interval)?;
+                }
+            }
+            #[cfg(not(feature = "config-files"))]
+            WatchKind::Config => return Err(String::from("Needs the config-files feature")),
+        }
+
+        Ok(())
+    }
+
+    /// Finalize some of the assets decoded in the background (called by the engine every frame)
+    pub fn update_loading(&mut self) {
+        for (batch, path, decoded) in self.loader.poll(MAX_ASSETS_FINALIZED_PER_FRAME) {
+            let result = match decoded {
+                Ok(DecodedAsset::Texture { pixels, width, height }) => {
+                    let video = self.video.as_ref();
+                    self.assets
+                        .textures
+                        .get_or_load(&path, |path| {
+                            let _scope = memory::scope(MemoryTag::Assets);
+                            video
+                                .ok_or("Can't load textures in headless mode")?
+                                .gl_handler
+                                .create_texture(path, &pixels, width, height)
+                                .map(|(texture, dimensions)| TextureAsset { texture, dimensions })
+                        })
+                        .map(|h| self.loader.complete(batch, |a| a.textures.push(h)))
+                }
+                Ok(DecodedAsset::Sound(bytes)) => {
+                    let audio = &mut self.audio;
+                    self.assets
+                        .sounds
+                        .get_or_load(&path, |path| {
+                            let sound = audio.sfx_from_bytes(&bytes, &path.display().to_string());
+                            if sound.is_loaded() {
+                                Ok(sound)
+                            } else {
+                                Err(format!("Couldn't decode sound '{}'", path.display()))
+                            }
+                        })
+                        .map(|h| self.loader.complete(batch, |a| a.sounds.push(h)))
+                }
+                Ok(DecodedAsset::Shader(shader)) => self
+                    .assets
+                    .shaders
+                    .get_or_load(&path, |_| Ok(shader))
+                    .map(|h| self.loader.complete(batch, |a| a.shaders.push(h))),
+                Ok(DecodedAsset::Font(font)) => self
+                    .assets
+                    .fonts
+                    .get_or_load(&path, |_| Ok(font))
+                    .map(|h| self.loader.complete(batch, |a| a.fonts.push(h))),
+                Ok(DecodedAsset::Mesh(mesh)) => self
+                    .assets
+                    .meshes
+                    .get_or_load(&path, |_| Ok(mesh))
+                    .map(|h| self.loader.complete(batch, |a| a.meshes.push(h))),
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = result {
+                self.loader.fail(batch, path, e);
+            }
+        }
+
+        for batch in self.loader.take_completed() {
+            self.events.push(EngineEvent::AssetsLoaded(batch));
+        }
+    }
+
+    /// Ask the program to stop at the end of the current frame
+    pub fn set_break_signal(&mut self) {
+        if self.exit_reason.is_none() {
+            self.exit_reason = Some(ExitReason::Quit);
+        }
+    }
+
+    /// Stop the program at the end of the current frame, the reason is returned by `Engine::run`.
+    /// Replaces the reason of a previous request
+    pub fn exit(&mut self, reason: ExitReason) {
+        self.exit_reason = Some(reason);
+    }
+
+    /// Stop the engine and start it again with new settings, keeping the process alive
+    pub fn restart(&mut self, config: EngineConfig) {
+        self.exit(ExitReason::Restart(Box::new(config)));
+    }
+
+    /// Show or hide the mouse cursor
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.ctx.mouse().show_cursor(visible);
+    }
+
+    pub fn is_cursor_visible(&self) -> bool {
+        self.ctx.mouse().is_cursor_showing()
+    }
+
+    /// Use a PNG image as mouse cursor, the hotspot being the clicking point in image pixels
+    pub fn set_cursor_from_file(&mut self, path: &Path, hotspot: (i32, i32)) -> Result<(), String> {
+        let bytes = pack::read(path)
+            .map_err(|e| format!("Couldn't open cursor file '{}': {}", path.display(), e))?;
+        let (mut pixels, width, height) = decode_png_rgba(io::Cursor::new(bytes))?;
+
+        let surface = Surface::from_data(
+            &mut pixels,
+            width,
+            height,
+            width * 4,
+            PixelFormatEnum::RGBA32,
+        )?;
+        let cursor = Cursor::from_surface(surface, hotspot.0, hotspot.1)?;
+
+        cursor.set();
+        self.cursor = Some(cursor);
+
+        Ok(())
+    }
+
+    /// Use one of the cursors provided by the OS
+    pub fn set_system_cursor(&mut self, system_cursor: SystemCursor) -> Result<(), String> {
+        let cursor = Cursor::from_system(system_cursor)?;
+
+        cursor.set();
+        self.cursor = Some(cursor);
+
+        Ok(())
+    }
+
+    /// Go back to the default arrow cursor
+    pub fn reset_cursor(&mut self) -> Result<(), String> {
+        self.set_system_cursor(SystemCursor::Arrow)
+    }
+
+    /// Get the text currently held by the OS clipboard
+    pub fn get_clipboard_text(&self) -> Result<String, String> {
+        self.video
+            .as_ref()
+            .ok_or("No clipboard in headless mode")?
+            .get_clipboard_text()
+    }
+
+    /// Replace the OS clipboard content with the given text
+    pub fn set_clipboard_text(&mut self, text: &str) -> Result<(), String> {
+        self.video
+            .as_mut()
+            .ok_or("No clipboard in headless mode")?
+            .set_clipboard_text(text)
     }
 
     /// Public function to set the Ctx's framerate
@@ -75,12 +1111,31 @@ impl CtxHandler {
         (1. / self.fps_manager.get_limit()) as u16
     }
 
+    /// Limit the framerate (e.g. 30, 60, 144), 0 removes the limit
+    pub fn set_target_fps(&mut self, fps: u32) {
+        self.fps_manager.set_target_fps(fps);
+    }
+
+    /// Target framerate, None if uncapped
+    pub fn get_target_fps(&self) -> Option<u32> {
+        self.fps_manager.get_target_fps()
+    }
+
+    /// Remove the framerate limit, disable vsync with `video.gl_set_swap_interval` to go past the refresh rate
+    pub fn set_uncapped_fps(&mut self) {
+        self.fps_manager.set_uncapped();
+    }
+
     pub fn get_current_framerate(&self) -> u16 {
         self.fps_manager.get_fps()
     }
 
     /// Wait for the next frame based on the current framerate
     pub fn wait(&mut self) {
-        self.fps_manager.wait()
+        // Keep polling events at a slower pace until the window comes back
+        match self.get_background_behavior().and_then(|b| b.max_fps) {
+            Some(fps) if fps > 0 => self.fps_manager.wait_at_least(1. / fps as f32),
+            _ => self.fps_manager.wait(),
+        }
     }
 }
@@ -9,6 +9,7 @@ use std::thread;
 pub struct FPSHandler {
     last_loop: Instant,
     delta: f32,
+    /// Minimum time of a frame, 0 when uncapped
     limit: f32,
 }
 
@@ -30,6 +31,29 @@ impl FPSHandler {
         self.limit = new_limit;
     }
 
+    /// Limit the framerate to `fps` frames per second (0 means uncapped)
+    pub fn set_target_fps(&mut self, fps: u32) {
+        self.limit = if fps == 0 { 0.0 } else { 1. / fps as f32 };
+    }
+
+    /// Target framerate, None if uncapped
+    pub fn get_target_fps(&self) -> Option<u32> {
+        if self.is_uncapped() {
+            None
+        } else {
+            Some((1. / self.limit).round() as u32)
+        }
+    }
+
+    /// Render frames as fast as possible (still bound by the present mode of the swapchain)
+    pub fn set_uncapped(&mut self) {
+        self.limit = 0.0;
+    }
+
+    pub fn is_uncapped(&self) -> bool {
+        self.limit <= 0.0
+    }
+
     pub fn get_fps(&self) -> u16 {
         (1. / self.get_delta()).round() as u16
     }
@@ -39,9 +63,14 @@ impl FPSHandler {
     }
 
     pub fn wait(&mut self) {
+        self.wait_at_least(self.limit)
+    }
+
+    /// Wait until `frame_time` seconds passed since the previous frame
+    pub fn wait_at_least(&mut self, frame_time: f32) {
         let time_elapsed = self.last_loop.elapsed().as_secs_f32();
 
-        let wait_time = self.limit - time_elapsed;
+        let wait_time = frame_time.max(self.limit) - time_elapsed;
 
         // If we are early on the framerate limit, wait for it
         if wait_time > 0. {
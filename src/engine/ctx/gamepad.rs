@@ -0,0 +1,209 @@
+// standard imports
+use std::collections::HashMap;
+
+// SDL2 imports
+use sdl2::controller::GameController;
+use sdl2::event::Event;
+use sdl2::{GameControllerSubsystem, JoystickSubsystem, Sdl};
+
+// import the engine modules
+use crate::engine::error::MagmaError;
+
+pub use sdl2::controller::{Axis, Button};
+
+/// Connected controller with its hardware identifier
+struct Gamepad {
+    controller: GameController,
+    guid: String,
+}
+
+/// Component of the CtxHandler to map connected controllers to player slots
+pub struct GamepadHandler {
+    /// None when the controller support is disabled, no controller ever connects then
+    subsystems: Option<(GameControllerSubsystem, JoystickSubsystem)>,
+
+    /// Connected controllers by SDL2 instance id
+    gamepads: HashMap<u32, Gamepad>,
+    /// Player slots holding the instance id of the controller in use
+    slots: Vec<Option<u32>>,
+    /// Preferred player slot for specific controller GUIDs
+    assignments: HashMap<String, usize>,
+}
+
+impl GamepadHandler {
+    #[cfg(feature = "gamepads")]
+    pub fn new(ctx: &Sdl, max_players: usize) -> Result<GamepadHandler, MagmaError> {
+        let error = |e| MagmaError::Sdl(format!("Couldn't obtain SDL2 Game Controller Subsystem: {}", e));
+        let controller_subsystem = ctx.game_controller().map_err(error)?;
+        let joystick_subsystem = ctx.joystick().map_err(error)?;
+
+        Ok(GamepadHandler {
+            subsystems: Some((controller_subsystem, joystick_subsystem)),
+            ..Self::disabled(max_players)
+        })
+    }
+
+    #[cfg(not(feature = "gamepads"))]
+    pub fn new(_ctx: &Sdl, _max_players: usize) -> Result<GamepadHandler, MagmaError> {
+        Err(MagmaError::Config(String::from(
+            "Built without the gamepads feature",
+        )))
+    }
+
+    /// Handler without controller support, every player stays disconnected
+    pub fn disabled(max_players: usize) -> GamepadHandler {
+        GamepadHandler {
+            subsystems: None,
+            gamepads: HashMap::new(),
+            slots: vec![None; max_players],
+            assignments: HashMap::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.subsystems.is_some()
+    }
+
+    /// Handle controller hot-plugging (called by the CtxHandler for every event)
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => self.connect(which),
+            Event::ControllerDeviceRemoved { which, .. } => self.disconnect(which),
+            _ => {}
+        }
+    }
+
+    /// Bind a controller GUID to a player slot, moving it there if it's already connected
+    pub fn assign_player(&mut self, player: usize, guid: &str) {
+        if player >= self.slots.len() {
+            eprintln!("Tried to assign controller to non-existing player slot {}", player);
+            return;
+        }
+
+        self.assignments.insert(guid.to_string(), player);
+
+        let connected = self
+            .gamepads
+            .iter()
+            .find(|(_, g)| g.guid == guid)
+            .map(|(id, _)| *id);
+
+        if let Some(id) = connected {
+            let previous_slot = self.slots.iter().position(|s| *s == Some(id));
+            let occupant = self.slots[player].filter(|occupant| *occupant != id);
+
+            if let Some(previous_slot) = previous_slot {
+                self.slots[previous_slot] = None;
+            }
+            self.slots[player] = Some(id);
+
+            // The previous occupant takes the slot left by the assigned controller, or the first free one
+            if let Some(occupant) = occupant {
+                match previous_slot.or_else(|| self.slots.iter().position(|s| s.is_none())) {
+                    Some(slot) => self.slots[slot] = Some(occupant),
+                    None => eprintln!("No free player slot left for the controller of player {}", player),
+                }
+            }
+        }
+    }
+
+    /// Number of available player slots
+    pub fn max_players(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Check if a player slot has a controller connected
+    pub fn is_connected(&self, player: usize) -> bool {
+        self.get_gamepad(player).is_some()
+    }
+
+    /// Get the GUID of the controller used by a player
+    pub fn get_guid(&self, player: usize) -> Option<&str> {
+        self.get_gamepad(player).map(|g| g.guid.as_str())
+    }
+
+    /// Get the name of the controller used by a player
+    pub fn get_name(&self, player: usize) -> Option<String> {
+        self.get_gamepad(player).map(|g| g.controller.name())
+    }
+
+    /// Check if a button is held by a player (false if no controller is in the slot)
+    pub fn button(&self, player: usize, button: Button) -> bool {
+        self.get_gamepad(player)
+            .map(|g| g.controller.button(button))
+            .unwrap_or(false)
+    }
+
+    /// Get a normalized axis value for a player, in range -1.0..=1.0
+    pub fn axis(&self, player: usize, axis: Axis) -> f32 {
+        self.get_gamepad(player)
+            .map(|g| (g.controller.axis(axis) as f32 / i16::MAX as f32).max(-1.0))
+            .unwrap_or(0.0)
+    }
+
+    /// Find the player slot of a raw SDL2 controller instance id
+    pub fn get_player(&self, instance_id: u32) -> Option<usize> {
+        self.slots.iter().position(|s| *s == Some(instance_id))
+    }
+
+    fn get_gamepad(&self, player: usize) -> Option<&Gamepad> {
+        self.slots
+            .get(player)
+            .copied()
+            .flatten()
+            .and_then(|id| self.gamepads.get(&id))
+    }
+
+    fn connect(&mut self, joystick_index: u32) {
+        let (controller_subsystem, joystick_subsystem) = match &self.subsystems {
+            Some(subsystems) => subsystems,
+            None => return,
+        };
+
+        let controller = match controller_subsystem.open(joystick_index) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("Couldn't open game controller {}: {}", joystick_index, e);
+                return;
+            }
+        };
+        let guid = joystick_subsystem
+            .device_guid(joystick_index)
+            .map(|g| g.string())
+            .unwrap_or_default();
+
+        let id = controller.instance_id();
+
+        // Prefer the assigned slot if free, otherwise the first free one
+        let slot = self
+            .assignments
+            .get(&guid)
+            .copied()
+            .filter(|s| self.slots[*s].is_none())
+            .or_else(|| self.slots.iter().position(|s| s.is_none()));
+
+        match slot {
+            Some(slot) => self.slots[slot] = Some(id),
+            None => eprintln!("No free player slot for controller '{}'", controller.name()),
+        }
+
+        self.gamepads.insert(id, Gamepad { controller, guid });
+    }
+
+    fn disconnect(&mut self, instance_id: u32) {
+        self.gamepads.remove(&instance_id);
+
+        let freed = match self.get_player(instance_id) {
+            Some(slot) => slot,
+            None => return,
+        };
+
+        // Give the freed slot to a controller that was left without one
+        let waiting = self
+            .gamepads
+            .keys()
+            .copied()
+            .find(|id| self.get_player(*id).is_none());
+        self.slots[freed] = waiting;
+    }
+}
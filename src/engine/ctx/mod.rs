@@ -1,12 +1,24 @@
+#[cfg(feature = "audio")]
 mod audio;
+#[cfg(not(feature = "audio"))]
+mod no_audio;
 mod video;
+pub mod gamepad;
 
 mod render;
 
-pub use render::{vulkan, draw_objects};
+pub use render::{vulkan, draw_objects, render_thread, pipeline_cache};
 
 pub mod ctxhandler;
 pub mod framerate;
 
-pub use ctxhandler::CtxHandler;
+#[cfg(feature = "audio")]
+pub use audio::{AudioHandler, AudioStream, SoundEffect};
+#[cfg(not(feature = "audio"))]
+pub use no_audio::{AudioHandler, AudioStream, SoundEffect};
+pub use ctxhandler::{CtxHandler, SystemCursor};
 pub use framerate::FPSHandler;
+pub use video::{
+    decode_png_rgba, encode_png_rgba, DisplayInfo, DisplayMode, FullscreenMode, SwapInterval,
+    VideoHandler,
+};
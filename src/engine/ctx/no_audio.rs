@@ -0,0 +1,136 @@
+//! Audio of the builds without the `audio` feature: the API of the SDL2 Mixer handler,
+//! where sounds never load and nothing plays
+
+// std imports
+use std::cell::Cell;
+use std::path::Path;
+
+// SDL2 imports
+use sdl2::Sdl;
+
+// other imports
+use crate::engine::config::AudioConfig;
+use crate::engine::error::MagmaError;
+
+/// Channel a sound effect plays on, never given without audio
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Channel(pub i32);
+
+/// Component of the CtxHandler standing for the audio, always disabled
+pub struct AudioHandler {
+    music_volume: Cell<i32>,
+    sfx_volume: i32,
+}
+
+impl AudioHandler {
+    pub fn new(_ctx: &Sdl, _config: &AudioConfig) -> Result<AudioHandler, MagmaError> {
+        Err(MagmaError::Audio(String::from(
+            "Built without the audio feature",
+        )))
+    }
+
+    /// Handler without audio device: sounds don't load and nothing plays
+    pub fn disabled(config: &AudioConfig) -> AudioHandler {
+        AudioHandler {
+            music_volume: Cell::new(config.music_volume),
+            sfx_volume: config.sfx_volume,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        false
+    }
+
+    //----------------
+    // SOUND EFFECTS
+    //----------------
+    pub fn sfx_from_file(&mut self, _path: &Path) -> SoundEffect {
+        SoundEffect { _private: () }
+    }
+
+    pub fn sfx_from_bytes(&mut self, _bytes: &[u8], _label: &str) -> SoundEffect {
+        SoundEffect { _private: () }
+    }
+
+    pub fn sfx_play(&self, _sfx: &SoundEffect) -> Option<Channel> {
+        None
+    }
+
+    pub fn sfx_get_volume(&self) -> i32 {
+        self.sfx_volume
+    }
+
+    pub fn sfx_set_volume(&mut self, volume: i32) {
+        self.sfx_volume = volume;
+    }
+
+    //--------
+    // MUSIC
+    //--------
+    pub fn music_from_file(&mut self, _path: &Path) -> Result<(), ()> {
+        Err(())
+    }
+
+    pub fn music_play(&self, _loops: i32) -> Result<(), String> {
+        Ok(())
+    }
+
+    pub fn music_pause(&self) {}
+
+    pub fn music_resume(&self) {}
+
+    pub fn music_rewind(&self) {}
+
+    pub fn music_stop(&self) {}
+
+    pub fn pause_all(&self) {}
+
+    pub fn resume_all(&self) {}
+
+    pub fn music_get_volume(&self) -> i32 {
+        self.music_volume.get()
+    }
+
+    pub fn music_set_volume(&self, volume: i32) {
+        self.music_volume.set(volume);
+    }
+
+    //----------
+    // STREAMS
+    //----------
+    pub fn open_stream(&self, _frequency: i32, _channels: u8) -> Result<AudioStream, String> {
+        Err(String::from("Built without the audio feature"))
+    }
+}
+
+/// Stream of samples, never opened without audio
+pub struct AudioStream {
+    _private: (),
+}
+
+impl AudioStream {
+    pub fn queue(&self, _samples: &[i16], _volume: i32) -> Result<(), String> {
+        Ok(())
+    }
+
+    pub fn play(&self) {}
+
+    pub fn pause(&self) {}
+
+    pub fn clear(&self) {}
+
+    pub fn queued_seconds(&self) -> f32 {
+        0.0
+    }
+}
+
+/// Sound effect that never loads
+pub struct SoundEffect {
+    _private: (),
+}
+
+impl SoundEffect {
+    pub fn is_loaded(&self) -> bool {
+        false
+    }
+}
@@ -1,9 +1,6 @@
 // standard imports
-use std::cell::RefCell;
-use std::cell::{Ref, RefMut};
-use std::ops::DerefMut;
-use std::rc::Rc;
-use std::sync::Arc;
+use std::cell::{Cell, RefCell};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
 // vulkan imports
 use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, ImmutableBuffer, TypedBufferAccess};
@@ -13,13 +10,18 @@ use vulkano::descriptor::descriptor_set::{
     PersistentDescriptorSet, PersistentDescriptorSetBuf, PersistentDescriptorSetImg,
     PersistentDescriptorSetSampler,
 };
-use vulkano::image::view::ImageView;
-use vulkano::image::ImmutableImage;
+use vulkano::device::DeviceOwned;
 use vulkano::pipeline::vertex::SingleBufferDefinition;
 use vulkano::pipeline::GraphicsPipeline;
 
 // vulkan implementation imports
-use super::vulkan::{GlobalUniformData, GraphicsHandler, Vertex, VertexArray, VertexBuffer};
+use super::vulkan::{
+    BatchVertex, DeviceRecovery, FrameError, GlobalUniformData, GraphicsHandler, Texture, Vertex,
+    VertexArray, VertexBuffer,
+};
+use crate::engine::color::Color;
+use crate::engine::geometry::{Aabb, Rect};
+use crate::engine::leaks::{ResourceKind, ResourceTracker};
 
 // other imports
 use bitflags::bitflags;
@@ -29,28 +31,191 @@ bitflags! {
     pub struct DrawFlags: u8 {
         const USED = 0b00000001;
         const VISIBLE = 0b00000010;
+        /// Skip the object when an opaque object of a higher z index covers it (see `Draw::occluder_bounds`),
+        /// worth it for the objects expensive to draw
+        const OCCLUSION_TEST = 0b00000100;
     }
 }
 
-pub trait Draw {
+/// Object drawn by the `GraphicsHandler`, `Send` so the handler can live on a render thread
+pub trait Draw: Send {
     fn draw(
         &self,
         gl_handler: &mut GraphicsHandler,
         command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
-    );
+    ) -> Result<(), FrameError>;
 
     fn get_z_index(&self) -> u8;
 
     fn flush_data(&self);
 
+    /// Write the data flushed last for the frame of a swapchain image and, while recording it, copy
+    /// it to the uniform buffers read by `draw` (see `UniformRing`). Called before the render pass,
+    /// only for the objects drawn on their own
+    fn upload_frame_data(
+        &self,
+        _image_num: usize,
+        _builder: Option<&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>>,
+    ) -> Result<(), FrameError> {
+        Ok(())
+    }
+
     fn write_flags(&mut self) -> &mut DrawFlags;
     fn read_flags(&self) -> DrawFlags;
 
     fn set_dead(&mut self);
     fn set_visible(&mut self, visible: bool);
+
+    /// Test the object against the occluders before drawing it, only while `cull_objects` is set
+    fn set_occlusion_test(&mut self, enabled: bool) {
+        self.write_flags().set(DrawFlags::OCCLUSION_TEST, enabled);
+    }
+
+    /// Copy of the user editable parameters, used by the engine snapshots
+    fn get_state(&self) -> DrawState;
+    fn set_state(&mut self, state: &DrawState);
+
+    /// Create the GPU resources again on the device replacing a lost one, keeping every parameter
+    fn recreate(&mut self, gl_handler: &GraphicsHandler, recovery: &DeviceRecovery) -> Result<(), String>;
+
+    /// World space quad to merge with others sharing the same texture, None if the object can't be batched
+    fn batch_quad(&self) -> Option<BatchQuad> {
+        None
+    }
+
+    /// World space quads to merge in the batches, false if the object can't be batched.
+    /// Objects made of many quads can skip the ones outside of `view`, the area seen by the camera
+    fn batch_quads(&self, _view: &Aabb, quads: &mut Vec<BatchQuad>) -> bool {
+        match self.batch_quad() {
+            Some(quad) => {
+                quads.push(quad);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// World space area covered by the object, objects outside of the view aren't recorded.
+    /// None for objects never culled (e.g. the ones already skipping what isn't seen)
+    fn cull_bounds(&self) -> Option<Aabb> {
+        None
+    }
+
+    /// World space area entirely covered by opaque pixels, hiding the objects of lower z indices
+    /// tested for occlusion. None for objects with any transparency
+    fn occluder_bounds(&self) -> Option<Aabb> {
+        None
+    }
+
+    /// World space triangles to merge in the batches, for objects that aren't made of quads.
+    /// Only used when `batch_quads` returns false, false if the object can't be batched
+    fn batch_mesh(&self, _view: &Aabb, _mesh: &mut BatchMesh) -> bool {
+        false
+    }
+
+    /// Key grouping the objects which can be merged together
+    fn batch_key(&self) -> usize {
+        0
+    }
+
+    /// True for objects built from the view (e.g. parallax layers), recorded again whenever the camera moves
+    fn follows_view(&self) -> bool {
+        false
+    }
 }
 
-pub type DrawObject<O> = Rc<RefCell<O>>;
+/// User editable parameters of a DrawObject
+#[derive(Clone, Copy, Debug)]
+pub struct DrawState {
+    pub color: Vector4<f32>,
+    pub global_position: Vector2<f32>,
+    pub scale: Vector2<f32>,
+    pub flags: DrawFlags,
+}
+
+/// Triangles ready to be merged in a batched draw
+#[derive(Default)]
+pub struct BatchMesh {
+    /// Texture to sample, None for plain colored meshes
+    pub texture: Option<Texture>,
+    pub vertices: Vec<BatchVertex>,
+    /// Three indices in `vertices` per triangle
+    pub indices: Vec<u32>,
+}
+
+impl BatchMesh {
+    /// Append vertices and their triangles, the indices counting from the first appended vertex
+    pub fn extend(
+        &mut self,
+        vertices: impl IntoIterator<Item = BatchVertex>,
+        indices: impl IntoIterator<Item = u32>,
+    ) {
+        let base = self.vertices.len() as u32;
+
+        self.vertices.extend(vertices);
+        self.indices.extend(indices.into_iter().map(|index| base + index));
+    }
+
+    /// Empty the mesh, keeping its memory for the next one
+    pub fn clear(&mut self) {
+        self.texture = None;
+        self.vertices.clear();
+        self.indices.clear();
+    }
+}
+
+/// Quad ready to be merged in a batched draw
+pub struct BatchQuad {
+    /// Texture to sample, None for plain colored quads
+    pub texture: Option<Texture>,
+    pub vertices: [BatchVertex; 4],
+}
+
+impl BatchQuad {
+    /// Build a quad from its center and half extents, the texture covering all of it
+    pub fn new(
+        texture: Option<Texture>,
+        center: Vector2<f32>,
+        half_extents: Vector2<f32>,
+        color: Vector4<f32>,
+    ) -> Self {
+        // Same corners (and order) as the quad vertex arrays
+        let corners = [[-1.0, -1.0], [-1.0, 1.0], [1.0, 1.0], [1.0, -1.0]];
+        let color = [color.x, color.y, color.z, color.w];
+
+        let mut vertices = [BatchVertex::default(); 4];
+        for (vertex, corner) in vertices.iter_mut().zip(corners.iter()) {
+            *vertex = BatchVertex {
+                world_pos: [
+                    center.x + corner[0] * half_extents.x,
+                    center.y + corner[1] * half_extents.y,
+                ],
+                tex_coords: [corner[0].max(0.0), corner[1].max(0.0)],
+                color,
+            };
+        }
+
+        Self { texture, vertices }
+    }
+
+    /// Sample only a region of the texture, in texture coordinates (0.0 to 1.0)
+    pub fn with_tex_region(mut self, min: [f32; 2], max: [f32; 2]) -> Self {
+        for vertex in self.vertices.iter_mut() {
+            vertex.tex_coords = [
+                min[0] + vertex.tex_coords[0] * (max[0] - min[0]),
+                min[1] + vertex.tex_coords[1] * (max[1] - min[1]),
+            ];
+        }
+        self
+    }
+}
+
+pub type DrawObject<O> = Arc<Mutex<O>>;
+
+/// Lock a DrawObject, still usable if a panic happened while it was locked
+pub(crate) fn lock<O: ?Sized>(object: &Mutex<O>) -> MutexGuard<'_, O> {
+    object.lock().unwrap_or_else(PoisonError::into_inner)
+}
 
 pub type SpriteObject = GraphicObject<Sprite>;
 
@@ -59,7 +224,7 @@ type SpriteImmutableDescriptorSet = PersistentDescriptorSet<(
         (
             (
                 (),
-                PersistentDescriptorSetImg<Arc<ImageView<Arc<ImmutableImage>>>>,
+                PersistentDescriptorSetImg<Texture>,
             ),
             PersistentDescriptorSetSampler,
         ),
@@ -78,31 +243,37 @@ impl<O: Draw + ?Sized> GraphicObject<O> {
         Self { draw_object }
     }
 
-    pub fn get_ref(&self) -> Ref<'_, O> {
-        self.draw_object.borrow()
+    /// Locks the object, like `get_mut`: don't keep both at the same time
+    pub fn get_ref(&self) -> MutexGuard<'_, O> {
+        lock(&self.draw_object)
     }
 
-    pub fn get_mut(&self) -> RefMut<'_, O> {
-        self.draw_object.borrow_mut()
+    pub fn get_mut(&self) -> MutexGuard<'_, O> {
+        lock(&self.draw_object)
     }
 }
 
 impl<O: Draw + ?Sized> Drop for GraphicObject<O> {
     fn drop(&mut self) {
-        self.draw_object.borrow_mut().set_dead();
+        lock(&self.draw_object).set_dead();
     }
 }
 
-fn draw<DescSet>(
+/// Record an indexed draw of a pipeline, counted in the render counters
+pub(crate) fn draw<DescSet>(
     gl_handler: &mut GraphicsHandler,
     pipeline: Arc<GraphicsPipeline<SingleBufferDefinition<Vertex>>>,
     cmnd_buf: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
     vertices: Arc<ImmutableBuffer<[Vertex]>>,
     indices: Arc<dyn TypedBufferAccess<Content = [u16]> + Send + Sync>,
     sets: DescSet,
-) where
+) -> Result<(), FrameError>
+where
     DescSet: DescriptorSetsCollection,
 {
+    gl_handler.counters.draw_calls += 1;
+    gl_handler.counters.vertices += vertices.len() as u32;
+
     cmnd_buf
         .draw_indexed(
             pipeline,
@@ -113,7 +284,81 @@ fn draw<DescSet>(
             (),
             vec![],
         )
-        .expect("Couldn't add Draw command to Vulkan Render Pass");
+        .map_err(|e| FrameError::Draw(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Uniform data of an object drawn on its own, with a slot per swapchain image like the global
+/// uniform ring: a frame copies the slot of its image into the uniform buffer before drawing,
+/// and the CPU only writes the slot of an image once its last frame is done
+#[derive(Clone)]
+pub(crate) struct UniformRing<T: Copy + Send + Sync + 'static> {
+    /// Read by the descriptor set of the object
+    buffer: Arc<CpuAccessibleBuffer<T>>,
+    /// Data of the last flush, written to the slots of the next frames
+    data: Cell<T>,
+    slots: RefCell<Vec<Arc<CpuAccessibleBuffer<T>>>>,
+}
+
+impl<T: Copy + Send + Sync + 'static> UniformRing<T> {
+    pub(crate) fn new(gl_handler: &GraphicsHandler, data: T) -> Self {
+        let buffer = CpuAccessibleBuffer::from_data(
+            gl_handler.get_device(),
+            BufferUsage::uniform_buffer_transfer_destination(),
+            true,
+            data,
+        )
+        .unwrap();
+
+        Self {
+            buffer,
+            data: Cell::new(data),
+            slots: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Uniform buffer to bind in the descriptor set
+    pub(crate) fn buffer(&self) -> Arc<CpuAccessibleBuffer<T>> {
+        self.buffer.clone()
+    }
+
+    /// Keep the data for the next frames recorded or resubmitted
+    pub(crate) fn set(&self, data: T) {
+        self.data.set(data);
+    }
+
+    /// Write the data in the slot of the image, growing the ring with the number of images,
+    /// and record its copy into the uniform buffer when a builder is given
+    pub(crate) fn upload(
+        &self,
+        image_num: usize,
+        builder: Option<&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>>,
+    ) -> Result<(), FrameError> {
+        let mut slots = self.slots.borrow_mut();
+        while slots.len() <= image_num {
+            let slot = CpuAccessibleBuffer::from_data(
+                self.buffer.device().clone(),
+                BufferUsage::transfer_source(),
+                false,
+                self.data.get(),
+            )
+            .map_err(|e| FrameError::BufferWrite(e.to_string()))?;
+            slots.push(slot);
+        }
+
+        let slot = slots[image_num].clone();
+        *slot
+            .write()
+            .map_err(|e| FrameError::BufferWrite(e.to_string()))? = self.data.get();
+
+        if let Some(builder) = builder {
+            builder
+                .copy_buffer(slot, self.buffer.clone())
+                .map_err(|e| FrameError::CommandBufferBuild(e.to_string()))?;
+        }
+        Ok(())
+    }
 }
 
 /// Struct to hold sprite specific data that both CPU and GPU must access
@@ -123,14 +368,19 @@ struct SpriteData {
     global_position: Vector4<f32>,
     scale: Vector4<f32>,
     image_dimensions: Vector4<u32>,
+    tex_region: Vector4<f32>,
 }
 
+/// World units covered by a texture pixel at scale 1, sprites are drawn twice as large as their image
+pub(crate) const WORLD_UNITS_PER_PIXEL: f32 = 2.0;
+
 /// Struct to handle sprite entities on screen capable of having transforms
 #[derive(Clone)]
 pub struct Sprite {
     vertex_buffer: VertexBuffer,
     descriptor_set: Arc<SpriteImmutableDescriptorSet>,
-    cpu_buffer: Arc<CpuAccessibleBuffer<SpriteData>>,
+    uniforms: UniformRing<SpriteData>,
+    texture: Texture,
 
     // flags and params
     z_index: u8,
@@ -139,11 +389,32 @@ pub struct Sprite {
     pub color: Vector4<f32>,
     pub global_position: Vector2<f32>,
     pub scale: Vector2<f32>,
+    /// Size of the region drawn, in pixels
     image_dimensions: Vector2<u32>,
+    /// Size of the whole texture, in pixels
+    texture_dimensions: Vector2<u32>,
+    /// Min and max texture coordinates of the region drawn
+    tex_region: Vector4<f32>,
+
+    _tracker: ResourceTracker,
 }
 
 impl Sprite {
-    pub fn new(texture_path: &str, gl_handler: &GraphicsHandler, z_index: u8) -> Self {
+    pub fn new(texture_path: &str, gl_handler: &GraphicsHandler, z_index: u8) -> Result<Self, String> {
+        let (texture, image_dimensions) = gl_handler.load_texture(texture_path)?;
+
+        Self::from_texture(texture, image_dimensions, texture_path, gl_handler, z_index)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Create a Sprite using an already loaded texture, `label` names it in the leak reports
+    pub fn from_texture(
+        texture: Texture,
+        image_dimensions: Vector2<u32>,
+        label: &str,
+        gl_handler: &GraphicsHandler,
+        z_index: u8,
+    ) -> Result<Self, FrameError> {
         let vao = VertexArray::from(vec![
             Vertex {
                 vert_pos: [-1.0, -1.0],
@@ -161,55 +432,116 @@ impl Sprite {
         let indices = gl_handler.new_index_buffer(&[0, 1, 2, 2, 3, 0]);
         let vertex_buffer = gl_handler.new_vertex_buffer(vao, indices);
 
-        let persistent_set = gl_handler.create_empty_descriptor_set_builder("Sprite", 0);
+        let persistent_set = gl_handler.create_empty_descriptor_set_builder("Sprite", 0)?;
         let sampler = gl_handler.create_texture_sampler();
 
         let color = Vector4::new(1.0, 1.0, 1.0, 1.0);
         let global_position = Vector2::new(0.0, 0.0);
         let scale = Vector2::new(1.0, 1.0);
+        let tex_region = Vector4::new(0.0, 0.0, 1.0, 1.0);
 
-        let (persistent_set, image_dimensions) =
-            gl_handler.create_and_bind_texture(texture_path, persistent_set, sampler);
+        let persistent_set = gl_handler.bind_texture(texture.clone(), persistent_set, sampler)?;
 
         let sprite_data = SpriteData {
             global_position: global_position.extend(0.0).extend(0.0),
             color,
             scale: scale.extend(0.0).extend(0.0),
             image_dimensions: image_dimensions.extend(0).extend(0),
+            tex_region,
         };
 
-        let cpu_buffer = CpuAccessibleBuffer::from_data(
-            gl_handler.get_device(),
-            BufferUsage::uniform_buffer(),
-            true,
-            sprite_data,
-        )
-        .unwrap();
+        let uniforms = UniformRing::new(gl_handler, sprite_data);
 
         let persistent_set = persistent_set
-            .add_buffer(cpu_buffer.clone())
-            .unwrap()
-            .add_buffer(gl_handler.get_global_uniform_buffer())
-            .unwrap()
+            .add_buffer(uniforms.buffer())
+            .and_then(|set| set.add_buffer(gl_handler.get_global_uniform_buffer()))
+            .map_err(|e| FrameError::DescriptorSet(e.to_string()))?
             .build()
-            .expect("Couldn't build Persistent Descriptor Set for Sprite object");
+            .map_err(|e| FrameError::DescriptorSet(e.to_string()))?;
 
         let descriptor_set = Arc::new(persistent_set);
 
         let mut draw_flags = DrawFlags::empty();
         draw_flags.insert(DrawFlags::USED | DrawFlags::VISIBLE);
 
-        Self {
+        Ok(Self {
             vertex_buffer,
             descriptor_set,
-            cpu_buffer,
+            uniforms,
+            texture,
             z_index,
             draw_flags,
             color,
             global_position,
             scale,
             image_dimensions,
-        }
+            texture_dimensions: image_dimensions,
+            tex_region,
+
+            _tracker: ResourceTracker::new(
+                ResourceKind::DrawObject,
+                &format!("Sprite '{}'", label),
+            ),
+        })
+    }
+
+    /// Tint multiplied with the texture colors
+    pub fn set_color(&mut self, color: impl Into<Color>) {
+        self.color = color.into().into();
+    }
+
+    pub fn get_color(&self) -> Color {
+        self.color.into()
+    }
+
+    /// Draw only a region of the texture (e.g. a frame of an atlas), in pixels from its top-left corner
+    pub fn set_region(&mut self, region: Rect) {
+        let (width, height) = (
+            self.texture_dimensions.x.max(1) as f32,
+            self.texture_dimensions.y.max(1) as f32,
+        );
+
+        self.image_dimensions = Vector2::new(
+            region.width.round().max(0.0) as u32,
+            region.height.round().max(0.0) as u32,
+        );
+        self.tex_region = Vector4::new(
+            region.x / width,
+            region.y / height,
+            (region.x + region.width) / width,
+            (region.y + region.height) / height,
+        );
+    }
+
+    /// Draw the whole texture again
+    pub fn clear_region(&mut self) {
+        let size = self.texture_dimensions;
+        self.set_region(Rect::new(0.0, 0.0, size.x as f32, size.y as f32));
+    }
+
+    /// Region of the texture drawn, in pixels
+    pub fn get_region(&self) -> Rect {
+        let (width, height) = (
+            self.texture_dimensions.x as f32,
+            self.texture_dimensions.y as f32,
+        );
+
+        Rect::new(
+            self.tex_region.x * width,
+            self.tex_region.y * height,
+            self.image_dimensions.x as f32,
+            self.image_dimensions.y as f32,
+        )
+    }
+
+    /// Area covered on screen, for hit testing and culling
+    pub fn bounds(&self) -> Aabb {
+        let half_extents = Vector2::new(
+            self.image_dimensions.x as f32 * self.scale.x,
+            self.image_dimensions.y as f32 * self.scale.y,
+        );
+
+        Aabb::from_center(self.global_position, half_extents)
     }
 }
 
@@ -218,7 +550,7 @@ impl Draw for Sprite {
         &self,
         gl_handler: &mut GraphicsHandler,
         command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
-    ) {
+    ) -> Result<(), FrameError> {
         draw(
             gl_handler,
             gl_handler.get_pipeline("Sprite"),
@@ -234,12 +566,21 @@ impl Draw for Sprite {
     }
 
     fn flush_data(&self) {
-        let mut write_lock = self.cpu_buffer.write().expect("Couldn't write the buffer");
-        let sprite_data = write_lock.deref_mut();
+        self.uniforms.set(SpriteData {
+            color: self.color,
+            global_position: self.global_position.extend(0.0).extend(0.0),
+            scale: self.scale.extend(0.0).extend(0.0),
+            image_dimensions: self.image_dimensions.extend(0).extend(0),
+            tex_region: self.tex_region,
+        });
+    }
 
-        sprite_data.color = self.color;
-        sprite_data.global_position = self.global_position.extend(0.0).extend(0.0);
-        sprite_data.scale = self.scale.extend(0.0).extend(0.0);
+    fn upload_frame_data(
+        &self,
+        image_num: usize,
+        builder: Option<&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>>,
+    ) -> Result<(), FrameError> {
+        self.uniforms.upload(image_num, builder)
     }
 
     fn write_flags(&mut self) -> &mut DrawFlags {
@@ -257,6 +598,64 @@ impl Draw for Sprite {
     fn set_visible(&mut self, visible: bool) {
         self.draw_flags.set(DrawFlags::VISIBLE, visible);
     }
+
+    fn get_state(&self) -> DrawState {
+        DrawState {
+            color: self.color,
+            global_position: self.global_position,
+            scale: self.scale,
+            flags: self.draw_flags,
+        }
+    }
+
+    fn set_state(&mut self, state: &DrawState) {
+        self.color = state.color;
+        self.global_position = state.global_position;
+        self.scale = state.scale;
+        self.draw_flags = state.flags;
+    }
+
+    fn recreate(&mut self, gl_handler: &GraphicsHandler, recovery: &DeviceRecovery) -> Result<(), String> {
+        let texture = recovery.texture(&self.texture)?;
+        let sprite = Sprite::from_texture(texture, self.texture_dimensions, "recreated", gl_handler, self.z_index)
+            .map_err(|e| e.to_string())?;
+
+        self.vertex_buffer = sprite.vertex_buffer;
+        self.descriptor_set = sprite.descriptor_set;
+        self.uniforms = sprite.uniforms;
+        self.texture = sprite.texture;
+        self.flush_data();
+
+        Ok(())
+    }
+
+    fn batch_quad(&self) -> Option<BatchQuad> {
+        let half_extents = Vector2::new(
+            self.image_dimensions.x as f32 * self.scale.x,
+            self.image_dimensions.y as f32 * self.scale.y,
+        );
+
+        Some(
+            BatchQuad::new(
+                Some(self.texture.clone()),
+                self.global_position,
+                half_extents,
+                self.color,
+            )
+            .with_tex_region(
+                [self.tex_region.x, self.tex_region.y],
+                [self.tex_region.z, self.tex_region.w],
+            ),
+        )
+    }
+
+    fn batch_key(&self) -> usize {
+        Arc::as_ptr(&self.texture) as usize
+    }
+
+    fn cull_bounds(&self) -> Option<Aabb> {
+        Some(self.bounds())
+    }
 }
 
 type PrimitiveImmutableDescriptorSet = PersistentDescriptorSet<(
@@ -281,7 +680,12 @@ struct PrimitiveData {
 pub struct Primitive {
     vertex_buffer: VertexBuffer,
     descriptor_set: Arc<PrimitiveImmutableDescriptorSet>,
-    cpu_buffer: Arc<CpuAccessibleBuffer<PrimitiveData>>,
+    uniforms: UniformRing<PrimitiveData>,
+    /// Shape kept to create the buffers again if the device is lost
+    vertex_array: VertexArray,
+    index_array: Vec<u16>,
+    /// Box holding the shape before scaling and moving it, None without vertices
+    local_bounds: Option<Aabb>,
 
     // general flags and params
     z_index: u8,
@@ -290,17 +694,21 @@ pub struct Primitive {
     pub color: Vector4<f32>,
     pub global_position: Vector2<f32>,
     pub scale: Vector2<f32>,
+
+    /// Rectangles can be merged in batched draws
+    is_rectangle: bool,
+    _tracker: ResourceTracker,
 }
 
 impl Primitive {
     /// Complex function to create custom shapes
     /// Should be avoided in favour of premade shapes
-    pub fn new(vertex_array: VertexArray, index_array: &[u16], scale: Vector2<f32>, color: Vector4<f32>, global_position: Vector2<f32>, gl_handler: &GraphicsHandler, z_index: u8) -> Self {
+    pub fn new(vertex_array: VertexArray, index_array: &[u16], scale: Vector2<f32>, color: Vector4<f32>, global_position: Vector2<f32>, gl_handler: &GraphicsHandler, z_index: u8) -> Result<Self, FrameError> {
         let indices = gl_handler.new_index_buffer(index_array);
 
-        let vertex_buffer = gl_handler.new_vertex_buffer(vertex_array, indices);
+        let vertex_buffer = gl_handler.new_vertex_buffer(vertex_array.clone(), indices);
 
-        let persistent_set = gl_handler.create_empty_descriptor_set_builder("Primitive", 0);
+        let persistent_set = gl_handler.create_empty_descriptor_set_builder("Primitive", 0)?;
 
 
         let primitive_data = PrimitiveData {
@@ -309,42 +717,48 @@ impl Primitive {
             scale: scale.extend(0.0).extend(0.0),
         };
 
-        let cpu_buffer = CpuAccessibleBuffer::from_data(
-            gl_handler.get_device(),
-            BufferUsage::uniform_buffer(),
-            true,
-            primitive_data,
-        )
-        .unwrap();
+        let uniforms = UniformRing::new(gl_handler, primitive_data);
 
         let persistent_set = persistent_set
-            .add_buffer(cpu_buffer.clone())
-            .unwrap()
-            .add_buffer(gl_handler.get_global_uniform_buffer())
-            .unwrap()
+            .add_buffer(uniforms.buffer())
+            .and_then(|set| set.add_buffer(gl_handler.get_global_uniform_buffer()))
+            .map_err(|e| FrameError::DescriptorSet(e.to_string()))?
             .build()
-            .expect("Couldn't build Persistent Descriptor Set for Sprite object");
+            .map_err(|e| FrameError::DescriptorSet(e.to_string()))?;
 
         let descriptor_set = Arc::new(persistent_set);
 
         let mut draw_flags = DrawFlags::empty();
         draw_flags.insert(DrawFlags::USED | DrawFlags::VISIBLE);
 
-        Self {
+        let points: Vec<Vector2<f32>> = vertex_array
+            .vertices()
+            .iter()
+            .map(|vertex| Vector2::from(vertex.vert_pos))
+            .collect();
+        let local_bounds = Aabb::from_points(&points);
+
+        Ok(Self {
             vertex_buffer,
             descriptor_set,
-            cpu_buffer,
+            uniforms,
+            vertex_array,
+            index_array: index_array.to_vec(),
+            local_bounds,
             z_index,
             draw_flags,
             color,
             global_position,
             scale,
-        }
+
+            is_rectangle: false,
+            _tracker: ResourceTracker::new(ResourceKind::DrawObject, "Primitive"),
+        })
     }
 
     /// Create a new Primitive of rectangular shape
     /// Here the `scale` parameter is also the dimensions of a pre-built rectangle (a Vector2(1.0, 1.0) would be a pixel when zoom isn't applied)
-    pub fn rectangle(scale: Vector2<f32>, color: Vector4<f32>, global_position: Vector2<f32>, gl_handler: &GraphicsHandler, z_index: u8) -> Self {
+    pub fn rectangle(scale: Vector2<f32>, color: Vector4<f32>, global_position: Vector2<f32>, gl_handler: &GraphicsHandler, z_index: u8) -> Result<Self, FrameError> {
         let vao = VertexArray::from(vec![
             Vertex {
                 vert_pos: [-1.0, -1.0],
@@ -360,7 +774,27 @@ impl Primitive {
             },
         ]);
         
-        Self::new(vao, &[0, 1, 2, 2, 3, 0], scale, color, global_position, gl_handler, z_index)
+        let mut rectangle = Self::new(vao, &[0, 1, 2, 2, 3, 0], scale, color, global_position, gl_handler, z_index)?;
+        rectangle.is_rectangle = true;
+
+        Ok(rectangle)
+    }
+
+    pub fn set_color(&mut self, color: impl Into<Color>) {
+        self.color = color.into().into();
+    }
+
+    pub fn get_color(&self) -> Color {
+        self.color.into()
+    }
+
+    /// Area covered on screen by a rectangle, None for other shapes
+    pub fn bounds(&self) -> Option<Aabb> {
+        if self.is_rectangle {
+            Some(Aabb::from_center(self.global_position, self.scale))
+        } else {
+            None
+        }
     }
 }
 
@@ -369,7 +803,7 @@ impl Draw for Primitive {
         &self,
         gl_handler: &mut GraphicsHandler,
         command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
-    ) {
+    ) -> Result<(), FrameError> {
         draw(
             gl_handler,
             gl_handler.get_pipeline("Primitive"),
@@ -385,14 +819,500 @@ impl Draw for Primitive {
     }
 
     fn flush_data(&self) {
-        let mut write_lock = self.cpu_buffer.write().expect("Couldn't write the buffer");
-        let sprite_data = write_lock.deref_mut();
+        self.uniforms.set(PrimitiveData {
+            color: self.color,
+            global_position: self.global_position.extend(0.0).extend(0.0),
+            scale: self.scale.extend(0.0).extend(0.0),
+        });
+    }
+
+    fn upload_frame_data(
+        &self,
+        image_num: usize,
+        builder: Option<&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>>,
+    ) -> Result<(), FrameError> {
+        self.uniforms.upload(image_num, builder)
+    }
+
+    fn write_flags(&mut self) -> &mut DrawFlags {
+        &mut self.draw_flags
+    }
+
+    fn read_flags(&self) -> DrawFlags {
+        self.draw_flags
+    }
+
+    fn set_dead(&mut self) {
+        self.draw_flags.remove(DrawFlags::USED);
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        self.draw_flags.set(DrawFlags::VISIBLE, visible);
+    }
+
+    fn get_state(&self) -> DrawState {
+        DrawState {
+            color: self.color,
+            global_position: self.global_position,
+            scale: self.scale,
+            flags: self.draw_flags,
+        }
+    }
+
+    fn set_state(&mut self, state: &DrawState) {
+        self.color = state.color;
+        self.global_position = state.global_position;
+        self.scale = state.scale;
+        self.draw_flags = state.flags;
+    }
+
+    fn recreate(&mut self, gl_handler: &GraphicsHandler, _recovery: &DeviceRecovery) -> Result<(), String> {
+        let primitive = Primitive::new(self.vertex_array.clone(), &self.index_array, self.scale, self.color, self.global_position, gl_handler, self.z_index)
+            .map_err(|e| e.to_string())?;
 
-        sprite_data.color = self.color;
-        sprite_data.global_position = self.global_position.extend(0.0).extend(0.0);
-        sprite_data.scale = self.scale.extend(0.0).extend(0.0);
+        self.vertex_buffer = primitive.vertex_buffer;
+        self.descriptor_set = primitive.descriptor_set;
+        self.uniforms = primitive.uniforms;
+
+        Ok(())
+    }
+
+    fn batch_quad(&self) -> Option<BatchQuad> {
+        if !self.is_rectangle {
+            return None;
+        }
+
+        Some(BatchQuad::new(None, self.global_position, self.scale, self.color))
+    }
+
+    fn cull_bounds(&self) -> Option<Aabb> {
+        let local = self.local_bounds?;
+        let to_world = |point: Vector2<f32>| {
+            self.global_position + Vector2::new(point.x * self.scale.x, point.y * self.scale.y)
+        };
+
+        Some(Aabb::new(to_world(local.min), to_world(local.max)))
+    }
+
+    fn occluder_bounds(&self) -> Option<Aabb> {
+        if !self.is_rectangle || self.color.w < 1.0 {
+            return None;
+        }
+
+        self.cull_bounds()
+    }
+
+    fn batch_mesh(&self, _view: &Aabb, mesh: &mut BatchMesh) -> bool {
+        // Same transform as the primitive vertex shader
+        let color = [self.color.x, self.color.y, self.color.z, self.color.w];
+        let vertices = self.vertex_array.vertices().iter().map(|vertex| BatchVertex {
+            world_pos: [
+                self.global_position.x + vertex.vert_pos[0] * self.scale.x,
+                self.global_position.y + vertex.vert_pos[1] * self.scale.y,
+            ],
+            tex_coords: [0.0, 0.0],
+            color,
+        });
+
+        mesh.extend(vertices, self.index_array.iter().map(|&index| index as u32));
+        true
+    }
+}
+
+pub type NineSliceObject = GraphicObject<NineSlice>;
+pub type ParallaxObject = GraphicObject<ParallaxLayer>;
+
+/// Widths of the borders of a nine-slice image, in pixels
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SliceBorders {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl SliceBorders {
+    pub fn new(left: f32, top: f32, right: f32, bottom: f32) -> Self {
+        Self {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    /// Same width on every side
+    pub fn uniform(width: f32) -> Self {
+        Self::new(width, width, width, width)
+    }
+}
+
+/// Image drawn at any size keeping its borders intact: corners aren't scaled,
+/// edges stretch along their side and the center fills the rest (UI panels, buttons...)
+pub struct NineSlice {
+    texture: Texture,
+    texture_dimensions: Vector2<u32>,
+    /// Part of the texture sliced, in pixels
+    region: Rect,
+    borders: SliceBorders,
+
+    z_index: u8,
+    draw_flags: DrawFlags,
+
+    pub color: Vector4<f32>,
+    pub global_position: Vector2<f32>,
+    /// Size of the panel in pixels, before the scale
+    pub size: Vector2<f32>,
+    pub scale: Vector2<f32>,
+
+    _tracker: ResourceTracker,
+}
+
+impl NineSlice {
+    /// Nine-slice of a whole texture, `label` names it in the leak reports
+    pub fn new(
+        texture: Texture,
+        texture_dimensions: Vector2<u32>,
+        borders: SliceBorders,
+        label: &str,
+        z_index: u8,
+    ) -> Self {
+        let size = Vector2::new(texture_dimensions.x as f32, texture_dimensions.y as f32);
+
+        Self {
+            texture,
+            texture_dimensions,
+            region: Rect::new(0.0, 0.0, size.x, size.y),
+            borders,
+            z_index,
+            draw_flags: DrawFlags::USED | DrawFlags::VISIBLE,
+            color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            global_position: Vector2::new(0.0, 0.0),
+            size,
+            scale: Vector2::new(1.0, 1.0),
+            _tracker: ResourceTracker::new(
+                ResourceKind::DrawObject,
+                &format!("Nine-slice '{}'", label),
+            ),
+        }
+    }
+
+    /// Slice only a region of the texture (e.g. from an atlas), in pixels
+    pub fn set_region(&mut self, region: Rect) {
+        self.region = region;
+    }
+
+    pub fn set_borders(&mut self, borders: SliceBorders) {
+        self.borders = borders;
+    }
+
+    pub fn get_borders(&self) -> SliceBorders {
+        self.borders
+    }
+
+    pub fn set_color(&mut self, color: impl Into<Color>) {
+        self.color = color.into().into();
+    }
+
+    pub fn get_color(&self) -> Color {
+        self.color.into()
+    }
+
+    /// Area covered on screen, for hit testing and culling
+    pub fn bounds(&self) -> Aabb {
+        let half_extents = Vector2::new(self.size.x * self.scale.x, self.size.y * self.scale.y);
+
+        Aabb::from_center(self.global_position, half_extents)
+    }
+
+    /// Append the nine pieces, as quads in world space
+    fn push_quads(&self, quads: &mut Vec<BatchQuad>) {
+        // Borders shrink evenly when the panel is smaller than them
+        let shrink = |start: f32, end: f32, size: f32| {
+            let total = start + end;
+            if total > size && total > 0.0 {
+                (start * size / total, end * size / total)
+            } else {
+                (start, end)
+            }
+        };
+        let (left, right) = shrink(self.borders.left, self.borders.right, self.size.x);
+        let (top, bottom) = shrink(self.borders.top, self.borders.bottom, self.size.y);
+
+        // Edges of the columns and rows, in panel pixels and texture pixels
+        let panel_x = [0.0, left, self.size.x - right, self.size.x];
+        let panel_y = [0.0, top, self.size.y - bottom, self.size.y];
+        let region = self.region;
+        let tex_x = [
+            region.x,
+            region.x + self.borders.left,
+            region.x + region.width - self.borders.right,
+            region.x + region.width,
+        ];
+        let tex_y = [
+            region.y,
+            region.y + self.borders.top,
+            region.y + region.height - self.borders.bottom,
+            region.y + region.height,
+        ];
+
+        let pixel = Vector2::new(self.scale.x, self.scale.y) * WORLD_UNITS_PER_PIXEL;
+        let top_left = self.global_position
+            - Vector2::new(self.size.x * self.scale.x, self.size.y * self.scale.y);
+        let (texture_width, texture_height) = (
+            self.texture_dimensions.x.max(1) as f32,
+            self.texture_dimensions.y.max(1) as f32,
+        );
+
+        for row in 0..3 {
+            for column in 0..3 {
+                let (x0, x1) = (panel_x[column], panel_x[column + 1]);
+                let (y0, y1) = (panel_y[row], panel_y[row + 1]);
+                if x1 <= x0 || y1 <= y0 {
+                    continue;
+                }
+
+                let center =
+                    top_left + Vector2::new((x0 + x1) / 2.0 * pixel.x, (y0 + y1) / 2.0 * pixel.y);
+                let half_extents =
+                    Vector2::new((x1 - x0) / 2.0 * pixel.x, (y1 - y0) / 2.0 * pixel.y);
+
+                let min = [tex_x[column] / texture_width, tex_y[row] / texture_height];
+                let max = [
+                    tex_x[column + 1] / texture_width,
+                    tex_y[row + 1] / texture_height,
+                ];
+                let quad =
+                    BatchQuad::new(Some(self.texture.clone()), center, half_extents, self.color);
+                quads.push(quad.with_tex_region(min, max));
+            }
+        }
+    }
+}
+
+impl Draw for NineSlice {
+    fn draw(
+        &self,
+        gl_handler: &mut GraphicsHandler,
+        command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), FrameError> {
+        let mut quads = Vec::with_capacity(9);
+        self.push_quads(&mut quads);
+
+        gl_handler.draw_quads(quads, command_buffer)
+    }
+
+    fn get_z_index(&self) -> u8 {
+        self.z_index
+    }
+
+    fn flush_data(&self) {}
+
+    fn write_flags(&mut self) -> &mut DrawFlags {
+        &mut self.draw_flags
+    }
+
+    fn read_flags(&self) -> DrawFlags {
+        self.draw_flags
     }
 
+    fn set_dead(&mut self) {
+        self.draw_flags.remove(DrawFlags::USED);
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        self.draw_flags.set(DrawFlags::VISIBLE, visible);
+    }
+
+    fn get_state(&self) -> DrawState {
+        DrawState {
+            color: self.color,
+            global_position: self.global_position,
+            scale: self.scale,
+            flags: self.draw_flags,
+        }
+    }
+
+    fn set_state(&mut self, state: &DrawState) {
+        self.color = state.color;
+        self.global_position = state.global_position;
+        self.scale = state.scale;
+        self.draw_flags = state.flags;
+    }
+
+    fn recreate(&mut self, _gl_handler: &GraphicsHandler, recovery: &DeviceRecovery) -> Result<(), String> {
+        self.texture = recovery.texture(&self.texture)?;
+        Ok(())
+    }
+
+    fn batch_quads(&self, _view: &Aabb, quads: &mut Vec<BatchQuad>) -> bool {
+        self.push_quads(quads);
+        true
+    }
+
+    fn batch_key(&self) -> usize {
+        Arc::as_ptr(&self.texture) as usize
+    }
+
+    fn cull_bounds(&self) -> Option<Aabb> {
+        Some(self.bounds())
+    }
+}
+
+/// Axes a parallax layer repeats its texture along
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParallaxRepeat {
+    None,
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+impl ParallaxRepeat {
+    fn horizontal(self) -> bool {
+        matches!(self, ParallaxRepeat::Horizontal | ParallaxRepeat::Both)
+    }
+
+    fn vertical(self) -> bool {
+        matches!(self, ParallaxRepeat::Vertical | ParallaxRepeat::Both)
+    }
+}
+
+/// Background image scrolling slower than the world to look far away (side-scroller skies, hills...).
+/// Give it a z index below the scene ones to draw it behind
+pub struct ParallaxLayer {
+    texture: Texture,
+    texture_dimensions: Vector2<u32>,
+
+    z_index: u8,
+    draw_flags: DrawFlags,
+
+    pub color: Vector4<f32>,
+    /// Position of the image when the camera is at the origin
+    pub global_position: Vector2<f32>,
+    pub scale: Vector2<f32>,
+    /// How much the layer follows the camera moves: 0.0 stays still on screen (infinitely far),
+    /// 1.0 moves with the world, in between for the distance from the scene
+    pub scroll_factor: Vector2<f32>,
+    pub repeat: ParallaxRepeat,
+
+    _tracker: ResourceTracker,
+}
+
+impl ParallaxLayer {
+    /// Layer repeating the whole texture horizontally, `label` names it in the leak reports
+    pub fn new(
+        texture: Texture,
+        texture_dimensions: Vector2<u32>,
+        scroll_factor: Vector2<f32>,
+        label: &str,
+        z_index: u8,
+    ) -> Self {
+        Self {
+            texture,
+            texture_dimensions,
+            z_index,
+            draw_flags: DrawFlags::USED | DrawFlags::VISIBLE,
+            color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            global_position: Vector2::new(0.0, 0.0),
+            scale: Vector2::new(1.0, 1.0),
+            scroll_factor,
+            repeat: ParallaxRepeat::Horizontal,
+            _tracker: ResourceTracker::new(
+                ResourceKind::DrawObject,
+                &format!("Parallax layer '{}'", label),
+            ),
+        }
+    }
+
+    pub fn set_repeat(&mut self, repeat: ParallaxRepeat) {
+        self.repeat = repeat;
+    }
+
+    pub fn set_color(&mut self, color: impl Into<Color>) {
+        self.color = color.into().into();
+    }
+
+    pub fn get_color(&self) -> Color {
+        self.color.into()
+    }
+
+    /// Append the copies of the image covering the view, as quads in world space
+    fn push_quads(&self, view: &Aabb, quads: &mut Vec<BatchQuad>) {
+        let size = Vector2::new(
+            self.texture_dimensions.x as f32 * self.scale.x,
+            self.texture_dimensions.y as f32 * self.scale.y,
+        ) * WORLD_UNITS_PER_PIXEL;
+        if size.x <= 0.0 || size.y <= 0.0 {
+            return;
+        }
+
+        // The center of the view is the camera position
+        let camera = view.center();
+        let center = Vector2::new(
+            self.global_position.x + camera.x * (1.0 - self.scroll_factor.x),
+            self.global_position.y + camera.y * (1.0 - self.scroll_factor.y),
+        );
+
+        // Copies touching the view along the repeated axes, only the image itself otherwise
+        let copies = |repeat: bool, center: f32, size: f32, min: f32, max: f32| {
+            if repeat {
+                let first = ((min - center) / size + 0.5).floor() as i32;
+                let last = ((max - center) / size + 0.5).floor() as i32;
+                first..=last
+            } else {
+                0..=0
+            }
+        };
+        let columns = copies(
+            self.repeat.horizontal(),
+            center.x,
+            size.x,
+            view.min.x,
+            view.max.x,
+        );
+        let rows = copies(
+            self.repeat.vertical(),
+            center.y,
+            size.y,
+            view.min.y,
+            view.max.y,
+        );
+
+        let half_extents = size / 2.0;
+        for row in rows {
+            for column in columns.clone() {
+                let position = center + Vector2::new(column as f32 * size.x, row as f32 * size.y);
+                quads.push(BatchQuad::new(
+                    Some(self.texture.clone()),
+                    position,
+                    half_extents,
+                    self.color,
+                ));
+            }
+        }
+    }
+}
+
+impl Draw for ParallaxLayer {
+    fn draw(
+        &self,
+        gl_handler: &mut GraphicsHandler,
+        command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), FrameError> {
+        let view = gl_handler.view_bounds();
+        let mut quads = Vec::new();
+        self.push_quads(&view, &mut quads);
+
+        gl_handler.draw_quads(quads, command_buffer)
+    }
+
+    fn get_z_index(&self) -> u8 {
+        self.z_index
+    }
+
+    fn flush_data(&self) {}
+
     fn write_flags(&mut self) -> &mut DrawFlags {
         &mut self.draw_flags
     }
@@ -408,4 +1328,38 @@ impl Draw for Primitive {
     fn set_visible(&mut self, visible: bool) {
         self.draw_flags.set(DrawFlags::VISIBLE, visible);
     }
+
+    fn get_state(&self) -> DrawState {
+        DrawState {
+            color: self.color,
+            global_position: self.global_position,
+            scale: self.scale,
+            flags: self.draw_flags,
+        }
+    }
+
+    fn set_state(&mut self, state: &DrawState) {
+        self.color = state.color;
+        self.global_position = state.global_position;
+        self.scale = state.scale;
+        self.draw_flags = state.flags;
+    }
+
+    fn recreate(&mut self, _gl_handler: &GraphicsHandler, recovery: &DeviceRecovery) -> Result<(), String> {
+        self.texture = recovery.texture(&self.texture)?;
+        Ok(())
+    }
+
+    fn batch_quads(&self, view: &Aabb, quads: &mut Vec<BatchQuad>) -> bool {
+        self.push_quads(view, quads);
+        true
+    }
+
+    fn batch_key(&self) -> usize {
+        Arc::as_ptr(&self.texture) as usize
+    }
+
+    fn follows_view(&self) -> bool {
+        true
+    }
 }
@@ -1,3 +1,4 @@
 pub mod vulkan;
 pub mod draw_objects;
-mod sendable;
+pub mod render_thread;
+pub mod pipeline_cache;
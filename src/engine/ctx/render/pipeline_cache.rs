@@ -0,0 +1,102 @@
+//! Vulkan pipeline cache kept on disk, so the pipelines compiled in a run are ready at the next startup
+//!
+//! The file is chosen once for the process with `set_file` (see `EngineConfig::pipeline_cache`),
+//! every new `GraphicsHandler` starts from its content and the engine rewrites it at the end.
+//! Data written by another driver or GPU, or truncated, is dropped before reaching the driver.
+
+// standard imports
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+// vulkan imports
+use vulkano::device::Device;
+use vulkano::pipeline::cache::PipelineCache;
+
+// import the engine modules
+use crate::engine::crash;
+use crate::engine::paths;
+
+/// Length of the version one header: length, version, vendor and device IDs, cache UUID
+const HEADER_LENGTH: usize = 32;
+/// `VK_PIPELINE_CACHE_HEADER_VERSION_ONE`
+const HEADER_VERSION_ONE: u32 = 1;
+
+/// File of the cache, None to keep the pipelines in memory only
+static FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+pub fn set_file(path: Option<PathBuf>) {
+    *FILE.lock().unwrap_or_else(|e| e.into_inner()) = path;
+}
+
+pub fn file() -> Option<PathBuf> {
+    FILE.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Cache of a new device, filled with the saved pipelines if there are any
+pub(crate) fn load(device: Arc<Device>) -> Result<Arc<PipelineCache>, String> {
+    let data = file().and_then(|path| fs::read(path).ok());
+
+    if let Some(data) = data {
+        match check_header(&device, &data) {
+            // The header matches the device, the driver validates the rest of the data
+            Ok(()) => match unsafe { PipelineCache::with_data(device.clone(), &data) } {
+                Ok(cache) => return Ok(cache),
+                Err(e) => crash::log(&format!("Couldn't read the pipeline cache: {}", e)),
+            },
+            Err(e) => crash::log(&format!("Pipeline cache ignored: {}", e)),
+        }
+    }
+
+    PipelineCache::empty(device).map_err(|e| format!("Couldn't create the pipeline cache: {}", e))
+}
+
+/// Write the pipelines of the cache to the file, nothing to do without one
+pub(crate) fn save(cache: &PipelineCache) -> Result<(), String> {
+    let path = match file() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let data = cache
+        .get_data()
+        .map_err(|e| format!("Couldn't read the pipeline cache: {}", e))?;
+    paths::write_atomic(&path, &data).map_err(|e| {
+        format!(
+            "Couldn't save the pipeline cache '{}': {}",
+            path.display(),
+            e
+        )
+    })
+}
+
+/// Check that the data was written for this device by this driver, with a complete header
+fn check_header(device: &Device, data: &[u8]) -> Result<(), String> {
+    if data.len() < HEADER_LENGTH {
+        return Err(String::from("truncated header"));
+    }
+    // Written by the driver in the byte order of the machine
+    let word = |index: usize| {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&data[index * 4..index * 4 + 4]);
+        u32::from_ne_bytes(bytes)
+    };
+
+    let header_length = word(0) as usize;
+    if header_length < HEADER_LENGTH || header_length > data.len() {
+        return Err(format!("invalid header length {}", header_length));
+    }
+    if word(1) != HEADER_VERSION_ONE {
+        return Err(format!("unsupported header version {}", word(1)));
+    }
+
+    let properties = device.physical_device().properties();
+    if word(2) != properties.vendor_id || word(3) != properties.device_id {
+        return Err(String::from("written for another GPU"));
+    }
+    if data[16..HEADER_LENGTH] != properties.pipeline_cache_uuid[..] {
+        return Err(String::from("written by another driver version"));
+    }
+
+    Ok(())
+}
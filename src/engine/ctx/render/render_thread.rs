@@ -0,0 +1,190 @@
+//! Render thread, recording and presenting a frame while the main thread updates the next one
+//!
+//! With `EngineConfig::render_thread`, the frame data of the objects is flushed on the main thread
+//! (the render thread uploads it to the slots of its swapchain image) and their draws are copied in a `DrawList`, then the `GraphicsHandler` goes to the
+//! render thread with the list and a copy of the window state: the frame never reads the objects
+//! updated in the meantime. Two lists are swapped between the threads, one is filled while the
+//! other one is drawn.
+//! The main thread goes on with the next update and the handler comes back the first time it's used:
+//! only the code touching the renderer waits for the frame to be presented.
+//! Errors of a frame drawn on the render thread are reported by the next `VideoHandler::update`.
+
+// standard imports
+use std::cell::{Cell, OnceCell};
+use std::ops::{Deref, DerefMut};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+// import the engine modules
+use super::vulkan::{DrawList, FrameData, FrameError, GraphicsHandler};
+use crate::engine::memory::{self, MemoryTag};
+
+const HANDLER_MISSING: &str = "GraphicsHandler missing outside of the render thread";
+
+type RenderJob = (Box<GraphicsHandler>, FrameData, DrawList);
+type RenderResult = (Box<GraphicsHandler>, Result<(), FrameError>, DrawList);
+
+/// Thread drawing the frames it receives, one at a time
+struct RenderThread {
+    /// None only while stopping the thread
+    jobs: Option<Sender<RenderJob>>,
+    results: Receiver<RenderResult>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RenderThread {
+    fn spawn() -> Result<Self, String> {
+        let (jobs, pending_jobs) = mpsc::channel::<RenderJob>();
+        let (finished, results) = mpsc::channel::<RenderResult>();
+
+        let thread = thread::Builder::new()
+            .name(String::from("render"))
+            .spawn(move || {
+                let _scope = memory::scope(MemoryTag::Graphics);
+                for (mut handler, frame, mut list) in pending_jobs {
+                    let result = handler.draw_frame(&frame, &mut list);
+                    if finished.send((handler, result, list)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .map_err(|e| format!("Couldn't spawn the render thread: {}", e))?;
+
+        Ok(Self {
+            jobs: Some(jobs),
+            results,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for RenderThread {
+    /// Stop the thread once its last frame is drawn
+    fn drop(&mut self) {
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// `GraphicsHandler` of a window, away on the render thread while a frame is drawn there.
+/// Using it waits for the frame, the handler is then back on the calling thread
+pub struct RenderHandle {
+    /// Empty while the render thread has the handler
+    handler: OnceCell<Box<GraphicsHandler>>,
+    /// Result of the frame drawn on the render thread, not reported yet
+    result: Cell<Option<Result<(), FrameError>>>,
+    /// List filled by the next frame, the render thread has the other one while drawing
+    draw_list: Cell<DrawList>,
+    thread: Option<RenderThread>,
+}
+
+impl RenderHandle {
+    /// Handler drawing the frames on the calling thread, see `set_threaded`
+    pub fn new(handler: GraphicsHandler) -> Self {
+        Self {
+            handler: OnceCell::from(Box::new(handler)),
+            result: Cell::new(None),
+            draw_list: Cell::new(DrawList::default()),
+            thread: None,
+        }
+    }
+
+    /// Draw the frames on a render thread, or on the calling thread again
+    pub fn set_threaded(&mut self, threaded: bool) -> Result<(), String> {
+        if threaded == self.is_threaded() {
+            return Ok(());
+        }
+
+        if threaded {
+            self.thread = Some(RenderThread::spawn()?);
+        } else {
+            self.handler();
+            self.thread = None;
+        }
+        Ok(())
+    }
+
+    pub fn is_threaded(&self) -> bool {
+        self.thread.is_some()
+    }
+
+    /// Check if a frame is being drawn on the render thread
+    pub fn is_rendering(&self) -> bool {
+        self.handler.get().is_none()
+    }
+
+    /// The handler, waiting for the render thread to give it back if it's drawing a frame
+    fn handler(&self) -> &GraphicsHandler {
+        self.handler.get_or_init(|| {
+            let thread = self.thread.as_ref().expect(HANDLER_MISSING);
+            let (handler, result, list) = thread
+                .results
+                .recv()
+                .expect("The render thread stopped while drawing a frame");
+            self.result.set(Some(result));
+            self.draw_list.set(list);
+            handler
+        })
+    }
+
+    /// Wait for the frame drawn on the render thread, returning its result
+    pub fn finish_frame(&mut self) -> Result<(), FrameError> {
+        self.handler();
+        self.result.take().unwrap_or(Ok(()))
+    }
+
+    /// Draw a frame, on the render thread if there is one. The frame is then only prepared here
+    /// (see `GraphicsHandler::prepare_frame`) and its result is returned by the next `finish_frame`
+    pub fn render(&mut self, frame: FrameData) -> Result<(), FrameError> {
+        self.handler();
+        let thread = match &self.thread {
+            Some(thread) => thread,
+            None => {
+                return self
+                    .handler
+                    .get_mut()
+                    .expect(HANDLER_MISSING)
+                    .vulkan_loop(&frame)
+            }
+        };
+
+        let mut list = self.draw_list.take();
+        let handler = self.handler.get_mut().expect(HANDLER_MISSING);
+        if let Err(e) = handler.prepare_frame(&frame, &mut list) {
+            self.draw_list.set(list);
+            return Err(e);
+        }
+        let handler = self.handler.take().expect(HANDLER_MISSING);
+        thread
+            .jobs
+            .as_ref()
+            .expect("The render thread is stopping")
+            .send((handler, frame, list))
+            .expect("The render thread stopped");
+        Ok(())
+    }
+}
+
+impl Deref for RenderHandle {
+    type Target = GraphicsHandler;
+
+    fn deref(&self) -> &GraphicsHandler {
+        self.handler()
+    }
+}
+
+impl DerefMut for RenderHandle {
+    fn deref_mut(&mut self) -> &mut GraphicsHandler {
+        self.handler();
+        self.handler.get_mut().expect(HANDLER_MISSING)
+    }
+}
+
+impl Drop for RenderHandle {
+    /// The handler is dropped here, the `VideoHandler` drops it before the window of its surface
+    fn drop(&mut self) {
+        self.handler();
+    }
+}
@@ -1,57 +1,105 @@
 // standard imports
-use std::cell::RefCell;
 use std::cmp::{max, min};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::convert::TryInto;
-use std::fs::File;
-use std::ops::DerefMut;
-use std::rc::Rc;
-use std::sync::Arc;
+use std::error::Error;
+use std::fmt;
+use std::io::Cursor;
+use std::hash::{Hash, Hasher};
+use std::iter;
+use std::mem;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
 
 // Vulkano imports
-use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, ImmutableBuffer, TypedBufferAccess};
+use vulkano::buffer::{
+    BufferUsage, CpuAccessibleBuffer, CpuBufferPool, ImmutableBuffer, TypedBufferAccess,
+};
 use vulkano::command_buffer::{
-    AutoCommandBufferBuilder, CommandBufferUsage, DynamicState, SubpassContents,
+    AutoCommandBufferBuilder, CommandBufferUsage, DynamicState, PrimaryAutoCommandBuffer,
+    SubpassContents,
 };
+use vulkano::device::DeviceOwned;
 use vulkano::Handle;
 
 use vulkano::descriptor::descriptor_set::{
-    PersistentDescriptorSet, PersistentDescriptorSetBuilder, PersistentDescriptorSetImg,
-    PersistentDescriptorSetSampler,
+    PersistentDescriptorSet, PersistentDescriptorSetBuf, PersistentDescriptorSetBuilder,
+    PersistentDescriptorSetImg, PersistentDescriptorSetSampler,
 };
 use vulkano::device::{Device, DeviceExtensions, Queue};
 use vulkano::format::Format;
 use vulkano::image::view::ImageView;
-use vulkano::image::{ImageDimensions, ImageUsage, ImmutableImage, MipmapsCount, SwapchainImage};
+use vulkano::image::{
+    AttachmentImage, ImageAccess, ImageCreateFlags, ImageDimensions, ImageUsage, ImmutableImage,
+    MipmapsCount, StorageImage, SwapchainImage,
+};
+use vulkano::instance::loader::{FunctionPointers, Loader};
 use vulkano::instance::{Instance, InstanceExtensions, PhysicalDevice, PhysicalDeviceType};
 use vulkano::memory::DeviceMemoryAllocError;
+use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor, BlendOp};
+use vulkano::pipeline::cache::PipelineCache;
 use vulkano::pipeline::vertex::SingleBufferDefinition;
 use vulkano::pipeline::viewport::Viewport;
 use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::query::{QueryPool, QueryResultFlags, QueryType};
 use vulkano::render_pass::RenderPass;
 use vulkano::render_pass::{Framebuffer, FramebufferAbstract, Subpass};
 use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
 use vulkano::swapchain;
-use vulkano::swapchain::{AcquireError, Surface, Swapchain, SwapchainCreationError};
+use vulkano::swapchain::{
+    AcquireError, ColorSpace, PresentMode, SupportedPresentModes, Surface, Swapchain,
+    SwapchainAcquireFuture, SwapchainCreationError,
+};
 use vulkano::sync;
-use vulkano::sync::{FlushError, GpuFuture};
+use vulkano::sync::{FenceSignalFuture, FlushError, GpuFuture, PipelineStage};
 use vulkano::Version;
 use vulkano::VulkanObject;
 
 // SDL2 imports
-use sdl2::video::{Window, WindowContext};
+use sdl2::video::Window;
 
 // other imports
-use super::draw_objects::{Draw, DrawFlags, DrawObject, Sprite, SpriteObject, Primitive, PrimitiveObject};
-use super::sendable::Sendable;
-use cgmath::{Vector2, Vector4};
+use super::draw_objects::{
+    lock, BatchMesh, BatchQuad, Draw, DrawFlags, DrawObject, DrawState, GraphicObject,
+    NineSlice, NineSliceObject, ParallaxLayer, ParallaxObject, Primitive, PrimitiveObject,
+    SliceBorders, Sprite, SpriteObject,
+};
+use super::pipeline_cache;
+use crate::engine::ctx::decode_png_rgba;
+use crate::engine::color::Color;
+use crate::engine::crash;
+use crate::engine::error::MagmaError;
+use crate::engine::geometry::Aabb;
+use crate::engine::leaks::{self, ResourceKind, ResourceTracker};
+use crate::engine::math::Vec2;
+use crate::engine::pack;
+use crate::engine::warmup::{self, AssetKind};
+use cgmath::{Rad, Vector2, Vector4};
 use png;
 
 /// Use of a macro due to literals needed.
 /// This creates a new pipeline object (using the specified shaders) and appends it to the HashMap.
+/// The `@pipeline` form only returns the pipeline, allowing other vertex types and topologies,
+/// the `@blended` one also takes the blending of the pipeline (alpha blending otherwise).
 #[macro_use]
 macro_rules! create_pipeline {
-    ($name: expr, $device: expr, $render_pass: expr, $vs_path: expr, $fs_path: expr, $map: expr) => {{
+    (@pipeline $vertex: ty, $topology: ident, $name: expr, $device: expr, $render_pass: expr, $cache: expr, $vs_path: expr, $fs_path: expr) => {{
+        create_pipeline!(
+            @blended AttachmentBlend::alpha_blending(),
+            $vertex,
+            $topology,
+            $name,
+            $device,
+            $render_pass,
+            $cache,
+            $vs_path,
+            $fs_path
+        )
+    }};
+    (@blended $blend: expr, $vertex: ty, $topology: ident, $name: expr, $device: expr, $render_pass: expr, $cache: expr, $vs_path: expr, $fs_path: expr) => {{
         mod vertex_shader {
             vulkano_shaders::shader! {
                ty: "vertex",
@@ -66,36 +114,214 @@ macro_rules! create_pipeline {
             }
         }
 
-        let vert_shader = vertex_shader::Shader::load($device.clone()).expect(&format!(
-            "Couldn't load Vertex Shader: pipeline name: {},\nshader path: {}",
-            $name, $vs_path
-        ));
-        let frag_shader = fragment_shader::Shader::load($device.clone()).expect(&format!(
-            "Couldn't load Fragment Shader: pipeline name: {},\nshader path: {}",
-            $name, $fs_path
-        ));
-
-        let pipeline = Arc::new(
+        let vert_shader = vertex_shader::Shader::load($device.clone()).map_err(|e| {
+            MagmaError::Graphics(format!(
+                "Couldn't load Vertex Shader: pipeline name: {},\nshader path: {}: {}",
+                $name, $vs_path, e
+            ))
+        })?;
+        let frag_shader = fragment_shader::Shader::load($device.clone()).map_err(|e| {
+            MagmaError::Graphics(format!(
+                "Couldn't load Fragment Shader: pipeline name: {},\nshader path: {}: {}",
+                $name, $fs_path, e
+            ))
+        })?;
+
+        Arc::new(
             GraphicsPipeline::start()
-                .vertex_input_single_buffer::<Vertex>()
+                .vertex_input_single_buffer::<$vertex>()
                 .vertex_shader(vert_shader.main_entry_point(), ())
-                .triangle_strip()
+                .$topology()
                 .viewports_dynamic_scissors_irrelevant(1)
-                .blend_alpha_blending()
+                .blend_collective($blend)
                 .fragment_shader(frag_shader.main_entry_point(), ())
                 .render_pass(Subpass::from($render_pass.clone(), 0).unwrap())
+                .build_with_cache($cache.clone())
                 .build($device.clone())
-                .expect("Couldn't create new Vulkan Graphics Pipeline"),
+                .map_err(|e| {
+                    MagmaError::Graphics(format!("Couldn't create new Vulkan Graphics Pipeline: {}", e))
+                })?,
+        )
+    }};
+    ($name: expr, $device: expr, $render_pass: expr, $cache: expr, $vs_path: expr, $fs_path: expr, $map: expr) => {{
+        let pipeline = create_pipeline!(
+            @pipeline Vertex,
+            triangle_strip,
+            $name,
+            $device,
+            $render_pass,
+            $cache,
+            $vs_path,
+            $fs_path
         );
-        $map.insert($name.to_string(), pipeline.clone());
+        $map.insert($name.to_string(), pipeline);
     };};
 }
 
-pub type Texture = Arc<ImageView<Arc<ImmutableImage>>>;
-pub type DescriptorSetImg = PersistentDescriptorSetImg<Arc<ImageView<Arc<ImmutableImage>>>>;
+/// Size of the host visible memory allocated in advance by the warmup
+const WARMUP_STAGING_SIZE: usize = 4 * 1024 * 1024;
+/// Batched vertices the pools have room for after the warmup, 4096 quads
+const WARMUP_BATCH_VERTICES: u64 = 4 * 4096;
+
+/// Image of a texture: immutable once loaded, or a storage image for the streamed ones
+pub type TextureImage = Arc<dyn ImageAccess + Send + Sync>;
+pub type Texture = Arc<ImageView<TextureImage>>;
+pub type DescriptorSetImg = PersistentDescriptorSetImg<Texture>;
 pub type DescriptorSetWithImage<R> =
     PersistentDescriptorSetBuilder<((R, DescriptorSetImg), PersistentDescriptorSetSampler)>;
 pub type GlobalUniformBuffer = CpuAccessibleBuffer<GlobalUniformData>;
+type BatchPipeline = Arc<GraphicsPipeline<SingleBufferDefinition<BatchVertex>>>;
+type BatchDescriptorSet = PersistentDescriptorSet<(
+    (((), DescriptorSetImg), PersistentDescriptorSetSampler),
+    PersistentDescriptorSetBuf<Arc<GlobalUniformBuffer>>,
+)>;
+
+/// Texture whose pixels are replaced while it's drawn (e.g. the frames of a video): they're
+/// written to a staging buffer and copied to the image on the GPU, the Descriptor Sets sampling
+/// the texture stay the same
+pub struct StreamingTexture {
+    image: Arc<StorageImage>,
+    texture: Texture,
+    staging: CpuBufferPool<u8>,
+    dimensions: Vector2<u32>,
+}
+
+impl StreamingTexture {
+    /// Texture to draw, e.g. with `new_sprite_from_texture`
+    pub fn texture(&self) -> Texture {
+        self.texture.clone()
+    }
+
+    pub fn dimensions(&self) -> Vector2<u32> {
+        self.dimensions
+    }
+}
+
+/// Recoverable errors happening while rendering a frame
+#[derive(Clone, Debug)]
+pub enum FrameError {
+    AcquireImage(String),
+    CommandBufferCreation(String),
+    RenderPassBegin(String),
+    Draw(String),
+    RenderPassEnd(String),
+    CommandBufferBuild(String),
+    Execution(String),
+    Flush(String),
+    SwapchainRecreation(String),
+    BufferWrite(String),
+    DescriptorSet(String),
+    GpuTimeout,
+    /// The driver was reset or the GPU removed, the engine recreates the device
+    DeviceLost,
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::AcquireImage(e) => write!(f, "Couldn't acquire next image from Vulkan Swapchain: {}", e),
+            FrameError::CommandBufferCreation(e) => write!(f, "Couldn't build Vulkan AutoCommandBuffer: {}", e),
+            FrameError::RenderPassBegin(e) => write!(f, "Couldn't begin Vulkan Render Pass: {}", e),
+            FrameError::Draw(e) => write!(f, "Couldn't add Draw command to Vulkan Render Pass: {}", e),
+            FrameError::RenderPassEnd(e) => write!(f, "Couldn't properly end Vulkan Render Pass: {}", e),
+            FrameError::CommandBufferBuild(e) => write!(f, "Couldn't build Vulkan Command Buffer: {}", e),
+            FrameError::Execution(e) => write!(f, "Couldn't execute Vulkan Command Buffer: {}", e),
+            FrameError::Flush(e) => write!(f, "Failed to flush Vulkan Future: {}", e),
+            FrameError::SwapchainRecreation(e) => write!(f, "Couldn't recreate Vulkan Swapchain: {}", e),
+            FrameError::BufferWrite(e) => write!(f, "Couldn't write Vulkan buffer: {}", e),
+            FrameError::DescriptorSet(e) => write!(f, "Couldn't build Vulkan Descriptor Set: {}", e),
+            FrameError::GpuTimeout => write!(f, "GPU Timeout"),
+            FrameError::DeviceLost => write!(f, "Vulkan Device lost"),
+        }
+    }
+}
+
+impl Error for FrameError {}
+
+/// Error of a flushed or waited GpuFuture
+fn flush_error(error: FlushError) -> FrameError {
+    match error {
+        FlushError::DeviceLost => FrameError::DeviceLost,
+        e => FrameError::Flush(e.to_string()),
+    }
+}
+
+/// Texture uploaded from a file or from generated pixels, uploaded again if the device is lost
+struct UploadedTexture {
+    path: PathBuf,
+    texture: Weak<ImageView<TextureImage>>,
+    dimensions: Vector2<u32>,
+}
+
+/// Textures uploaded again on the device replacing a lost one, by the texture they replace
+pub struct DeviceRecovery {
+    /// Lost texture (kept alive so its address stays unique), new texture and its size
+    textures: HashMap<usize, (Texture, Texture, Vector2<u32>)>,
+    /// Only the textures above were replaced (reloaded from disk), the others are still valid
+    same_device: bool,
+    /// DrawObjects which couldn't be recreated, they were removed
+    pub lost_objects: usize,
+}
+
+impl DeviceRecovery {
+    /// Texture of the new device replacing one of the lost device, or the reloaded version of a texture
+    pub fn texture(&self, lost: &Texture) -> Result<Texture, String> {
+        match self.textures.get(&(Arc::as_ptr(lost) as usize)) {
+            Some((_, texture, _)) => Ok(texture.clone()),
+            None if self.same_device => Ok(lost.clone()),
+            None => Err(String::from("Texture not recreated on the new Vulkan Device")),
+        }
+    }
+}
+
+/// Window state a frame is rendered with, copied from the window so it can be rendered on another thread
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameData {
+    /// The window was resized since the last frame
+    pub resized: bool,
+    /// Logical size of the window
+    pub size: (u32, u32),
+    /// Size of the window in pixels, the size of the swapchain images
+    pub drawable_size: (u32, u32),
+    /// View set before preparing the frame, None keeps the current one
+    pub camera: Option<CameraView>,
+    /// Copy the frame, to present it again while the window is resized or to capture it
+    pub keep: bool,
+}
+
+impl FrameData {
+    pub fn new(window: &Window, resized: bool) -> Self {
+        Self {
+            resized,
+            size: window.size(),
+            drawable_size: window.vulkan_drawable_size(),
+            camera: None,
+            keep: false,
+        }
+    }
+}
+
+/// Camera of a frame, copied from `ctx.camera` so the main thread never waits for the renderer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraView {
+    pub position: Vector2<f32>,
+    /// Zoom and stretch the whole view, a negative dimension flips the view on it
+    pub scale: Vector2<f32>,
+    /// Counterclockwise rotation of the view, the world appears rotated the other way
+    pub rotation: Rad<f32>,
+    /// Color drawn over the whole view after every object
+    pub overlay: Color,
+}
+
+/// In-memory copy of the renderer state, restorable at any time
+#[derive(Clone)]
+pub struct RenderSnapshot {
+    /// Objects are kept alive to bring back the ones destroyed after the snapshot
+    objects: Vec<(DrawObject<dyn Draw>, DrawState)>,
+    camera_position: Vector2<f32>,
+    camera_scale: Vector2<f32>,
+    camera_rotation: Rad<f32>,
+}
 
 /// Struct to hold the global data needed for graphics
 #[derive(Clone, Copy)]
@@ -103,6 +329,178 @@ pub struct GlobalUniformData {
     window_size: Vector4<u32>,
     camera_position: Vector4<f32>,
     camera_scale: Vector4<f32>,
+    /// Cosine and sine of the camera rotation
+    camera_rotation: Vector4<f32>,
+}
+
+/// Work recorded for the last frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderCounters {
+    pub draw_calls: u32,
+    pub vertices: u32,
+    /// Draw calls merging objects sharing the batch pipeline and a texture
+    pub batches: u32,
+    /// Objects drawn in the batches, `batched_objects - batches` draw calls were saved
+    pub batched_objects: u32,
+    /// Visible objects recorded, and the ones skipped because they were outside of the view or occluded
+    pub drawn_objects: u32,
+    pub culled_objects: u32,
+}
+
+/// Parts of a frame timed on the GPU, in order
+const GPU_PASSES: [&str; 2] = ["Scene", "Frame copy"];
+
+/// GPU time of a part of the last frame, measured with timestamp queries
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GpuPassTime {
+    pub name: &'static str,
+    /// In seconds
+    pub time: f32,
+}
+
+/// Timestamps written at the start of a frame and at the end of every pass
+struct GpuTimer {
+    pool: Arc<QueryPool>,
+    /// Seconds per timestamp tick
+    period: f64,
+}
+
+impl GpuTimer {
+    fn new(device: Arc<Device>, queue: &Queue, period: Option<f32>) -> Option<Self> {
+        // Not every queue can write timestamps
+        queue.family().timestamp_valid_bits()?;
+        let pool = QueryPool::new(device, QueryType::Timestamp, GPU_PASSES.len() as u32 + 1).ok()?;
+
+        Some(Self {
+            pool,
+            period: period? as f64 * 1e-9,
+        })
+    }
+
+    fn begin(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> Result<(), FrameError> {
+        // Reset by the Command Buffer itself, so it can be submitted again
+        unsafe {
+            builder
+                .reset_query_pool(self.pool.clone(), 0..GPU_PASSES.len() as u32 + 1)
+                .map_err(|e| FrameError::CommandBufferBuild(e.to_string()))?;
+            builder
+                .write_timestamp(self.pool.clone(), 0, PipelineStage::TopOfPipe)
+                .map_err(|e| FrameError::CommandBufferBuild(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    fn end_pass(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, pass: usize) -> Result<(), FrameError> {
+        unsafe {
+            builder
+                .write_timestamp(self.pool.clone(), pass as u32 + 1, PipelineStage::BottomOfPipe)
+                .map_err(|e| FrameError::CommandBufferBuild(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Times of the passes of the last frame, if the GPU finished it
+    fn read(&self) -> Option<Vec<GpuPassTime>> {
+        let mut ticks = [0u64; GPU_PASSES.len() + 1];
+        let flags = QueryResultFlags {
+            wait: false,
+            with_availability: false,
+            partial: false,
+        };
+        let ready = self
+            .pool
+            .queries_range(0..ticks.len() as u32)?
+            .get_results(&mut ticks[..], flags)
+            .ok()?;
+        if !ready {
+            return None;
+        }
+
+        let times = GPU_PASSES
+            .iter()
+            .zip(ticks.windows(2))
+            .map(|(name, ticks)| GpuPassTime {
+                name,
+                time: (ticks[1].wrapping_sub(ticks[0]) as f64 * self.period) as f32,
+            })
+            .collect();
+        Some(times)
+    }
+}
+
+/// Longest wait for a frame in flight before reporting the GPU as stuck
+const GPU_TIMEOUT: Duration = Duration::from_secs(10);
+
+type FrameFence = Arc<FenceSignalFuture<Box<dyn GpuFuture + Send + Sync>>>;
+
+/// Fences of the frames in flight, one per swapchain image.
+/// A frame waits only for the last one drawn to its image, the semaphores between acquiring,
+/// executing and presenting it are handled by the futures
+struct FrameSync {
+    fences: Vec<Option<FrameFence>>,
+    /// Image of the last submitted frame, the next frame is executed after it
+    previous: Option<usize>,
+    /// Uploads submitted after the last frame (see `update_streaming_texture`), the next
+    /// frame is executed after them
+    uploads: Option<Box<dyn GpuFuture + Send + Sync>>,
+}
+
+impl FrameSync {
+    fn new() -> Self {
+        Self {
+            fences: Vec::new(),
+            previous: None,
+            uploads: None,
+        }
+    }
+
+    /// Wait for the last frame drawn to the image, its resources can then be written again
+    fn wait_image(&self, image_num: usize) -> Result<(), FrameError> {
+        match self.fences.get(image_num) {
+            Some(Some(fence)) => fence.wait(Some(GPU_TIMEOUT)).map_err(|e| match e {
+                FlushError::DeviceLost => FrameError::DeviceLost,
+                _ => FrameError::GpuTimeout,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Future the work of a new frame starts after
+    fn previous_future(&mut self, device: Arc<Device>) -> Box<dyn GpuFuture + Send + Sync> {
+        // Already executed after the last frame
+        if let Some(uploads) = self.uploads.take() {
+            return uploads;
+        }
+
+        match self.previous.and_then(|image_num| self.fences[image_num].clone()) {
+            Some(fence) => fence.boxed_send_sync(),
+            None => sync::now(device).boxed_send_sync(),
+        }
+    }
+
+    /// Register the frame submitted for the image, None if its work was dropped
+    fn submitted(&mut self, image_num: usize, fence: Option<FrameFence>) {
+        if self.fences.len() <= image_num {
+            self.fences.resize(image_num + 1, None);
+        }
+
+        self.fences[image_num] = fence;
+        self.previous = Some(image_num);
+    }
+
+    /// Work submitted after the last frame, the next frame waits for it
+    fn uploaded(&mut self, future: Box<dyn GpuFuture + Send + Sync>) {
+        self.uploads = Some(future);
+    }
+
+    /// Forget every frame in flight, the next one starts from a clean state
+    fn clear(&mut self) {
+        self.fences.clear();
+        self.previous = None;
+        self.uploads = None;
+    }
 }
 
 /// Struct to handle connections to the Vulkano (and thus Vulkan) API
@@ -111,29 +509,116 @@ pub struct GraphicsHandler {
     swapchain: SwapchainHandler,
     render_pass: Arc<RenderPass>,
     pipelines: HashMap<String, Arc<GraphicsPipeline<SingleBufferDefinition<Vertex>>>>,
-    previous_frame_end: Option<Box<dyn GpuFuture>>,
+    /// Compiled pipelines, saved to disk with `save_pipeline_cache`
+    pipeline_cache: Arc<PipelineCache>,
+    frame_sync: FrameSync,
     device: Arc<Device>,
     queue: Arc<Queue>,
     draw_objects: Vec<DrawObject<dyn Draw>>,
+    /// Textures still alive with their origin. They aren't shared by path: the asset cache
+    /// (`ctx.load_texture`) does that
+    uploaded_textures: Mutex<Vec<UploadedTexture>>,
+    /// Pixels of the textures not read from a file (e.g. atlas pages), uploaded again if the device is lost
+    texture_pixels: Mutex<HashMap<PathBuf, Vec<u8>>>,
+
+    /// Merge consecutive quads sharing the same texture into single draws (ignored when reusing Command Buffers)
+    pub batch_draws: bool,
+    batch_pipeline: BatchPipeline,
+    batch_sampler: Arc<Sampler>,
+    batch_sets: HashMap<usize, Arc<BatchDescriptorSet>>,
+    white_texture: Texture,
+    /// Host visible memory allocated by the warmup, alive as long as the handler so the driver doesn't reclaim it
+    warmup_staging: Option<Arc<CpuAccessibleBuffer<[u8]>>>,
+    /// Vertices and indices of the batches, in pooled buffers reused every frame
+    batch_vertex_pool: CpuBufferPool<BatchVertex>,
+    batch_index_pool: CpuBufferPool<u32>,
+    /// Merged quads and meshes, kept so the next frames don't allocate them again
+    batch: DrawBatch,
+    /// Draws of the frames drawn by `vulkan_loop`, the render thread brings its own
+    draw_list: DrawList,
+
+    /// Skip the objects outside of the view (see `Draw::cull_bounds`) instead of recording them,
+    /// and the objects with `DrawFlags::OCCLUSION_TEST` hidden behind an opaque object of a higher z index.
+    /// There's no depth buffer to run GPU occlusion queries on, the occluders are tested on the CPU
+    pub cull_objects: bool,
+
+    /// Record the Command Buffers once and resubmit them until the draw list changes
+    pub reuse_command_buffers: bool,
+    recorded_command_buffers: HashMap<usize, Arc<PrimaryAutoCommandBuffer>>,
+    recorded_signature: u64,
+    /// Counted while recording, resubmitted Command Buffers keep the counts of their recording
+    pub(crate) counters: RenderCounters,
+    /// None when the GPU can't write timestamps
+    gpu_timer: Option<GpuTimer>,
+    gpu_pass_times: Vec<GpuPassTime>,
 
     global_uniform_buffer: Arc<GlobalUniformBuffer>,
+    /// Global data of the frames in flight, one slot per swapchain image copied into the global
+    /// uniform buffer when its frame starts: the CPU never writes a buffer the GPU may be reading
+    uniform_ring: Vec<Arc<GlobalUniformBuffer>>,
+    /// Logical size of the window, the unit used by every object position
     pub window_size: Vector2<u32>,
-    pub camera_position: Vector2<f32>,
-    /// Zoom and stretch the whole view (If any of the dimensions is negative, it'll revert the view on that dimension)
-    pub camera_scale: Vector2<f32>,
+    /// Camera of the view, set from `ctx.camera` before every frame of the main window
+    camera: CameraView,
+    clear_color: Color,
 }
 
+// The handler can be moved to and shared with a render thread
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<GraphicsHandler>();
+};
+
 impl GraphicsHandler {
-    /// Vulkan object handler instancing and init
-    pub fn new(window: &Window) -> Self {
-        let instance = create_instance();
+    /// Vulkan object handler instancing and init, failing without a usable device
+    pub fn new(window: &Window) -> Result<Self, MagmaError> {
+        let instance = create_instance(window).map_err(MagmaError::Graphics)?;
 
-        let surface = create_surface(instance.clone(), window);
+        let surface = create_surface(instance.clone(), window).map_err(MagmaError::Graphics)?;
 
         // Get the device info and queue
-        let (physical, device, queue) = get_device(&instance, surface.clone());
+        let (device, queue) = {
+            let (_, device, queue) =
+                get_device(&instance, surface.clone()).map_err(MagmaError::Graphics)?;
+            (device, queue)
+        };
+        let pipeline_cache = pipeline_cache::load(device.clone()).map_err(MagmaError::Graphics)?;
+
+        Self::with_device(window, instance, surface, device, queue, pipeline_cache)
+    }
+
+    /// Renderer of another window on the Instance and Device of `main`, so they can draw the same textures
+    pub fn new_sharing(window: &Window, main: &GraphicsHandler) -> Result<Self, MagmaError> {
+        let surface = create_surface(main.instance.clone(), window).map_err(MagmaError::Graphics)?;
+        if !surface.is_supported(main.queue.family()).unwrap_or(false) {
+            return Err(MagmaError::Graphics(String::from(
+                "The GPU of the main window can't present to the new window",
+            )));
+        }
+
+        Self::with_device(
+            window,
+            main.instance.clone(),
+            surface,
+            main.device.clone(),
+            main.queue.clone(),
+            main.pipeline_cache.clone(),
+        )
+    }
+
+    fn with_device(
+        window: &Window,
+        instance: Arc<Instance>,
+        surface: Arc<Surface<SurfaceWindow>>,
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        pipeline_cache: Arc<PipelineCache>,
+    ) -> Result<Self, MagmaError> {
+        let physical = device.physical_device();
 
-        let (swapchain, images) = create_raw_swapchain(window, device.clone(), surface, physical);
+        let (swapchain, images, usage, present_modes) =
+            create_raw_swapchain(window, device.clone(), surface, physical)
+                .map_err(MagmaError::Graphics)?;
 
         let render_pass = Arc::new(
             vulkano::single_pass_renderpass!(
@@ -151,7 +636,7 @@ impl GraphicsHandler {
                     depth_stencil: {}
                 }
             )
-            .expect("Couldn't create new Vulkan RenderPass"),
+            .map_err(|e| MagmaError::Graphics(format!("Couldn't create new Vulkan RenderPass: {}", e)))?,
         );
 
         let mut pipelines = HashMap::new();
@@ -159,6 +644,7 @@ impl GraphicsHandler {
             "Primitive",
             device,
             render_pass,
+            pipeline_cache,
             "assets/shaders/primitive.vert",
             "assets/shaders/primitive.frag",
             &mut pipelines
@@ -167,14 +653,41 @@ impl GraphicsHandler {
             "Sprite",
             device,
             render_pass,
+            pipeline_cache,
             "assets/shaders/sprite.vert",
             "assets/shaders/sprite.frag",
             &mut pipelines
         );
 
-        let swapchain = SwapchainHandler::new(swapchain, images, render_pass.clone());
+        // The light map multiplies the colors of the scene under it
+        let light_pipeline = create_pipeline!(
+            @blended multiply_blending(),
+            Vertex,
+            triangle_strip,
+            "Light",
+            device,
+            render_pass,
+            pipeline_cache,
+            "assets/shaders/light.vert",
+            "assets/shaders/light.frag"
+        );
+        pipelines.insert("Light".to_string(), light_pipeline);
 
-        let previous_frame_end = Some(sync::now(device.clone()).boxed());
+        let batch_pipeline = create_pipeline!(
+            @pipeline BatchVertex,
+            triangle_list,
+            "Batch",
+            device,
+            render_pass,
+            pipeline_cache,
+            "assets/shaders/batch.vert",
+            "assets/shaders/batch.frag"
+        );
+
+        let swapchain =
+            SwapchainHandler::new(swapchain, images, usage, present_modes, render_pass.clone());
+
+        let gpu_timer = GpuTimer::new(device.clone(), &queue, physical.properties().timestamp_period);
 
         let mut draw_objects = Vec::new();
         draw_objects.reserve(50);
@@ -187,6 +700,7 @@ impl GraphicsHandler {
         let global_uniform_data = GlobalUniformData {
             camera_position: camera_position.extend(0.0).extend(0.0),
             camera_scale: camera_scale.extend(0.0).extend(0.0),
+            camera_rotation: Vector4::new(1.0, 0.0, 0.0, 0.0),
             window_size: window_size.extend(0).extend(0),
         };
         let global_uniform_buffer = CpuAccessibleBuffer::from_data(
@@ -195,156 +709,1082 @@ impl GraphicsHandler {
             true,
             global_uniform_data,
         )
-        .unwrap();
-
-        Self {
+        .map_err(|e| MagmaError::Graphics(format!("Couldn't create Vulkan global uniform buffer: {}", e)))?;
+
+        // Untextured quads are batched by sampling a white pixel
+        let (white_image, white_future) = ImmutableImage::from_iter(
+            [255u8, 255, 255, 255].iter().cloned(),
+            ImageDimensions::Dim2d {
+                width: 1,
+                height: 1,
+                array_layers: 1,
+            },
+            MipmapsCount::One,
+            Format::R8G8B8A8Srgb,
+            queue.clone(),
+        )
+        .map_err(|e| MagmaError::Graphics(format!("Couldn't create Vulkan white texture: {}", e)))?;
+        white_future
+            .flush()
+            .map_err(|e| MagmaError::Graphics(format!("Couldn't upload Vulkan white texture: {}", e)))?;
+        let white_texture = ImageView::new(white_image as TextureImage)
+            .map_err(|e| MagmaError::Graphics(format!("Couldn't create Vulkan white texture view: {}", e)))?;
+        let batch_sampler = Sampler::simple_repeat_linear_no_mipmap(device.clone());
+
+        Ok(Self {
             instance,
             swapchain,
             render_pass,
             pipelines,
-            previous_frame_end,
+            pipeline_cache,
+            frame_sync: FrameSync::new(),
             device,
             queue,
             draw_objects,
+            uploaded_textures: Mutex::new(Vec::new()),
+            texture_pixels: Mutex::new(HashMap::new()),
+
+            batch_draws: true,
+            batch_pipeline,
+            batch_sampler,
+            batch_sets: HashMap::new(),
+            white_texture,
+            warmup_staging: None,
+            batch_vertex_pool: CpuBufferPool::vertex_buffer(device.clone()),
+            batch_index_pool: CpuBufferPool::new(device.clone(), BufferUsage::index_buffer()),
+            batch: DrawBatch::default(),
+            draw_list: DrawList::default(),
+
+            cull_objects: true,
+
+            reuse_command_buffers: false,
+            recorded_command_buffers: HashMap::new(),
+            recorded_signature: 0,
+            counters: RenderCounters::default(),
+            gpu_timer,
+            gpu_pass_times: Vec::new(),
 
             global_uniform_buffer,
+            uniform_ring: Vec::new(),
             window_size,
-            camera_position,
-            camera_scale,
-        }
+            camera: CameraView {
+                position: camera_position,
+                scale: camera_scale,
+                rotation: Rad(0.0),
+                overlay: Color::TRANSPARENT,
+            },
+            clear_color: Color::BLACK,
+        })
+    }
+
+    /// Color of the background, behind every object
+    pub fn set_clear_color(&mut self, color: impl Into<Color>) {
+        self.clear_color = color.into();
+
+        // The color is part of the recorded Command Buffers
+        self.recorded_command_buffers.clear();
+    }
+
+    pub fn get_clear_color(&self) -> Color {
+        self.clear_color
+    }
+
+    /// Camera of the view, see `CameraView`
+    pub fn camera_view(&self) -> CameraView {
+        self.camera
+    }
+
+    /// Change the view right away, the main window takes the one of `ctx.camera` every frame
+    pub fn set_camera_view(&mut self, view: CameraView) {
+        self.camera = view;
+    }
+
+    /// Position of a world point in normalized device coordinates (-1.0 to 1.0 on screen, Y down)
+    pub fn world_to_ndc(&self, point: Vec2) -> Vec2 {
+        let extent = self.view_extent();
+        let offset = point - self.camera.position;
+        let (sin, cos) = self.camera.rotation.0.sin_cos();
+        let view = Vec2::new(
+            offset.x * cos + offset.y * sin,
+            -offset.x * sin + offset.y * cos,
+        );
+
+        Vec2::new(view.x / extent.x, view.y / extent.y)
+    }
+
+    /// World point at a position in normalized device coordinates
+    pub fn ndc_to_world(&self, point: Vec2) -> Vec2 {
+        let extent = self.view_extent();
+        let view = Vec2::new(point.x * extent.x, point.y * extent.y);
+        let (sin, cos) = self.camera.rotation.0.sin_cos();
+
+        self.camera.position + Vec2::new(view.x * cos - view.y * sin, view.x * sin + view.y * cos)
+    }
+
+    /// Area of the world seen by the camera (holding the whole view when it's rotated)
+    pub fn view_bounds(&self) -> Aabb {
+        let corners = [
+            self.ndc_to_world(Vec2::new(-1.0, -1.0)),
+            self.ndc_to_world(Vec2::new(-1.0, 1.0)),
+            self.ndc_to_world(Vec2::new(1.0, 1.0)),
+            self.ndc_to_world(Vec2::new(1.0, -1.0)),
+        ];
+
+        Aabb::from_points(&corners).expect("The view has corners")
+    }
+
+    /// Distance in world units from the camera to the edges of the view, like in the shaders
+    fn view_extent(&self) -> Vec2 {
+        Vec2::new(
+            self.window_size.x as f32 * self.camera.scale.x,
+            self.window_size.y as f32 * self.camera.scale.y,
+        )
     }
 
     /// Rendering function to call every frame
-    pub fn vulkan_loop(&mut self, resized: bool, window: &Window) {
-        // Update the render object list and flush all the data to the gpu
-        {
-            self.draw_objects
-                .retain(|o| o.borrow().read_flags().contains(DrawFlags::USED));
-            self.flush_global_data();
-            for o in &self.draw_objects {
-                o.borrow().flush_data();
-            }
+    pub fn vulkan_loop(&mut self, frame: &FrameData) -> Result<(), FrameError> {
+        let mut list = mem::take(&mut self.draw_list);
+        let result = self
+            .prepare_frame(frame, &mut list)
+            .and_then(|_| self.draw_frame(frame, &mut list));
+        self.draw_list = list;
+        result
+    }
+
+    /// Update the render object list, flush all the data to the gpu and collect the draws of the
+    /// frame in `list`. Done on the thread updating the objects, the frame can then be drawn anywhere
+    pub(crate) fn prepare_frame(
+        &mut self,
+        frame: &FrameData,
+        list: &mut DrawList,
+    ) -> Result<(), FrameError> {
+        self.draw_objects
+            .retain(|o| lock(o).read_flags().contains(DrawFlags::USED));
+        if frame.resized {
+            self.window_size = frame.size.into();
+        }
+        if let Some(view) = frame.camera {
+            self.set_camera_view(view);
+        }
+
+        // Only kept until the frames are recorded, in the uniform rings of the images
+        for o in &self.draw_objects {
+            lock(o).flush_data();
         }
 
+        self.collect_draws(list);
+        Ok(())
+    }
+
+    /// Record and present a frame prepared with `prepare_frame`, without reading the objects
+    /// batched in `list`
+    pub(crate) fn draw_frame(
+        &mut self,
+        frame: &FrameData,
+        list: &mut DrawList,
+    ) -> Result<(), FrameError> {
         // Check the window resize and make new framebuffers if needed
         {
-            // If the window is being resized, return true, otherwise keep the original value (in case of pending resizes)
-            let recreate: bool = {
-                if resized {
-                    self.window_size = window.size().into();
-                    true
-                } else {
-                    self.swapchain.get_recreate()
-                }
-            };
-
-            self.swapchain.set_recreate(recreate);
+            // Keep the pending recreation of the previous frames
+            if frame.resized {
+                self.swapchain.set_recreate(true);
+            }
 
             let pass = self.render_pass.clone();
             let swapchain = self.get_swapchain();
 
-            // Not an actual error, just a way to signify the need to retry the procedure
-            if swapchain.check_and_recreate(window, pass).is_err() {
-                return;
+            // Not ready yet, just a way to signify the need to retry the procedure
+            if !swapchain.check_and_recreate(frame.drawable_size, pass)? {
+                return Ok(());
             }
         }
 
         // START OF THE ACTUAL LOOP
 
-        // Get the future image
-        let (image_num, suboptimal, acquire_future) =
-            match swapchain::acquire_next_image(self.get_swapchain().chain.clone(), None) {
-                Ok(r) => r,
-                Err(AcquireError::OutOfDate) => {
-                    self.get_swapchain().set_recreate(true);
-                    return;
+        let (image_num, acquire_future) = match self.acquire_image()? {
+            Some(acquired) => acquired,
+            None => return Ok(()),
+        };
+
+        let keep = frame.keep && self.get_swapchain().last_frame.is_some();
+        let recorded = self.frame_command_buffer(image_num, list, keep);
+        self.submit_or_clear(acquire_future, recorded, image_num)?;
+        // Only the copy of the frame just drawn can be presented again
+        self.get_swapchain().frame_kept = keep;
+        Ok(())
+    }
+
+    /// Command Buffer drawing the list to an acquired image, the one recorded for the image if
+    /// nothing changed
+    fn frame_command_buffer(
+        &mut self,
+        image_num: usize,
+        list: &mut DrawList,
+        keep: bool,
+    ) -> Result<Arc<PrimaryAutoCommandBuffer>, FrameError> {
+        // The uniforms and the Command Buffer of the image may still be used by its last frame
+        self.frame_sync.wait_image(image_num)?;
+
+        // The timestamps of the last frames are written once the GPU is done with them
+        if let Some(times) = self.gpu_timer.as_ref().and_then(GpuTimer::read) {
+            self.gpu_pass_times = times;
+        }
+
+        self.write_global_data(image_num)?;
+
+        // Reuse the Command Buffer recorded for this image if nothing changed in the draw list
+        let mut hasher = DefaultHasher::new();
+        self.swapchain.generation.hash(&mut hasher);
+        list.signature.hash(&mut hasher);
+        keep.hash(&mut hasher);
+        let signature = hasher.finish();
+        if signature != self.recorded_signature {
+            self.recorded_command_buffers.clear();
+            self.recorded_signature = signature;
+        }
+
+        if let Some(command_buffer) = self.recorded_command_buffers.get(&image_num).cloned() {
+            // The copies of the recording read the new data from the slots of the image
+            for item in &list.items {
+                if let DrawItem::Object(obj) = item {
+                    lock(obj).upload_frame_data(image_num, None)?;
                 }
-                Err(e) => panic!("Couldn't acquire next image from Vulkan Swapchain: {}", e),
-            };
-        self.get_swapchain().set_recreate(suboptimal);
+            }
+            return Ok(command_buffer);
+        }
 
-        // Create Command Buffer for draw calls
+        let command_buffer = Arc::new(self.record_frame(image_num, list, keep)?);
+        if self.reuse_command_buffers {
+            self.recorded_command_buffers
+                .insert(image_num, command_buffer.clone());
+        }
+        Ok(command_buffer)
+    }
+
+    /// Submit the Command Buffer of an acquired image. If it couldn't be recorded, the image is
+    /// presented cleared instead: it only goes back to the swapchain once presented
+    fn submit_or_clear(
+        &mut self,
+        acquire_future: SwapchainAcquireFuture<SurfaceWindow>,
+        recorded: Result<Arc<PrimaryAutoCommandBuffer>, FrameError>,
+        image_num: usize,
+    ) -> Result<(), FrameError> {
+        let error = match recorded {
+            Ok(command_buffer) => return self.submit(acquire_future, command_buffer, image_num),
+            Err(e) => e,
+        };
+
+        // The error of the recording is the one reported
+        if !matches!(error, FrameError::DeviceLost) {
+            if let Ok(cleared) = self.record_cleared(image_num) {
+                let _ = self.submit(acquire_future, Arc::new(cleared), image_num);
+            }
+        }
+        Err(error)
+    }
+
+    /// Command Buffer only clearing an image, presented when its frame couldn't be drawn
+    fn record_cleared(&self, image_num: usize) -> Result<PrimaryAutoCommandBuffer, FrameError> {
         let mut builder = AutoCommandBufferBuilder::primary(
             self.get_device(),
             self.queue.family(),
             CommandBufferUsage::OneTimeSubmit,
         )
-        .expect("Couldn't build Vulkan AutoCommandBuffer");
+        .map_err(|e| FrameError::CommandBufferCreation(e.to_string()))?;
+
+        builder
+            .begin_render_pass(
+                self.swapchain.framebuffers[image_num].clone(),
+                SubpassContents::Inline,
+                iter::once(<[f32; 4]>::from(self.clear_color).into()),
+            )
+            .map_err(|e| FrameError::RenderPassBegin(e.to_string()))?
+            .end_render_pass()
+            .map_err(|e| FrameError::RenderPassEnd(e.to_string()))?;
+
+        builder
+            .build()
+            .map_err(|e| FrameError::CommandBufferBuild(e.to_string()))
+    }
+
+    /// Next image of the swapchain to draw to, None if the swapchain must be rebuilt first.
+    /// Suboptimal swapchains are still drawn to, they're rebuilt before the next frame
+    fn acquire_image(
+        &mut self,
+    ) -> Result<Option<(usize, SwapchainAcquireFuture<SurfaceWindow>)>, FrameError> {
+        match swapchain::acquire_next_image(self.get_swapchain().chain.clone(), None) {
+            Ok((image_num, suboptimal, acquire_future)) => {
+                if suboptimal {
+                    self.get_swapchain().set_recreate(true);
+                }
+                Ok(Some((image_num, acquire_future)))
+            }
+            Err(AcquireError::OutOfDate) => {
+                self.get_swapchain().set_recreate(true);
+                Ok(None)
+            }
+            Err(AcquireError::DeviceLost) => Err(FrameError::DeviceLost),
+            Err(e) => Err(FrameError::AcquireImage(e.to_string())),
+        }
+    }
+
+    /// Record the draw calls of the list in a new Command Buffer, copying the image to the last
+    /// frame when `keep` is set
+    fn record_frame(
+        &mut self,
+        image_num: usize,
+        list: &mut DrawList,
+        keep: bool,
+    ) -> Result<PrimaryAutoCommandBuffer, FrameError> {
+        self.counters = RenderCounters::default();
+
+        let usage = if self.reuse_command_buffers {
+            CommandBufferUsage::MultipleSubmit
+        } else {
+            CommandBufferUsage::OneTimeSubmit
+        };
+
+        // Create Command Buffer for draw calls
+        let mut builder =
+            AutoCommandBufferBuilder::primary(self.get_device(), self.queue.family(), usage)
+                .map_err(|e| FrameError::CommandBufferCreation(e.to_string()))?;
+
+        if let Some(timer) = &self.gpu_timer {
+            timer.begin(&mut builder)?;
+        }
+
+        // Global data of the frame, from the ring slot of its image
+        builder
+            .copy_buffer(
+                self.uniform_ring[image_num].clone(),
+                self.global_uniform_buffer.clone(),
+            )
+            .map_err(|e| FrameError::CommandBufferBuild(e.to_string()))?;
+
+        // Data of the objects drawn on their own, from their slots of the image
+        for item in &list.items {
+            if let DrawItem::Object(obj) = item {
+                lock(obj).upload_frame_data(image_num, Some(&mut builder))?;
+            }
+        }
 
         // Initialize Command Buffer with the Render Pass
         builder
             .begin_render_pass(
                 self.get_swapchain().framebuffers[image_num].clone(),
                 SubpassContents::Inline,
-                vec![[0.0, 0.0, 0.0, 1.0].into()],
+                iter::once(<[f32; 4]>::from(self.clear_color).into()),
             )
-            .expect("Couldn't begin Vulkan Render Pass");
+            .map_err(|e| FrameError::RenderPassBegin(e.to_string()))?;
+
+        // The buffers of the recording are kept for the next frames, even when it fails
+        let mut batch = mem::take(&mut self.batch);
+        let recorded = self.record_objects(list, &mut batch, &mut builder);
+        batch.clear();
+        self.batch = batch;
+        recorded?;
+
+        // Build Command Buffer
+        builder
+            .end_render_pass()
+            .map_err(|e| FrameError::RenderPassEnd(e.to_string()))?;
+        if let Some(timer) = &self.gpu_timer {
+            timer.end_pass(&mut builder, 0)?;
+        }
+
+        // Keep a copy of the frame to show while the window is being resized
+        if let Some(last_frame) = self.get_swapchain().last_frame.clone().filter(|_| keep) {
+            let image = self.get_swapchain().images[image_num].clone();
+            let dimensions = image.dimensions();
+            builder
+                .copy_image(
+                    image,
+                    [0, 0, 0],
+                    0,
+                    0,
+                    last_frame,
+                    [0, 0, 0],
+                    0,
+                    0,
+                    [dimensions[0], dimensions[1], 1],
+                    1,
+                )
+                .map_err(|e| FrameError::CommandBufferBuild(e.to_string()))?;
+        }
+        if let Some(timer) = &self.gpu_timer {
+            timer.end_pass(&mut builder, 1)?;
+        }
+
+        builder
+            .build()
+            .map_err(|e| FrameError::CommandBufferBuild(e.to_string()))
+    }
+
+    /// Record a single draw for every quad merged in the batch
+    fn flush_batch(
+        &mut self,
+        batch: &mut DrawBatch,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), FrameError> {
+        if batch.vertices.is_empty() {
+            return Ok(());
+        }
+
+        self.counters.draw_calls += 1;
+        self.counters.vertices += batch.vertices.len() as u32;
+        self.counters.batches += 1;
+
+        let texture = batch
+            .texture
+            .take()
+            .unwrap_or_else(|| self.white_texture.clone());
+        let set = self.get_batch_descriptor_set(texture)?;
+
+        let vertices = self
+            .batch_vertex_pool
+            .chunk(batch.vertices.drain(..))
+            .map_err(|e| FrameError::Draw(e.to_string()))?;
+        let indices = self
+            .batch_index_pool
+            .chunk(batch.indices.drain(..))
+            .map_err(|e| FrameError::Draw(e.to_string()))?;
+
+        builder
+            .draw_indexed(
+                self.batch_pipeline.clone(),
+                self.swapchain.get_dynamic_state(),
+                vertices,
+                indices,
+                set,
+                (),
+                vec![],
+            )
+            .map_err(|e| FrameError::Draw(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Collect the draws of the visible objects in `list`, merging what can be batched.
+    /// The objects are only read here, the frame is then recorded from the list
+    fn collect_draws(&mut self, list: &mut DrawList) {
+        list.clear();
+        let view = self.view_bounds();
+        self.collect_occluders(&view, &mut list.occluders);
+        list.signature = self.draw_list_signature(&list.occluders);
+
+        // Filter all visible DrawObjects, leaving out the ones outside of the view or hidden
+        for obj in &self.draw_objects {
+            let o = lock(obj);
+            if !o.read_flags().contains(DrawFlags::VISIBLE) {
+                continue;
+            }
+
+            if self.is_in_view(&*o, &view) && !self.is_occluded(&*o, &list.occluders) {
+                list.visible.push(obj.clone());
+            } else {
+                list.culled_objects += 1;
+            }
+        }
+        list.drawn_objects = list.visible.len() as u32;
+
+        // Recorded vertices would go stale when reusing Command Buffers
+        let batching = self.batch_draws && !self.reuse_command_buffers;
+
+        let mut visible = mem::take(&mut list.visible);
+        if batching {
+            // Order isn't defined inside the same z index, group the objects by texture
+            visible.sort_unstable_by_key(|o| {
+                let o = lock(o);
+                (o.get_z_index(), o.batch_key())
+            });
+        }
+
+        for obj in visible.drain(..) {
+            let o = lock(&obj);
+
+            // Without batching, the objects with a pipeline of their own keep drawing with it
+            let own_pipeline = !batching && o.batch_quad().is_some();
+            if self.reuse_command_buffers || own_pipeline {
+                drop(o);
+                list.items.push(DrawItem::Object(obj));
+                continue;
+            }
+
+            let first_quad = list.quads.len();
+            if o.batch_quads(&view, &mut list.quads) {
+                list.items
+                    .push(DrawItem::Quads(list.quads.len() - first_quad));
+            } else {
+                list.quads.truncate(first_quad);
+
+                let index = list.mesh_count;
+                if o.batch_mesh(&view, list.next_mesh()) {
+                    list.mesh_count += 1;
+                    list.items.push(DrawItem::Mesh(index));
+                } else {
+                    drop(o);
+                    list.items.push(DrawItem::Object(obj));
+                    continue;
+                }
+            }
+
+            if batching {
+                list.batched_objects += 1;
+            }
+        }
+        list.visible = visible;
+    }
+
+    /// Record the draws of the list and the overlay, in the render pass
+    fn record_objects(
+        &mut self,
+        list: &mut DrawList,
+        batch: &mut DrawBatch,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), FrameError> {
+        self.counters.drawn_objects += list.drawn_objects;
+        self.counters.culled_objects += list.culled_objects;
+        self.counters.batched_objects += list.batched_objects;
+
+        let batching = self.batch_draws && !self.reuse_command_buffers;
+
+        let mut quads = list.quads.drain(..);
+        for item in &list.items {
+            match item {
+                DrawItem::Quads(count) if batching => {
+                    for quad in quads.by_ref().take(*count) {
+                        if !batch.accepts(&quad.texture) {
+                            self.flush_batch(batch, builder)?;
+                        }
+                        batch.push(quad);
+                    }
+                }
+                DrawItem::Quads(count) => {
+                    let quads = quads.by_ref().take(*count).collect();
+                    self.draw_quads(quads, builder)?;
+                }
+                DrawItem::Mesh(index) if batching => {
+                    let mesh = &list.meshes[*index];
+                    if !batch.accepts(&mesh.texture) {
+                        self.flush_batch(batch, builder)?;
+                    }
+                    batch.push_mesh(mesh);
+                }
+                DrawItem::Mesh(index) => self.draw_mesh(&list.meshes[*index], builder)?,
+                DrawItem::Object(obj) => {
+                    // Keep the drawing order by flushing what came before
+                    self.flush_batch(batch, builder)?;
+                    lock(obj).draw(self, builder)?;
+                }
+            }
+        }
+        drop(quads);
+        // The objects drawn on their own can be dropped before the next frame
+        list.clear();
+
+        if self.camera.overlay.a > 0.0 {
+            let view = self.view_bounds();
+            let overlay = BatchQuad::new(
+                None,
+                view.center(),
+                view.half_extents(),
+                self.camera.overlay.into(),
+            );
+            if !batch.accepts(&overlay.texture) {
+                self.flush_batch(batch, builder)?;
+            }
+            batch.push(overlay);
+        }
+        self.flush_batch(batch, builder)
+    }
+
+    /// Draw quads through the batch pipeline, for objects made of many quads when batching is off
+    pub(crate) fn draw_quads(
+        &mut self,
+        quads: Vec<BatchQuad>,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), FrameError> {
+        let mut batch = DrawBatch::default();
+
+        for quad in quads {
+            if !batch.accepts(&quad.texture) {
+                self.flush_batch(&mut batch, builder)?;
+            }
+            batch.push(quad);
+        }
+        self.flush_batch(&mut batch, builder)
+    }
 
-        // Filter all visible DrawObjects
-        let cloned_list = self.draw_objects.clone();
-        for obj in cloned_list
+    /// Draw triangles through the batch pipeline, for meshes drawn on their own when batching is off
+    pub(crate) fn draw_mesh(
+        &mut self,
+        mesh: &BatchMesh,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), FrameError> {
+        let mut batch = DrawBatch::default();
+
+        batch.push_mesh(mesh);
+        self.flush_batch(&mut batch, builder)
+    }
+
+    /// Descriptor Set binding a texture to the batch pipeline, cached per texture
+    fn get_batch_descriptor_set(
+        &mut self,
+        texture: Texture,
+    ) -> Result<Arc<BatchDescriptorSet>, FrameError> {
+        let key = Arc::as_ptr(&texture) as usize;
+
+        if let Some(set) = self.batch_sets.get(&key) {
+            return Ok(set.clone());
+        }
+
+        // Forget the sets of textures which may not be used anymore
+        if self.batch_sets.len() > 256 {
+            self.batch_sets.clear();
+        }
+
+        let layout = self
+            .batch_pipeline
+            .layout()
+            .descriptor_set_layout(0)
+            .ok_or_else(|| FrameError::DescriptorSet(String::from("Batch layout missing")))?;
+        let set = Arc::new(
+            PersistentDescriptorSet::start(layout.clone())
+                .add_sampled_image(texture, self.batch_sampler.clone())
+                .map_err(|e| FrameError::DescriptorSet(e.to_string()))?
+                .add_buffer(self.get_global_uniform_buffer())
+                .map_err(|e| FrameError::DescriptorSet(e.to_string()))?
+                .build()
+                .map_err(|e| FrameError::DescriptorSet(e.to_string()))?,
+        );
+
+        self.batch_sets.insert(key, set.clone());
+        Ok(set)
+    }
+
+    /// Check if an object can be seen in `view`, always true when culling is off
+    fn is_in_view(&self, obj: &dyn Draw, view: &Aabb) -> bool {
+        if !self.cull_objects {
+            return true;
+        }
+
+        obj.cull_bounds().map_or(true, |bounds| bounds.intersects(view))
+    }
+
+    /// Areas hiding what's drawn before them, from the visible opaque objects in `view`
+    fn collect_occluders(&self, view: &Aabb, occluders: &mut Vec<(u8, Aabb)>) {
+        occluders.clear();
+        if !self.cull_objects {
+            return;
+        }
+
+        for obj in &self.draw_objects {
+            let obj = lock(obj);
+            if !obj.read_flags().contains(DrawFlags::VISIBLE) {
+                continue;
+            }
+            if let Some(bounds) = obj.occluder_bounds().filter(|b| b.intersects(view)) {
+                occluders.push((obj.get_z_index(), bounds));
+            }
+        }
+    }
+
+    /// Check if an object tested for occlusion is entirely covered by an occluder of a higher z index.
+    /// Objects of the same z index have no defined order, they never hide each other
+    fn is_occluded(&self, obj: &dyn Draw, occluders: &[(u8, Aabb)]) -> bool {
+        if !self.cull_objects || !obj.read_flags().contains(DrawFlags::OCCLUSION_TEST) {
+            return false;
+        }
+
+        let bounds = match obj.cull_bounds() {
+            Some(bounds) => bounds,
+            None => return false,
+        };
+        let z_index = obj.get_z_index();
+        occluders
             .iter()
-            .filter(|o| o.borrow().read_flags().contains(DrawFlags::VISIBLE))
-        {
-            // Draw object if visible
-            obj.borrow_mut().draw(self, &mut builder);
+            .any(|(occluder_z, occluder)| *occluder_z > z_index && occluder.encloses(&bounds))
+    }
+
+    /// Hash of the draw list content and visibility, changing whenever the recorded commands would
+    fn draw_list_signature(&self, occluders: &[(u8, Aabb)]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+
+        let view = self.view_bounds();
+        let mut follows_view = false;
+        for obj in &self.draw_objects {
+            (Arc::as_ptr(obj) as *const () as usize).hash(&mut hasher);
+            let obj = lock(obj);
+            let drawn = obj.read_flags().contains(DrawFlags::VISIBLE)
+                && self.is_in_view(&*obj, &view)
+                && !self.is_occluded(&*obj, occluders);
+            drawn.hash(&mut hasher);
+            follows_view |= obj.follows_view();
+        }
+
+        // The overlay covers the view with its color
+        let overlay: [f32; 4] = self.camera.overlay.into();
+        for value in &overlay {
+            value.to_bits().hash(&mut hasher);
         }
+        follows_view |= self.camera.overlay.a > 0.0;
+
+        // Objects built from the view go stale as soon as the camera moves
+        if follows_view {
+            for value in &[view.min.x, view.min.y, view.max.x, view.max.y] {
+                value.to_bits().hash(&mut hasher);
+            }
+        }
+
+        hasher.finish()
+    }
+
+    /// Check if the last frame drawn was copied (see `FrameData::keep`)
+    pub fn has_last_frame(&self) -> bool {
+        self.swapchain.frame_kept
+    }
+
+    /// Present the last rendered frame letterboxed in the window, used while the swapchain waits to be rebuilt.
+    /// Nothing is presented if the last frame wasn't kept
+    pub fn present_last_frame(&mut self, frame: &FrameData) -> Result<(), FrameError> {
+        let last_frame = match self.get_swapchain().last_frame.clone() {
+            Some(last_frame) if self.has_last_frame() => last_frame,
+            _ => return Ok(()),
+        };
+
+        // Nothing to do until the swapchain is rebuilt if it can't be presented anymore
+        let (image_num, acquire_future) = match self.acquire_image()? {
+            Some(acquired) => acquired,
+            None => return Ok(()),
+        };
+
+        let recorded = self
+            .record_last_frame(image_num, last_frame, frame.size)
+            .map(Arc::new);
+        self.submit_or_clear(acquire_future, recorded, image_num)
+    }
+
+    /// Command Buffer blitting the last frame to a swapchain image, letterboxed in the window
+    fn record_last_frame(
+        &self,
+        image_num: usize,
+        last_frame: Arc<AttachmentImage>,
+        window_size: (u32, u32),
+    ) -> Result<PrimaryAutoCommandBuffer, FrameError> {
+        let target = self.swapchain.images[image_num].clone();
+        let frame_dimensions = last_frame.dimensions();
+        let (top_left, bottom_right) =
+            letterbox(frame_dimensions, target.dimensions(), window_size);
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.get_device(),
+            self.queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .map_err(|e| FrameError::CommandBufferCreation(e.to_string()))?;
+
+        builder
+            .clear_color_image(target.clone(), [0.0, 0.0, 0.0, 1.0].into())
+            .map_err(|e| FrameError::CommandBufferBuild(e.to_string()))?
+            .blit_image(
+                last_frame,
+                [0, 0, 0],
+                [frame_dimensions[0] as i32, frame_dimensions[1] as i32, 1],
+                0,
+                0,
+                target,
+                top_left,
+                bottom_right,
+                0,
+                0,
+                1,
+                Filter::Linear,
+            )
+            .map_err(|e| FrameError::CommandBufferBuild(e.to_string()))?;
+
+        builder
+            .build()
+            .map_err(|e| FrameError::CommandBufferBuild(e.to_string()))
+    }
+
+    /// Read back the last rendered frame as RGBA8 pixels, waits for the GPU.
+    /// None if the frame wasn't kept (see `FrameData::keep`)
+    pub fn capture_last_frame(&mut self) -> Result<Option<(Vec<u8>, u32, u32)>, FrameError> {
+        let last_frame = match self.get_swapchain().last_frame.clone() {
+            Some(last_frame) if self.has_last_frame() => last_frame,
+            _ => return Ok(None),
+        };
+        let [width, height] = last_frame.dimensions();
 
-        // Build Command Buffer
+        let buffer = CpuAccessibleBuffer::from_iter(
+            self.get_device(),
+            BufferUsage::transfer_destination(),
+            false,
+            (0..width * height * 4).map(|_| 0u8),
+        )
+        .map_err(|e| FrameError::CommandBufferCreation(e.to_string()))?;
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.get_device(),
+            self.queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .map_err(|e| FrameError::CommandBufferCreation(e.to_string()))?;
         builder
-            .end_render_pass()
-            .expect("Couldn't properly end Vulkan Render Pass");
+            .copy_image_to_buffer(last_frame.clone(), buffer.clone())
+            .map_err(|e| FrameError::CommandBufferBuild(e.to_string()))?;
         let command_buffer = builder
             .build()
-            .expect("Couldn't build Vulkan Command Buffer");
+            .map_err(|e| FrameError::CommandBufferBuild(e.to_string()))?;
 
-        // Run Command Buffer and obtain Future
-        let future = self
-            .previous_frame_end
-            .take()
-            .unwrap()
+        // Copied once the frames in flight are drawn
+        let device = self.get_device();
+        self.frame_sync
+            .previous_future(device)
+            .then_execute(self.queue.clone(), command_buffer)
+            .map_err(|e| FrameError::Execution(e.to_string()))?
+            .then_signal_fence_and_flush()
+            .map_err(flush_error)?
+            .wait(None)
+            .map_err(flush_error)?;
+
+        let mut pixels = buffer
+            .read()
+            .map_err(|e| FrameError::Execution(e.to_string()))?
+            .to_vec();
+
+        // Swapchains are usually BGRA
+        if matches!(
+            self.get_swapchain().chain.format(),
+            Format::B8G8R8A8Unorm | Format::B8G8R8A8Srgb
+        ) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        Ok(Some((pixels, width, height)))
+    }
+
+    /// Execute a frame's Command Buffer and present the result
+    fn submit(
+        &mut self,
+        acquire_future: SwapchainAcquireFuture<SurfaceWindow>,
+        command_buffer: Arc<PrimaryAutoCommandBuffer>,
+        image_num: usize,
+    ) -> Result<(), FrameError> {
+        // Run Command Buffer after the previous frame, without waiting for it on the CPU
+        let device = self.get_device();
+        let execution = self
+            .frame_sync
+            .previous_future(device)
             .join(acquire_future)
             .then_execute(self.queue.clone(), command_buffer)
-            .expect("Couldn't execute Vulkan Command Buffer")
+            .map_err(|e| FrameError::Execution(e.to_string()))?;
+        let future = execution
             .then_swapchain_present(
                 self.queue.clone(),
                 self.get_swapchain().chain.clone(),
                 image_num,
             )
+            .boxed_send_sync()
             .then_signal_fence_and_flush();
 
-        // Check the Future's output
+        // Check the Future's output, the fence is waited before drawing to the image again
         match future {
-            Ok(future) => {
-                // If the GPU is stuck rendering for too long terminate the program
-                future
-                    .wait(Some(std::time::Duration::from_secs(10)))
-                    .expect("GPU Timeout, terminating the program");
-                self.previous_frame_end = Some(future.boxed());
+            Ok(fence) => {
+                self.frame_sync.submitted(image_num, Some(Arc::new(fence)));
+                Ok(())
             }
             // Not a real error, may happen with weird Window resizing
             Err(FlushError::OutOfDate) => {
                 self.get_swapchain().set_recreate(true);
-                self.previous_frame_end = Some(sync::now(self.get_device()).boxed());
+                self.frame_sync.submitted(image_num, None);
+                Ok(())
             }
             // Couldn't flush the future, big problem, pls fix yourself
             Err(e) => {
-                eprintln!("Failed to flush Vulkan Future: {:?}", e);
-                self.previous_frame_end = Some(sync::now(self.get_device()).boxed());
+                self.frame_sync.submitted(image_num, None);
+                Err(flush_error(e))
+            }
+        }
+    }
+
+    /// Prepare the GPU resources otherwise created lazily during the first frames.
+    /// Pipelines are already built at creation through the pipeline cache (see `EngineConfig::pipeline_cache`),
+    /// compiled by the driver only the first time: only pools and cached sets are left.
+    pub fn warmup(&mut self) {
+        // Descriptor Set used by every untextured batch
+        let white_texture = self.white_texture.clone();
+        if let Err(e) = self.get_batch_descriptor_set(white_texture) {
+            crash::log(&format!("Warmup skipped the batch Descriptor Set: {}", e));
+        }
+
+        // Host visible memory allocated before the first uploads, kept with the handler
+        let staging = CpuAccessibleBuffer::from_iter(
+            self.get_device(),
+            BufferUsage::all(),
+            false,
+            (0..WARMUP_STAGING_SIZE).map(|_| 0u8),
+        );
+        match staging {
+            Ok(staging) => self.warmup_staging = Some(staging),
+            Err(e) => crash::log(&format!("Couldn't preallocate Vulkan staging memory: {}", e)),
+        }
+
+        // Six indices for the four vertices of every quad
+        let reserved = self
+            .batch_vertex_pool
+            .reserve(WARMUP_BATCH_VERTICES)
+            .and_then(|_| self.batch_index_pool.reserve(WARMUP_BATCH_VERTICES / 4 * 6));
+        if let Err(e) = reserved {
+            crash::log(&format!("Couldn't preallocate Vulkan batch buffers: {}", e));
+        }
+    }
+
+    /// Write the compiled pipelines to the file of the pipeline cache, read by the next startups
+    pub fn save_pipeline_cache(&self) -> Result<(), String> {
+        pipeline_cache::save(&self.pipeline_cache)
+    }
+
+    /// Copy the state of every DrawObject and the camera
+    pub fn snapshot(&self) -> RenderSnapshot {
+        let objects = self
+            .draw_objects
+            .iter()
+            .map(|o| (o.clone(), lock(o).get_state()))
+            .collect();
+
+        RenderSnapshot {
+            objects,
+            camera_position: self.camera.position,
+            camera_scale: self.camera.scale,
+            camera_rotation: self.camera.rotation,
+        }
+    }
+
+    /// Go back to a snapshot: objects created after it are removed, destroyed ones are brought back.
+    /// Brought back objects have no user handle anymore and stay owned by the renderer.
+    pub fn restore(&mut self, snapshot: &RenderSnapshot) {
+        for obj in &self.draw_objects {
+            let in_snapshot = snapshot.objects.iter().any(|(o, _)| Arc::ptr_eq(o, obj));
+            if !in_snapshot {
+                lock(obj).set_dead();
+            }
+        }
+
+        self.draw_objects = snapshot
+            .objects
+            .iter()
+            .map(|(obj, state)| {
+                lock(obj).set_state(state);
+                obj.clone()
+            })
+            .collect();
+        self.sort_draw_objects();
+
+        self.camera.position = snapshot.camera_position;
+        self.camera.scale = snapshot.camera_scale;
+        self.camera.rotation = snapshot.camera_rotation;
+    }
+
+    /// Drop every pending GPU work and rebuild the swapchain on the next frame
+    pub fn reset(&mut self) {
+        self.frame_sync.clear();
+        self.get_swapchain().set_recreate(true);
+    }
+
+    /// Start again on a new device after this one was lost (driver reset, GPU switched or removed).
+    /// The textures in use are uploaded again, from their file or from their pixels for the generated ones,
+    /// then every DrawObject creates its GPU resources again: the ones which can't are removed
+    pub fn recreate_device(&mut self, window: &Window) -> Result<DeviceRecovery, MagmaError> {
+        let mut handler = GraphicsHandler::new(window)?;
+        let mut recovery = DeviceRecovery {
+            textures: HashMap::new(),
+            same_device: false,
+            lost_objects: 0,
+        };
+
+        {
+            let uploaded = lock(&self.uploaded_textures);
+            let generated = lock(&self.texture_pixels);
+            for UploadedTexture { path, texture, dimensions } in uploaded.iter() {
+                let lost = match texture.upgrade() {
+                    Some(texture) => texture,
+                    None => continue,
+                };
+
+                let uploaded = match generated.get(path) {
+                    Some(pixels) => handler.create_texture(path, pixels, dimensions.x, dimensions.y),
+                    None => pack::read(path)
+                        .map_err(|e| format!("Couldn't read texture '{}': {}", path.display(), e))
+                        .and_then(|bytes| decode_png_rgba(Cursor::new(bytes)))
+                        .and_then(|(pixels, width, height)| handler.create_texture(path, &pixels, width, height)),
+                };
+                match uploaded {
+                    Ok((texture, dimensions)) => {
+                        recovery
+                            .textures
+                            .insert(Arc::as_ptr(&lost) as usize, (lost, texture, dimensions));
+                    }
+                    Err(e) => crash::log(&format!("Couldn't recreate texture after Device loss: {}", e)),
+                }
+            }
+        }
+
+        for object in self.draw_objects.drain(..) {
+            if !lock(&object).read_flags().contains(DrawFlags::USED) {
+                continue;
+            }
+
+            let recreated = lock(&object).recreate(&handler, &recovery);
+            match recreated {
+                Ok(()) => handler.draw_objects.push(object),
+                Err(e) => {
+                    crash::log(&format!("Couldn't recreate a DrawObject after Device loss: {}", e));
+                    lock(&object).set_dead();
+                    recovery.lost_objects += 1;
+                }
             }
         }
+        handler.sort_draw_objects();
+
+        handler.batch_draws = self.batch_draws;
+        handler.cull_objects = self.cull_objects;
+        handler.reuse_command_buffers = self.reuse_command_buffers;
+        handler.camera = self.camera;
+        handler.clear_color = self.clear_color;
+        handler.set_present_mode(self.get_present_mode());
+
+        // Everything of the lost device is released here
+        *self = handler;
+
+        Ok(recovery)
+    }
 
-        // Clean the GpuFuture (unlock blocked memory and free remainings)
-        self.previous_frame_end.as_mut().unwrap().cleanup_finished();
+    /// Change how frames are queued for presentation, applied by rebuilding the swapchain on the next frame.
+    /// Returns false if the surface doesn't support the mode (Fifo is always available)
+    pub fn set_present_mode(&mut self, mode: PresentMode) -> bool {
+        self.swapchain.request_present_mode(mode)
+    }
+
+    pub fn get_present_mode(&self) -> PresentMode {
+        self.swapchain.get_present_mode()
+    }
+
+    /// Draw calls and vertices of the last rendered frame
+    pub fn get_render_counters(&self) -> RenderCounters {
+        self.counters
+    }
+
+    /// GPU time of the passes of the last frame, empty if the GPU can't measure them
+    pub fn get_gpu_pass_times(&self) -> &[GpuPassTime] {
+        &self.gpu_pass_times
     }
 
     /// Sorter for the DrawObjects
     fn sort_draw_objects(&mut self) {
-        self.draw_objects.sort_by(|a, b| {
-            a.borrow_mut()
-                .get_z_index()
-                .cmp(&b.borrow_mut().get_z_index())
-        });
+        self.draw_objects.sort_by_key(|o| lock(o).get_z_index());
     }
 
     /// Getter for the used Swapchain
@@ -378,17 +1818,37 @@ impl GraphicsHandler {
         self.global_uniform_buffer.clone()
     }
 
-    /// Flusher for the global uniform buffer
-    fn flush_global_data(&self) {
-        let mut write_lock = self
-            .global_uniform_buffer
-            .write()
-            .expect("Couldn't write global GPU buffer");
-        let global_data = write_lock.deref_mut();
+    /// Global data of the current view
+    fn global_data(&self) -> GlobalUniformData {
+        let (sin, cos) = self.camera.rotation.0.sin_cos();
 
-        global_data.window_size = self.window_size.extend(0).extend(0);
-        global_data.camera_position = self.camera_position.extend(0.0).extend(0.0);
-        global_data.camera_scale = self.camera_scale.extend(0.0).extend(0.0);
+        GlobalUniformData {
+            window_size: self.window_size.extend(0).extend(0),
+            camera_position: self.camera.position.extend(0.0).extend(0.0),
+            camera_scale: self.camera.scale.extend(0.0).extend(0.0),
+            camera_rotation: Vector4::new(cos, sin, 0.0, 0.0),
+        }
+    }
+
+    /// Write the global data of the frame in the ring slot of its swapchain image, the ring
+    /// grows with the number of images
+    fn write_global_data(&mut self, image_num: usize) -> Result<(), FrameError> {
+        while self.uniform_ring.len() <= image_num {
+            let slot = CpuAccessibleBuffer::from_data(
+                self.get_device(),
+                BufferUsage::transfer_source(),
+                false,
+                self.global_data(),
+            )
+            .map_err(|e| FrameError::BufferWrite(e.to_string()))?;
+            self.uniform_ring.push(slot);
+        }
+
+        let mut write_lock = self.uniform_ring[image_num]
+            .write()
+            .map_err(|e| FrameError::BufferWrite(e.to_string()))?;
+        *write_lock = self.global_data();
+        Ok(())
     }
 
     /// Create a new Immutable Vertex Buffer
@@ -413,25 +1873,74 @@ impl GraphicsHandler {
         )
         .unwrap();
         future.flush().unwrap();
+
+        leaks::track_shared(&buffer, ResourceKind::IndexBuffer, &format!("{} indices", indices.len()));
         buffer
     }
 
-    /// Create a new SpriteObject
-    pub fn new_sprite(&mut self, texture_path: &str, z_index: u8) -> SpriteObject {
-        let sprite = Rc::new(RefCell::new(Sprite::new(texture_path, self, z_index)));
+    /// Create a new SpriteObject, failing with `MagmaError::Asset` if the texture is missing or invalid
+    pub fn new_sprite(&mut self, texture_path: &str, z_index: u8) -> Result<SpriteObject, MagmaError> {
+        let sprite = Sprite::new(texture_path, self, z_index).map_err(MagmaError::Asset)?;
+        let sprite = Arc::new(Mutex::new(sprite));
+
+        self.append_draw_object(sprite.clone());
+
+        Ok(SpriteObject::new(sprite))
+    }
+
+    /// Create a new SpriteObject from an already loaded texture
+    pub fn new_sprite_from_texture(&mut self, texture: Texture, dimensions: Vector2<u32>, label: &str, z_index: u8) -> Result<SpriteObject, MagmaError> {
+        let sprite = Sprite::from_texture(texture, dimensions, label, self, z_index)
+            .map_err(|e| MagmaError::Graphics(e.to_string()))?;
+        let sprite = Arc::new(Mutex::new(sprite));
 
         self.append_draw_object(sprite.clone());
 
-        SpriteObject::new(sprite)
+        Ok(SpriteObject::new(sprite))
+    }
+
+    /// Create a new NineSliceObject, sized like its texture until changed
+    pub fn new_nine_slice(&mut self, texture_path: &str, borders: SliceBorders, z_index: u8) -> Result<NineSliceObject, String> {
+        let (texture, dimensions) = self.load_texture(texture_path)?;
+
+        Ok(self.new_nine_slice_from_texture(texture, dimensions, borders, texture_path, z_index))
+    }
+
+    /// Create a new NineSliceObject from an already loaded texture
+    pub fn new_nine_slice_from_texture(&mut self, texture: Texture, dimensions: Vector2<u32>, borders: SliceBorders, label: &str, z_index: u8) -> NineSliceObject {
+        self.add_draw_object(NineSlice::new(texture, dimensions, borders, label, z_index))
+    }
+
+    /// Create a new ParallaxObject repeating its texture horizontally
+    pub fn new_parallax_layer(&mut self, texture_path: &str, scroll_factor: Vector2<f32>, z_index: u8) -> Result<ParallaxObject, String> {
+        let (texture, dimensions) = self.load_texture(texture_path)?;
+
+        Ok(self.new_parallax_layer_from_texture(texture, dimensions, scroll_factor, texture_path, z_index))
+    }
+
+    /// Create a new ParallaxObject from an already loaded texture
+    pub fn new_parallax_layer_from_texture(&mut self, texture: Texture, dimensions: Vector2<u32>, scroll_factor: Vector2<f32>, label: &str, z_index: u8) -> ParallaxObject {
+        self.add_draw_object(ParallaxLayer::new(texture, dimensions, scroll_factor, label, z_index))
     }
 
     /// Create a new rectangular PrimitiveObject
-    pub fn new_rectangle(&mut self, scale: Vector2<f32>, color: Vector4<f32>, global_position: Vector2<f32>, z_index: u8) -> PrimitiveObject {
-        let primitive = Rc::new(RefCell::new(Primitive::rectangle(scale, color, global_position, self, z_index)));
+    pub fn new_rectangle(&mut self, scale: Vector2<f32>, color: impl Into<Color>, global_position: Vector2<f32>, z_index: u8) -> Result<PrimitiveObject, MagmaError> {
+        let primitive = Primitive::rectangle(scale, color.into().into(), global_position, self, z_index)
+            .map_err(|e| MagmaError::Graphics(e.to_string()))?;
+        let primitive = Arc::new(Mutex::new(primitive));
 
         self.append_draw_object(primitive.clone());
 
-        PrimitiveObject::new(primitive)
+        Ok(PrimitiveObject::new(primitive))
+    }
+
+    /// Register a DrawObject built outside of the handler (e.g. tilemap layers)
+    pub(crate) fn add_draw_object<O: Draw + 'static>(&mut self, object: O) -> GraphicObject<O> {
+        let object = Arc::new(Mutex::new(object));
+
+        self.append_draw_object(object.clone());
+
+        GraphicObject::new(object)
     }
 
     /// Append a new DrawObject to the draw_object vector for draw
@@ -445,54 +1954,226 @@ impl GraphicsHandler {
         &self,
         pipeline_name: &str,
         layout_number: usize,
-    ) -> PersistentDescriptorSetBuilder<()> {
+    ) -> Result<PersistentDescriptorSetBuilder<()>, FrameError> {
         let pipeline = self.get_pipeline(pipeline_name);
         let layout = pipeline
             .layout()
             .descriptor_set_layout(layout_number)
-            .expect("Couldn't use Descriptor Set Layout");
-        PersistentDescriptorSet::start(layout.clone())
+            .ok_or_else(|| {
+                FrameError::DescriptorSet(format!(
+                    "{} pipeline has no layout {}",
+                    pipeline_name, layout_number
+                ))
+            })?;
+        Ok(PersistentDescriptorSet::start(layout.clone()))
     }
 
-    /// Bind a texture to a new Immutable Descriptor Set
-    pub fn create_and_bind_texture<R>(
-        &self,
-        texture_path: &str,
-        desc_set_builder: PersistentDescriptorSetBuilder<R>,
-        sampler: Arc<Sampler>,
-    ) -> (
-        DescriptorSetWithImage<R>,
-        Vector2<u32>,
-    ) {
-        let decoder = png::Decoder::new(File::open(texture_path).unwrap());
-        let (info, mut reader) = decoder.read_info().unwrap();
+    /// Load a texture from a PNG file. Every call uploads it again,
+    /// textures used in many places are shared by loading them with `ctx.load_texture`
+    pub fn load_texture(&self, texture_path: &str) -> Result<(Texture, Vector2<u32>), String> {
+        let path = Path::new(texture_path);
+
+        warmup::record(AssetKind::Texture, path);
+
+        let bytes = pack::read(path).map_err(|e| format!("Couldn't open texture '{}': {}", texture_path, e))?;
+        let decoder = png::Decoder::new(Cursor::new(bytes));
+        let (info, mut reader) = decoder
+            .read_info()
+            .map_err(|e| format!("Couldn't decode texture '{}': {}", texture_path, e))?;
 
         let mut buf = vec![0; info.buffer_size()];
 
-        reader.next_frame(&mut buf).unwrap();
+        reader
+            .next_frame(&mut buf)
+            .map_err(|e| format!("Couldn't decode texture '{}': {}", texture_path, e))?;
 
+        self.create_texture(path, &buf, info.width, info.height)
+    }
+
+    /// Upload already decoded RGBA8 pixels, reloaded from the given path when needed
+    pub fn create_texture(&self, path: &Path, pixels: &[u8], width: u32, height: u32) -> Result<(Texture, Vector2<u32>), String> {
         let dimensions = ImageDimensions::Dim2d {
-            width: info.width,
-            height: info.height,
+            width,
+            height,
             array_layers: 1,
         };
         let (image, future) = ImmutableImage::from_iter(
-            buf.iter().cloned(),
+            pixels.iter().cloned(),
             dimensions,
             MipmapsCount::One,
             Format::R8G8B8A8Srgb,
             self.get_queue(),
         )
-        .unwrap();
+        .map_err(|e| format!("Couldn't upload texture '{}': {}", path.display(), e))?;
+
+        let (texture, _tex_future) = (ImageView::new(image as TextureImage).unwrap(), future);
+        let dimensions = Vector2::new(width, height);
+        leaks::track_shared(&texture, ResourceKind::Texture, &path.display().to_string());
+
+        let mut uploaded = lock(&self.uploaded_textures);
+        uploaded.retain(|u| u.texture.strong_count() > 0);
+        uploaded.push(UploadedTexture {
+            path: path.to_path_buf(),
+            texture: Arc::downgrade(&texture),
+            dimensions,
+        });
+
+        let mut generated = lock(&self.texture_pixels);
+        generated.retain(|path, _| uploaded.iter().any(|u| &u.path == path));
+        if !path.is_file() && !pack::is_packed(path) {
+            generated.insert(path.to_path_buf(), pixels.to_vec());
+        }
+
+        Ok((texture, dimensions))
+    }
+
+    /// Files of the textures still alive, the generated ones excluded
+    pub(crate) fn texture_files(&self) -> Vec<PathBuf> {
+        let uploaded = lock(&self.uploaded_textures);
+        let generated = lock(&self.texture_pixels);
+
+        let mut files: Vec<PathBuf> = uploaded
+            .iter()
+            .filter(|u| u.texture.strong_count() > 0 && !generated.contains_key(&u.path))
+            .map(|u| u.path.clone())
+            .collect();
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    /// Upload the new version of a texture loaded from `path`, then recreate the DrawObjects so the ones
+    /// drawing a previous version (e.g. the sprites created from the path) get new descriptor sets
+    pub fn reload_texture(&self, path: &Path, pixels: &[u8], width: u32, height: u32) -> Result<(Texture, Vector2<u32>), String> {
+        let previous: Vec<Texture> = lock(&self.uploaded_textures)
+            .iter()
+            .filter(|u| u.path == path)
+            .filter_map(|u| u.texture.upgrade())
+            .collect();
+        let (texture, dimensions) = self.create_texture(path, pixels, width, height)?;
+        if previous.is_empty() {
+            return Ok((texture, dimensions));
+        }
+
+        let mut replacement = DeviceRecovery {
+            textures: HashMap::new(),
+            same_device: true,
+            lost_objects: 0,
+        };
+        for previous in previous {
+            replacement
+                .textures
+                .insert(Arc::as_ptr(&previous) as usize, (previous, texture.clone(), dimensions));
+        }
+
+        for object in self.draw_objects.iter() {
+            if let Err(e) = lock(object).recreate(self, &replacement) {
+                crash::log(&format!("Couldn't update a DrawObject with '{}': {}", path.display(), e));
+            }
+        }
+
+        Ok((texture, dimensions))
+    }
+
+    /// Texture updated with `update_streaming_texture`, its pixels are undefined until then.
+    /// It's not uploaded again when the device is lost: the objects drawing it are removed
+    pub fn create_streaming_texture(&self, width: u32, height: u32) -> Result<StreamingTexture, String> {
+        let usage = ImageUsage {
+            sampled: true,
+            transfer_destination: true,
+            ..ImageUsage::none()
+        };
+        let image = StorageImage::with_usage(
+            self.get_device(),
+            ImageDimensions::Dim2d {
+                width,
+                height,
+                array_layers: 1,
+            },
+            Format::R8G8B8A8Srgb,
+            usage,
+            ImageCreateFlags::none(),
+            iter::once(self.queue.family()),
+        )
+        .map_err(|e| format!("Couldn't create streaming texture: {}", e))?;
+        let texture = ImageView::new(image.clone() as TextureImage)
+            .map_err(|e| format!("Couldn't create streaming texture view: {}", e))?;
+        leaks::track_shared(&texture, ResourceKind::Texture, &format!("Streaming texture {}x{}", width, height));
+
+        Ok(StreamingTexture {
+            image,
+            texture,
+            staging: CpuBufferPool::upload(self.get_device()),
+            dimensions: Vector2::new(width, height),
+        })
+    }
 
-        let (texture, _tex_future) = (ImageView::new(image).unwrap(), future);
+    /// Replace the RGBA8 pixels of a streaming texture, copied on the GPU after the frames in flight
+    /// and before the next one. Doesn't wait for the GPU
+    pub fn update_streaming_texture(&mut self, texture: &StreamingTexture, pixels: &[u8]) -> Result<(), String> {
+        let size = texture.dimensions.x as usize * texture.dimensions.y as usize * 4;
+        if pixels.len() != size {
+            return Err(format!(
+                "Streaming texture expects {} bytes of pixels, got {}",
+                size,
+                pixels.len()
+            ));
+        }
 
-        (
-            desc_set_builder
-                .add_sampled_image(texture, sampler)
-                .expect("Couldn't add Sampled Image to Descriptor Set"),
-            Vector2::new(info.width, info.height),
+        let staging = texture
+            .staging
+            .chunk(pixels.iter().cloned())
+            .map_err(|e| format!("Couldn't stage streaming texture pixels: {}", e))?;
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.get_device(),
+            self.queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
         )
+        .map_err(|e| format!("Couldn't create streaming texture upload: {}", e))?;
+        builder
+            .copy_buffer_to_image(staging, texture.image.clone())
+            .map_err(|e| format!("Couldn't record streaming texture upload: {}", e))?;
+        let command_buffer = builder
+            .build()
+            .map_err(|e| format!("Couldn't build streaming texture upload: {}", e))?;
+
+        let device = self.get_device();
+        let upload = self
+            .frame_sync
+            .previous_future(device)
+            .then_execute(self.queue.clone(), command_buffer)
+            .map_err(|e| format!("Couldn't upload streaming texture: {}", e))?;
+        self.frame_sync.uploaded(upload.boxed_send_sync());
+
+        Ok(())
+    }
+
+    /// Bind a texture to a new Immutable Descriptor Set
+    pub fn bind_texture<R>(
+        &self,
+        texture: Texture,
+        desc_set_builder: PersistentDescriptorSetBuilder<R>,
+        sampler: Arc<Sampler>,
+    ) -> Result<DescriptorSetWithImage<R>, FrameError> {
+        desc_set_builder
+            .add_sampled_image(texture, sampler)
+            .map_err(|e| FrameError::DescriptorSet(e.to_string()))
+    }
+
+    /// Load a texture and bind it to a new Immutable Descriptor Set
+    pub fn create_and_bind_texture<R>(
+        &self,
+        texture_path: &str,
+        desc_set_builder: PersistentDescriptorSetBuilder<R>,
+        sampler: Arc<Sampler>,
+    ) -> Result<(DescriptorSetWithImage<R>, Vector2<u32>, Texture), String> {
+        let (texture, dimensions) = self.load_texture(texture_path)?;
+
+        let set = self
+            .bind_texture(texture.clone(), desc_set_builder, sampler)
+            .map_err(|e| e.to_string())?;
+
+        Ok((set, dimensions, texture))
     }
 
     /// Create a Texture Sampler to bind Textures to
@@ -516,17 +2197,30 @@ impl GraphicsHandler {
 
 /// Type to hold swapchain and corresponding images
 pub struct SwapchainHandler {
-    chain: Arc<Swapchain<Sendable<Rc<WindowContext>>>>,
-    images: Vec<Arc<SwapchainImage<Sendable<Rc<WindowContext>>>>>,
+    chain: Arc<Swapchain<SurfaceWindow>>,
+    images: Vec<Arc<SwapchainImage<SurfaceWindow>>>,
     framebuffers: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
+    /// Copy of the last frame kept, None when the surface can't copy the swapchain images
+    last_frame: Option<Arc<AttachmentImage>>,
+    /// The last frame drawn was copied in `last_frame`
+    frame_kept: bool,
+    /// Usage of the images, the transfers aren't supported everywhere
+    usage: ImageUsage,
+    /// Incremented at every recreation, anything recorded for older images is invalid
+    generation: u64,
+    present_modes: SupportedPresentModes,
+    /// Present mode to switch to at the next recreation
+    requested_present_mode: Option<PresentMode>,
     must_recreate: bool,
     dynamic_state: Box<DynamicState>,
 }
 
 impl SwapchainHandler {
     fn new(
-        swapchain: Arc<Swapchain<Sendable<Rc<WindowContext>>>>,
-        images: Vec<Arc<SwapchainImage<Sendable<Rc<WindowContext>>>>>,
+        swapchain: Arc<Swapchain<SurfaceWindow>>,
+        images: Vec<Arc<SwapchainImage<SurfaceWindow>>>,
+        usage: ImageUsage,
+        present_modes: SupportedPresentModes,
         render_pass: Arc<RenderPass>,
     ) -> Self {
         let mut dynamic_state = Box::new(DynamicState {
@@ -540,43 +2234,58 @@ impl SwapchainHandler {
 
         let framebuffers =
             window_size_dependent_setup(&images[..], render_pass, dynamic_state.as_mut());
+        let last_frame = create_last_frame_image(&swapchain, usage);
 
         Self {
             chain: swapchain,
             images,
             framebuffers,
+            last_frame,
+            frame_kept: false,
+            usage,
+            generation: 0,
+            present_modes,
+            requested_present_mode: None,
             must_recreate: false,
             dynamic_state,
         }
     }
 
-    fn check_and_recreate(&mut self, window: &Window, pass: Arc<RenderPass>) -> Result<(), ()> {
+    /// Rebuild the swapchain if needed, returns false if it can't be done yet (retry on the next frame)
+    fn check_and_recreate(&mut self, drawable_size: (u32, u32), pass: Arc<RenderPass>) -> Result<bool, FrameError> {
         if self.must_recreate {
-            let dimensions: [u32; 2] = {
-                let size = window.size();
-                [size.0, size.1]
+            let dimensions = [drawable_size.0, drawable_size.1];
+
+            let present_mode = self
+                .requested_present_mode
+                .unwrap_or_else(|| self.chain.present_mode());
+
+            let (new_swapchain, new_images) = match self
+                .chain
+                .recreate()
+                .dimensions(dimensions)
+                .present_mode(present_mode)
+                .build()
+            {
+                Ok(r) => r,
+                Err(SwapchainCreationError::UnsupportedDimensions) => return Ok(false),
+                Err(SwapchainCreationError::DeviceLost) => return Err(FrameError::DeviceLost),
+                Err(e) => return Err(FrameError::SwapchainRecreation(e.to_string())),
             };
 
-            let (new_swapchain, new_images) =
-                match self.chain.recreate().dimensions(dimensions).build() {
-                    Ok(r) => r,
-                    Err(SwapchainCreationError::UnsupportedDimensions) => return Err(()),
-                    Err(e) => panic!("Failed to recreate swapchain: {:?}", e),
-                };
-
             self.chain = new_swapchain;
             self.images = new_images;
 
             let framebuffers =
                 window_size_dependent_setup(&self.images[..], pass, &mut self.dynamic_state);
             self.framebuffers = framebuffers;
+            self.last_frame = create_last_frame_image(&self.chain, self.usage);
+            self.frame_kept = false;
+            self.generation += 1;
+            self.requested_present_mode = None;
             self.must_recreate = false;
         }
-        Ok(())
-    }
-
-    fn get_recreate(&self) -> bool {
-        self.must_recreate
+        Ok(true)
     }
 
     fn set_recreate(&mut self, new_value: bool) {
@@ -586,6 +2295,24 @@ impl SwapchainHandler {
     pub fn get_dynamic_state(&mut self) -> &mut DynamicState {
         self.dynamic_state.as_mut()
     }
+
+    fn get_present_mode(&self) -> PresentMode {
+        self.requested_present_mode
+            .unwrap_or_else(|| self.chain.present_mode())
+    }
+
+    /// Switch present mode at the next recreation, false if the surface doesn't support it
+    fn request_present_mode(&mut self, mode: PresentMode) -> bool {
+        if !self.present_modes.supports(mode) {
+            return false;
+        }
+
+        if mode != self.get_present_mode() {
+            self.requested_present_mode = Some(mode);
+            self.must_recreate = true;
+        }
+        true
+    }
 }
 
 /// Struct to hold vertex data
@@ -595,7 +2322,127 @@ pub struct Vertex {
 }
 vulkano::impl_vertex!(Vertex, vert_pos);
 
+impl From<Vec2> for Vertex {
+    fn from(position: Vec2) -> Self {
+        Self {
+            vert_pos: position.into(),
+        }
+    }
+}
+
+/// Vertex of a batched quad, already in world space
+#[derive(Default, Copy, Clone, Debug)]
+pub struct BatchVertex {
+    pub world_pos: [f32; 2],
+    pub tex_coords: [f32; 2],
+    pub color: [f32; 4],
+}
+vulkano::impl_vertex!(BatchVertex, world_pos, tex_coords, color);
+
+/// Draws of a frame, taken from the objects by `prepare_frame` on the thread updating them.
+/// The frame is recorded from this copy only: the objects can change while it's being drawn.
+/// Swapped with the render thread every frame, so the buffers are never allocated again
+#[derive(Default)]
+pub(crate) struct DrawList {
+    items: Vec<DrawItem>,
+    /// Quads of every `DrawItem::Quads`, in order
+    quads: Vec<BatchQuad>,
+    /// Only the first `mesh_count` meshes are used, the others are kept for the next frames
+    meshes: Vec<BatchMesh>,
+    mesh_count: usize,
+    /// Visible objects, emptied once the draws are collected
+    visible: Vec<DrawObject<dyn Draw>>,
+    /// Areas covered by opaque objects in view, with their z index
+    occluders: Vec<(u8, Aabb)>,
+    /// Hash of the draw list content and visibility, changing whenever the recorded commands would
+    signature: u64,
+    drawn_objects: u32,
+    culled_objects: u32,
+    batched_objects: u32,
+}
+
+impl DrawList {
+    fn clear(&mut self) {
+        self.items.clear();
+        self.quads.clear();
+        self.mesh_count = 0;
+        self.visible.clear();
+        self.occluders.clear();
+        self.drawn_objects = 0;
+        self.culled_objects = 0;
+        self.batched_objects = 0;
+    }
+
+    /// Next unused mesh, emptied
+    fn next_mesh(&mut self) -> &mut BatchMesh {
+        if self.mesh_count == self.meshes.len() {
+            self.meshes.push(BatchMesh::default());
+        }
+        let mesh = &mut self.meshes[self.mesh_count];
+        mesh.clear();
+        mesh
+    }
+}
+
+/// Draw of a single object in a `DrawList`
+enum DrawItem {
+    /// Number of quads taken from the list
+    Quads(usize),
+    /// Index of the mesh in the list
+    Mesh(usize),
+    /// Object drawn on its own, from the data uploaded for the frame (see `Draw::upload_frame_data`)
+    Object(DrawObject<dyn Draw>),
+}
+
+/// Quads and meshes merged together because they share the same texture
+#[derive(Default)]
+struct DrawBatch {
+    texture: Option<Texture>,
+    vertices: Vec<BatchVertex>,
+    indices: Vec<u32>,
+}
+
+impl DrawBatch {
+    /// Check if a quad or mesh sampling the texture can be merged in this batch
+    fn accepts(&self, texture: &Option<Texture>) -> bool {
+        if self.vertices.is_empty() {
+            return true;
+        }
+
+        match (&self.texture, texture) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    fn push(&mut self, quad: BatchQuad) {
+        let base = self.vertices.len() as u32;
+
+        self.texture = quad.texture;
+        self.vertices.extend_from_slice(&quad.vertices);
+        self.indices
+            .extend_from_slice(&[base, base + 1, base + 2, base + 2, base + 3, base]);
+    }
+
+    fn push_mesh(&mut self, mesh: &BatchMesh) {
+        let base = self.vertices.len() as u32;
+
+        self.texture = mesh.texture.clone();
+        self.vertices.extend_from_slice(&mesh.vertices);
+        self.indices.extend(mesh.indices.iter().map(|index| base + index));
+    }
+
+    /// Forget what's left of a failed recording
+    fn clear(&mut self) {
+        self.texture = None;
+        self.vertices.clear();
+        self.indices.clear();
+    }
+}
+
 /// Simple struct to hold an array of vertices
+#[derive(Clone)]
 pub struct VertexArray {
     data: Vec<Vertex>,
 }
@@ -606,11 +2453,18 @@ impl From<Vec<Vertex>> for VertexArray {
     }
 }
 
+impl VertexArray {
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.data
+    }
+}
+
 /// Struct to hold a vertex buffer with data
 #[derive(Clone)]
 pub struct VertexBuffer {
     buffer: Arc<ImmutableBuffer<[Vertex]>>,
     indices: Arc<dyn TypedBufferAccess<Content = [u16]> + Send + Sync>,
+    _tracker: ResourceTracker,
 }
 
 impl VertexBuffer {
@@ -628,7 +2482,16 @@ impl VertexBuffer {
 
         future.flush().unwrap();
 
-        Ok(Self { buffer, indices })
+        let _tracker = ResourceTracker::new(
+            ResourceKind::VertexBuffer,
+            &format!("{} vertices", array.data.len()),
+        );
+
+        Ok(Self {
+            buffer,
+            indices,
+            _tracker,
+        })
     }
 
     pub fn get_vertices(&self) -> Arc<ImmutableBuffer<[Vertex]>> {
@@ -643,7 +2506,7 @@ impl VertexBuffer {
 /// Called during init and at every resize of the window
 /// There is no error handling, if something goes wrong here, panic is the best solution
 fn window_size_dependent_setup(
-    images: &[Arc<SwapchainImage<Sendable<Rc<WindowContext>>>>],
+    images: &[Arc<SwapchainImage<SurfaceWindow>>],
     render_pass: Arc<RenderPass>,
     dynamic_state: &mut DynamicState,
 ) -> Vec<Arc<dyn FramebufferAbstract + Send + Sync>> {
@@ -671,52 +2534,176 @@ fn window_size_dependent_setup(
         .collect::<Vec<_>>()
 }
 
-fn create_instance() -> Arc<Instance> {
-    let instance_extensions = InstanceExtensions::supported_by_core()
-        .expect("Couldn't obtain Vulkan Instance Extensions");
+/// Image holding a copy of the swapchain content, with the same size and format.
+/// None if the swapchain images can't be copied from and to
+fn create_last_frame_image(
+    swapchain: &Arc<Swapchain<SurfaceWindow>>,
+    usage: ImageUsage,
+) -> Option<Arc<AttachmentImage>> {
+    if !usage.transfer_source || !usage.transfer_destination {
+        return None;
+    }
+
+    let image = AttachmentImage::with_usage(
+        swapchain.device().clone(),
+        swapchain.dimensions(),
+        swapchain.format(),
+        ImageUsage {
+            transfer_source: true,
+            transfer_destination: true,
+            ..ImageUsage::none()
+        },
+    )
+    .expect("Couldn't create Vulkan Image for the last frame copy");
+    Some(image)
+}
+
+/// Region of the swapchain image the last frame must be blitted to, to keep its aspect ratio in the window.
+/// The swapchain image is stretched by the compositor to the window size, which is taken into account.
+fn letterbox(frame: [u32; 2], target: [u32; 2], window: (u32, u32)) -> ([i32; 3], [i32; 3]) {
+    let (frame_w, frame_h) = (frame[0] as f32, frame[1] as f32);
+    let (window_w, window_h) = (window.0.max(1) as f32, window.1.max(1) as f32);
+
+    // Size of the frame in window pixels
+    let scale = (window_w / frame_w).min(window_h / frame_h);
+    let (drawn_w, drawn_h) = (frame_w * scale, frame_h * scale);
+
+    // Back to swapchain image pixels
+    let width = drawn_w * target[0] as f32 / window_w;
+    let height = drawn_h * target[1] as f32 / window_h;
+
+    let x = (target[0] as f32 - width) / 2.0;
+    let y = (target[1] as f32 - height) / 2.0;
+
+    (
+        [x as i32, y as i32, 0],
+        [(x + width) as i32, (y + height) as i32, 1],
+    )
+}
+
+/// Blending multiplying the colors under the fragments by the fragment colors, keeping their alpha
+fn multiply_blending() -> AttachmentBlend {
+    AttachmentBlend {
+        enabled: true,
+        color_op: BlendOp::Add,
+        color_source: BlendFactor::DstColor,
+        color_destination: BlendFactor::Zero,
+        alpha_op: BlendOp::Add,
+        alpha_source: BlendFactor::Zero,
+        alpha_destination: BlendFactor::One,
+        mask_red: true,
+        mask_green: true,
+        mask_blue: true,
+        mask_alpha: true,
+    }
+}
+
+/// Vulkan library loaded by SDL2, so the instance and the surfaces of the windows come from
+/// the same implementation (MoltenVK on macOS when it's installed, see `VideoHandler::new`)
+struct SdlLoader {
+    get_instance_proc_addr: ash::vk::PFN_vkGetInstanceProcAddr,
+}
+
+unsafe impl Loader for SdlLoader {
+    fn get_instance_proc_addr(
+        &self,
+        instance: ash::vk::Instance,
+        name: *const c_char,
+    ) -> ash::vk::PFN_vkVoidFunction {
+        unsafe { (self.get_instance_proc_addr)(instance, name) }
+    }
+}
+
+fn create_instance(window: &Window) -> Result<Arc<Instance>, String> {
+    let get_instance_proc_addr = window
+        .subsystem()
+        .vulkan_get_proc_address_function()
+        .map_err(|e| format!("Couldn't load the Vulkan library: {}", e))?;
+    if get_instance_proc_addr.is_null() {
+        return Err(String::from("The Vulkan library has no vkGetInstanceProcAddr"));
+    }
+
+    let loader = FunctionPointers::new(Box::new(SdlLoader {
+        get_instance_proc_addr: unsafe { mem::transmute(get_instance_proc_addr) },
+    }) as Box<dyn Loader + Send + Sync>);
 
-    Instance::new(None, Version::V1_2, &instance_extensions, None)
-        .expect("Couldn't create a new Vulkan instance")
+    let instance_extensions = InstanceExtensions::supported_by_core_with_loader(&loader)
+        .map_err(|e| format!("Couldn't obtain Vulkan Instance Extensions: {}", e))?;
+
+    Instance::with_loader(loader, None, Version::V1_2, &instance_extensions, None)
+        .map_err(|e| format!("Couldn't create a new Vulkan instance: {}", e))
+}
+
+/// Window of a surface. The window stays on the main thread with its `VideoHandler` and the
+/// surface only keeps its id, so the `GraphicsHandler` can be moved to a render thread.
+/// The `VideoHandler` drops the `GraphicsHandler` before its window, the surface never outlives it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SurfaceWindow {
+    pub id: u32,
 }
 
 fn create_surface(
     instance: Arc<Instance>,
     window: &Window,
-) -> Arc<Surface<Sendable<Rc<WindowContext>>>> {
+) -> Result<Arc<Surface<SurfaceWindow>>, String> {
+    let instance_handle = instance
+        .internal_object()
+        .as_raw()
+        .try_into()
+        .map_err(|_| String::from("Vulkan Instance handle doesn't fit SDL2"))?;
     let surface_handle = window
-        .vulkan_create_surface(instance.internal_object().as_raw().try_into().unwrap())
-        .expect("Couldn't create a new surface from the Vulkan Instance");
+        .vulkan_create_surface(instance_handle)
+        .map_err(|e| format!("Couldn't create a new surface from the Vulkan Instance: {}", e))?;
     // Use the SDL2 surface from the Window as surface
     unsafe {
-        Arc::new(Surface::from_raw_surface(
+        Ok(Arc::new(Surface::from_raw_surface(
             instance,
             ash::vk::SurfaceKHR::from_raw(surface_handle),
-            Sendable::new(window.context()),
-        ))
+            SurfaceWindow { id: window.id() },
+        )))
     }
 }
 
 fn get_device(
     instance: &'_ Arc<Instance>,
-    surface: Arc<Surface<Sendable<Rc<WindowContext>>>>,
-) -> (PhysicalDevice<'_>, Arc<Device>, Arc<Queue>) {
+    surface: Arc<Surface<SurfaceWindow>>,
+) -> Result<(PhysicalDevice<'_>, Arc<Device>, Arc<Queue>), String> {
     let (physical_device, queue_family) = PhysicalDevice::enumerate(&instance)
         .filter_map(|p| {
             p.queue_families()
                 .find(|&q| q.supports_graphics() && surface.is_supported(q).unwrap_or(false))
                 .map(|q| (p, q))
         })
-        .min_by_key(|(p, _)| match p.properties().device_type.unwrap() {
-            PhysicalDeviceType::DiscreteGpu => 0,
-            PhysicalDeviceType::IntegratedGpu => 1,
-            PhysicalDeviceType::VirtualGpu => 2,
-            PhysicalDeviceType::Cpu => 3,
-            PhysicalDeviceType::Other => 4,
+        .min_by_key(|(p, _)| match p.properties().device_type {
+            Some(PhysicalDeviceType::DiscreteGpu) => 0,
+            Some(PhysicalDeviceType::IntegratedGpu) => 1,
+            Some(PhysicalDeviceType::VirtualGpu) => 2,
+            Some(PhysicalDeviceType::Cpu) => 3,
+            Some(PhysicalDeviceType::Other) | None => 4,
         })
-        .unwrap();
-
+        .ok_or("No Vulkan device can draw on the window")?;
+
+    // Written in the crash reports
+    let properties = physical_device.properties();
+    crash::set_info("GPU", properties.device_name.as_deref().unwrap_or("unknown"));
+    crash::set_info(
+        "GPU driver version",
+        &properties
+            .driver_version
+            .map_or_else(|| String::from("unknown"), |version| version.to_string()),
+    );
+    crash::set_info(
+        "Vulkan version",
+        &properties
+            .api_version
+            .map_or_else(|| String::from("unknown"), |version| version.to_string()),
+    );
+
+    // Must be enabled on the implementations which aren't fully conformant, as MoltenVK
+    let supported_ext = DeviceExtensions::supported_by_device(physical_device);
     let device_ext = DeviceExtensions {
         khr_swapchain: true,
+        khr_portability_subset: supported_ext.khr_portability_subset,
         ..DeviceExtensions::none()
     };
     let (device, mut queues) = Device::new(
@@ -725,48 +2712,82 @@ fn get_device(
         &device_ext,
         [(queue_family, 0.5)].iter().cloned(),
     )
-    .expect("Couldn't create Vulkan Device");
+    .map_err(|e| format!("Couldn't create Vulkan Device: {}", e))?;
 
-    (
+    Ok((
         physical_device,
         device,
-        queues.next().expect("Couldn't get first queue object"),
-    )
+        queues.next().ok_or("Couldn't get first queue object")?,
+    ))
 }
 
-type SdlSwapchain = Arc<Swapchain<Sendable<Rc<WindowContext>>>>;
-type SdlSwapchainImagesVector = Vec<Arc<SwapchainImage<Sendable<Rc<WindowContext>>>>>;
+type SdlSwapchain = Arc<Swapchain<SurfaceWindow>>;
+type SdlSwapchainImagesVector = Vec<Arc<SwapchainImage<SurfaceWindow>>>;
 
 fn create_raw_swapchain(
     window: &Window,
     device: Arc<Device>,
-    surface: Arc<Surface<Sendable<Rc<WindowContext>>>>,
+    surface: Arc<Surface<SurfaceWindow>>,
     physical: PhysicalDevice,
-) -> (
+) -> Result<(
     SdlSwapchain,
     SdlSwapchainImagesVector,
-) {
+    ImageUsage,
+    SupportedPresentModes,
+), String> {
     // Get all the device capabilities and limitations
     let caps = surface
         .capabilities(physical)
-        .expect("Couldn't obtain Vulkan Capabilities from Physical Device");
-    let alpha = caps.supported_composite_alpha.iter().next().unwrap();
-    let format = caps.supported_formats[0].0;
+        .map_err(|e| format!("Couldn't obtain Vulkan Capabilities from Physical Device: {}", e))?;
+    let alpha = caps
+        .supported_composite_alpha
+        .iter()
+        .next()
+        .ok_or("No composite alpha mode supported by the Vulkan surface")?;
+    // MoltenVK also lists extended color spaces and wider formats first, the usual 8-bit sRGB
+    // ones are preferred everywhere
+    let formats = &caps.supported_formats;
+    let (format, color_space) = formats
+        .iter()
+        .find(|(format, color_space)| {
+            *color_space == ColorSpace::SrgbNonLinear
+                && matches!(
+                    format,
+                    Format::B8G8R8A8Unorm
+                        | Format::B8G8R8A8Srgb
+                        | Format::R8G8B8A8Unorm
+                        | Format::R8G8B8A8Srgb
+                )
+        })
+        .or_else(|| formats.first())
+        .copied()
+        .ok_or("No format supported by the Vulkan surface")?;
 
     let buffers_count = match caps.max_image_count {
         None => max(2, caps.min_image_count),
         Some(limit) => min(max(2, caps.min_image_count), limit),
     };
     let dimensions: [u32; 2] = {
-        let size = window.size();
+        let size = window.vulkan_drawable_size();
         [size.0, size.1]
     };
-    Swapchain::start(device, surface)
+    // The copies of the frames need the transfers, optional for the surfaces
+    let supported = caps.supported_usage_flags;
+    let usage = ImageUsage {
+        color_attachment: true,
+        transfer_source: supported.transfer_source,
+        transfer_destination: supported.transfer_destination,
+        ..ImageUsage::none()
+    };
+    let (swapchain, images) = Swapchain::start(device, surface)
         .dimensions(dimensions)
-        .usage(ImageUsage::color_attachment())
+        .usage(usage)
         .format(format)
+        .color_space(color_space)
         .composite_alpha(alpha)
         .num_images(buffers_count)
         .build()
-        .expect("Couldn't build Vulkan Swapchain")
+        .map_err(|e| format!("Couldn't build Vulkan Swapchain: {}", e))?;
+
+    Ok((swapchain, images, usage, caps.present_modes))
 }
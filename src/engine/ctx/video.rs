@@ -1,45 +1,202 @@
 // standard imports
+use std::io::{Cursor, Read, Write};
+use std::mem;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 // SDL2 imports
-use sdl2::video::Window;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::surface::Surface;
+use sdl2::sys::SDL_WindowFlags;
+use sdl2::video::{FullscreenType, Window, WindowPos};
 use sdl2::{Sdl, VideoSubsystem};
 
 // vulkan implementation imports
-use super::vulkan::GraphicsHandler;
+use super::render_thread::RenderHandle;
+use super::vulkan::{CameraView, DeviceRecovery, FrameData, FrameError, GraphicsHandler};
+use vulkano::swapchain::PresentMode;
 
 // other imports
-use super::draw_objects::{SpriteObject, PrimitiveObject};
-use cgmath::{Vector2, Vector4};
+use super::draw_objects::{NineSliceObject, ParallaxObject, SliceBorders, SpriteObject, PrimitiveObject};
+use crate::engine::assets::{Handle, TextureAsset};
+use crate::engine::color::Color;
+use crate::engine::config::{WindowConfig, WindowPlacement};
+use crate::engine::error::MagmaError;
+use crate::engine::geometry::Rect;
+use crate::engine::lighting::{LightLayer, LightLayerObject};
+use crate::engine::math::Vec2;
+use crate::engine::memory::{self, MemoryTag};
+use crate::engine::pack;
+use cgmath::Vector2;
+#[cfg(feature = "serialization")]
+use serde::{Deserialize, Serialize};
+
+/// MoltenVK as installed by the Vulkan SDK or bundled with the game, found by the dynamic linker
+#[cfg(target_os = "macos")]
+const MOLTENVK_LIBRARY: &str = "libMoltenVK.dylib";
+
+/// Fullscreen state of the window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FullscreenMode {
+    Windowed,
+    /// Change the display mode to the window size
+    Exclusive,
+    /// Borderless window covering the whole desktop at its native resolution
+    Borderless,
+}
+
+/// Synchronization of the presented frames with the display refresh
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "lowercase"))]
+pub enum SwapInterval {
+    /// Wait for the vertical blank, no tearing
+    VSync,
+    /// Present as soon as possible, lowest latency but may tear
+    Immediate,
+    /// VSync, but late frames are presented immediately instead of waiting for the next blank
+    Adaptive,
+}
+
+impl Default for SwapInterval {
+    fn default() -> Self {
+        SwapInterval::VSync
+    }
+}
+
+/// Resolution and refresh rate supported by a display
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DisplayMode {
+    pub width: i32,
+    pub height: i32,
+    pub refresh_rate: i32,
+}
+
+/// Connected display with its position in the desktop and supported modes
+#[derive(Clone, Debug)]
+pub struct DisplayInfo {
+    pub index: usize,
+    pub name: String,
+    /// Position and size of the display in desktop coordinates (x, y, width, height)
+    pub bounds: (i32, i32, u32, u32),
+    pub desktop_mode: DisplayMode,
+    pub modes: Vec<DisplayMode>,
+}
 
 /// Component of the CtxHandler to handle all calls to graphic APIs
 pub struct VideoHandler {
     video_subsystem: VideoSubsystem,
+    /// Declared before the window to be dropped first, its surface only holds the window id
+    pub gl_handler: RenderHandle,
     window: Window,
-    pub gl_handler: GraphicsHandler,
 
     window_resized: bool,
+    /// Time and size of the last resize event still waiting to be applied
+    pending_resize: Option<(Instant, (u32, u32))>,
+    pub resize_debounce: Duration,
+    /// View of the next frame, set without waiting for the renderer
+    camera_view: Option<CameraView>,
+    /// Copy every frame drawn, e.g. to capture them in the tests (see `FrameData::keep`)
+    pub keep_frames: bool,
+    keep_next_frame: bool,
 }
 
 impl VideoHandler {
-    pub fn new(ctx: &Sdl) -> VideoHandler {
-        let video_subsystem = ctx.video().expect("Couldn't obtain SDL2 Video Subsystem");
-
-        let window = video_subsystem
-            .window("Rust Testing Grounds", 800, 600)
-            .position_centered()
-            .vulkan()
-            .resizable()
+    pub fn new(ctx: &Sdl, config: &WindowConfig) -> Result<VideoHandler, MagmaError> {
+        Self::open(ctx, config, None)
+    }
+
+    /// Open another window, rendered on the Vulkan Instance and Device of the `main` one
+    pub fn new_sharing(ctx: &Sdl, config: &WindowConfig, main: &VideoHandler) -> Result<VideoHandler, MagmaError> {
+        Self::open(ctx, config, Some(&*main.gl_handler))
+    }
+
+    fn open(ctx: &Sdl, config: &WindowConfig, main: Option<&GraphicsHandler>) -> Result<VideoHandler, MagmaError> {
+        let video_subsystem = ctx
+            .video()
+            .map_err(|e| MagmaError::Sdl(format!("Couldn't obtain SDL2 Video Subsystem: {}", e)))?;
+
+        // The Vulkan loaders since 1.3.216 only show MoltenVK to the instances asking for the
+        // portability devices, which vulkano can't ask for: SDL2 loads it directly when it's
+        // installed, the Vulkan loader is used otherwise
+        #[cfg(target_os = "macos")]
+        let _ = video_subsystem.vulkan_load_library(MOLTENVK_LIBRARY);
+
+        let mut window_builder =
+            video_subsystem.window(&config.title, config.width, config.height);
+        window_builder.vulkan();
+
+        match config.display {
+            Some(display) => {
+                let (x, y) = placement_position(
+                    &video_subsystem,
+                    display,
+                    config.placement,
+                    (config.width, config.height),
+                )
+                .map_err(|e| MagmaError::Config(format!("Couldn't place SDL2 Window on the requested display: {}", e)))?;
+                window_builder.position(x, y);
+            }
+            None => match config.placement {
+                WindowPlacement::Centered => {
+                    window_builder.position_centered();
+                }
+                WindowPlacement::At(x, y) => {
+                    window_builder.position(x, y);
+                }
+            },
+        }
+
+        if config.resizable {
+            window_builder.resizable();
+        }
+        if config.fullscreen {
+            window_builder.fullscreen_desktop();
+        }
+        if config.borderless {
+            window_builder.borderless();
+        }
+        if config.allow_highdpi {
+            window_builder.allow_highdpi();
+        }
+        if config.always_on_top {
+            let flags = window_builder.window_flags()
+                | SDL_WindowFlags::SDL_WINDOW_ALWAYS_ON_TOP as u32;
+            window_builder.set_window_flags(flags);
+        }
+
+        let mut window = window_builder
             .build()
-            .expect("Couldn't build SDL2 Window from Video Subsystem");
+            .map_err(|e| MagmaError::Sdl(format!("Couldn't build SDL2 Window from Video Subsystem: {}", e)))?;
 
-        let gl_handler = GraphicsHandler::new(&window);
+        if let Some((width, height)) = config.minimum_size {
+            window
+                .set_minimum_size(width, height)
+                .map_err(|e| MagmaError::Config(format!("Couldn't set SDL2 Window minimum size: {}", e)))?;
+        }
+        if let Some((width, height)) = config.maximum_size {
+            window
+                .set_maximum_size(width, height)
+                .map_err(|e| MagmaError::Config(format!("Couldn't set SDL2 Window maximum size: {}", e)))?;
+        }
 
-        VideoHandler {
+        let gl_handler = match main {
+            Some(main) => GraphicsHandler::new_sharing(&window, main)?,
+            None => GraphicsHandler::new(&window)?,
+        };
+        let gl_handler = RenderHandle::new(gl_handler);
+
+        Ok(VideoHandler {
             video_subsystem,
             window,
             gl_handler,
             window_resized: false,
-        }
+            pending_resize: None,
+            resize_debounce: config.resize_debounce,
+            camera_view: None,
+            keep_frames: false,
+            keep_next_frame: false,
+        })
     }
 
     fn get_window_resized(&self) -> bool {
@@ -49,20 +206,426 @@ impl VideoHandler {
         self.window_resized = new_value;
     }
 
-    pub fn new_sprite(&mut self, texture_path: &str, z_index: u8) -> SpriteObject {
+    /// Register a resize from the user, applied once the size stops changing
+    pub fn notify_resize(&mut self, width: u32, height: u32) {
+        match self.pending_resize {
+            // Same size as before, keep the original time to not delay the rebuild further
+            Some((_, size)) if size == (width, height) => {}
+            _ => self.pending_resize = Some((Instant::now(), (width, height))),
+        }
+    }
+
+    /// Check if a debounced resize is ready to be applied
+    fn take_settled_resize(&mut self) -> bool {
+        match self.pending_resize {
+            Some((time, _)) if time.elapsed() >= self.resize_debounce => {
+                self.pending_resize = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Check if the window is being resized right now
+    pub fn is_resizing(&self) -> bool {
+        self.pending_resize.is_some()
+    }
+
+    /// Get the text currently held by the OS clipboard
+    pub fn get_clipboard_text(&self) -> Result<String, String> {
+        self.video_subsystem.clipboard().clipboard_text()
+    }
+
+    /// Replace the OS clipboard content with the given text
+    pub fn set_clipboard_text(&self, text: &str) -> Result<(), String> {
+        self.video_subsystem.clipboard().set_clipboard_text(text)
+    }
+
+    /// Check if the OS clipboard holds any text
+    pub fn has_clipboard_text(&self) -> bool {
+        self.video_subsystem.clipboard().has_clipboard_text()
+    }
+
+    /// Switch the window between windowed and fullscreen modes
+    pub fn set_fullscreen(&mut self, mode: FullscreenMode) -> Result<(), String> {
+        let fullscreen_type = match mode {
+            FullscreenMode::Windowed => FullscreenType::Off,
+            FullscreenMode::Exclusive => FullscreenType::True,
+            FullscreenMode::Borderless => FullscreenType::Desktop,
+        };
+
+        self.window.set_fullscreen(fullscreen_type)?;
+
+        // The drawable size may have changed, the swapchain must follow
+        self.set_window_resized(true);
+
+        Ok(())
+    }
+
+    /// Get the current fullscreen state of the window
+    pub fn get_fullscreen(&self) -> FullscreenMode {
+        match self.window.fullscreen_state() {
+            FullscreenType::Off => FullscreenMode::Windowed,
+            FullscreenType::True => FullscreenMode::Exclusive,
+            FullscreenType::Desktop => FullscreenMode::Borderless,
+        }
+    }
+
+    /// Control tearing vs latency, by choosing the present mode of the swapchain
+    pub fn gl_set_swap_interval(&mut self, interval: SwapInterval) -> Result<(), String> {
+        let mode = match interval {
+            SwapInterval::VSync => PresentMode::Fifo,
+            SwapInterval::Immediate => PresentMode::Immediate,
+            SwapInterval::Adaptive => PresentMode::Relaxed,
+        };
+
+        if self.gl_handler.set_present_mode(mode) {
+            Ok(())
+        } else {
+            Err(format!("Swap interval {:?} isn't supported by the display surface", interval))
+        }
+    }
+
+    pub fn gl_get_swap_interval(&self) -> SwapInterval {
+        match self.gl_handler.get_present_mode() {
+            PresentMode::Immediate | PresentMode::Mailbox => SwapInterval::Immediate,
+            PresentMode::Relaxed => SwapInterval::Adaptive,
+            PresentMode::Fifo => SwapInterval::VSync,
+        }
+    }
+
+    /// List every connected display
+    pub fn get_displays(&self) -> Result<Vec<DisplayInfo>, String> {
+        let count = self.video_subsystem.num_video_displays()?;
+
+        (0..count)
+            .map(|i| {
+                let bounds = self.video_subsystem.display_bounds(i)?;
+                let desktop = self.video_subsystem.desktop_display_mode(i)?;
+
+                let mode_count = self.video_subsystem.num_display_modes(i)?;
+                let modes = (0..mode_count)
+                    .filter_map(|m| self.video_subsystem.display_mode(i, m).ok())
+                    .map(|m| DisplayMode {
+                        width: m.w,
+                        height: m.h,
+                        refresh_rate: m.refresh_rate,
+                    })
+                    .collect();
+
+                Ok(DisplayInfo {
+                    index: i as usize,
+                    name: self.video_subsystem.display_name(i)?,
+                    bounds: (bounds.x(), bounds.y(), bounds.width(), bounds.height()),
+                    desktop_mode: DisplayMode {
+                        width: desktop.w,
+                        height: desktop.h,
+                        refresh_rate: desktop.refresh_rate,
+                    },
+                    modes,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the index of the display the window is on
+    pub fn get_window_display(&self) -> Result<usize, String> {
+        self.window.display_index().map(|i| i as usize)
+    }
+
+    /// Move the window to a display
+    pub fn move_to_display(&mut self, display: usize, placement: WindowPlacement) -> Result<(), String> {
+        let (x, y) = placement_position(&self.video_subsystem, display, placement, self.window.size())?;
+
+        self.window
+            .set_position(WindowPos::Positioned(x), WindowPos::Positioned(y));
+
+        Ok(())
+    }
+
+    /// Identifier of the window, used to route its events
+    pub fn get_window_id(&self) -> u32 {
+        self.window.id()
+    }
+
+    /// Size of the window in logical units (points), used by the OS for placement
+    pub fn get_window_size(&self) -> (u32, u32) {
+        self.window.size()
+    }
+
+    /// Size of the window in physical pixels, used for the swapchain
+    pub fn get_drawable_size(&self) -> (u32, u32) {
+        self.window.vulkan_drawable_size()
+    }
+
+    /// Ratio between physical pixels and logical units (e.g. 2.0 on Retina displays)
+    pub fn get_scale_factor(&self) -> f32 {
+        let logical = self.get_window_size();
+        let physical = self.get_drawable_size();
+
+        if logical.0 == 0 {
+            return 1.0;
+        }
+        physical.0 as f32 / logical.0 as f32
+    }
+
+    /// Show or hide the window decorations
+    pub fn set_bordered(&mut self, bordered: bool) {
+        self.window.set_bordered(bordered);
+    }
+
+    /// Limit how small the user can resize the window
+    pub fn set_minimum_size(&mut self, width: u32, height: u32) -> Result<(), String> {
+        self.window
+            .set_minimum_size(width, height)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Limit how big the user can resize the window
+    pub fn set_maximum_size(&mut self, width: u32, height: u32) -> Result<(), String> {
+        self.window
+            .set_maximum_size(width, height)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Change the window title
+    pub fn set_window_title(&mut self, title: &str) -> Result<(), String> {
+        self.window.set_title(title).map_err(|e| e.to_string())
+    }
+
+    pub fn get_window_title(&self) -> &str {
+        self.window.title()
+    }
+
+    /// Set the window icon from a PNG file
+    pub fn set_window_icon_from_file(&mut self, path: &Path) -> Result<(), String> {
+        let bytes = pack::read(path)
+            .map_err(|e| format!("Couldn't open icon file '{}': {}", path.display(), e))?;
+
+        self.set_window_icon_from_reader(Cursor::new(bytes))
+    }
+
+    /// Set the window icon from PNG encoded bytes
+    pub fn set_window_icon_from_bytes(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.set_window_icon_from_reader(bytes)
+    }
+
+    fn set_window_icon_from_reader<R: Read>(&mut self, reader: R) -> Result<(), String> {
+        let (mut pixels, width, height) = decode_png_rgba(reader)?;
+
+        let surface = Surface::from_data(
+            &mut pixels,
+            width,
+            height,
+            width * 4,
+            PixelFormatEnum::RGBA32,
+        )?;
+
+        // SDL2 copies the surface, the pixels can be freed right after
+        self.window.set_icon(surface);
+
+        Ok(())
+    }
+
+    /// Sprite of a PNG file, uploaded for this sprite only: share a texture between sprites with
+    /// `ctx.load_texture` and `new_sprite_from_asset`
+    pub fn new_sprite(&mut self, texture_path: &str, z_index: u8) -> Result<SpriteObject, MagmaError> {
         self.gl_handler.new_sprite(texture_path, z_index)
     }
 
-    pub fn new_rectangle(&mut self, scale: Vector2<f32>, color: Vector4<f32>, global_position: Vector2<f32>, z_index: u8) -> PrimitiveObject {
+    pub fn new_sprite_from_asset(&mut self, texture: &Handle<TextureAsset>, z_index: u8) -> Result<SpriteObject, MagmaError> {
+        let label = texture.path().display().to_string();
+        self.gl_handler
+            .new_sprite_from_texture(texture.texture.clone(), texture.dimensions, &label, z_index)
+    }
+
+    /// Nine-slice panel of a texture, its borders keep their size whatever the panel size
+    pub fn new_nine_slice(&mut self, texture_path: &str, borders: SliceBorders, z_index: u8) -> Result<NineSliceObject, String> {
+        self.gl_handler.new_nine_slice(texture_path, borders, z_index)
+    }
+
+    pub fn new_nine_slice_from_asset(&mut self, texture: &Handle<TextureAsset>, borders: SliceBorders, z_index: u8) -> NineSliceObject {
+        let label = texture.path().display().to_string();
+        self.gl_handler
+            .new_nine_slice_from_texture(texture.texture.clone(), texture.dimensions, borders, &label, z_index)
+    }
+
+    /// Light map multiplied over the objects with lower z indices, dark until lights are added
+    pub fn new_light_layer(&mut self, z_index: u8) -> Result<LightLayerObject, MagmaError> {
+        let layer = LightLayer::new(&self.gl_handler, z_index)
+            .map_err(|e| MagmaError::Graphics(e.to_string()))?;
+        Ok(self.gl_handler.add_draw_object(layer))
+    }
+
+    /// Background layer scrolling by `scroll_factor` of the camera moves, drawn behind the objects of higher z index
+    pub fn new_parallax_layer(&mut self, texture_path: &str, scroll_factor: Vector2<f32>, z_index: u8) -> Result<ParallaxObject, String> {
+        self.gl_handler.new_parallax_layer(texture_path, scroll_factor, z_index)
+    }
+
+    pub fn new_parallax_layer_from_asset(&mut self, texture: &Handle<TextureAsset>, scroll_factor: Vector2<f32>, z_index: u8) -> ParallaxObject {
+        let label = texture.path().display().to_string();
+        self.gl_handler
+            .new_parallax_layer_from_texture(texture.texture.clone(), texture.dimensions, scroll_factor, &label, z_index)
+    }
+
+    pub fn new_rectangle(&mut self, scale: Vector2<f32>, color: impl Into<Color>, global_position: Vector2<f32>, z_index: u8) -> Result<PrimitiveObject, MagmaError> {
         self.gl_handler.new_rectangle(scale, color, global_position, z_index)
     }
 
-    /// Frame-by-frame update of the graphics and everything related
-    pub fn update(&mut self) {
-        let resized = self.get_window_resized();
+    /// Color of the background, behind every object
+    pub fn set_clear_color(&mut self, color: impl Into<Color>) {
+        self.gl_handler.set_clear_color(color);
+    }
+
+    /// Region of the window (in logical units) showing the rendered frame.
+    /// The whole window, except while a resize is pending: the last frame is then letterboxed
+    pub fn get_viewport(&self) -> Rect {
+        let (window_w, window_h) = self.get_window_size();
+        let (window_w, window_h) = (window_w as f32, window_h as f32);
+
+        if !self.is_resizing() {
+            return Rect::new(0.0, 0.0, window_w, window_h);
+        }
+
+        let frame = self.gl_handler.window_size;
+        let (frame_w, frame_h) = (frame.x.max(1) as f32, frame.y.max(1) as f32);
+        let scale = (window_w / frame_w).min(window_h / frame_h);
+        let size = Vec2::new(frame_w * scale, frame_h * scale);
+
+        Rect::new(
+            (window_w - size.x) / 2.0,
+            (window_h - size.y) / 2.0,
+            size.x,
+            size.y,
+        )
+    }
+
+    /// World position under a point of the window (e.g. the mouse cursor), through the viewport and the camera
+    pub fn screen_to_world(&self, point: Vec2) -> Vec2 {
+        let viewport = self.get_viewport();
+        let ndc = Vec2::new(
+            (point.x - viewport.x) / viewport.width * 2.0 - 1.0,
+            (point.y - viewport.y) / viewport.height * 2.0 - 1.0,
+        );
 
-        self.gl_handler.vulkan_loop(resized, &self.window);
+        self.gl_handler.ndc_to_world(ndc)
+    }
+
+    /// Point of the window showing a world position (e.g. to place UI over an object)
+    pub fn world_to_screen(&self, point: Vec2) -> Vec2 {
+        let viewport = self.get_viewport();
+        let ndc = self.gl_handler.world_to_ndc(point);
+
+        Vec2::new(
+            viewport.x + (ndc.x + 1.0) / 2.0 * viewport.width,
+            viewport.y + (ndc.y + 1.0) / 2.0 * viewport.height,
+        )
+    }
+
+    /// Camera of the next frame, applied when it's prepared (see `GraphicsHandler::set_camera_view`
+    /// to change the view right away)
+    pub fn set_camera_view(&mut self, view: CameraView) {
+        self.camera_view = Some(view);
+    }
+
+    /// Copy the next frame drawn, read back with `GraphicsHandler::capture_last_frame` once it's drawn
+    pub fn keep_next_frame(&mut self) {
+        self.keep_next_frame = true;
+    }
+
+    /// Frame-by-frame update of the graphics and everything related.
+    /// With a render thread, the errors of a frame are returned by the next update
+    pub fn update(&mut self) -> Result<(), FrameError> {
+        let _scope = memory::scope(MemoryTag::Graphics);
+        // Nothing is drawn after a failed frame, the error is handled first
+        self.gl_handler.finish_frame()?;
+
+        if self.take_settled_resize() {
+            self.set_window_resized(true);
+        } else if self.is_resizing() && self.gl_handler.has_last_frame() {
+            // Rebuilding the swapchain now would be wasted on the next resize event
+            return self
+                .gl_handler
+                .present_last_frame(&FrameData::new(&self.window, false));
+        }
+
+        let mut frame = FrameData::new(&self.window, self.get_window_resized());
+        frame.camera = self.camera_view.take();
+        // A resize just started, its frame is presented until the swapchain is rebuilt
+        frame.keep = self.keep_frames || self.is_resizing() || mem::take(&mut self.keep_next_frame);
+
+        let result = self.gl_handler.render(frame);
 
         self.set_window_resized(false);
+
+        result
     }
+
+    /// Start the renderer again on a new device after `FrameError::DeviceLost`, see `GraphicsHandler::recreate_device`
+    pub fn recreate_device(&mut self) -> Result<DeviceRecovery, MagmaError> {
+        let _scope = memory::scope(MemoryTag::Graphics);
+        self.gl_handler.recreate_device(&self.window)
+    }
+}
+
+/// Desktop coordinates of a window of the given size placed on a display
+fn placement_position(
+    video_subsystem: &VideoSubsystem,
+    display: usize,
+    placement: WindowPlacement,
+    window_size: (u32, u32),
+) -> Result<(i32, i32), String> {
+    let bounds = video_subsystem.display_bounds(display as i32)?;
+
+    Ok(match placement {
+        WindowPlacement::Centered => (
+            bounds.x() + (bounds.width() as i32 - window_size.0 as i32) / 2,
+            bounds.y() + (bounds.height() as i32 - window_size.1 as i32) / 2,
+        ),
+        WindowPlacement::At(x, y) => (bounds.x() + x, bounds.y() + y),
+    })
+}
+
+/// Decode a PNG image into tightly packed RGBA8 pixels, returning also width and height
+pub fn decode_png_rgba<R: Read>(reader: R) -> Result<(Vec<u8>, u32, u32), String> {
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+    let (info, mut reader) = decoder
+        .read_info()
+        .map_err(|e| format!("Couldn't read PNG info: {}", e))?;
+
+    let mut buf = vec![0; info.buffer_size()];
+    reader
+        .next_frame(&mut buf)
+        .map_err(|e| format!("Couldn't decode PNG frame: {}", e))?;
+
+    // After the expansion every pixel is 8-bit, only the channel count may differ
+    let pixels = match info.color_type {
+        png::ColorType::RGBA => buf,
+        png::ColorType::RGB => buf
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return Err(String::from("Unexpanded indexed PNG")),
+    };
+
+    Ok((pixels, info.width, info.height))
+}
+
+/// Encode tightly packed RGBA8 pixels as a PNG image
+pub fn encode_png_rgba<W: Write>(writer: W, pixels: &[u8], width: u32, height: u32) -> Result<(), String> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .map_err(|e| format!("Couldn't encode PNG: {}", e))
 }
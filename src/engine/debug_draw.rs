@@ -0,0 +1,262 @@
+//! Debug renderer drawing lines, shapes and outlines in world units
+//!
+//! Meant for gizmos and diagnostics (collider shapes, paths, bounds): the lines stay until
+//! `clear`, so the usual pattern is to clear and draw again every frame.
+
+// standard imports
+use std::f32::consts::PI;
+
+// vulkan imports
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+
+// import the engine modules
+use super::color::Color;
+use super::ctx::draw_objects::{BatchQuad, Draw, DrawFlags, DrawState, GraphicObject};
+use super::ctx::vulkan::{DeviceRecovery, FrameError, GraphicsHandler};
+use super::ctx::VideoHandler;
+use super::geometry::Aabb;
+use super::leaks::{ResourceKind, ResourceTracker};
+use super::math::{Vec2, Vec4};
+
+// other imports
+use cgmath::InnerSpace;
+
+/// Segments of the outlines of circles
+const CIRCLE_SEGMENTS: usize = 24;
+
+pub type DebugLinesObject = GraphicObject<DebugLines>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Line {
+    start: Vec2,
+    end: Vec2,
+    color: Color,
+}
+
+/// Lines drawn over the objects with lower z indices
+pub struct DebugLines {
+    lines: Vec<Line>,
+
+    z_index: u8,
+    draw_flags: DrawFlags,
+
+    /// Width of the lines, in world units
+    pub thickness: f32,
+    /// Multiplied with the colors of the lines
+    pub color: Vec4,
+
+    _tracker: ResourceTracker,
+}
+
+impl DebugLines {
+    /// Create the debug renderer of a window
+    pub fn create(video: &mut VideoHandler, z_index: u8) -> DebugLinesObject {
+        video.gl_handler.add_draw_object(Self::new(z_index))
+    }
+
+    pub fn new(z_index: u8) -> Self {
+        Self {
+            lines: Vec::new(),
+            z_index,
+            draw_flags: DrawFlags::USED | DrawFlags::VISIBLE,
+            thickness: 2.0,
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            _tracker: ResourceTracker::new(ResourceKind::DrawObject, "Debug lines"),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn line(&mut self, start: Vec2, end: Vec2, color: impl Into<Color>) {
+        self.lines.push(Line {
+            start,
+            end,
+            color: color.into(),
+        });
+    }
+
+    /// Lines linking the points, back to the first one if `closed`
+    pub fn polyline(&mut self, points: &[Vec2], closed: bool, color: impl Into<Color>) {
+        let color = color.into();
+
+        for pair in points.windows(2) {
+            self.line(pair[0], pair[1], color);
+        }
+        if closed && points.len() > 2 {
+            self.line(points[points.len() - 1], points[0], color);
+        }
+    }
+
+    pub fn rect(&mut self, aabb: &Aabb, color: impl Into<Color>) {
+        let corners = [
+            aabb.min,
+            Vec2::new(aabb.max.x, aabb.min.y),
+            aabb.max,
+            Vec2::new(aabb.min.x, aabb.max.y),
+        ];
+        self.polyline(&corners, true, color);
+    }
+
+    pub fn circle(&mut self, center: Vec2, radius: f32, color: impl Into<Color>) {
+        let points: Vec<Vec2> = (0..CIRCLE_SEGMENTS)
+            .map(|i| {
+                let angle = i as f32 / CIRCLE_SEGMENTS as f32 * 2.0 * PI;
+                center + Vec2::new(angle.cos(), angle.sin()) * radius
+            })
+            .collect();
+        self.polyline(&points, true, color);
+    }
+
+    /// Small cross marking a point
+    pub fn point(&mut self, position: Vec2, size: f32, color: impl Into<Color>) {
+        let color = color.into();
+        let half = size / 2.0;
+
+        self.line(
+            position - Vec2::new(half, half),
+            position + Vec2::new(half, half),
+            color,
+        );
+        self.line(
+            position - Vec2::new(half, -half),
+            position + Vec2::new(half, -half),
+            color,
+        );
+    }
+
+    /// Line with a head at the end
+    pub fn arrow(&mut self, start: Vec2, end: Vec2, color: impl Into<Color>) {
+        let color = color.into();
+        self.line(start, end, color);
+
+        let direction = end - start;
+        let length = direction.magnitude();
+        if length <= 0.0 {
+            return;
+        }
+
+        let back = -direction / length * (length * 0.2).min(self.thickness * 8.0);
+        let side = Vec2::new(-back.y, back.x) * 0.5;
+        self.line(end, end + back + side, color);
+        self.line(end, end + back - side, color);
+    }
+
+    fn push_quads(&self, view: Option<&Aabb>, quads: &mut Vec<BatchQuad>) {
+        let half_thickness = self.thickness / 2.0;
+
+        for line in &self.lines {
+            if let Some(view) = view {
+                let bounds = Aabb::new(line.start, line.end);
+                let bounds = Aabb::new(
+                    bounds.min - Vec2::new(half_thickness, half_thickness),
+                    bounds.max + Vec2::new(half_thickness, half_thickness),
+                );
+                if !view.intersects(&bounds) {
+                    continue;
+                }
+            }
+
+            let direction = line.end - line.start;
+            let length = direction.magnitude();
+            let side = if length > 0.0 {
+                Vec2::new(-direction.y, direction.x) / length * half_thickness
+            } else {
+                Vec2::new(0.0, half_thickness)
+            };
+
+            let color: Vec4 = line.color.into();
+            let color = Vec4::new(
+                color.x * self.color.x,
+                color.y * self.color.y,
+                color.z * self.color.z,
+                color.w * self.color.w,
+            );
+
+            // Same corner order as the quads built from a center
+            let mut quad = BatchQuad::new(None, line.start, Vec2::new(0.0, 0.0), color);
+            let corners = [
+                line.start - side,
+                line.start + side,
+                line.end + side,
+                line.end - side,
+            ];
+            for (vertex, corner) in quad.vertices.iter_mut().zip(corners.iter()) {
+                vertex.world_pos = [corner.x, corner.y];
+            }
+            quads.push(quad);
+        }
+    }
+}
+
+impl Draw for DebugLines {
+    fn draw(
+        &self,
+        gl_handler: &mut GraphicsHandler,
+        command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), FrameError> {
+        let mut quads = Vec::new();
+        self.push_quads(None, &mut quads);
+
+        gl_handler.draw_quads(quads, command_buffer)
+    }
+
+    fn get_z_index(&self) -> u8 {
+        self.z_index
+    }
+
+    fn flush_data(&self) {}
+
+    fn write_flags(&mut self) -> &mut DrawFlags {
+        &mut self.draw_flags
+    }
+
+    fn read_flags(&self) -> DrawFlags {
+        self.draw_flags
+    }
+
+    fn set_dead(&mut self) {
+        self.draw_flags.remove(DrawFlags::USED);
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        self.draw_flags.set(DrawFlags::VISIBLE, visible);
+    }
+
+    fn get_state(&self) -> DrawState {
+        DrawState {
+            color: self.color,
+            global_position: Vec2::new(0.0, 0.0),
+            scale: Vec2::new(1.0, 1.0),
+            flags: self.draw_flags,
+        }
+    }
+
+    fn set_state(&mut self, state: &DrawState) {
+        self.color = state.color;
+        self.draw_flags = state.flags;
+    }
+
+    /// Only made of batched quads, nothing to create
+    fn recreate(
+        &mut self,
+        _gl_handler: &GraphicsHandler,
+        _recovery: &DeviceRecovery,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn batch_quads(&self, view: &Aabb, quads: &mut Vec<BatchQuad>) -> bool {
+        self.push_quads(Some(view), quads);
+        true
+    }
+}
@@ -0,0 +1,473 @@
+//! Embedded HTTP debug server to inspect a running build from a browser on another device
+//!
+//! The connections are served on a worker thread, the engine publishes the `DebugData` every frame
+//! from the world, the `Console` resource and the log of `crash::log`.
+//!
+//! Routes (all JSON unless stated otherwise):
+//! - `GET /stats` frame statistics
+//! - `GET /entities` living entities of the world
+//! - `GET /cvars` console variables
+//! - `GET /logs` the most recent log lines
+//! - `GET /memory` memory usage per subsystem (needs the TrackingAllocator)
+//! - `GET /logs/stream` live log lines as a `text/event-stream`
+//! - `GET /screenshot` the last captured screenshot as PNG, a new one is captured for the next request
+//! - `GET /ws` WebSocket (with the `net` feature) pushing `{"log": line}` for every new log line,
+//!   and answering a text message naming one of the JSON routes with `{"route": name, "data": json}`
+
+// standard imports
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// import the IPC module for the shared snapshot
+use super::console::Console;
+use super::crash;
+use super::ctx::draw_objects::lock;
+use super::ctx::encode_png_rgba;
+use super::game::Context;
+use super::ipc::IpcSnapshot;
+use super::memory;
+#[cfg(feature = "net")]
+use super::net::websocket::{self, OPCODE_CLOSE, OPCODE_PING, OPCODE_PONG, OPCODE_TEXT};
+
+/// Maximum amount of log lines kept in memory
+const LOG_CAPACITY: usize = 512;
+/// Longest wait of the worker thread for new log lines before accepting the pending connections
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// Longest wait for the request head of a client, only stalls the worker thread
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+/// Biggest message accepted from the WebSocket clients, they only send route names
+#[cfg(feature = "net")]
+const MAX_WEBSOCKET_MESSAGE: usize = 4096;
+
+/// Data published by the engine for the debug server to expose
+#[derive(Default)]
+struct DebugData {
+    stats: IpcSnapshot,
+    entities: Vec<String>,
+    cvars: Vec<(String, String)>,
+    /// PNG encoded screenshot
+    screenshot: Option<Vec<u8>>,
+    logs: VecDeque<String>,
+}
+
+/// Browser connected to `/ws`
+#[cfg(feature = "net")]
+struct WebSocketClient {
+    stream: TcpStream,
+    /// Received bytes not making a whole frame yet
+    buffer: Vec<u8>,
+}
+
+/// HTTP server serving the DebugData from a worker thread
+pub struct DebugServer {
+    data: Arc<Mutex<DebugData>>,
+    /// New log lines for the live streams, None only while stopping the worker
+    logs: Option<Sender<String>>,
+    worker: Option<JoinHandle<()>>,
+    /// Set by the worker when a client asks for a screenshot
+    screenshot_requested: Arc<AtomicBool>,
+    /// The next frame is copied for the screenshot
+    frame_kept: bool,
+    /// Lines of the engine log already published
+    log_seen: u64,
+}
+
+impl DebugServer {
+    /// Start listening on the given address. `127.0.0.1:8080` is only reachable from this machine,
+    /// `0.0.0.0:8080` from the other devices of the network too, which can then read the game data
+    pub fn bind(address: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        let data = Arc::new(Mutex::new(DebugData::default()));
+        let screenshot_requested = Arc::new(AtomicBool::new(false));
+        let (logs, pending_logs) = mpsc::channel();
+        let mut worker = DebugWorker {
+            listener,
+            data: data.clone(),
+            screenshot_requested: screenshot_requested.clone(),
+            log_streams: Vec::new(),
+            #[cfg(feature = "net")]
+            websockets: Vec::new(),
+        };
+
+        let worker = thread::Builder::new()
+            .name(String::from("debug server"))
+            .spawn(move || worker.run(pending_logs))?;
+
+        Ok(Self {
+            data,
+            logs: Some(logs),
+            worker: Some(worker),
+            screenshot_requested,
+            frame_kept: false,
+            log_seen: 0,
+        })
+    }
+
+    /// Data served to the clients, the worker thread waits for the guard to be dropped
+    fn data(&self) -> MutexGuard<'_, DebugData> {
+        lock(&self.data)
+    }
+
+    /// Publish the state of the engine (called by the engine every frame)
+    pub(crate) fn update(&mut self, ctx: &mut Context, stats: IpcSnapshot) {
+        let (lines, log_seen) = crash::lines_since(self.log_seen);
+        self.log_seen = log_seen;
+        for line in lines.iter() {
+            self.log(line);
+        }
+
+        // Built before locking the data, the worker may be answering meanwhile
+        let entities = ctx
+            .world
+            .entities()
+            .map(|entity| format!("{:?}", entity))
+            .collect();
+        let cvars = ctx
+            .world
+            .resource::<Console>()
+            .map_or_else(Vec::new, |console| {
+                console
+                    .cvars()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect()
+            });
+        {
+            let mut data = self.data();
+            data.stats = stats;
+            data.entities = entities;
+            data.cvars = cvars;
+        }
+
+        // The frame asked for the last time is drawn, it's read now
+        if self.frame_kept {
+            self.frame_kept = false;
+            let captured = match ctx.try_video() {
+                Some(video) => video.gl_handler.capture_last_frame(),
+                None => Ok(None),
+            };
+            match captured {
+                Ok(Some((pixels, width, height))) => {
+                    let mut png = Vec::new();
+                    match encode_png_rgba(&mut png, &pixels, width, height) {
+                        Ok(()) => self.data().screenshot = Some(png),
+                        Err(e) => {
+                            crash::log(&format!("Couldn't encode the debug screenshot: {}", e))
+                        }
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => crash::log(&format!("Couldn't capture the debug screenshot: {}", e)),
+            }
+        }
+
+        if self.screenshot_requested.swap(false, Ordering::SeqCst) {
+            if let Some(video) = ctx.try_video() {
+                video.keep_next_frame();
+                self.frame_kept = true;
+            }
+        }
+    }
+
+    /// Add a line to the log history and to the live streams.
+    /// The lines of `crash::log` are already added by the engine
+    pub fn log(&mut self, line: &str) {
+        {
+            let mut data = self.data();
+            if data.logs.len() == LOG_CAPACITY {
+                data.logs.pop_front();
+            }
+            data.logs.push_back(line.to_string());
+        }
+
+        if let Some(logs) = &self.logs {
+            let _ = logs.send(line.to_string());
+        }
+    }
+}
+
+impl Drop for DebugServer {
+    /// Stop the worker, closing the connections
+    fn drop(&mut self) {
+        self.logs = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Connections of the debug server, owned by its worker thread
+struct DebugWorker {
+    listener: TcpListener,
+    data: Arc<Mutex<DebugData>>,
+    screenshot_requested: Arc<AtomicBool>,
+    log_streams: Vec<TcpStream>,
+    #[cfg(feature = "net")]
+    websockets: Vec<WebSocketClient>,
+}
+
+impl DebugWorker {
+    /// Serve the clients until the DebugServer is dropped
+    fn run(&mut self, pending_logs: Receiver<String>) {
+        let mut lines = Vec::new();
+        loop {
+            match pending_logs.recv_timeout(POLL_INTERVAL) {
+                Ok(line) => lines.push(line),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            lines.extend(pending_logs.try_iter());
+
+            self.poll(&lines);
+            lines.clear();
+        }
+    }
+
+    /// Serve pending requests and push new log lines to the live streams
+    fn poll(&mut self, lines: &[String]) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = self.serve(stream) {
+                        eprintln!("Debug server request failed: {}", e);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    eprintln!("Couldn't accept debug server connection: {}", e);
+                    break;
+                }
+            }
+        }
+
+        if !lines.is_empty() {
+            let mut events = String::new();
+            for line in lines.iter() {
+                events.push_str(&format!("data: {}\n\n", line));
+            }
+
+            self.log_streams
+                .retain(|mut s| s.write_all(events.as_bytes()).is_ok());
+        }
+
+        #[cfg(feature = "net")]
+        {
+            let clients = std::mem::take(&mut self.websockets);
+            for mut client in clients {
+                match self.serve_websocket(&mut client, lines) {
+                    Ok(true) => self.websockets.push(client),
+                    Ok(false) => {}
+                    Err(e) => eprintln!("Debug server WebSocket closed: {}", e),
+                }
+            }
+        }
+    }
+
+    /// Answer a new connection, its reads only block the worker thread
+    fn serve(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+        // Whole head, the WebSocket handshake needs the headers
+        let mut head = String::new();
+        let mut reader = BufReader::new(&stream);
+        loop {
+            let length = reader.read_line(&mut head)?;
+            if length == 0 || head.ends_with("\r\n\r\n") || head == "\r\n" {
+                break;
+            }
+        }
+
+        let mut parts = head.split_whitespace();
+        let method = parts.next().unwrap_or("");
+        let path = parts.next().unwrap_or("");
+
+        if method != "GET" {
+            return respond(&mut stream, "405 Method Not Allowed", "text/plain", b"");
+        }
+        if let Some(body) = self.json_route(path) {
+            return respond(&mut stream, "200 OK", "application/json", body.as_bytes());
+        }
+
+        match path {
+            "/logs/stream" => {
+                stream.write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nAccess-Control-Allow-Origin: *\r\n\r\n",
+                )?;
+                stream.set_nonblocking(true)?;
+                self.log_streams.push(stream);
+                Ok(())
+            }
+            #[cfg(feature = "net")]
+            "/ws" => {
+                let response = websocket::server_response(&head)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                stream.write_all(response.as_bytes())?;
+                stream.set_nonblocking(true)?;
+                self.websockets.push(WebSocketClient {
+                    stream,
+                    buffer: Vec::new(),
+                });
+                Ok(())
+            }
+            "/screenshot" => {
+                self.screenshot_requested.store(true, Ordering::SeqCst);
+
+                // Copied, the game thread can't publish while the image is sent
+                let screenshot = lock(&self.data).screenshot.clone();
+                match screenshot {
+                    Some(png) => respond(&mut stream, "200 OK", "image/png", &png),
+                    None => respond(
+                        &mut stream,
+                        "503 Service Unavailable",
+                        "text/plain",
+                        b"Capturing the screenshot, try again",
+                    ),
+                }
+            }
+            _ => respond(&mut stream, "404 Not Found", "text/plain", b"Unknown route"),
+        }
+    }
+
+    /// Answer the messages of a WebSocket client and push it the new log lines, false once it's closed
+    #[cfg(feature = "net")]
+    fn serve_websocket(&self, client: &mut WebSocketClient, lines: &[String]) -> io::Result<bool> {
+        let mut chunk = [0; 4096];
+        loop {
+            match client.stream.read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(length) => client.buffer.extend_from_slice(&chunk[..length]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        while let Some(frame) =
+            websocket::decode_frame(&client.buffer, MAX_WEBSOCKET_MESSAGE, true).map_err(invalid)?
+        {
+            client.buffer.drain(..frame.length);
+
+            match frame.opcode {
+                OPCODE_TEXT => {
+                    let route = String::from_utf8_lossy(&frame.payload);
+                    let route = route.trim();
+                    let reply = match self.json_route(route) {
+                        Some(data) => {
+                            format!("{{\"route\":{},\"data\":{}}}", json_string(route), data)
+                        }
+                        None => format!(
+                            "{{\"route\":{},\"error\":\"Unknown route\"}}",
+                            json_string(route)
+                        ),
+                    };
+                    send_frame(client, OPCODE_TEXT, reply.as_bytes())?;
+                }
+                OPCODE_PING => send_frame(client, OPCODE_PONG, &frame.payload)?,
+                OPCODE_CLOSE => {
+                    let _ = send_frame(client, OPCODE_CLOSE, &[]);
+                    return Ok(false);
+                }
+                _ => {}
+            }
+        }
+
+        for line in lines {
+            let message = format!("{{\"log\":{}}}", json_string(line));
+            send_frame(client, OPCODE_TEXT, message.as_bytes())?;
+        }
+
+        Ok(true)
+    }
+
+    /// Body of the JSON routes, None for the other paths
+    fn json_route(&self, path: &str) -> Option<String> {
+        let data = lock(&self.data);
+        let body = match path {
+            "/stats" => {
+                let s = &data.stats;
+                format!(
+                    "{{\"frame\":{},\"fps\":{},\"fps_limit\":{},\"delta\":{},\"window\":[{},{}]}}",
+                    s.frame, s.framerate, s.framerate_limit, s.delta, s.window_size.0, s.window_size.1
+                )
+            }
+            "/entities" => json_array(data.entities.iter().map(|e| json_string(e))),
+            "/cvars" => {
+                let fields: Vec<String> = data
+                    .cvars
+                    .iter()
+                    .map(|(k, v)| format!("{}:{}", json_string(k), json_string(v)))
+                    .collect();
+                format!("{{{}}}", fields.join(","))
+            }
+            "/logs" => json_array(data.logs.iter().map(|l| json_string(l))),
+            "/memory" => {
+                let report = memory::report();
+                let usages = report.usages.iter().map(|u| {
+                    format!(
+                        "{{\"tag\":\"{:?}\",\"bytes\":{},\"peak\":{},\"allocations\":{}}}",
+                        u.tag, u.current_bytes, u.peak_bytes, u.allocations
+                    )
+                });
+                format!(
+                    "{{\"tracking\":{},\"subsystems\":{}}}",
+                    memory::is_tracking(),
+                    json_array(usages)
+                )
+            }
+            _ => return None,
+        };
+
+        Some(body)
+    }
+}
+
+#[cfg(feature = "net")]
+fn send_frame(client: &mut WebSocketClient, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    // Servers never mask their frames
+    client
+        .stream
+        .write_all(&websocket::encode_frame(opcode, payload, None))
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    let header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+
+    stream.write_all(header.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+fn json_array(items: impl Iterator<Item = String>) -> String {
+    format!("[{}]", items.collect::<Vec<_>>().join(","))
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
@@ -0,0 +1,518 @@
+//! Lightweight entity-component-system, home of the game state
+//!
+//! Components of the same type are stored together, indexed by entity. Systems are run in
+//! order by the engine at every update, before `Game::update`.
+
+// standard imports
+use std::any::{Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::time::Instant;
+
+// import the engine modules
+use super::game::Context;
+use super::memory::{self, MemoryTag};
+use super::time::Time;
+
+/// Identifier of an entity, invalid once the entity is despawned
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    /// Index of the entity, reused after despawns
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Number usable as id by the other engine modules (hitboxes, health...)
+    pub fn to_bits(&self) -> u64 {
+        (self.generation as u64) << 32 | self.index as u64
+    }
+
+    pub fn from_bits(bits: u64) -> Entity {
+        Entity {
+            index: bits as u32,
+            generation: (bits >> 32) as u32,
+        }
+    }
+}
+
+/// Components of a single type
+#[derive(Clone)]
+pub struct Storage<T> {
+    components: Vec<Option<(u32, T)>>,
+    count: usize,
+}
+
+impl<T> Storage<T> {
+    fn new() -> Self {
+        Self {
+            components: Vec::new(),
+            count: 0,
+        }
+    }
+
+    fn insert(&mut self, entity: Entity, component: T) -> Option<T> {
+        let index = entity.index as usize;
+        if index >= self.components.len() {
+            self.components.resize_with(index + 1, || None);
+        }
+
+        let old = self.components[index].replace((entity.generation, component));
+        match old {
+            Some((generation, old)) if generation == entity.generation => Some(old),
+            Some(_) => None,
+            None => {
+                self.count += 1;
+                None
+            }
+        }
+    }
+
+    fn take(&mut self, entity: Entity) -> Option<T> {
+        match self.components.get(entity.index as usize) {
+            Some(Some((generation, _))) if *generation == entity.generation => {
+                self.count -= 1;
+                self.components[entity.index as usize].take().map(|(_, c)| c)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        match self.components.get(entity.index as usize) {
+            Some(Some((generation, component))) if *generation == entity.generation => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.components.get_mut(entity.index as usize) {
+            Some(Some((generation, component))) if *generation == entity.generation => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.get(entity).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.components.iter().enumerate().filter_map(|(index, slot)| {
+            slot.as_ref().map(|(generation, component)| {
+                (
+                    Entity {
+                        index: index as u32,
+                        generation: *generation,
+                    },
+                    component,
+                )
+            })
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.components.iter_mut().enumerate().filter_map(|(index, slot)| {
+            slot.as_mut().map(|(generation, component)| {
+                (
+                    Entity {
+                        index: index as u32,
+                        generation: *generation,
+                    },
+                    component,
+                )
+            })
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+/// Type-erased storage, to remove the components of despawned entities
+trait AnyStorage {
+    fn remove(&mut self, entity: Entity);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStorage for Storage<T> {
+    fn remove(&mut self, entity: Entity) {
+        self.take(entity);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Copies a type-erased storage, only known for the types registered with `World::register_snapshot`
+type CloneStorage = fn(&dyn AnyStorage) -> Box<dyn AnyStorage>;
+
+fn clone_storage<T: Clone + 'static>(storage: &dyn AnyStorage) -> Box<dyn AnyStorage> {
+    Box::new(
+        storage
+            .as_any()
+            .downcast_ref::<Storage<T>>()
+            .unwrap()
+            .clone(),
+    )
+}
+
+/// Copy of the entities and of their registered components
+pub struct WorldSnapshot {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    /// None for the registered types without a storage yet
+    storages: Vec<(TypeId, CloneStorage, Option<Box<dyn AnyStorage>>)>,
+}
+
+impl Clone for WorldSnapshot {
+    fn clone(&self) -> Self {
+        let storages = self
+            .storages
+            .iter()
+            .map(|(type_id, clone, storage)| {
+                (*type_id, *clone, storage.as_ref().map(|s| clone(&**s)))
+            })
+            .collect();
+
+        Self {
+            generations: self.generations.clone(),
+            alive: self.alive.clone(),
+            free: self.free.clone(),
+            storages,
+        }
+    }
+}
+
+/// Every entity with its components, plus global resources
+#[derive(Default)]
+pub struct World {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    storages: HashMap<TypeId, RefCell<Box<dyn AnyStorage>>>,
+    resources: HashMap<TypeId, RefCell<Box<dyn Any>>>,
+    /// Component types copied by the snapshots
+    snapshot_types: HashMap<TypeId, CloneStorage>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        let _scope = memory::scope(MemoryTag::Ecs);
+
+        match self.free.pop() {
+            Some(index) => {
+                self.alive[index as usize] = true;
+                Entity {
+                    index,
+                    generation: self.generations[index as usize],
+                }
+            }
+            None => {
+                self.generations.push(0);
+                self.alive.push(true);
+                Entity {
+                    index: (self.generations.len() - 1) as u32,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    /// Spawn an entity and add components to it in a chain
+    pub fn build(&mut self) -> EntityBuilder<'_> {
+        let entity = self.spawn();
+
+        EntityBuilder {
+            world: self,
+            entity,
+        }
+    }
+
+    /// Remove an entity with all of its components
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        for storage in self.storages.values_mut() {
+            storage.get_mut().remove(entity);
+        }
+
+        let index = entity.index as usize;
+        self.alive[index] = false;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free.push(entity.index);
+
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        let index = entity.index as usize;
+
+        index < self.alive.len() && self.alive[index] && self.generations[index] == entity.generation
+    }
+
+    /// Every living entity
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.alive
+            .iter()
+            .enumerate()
+            .filter(|(_, alive)| **alive)
+            .map(move |(index, _)| Entity {
+                index: index as u32,
+                generation: self.generations[index],
+            })
+    }
+
+    /// Add a component to an entity, returns the one it replaced
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) -> Option<T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+
+        let _scope = memory::scope(MemoryTag::Ecs);
+        self.storage_mut::<T>().insert(entity, component)
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        let storage = self.storages.get_mut(&TypeId::of::<T>())?;
+
+        storage
+            .get_mut()
+            .as_any_mut()
+            .downcast_mut::<Storage<T>>()
+            .and_then(|s| s.take(entity))
+    }
+
+    /// Access all components of a type (panics if they are already mutably borrowed)
+    pub fn borrow<T: 'static>(&self) -> Option<Ref<'_, Storage<T>>> {
+        let storage = self.storages.get(&TypeId::of::<T>())?;
+
+        Some(Ref::map(storage.borrow(), |s| {
+            s.as_any().downcast_ref::<Storage<T>>().unwrap()
+        }))
+    }
+
+    /// Mutably access all components of a type, other types can be borrowed at the same time
+    pub fn borrow_mut<T: 'static>(&self) -> Option<RefMut<'_, Storage<T>>> {
+        let storage = self.storages.get(&TypeId::of::<T>())?;
+
+        Some(RefMut::map(storage.borrow_mut(), |s| {
+            s.as_any_mut().downcast_mut::<Storage<T>>().unwrap()
+        }))
+    }
+
+    fn storage_mut<T: 'static>(&mut self) -> &mut Storage<T> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| RefCell::new(Box::new(Storage::<T>::new())))
+            .get_mut()
+            .as_any_mut()
+            .downcast_mut::<Storage<T>>()
+            .unwrap()
+    }
+
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<Ref<'_, T>> {
+        let storage = self.borrow::<T>()?;
+        storage.get(entity)?;
+
+        Some(Ref::map(storage, |s| s.get(entity).unwrap()))
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+        let storage = self.storages.get_mut(&TypeId::of::<T>())?;
+
+        storage
+            .get_mut()
+            .as_any_mut()
+            .downcast_mut::<Storage<T>>()
+            .and_then(|s| s.get_mut(entity))
+    }
+
+    pub fn has<T: 'static>(&self, entity: Entity) -> bool {
+        self.borrow::<T>().map_or(false, |s| s.contains(entity))
+    }
+
+    /// Add a global value shared by the systems, replacing the previous one of the same type
+    pub fn insert_resource<R: 'static>(&mut self, resource: R) {
+        let _scope = memory::scope(MemoryTag::Ecs);
+
+        self.resources
+            .insert(TypeId::of::<R>(), RefCell::new(Box::new(resource)));
+    }
+
+    pub fn resource<R: 'static>(&self) -> Option<Ref<'_, R>> {
+        let resource = self.resources.get(&TypeId::of::<R>())?;
+
+        Some(Ref::map(resource.borrow(), |r| r.downcast_ref::<R>().unwrap()))
+    }
+
+    pub fn resource_mut<R: 'static>(&self) -> Option<RefMut<'_, R>> {
+        let resource = self.resources.get(&TypeId::of::<R>())?;
+
+        Some(RefMut::map(resource.borrow_mut(), |r| {
+            r.downcast_mut::<R>().unwrap()
+        }))
+    }
+
+    /// Copy the components of a type in the snapshots of the world
+    pub fn register_snapshot<T: Clone + 'static>(&mut self) {
+        self.snapshot_types
+            .insert(TypeId::of::<T>(), clone_storage::<T>);
+    }
+
+    /// Copy the entities and the components of the types registered with `register_snapshot`.
+    /// Resources aren't copied
+    pub fn snapshot(&self) -> WorldSnapshot {
+        let storages = self
+            .snapshot_types
+            .iter()
+            .map(|(type_id, clone)| {
+                let storage = self.storages.get(type_id).map(|s| clone(&**s.borrow()));
+                (*type_id, *clone, storage)
+            })
+            .collect();
+
+        WorldSnapshot {
+            generations: self.generations.clone(),
+            alive: self.alive.clone(),
+            free: self.free.clone(),
+            storages,
+        }
+    }
+
+    /// Go back to a snapshot: entities spawned after it are despawned, despawned ones come back.
+    /// Components of unregistered types keep their current value, and are missing on the entities brought back
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) {
+        let spawned_after: Vec<Entity> = self
+            .entities()
+            .filter(|entity| {
+                let index = entity.index as usize;
+                index >= snapshot.alive.len()
+                    || !snapshot.alive[index]
+                    || snapshot.generations[index] != entity.generation
+            })
+            .collect();
+
+        for entity in spawned_after {
+            for storage in self.storages.values_mut() {
+                storage.get_mut().remove(entity);
+            }
+        }
+
+        self.generations = snapshot.generations.clone();
+        self.alive = snapshot.alive.clone();
+        self.free = snapshot.free.clone();
+
+        for (type_id, clone, storage) in &snapshot.storages {
+            match storage {
+                Some(storage) => {
+                    self.storages
+                        .insert(*type_id, RefCell::new(clone(&**storage)));
+                }
+                None => {
+                    self.storages.remove(type_id);
+                }
+            }
+        }
+    }
+}
+
+/// Chain of component insertions on a new entity
+pub struct EntityBuilder<'a> {
+    world: &'a mut World,
+    entity: Entity,
+}
+
+impl<'a> EntityBuilder<'a> {
+    pub fn with<T: 'static>(self, component: T) -> Self {
+        self.world.insert(self.entity, component);
+        self
+    }
+
+    pub fn finish(self) -> Entity {
+        self.entity
+    }
+}
+
+/// Logic run on the world at every update
+pub trait System {
+    fn run(&mut self, time: &Time, ctx: &mut Context);
+}
+
+impl<F: FnMut(&Time, &mut Context)> System for F {
+    fn run(&mut self, time: &Time, ctx: &mut Context) {
+        self(time, ctx)
+    }
+}
+
+/// Ordered list of systems
+#[derive(Default)]
+pub struct Schedule {
+    /// Name, system and CPU time of its last run in seconds
+    systems: Vec<(String, Box<dyn System>, f32)>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a system, run after the ones already added
+    pub fn add_system<S: System + 'static>(&mut self, name: &str, system: S) {
+        self.systems.push((name.to_string(), Box::new(system), 0.0));
+    }
+
+    pub fn remove_system(&mut self, name: &str) -> bool {
+        let count = self.systems.len();
+        self.systems.retain(|(n, _, _)| n != name);
+
+        self.systems.len() != count
+    }
+
+    pub fn system_names(&self) -> impl Iterator<Item = &str> {
+        self.systems.iter().map(|(name, _, _)| name.as_str())
+    }
+
+    /// CPU time of the last run of every system in seconds, in order of execution
+    pub fn system_times(&self) -> impl Iterator<Item = (&str, f32)> {
+        self.systems
+            .iter()
+            .map(|(name, _, time)| (name.as_str(), *time))
+    }
+
+    /// Move the systems of another schedule at the end of this one
+    pub fn append(&mut self, other: &mut Schedule) {
+        self.systems.append(&mut other.systems);
+    }
+
+    pub fn run(&mut self, time: &Time, ctx: &mut Context) {
+        let _scope = memory::scope(MemoryTag::Ecs);
+
+        for (_, system, cpu_time) in self.systems.iter_mut() {
+            let start = Instant::now();
+            system.run(time, ctx);
+            *cpu_time = start.elapsed().as_secs_f32();
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Engine-wide error type, returned when the engine or one of its subsystems can't start
+//!
+//! Every variant holds the message of the failure, the variant tells which part of the
+//! engine failed so games can react to it (e.g. retrying without audio or with another
+//! window configuration). Errors happening while running a frame are `FrameError`s instead.
+
+// standard imports
+use std::error::Error;
+use std::fmt;
+
+/// Why the engine, a subsystem or the game failed
+#[derive(Clone, Debug, PartialEq)]
+pub enum MagmaError {
+    /// SDL2 context, event pump, window or controllers
+    Sdl(String),
+    /// Vulkan instance, device, swapchain or pipelines
+    Graphics(String),
+    /// SDL2 Mixer and the audio device
+    Audio(String),
+    /// Asset missing, unreadable or invalid
+    Asset(String),
+    /// Settings that can't be used (e.g. a display that doesn't exist)
+    Config(String),
+    /// The game stopped with an error, see `ExitReason::Error`
+    Game(String),
+}
+
+impl MagmaError {
+    /// Message of the failure, without the part of the engine that failed
+    pub fn message(&self) -> &str {
+        match self {
+            MagmaError::Sdl(e)
+            | MagmaError::Graphics(e)
+            | MagmaError::Audio(e)
+            | MagmaError::Asset(e)
+            | MagmaError::Config(e)
+            | MagmaError::Game(e) => e,
+        }
+    }
+}
+
+impl fmt::Display for MagmaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MagmaError::Sdl(e) => write!(f, "SDL2 error: {}", e),
+            MagmaError::Graphics(e) => write!(f, "Graphics error: {}", e),
+            MagmaError::Audio(e) => write!(f, "Audio error: {}", e),
+            MagmaError::Asset(e) => write!(f, "Asset error: {}", e),
+            MagmaError::Config(e) => write!(f, "Configuration error: {}", e),
+            MagmaError::Game(e) => write!(f, "Game error: {}", e),
+        }
+    }
+}
+
+impl Error for MagmaError {}
+
+/// For the engine functions returning a `String` error
+impl From<MagmaError> for String {
+    fn from(error: MagmaError) -> String {
+        error.to_string()
+    }
+}
@@ -0,0 +1,160 @@
+//! Engine-wide event bus, letting the subsystems report to the game what happened during a frame
+
+// standard imports
+use std::collections::vec_deque::{self, VecDeque};
+use std::path::PathBuf;
+
+// SDL2 imports
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+
+// import the ctx module
+use super::ctx::gamepad::{Axis, Button};
+use super::ctx::vulkan::FrameError;
+use super::health::HealthEvent;
+use super::hot_reload::WatchKind;
+use super::loading::BatchId;
+#[cfg(feature = "physics2d")]
+use super::physics2d::PhysicsEvent;
+use super::hitbox::HitEvent;
+use super::water::WaterEvent;
+
+/// Identifier of a window, as given by SDL2
+pub type WindowId = u32;
+
+/// Changes of the window state reported by the OS
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowEvent {
+    Shown,
+    Hidden,
+    /// The window content must be redrawn
+    Exposed,
+    Moved(i32, i32),
+    Resized(u32, u32),
+    Minimized,
+    Maximized,
+    Restored,
+    FocusGained,
+    FocusLost,
+    MouseEntered,
+    MouseLeft,
+    CloseRequested,
+}
+
+/// Changes of the application state reported by the mobile OSes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LifecycleEvent {
+    /// The OS is killing the app: save now, there may not be another frame
+    Terminating,
+    /// The OS is low on memory, free what can be loaded again
+    LowMemory,
+    /// Going to the background, rendering and audio are suspended from now on
+    WillEnterBackground,
+    DidEnterBackground,
+    WillEnterForeground,
+    /// Back in the foreground. On Android the renderer of the main window was started again
+    /// on its new surface, reported by `EngineEvent::DeviceRecovered` just before
+    DidEnterForeground,
+}
+
+/// Input of the player, mouse and touch coordinates are in pixels of `window`.
+/// Key, mouse and touch events carry the window they were sent to, with the focus or under the pointer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEvent {
+    KeyDown { window: WindowId, key: Keycode, repeat: bool },
+    KeyUp { window: WindowId, key: Keycode },
+    MouseMoved { window: WindowId, x: i32, y: i32 },
+    MouseButtonDown { window: WindowId, button: MouseButton, x: i32, y: i32 },
+    MouseButtonUp { window: WindowId, button: MouseButton, x: i32, y: i32 },
+    MouseWheel { window: WindowId, x: i32, y: i32 },
+    GamepadButton { player: usize, button: Button, pressed: bool },
+    /// Normalized axis value, in range -1.0..=1.0
+    GamepadAxis { player: usize, axis: Axis, value: f32 },
+    /// A finger touched the screen, `finger` identifies it until it's lifted
+    TouchDown { window: WindowId, finger: i64, x: f32, y: f32 },
+    TouchMoved { window: WindowId, finger: i64, x: f32, y: f32 },
+    TouchUp { window: WindowId, finger: i64, x: f32, y: f32 },
+}
+
+impl InputEvent {
+    /// Window receiving a key, mouse or touch event, None for the controllers
+    pub fn window(&self) -> Option<WindowId> {
+        match *self {
+            InputEvent::KeyDown { window, .. }
+            | InputEvent::KeyUp { window, .. }
+            | InputEvent::MouseMoved { window, .. }
+            | InputEvent::MouseButtonDown { window, .. }
+            | InputEvent::MouseButtonUp { window, .. }
+            | InputEvent::MouseWheel { window, .. }
+            | InputEvent::TouchDown { window, .. }
+            | InputEvent::TouchMoved { window, .. }
+            | InputEvent::TouchUp { window, .. } => Some(window),
+            InputEvent::GamepadButton { .. } | InputEvent::GamepadAxis { .. } => None,
+        }
+    }
+}
+
+/// Events generated by the engine subsystems
+#[derive(Clone, Debug)]
+pub enum EngineEvent {
+    /// A frame couldn't be rendered, already handled following the FrameErrorPolicy
+    FrameError(FrameError),
+    Window(WindowId, WindowEvent),
+    /// The app is going to the background or coming back (Android and iOS)
+    Lifecycle(LifecycleEvent),
+    /// Keyboard, mouse or controller input (recorded and played back by replays)
+    Input(InputEvent),
+    /// Text typed by the player, following the keyboard layout (not recorded by replays)
+    TextInput(String),
+    /// A marker of an animation event track was reached
+    AnimationEvent {
+        source: u64,
+        name: String,
+        frame: usize,
+    },
+    /// A sprite animation which doesn't loop reached its end
+    AnimationFinished { source: u64, animation: String },
+    /// An attack hitbox touched a hurtbox
+    Hit(HitEvent),
+    Health(HealthEvent),
+    Water(WaterEvent),
+    /// Colliders of the 2D physics started or stopped touching
+    #[cfg(feature = "physics2d")]
+    Physics(PhysicsEvent),
+    /// Every asset of a background loading batch was handled (see `AssetLoader::finish`)
+    AssetsLoaded(BatchId),
+    /// A watched file changed on disk and was reloaded, handles get it with `Handle::refresh`
+    AssetReloaded { path: PathBuf, kind: WatchKind },
+    /// The GPU of a window was lost and the renderer started again on a new device.
+    /// The textures of the asset manager were uploaded again, handles get them with
+    /// `Handle::refresh`; `lost_objects` DrawObjects couldn't be recreated and were removed
+    DeviceRecovered {
+        window: WindowId,
+        lost_objects: usize,
+    },
+}
+
+/// Queue of the events generated since the last drain
+#[derive(Default)]
+pub struct EventBus {
+    queue: VecDeque<EngineEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, event: EngineEvent) {
+        self.queue.push_back(event);
+    }
+
+    /// Take every queued event in order of arrival
+    pub fn drain(&mut self) -> vec_deque::Drain<'_, EngineEvent> {
+        self.queue.drain(..)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
@@ -0,0 +1,31 @@
+//! Typed reasons for the engine to stop, and the in-process restart flow
+
+// import the engine modules
+use super::config::EngineConfig;
+use super::error::MagmaError;
+
+/// Why `Engine::run` returned
+#[derive(Clone, Debug)]
+pub enum ExitReason {
+    /// The player closed the game, or the game asked to quit
+    Quit,
+    /// The game can't continue, `Engine::run` returns the error
+    Error(MagmaError),
+    /// Tear down every subsystem and start again with the new settings
+    /// (e.g. after changing the backend or the adapter)
+    Restart(Box<EngineConfig>),
+}
+
+impl ExitReason {
+    /// Process exit code for the reason, to pass to `std::process::exit`
+    pub fn code(&self) -> i32 {
+        match self {
+            ExitReason::Quit | ExitReason::Restart(_) => 0,
+            ExitReason::Error(_) => 1,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, ExitReason::Error(_))
+    }
+}
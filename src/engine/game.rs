@@ -0,0 +1,37 @@
+//! Entry point of the user code, driven by `Engine::run`
+
+// import the ctx module
+use super::ctx::{CtxHandler, VideoHandler};
+use super::events::EngineEvent;
+use super::time::Time;
+
+/// Everything a game can use from the engine: video, audio, input, timing and events
+pub type Context = CtxHandler;
+
+/// Data of the frame about to be rendered
+pub struct Frame<'a> {
+    /// Main window renderer, objects and camera changed here appear in this frame
+    pub video: &'a mut VideoHandler,
+    pub time: &'a Time,
+    /// With a fixed update rate, progress between the last update and the next one (0.0 to 1.0),
+    /// to interpolate the rendered state. Always 1.0 otherwise
+    pub alpha: f32,
+}
+
+/// Game logic run by the engine loop
+pub trait Game {
+    /// Called once before the first frame, to load the assets and build the scene
+    fn init(&mut self, _ctx: &mut Context) {}
+
+    /// Advance the game state by `time.delta()` seconds (the fixed step with `LoopMode::Fixed`)
+    fn update(&mut self, time: &Time, ctx: &mut Context);
+
+    /// Prepare the draw objects and camera for the frame
+    fn render(&mut self, _frame: &mut Frame) {}
+
+    /// Called for every event generated by the engine, before `update`
+    fn on_event(&mut self, _event: &EngineEvent, _ctx: &mut Context) {}
+
+    /// Called once after the last frame
+    fn shutdown(&mut self, _ctx: &mut Context) {}
+}
@@ -0,0 +1,264 @@
+//! 2D shapes for hit testing, culling and simple collision
+//!
+//! `Rect` is described by its top-left corner and size (texture regions, UI layout),
+//! `Aabb` by its min and max corners (bounds and collision), `Circle` by center and radius.
+
+// import the engine modules
+use super::hitbox::HitRect;
+use super::math::Vec2;
+
+// other imports
+use cgmath::InnerSpace;
+
+/// Rectangle from its top-left corner and size
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn from_center(center: Vec2, size: Vec2) -> Self {
+        Self::new(
+            center.x - size.x / 2.0,
+            center.y - size.y / 2.0,
+            size.x,
+            size.y,
+        )
+    }
+
+    pub fn position(&self) -> Vec2 {
+        Vec2::new(self.x, self.y)
+    }
+
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(self.width, self.height)
+    }
+
+    pub fn center(&self) -> Vec2 {
+        Vec2::new(self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+
+    pub fn area(&self) -> f32 {
+        self.width * self.height
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.to_aabb().contains(point)
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.to_aabb().intersects(&other.to_aabb())
+    }
+
+    /// Smallest rectangle holding both
+    pub fn union(&self, other: &Rect) -> Rect {
+        self.to_aabb().union(&other.to_aabb()).to_rect()
+    }
+
+    /// Common part of both, None if they don't intersect
+    pub fn overlap(&self, other: &Rect) -> Option<Rect> {
+        self.to_aabb()
+            .overlap(&other.to_aabb())
+            .map(|aabb| aabb.to_rect())
+    }
+
+    pub fn to_aabb(&self) -> Aabb {
+        Aabb::new(
+            Vec2::new(self.x, self.y),
+            Vec2::new(self.x + self.width, self.y + self.height),
+        )
+    }
+}
+
+/// Axis-aligned box from its min and max corners
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Aabb {
+    /// Box between two corners, in any order
+    pub fn new(a: Vec2, b: Vec2) -> Self {
+        Self {
+            min: Vec2::new(a.x.min(b.x), a.y.min(b.y)),
+            max: Vec2::new(a.x.max(b.x), a.y.max(b.y)),
+        }
+    }
+
+    pub fn from_center(center: Vec2, half_extents: Vec2) -> Self {
+        Self::new(center - half_extents, center + half_extents)
+    }
+
+    /// Smallest box holding every point, None without points
+    pub fn from_points(points: &[Vec2]) -> Option<Self> {
+        let first = *points.first()?;
+
+        Some(points.iter().fold(Self::new(first, first), |aabb, point| {
+            aabb.expand_to(*point)
+        }))
+    }
+
+    pub fn center(&self) -> Vec2 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn half_extents(&self) -> Vec2 {
+        (self.max - self.min) / 2.0
+    }
+
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.x >= self.min.x
+            && point.x <= self.max.x
+            && point.y >= self.min.y
+            && point.y <= self.max.y
+    }
+
+    /// Check if `other` is entirely inside
+    pub fn encloses(&self, other: &Aabb) -> bool {
+        other.min.x >= self.min.x
+            && other.max.x <= self.max.x
+            && other.min.y >= self.min.y
+            && other.max.y <= self.max.y
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec2::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y)),
+            max: Vec2::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y)),
+        }
+    }
+
+    pub fn overlap(&self, other: &Aabb) -> Option<Aabb> {
+        if !self.intersects(other) {
+            return None;
+        }
+
+        Some(Aabb {
+            min: Vec2::new(self.min.x.max(other.min.x), self.min.y.max(other.min.y)),
+            max: Vec2::new(self.max.x.min(other.max.x), self.max.y.min(other.max.y)),
+        })
+    }
+
+    /// Smallest box holding this one and the point
+    pub fn expand_to(&self, point: Vec2) -> Aabb {
+        self.union(&Aabb::new(point, point))
+    }
+
+    /// Point of the box nearest to the given one (itself if inside)
+    pub fn closest_point(&self, point: Vec2) -> Vec2 {
+        Vec2::new(
+            point.x.max(self.min.x).min(self.max.x),
+            point.y.max(self.min.y).min(self.max.y),
+        )
+    }
+
+    /// Shortest move getting `other` out of this box, None if they don't intersect
+    pub fn penetration(&self, other: &Aabb) -> Option<Vec2> {
+        let overlap = self.overlap(other)?.size();
+        let direction = other.center() - self.center();
+
+        Some(if overlap.x < overlap.y {
+            Vec2::new(overlap.x.copysign(direction.x), 0.0)
+        } else {
+            Vec2::new(0.0, overlap.y.copysign(direction.y))
+        })
+    }
+
+    pub fn to_rect(&self) -> Rect {
+        let size = self.size();
+        Rect::new(self.min.x, self.min.y, size.x, size.y)
+    }
+}
+
+impl From<HitRect> for Aabb {
+    fn from(rect: HitRect) -> Self {
+        Aabb::from_center(rect.center, rect.half_extents)
+    }
+}
+
+impl From<Rect> for Aabb {
+    fn from(rect: Rect) -> Self {
+        rect.to_aabb()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Circle {
+    pub center: Vec2,
+    pub radius: f32,
+}
+
+impl Circle {
+    pub fn new(center: Vec2, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        (point - self.center).magnitude2() <= self.radius * self.radius
+    }
+
+    pub fn intersects(&self, other: &Circle) -> bool {
+        let distance = self.radius + other.radius;
+        (other.center - self.center).magnitude2() <= distance * distance
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.contains(aabb.closest_point(self.center))
+    }
+
+    /// Depth of the overlap of two circles, None if they don't intersect
+    pub fn overlap(&self, other: &Circle) -> Option<f32> {
+        let depth = self.radius + other.radius - (other.center - self.center).magnitude();
+
+        if depth >= 0.0 {
+            Some(depth)
+        } else {
+            None
+        }
+    }
+
+    /// Smallest circle holding both
+    pub fn union(&self, other: &Circle) -> Circle {
+        let offset = other.center - self.center;
+        let distance = offset.magnitude();
+
+        if distance + other.radius <= self.radius {
+            return *self;
+        }
+        if distance + self.radius <= other.radius {
+            return *other;
+        }
+
+        let radius = (distance + self.radius + other.radius) / 2.0;
+        let center = self.center + offset / distance * (radius - self.radius);
+        Circle { center, radius }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_center(self.center, Vec2::new(self.radius, self.radius))
+    }
+}
@@ -0,0 +1,272 @@
+//! Optional gameplay components for health, damage and timed status effects
+//!
+//! Every change is reported through the event bus as `EngineEvent::Health`, so UI and audio
+//! can react without being coupled to the gameplay code.
+
+// standard imports
+use std::collections::HashMap;
+
+// import the event bus
+use super::events::{EngineEvent, EventBus};
+use super::hitbox::HitEvent;
+
+/// Kind of a damage, used by the resistances
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DamageKind {
+    Physical,
+    Fire,
+    Ice,
+    Electric,
+    Poison,
+    /// Game-specific kinds
+    Custom(u32),
+}
+
+/// Damage applied to a health component
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Damage {
+    pub amount: f32,
+    pub kind: DamageKind,
+    /// Entity dealing the damage
+    pub source: u64,
+}
+
+impl From<&HitEvent> for Damage {
+    fn from(hit: &HitEvent) -> Self {
+        Self {
+            amount: hit.payload.damage,
+            kind: DamageKind::Physical,
+            source: hit.attacker,
+        }
+    }
+}
+
+/// Changes of a health component
+#[derive(Clone, Debug, PartialEq)]
+pub enum HealthEvent {
+    /// Damage actually taken, after the resistances
+    Damaged { entity: u64, damage: Damage },
+    /// Damage ignored because of an invulnerability window
+    Blocked { entity: u64, damage: Damage },
+    Healed { entity: u64, amount: f32 },
+    Died { entity: u64, killer: u64 },
+    StatusApplied { entity: u64, status: String },
+    StatusExpired { entity: u64, status: String },
+}
+
+/// Effect lasting for a time, optionally dealing damage at a fixed interval (poison, burning...)
+#[derive(Clone, Debug)]
+pub struct StatusEffect {
+    pub name: String,
+    /// Remaining time in seconds
+    pub remaining: f32,
+    /// Damage dealt every `tick_interval` seconds
+    pub tick_damage: Option<Damage>,
+    pub tick_interval: f32,
+    /// Multiplier of the incoming damage while active (1.0 for none)
+    pub damage_multiplier: f32,
+    since_tick: f32,
+}
+
+impl StatusEffect {
+    pub fn new(name: &str, duration: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            remaining: duration,
+            tick_damage: None,
+            tick_interval: 1.0,
+            damage_multiplier: 1.0,
+            since_tick: 0.0,
+        }
+    }
+
+    pub fn with_tick_damage(mut self, damage: Damage, interval: f32) -> Self {
+        self.tick_damage = Some(damage);
+        self.tick_interval = interval;
+        self
+    }
+
+    pub fn with_damage_multiplier(mut self, multiplier: f32) -> Self {
+        self.damage_multiplier = multiplier;
+        self
+    }
+}
+
+/// Health of an entity, with resistances, invulnerability windows and status effects
+#[derive(Clone, Debug)]
+pub struct Health {
+    pub entity: u64,
+    current: f32,
+    pub max: f32,
+    /// Multiplier of the damage taken for each kind (missing kinds take full damage)
+    pub resistances: HashMap<DamageKind, f32>,
+    /// Invulnerability given after every damage taken, in seconds
+    pub invulnerability_after_hit: f32,
+    invulnerable_for: f32,
+    statuses: Vec<StatusEffect>,
+}
+
+impl Health {
+    pub fn new(entity: u64, max: f32) -> Self {
+        Self {
+            entity,
+            current: max,
+            max,
+            resistances: HashMap::new(),
+            invulnerability_after_hit: 0.0,
+            invulnerable_for: 0.0,
+            statuses: Vec::new(),
+        }
+    }
+
+    pub fn with_resistance(mut self, kind: DamageKind, multiplier: f32) -> Self {
+        self.resistances.insert(kind, multiplier);
+        self
+    }
+
+    pub fn with_invulnerability_after_hit(mut self, seconds: f32) -> Self {
+        self.invulnerability_after_hit = seconds;
+        self
+    }
+
+    pub fn get_current(&self) -> f32 {
+        self.current
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+
+    pub fn is_invulnerable(&self) -> bool {
+        self.invulnerable_for > 0.0
+    }
+
+    /// Ignore every damage for the given time (dodges, respawns...)
+    pub fn set_invulnerable(&mut self, seconds: f32) {
+        self.invulnerable_for = self.invulnerable_for.max(seconds);
+    }
+
+    /// Apply a damage, returns the amount actually taken
+    pub fn apply_damage(&mut self, damage: Damage, bus: &mut EventBus) -> f32 {
+        if self.is_dead() {
+            return 0.0;
+        }
+
+        if self.is_invulnerable() {
+            bus.push(EngineEvent::Health(HealthEvent::Blocked {
+                entity: self.entity,
+                damage,
+            }));
+            return 0.0;
+        }
+
+        let taken = self.take(damage, bus);
+        self.invulnerable_for = self.invulnerability_after_hit;
+
+        taken
+    }
+
+    /// Remove the health without triggering the invulnerability window
+    fn take(&mut self, damage: Damage, bus: &mut EventBus) -> f32 {
+        let resistance = self.resistances.get(&damage.kind).copied().unwrap_or(1.0);
+        let multiplier: f32 = self.statuses.iter().map(|s| s.damage_multiplier).product();
+
+        let amount = (damage.amount * resistance * multiplier).max(0.0).min(self.current);
+        self.current -= amount;
+
+        bus.push(EngineEvent::Health(HealthEvent::Damaged {
+            entity: self.entity,
+            damage: Damage { amount, ..damage },
+        }));
+        if self.is_dead() {
+            bus.push(EngineEvent::Health(HealthEvent::Died {
+                entity: self.entity,
+                killer: damage.source,
+            }));
+        }
+
+        amount
+    }
+
+    /// Restore health up to the maximum, returns the amount actually healed
+    pub fn heal(&mut self, amount: f32, bus: &mut EventBus) -> f32 {
+        if self.is_dead() {
+            return 0.0;
+        }
+
+        let healed = amount.max(0.0).min(self.max - self.current);
+        self.current += healed;
+
+        if healed > 0.0 {
+            bus.push(EngineEvent::Health(HealthEvent::Healed {
+                entity: self.entity,
+                amount: healed,
+            }));
+        }
+
+        healed
+    }
+
+    /// Bring back a dead entity with full health
+    pub fn revive(&mut self) {
+        self.current = self.max;
+        self.statuses.clear();
+    }
+
+    /// Add a status effect, replacing the one with the same name
+    pub fn apply_status(&mut self, status: StatusEffect, bus: &mut EventBus) {
+        self.statuses.retain(|s| s.name != status.name);
+
+        bus.push(EngineEvent::Health(HealthEvent::StatusApplied {
+            entity: self.entity,
+            status: status.name.clone(),
+        }));
+        self.statuses.push(status);
+    }
+
+    pub fn has_status(&self, name: &str) -> bool {
+        self.statuses.iter().any(|s| s.name == name)
+    }
+
+    pub fn get_statuses(&self) -> &[StatusEffect] {
+        &self.statuses
+    }
+
+    /// Advance the timers, applying the damage of the status effects
+    pub fn update(&mut self, dt: f32, bus: &mut EventBus) {
+        self.invulnerable_for = (self.invulnerable_for - dt).max(0.0);
+
+        let mut ticks = Vec::new();
+        for status in self.statuses.iter_mut() {
+            let active = dt.min(status.remaining.max(0.0));
+            status.remaining -= dt;
+
+            if let Some(damage) = status.tick_damage {
+                status.since_tick += active;
+                while status.tick_interval > 0.0 && status.since_tick >= status.tick_interval {
+                    status.since_tick -= status.tick_interval;
+                    ticks.push(damage);
+                }
+            }
+        }
+
+        // Damage over time ignores the invulnerability windows
+        for damage in ticks {
+            if !self.is_dead() {
+                self.take(damage, bus);
+            }
+        }
+
+        let entity = self.entity;
+        self.statuses.retain(|status| {
+            let active = status.remaining > 0.0;
+            if !active {
+                bus.push(EngineEvent::Health(HealthEvent::StatusExpired {
+                    entity,
+                    status: status.name.clone(),
+                }));
+            }
+            active
+        });
+    }
+}
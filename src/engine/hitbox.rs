@@ -0,0 +1,259 @@
+//! Frame-data-driven hitboxes and hurtboxes for action and fighting games
+//!
+//! An attack is described by its frame data: hitboxes active on a window of frames of the
+//! attack animation, each one with the damage it deals. The attack follows the animation
+//! frame and hits every overlapping hurtbox of another team at most once per activation.
+
+// standard imports
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+// import the event bus
+use super::events::{EngineEvent, EventBus};
+
+// import the engine modules
+use super::pack;
+
+// other imports
+use cgmath::Vector2;
+
+/// Name of the animation event starting a new activation, letting the same attack hit again
+pub const REHIT_EVENT: &str = "rehit";
+
+/// Axis-aligned box, centered on its position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HitRect {
+    pub center: Vector2<f32>,
+    pub half_extents: Vector2<f32>,
+}
+
+impl HitRect {
+    pub fn new(center: Vector2<f32>, half_extents: Vector2<f32>) -> Self {
+        Self {
+            center,
+            half_extents,
+        }
+    }
+
+    pub fn overlaps(&self, other: &HitRect) -> bool {
+        (self.center.x - other.center.x).abs() <= self.half_extents.x + other.half_extents.x
+            && (self.center.y - other.center.y).abs() <= self.half_extents.y + other.half_extents.y
+    }
+}
+
+/// What a hitbox does to the hurtbox it touches
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DamagePayload {
+    pub damage: f32,
+    /// Push given to the target, mirrored with the attacker facing
+    pub knockback: Vector2<f32>,
+    /// Frames the target can't act for
+    pub hitstun: u32,
+}
+
+/// Hitbox of an attack, active between two frames of its animation (both included)
+#[derive(Clone, Debug)]
+pub struct HitboxData {
+    pub start_frame: usize,
+    pub end_frame: usize,
+    /// Box relative to the attacker position, for an attacker facing right
+    pub rect: HitRect,
+    pub payload: DamagePayload,
+}
+
+impl HitboxData {
+    pub fn is_active(&self, frame: usize) -> bool {
+        frame >= self.start_frame && frame <= self.end_frame
+    }
+}
+
+/// Frame data of an attack
+#[derive(Clone, Debug, Default)]
+pub struct AttackData {
+    pub hitboxes: Vec<HitboxData>,
+}
+
+impl AttackData {
+    /// Parse frame data from text, one hitbox per line (`#` starts a comment):
+    /// `<start frame> <end frame> <x> <y> <half width> <half height> <damage> [<knockback x> <knockback y> [<hitstun>]]`
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut hitboxes = Vec::new();
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let values: Vec<f32> = line
+                .split_whitespace()
+                .map(|v| v.parse::<f32>())
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("Invalid value on line {}: {}", number + 1, e))?;
+
+            if values.len() < 7 {
+                return Err(format!("Missing hitbox values on line {}", number + 1));
+            }
+
+            let get = |index: usize| values.get(index).copied().unwrap_or(0.0);
+            hitboxes.push(HitboxData {
+                start_frame: values[0] as usize,
+                end_frame: values[1] as usize,
+                rect: HitRect::new(
+                    Vector2::new(values[2], values[3]),
+                    Vector2::new(values[4], values[5]),
+                ),
+                payload: DamagePayload {
+                    damage: values[6],
+                    knockback: Vector2::new(get(7), get(8)),
+                    hitstun: get(9) as u32,
+                },
+            });
+        }
+
+        Ok(Self { hitboxes })
+    }
+
+    /// Load frame data from a file in the format read by `parse`
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let source = pack::read_to_string(path)?;
+
+        Self::parse(&source).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Area of an entity that can be hit
+#[derive(Clone, Copy, Debug)]
+pub struct Hurtbox {
+    pub owner: u64,
+    pub team: u32,
+    /// Box in world coordinates
+    pub rect: HitRect,
+    pub invulnerable: bool,
+}
+
+/// Hit landed by an attack, also sent through the event bus as `EngineEvent::Hit`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HitEvent {
+    pub attacker: u64,
+    pub target: u64,
+    pub payload: DamagePayload,
+    /// Center of the overlap, for hit sparks
+    pub point: Vector2<f32>,
+}
+
+/// Attack being performed by an entity
+#[derive(Clone, Debug)]
+pub struct Attack {
+    pub data: Rc<AttackData>,
+    pub owner: u64,
+    pub team: u32,
+    /// Animation whose events drive the activations
+    pub animation_source: u64,
+    pub position: Vector2<f32>,
+    pub facing_right: bool,
+    /// Let the attack hit entities of its own team
+    pub friendly_fire: bool,
+    frame: usize,
+    /// Targets already hit during the current activation
+    hit_targets: HashSet<u64>,
+}
+
+impl Attack {
+    pub fn new(data: Rc<AttackData>, owner: u64, team: u32, animation_source: u64) -> Self {
+        Self {
+            data,
+            owner,
+            team,
+            animation_source,
+            position: Vector2::new(0.0, 0.0),
+            facing_right: true,
+            friendly_fire: false,
+            frame: 0,
+            hit_targets: HashSet::new(),
+        }
+    }
+
+    /// Follow the frame of the attack animation
+    pub fn set_frame(&mut self, frame: usize) {
+        self.frame = frame;
+    }
+
+    pub fn get_frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Start a new activation, every target can be hit again
+    pub fn reactivate(&mut self) {
+        self.hit_targets.clear();
+    }
+
+    /// Reactivate on the `REHIT_EVENT` markers of the attack animation
+    pub fn on_event(&mut self, event: &EngineEvent) {
+        if let EngineEvent::AnimationEvent { source, name, .. } = event {
+            if *source == self.animation_source && name == REHIT_EVENT {
+                self.reactivate();
+            }
+        }
+    }
+
+    /// Hitboxes active on the current frame, in world coordinates
+    pub fn active_hitboxes(&self) -> impl Iterator<Item = (HitRect, &DamagePayload)> + '_ {
+        let direction = if self.facing_right { 1.0 } else { -1.0 };
+
+        self.data
+            .hitboxes
+            .iter()
+            .filter(move |h| h.is_active(self.frame))
+            .map(move |h| {
+                let center = Vector2::new(
+                    self.position.x + h.rect.center.x * direction,
+                    self.position.y + h.rect.center.y,
+                );
+                (HitRect::new(center, h.rect.half_extents), &h.payload)
+            })
+    }
+
+    fn can_hit(&self, hurtbox: &Hurtbox) -> bool {
+        hurtbox.owner != self.owner
+            && !hurtbox.invulnerable
+            && (self.friendly_fire || hurtbox.team != self.team)
+            && !self.hit_targets.contains(&hurtbox.owner)
+    }
+
+    /// Check the active hitboxes against the hurtboxes, every hit is pushed on the event bus
+    pub fn resolve(&mut self, hurtboxes: &[Hurtbox], bus: &mut EventBus) -> Vec<HitEvent> {
+        let direction = if self.facing_right { 1.0 } else { -1.0 };
+        let mut hits = Vec::new();
+
+        for hurtbox in hurtboxes {
+            if !self.can_hit(hurtbox) {
+                continue;
+            }
+
+            // The first active hitbox touching the target wins, a target is hit once per activation
+            let hit = self
+                .active_hitboxes()
+                .find(|(rect, _)| rect.overlaps(&hurtbox.rect))
+                .map(|(rect, payload)| HitEvent {
+                    attacker: self.owner,
+                    target: hurtbox.owner,
+                    payload: DamagePayload {
+                        knockback: Vector2::new(payload.knockback.x * direction, payload.knockback.y),
+                        ..*payload
+                    },
+                    point: (rect.center + hurtbox.rect.center) / 2.0,
+                });
+
+            if let Some(hit) = hit {
+                self.hit_targets.insert(hurtbox.owner);
+                bus.push(EngineEvent::Hit(hit));
+                hits.push(hit);
+            }
+        }
+
+        hits
+    }
+}
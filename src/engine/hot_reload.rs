@@ -0,0 +1,97 @@
+//! Development hot-reloading: loaded assets and config files are watched on disk and reloaded when changed
+//!
+//! Files are polled for their modification time, no OS notification is needed. Reloaded assets
+//! replace the cached ones and `EngineEvent::AssetReloaded` is sent, the systems holding handles
+//! get the new version with `Handle::refresh`.
+
+// standard imports
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Kind of a watched file, deciding how it's reloaded
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WatchKind {
+    Texture,
+    Sound,
+    Shader,
+    Font,
+    Mesh,
+    /// Lua script, the `ScriptEngine` re-binds the running instances to the new version
+    Script,
+    /// Entity definition, the `Prefabs` system updates the entities spawned from it
+    Prefab,
+    /// Engine config file, its runtime settings (volumes, vsync, key bindings) are applied again
+    Config,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Polls the modification time of the watched files
+pub struct AssetWatcher {
+    files: HashMap<PathBuf, (WatchKind, Option<SystemTime>)>,
+    /// Time between two checks of the files
+    pub interval: Duration,
+    last_poll: Instant,
+}
+
+impl AssetWatcher {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            files: HashMap::new(),
+            interval,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn watch(&mut self, path: &Path, kind: WatchKind) {
+        self.files
+            .entry(path.to_path_buf())
+            .or_insert_with(|| (kind, modified(path)));
+    }
+
+    pub fn unwatch(&mut self, path: &Path) -> bool {
+        self.files.remove(path).is_some()
+    }
+
+    pub fn is_watching(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    /// Watch exactly the given files of a kind, e.g. the ones still loaded in a cache
+    pub(crate) fn sync(&mut self, kind: WatchKind, paths: &[PathBuf]) {
+        self.files
+            .retain(|path, (watched, _)| *watched != kind || paths.contains(path));
+        for path in paths {
+            self.watch(path, kind);
+        }
+    }
+
+    /// The files can be checked again, an interval passed since the last check
+    pub fn is_due(&self) -> bool {
+        self.last_poll.elapsed() >= self.interval
+    }
+
+    /// Files changed since the last check, checked at most once per interval
+    pub fn poll(&mut self) -> Vec<(PathBuf, WatchKind)> {
+        if !self.is_due() {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = Vec::new();
+        for (path, (kind, time)) in self.files.iter_mut() {
+            let current = modified(path);
+            // A file being rewritten may be missing for a moment, wait for it to come back
+            if current.is_some() && current != *time {
+                *time = current;
+                changed.push((path.clone(), *kind));
+            }
+        }
+
+        changed
+    }
+}
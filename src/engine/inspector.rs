@@ -0,0 +1,528 @@
+//! Runtime inspector of the entities: hierarchy, component values and live editing
+//!
+//! The `Inspector` resource of the world knows the component types it can show, read and
+//! written through serde like the prefabs. The `InspectorPlugin` draws its window over the
+//! game, listing the entities as a tree following their `Parent` and showing the components of
+//! the selected one (Page Up / Page Down to change it). Values are edited from the developer
+//! console, where the plugin adds its commands:
+//!
+//! ```text
+//! inspect 12                           select entity 12 and show the inspector
+//! get 12 Transform                     print a component, or one of its fields
+//! set 12 Transform.translation.x 40    change a field (the value is JSON, or a plain text)
+//! ```
+//!
+//! The window is drawn with the engine overlay objects (bitmap text over a rectangle) like the
+//! console and the profiler, there's no immediate mode UI integration to build it on.
+//! Needs the `serialization` feature.
+
+// standard imports
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+// SDL2 imports
+use sdl2::keyboard::Keycode;
+
+// import the engine modules
+use super::bitmap_font::{BitmapFont, BitmapTextObject};
+use super::color::Color;
+use super::console::Console;
+use super::crash;
+use super::ctx::draw_objects::{Draw, PrimitiveObject, WORLD_UNITS_PER_PIXEL};
+use super::ecs::{Entity, World};
+use super::events::{EngineEvent, InputEvent};
+use super::game::{Context, Frame};
+use super::math::Vec2;
+use super::plugin::EnginePlugin;
+use super::prefab::PrefabInstance;
+use super::time::Time;
+use super::transform::{Parent, Transform};
+
+// other imports
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Over the game, under the profiler and the console
+const INSPECTOR_Z_INDEX: u8 = 245;
+/// Sizes in pixels
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 6.0;
+/// Entities listed around the selected one
+const LISTED_ENTITIES: usize = 16;
+
+/// Type-erased access to an inspectable component type
+#[derive(Clone, Copy)]
+struct ComponentType {
+    read: fn(&World, Entity) -> Option<Value>,
+    write: fn(&mut World, Entity, Value) -> Result<(), String>,
+}
+
+fn read_component<T: Serialize + 'static>(world: &World, entity: Entity) -> Option<Value> {
+    serde_json::to_value(&*world.get::<T>(entity)?).ok()
+}
+
+fn write_component<T: DeserializeOwned + 'static>(
+    world: &mut World,
+    entity: Entity,
+    value: Value,
+) -> Result<(), String> {
+    let component: T = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    world.insert(entity, component);
+    Ok(())
+}
+
+/// Field of a value following a path of names and indices ("translation.x", "points.2")
+fn field_mut<'a>(value: &'a mut Value, path: &[&str]) -> Result<&'a mut Value, String> {
+    path.iter().try_fold(value, |value, name| {
+        let field = match value {
+            Value::Object(fields) => fields.get_mut(*name),
+            Value::Array(items) => name
+                .parse::<usize>()
+                .ok()
+                .and_then(move |i| items.get_mut(i)),
+            _ => None,
+        };
+        field.ok_or_else(|| format!("No field '{}'", name))
+    })
+}
+
+/// Inspectable component types, selected entity and visibility of the inspector window
+pub struct Inspector {
+    components: BTreeMap<String, ComponentType>,
+    selected: Option<Entity>,
+    open: bool,
+}
+
+impl Default for Inspector {
+    /// Inspector of the transforms, the other components are added with `register_component`
+    fn default() -> Self {
+        let mut inspector = Self {
+            components: BTreeMap::new(),
+            selected: None,
+            open: false,
+        };
+        inspector.register_component::<Transform>("Transform");
+        inspector
+    }
+}
+
+impl Inspector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Show and edit a component type under a name
+    pub fn register_component<T>(&mut self, name: &str)
+    where
+        T: Serialize + DeserializeOwned + 'static,
+    {
+        self.components.insert(
+            name.to_string(),
+            ComponentType {
+                read: read_component::<T>,
+                write: write_component::<T>,
+            },
+        );
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn set_open(&mut self, open: bool) {
+        self.open = open;
+    }
+
+    pub fn selected(&self) -> Option<Entity> {
+        self.selected
+    }
+
+    pub fn select(&mut self, entity: Option<Entity>) {
+        self.selected = entity;
+    }
+
+    /// Values of the inspectable components of an entity, by name
+    pub fn components(&self, world: &World, entity: Entity) -> Vec<(&str, Value)> {
+        self.components
+            .iter()
+            .filter_map(|(name, component)| {
+                (component.read)(world, entity).map(|value| (name.as_str(), value))
+            })
+            .collect()
+    }
+
+    /// Value of a component or of one of its fields ("Transform.translation")
+    pub fn get(&self, world: &World, entity: Entity, path: &str) -> Result<Value, String> {
+        let mut names: Vec<&str> = path.split('.').collect();
+        let component = self.component(names.remove(0))?;
+
+        let mut value =
+            (component.read)(world, entity).ok_or_else(|| format!("The entity has no {}", path))?;
+        field_mut(&mut value, &names).map(|field| field.take())
+    }
+
+    /// Change a component or one of its fields with the `Inspector` resource of the world,
+    /// the value must keep the component valid
+    pub fn set(
+        world: &mut World,
+        entity: Entity,
+        path: &str,
+        new_value: Value,
+    ) -> Result<(), String> {
+        let mut names: Vec<&str> = path.split('.').collect();
+        let component = *world
+            .resource::<Inspector>()
+            .ok_or("No inspector in the world")?
+            .component(names.remove(0))?;
+
+        let mut value =
+            (component.read)(world, entity).ok_or_else(|| format!("The entity has no {}", path))?;
+        *field_mut(&mut value, &names)? = new_value;
+        (component.write)(world, entity, value)
+    }
+
+    fn component(&self, name: &str) -> Result<&ComponentType, String> {
+        self.components
+            .get(name)
+            .ok_or_else(|| format!("Unknown component '{}'", name))
+    }
+}
+
+/// Living entity with the given index
+fn find_entity(world: &World, index: &str) -> Result<Entity, String> {
+    let index: u32 = index
+        .trim_start_matches('#')
+        .parse()
+        .map_err(|_| format!("'{}' isn't an entity index", index))?;
+
+    world
+        .entities()
+        .find(|entity| entity.index() == index)
+        .ok_or_else(|| format!("No entity #{}", index))
+}
+
+/// Entities in depth-first order of the hierarchy, with their depth
+fn entity_tree(world: &World) -> Vec<(Entity, usize)> {
+    let mut children: HashMap<Entity, Vec<Entity>> = HashMap::new();
+    let mut roots = Vec::new();
+    {
+        let parents = world.borrow::<Parent>();
+        for entity in world.entities() {
+            let parent = parents
+                .as_ref()
+                .and_then(|parents| parents.get(entity).map(|parent| parent.0))
+                .filter(|parent| world.is_alive(*parent));
+            match parent {
+                Some(parent) => children.entry(parent).or_default().push(entity),
+                None => roots.push(entity),
+            }
+        }
+    }
+
+    let mut tree = Vec::new();
+    // Reversed to pop the entities in order, the depth stops cycles
+    let mut stack: Vec<(Entity, usize)> = roots.into_iter().rev().map(|e| (e, 0)).collect();
+    while let Some((entity, depth)) = stack.pop() {
+        tree.push((entity, depth));
+        if let Some(children) = children.get(&entity) {
+            if depth < 32 {
+                stack.extend(children.iter().rev().map(|child| (*child, depth + 1)));
+            }
+        }
+    }
+    tree
+}
+
+/// Name of an entity in the list: its index, and its prefab if it has one
+fn entity_label(world: &World, entity: Entity) -> String {
+    let prefab = world.get::<PrefabInstance>(entity).and_then(|instance| {
+        instance
+            .source
+            .path()
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+    });
+
+    match prefab {
+        Some(prefab) => format!("#{} {}", entity.index(), prefab),
+        None => format!("#{}", entity.index()),
+    }
+}
+
+/// Value given to `set`: JSON, or a plain text
+fn parse_value(text: &str) -> Value {
+    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()))
+}
+
+fn register_commands(console: &mut Console) {
+    console.register_command(
+        "inspect",
+        "inspect <entity>: select an entity and show the inspector",
+        |arguments, ctx| {
+            let index = arguments.first().ok_or("Usage: inspect <entity>")?;
+            let entity = find_entity(&ctx.world, index)?;
+
+            let mut inspector = ctx
+                .world
+                .resource_mut::<Inspector>()
+                .ok_or("No inspector")?;
+            inspector.select(Some(entity));
+            inspector.set_open(true);
+            Ok(String::new())
+        },
+    );
+    console.register_command(
+        "get",
+        "get <entity> <Component[.field]>: print a value of an entity",
+        |arguments, ctx| match arguments {
+            [index, path] => {
+                let entity = find_entity(&ctx.world, index)?;
+                let inspector = ctx.world.resource::<Inspector>().ok_or("No inspector")?;
+                let value = inspector.get(&ctx.world, entity, path)?;
+                serde_json::to_string_pretty(&value).map_err(|e| e.to_string())
+            }
+            _ => Err(String::from("Usage: get <entity> <Component[.field]>")),
+        },
+    );
+    console.register_command(
+        "set",
+        "set <entity> <Component[.field]> <value>: change a value of an entity",
+        |arguments, ctx| match arguments {
+            [index, path, value] => {
+                let entity = find_entity(&ctx.world, index)?;
+                Inspector::set(&mut ctx.world, entity, path, parse_value(value))?;
+                Ok(format!("{} = {}", path, value))
+            }
+            _ => Err(String::from(
+                "Usage: set <entity> <Component[.field]> <value>",
+            )),
+        },
+    );
+}
+
+/// Window of the `Inspector`, added with `Engine::add_plugin` after the `ConsolePlugin`
+/// to get the editing commands
+pub struct InspectorPlugin {
+    font: Arc<BitmapFont>,
+    toggle_key: Keycode,
+    /// Moved to the world when the plugin is added
+    inspector: Option<Inspector>,
+    /// Lines shown, built by `on_update`
+    content: String,
+
+    background: Option<PrimitiveObject>,
+    text: Option<BitmapTextObject>,
+}
+
+impl InspectorPlugin {
+    /// Inspector drawn with a bitmap font, shown with F2
+    pub fn new(font: Arc<BitmapFont>) -> Self {
+        Self {
+            font,
+            toggle_key: Keycode::F2,
+            inspector: Some(Inspector::new()),
+            content: String::new(),
+            background: None,
+            text: None,
+        }
+    }
+
+    pub fn with_toggle_key(mut self, key: Keycode) -> Self {
+        self.toggle_key = key;
+        self
+    }
+
+    /// Show and edit a component type under a name (`Transform` is always there)
+    pub fn with_component<T>(mut self, name: &str) -> Self
+    where
+        T: Serialize + DeserializeOwned + 'static,
+    {
+        if let Some(inspector) = self.inspector.as_mut() {
+            inspector.register_component::<T>(name);
+        }
+        self
+    }
+
+    /// Select the entity before or after the selected one in the list
+    fn move_selection(ctx: &mut Context, offset: isize) {
+        let tree = entity_tree(&ctx.world);
+        let mut inspector = match ctx.world.resource_mut::<Inspector>() {
+            Some(inspector) => inspector,
+            None => return,
+        };
+        if tree.is_empty() {
+            inspector.select(None);
+            return;
+        }
+
+        let current = inspector
+            .selected()
+            .and_then(|selected| tree.iter().position(|(entity, _)| *entity == selected));
+        let index = match current {
+            Some(index) => (index as isize + offset)
+                .max(0)
+                .min(tree.len() as isize - 1) as usize,
+            None => 0,
+        };
+        inspector.select(Some(tree[index].0));
+    }
+
+    fn describe(inspector: &Inspector, world: &World) -> String {
+        let tree = entity_tree(world);
+        let selected = inspector
+            .selected()
+            .filter(|entity| world.is_alive(*entity));
+
+        let mut content = format!("Entities ({})", tree.len());
+        let position = selected
+            .and_then(|selected| tree.iter().position(|(entity, _)| *entity == selected))
+            .unwrap_or(0);
+        let first = position.saturating_sub(LISTED_ENTITIES / 2);
+        if first > 0 {
+            content += "\n  ...";
+        }
+        for (entity, depth) in tree.iter().skip(first).take(LISTED_ENTITIES) {
+            let marker = if Some(*entity) == selected { ">" } else { " " };
+            content += &format!(
+                "\n{} {}{}",
+                marker,
+                "  ".repeat(*depth),
+                entity_label(world, *entity)
+            );
+        }
+        if first + LISTED_ENTITIES < tree.len() {
+            content += "\n  ...";
+        }
+
+        match selected {
+            Some(entity) => {
+                content += &format!("\n\n{}", entity_label(world, entity));
+                let components = inspector.components(world, entity);
+                if components.is_empty() {
+                    content += "\nNo inspectable component";
+                }
+                for (name, value) in components {
+                    let value = serde_json::to_string_pretty(&value).unwrap_or_default();
+                    content += &format!("\n{}: {}", name, value);
+                }
+            }
+            None => content += "\n\nPage Up / Page Down to select an entity",
+        }
+
+        content
+    }
+}
+
+impl EnginePlugin for InspectorPlugin {
+    fn name(&self) -> &str {
+        "inspector"
+    }
+
+    fn build(&mut self, ctx: &mut Context) {
+        if let Some(inspector) = self.inspector.take() {
+            ctx.world.insert_resource(inspector);
+        }
+        if let Some(mut console) = ctx.world.resource_mut::<Console>() {
+            register_commands(&mut console);
+        }
+    }
+
+    fn on_update(&mut self, _time: &Time, ctx: &mut Context) {
+        let inspector = match ctx.world.resource::<Inspector>() {
+            Some(inspector) => inspector,
+            None => return,
+        };
+
+        if inspector.is_open() {
+            self.content = Self::describe(&inspector, &ctx.world);
+        } else {
+            self.content.clear();
+        }
+    }
+
+    fn on_event(&mut self, event: &EngineEvent, ctx: &mut Context) {
+        let key = match event {
+            EngineEvent::Input(InputEvent::KeyDown { key, .. }) => *key,
+            _ => return,
+        };
+        let open = match ctx.world.resource_mut::<Inspector>() {
+            Some(mut inspector) => {
+                if key == self.toggle_key {
+                    let open = !inspector.is_open();
+                    inspector.set_open(open);
+                    return;
+                }
+                inspector.is_open()
+            }
+            None => return,
+        };
+
+        match key {
+            Keycode::PageUp if open => Self::move_selection(ctx, -1),
+            Keycode::PageDown if open => Self::move_selection(ctx, 1),
+            _ => {}
+        }
+    }
+
+    fn on_render(&mut self, frame: &mut Frame) {
+        let video = &mut *frame.video;
+
+        // Only filled while open
+        if self.content.is_empty() {
+            if let Some(background) = &self.background {
+                background.get_mut().set_visible(false);
+            }
+            if let Some(text) = &self.text {
+                text.get_mut().set_visible(false);
+            }
+            return;
+        }
+
+        if self.background.is_none() {
+            match video.new_rectangle(
+                Vec2::new(1.0, 1.0),
+                Color::rgba(0.05, 0.05, 0.1, 0.8),
+                Vec2::new(0.0, 0.0),
+                INSPECTOR_Z_INDEX,
+            ) {
+                Ok(background) => self.background = Some(background),
+                Err(e) => crash::log(&format!("Couldn't create the inspector background: {}", e)),
+            }
+        }
+        if self.text.is_none() {
+            match self.font.new_text(video, "", INSPECTOR_Z_INDEX + 1) {
+                Ok(text) => self.text = Some(text),
+                Err(e) => {
+                    crash::log(&format!("Couldn't create the inspector text: {}", e));
+                    self.content.clear();
+                    return;
+                }
+            }
+        }
+
+        // Sizes in pixels whatever the camera zoom, on the right of the view
+        let view = video.gl_handler.view_bounds();
+        let view_size = view.max - view.min;
+        let (window_width, _) = video.get_window_size();
+        let pixel = view_size.x / window_width.max(1) as f32;
+
+        let text_size = self.font.measure(&self.content) * pixel;
+        let panel_size = text_size + Vec2::new(2.0, 2.0) * PADDING * pixel;
+        let left = view.max.x - MARGIN * pixel - panel_size.x;
+        let top = view.min.y + MARGIN * pixel;
+
+        {
+            let mut background = self.background.as_ref().unwrap().get_mut();
+            background.global_position = Vec2::new(left, top) + panel_size / 2.0;
+            background.scale = panel_size / 2.0;
+            background.set_visible(true);
+        }
+
+        let mut text = self.text.as_ref().unwrap().get_mut();
+        text.global_position = Vec2::new(left, top) + Vec2::new(PADDING, PADDING) * pixel;
+        text.scale = Vec2::new(pixel, pixel) / WORLD_UNITS_PER_PIXEL;
+        text.set_text(&self.content);
+        text.set_visible(true);
+    }
+}
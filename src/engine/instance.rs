@@ -0,0 +1,262 @@
+//! Process-wide single-instance guard, with launch argument forwarding to the running instance
+//!
+//! The lock is a local socket named after the application and the user, a Unix domain socket in the
+//! runtime directory of the user or a named pipe on Windows: the other users of the machine run
+//! their own instance, and can't send arguments to this one.
+
+// standard imports
+use std::env;
+use std::io::{self, Read, Write};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// other imports
+use interprocess::local_socket::{LocalSocketListener, LocalSocketStream};
+
+// import the engine modules
+use super::pack::content_hash;
+
+/// Separator between forwarded arguments (can't appear in OS arguments)
+const ARG_SEPARATOR: char = '\0';
+/// Reply of the primary instance to launches of the same application
+const ACK: u8 = 1;
+/// Reply of the primary instance to launches of another application with the same socket name
+const NACK: u8 = 0;
+/// Longest wait of a launch for the reply of the primary instance, and of the primary instance
+/// for the whole message of a launch
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(1);
+/// Biggest forwarded message accepted, launch arguments are short
+const MAX_MESSAGE_LENGTH: usize = 1 << 20;
+
+/// Result of trying to become the only running instance
+pub enum InstanceStatus {
+    /// This process is the first instance, the guard must be kept alive for the whole run
+    Primary(InstanceGuard),
+    /// Another instance is already running and has received the launch arguments
+    Secondary,
+}
+
+/// Launch connected to the primary instance, its message isn't complete yet
+struct PendingLaunch {
+    stream: LocalSocketStream,
+    /// Length prefixed message received so far
+    buffer: Vec<u8>,
+    connected: Instant,
+}
+
+/// Lock held by the primary instance, listening for arguments sent by later launches
+pub struct InstanceGuard {
+    listener: LocalSocketListener,
+    app_id: String,
+    launches: Vec<PendingLaunch>,
+    /// Socket file removed when the guard is dropped
+    #[cfg(unix)]
+    path: PathBuf,
+}
+
+impl InstanceGuard {
+    /// Try to become the single instance identified by `app_id` for the current user.
+    /// If another instance already holds the lock, `args` are forwarded to it.
+    pub fn acquire(app_id: &str, args: &[String]) -> io::Result<InstanceStatus> {
+        let name = instance_socket_name(app_id);
+
+        let listener = match LocalSocketListener::bind(name.as_str()) {
+            Ok(listener) => listener,
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+                match LocalSocketStream::connect(name.as_str()) {
+                    Ok(stream) => {
+                        return if forward_args(stream, app_id, args)? {
+                            Ok(InstanceStatus::Secondary)
+                        } else {
+                            Err(io::Error::new(
+                                io::ErrorKind::AddrInUse,
+                                "The single instance socket is held by another application",
+                            ))
+                        };
+                    }
+                    // Left behind by an instance that didn't stop properly, nothing listens to it anymore
+                    #[cfg(unix)]
+                    Err(_) => {
+                        std::fs::remove_file(&name)?;
+                        LocalSocketListener::bind(name.as_str())?
+                    }
+                    #[cfg(not(unix))]
+                    Err(_) => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        };
+        listener.set_nonblocking(true)?;
+
+        Ok(InstanceStatus::Primary(Self {
+            listener,
+            app_id: app_id.to_string(),
+            launches: Vec::new(),
+            #[cfg(unix)]
+            path: PathBuf::from(name),
+        }))
+    }
+
+    /// Fetch the argument lists forwarded by other launches since the last call.
+    /// Never blocks, the messages still incomplete are read by the next calls
+    pub fn poll_forwarded_args(&mut self) -> Vec<Vec<String>> {
+        loop {
+            match self.listener.accept() {
+                Ok(stream) => match stream.set_nonblocking(true) {
+                    Ok(()) => self.launches.push(PendingLaunch {
+                        stream,
+                        buffer: Vec::new(),
+                        connected: Instant::now(),
+                    }),
+                    Err(e) => eprintln!("Couldn't set up forwarded instance connection: {}", e),
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    eprintln!("Couldn't accept forwarded instance connection: {}", e);
+                    break;
+                }
+            }
+        }
+
+        let mut forwarded = Vec::new();
+        let app_id = &self.app_id;
+        self.launches
+            .retain_mut(|launch| match read_args(launch, app_id) {
+                Ok(Some(args)) => {
+                    forwarded.push(args);
+                    false
+                }
+                Ok(None) if launch.connected.elapsed() > FORWARD_TIMEOUT => {
+                    eprintln!("Forwarded instance arguments timed out");
+                    false
+                }
+                Ok(None) => true,
+                Err(e) => {
+                    eprintln!("Couldn't read forwarded instance arguments: {}", e);
+                    false
+                }
+            });
+
+        forwarded
+    }
+}
+
+#[cfg(unix)]
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Local socket path derived from the application id and the user, binding it acts as the process lock.
+/// The hash is stable between builds, every version of the application finds the same socket
+#[cfg(unix)]
+fn instance_socket_name(app_id: &str) -> String {
+    let hash = content_hash(app_id.as_bytes());
+
+    // Only the user can open their runtime directory, the temporary directory is shared
+    let path = match env::var_os("XDG_RUNTIME_DIR") {
+        Some(directory) => {
+            PathBuf::from(directory).join(format!("magma-instance-{:016x}.sock", hash))
+        }
+        None => env::temp_dir().join(format!("magma-instance-{}-{:016x}.sock", user_name(), hash)),
+    };
+
+    path.to_string_lossy().into_owned()
+}
+
+/// Pipe name derived from the application id and the user, binding it acts as the process lock
+#[cfg(not(unix))]
+fn instance_socket_name(app_id: &str) -> String {
+    format!(
+        "magma-instance-{}-{:016x}",
+        user_name(),
+        content_hash(app_id.as_bytes())
+    )
+}
+
+fn user_name() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect()
+}
+
+/// Send the arguments after the application id, true if the listener is an instance of the application
+fn forward_args(mut stream: LocalSocketStream, app_id: &str, args: &[String]) -> io::Result<bool> {
+    let mut message = app_id.to_string();
+    for arg in args {
+        message.push(ARG_SEPARATOR);
+        message += arg;
+    }
+    stream.write_all(&(message.len() as u32).to_le_bytes())?;
+    stream.write_all(message.as_bytes())?;
+
+    // The primary instance answers on its next poll, unless it's stuck
+    stream.set_nonblocking(true)?;
+    let deadline = Instant::now() + FORWARD_TIMEOUT;
+    let mut reply = [NACK];
+    loop {
+        match stream.read(&mut reply) {
+            Ok(0) => return Ok(false),
+            Ok(_) => return Ok(reply[0] == ACK),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock && Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(10))
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "The running instance didn't answer",
+                ))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Read what a launch sent so far, its arguments once the message is complete.
+/// Messages not sent by a launch of the application are an error
+fn read_args(launch: &mut PendingLaunch, app_id: &str) -> io::Result<Option<Vec<String>>> {
+    let mut chunk = [0; 4096];
+    loop {
+        match launch.stream.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(length) => launch.buffer.extend_from_slice(&chunk[..length]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    if launch.buffer.len() < 4 {
+        return Ok(None);
+    }
+    let mut length = [0; 4];
+    length.copy_from_slice(&launch.buffer[..4]);
+    let length = u32::from_le_bytes(length) as usize;
+    if length > MAX_MESSAGE_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Forwarded message too long",
+        ));
+    }
+    if launch.buffer.len() < 4 + length {
+        return Ok(None);
+    }
+
+    let message = String::from_utf8_lossy(&launch.buffer[4..4 + length]);
+    let mut parts = message.split(ARG_SEPARATOR);
+    if parts.next() != Some(app_id) {
+        launch.stream.write_all(&[NACK])?;
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Not sent by a launch of this application",
+        ));
+    }
+    launch.stream.write_all(&[ACK])?;
+
+    Ok(Some(parts.map(String::from).collect()))
+}
@@ -0,0 +1,219 @@
+//! Local IPC bridge to let external tools (editors, profilers, CI harnesses) talk to a running engine
+//!
+//! The protocol is line based plain text over a local socket, a Unix domain socket or a named pipe
+//! on Windows: unlike a loopback port, it can't be reached by the other users of the machine.
+//! - `state` replies with a single `state key=value ...` line
+//! - `cmd <command>` runs a command of the `Console`, as if typed by the player
+//! - `stats on` / `stats off` toggles the per-frame `stats ...` stream
+//! - `quit` asks the engine to stop
+//!
+//! The server needs the `ipc` feature, the snapshot is also used by the debug server.
+
+// standard imports
+#[cfg(feature = "ipc")]
+use std::io::{self, Read, Write};
+#[cfg(all(unix, feature = "ipc"))]
+use std::path::PathBuf;
+
+// other imports
+#[cfg(feature = "ipc")]
+use interprocess::local_socket::{LocalSocketListener, LocalSocketStream};
+
+/// Longest line accepted from the IPC clients, the client is dropped past it
+#[cfg(feature = "ipc")]
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// Snapshot of the engine state sent to the IPC clients
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IpcSnapshot {
+    pub frame: u64,
+    pub framerate: u16,
+    pub framerate_limit: u16,
+    pub delta: f32,
+    pub window_size: (u32, u32),
+}
+
+#[cfg(feature = "ipc")]
+impl IpcSnapshot {
+    fn to_line(&self, header: &str) -> String {
+        format!(
+            "{} frame={} fps={} fps_limit={} delta={} window={}x{}\n",
+            header,
+            self.frame,
+            self.framerate,
+            self.framerate_limit,
+            self.delta,
+            self.window_size.0,
+            self.window_size.1
+        )
+    }
+}
+
+/// Requests coming from the IPC clients which the engine must act upon
+#[cfg(feature = "ipc")]
+#[derive(Clone, Debug, PartialEq)]
+pub enum IpcRequest {
+    Command(String),
+    Quit,
+}
+
+#[cfg(feature = "ipc")]
+struct IpcClient {
+    stream: LocalSocketStream,
+    /// Received bytes after the last complete line, kept until its `\n` arrives
+    partial_line: Vec<u8>,
+    streaming_stats: bool,
+    closed: bool,
+}
+
+#[cfg(feature = "ipc")]
+impl IpcClient {
+    fn new(stream: LocalSocketStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+
+        Ok(Self {
+            stream,
+            partial_line: Vec::new(),
+            streaming_stats: false,
+            closed: false,
+        })
+    }
+
+    /// Read every complete line currently available, the rest waits for the next reads
+    fn read_lines(&mut self) -> Vec<String> {
+        let mut chunk = [0; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(length) => self.partial_line.extend_from_slice(&chunk[..length]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+
+        let mut lines = Vec::new();
+        while let Some(end) = self.partial_line.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.partial_line.drain(..=end).collect();
+            lines.push(String::from_utf8_lossy(&line).trim().to_string());
+        }
+        if self.partial_line.len() > MAX_LINE_LENGTH {
+            self.closed = true;
+        }
+
+        lines
+    }
+
+    fn send(&mut self, line: &str) {
+        if self.stream.write_all(line.as_bytes()).is_err() {
+            self.closed = true;
+        }
+    }
+}
+
+/// Non-blocking server accepting IPC clients on a local socket
+#[cfg(feature = "ipc")]
+pub struct IpcServer {
+    listener: LocalSocketListener,
+    clients: Vec<IpcClient>,
+    /// Socket file removed when the server stops
+    #[cfg(unix)]
+    path: PathBuf,
+}
+
+#[cfg(feature = "ipc")]
+impl IpcServer {
+    /// Start listening on a local socket: the path of the socket file on Unix, the name of the
+    /// pipe on Windows (`\\.\pipe\<name>`)
+    pub fn bind(name: &str) -> io::Result<Self> {
+        // Left behind by an engine that didn't stop properly, nothing listens to it anymore
+        #[cfg(unix)]
+        if LocalSocketStream::connect(name).is_err() {
+            let _ = std::fs::remove_file(name);
+        }
+
+        let listener = LocalSocketListener::bind(name)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+            #[cfg(unix)]
+            path: PathBuf::from(name),
+        })
+    }
+
+    /// Accept new clients, answer state queries and collect the requests for the engine
+    pub fn poll(&mut self, snapshot: &IpcSnapshot) -> Vec<IpcRequest> {
+        self.accept_clients();
+
+        let mut requests = Vec::new();
+        for client in self.clients.iter_mut() {
+            for line in client.read_lines() {
+                let (keyword, argument) = match line.find(' ') {
+                    Some(i) => (&line[..i], line[i + 1..].trim()),
+                    None => (line.as_str(), ""),
+                };
+
+                match keyword {
+                    "" => {}
+                    "state" => client.send(&snapshot.to_line("state")),
+                    "cmd" if !argument.is_empty() => {
+                        requests.push(IpcRequest::Command(argument.to_string()));
+                        client.send("ok\n");
+                    }
+                    "stats" if argument == "on" || argument == "off" => {
+                        client.streaming_stats = argument == "on";
+                        client.send("ok\n");
+                    }
+                    "quit" => {
+                        requests.push(IpcRequest::Quit);
+                        client.send("ok\n");
+                    }
+                    _ => client.send(&format!("error unknown request '{}'\n", line)),
+                }
+            }
+        }
+
+        self.clients.retain(|c| !c.closed);
+
+        requests
+    }
+
+    /// Send the frame stats to every client that asked for the stream
+    pub fn publish_stats(&mut self, snapshot: &IpcSnapshot) {
+        let line = snapshot.to_line("stats");
+
+        for client in self.clients.iter_mut().filter(|c| c.streaming_stats) {
+            client.send(&line);
+        }
+    }
+
+    fn accept_clients(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok(stream) => match IpcClient::new(stream) {
+                    Ok(client) => self.clients.push(client),
+                    Err(e) => eprintln!("Couldn't set up IPC client: {}", e),
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    eprintln!("Couldn't accept IPC client: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(unix, feature = "ipc"))]
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
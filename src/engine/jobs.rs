@@ -0,0 +1,258 @@
+//! Work-stealing thread pool, to run asset decoding, pathfinding and generation off the main thread
+//!
+//! Jobs spawned with `spawn_frame` are awaited by the engine before rendering the frame,
+//! the ones spawned with `spawn` can take as many frames as they need.
+
+// standard imports
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+#[derive(Default)]
+struct Counters {
+    /// Jobs waiting in the queues
+    queued: usize,
+    /// Jobs not finished yet, queued or running
+    pending: usize,
+    /// Pending jobs that must finish before the frame is rendered
+    frame_pending: usize,
+    shutdown: bool,
+}
+
+struct Shared {
+    /// One queue per worker, the owner pops from the front and the others steal from the back
+    queues: Vec<Mutex<VecDeque<Job>>>,
+    counters: Mutex<Counters>,
+    work_available: Condvar,
+    job_finished: Condvar,
+    next_queue: AtomicUsize,
+}
+
+impl Shared {
+    /// Take a job from the worker's own queue, or steal one from the others
+    fn take_job(&self, worker: usize) -> Option<Job> {
+        let count = self.queues.len();
+
+        // Only one queue locked at a time, two workers stealing from each other can't deadlock
+        let own = self.queues[worker].lock().unwrap().pop_front();
+        let job = match own {
+            Some(job) => job,
+            None => (1..count).find_map(|offset| {
+                let victim = (worker + offset) % count;
+                self.queues[victim].lock().unwrap().pop_back()
+            })?,
+        };
+        self.counters.lock().unwrap().queued -= 1;
+
+        Some(job)
+    }
+
+    fn run_worker(&self, worker: usize) {
+        loop {
+            if let Some(job) = self.take_job(worker) {
+                job();
+                continue;
+            }
+
+            let mut counters = self.counters.lock().unwrap();
+            while counters.queued == 0 && !counters.shutdown {
+                counters = self.work_available.wait(counters).unwrap();
+            }
+            // Queued jobs are still run when shutting down
+            if counters.queued == 0 && counters.shutdown {
+                return;
+            }
+        }
+    }
+}
+
+/// Result of a spawned job
+pub struct JobHandle<T> {
+    receiver: Receiver<T>,
+    result: Option<T>,
+}
+
+impl<T> JobHandle<T> {
+    /// Check if the job finished, without blocking
+    pub fn is_finished(&mut self) -> bool {
+        self.poll();
+        self.result.is_some()
+    }
+
+    /// Take the result if the job finished, without blocking
+    pub fn try_take(&mut self) -> Option<T> {
+        self.poll();
+        self.result.take()
+    }
+
+    /// Block until the job finished, an error if it panicked
+    pub fn join(self) -> Result<T, String> {
+        match self.result {
+            Some(result) => Ok(result),
+            None => self
+                .receiver
+                .recv()
+                .map_err(|_| String::from("Job panicked")),
+        }
+    }
+
+    fn poll(&mut self) {
+        if self.result.is_none() {
+            match self.receiver.try_recv() {
+                Ok(result) => self.result = Some(result),
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => {}
+            }
+        }
+    }
+}
+
+/// Pool of worker threads running the jobs
+pub struct JobPool {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl JobPool {
+    /// Start `threads` workers (at least one)
+    pub fn new(threads: usize) -> Self {
+        let threads = threads.max(1);
+        let shared = Arc::new(Shared {
+            queues: (0..threads).map(|_| Mutex::new(VecDeque::new())).collect(),
+            counters: Mutex::new(Counters::default()),
+            work_available: Condvar::new(),
+            job_finished: Condvar::new(),
+            next_queue: AtomicUsize::new(0),
+        });
+
+        let workers = (0..threads)
+            .map(|worker| {
+                let shared = shared.clone();
+                thread::Builder::new()
+                    .name(format!("job-worker-{}", worker))
+                    .spawn(move || shared.run_worker(worker))
+                    .expect("Couldn't spawn job worker thread")
+            })
+            .collect();
+
+        Self { shared, workers }
+    }
+
+    /// One worker per core, leaving one to the main thread
+    pub fn with_default_threads() -> Self {
+        let cores = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4);
+
+        Self::new(cores.saturating_sub(1))
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Run a job in the background, for as many frames as it takes
+    pub fn spawn<T, F>(&self, job: F) -> JobHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        self.submit(job, false)
+    }
+
+    /// Run a job that the engine waits for before rendering the current frame
+    pub fn spawn_frame<T, F>(&self, job: F) -> JobHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        self.submit(job, true)
+    }
+
+    fn submit<T, F>(&self, job: F, frame: bool) -> JobHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let shared = Arc::downgrade(&self.shared);
+
+        let job: Job = Box::new(move || {
+            // A panicking job only drops its sender, the worker keeps going
+            if let Ok(result) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                let _ = sender.send(result);
+            }
+
+            if let Some(shared) = shared.upgrade() {
+                let mut counters = shared.counters.lock().unwrap();
+                counters.pending -= 1;
+                if frame {
+                    counters.frame_pending -= 1;
+                }
+                shared.job_finished.notify_all();
+            }
+        });
+
+        let queue =
+            self.shared.next_queue.fetch_add(1, Ordering::Relaxed) % self.shared.queues.len();
+        {
+            // Counted while queuing, so a worker can't take the job before it's counted
+            let mut counters = self.shared.counters.lock().unwrap();
+            self.shared.queues[queue].lock().unwrap().push_back(job);
+            counters.queued += 1;
+            counters.pending += 1;
+            if frame {
+                counters.frame_pending += 1;
+            }
+        }
+        self.shared.work_available.notify_one();
+
+        JobHandle {
+            receiver,
+            result: None,
+        }
+    }
+
+    /// Jobs not finished yet
+    pub fn pending(&self) -> usize {
+        self.shared.counters.lock().unwrap().pending
+    }
+
+    /// Block until every job finished
+    pub fn wait_all(&self) {
+        let mut counters = self.shared.counters.lock().unwrap();
+        while counters.pending > 0 {
+            counters = self.shared.job_finished.wait(counters).unwrap();
+        }
+    }
+
+    /// Block until the jobs spawned with `spawn_frame` finished (called by the engine before rendering)
+    pub fn wait_frame_jobs(&self) {
+        let mut counters = self.shared.counters.lock().unwrap();
+        while counters.frame_pending > 0 {
+            counters = self.shared.job_finished.wait(counters).unwrap();
+        }
+    }
+}
+
+impl Default for JobPool {
+    fn default() -> Self {
+        Self::with_default_threads()
+    }
+}
+
+impl Drop for JobPool {
+    /// Finish the queued jobs, then stop the workers
+    fn drop(&mut self) {
+        self.shared.counters.lock().unwrap().shutdown = true;
+        self.shared.work_available.notify_all();
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Move-and-slide controller for characters moved by the game code instead of a physics engine
+//!
+//! The motion is split in two passes: along the ground, where the character slides along
+//! walls, climbs walkable slopes and small steps, then along the up axis, where it lands on
+//! the ground. Any `CollisionQuery` works as the level (the world, a tile grid, the physics).
+//! Coordinates follow the screen, with the y axis pointing down.
+
+// import the engine modules
+use super::collision::{CollisionQuery, QueryFilter, Shape, ShapeHit};
+use super::ecs::Entity;
+use super::math::{Deg, Rad, Vec2};
+
+// other imports
+use cgmath::InnerSpace;
+
+/// Motions shorter than this are done
+const MIN_MOTION: f32 = 1e-4;
+
+#[derive(Clone, Debug)]
+pub struct KinematicController {
+    /// Shape of the character, centered on the origin
+    pub shape: Shape,
+    pub position: Vec2,
+    /// Unit vector pointing away from the ground
+    pub up: Vec2,
+    /// Steepest slope the character stands on and walks up, the others are walls
+    pub max_slope: Rad<f32>,
+    /// Tallest obstacle climbed without jumping, 0.0 to disable the steps
+    pub step_height: f32,
+    /// Distance the character sticks to the ground when it goes down slopes and steps
+    pub snap_distance: f32,
+    /// Gap kept with the surfaces, so moves along them don't catch on them
+    pub skin: f32,
+    /// Surfaces slid along in a single move
+    pub max_slides: usize,
+    /// Colliders blocking the character, exclude the entity of the character if it has one
+    pub filter: QueryFilter,
+
+    grounded: bool,
+    ground_normal: Vec2,
+    ground_entity: Option<Entity>,
+    collisions: Vec<ShapeHit>,
+}
+
+impl KinematicController {
+    pub fn new(shape: impl Into<Shape>, position: Vec2) -> Self {
+        Self {
+            shape: shape.into(),
+            position,
+            up: Vec2::new(0.0, -1.0),
+            max_slope: Deg(45.0).into(),
+            step_height: 0.0,
+            snap_distance: 4.0,
+            skin: 0.1,
+            max_slides: 4,
+            filter: QueryFilter::new(),
+            grounded: false,
+            ground_normal: Vec2::new(0.0, -1.0),
+            ground_entity: None,
+            collisions: Vec::new(),
+        }
+    }
+
+    pub fn with_step_height(mut self, step_height: f32) -> Self {
+        self.step_height = step_height;
+        self
+    }
+
+    pub fn with_max_slope(mut self, max_slope: impl Into<Rad<f32>>) -> Self {
+        self.max_slope = max_slope.into();
+        self
+    }
+
+    pub fn with_filter(mut self, filter: QueryFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Standing on a walkable surface after the last move
+    pub fn is_grounded(&self) -> bool {
+        self.grounded
+    }
+
+    /// Normal of the ground stood on, `up` in the air
+    pub fn ground_normal(&self) -> Vec2 {
+        if self.grounded {
+            self.ground_normal
+        } else {
+            self.up
+        }
+    }
+
+    /// Entity of the collider stood on, None on tiles or in the air
+    pub fn ground_entity(&self) -> Option<Entity> {
+        self.ground_entity
+    }
+
+    /// Surfaces hit by the last move
+    pub fn collisions(&self) -> &[ShapeHit] {
+        &self.collisions
+    }
+
+    /// Shape placed at the position
+    pub fn placed_shape(&self) -> Shape {
+        self.shape.translated(self.position)
+    }
+
+    /// Move by `motion` (usually the velocity times the delta), sliding along the surfaces hit.
+    /// Returns the motion actually done, divide it by the delta for the new velocity
+    pub fn move_and_slide(&mut self, motion: Vec2, level: &dyn CollisionQuery) -> Vec2 {
+        let start = self.position;
+        let was_grounded = self.grounded;
+        self.collisions.clear();
+        self.grounded = false;
+        self.ground_entity = None;
+
+        let vertical = self.up * motion.dot(self.up);
+        let lateral = motion - vertical;
+
+        self.slide(lateral, level, was_grounded, false);
+        self.slide(vertical, level, false, true);
+
+        // Stay on the ground going down slopes and steps, unless jumping
+        if was_grounded && !self.grounded && motion.dot(self.up) <= 0.0 {
+            self.snap_to_ground(level);
+        }
+
+        self.position - start
+    }
+
+    fn slide(
+        &mut self,
+        motion: Vec2,
+        level: &dyn CollisionQuery,
+        can_step: bool,
+        stop_on_floor: bool,
+    ) {
+        let mut remaining = motion;
+        let mut stepped = false;
+
+        for _ in 0..self.max_slides {
+            if remaining.magnitude() < MIN_MOTION {
+                break;
+            }
+
+            let hit = match level.shape_cast(&self.placed_shape(), remaining, &self.filter) {
+                Some(hit) => hit,
+                None => {
+                    self.position += remaining;
+                    break;
+                }
+            };
+
+            self.advance(remaining * hit.time);
+            self.collisions.push(hit);
+            let rest = remaining * (1.0 - hit.time);
+
+            if self.is_floor(hit.normal) {
+                self.set_ground(&hit);
+                if stop_on_floor {
+                    break;
+                }
+            } else if can_step && !stepped && self.is_wall(hit.normal) {
+                stepped = true;
+                if let Some(position) = self.try_step(rest, level) {
+                    self.position = position;
+                    break;
+                }
+            }
+
+            // Keep the part of the motion going along the surface
+            remaining = rest - hit.normal * rest.dot(hit.normal);
+
+            // Steep slopes can't be climbed, only slid down
+            if !self.is_floor(hit.normal)
+                && remaining.dot(self.up) > 0.0
+                && motion.dot(self.up) <= 0.0
+            {
+                remaining -= self.up * remaining.dot(self.up);
+            }
+        }
+    }
+
+    /// Move up to a surface, stopping `skin` before it
+    fn advance(&mut self, travel: Vec2) {
+        let length = travel.magnitude();
+        if length > self.skin {
+            self.position += travel * ((length - self.skin) / length);
+        }
+    }
+
+    /// Climb an obstacle: up by the step height, forward, then down on a walkable surface
+    fn try_step(&self, motion: Vec2, level: &dyn CollisionQuery) -> Option<Vec2> {
+        if self.step_height <= 0.0 {
+            return None;
+        }
+
+        let shape = self.placed_shape();
+        let rise = self.up * self.step_height;
+        let rise = match level.shape_cast(&shape, rise, &self.filter) {
+            Some(hit) => rise * hit.time,
+            None => rise,
+        };
+
+        // Still blocked when raised, too tall
+        let raised = shape.translated(rise);
+        if level.shape_cast(&raised, motion, &self.filter).is_some() {
+            return None;
+        }
+
+        let forward = shape.translated(rise + motion);
+        let hit = level.shape_cast(&forward, -rise - self.up * self.skin, &self.filter)?;
+        if !self.is_floor(hit.normal) {
+            return None;
+        }
+
+        let drop = (-rise - self.up * self.skin) * hit.time;
+        Some(self.position + rise + motion + drop + self.up * self.skin)
+    }
+
+    fn snap_to_ground(&mut self, level: &dyn CollisionQuery) {
+        let probe = -self.up * (self.snap_distance + self.skin);
+        if let Some(hit) = level.shape_cast(&self.placed_shape(), probe, &self.filter) {
+            if self.is_floor(hit.normal) {
+                self.advance(probe * hit.time);
+                self.set_ground(&hit);
+            }
+        }
+    }
+
+    fn set_ground(&mut self, hit: &ShapeHit) {
+        self.grounded = true;
+        self.ground_normal = hit.normal;
+        self.ground_entity = hit.entity;
+    }
+
+    fn is_floor(&self, normal: Vec2) -> bool {
+        normal.dot(self.up) >= self.max_slope.0.cos() - MIN_MOTION
+    }
+
+    /// Surfaces facing the side, neither floors nor ceilings
+    fn is_wall(&self, normal: Vec2) -> bool {
+        normal.dot(self.up).abs() < self.max_slope.0.cos()
+    }
+}
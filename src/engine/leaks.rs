@@ -0,0 +1,195 @@
+//! Debug-build tracking of live GPU resources and asset handles
+//!
+//! Every tracked object owns a `ResourceTracker`, registered on creation and removed on drop.
+//! Resources shared through an `Arc` of a foreign type (vulkano images and buffers) are tracked
+//! with `track_shared` instead, until their last strong reference is dropped.
+//! Whatever is still registered after the engine is dropped has leaked.
+//! In release builds the tracker is zero-sized and does nothing.
+
+// standard imports
+#[cfg(debug_assertions)]
+use std::backtrace::Backtrace;
+#[cfg(debug_assertions)]
+use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(debug_assertions)]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+#[cfg(debug_assertions)]
+use std::sync::Mutex;
+
+/// Category of a tracked resource
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceKind {
+    VertexBuffer,
+    IndexBuffer,
+    Texture,
+    DrawObject,
+    AssetHandle,
+}
+
+#[cfg(debug_assertions)]
+struct LiveResource {
+    kind: ResourceKind,
+    label: String,
+    backtrace: Backtrace,
+}
+
+#[cfg(debug_assertions)]
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(debug_assertions)]
+static LIVE_RESOURCES: Mutex<BTreeMap<u64, LiveResource>> = Mutex::new(BTreeMap::new());
+
+/// Tracker of a resource that can't own it, released once `is_alive` returns false
+#[cfg(debug_assertions)]
+struct SharedResource {
+    is_alive: Box<dyn Fn() -> bool + Send>,
+    _tracker: ResourceTracker,
+}
+
+#[cfg(debug_assertions)]
+static SHARED_RESOURCES: Mutex<Vec<SharedResource>> = Mutex::new(Vec::new());
+
+/// Token registering a live resource for as long as it exists
+pub struct ResourceTracker {
+    #[cfg(debug_assertions)]
+    id: u64,
+    #[cfg(debug_assertions)]
+    kind: ResourceKind,
+    #[cfg(debug_assertions)]
+    label: String,
+}
+
+impl ResourceTracker {
+    /// Register a new live resource with a label to recognize it in the report
+    #[cfg(debug_assertions)]
+    pub fn new(kind: ResourceKind, label: &str) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+        let resource = LiveResource {
+            kind,
+            label: label.to_string(),
+            backtrace: Backtrace::capture(),
+        };
+        if let Ok(mut map) = LIVE_RESOURCES.lock() {
+            map.insert(id, resource);
+        }
+
+        Self {
+            id,
+            kind,
+            label: label.to_string(),
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    pub fn new(_kind: ResourceKind, _label: &str) -> Self {
+        Self {}
+    }
+}
+
+impl Clone for ResourceTracker {
+    /// A cloned resource is a new resource that must be released on its own
+    #[cfg(debug_assertions)]
+    fn clone(&self) -> Self {
+        Self::new(self.kind, &self.label)
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn clone(&self) -> Self {
+        Self {}
+    }
+}
+
+#[cfg(debug_assertions)]
+impl Drop for ResourceTracker {
+    fn drop(&mut self) {
+        if let Ok(mut map) = LIVE_RESOURCES.lock() {
+            map.remove(&self.id);
+        }
+    }
+}
+
+/// Track a resource shared through an `Arc` that can't own a `ResourceTracker`,
+/// it's registered until the last strong reference is dropped
+#[cfg(debug_assertions)]
+pub fn track_shared<T>(resource: &Arc<T>, kind: ResourceKind, label: &str)
+where
+    T: ?Sized + Send + Sync + 'static,
+{
+    let resource = Arc::downgrade(resource);
+    let shared = SharedResource {
+        is_alive: Box::new(move || resource.strong_count() > 0),
+        _tracker: ResourceTracker::new(kind, label),
+    };
+
+    if let Ok(mut resources) = SHARED_RESOURCES.lock() {
+        resources.retain(|r| (r.is_alive)());
+        resources.push(shared);
+    }
+}
+
+#[cfg(not(debug_assertions))]
+pub fn track_shared<T>(_resource: &Arc<T>, _kind: ResourceKind, _label: &str)
+where
+    T: ?Sized + Send + Sync + 'static,
+{
+}
+
+/// A resource still alive at the time of the report
+pub struct LeakedResource {
+    pub kind: ResourceKind,
+    pub label: String,
+    /// Backtrace of the creation (needs `RUST_BACKTRACE=1` to be captured)
+    pub backtrace: String,
+}
+
+/// List of the resources still alive
+pub struct LeakReport {
+    pub leaks: Vec<LeakedResource>,
+}
+
+impl fmt::Display for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.leaks.is_empty() {
+            return writeln!(f, "No leaked resources");
+        }
+
+        writeln!(f, "{} leaked resources:", self.leaks.len())?;
+        for leak in &self.leaks {
+            writeln!(f, "- {:?} '{}' created at:\n{}", leak.kind, leak.label, leak.backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collect every resource still alive (always empty in release builds)
+pub fn report() -> LeakReport {
+    #[cfg(debug_assertions)]
+    {
+        // Released first, their trackers are removed from the live resources on drop
+        if let Ok(mut resources) = SHARED_RESOURCES.lock() {
+            resources.retain(|r| (r.is_alive)());
+        }
+
+        let map = match LIVE_RESOURCES.lock() {
+            Ok(m) => m,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let leaks = map
+            .values()
+            .map(|r| LeakedResource {
+                kind: r.kind,
+                label: r.label.clone(),
+                backtrace: r.backtrace.to_string(),
+            })
+            .collect();
+
+        LeakReport { leaks }
+    }
+
+    #[cfg(not(debug_assertions))]
+    LeakReport { leaks: Vec::new() }
+}
@@ -0,0 +1,422 @@
+//! 2D dynamic lighting: point and cone lights with falloff, shadows cast by occluder polygons
+//!
+//! A `LightLayer` covers the whole view with a light map (the ambient color plus every light
+//! reaching the pixel) multiplied over the objects drawn before it. Give it a z index above
+//! the scene and below the UI, which stays unlit. Lights and occluders are read at every frame.
+
+// standard imports
+use std::sync::Arc;
+
+// vulkan imports
+use vulkano::buffer::CpuAccessibleBuffer;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+use vulkano::descriptor::descriptor_set::{PersistentDescriptorSet, PersistentDescriptorSetBuf};
+
+// import the engine modules
+use super::color::Color;
+use super::ctx::draw_objects::{self, Draw, DrawFlags, DrawState, GraphicObject, UniformRing};
+use super::ctx::vulkan::{
+    DeviceRecovery, FrameError, GlobalUniformBuffer, GraphicsHandler, Vertex, VertexArray,
+    VertexBuffer,
+};
+use super::geometry::Aabb;
+use super::leaks::{ResourceKind, ResourceTracker};
+use super::math::{Rad, Vec2};
+
+// other imports
+use cgmath::Vector2;
+
+/// Lights sent to the GPU, the others are ignored (same limit as the shader)
+pub const MAX_LIGHTS: usize = 32;
+/// Occluder edges sent to the GPU, the others don't cast shadows (same limit as the shader)
+pub const MAX_SHADOW_SEGMENTS: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    /// Shining all around
+    Point,
+    /// Shining in a direction (angle from the X axis towards the Y axis, so clockwise on screen)
+    Cone {
+        direction: Rad<f32>,
+        /// Full opening of the cone
+        angle: Rad<f32>,
+        /// Part of the cone fading to its edges, from 0.0 (sharp) to 1.0
+        softness: f32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub position: Vec2,
+    pub kind: LightKind,
+    pub color: Color,
+    /// Multiplies the color
+    pub intensity: f32,
+    /// Distance the light reaches, in world units
+    pub radius: f32,
+    /// Exponent of the fade to the radius: 1.0 is linear, higher values keep the light close to the center
+    pub falloff: f32,
+    /// Stopped by the occluders
+    pub cast_shadows: bool,
+    pub enabled: bool,
+}
+
+impl Light {
+    /// White light shining all around
+    pub fn point(position: Vec2, radius: f32) -> Self {
+        Self {
+            position,
+            kind: LightKind::Point,
+            color: Color::WHITE,
+            intensity: 1.0,
+            radius,
+            falloff: 1.0,
+            cast_shadows: true,
+            enabled: true,
+        }
+    }
+
+    /// White light shining in a direction (e.g. torches, car headlights)
+    pub fn cone(position: Vec2, radius: f32, direction: Rad<f32>, angle: Rad<f32>) -> Self {
+        Self {
+            kind: LightKind::Cone {
+                direction,
+                angle,
+                softness: 0.2,
+            },
+            ..Self::point(position, radius)
+        }
+    }
+
+    pub fn color(mut self, color: impl Into<Color>) -> Self {
+        self.color = color.into();
+        self
+    }
+
+    pub fn intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn falloff(mut self, falloff: f32) -> Self {
+        self.falloff = falloff;
+        self
+    }
+
+    pub fn cast_shadows(mut self, cast_shadows: bool) -> Self {
+        self.cast_shadows = cast_shadows;
+        self
+    }
+
+    fn data(&self) -> LightData {
+        let color = [
+            self.color.r * self.intensity,
+            self.color.g * self.intensity,
+            self.color.b * self.intensity,
+            if self.cast_shadows { 1.0 } else { 0.0 },
+        ];
+
+        // Point lights get edges every direction is inside of
+        let cone = match self.kind {
+            LightKind::Point => [0.0, 0.0, -2.0, -1.0],
+            LightKind::Cone {
+                direction,
+                angle,
+                softness,
+            } => {
+                let half_angle = (angle.0 / 2.0).max(0.0);
+                let inner = half_angle * (1.0 - softness.max(0.0).min(1.0));
+                let (outer_cos, inner_cos) = (half_angle.cos(), inner.cos());
+
+                [
+                    direction.0.cos(),
+                    direction.0.sin(),
+                    outer_cos,
+                    // Edges can't be equal for the fade
+                    inner_cos.max(outer_cos + 1e-4),
+                ]
+            }
+        };
+
+        LightData {
+            position_radius: [
+                self.position.x,
+                self.position.y,
+                self.radius,
+                self.falloff.max(0.0),
+            ],
+            color,
+            cone,
+        }
+    }
+}
+
+/// Shape stopping the lights casting shadows
+#[derive(Clone, Debug, PartialEq)]
+pub struct Occluder {
+    pub points: Vec<Vec2>,
+    /// Link the last point to the first one
+    pub closed: bool,
+    pub enabled: bool,
+}
+
+impl Occluder {
+    /// Closed polygon
+    pub fn polygon(points: Vec<Vec2>) -> Self {
+        Self {
+            points,
+            closed: true,
+            enabled: true,
+        }
+    }
+
+    /// Open line (e.g. a thin wall)
+    pub fn line(points: Vec<Vec2>) -> Self {
+        Self {
+            closed: false,
+            ..Self::polygon(points)
+        }
+    }
+
+    pub fn rectangle(aabb: &Aabb) -> Self {
+        Self::polygon(vec![
+            aabb.min,
+            Vec2::new(aabb.max.x, aabb.min.y),
+            aabb.max,
+            Vec2::new(aabb.min.x, aabb.max.y),
+        ])
+    }
+
+    /// Edges of the shape, start and end
+    pub fn segments(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        let closing = if self.closed && self.points.len() > 2 {
+            self.points.last().zip(self.points.first())
+        } else {
+            None
+        };
+
+        self.points
+            .windows(2)
+            .map(|pair| (pair[0], pair[1]))
+            .chain(closing.map(|(&last, &first)| (last, first)))
+    }
+}
+
+/// Light of the GPU, layout of the shader
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct LightData {
+    position_radius: [f32; 4],
+    color: [f32; 4],
+    cone: [f32; 4],
+}
+
+/// Lights and occluders of the GPU, layout of the shader
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct LightingData {
+    ambient: [f32; 4],
+    /// Lights and occluder segments
+    counts: [u32; 4],
+    lights: [LightData; MAX_LIGHTS],
+    segments: [[f32; 4]; MAX_SHADOW_SEGMENTS],
+}
+
+type LightDescriptorSet = PersistentDescriptorSet<(
+    (
+        (),
+        PersistentDescriptorSetBuf<Arc<CpuAccessibleBuffer<LightingData>>>,
+    ),
+    PersistentDescriptorSetBuf<Arc<GlobalUniformBuffer>>,
+)>;
+
+pub type LightLayerObject = GraphicObject<LightLayer>;
+
+/// Light map drawn over the objects with lower z indices
+pub struct LightLayer {
+    vertex_buffer: VertexBuffer,
+    descriptor_set: Arc<LightDescriptorSet>,
+    uniforms: UniformRing<LightingData>,
+
+    z_index: u8,
+    draw_flags: DrawFlags,
+
+    /// Light of the places no light reaches, black by default
+    pub ambient: Color,
+    pub lights: Vec<Light>,
+    pub occluders: Vec<Occluder>,
+
+    _tracker: ResourceTracker,
+}
+
+impl LightLayer {
+    pub fn new(gl_handler: &GraphicsHandler, z_index: u8) -> Result<Self, FrameError> {
+        // Whole screen, in normalized device coordinates
+        let vao = VertexArray::from(vec![
+            Vertex {
+                vert_pos: [-1.0, -1.0],
+            },
+            Vertex {
+                vert_pos: [-1.0, 1.0],
+            },
+            Vertex {
+                vert_pos: [1.0, 1.0],
+            },
+            Vertex {
+                vert_pos: [1.0, -1.0],
+            },
+        ]);
+        let indices = gl_handler.new_index_buffer(&[0, 1, 2, 2, 3, 0]);
+        let vertex_buffer = gl_handler.new_vertex_buffer(vao, indices);
+
+        let lighting_data = LightingData {
+            ambient: [0.0, 0.0, 0.0, 1.0],
+            counts: [0; 4],
+            lights: [LightData::default(); MAX_LIGHTS],
+            segments: [[0.0; 4]; MAX_SHADOW_SEGMENTS],
+        };
+        let uniforms = UniformRing::new(gl_handler, lighting_data);
+
+        let descriptor_set = gl_handler
+            .create_empty_descriptor_set_builder("Light", 0)?
+            .add_buffer(uniforms.buffer())
+            .and_then(|set| set.add_buffer(gl_handler.get_global_uniform_buffer()))
+            .map_err(|e| FrameError::DescriptorSet(e.to_string()))?
+            .build()
+            .map_err(|e| FrameError::DescriptorSet(e.to_string()))?;
+
+        Ok(Self {
+            vertex_buffer,
+            descriptor_set: Arc::new(descriptor_set),
+            uniforms,
+            z_index,
+            draw_flags: DrawFlags::USED | DrawFlags::VISIBLE,
+            ambient: Color::BLACK,
+            lights: Vec::new(),
+            occluders: Vec::new(),
+            _tracker: ResourceTracker::new(ResourceKind::DrawObject, "Light layer"),
+        })
+    }
+
+    pub fn set_ambient(&mut self, ambient: impl Into<Color>) {
+        self.ambient = ambient.into();
+    }
+
+    /// Add a light, returning its index in `lights`
+    pub fn add_light(&mut self, light: Light) -> usize {
+        self.lights.push(light);
+        self.lights.len() - 1
+    }
+
+    /// Add an occluder, returning its index in `occluders`
+    pub fn add_occluder(&mut self, occluder: Occluder) -> usize {
+        self.occluders.push(occluder);
+        self.occluders.len() - 1
+    }
+}
+
+impl Draw for LightLayer {
+    fn draw(
+        &self,
+        gl_handler: &mut GraphicsHandler,
+        command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), FrameError> {
+        draw_objects::draw(
+            gl_handler,
+            gl_handler.get_pipeline("Light"),
+            command_buffer,
+            self.vertex_buffer.get_vertices(),
+            self.vertex_buffer.get_indices(),
+            self.descriptor_set.clone(),
+        )
+    }
+
+    fn get_z_index(&self) -> u8 {
+        self.z_index
+    }
+
+    fn flush_data(&self) {
+        let mut lighting_data = LightingData {
+            ambient: [self.ambient.r, self.ambient.g, self.ambient.b, 1.0],
+            counts: [0; 4],
+            lights: [LightData::default(); MAX_LIGHTS],
+            segments: [[0.0; 4]; MAX_SHADOW_SEGMENTS],
+        };
+
+        let lights = self.lights.iter().filter(|light| light.enabled);
+        let mut light_count = 0;
+        for (data, light) in lighting_data.lights.iter_mut().zip(lights) {
+            *data = light.data();
+            light_count += 1;
+        }
+
+        let segments = self
+            .occluders
+            .iter()
+            .filter(|occluder| occluder.enabled)
+            .flat_map(Occluder::segments);
+        let mut segment_count = 0;
+        for (data, (start, end)) in lighting_data.segments.iter_mut().zip(segments) {
+            *data = [start.x, start.y, end.x, end.y];
+            segment_count += 1;
+        }
+
+        lighting_data.counts = [light_count, segment_count, 0, 0];
+
+        self.uniforms.set(lighting_data);
+    }
+
+    fn upload_frame_data(
+        &self,
+        image_num: usize,
+        builder: Option<&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>>,
+    ) -> Result<(), FrameError> {
+        self.uniforms.upload(image_num, builder)
+    }
+
+    fn write_flags(&mut self) -> &mut DrawFlags {
+        &mut self.draw_flags
+    }
+
+    fn read_flags(&self) -> DrawFlags {
+        self.draw_flags
+    }
+
+    fn set_dead(&mut self) {
+        self.draw_flags.remove(DrawFlags::USED);
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        self.draw_flags.set(DrawFlags::VISIBLE, visible);
+    }
+
+    /// The ambient light is the color, the layer has no position or scale
+    fn get_state(&self) -> DrawState {
+        DrawState {
+            color: self.ambient.into(),
+            global_position: Vector2::new(0.0, 0.0),
+            scale: Vector2::new(1.0, 1.0),
+            flags: self.draw_flags,
+        }
+    }
+
+    fn set_state(&mut self, state: &DrawState) {
+        self.ambient = state.color.into();
+        self.draw_flags = state.flags;
+    }
+
+    fn recreate(
+        &mut self,
+        gl_handler: &GraphicsHandler,
+        _recovery: &DeviceRecovery,
+    ) -> Result<(), String> {
+        let layer = LightLayer::new(gl_handler, self.z_index).map_err(|e| e.to_string())?;
+
+        self.vertex_buffer = layer.vertex_buffer;
+        self.descriptor_set = layer.descriptor_set;
+        self.uniforms = layer.uniforms;
+        self.flush_data();
+
+        Ok(())
+    }
+}
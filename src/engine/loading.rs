@@ -0,0 +1,254 @@
+//! Background asset loading, reporting the progress of every batch
+//!
+//! Files are read and decoded on a worker thread per batch, the GPU uploads and the audio
+//! chunks are finalized by the engine on the main thread, a few per frame.
+
+// standard imports
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+// import the engine modules
+use super::assets::{FontData, Handle, Mesh, ShaderSource, TextureAsset};
+use super::ctx::{decode_png_rgba, SoundEffect};
+use super::pack;
+use super::warmup::{self, AssetKind};
+
+/// Identifier of a loading batch
+pub type BatchId = u64;
+
+/// Kind of asset requested in a batch
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadKind {
+    Texture,
+    Sound,
+    Shader,
+    Font,
+    Mesh,
+}
+
+/// Asset decoded by a worker thread, waiting to be finalized
+pub(crate) enum DecodedAsset {
+    Texture {
+        pixels: Vec<u8>,
+        width: u32,
+        height: u32,
+    },
+    Sound(Vec<u8>),
+    Shader(ShaderSource),
+    Font(FontData),
+    Mesh(Mesh),
+}
+
+/// Progress of a batch
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoadProgress {
+    pub total: usize,
+    pub loaded: usize,
+    pub failed: usize,
+}
+
+impl LoadProgress {
+    /// Fraction of the batch already handled, from 0.0 to 1.0
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            (self.loaded + self.failed) as f32 / self.total as f32
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.loaded + self.failed >= self.total
+    }
+}
+
+/// Handles of the assets loaded by a batch
+#[derive(Default)]
+pub struct LoadedAssets {
+    pub textures: Vec<Handle<TextureAsset>>,
+    pub sounds: Vec<Handle<SoundEffect>>,
+    pub shaders: Vec<Handle<ShaderSource>>,
+    pub fonts: Vec<Handle<FontData>>,
+    pub meshes: Vec<Handle<Mesh>>,
+    /// Path and reason of every failed asset
+    pub errors: Vec<(PathBuf, String)>,
+}
+
+impl LoadedAssets {
+    /// Find a loaded texture by its path, as requested or relative to the asset root
+    pub fn texture(&self, path: &Path) -> Option<&Handle<TextureAsset>> {
+        self.textures.iter().find(|h| h.path().ends_with(path))
+    }
+
+    pub fn sound(&self, path: &Path) -> Option<&Handle<SoundEffect>> {
+        self.sounds.iter().find(|h| h.path().ends_with(path))
+    }
+}
+
+struct LoadBatch {
+    id: BatchId,
+    receiver: Receiver<(PathBuf, Result<DecodedAsset, String>)>,
+    progress: LoadProgress,
+    /// Assets sent by the worker so far
+    received: usize,
+    assets: LoadedAssets,
+    /// The completion was already reported
+    reported: bool,
+}
+
+/// Batches being loaded in the background
+#[derive(Default)]
+pub struct AssetLoader {
+    batches: Vec<LoadBatch>,
+    next_id: BatchId,
+}
+
+fn decode(kind: LoadKind, path: &Path) -> Result<DecodedAsset, String> {
+    let error = |e: &dyn std::fmt::Display| format!("Couldn't load '{}': {}", path.display(), e);
+
+    match kind {
+        LoadKind::Texture => {
+            let bytes = pack::read(path).map_err(|e| error(&e))?;
+            let (pixels, width, height) = decode_png_rgba(Cursor::new(bytes)).map_err(|e| error(&e))?;
+            Ok(DecodedAsset::Texture {
+                pixels,
+                width,
+                height,
+            })
+        }
+        LoadKind::Sound => pack::read(path).map(DecodedAsset::Sound).map_err(|e| error(&e)),
+        LoadKind::Shader => pack::read_to_string(path)
+            .map(|code| DecodedAsset::Shader(ShaderSource { code }))
+            .map_err(|e| error(&e)),
+        LoadKind::Font => pack::read(path)
+            .map(|bytes| DecodedAsset::Font(FontData { bytes }))
+            .map_err(|e| error(&e)),
+        LoadKind::Mesh => {
+            let source = pack::read_to_string(path).map_err(|e| error(&e))?;
+            Mesh::parse_obj(&source).map(DecodedAsset::Mesh).map_err(|e| error(&e))
+        }
+    }
+}
+
+impl AssetLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start decoding the assets on a worker thread
+    pub fn start(&mut self, requests: Vec<(LoadKind, PathBuf)>) -> BatchId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let total = requests.len();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::Builder::new()
+            .name(format!("asset-loader-{}", id))
+            .spawn(move || {
+                for (kind, path) in requests {
+                    match kind {
+                        LoadKind::Texture => warmup::record(AssetKind::Texture, &path),
+                        LoadKind::Sound => warmup::record(AssetKind::Sound, &path),
+                        _ => {}
+                    }
+
+                    let decoded = decode(kind, &path);
+                    // The batch was dropped, stop working for nothing
+                    if sender.send((path, decoded)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Couldn't spawn asset loader thread");
+
+        self.batches.push(LoadBatch {
+            id,
+            receiver,
+            progress: LoadProgress {
+                total,
+                ..LoadProgress::default()
+            },
+            received: 0,
+            assets: LoadedAssets::default(),
+            reported: false,
+        });
+
+        id
+    }
+
+    pub fn progress(&self, id: BatchId) -> Option<LoadProgress> {
+        self.batches.iter().find(|b| b.id == id).map(|b| b.progress)
+    }
+
+    /// Take up to `max` decoded assets, to be finalized on the main thread
+    pub(crate) fn poll(&mut self, max: usize) -> Vec<(BatchId, PathBuf, Result<DecodedAsset, String>)> {
+        let mut decoded = Vec::new();
+
+        for batch in self.batches.iter_mut() {
+            while decoded.len() < max {
+                match batch.receiver.try_recv() {
+                    Ok((path, result)) => {
+                        batch.received += 1;
+                        decoded.push((batch.id, path, result));
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    // The worker died (panicked decoding), the rest of the batch failed
+                    Err(TryRecvError::Disconnected) => {
+                        let missing = batch.progress.total - batch.received;
+                        if missing > 0 {
+                            batch.received = batch.progress.total;
+                            batch.progress.failed += missing;
+                            batch
+                                .assets
+                                .errors
+                                .push((PathBuf::new(), String::from("Asset loader thread stopped")));
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        decoded
+    }
+
+    /// Store the result of a finalized asset in its batch
+    pub(crate) fn complete<F: FnOnce(&mut LoadedAssets)>(&mut self, id: BatchId, store: F) {
+        if let Some(batch) = self.batches.iter_mut().find(|b| b.id == id) {
+            batch.progress.loaded += 1;
+            store(&mut batch.assets);
+        }
+    }
+
+    pub(crate) fn fail(&mut self, id: BatchId, path: PathBuf, error: String) {
+        if let Some(batch) = self.batches.iter_mut().find(|b| b.id == id) {
+            batch.progress.failed += 1;
+            batch.assets.errors.push((path, error));
+        }
+    }
+
+    /// Batches completed since the last call
+    pub(crate) fn take_completed(&mut self) -> Vec<BatchId> {
+        self.batches
+            .iter_mut()
+            .filter(|b| b.progress.is_done() && !b.reported)
+            .map(|b| {
+                b.reported = true;
+                b.id
+            })
+            .collect()
+    }
+
+    /// Take the handles of a completed batch, None if it's still loading
+    pub fn finish(&mut self, id: BatchId) -> Option<LoadedAssets> {
+        let index = self
+            .batches
+            .iter()
+            .position(|b| b.id == id && b.progress.is_done())?;
+
+        Some(self.batches.remove(index).assets)
+    }
+}
@@ -0,0 +1,250 @@
+//! Level of detail for meshes, drawing simpler versions of a mesh when the camera is far away
+//!
+//! The distance of a 2D camera is how much of the world it sees: the half size of the view in
+//! world units, growing when zooming out (see `camera_distance`). Every frame the level whose
+//! switch distance was passed last is drawn, blended with the next level in the `crossfade`
+//! band before its switch distance.
+
+// standard imports
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+// vulkan imports
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+
+// import the engine modules
+use super::assets::Mesh;
+use super::ctx::draw_objects::{BatchMesh, Draw, DrawFlags, DrawState, GraphicObject};
+use super::ctx::vulkan::{BatchVertex, DeviceRecovery, FrameError, GraphicsHandler};
+use super::ctx::VideoHandler;
+use super::geometry::Aabb;
+use super::leaks::{ResourceKind, ResourceTracker};
+use super::math::{Vec2, Vec4};
+
+pub type LodMeshObject = GraphicObject<LodMesh>;
+
+/// Distance of the camera seeing `view`, the larger half size of the view in world units
+pub fn camera_distance(view: &Aabb) -> f32 {
+    let half_extents = view.half_extents();
+    half_extents.x.max(half_extents.y)
+}
+
+/// Mesh drawn from its switch distance on, until the switch distance of the next level
+#[derive(Clone, Debug)]
+pub struct LodLevel {
+    pub mesh: Arc<Mesh>,
+    pub switch_distance: f32,
+}
+
+impl LodLevel {
+    pub fn new(mesh: Mesh, switch_distance: f32) -> Self {
+        Self {
+            mesh: Arc::new(mesh),
+            switch_distance,
+        }
+    }
+}
+
+/// Mesh with many levels of detail, drawn in a single color like the primitives
+pub struct LodMesh {
+    /// Sorted by switch distance
+    levels: Vec<LodLevel>,
+    /// Box holding every level before scaling and moving them, None without vertices
+    local_bounds: Option<Aabb>,
+
+    z_index: u8,
+    draw_flags: DrawFlags,
+
+    pub color: Vec4,
+    pub global_position: Vec2,
+    pub scale: Vec2,
+    /// Distance before each switch distance where the two levels are blended, 0 to switch at once
+    pub crossfade: f32,
+
+    _tracker: ResourceTracker,
+}
+
+impl LodMesh {
+    /// Create a mesh with levels of detail and draw it in a window
+    pub fn create(
+        video: &mut VideoHandler,
+        levels: Vec<LodLevel>,
+        color: Vec4,
+        global_position: Vec2,
+        scale: Vec2,
+        z_index: u8,
+    ) -> LodMeshObject {
+        video
+            .gl_handler
+            .add_draw_object(Self::new(levels, color, global_position, scale, z_index))
+    }
+
+    pub fn new(
+        mut levels: Vec<LodLevel>,
+        color: Vec4,
+        global_position: Vec2,
+        scale: Vec2,
+        z_index: u8,
+    ) -> Self {
+        levels.sort_by(|a, b| {
+            a.switch_distance
+                .partial_cmp(&b.switch_distance)
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let points: Vec<Vec2> = levels
+            .iter()
+            .flat_map(|level| level.mesh.vertices.iter())
+            .map(|&vertex| Vec2::from(vertex))
+            .collect();
+
+        Self {
+            local_bounds: Aabb::from_points(&points),
+            levels,
+            z_index,
+            draw_flags: DrawFlags::USED | DrawFlags::VISIBLE,
+            color,
+            global_position,
+            scale,
+            crossfade: 0.0,
+            _tracker: ResourceTracker::new(ResourceKind::DrawObject, "LOD mesh"),
+        }
+    }
+
+    pub fn levels(&self) -> &[LodLevel] {
+        &self.levels
+    }
+
+    /// Index of the level drawn at `distance`, None without levels
+    pub fn level_at(&self, distance: f32) -> Option<usize> {
+        if self.levels.is_empty() {
+            return None;
+        }
+
+        let passed = self
+            .levels
+            .iter()
+            .take_while(|level| level.switch_distance <= distance)
+            .count();
+        Some(passed.saturating_sub(1))
+    }
+
+    /// Levels drawn at `distance` with their opacity, two of them while crossfading
+    pub fn blend_at(&self, distance: f32) -> Vec<(usize, f32)> {
+        let index = match self.level_at(distance) {
+            Some(index) => index,
+            None => return Vec::new(),
+        };
+
+        if let Some(next) = self.levels.get(index + 1) {
+            let fade_start = next.switch_distance - self.crossfade;
+            if self.crossfade > 0.0 && distance > fade_start {
+                let t = (distance - fade_start) / self.crossfade;
+                return vec![(index, 1.0 - t), (index + 1, t)];
+            }
+        }
+        vec![(index, 1.0)]
+    }
+
+    /// Append the world space triangles of the levels seen from a camera at `distance`
+    fn push_mesh(&self, distance: f32, batch: &mut BatchMesh) {
+        for (index, opacity) in self.blend_at(distance) {
+            let mesh = &self.levels[index].mesh;
+            let color = [
+                self.color.x,
+                self.color.y,
+                self.color.z,
+                self.color.w * opacity,
+            ];
+
+            let vertices = mesh.vertices.iter().map(|vertex| BatchVertex {
+                world_pos: [
+                    self.global_position.x + vertex[0] * self.scale.x,
+                    self.global_position.y + vertex[1] * self.scale.y,
+                ],
+                tex_coords: [0.0, 0.0],
+                color,
+            });
+            batch.extend(vertices, mesh.indices.iter().copied());
+        }
+    }
+}
+
+impl Draw for LodMesh {
+    fn draw(
+        &self,
+        gl_handler: &mut GraphicsHandler,
+        command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), FrameError> {
+        let mut mesh = BatchMesh::default();
+        self.push_mesh(camera_distance(&gl_handler.view_bounds()), &mut mesh);
+
+        gl_handler.draw_mesh(&mesh, command_buffer)
+    }
+
+    fn get_z_index(&self) -> u8 {
+        self.z_index
+    }
+
+    fn flush_data(&self) {}
+
+    fn write_flags(&mut self) -> &mut DrawFlags {
+        &mut self.draw_flags
+    }
+
+    fn read_flags(&self) -> DrawFlags {
+        self.draw_flags
+    }
+
+    fn set_dead(&mut self) {
+        self.draw_flags.remove(DrawFlags::USED);
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        self.draw_flags.set(DrawFlags::VISIBLE, visible);
+    }
+
+    fn get_state(&self) -> DrawState {
+        DrawState {
+            color: self.color,
+            global_position: self.global_position,
+            scale: self.scale,
+            flags: self.draw_flags,
+        }
+    }
+
+    fn set_state(&mut self, state: &DrawState) {
+        self.color = state.color;
+        self.global_position = state.global_position;
+        self.scale = state.scale;
+        self.draw_flags = state.flags;
+    }
+
+    /// Only drawn through the batch pipeline, nothing to create
+    fn recreate(
+        &mut self,
+        _gl_handler: &GraphicsHandler,
+        _recovery: &DeviceRecovery,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn cull_bounds(&self) -> Option<Aabb> {
+        let local = self.local_bounds?;
+        let to_world = |point: Vec2| {
+            self.global_position + Vec2::new(point.x * self.scale.x, point.y * self.scale.y)
+        };
+
+        Some(Aabb::new(to_world(local.min), to_world(local.max)))
+    }
+
+    fn batch_mesh(&self, view: &Aabb, mesh: &mut BatchMesh) -> bool {
+        self.push_mesh(camera_distance(view), mesh);
+        true
+    }
+
+    /// The level changes with the zoom of the camera
+    fn follows_view(&self) -> bool {
+        self.levels.len() > 1
+    }
+}
@@ -1,74 +1,540 @@
 // standard imports
-use std::path::Path;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Instant;
 
 // import the ctx mdule
-use super::ctx::CtxHandler;
+use super::animation;
+use super::bench::{BenchmarkKind, BenchmarkReport, BenchmarkScene};
+use super::camera::Camera2D;
+use super::ctx::vulkan::RenderSnapshot;
+use super::ctx::{CtxHandler, SwapInterval};
+use super::ecs::WorldSnapshot;
+use super::config::{EngineConfig, LoopMode, WindowConfig};
+#[cfg(feature = "ipc")]
+use super::console;
+use super::crash;
+use super::error::MagmaError;
+use super::events::{EngineEvent, WindowId};
+use super::exit::ExitReason;
+use super::game::{Frame, Game};
+use super::time::Time;
+use super::timer;
+use super::transform::TransformPropagation;
+use super::tween::{self, TweenSnapshot};
+use super::ipc::IpcSnapshot;
+#[cfg(feature = "ipc")]
+use super::ipc::{IpcRequest, IpcServer};
+use super::paths::DirKind;
+#[cfg(feature = "physics2d")]
+use super::physics2d;
+#[cfg(feature = "physics3d")]
+use super::physics3d;
+use super::plugin::{EnginePlugin, PluginSet};
+use super::random::Rng;
+use super::replay::{Replay, ReplayState};
+use super::warmup::{self, UsageProfile};
 
 // other imports
 
+/// Longest frame time simulated with a fixed update rate, avoids a spiral of catching up after hitches
+const MAX_FIXED_FRAME_TIME: f32 = 0.25;
+
+/// In-memory copy of the engine state, used by editors to enter and leave play mode.
+///
+/// It holds the draw objects, the camera, the timing, `ctx.rng`, the progress of `ctx.tweens` and
+/// the entities of `ctx.world` with the components registered by `World::register_snapshot`
+/// (the transforms by default). World resources, audio, scenes and the game itself aren't part of it
+#[derive(Clone)]
+pub struct EngineSnapshot {
+    /// None in headless mode
+    render: Option<RenderSnapshot>,
+    time: Time,
+    accumulator: f32,
+    rng: Rng,
+    camera: Camera2D,
+    world: WorldSnapshot,
+    tweens: TweenSnapshot,
+}
+
 /// Main struct to handle the whole program in all it's components
 pub struct Engine {
     ctx_handler: CtxHandler,
+
+    time: Time,
+    loop_mode: LoopMode,
+    /// Time not simulated yet with a fixed update rate
+    accumulator: f32,
+    #[cfg(feature = "ipc")]
+    ipc_server: Option<IpcServer>,
+    warmup_profile: Option<PathBuf>,
+    replay: ReplayState,
+    plugins: PluginSet,
+    /// Events handled during the frame, kept to not allocate them again every frame
+    frame_events: Vec<EngineEvent>,
+    transforms: TransformPropagation,
 }
 
 impl Engine {
-    /// Engine init process
-    pub fn new() -> Self {
-        let ctx_handler = CtxHandler::new();
+    /// Choose the settings and the optional subsystems of a new engine
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::default()
+    }
+
+    /// Engine init process, failing if SDL2, the window or the renderer can't start
+    pub fn new(config: EngineConfig) -> Result<Self, MagmaError> {
+        // Installed first, so the panics of the startup are reported too
+        let paths = config.resolve_paths();
+        crash::install(
+            config.crash_reports,
+            paths.project_name(),
+            paths.resolve(DirKind::Logs),
+        );
+
+        let mut ctx_handler = CtxHandler::new(&config)?;
+
+        let warmup_profile = config.warmup_profile.as_ref().and_then(|path| {
+            ctx_handler
+                .paths
+                .file(DirKind::Cache, path)
+                .map_err(|e| crash::log(&format!("Warmup profile disabled: {}", e)))
+                .ok()
+        });
+
+        if let Some(profile_path) = &warmup_profile {
+            // The first run has no profile yet, only the GPU side can be warmed up
+            if let Ok(profile) = UsageProfile::load(profile_path) {
+                profile.pre_touch();
+            }
+            if let Some(video) = ctx_handler.try_video() {
+                video.gl_handler.warmup();
+            }
+        }
+
+        Ok(Self {
+            ctx_handler,
+
+            time: Time::new(),
+            loop_mode: config.loop_mode,
+            accumulator: 0.0,
+            #[cfg(feature = "ipc")]
+            ipc_server: None,
+            warmup_profile,
+            replay: ReplayState::Idle,
+            plugins: PluginSet::default(),
+            frame_events: Vec::new(),
+            transforms: TransformPropagation::new(),
+        })
+    }
+
+    /// Register a plugin, its `build` hook is called right away.
+    /// Fails if a plugin with the same name is already registered
+    pub fn add_plugin<P: EnginePlugin + 'static>(&mut self, plugin: P) -> Result<(), String> {
+        self.plugins.add(Box::new(plugin), &mut self.ctx_handler)
+    }
+
+    pub fn has_plugin(&self, name: &str) -> bool {
+        self.plugins.contains(name)
+    }
+
+    /// Names of the registered plugins, in order of registration
+    pub fn get_plugin_names(&self) -> Vec<&str> {
+        self.plugins.names()
+    }
+
+    /// Copy the current state of the engine
+    pub fn snapshot(&self) -> EngineSnapshot {
+        EngineSnapshot {
+            render: self
+                .ctx_handler
+                .main_video()
+                .map(|video| video.gl_handler.snapshot()),
+            time: self.time,
+            accumulator: self.accumulator,
+            rng: self.ctx_handler.rng.clone(),
+            camera: self.ctx_handler.camera.clone(),
+            world: self.ctx_handler.world.snapshot(),
+            tweens: self.ctx_handler.tweens.snapshot(),
+        }
+    }
+
+    /// Bring the engine back to the exact state of a snapshot
+    pub fn restore(&mut self, snapshot: &EngineSnapshot) {
+        if let (Some(video), Some(render)) = (self.ctx_handler.try_video(), &snapshot.render) {
+            video.gl_handler.restore(render);
+        }
+        self.time = snapshot.time;
+        self.accumulator = snapshot.accumulator;
+        self.ctx_handler.rng = snapshot.rng.clone();
+        self.ctx_handler.camera = snapshot.camera.clone();
+        self.ctx_handler.world.restore(&snapshot.world);
+        self.ctx_handler.tweens.restore(&snapshot.tweens);
+    }
+
+    /// Open an additional window, e.g. for editor tool panels
+    pub fn create_window(&mut self, config: WindowConfig) -> Result<WindowId, MagmaError> {
+        self.ctx_handler.create_window(&config)
+    }
 
-        Self { ctx_handler }
+    /// Close a window opened with `create_window`
+    pub fn close_window(&mut self, id: WindowId) -> bool {
+        self.ctx_handler.close_window(id)
     }
 
-    /// Main function to run the program
-    pub fn run(&mut self) {
-        if self
+    /// Start the IPC bridge on a local socket, see `IpcServer::bind` for the name
+    #[cfg(feature = "ipc")]
+    pub fn enable_ipc(&mut self, name: &str) -> io::Result<()> {
+        self.ipc_server = Some(IpcServer::bind(name)?);
+
+        Ok(())
+    }
+
+    /// Start the HTTP debug server on the given address, see `Context::enable_debug_server`
+    pub fn enable_debug_server(&mut self, address: SocketAddr) -> io::Result<()> {
+        self.ctx_handler.enable_debug_server(address)
+    }
+
+    /// Snapshot of the current engine state for the debugging tools
+    fn ipc_snapshot(&self) -> IpcSnapshot {
+        // Read from the window, the renderer may be busy on the render thread
+        let window_size = self
             .ctx_handler
-            .audio
-            .music_from_file(Path::new("assets/example.ogg")).is_ok()
-        {
-            println!("Music was loaded fine!");
-            match self.ctx_handler.audio.music_play(-1) {
-                Ok(_) => println!("Music played fine!"),
-                Err(_) => println!("Music couldn't play..."),
-            }
-        } else {
-            println!("Music couldn't be loaded...");
+            .main_video()
+            .map_or((0, 0), |video| video.get_window_size());
+
+        IpcSnapshot {
+            frame: self.time.frame_count(),
+            framerate: self.ctx_handler.get_current_framerate(),
+            framerate_limit: self.ctx_handler.get_framerate_limit(),
+            delta: self.ctx_handler.fps_manager.get_delta(),
+            window_size,
         }
+    }
+
+    /// Publish the debug server data, answer the IPC clients and queue their requests
+    fn update_ipc(&mut self) {
+        let snapshot = self.ipc_snapshot();
 
-        // before, z index wasn't sorted and depth depended on the order in the vector
-        // now the order isn't important but the z index must be specified
-        let _ferris = self.ctx_handler.video.new_sprite("assets/rust.png", 1);
-        let python = self.ctx_handler.video.new_sprite("assets/python.png", 1);
+        if let Some(mut server) = self.ctx_handler.debug_server.take() {
+            server.update(&mut self.ctx_handler, snapshot);
+            self.ctx_handler.debug_server = Some(server);
+        }
 
-        let _rect = self.ctx_handler.video.new_rectangle((100.0, 100.0).into(), (0.0, 0.0, 1.0, 1.0).into(), (200.0, 200.0).into(), 2);
+        #[cfg(feature = "ipc")]
+        if let Some(server) = self.ipc_server.as_mut() {
+            for request in server.poll(&snapshot) {
+                match request {
+                    IpcRequest::Command(command) => {
+                        console::execute(&command, &mut self.ctx_handler)
+                    }
+                    IpcRequest::Quit => self.ctx_handler.set_break_signal(),
+                }
+            }
+
+            server.publish_stats(&snapshot);
+        }
+    }
 
-        let mut i = 0.0;
-        'mainloop: loop {
-            self.ctx_handler.check_events();
-            if self.ctx_handler.get_break_signal() {
-                break 'mainloop;
+    /// Run a generated stress scene for a number of frames measured after its warmup, then
+    /// return their timings (see `BenchmarkReport::to_json`). Headless engines only measure the updates.
+    /// The framerate limit and vsync are disabled during the run, then restored
+    pub fn run_benchmark(
+        &mut self,
+        scene: BenchmarkKind,
+        frames: usize,
+    ) -> Result<BenchmarkReport, MagmaError> {
+        let limit = self.ctx_handler.fps_manager.get_limit();
+        self.ctx_handler.set_uncapped_fps();
+        let swap_interval = self.ctx_handler.try_video().map(|video| {
+            let interval = video.gl_get_swap_interval();
+            if let Err(e) = video.gl_set_swap_interval(SwapInterval::Immediate) {
+                crash::log(&format!("Benchmark bound by vsync: {}", e));
             }
+            interval
+        });
 
-            i += 2.0;
-            {
-                self.ctx_handler.video.gl_handler.camera_scale.y = 1.0 - (i / 1000.0);
+        let mut scene = BenchmarkScene::with_frames(scene, frames);
+        let result = self.run(&mut scene);
 
-                let mut sprite = python.get_mut();
-                sprite.global_position.x = i;
-                sprite.color = cgmath::Vector4::new(1.0, 1.0, 1.0, 1.0 - (i / 255.0));
+        self.ctx_handler.fps_manager.set_limit(limit);
+        if let (Some(interval), Some(video)) = (swap_interval, self.ctx_handler.try_video()) {
+            if let Err(e) = video.gl_set_swap_interval(interval) {
+                crash::log(&e);
             }
+        }
+        result?;
+
+        scene.get_report().cloned().ok_or_else(|| {
+            MagmaError::Game(String::from("The benchmark was stopped before the end"))
+        })
+    }
 
-            self.ctx_handler.video.update();
+    /// Drive the game until the window is closed or a break signal is set, returning why it stopped.
+    /// A game stopping with `ExitReason::Error` returns the error
+    pub fn run<G: Game>(&mut self, game: &mut G) -> Result<ExitReason, MagmaError> {
+        game.init(&mut self.ctx_handler);
+
+        loop {
+            let delta = self.ctx_handler.fps_manager.get_delta();
+            if !self.step(game, delta) {
+                break;
+            }
 
             self.ctx_handler.wait();
+        }
+
+        game.shutdown(&mut self.ctx_handler);
+        self.plugins.shutdown(&mut self.ctx_handler);
+
+        self.save_warmup_profile();
+        if let Some(video) = self.ctx_handler.try_video() {
+            if let Err(e) = video.gl_handler.save_pipeline_cache() {
+                crash::log(&e);
+            }
+        }
+
+        match self.ctx_handler.take_exit_reason() {
+            Some(ExitReason::Error(e)) => Err(e),
+            reason => Ok(reason.unwrap_or(ExitReason::Quit)),
+        }
+    }
+
+    /// Run a game, building a new engine and game whenever a restart is requested.
+    /// The previous engine is dropped first, so every subsystem is initialized again
+    pub fn run_app<G, F>(
+        mut config: EngineConfig,
+        mut make_game: F,
+    ) -> Result<ExitReason, MagmaError>
+    where
+        G: Game,
+        F: FnMut(&EngineConfig) -> G,
+    {
+        loop {
+            let reason = {
+                let mut engine = Engine::new(config.clone())?;
+                let mut game = make_game(&config);
 
-            println!("{}", self.ctx_handler.get_current_framerate());
+                engine.run(&mut game)?
+            };
+
+            match reason {
+                ExitReason::Restart(new_config) => config = *new_config,
+                reason => return Ok(reason),
+            }
         }
     }
+
+    /// Run a single frame of the game as if `delta` seconds had passed, without waiting for the framerate limit.
+    /// Returns false if the game must stop. `Game::init` must have been called before the first step
+    pub fn step<G: Game>(&mut self, game: &mut G, delta: f32) -> bool {
+        let started = Instant::now();
+        self.ctx_handler.check_events();
+        self.ctx_handler.update_loading();
+        self.ctx_handler.update_hot_reload();
+        self.update_ipc();
+        if self.ctx_handler.get_break_signal() {
+            return false;
+        }
+
+        // Collected first, the game gets the whole context while handling them
+        let mut events = std::mem::take(&mut self.frame_events);
+        events.extend(self.ctx_handler.events.drain());
+        let game_delta = self.replay.process_frame(delta, &mut events);
+        for event in &events {
+            self.plugins.event(event, &mut self.ctx_handler);
+            game.on_event(event, &mut self.ctx_handler);
+        }
+        events.clear();
+        self.frame_events = events;
+
+        self.time.advance(game_delta);
+
+        let alpha = match self.loop_mode {
+            LoopMode::Variable => {
+                let time = self.time;
+                self.run_systems(&time);
+                self.plugins.update(&time, &mut self.ctx_handler);
+                game.update(&time, &mut self.ctx_handler);
+                1.0
+            }
+            LoopMode::Fixed(rate) => {
+                let step = 1.0 / rate;
+                let step_time = self.time.with_delta(step);
+
+                self.accumulator += game_delta.min(MAX_FIXED_FRAME_TIME);
+                while self.accumulator >= step {
+                    self.run_systems(&step_time);
+                    self.plugins.update(&step_time, &mut self.ctx_handler);
+                    game.update(&step_time, &mut self.ctx_handler);
+                    self.accumulator -= step;
+                }
+
+                self.accumulator / step
+            }
+        };
+
+        // Tweens follow the game time, replays included
+        tween::update_tweens(&mut self.ctx_handler, game_delta);
+
+        // World placement of the entities moved by the updates
+        self.transforms.run(&mut self.ctx_handler.world);
+        self.ctx_handler.update_camera(game_delta);
+
+        // Frame jobs may still be writing what gets rendered
+        self.ctx_handler.jobs.wait_frame_jobs();
+
+        // Nothing to render in headless mode
+        if let Some(video) = self.ctx_handler.try_video() {
+            let mut frame = Frame {
+                video,
+                time: &self.time,
+                alpha,
+            };
+            game.render(&mut frame);
+            self.plugins.render(&mut frame);
+        }
+
+        // Counters of the last drawn frame, read before the next one goes to the render thread
+        let counters = self
+            .ctx_handler
+            .main_video()
+            .map(|video| video.gl_handler.get_render_counters())
+            .unwrap_or_default();
+        self.ctx_handler.update_video();
+        self.ctx_handler.frame_stats.record(delta, counters);
+        self.ctx_handler
+            .frame_stats
+            .set_work_time(started.elapsed().as_secs_f32());
+        self.time.end_frame();
+
+        true
+    }
+
+    /// Record the input and frame times from the next frame, reseeding `ctx.rng` so a replay reproduces them
+    pub fn start_recording(&mut self) {
+        let seed = self.ctx_handler.rng.next_u64();
+        self.ctx_handler.rng.reseed(seed);
+        self.accumulator = 0.0;
+
+        self.replay = ReplayState::Recording(Replay::new(seed, self.loop_mode));
+    }
+
+    /// Stop recording, returning the recorded session
+    pub fn stop_recording(&mut self) -> Option<Replay> {
+        match std::mem::take(&mut self.replay) {
+            ReplayState::Recording(replay) => Some(replay),
+            state => {
+                self.replay = state;
+                None
+            }
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.replay.is_recording()
+    }
+
+    /// Play a recorded session back from the next frame, switching to its loop mode.
+    /// The live input is ignored until the last recorded frame
+    pub fn play_replay(&mut self, replay: Replay) {
+        self.ctx_handler.rng.reseed(replay.seed);
+        self.loop_mode = replay.loop_mode;
+        self.accumulator = 0.0;
+
+        self.replay = ReplayState::Playing { replay, frame: 0 };
+    }
+
+    /// Check if recorded frames are still to be played back
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_playing()
+    }
+
+    /// Access the context outside of the game callbacks (e.g. from tests)
+    pub fn get_context(&mut self) -> &mut CtxHandler {
+        &mut self.ctx_handler
+    }
+
+    /// Tick the timer and animator components, step the physics and run the ECS systems, they get the whole context like the game
+    fn run_systems(&mut self, time: &Time) {
+        timer::tick_components(&self.ctx_handler.world, time.delta());
+        animation::advance_animators(
+            &self.ctx_handler.world,
+            time.delta(),
+            &mut self.ctx_handler.events,
+        );
+        #[cfg(feature = "physics2d")]
+        physics2d::step_world(
+            &self.ctx_handler.world,
+            time.delta(),
+            &mut self.ctx_handler.events,
+        );
+        #[cfg(feature = "physics3d")]
+        physics3d::step_world(&self.ctx_handler.world, time.delta());
+
+        let mut systems = std::mem::take(&mut self.ctx_handler.systems);
+        systems.run(time, &mut self.ctx_handler);
+
+        // Keep the systems added while running
+        systems.append(&mut self.ctx_handler.systems);
+        self.ctx_handler.systems = systems;
+    }
+
+    /// Save the assets used in this run for the next startup warmup
+    fn save_warmup_profile(&self) {
+        if let Some(profile_path) = &self.warmup_profile {
+            if let Err(e) = warmup::recorded_profile().save(profile_path) {
+                crash::log(&format!(
+                    "Couldn't save warmup profile '{}': {}",
+                    profile_path.display(),
+                    e
+                ));
+            }
+        }
+    }
+}
+
+/// Builds an Engine choosing the subsystems to start, see `Engine::builder`
+#[derive(Clone, Debug, Default)]
+pub struct EngineBuilder {
+    config: EngineConfig,
 }
 
-impl Default for Engine {
-    fn default() -> Self {
-        Self::new()
+impl EngineBuilder {
+    /// Start from existing settings, the subsystem choices made so far are kept
+    pub fn config(mut self, config: EngineConfig) -> Self {
+        let subsystems = self.config.subsystems;
+        let headless = self.config.headless || config.headless;
+        self.config = config.subsystems(subsystems).headless(headless);
+        self
+    }
+
+    pub fn window(mut self, window: WindowConfig) -> Self {
+        self.config.window = window;
+        self
+    }
+
+    /// Start without audio device (e.g. tools and servers), sounds don't load and nothing plays.
+    /// Always the case without the `audio` feature
+    pub fn without_audio(mut self) -> Self {
+        self.config.subsystems.audio = false;
+        self
+    }
+
+    /// Start without controller support, every player stays disconnected.
+    /// Always the case without the `gamepads` feature
+    pub fn without_gamepads(mut self) -> Self {
+        self.config.subsystems.gamepads = false;
+        self
+    }
+
+    /// Only run the update loop, without window, renderer, audio and controllers (dedicated servers)
+    pub fn headless(mut self) -> Self {
+        self.config.headless = true;
+        self
+    }
+
+    pub fn build(self) -> Result<Engine, MagmaError> {
+        Engine::new(self.config)
     }
 }
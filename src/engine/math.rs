@@ -0,0 +1,41 @@
+//! Math types used by every engine API, aliases of the cgmath ones
+//!
+//! Positions, sizes and colors of the draw objects, the camera and the transforms are all
+//! `Vec2`/`Vec4`, games don't need their own math crate nor conversions.
+//! `use magma::math::prelude::*` brings the vector and matrix operations in scope.
+
+// other imports
+use cgmath::{InnerSpace, Matrix3, Matrix4, Quaternion, Vector2, Vector3, Vector4};
+
+pub use cgmath::{vec2, vec3, vec4, Deg, Rad};
+
+/// Traits of the vector, matrix and rotation operations
+pub mod prelude {
+    pub use cgmath::prelude::*;
+}
+
+pub type Vec2 = Vector2<f32>;
+pub type Vec3 = Vector3<f32>;
+pub type Vec4 = Vector4<f32>;
+pub type IVec2 = Vector2<i32>;
+pub type UVec2 = Vector2<u32>;
+pub type Mat3 = Matrix3<f32>;
+pub type Mat4 = Matrix4<f32>;
+pub type Quat = Quaternion<f32>;
+
+/// Linear interpolation between `a` and `b`, `t` going from 0.0 to 1.0
+pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Move `current` towards `target` by at most `max_delta`, without overshooting
+pub fn move_towards(current: Vec2, target: Vec2, max_delta: f32) -> Vec2 {
+    let offset = target - current;
+    let distance = offset.magnitude();
+
+    if distance <= max_delta || distance == 0.0 {
+        target
+    } else {
+        current + offset / distance * max_delta
+    }
+}
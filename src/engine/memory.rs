@@ -0,0 +1,195 @@
+//! Allocation tracking split by engine subsystem
+//!
+//! Install the allocator in the final binary (ideally only in debug builds):
+//! `#[cfg(debug_assertions)] #[global_allocator] static ALLOC: TrackingAllocator = TrackingAllocator;`
+//! then wrap subsystem work in `memory::scope(MemoryTag::...)` to attribute its allocations.
+//! The report is shown in the on-screen overlay of `ProfilerPlugin`, or printed with `report()`.
+
+// standard imports
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::fmt;
+use std::mem::{align_of, size_of};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Subsystems the allocations can be attributed to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryTag {
+    Untagged,
+    Graphics,
+    Audio,
+    Assets,
+    Ecs,
+    Scripting,
+    Network,
+}
+
+const TAG_COUNT: usize = 7;
+
+impl MemoryTag {
+    pub const ALL: [MemoryTag; TAG_COUNT] = [
+        MemoryTag::Untagged,
+        MemoryTag::Graphics,
+        MemoryTag::Audio,
+        MemoryTag::Assets,
+        MemoryTag::Ecs,
+        MemoryTag::Scripting,
+        MemoryTag::Network,
+    ];
+
+    fn from_index(index: usize) -> MemoryTag {
+        Self::ALL.get(index).copied().unwrap_or(MemoryTag::Untagged)
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static CURRENT_BYTES: [AtomicUsize; TAG_COUNT] = [ZERO; TAG_COUNT];
+static PEAK_BYTES: [AtomicUsize; TAG_COUNT] = [ZERO; TAG_COUNT];
+static ALLOCATIONS: [AtomicUsize; TAG_COUNT] = [ZERO; TAG_COUNT];
+
+thread_local! {
+    static CURRENT_TAG: Cell<usize> = Cell::new(0);
+}
+
+/// Global allocator wrapping the system one, storing the owner tag in front of every allocation
+pub struct TrackingAllocator;
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let (full_layout, offset) = match layout_with_header(layout) {
+            Some(l) => l,
+            None => return ptr::null_mut(),
+        };
+
+        let base = System.alloc(full_layout);
+        if base.is_null() {
+            return base;
+        }
+
+        let tag = CURRENT_TAG.try_with(|t| t.get()).unwrap_or(0);
+        (base as *mut usize).write(tag);
+
+        ACTIVE.store(true, Ordering::Relaxed);
+        ALLOCATIONS[tag].fetch_add(1, Ordering::Relaxed);
+        let current = CURRENT_BYTES[tag].fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK_BYTES[tag].fetch_max(current, Ordering::Relaxed);
+
+        base.add(offset)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // The layout was already validated by the matching alloc call
+        let (full_layout, offset) = layout_with_header(layout).unwrap();
+
+        let base = ptr.sub(offset);
+        let tag = (base as *const usize).read();
+
+        CURRENT_BYTES[tag].fetch_sub(layout.size(), Ordering::Relaxed);
+
+        System.dealloc(base, full_layout)
+    }
+}
+
+/// Layout with enough space in front to hold the tag, while keeping the requested alignment
+fn layout_with_header(layout: Layout) -> Option<(Layout, usize)> {
+    let align = layout.align().max(align_of::<usize>());
+    let offset = size_of::<usize>().max(align);
+
+    let full = Layout::from_size_align(layout.size().checked_add(offset)?, align).ok()?;
+
+    Some((full, offset))
+}
+
+/// Guard restoring the previous tag when dropped
+pub struct MemoryScope {
+    previous: usize,
+}
+
+impl Drop for MemoryScope {
+    fn drop(&mut self) {
+        let previous = self.previous;
+        let _ = CURRENT_TAG.try_with(|t| t.set(previous));
+    }
+}
+
+/// Attribute the allocations of the current thread to `tag` until the guard is dropped
+pub fn scope(tag: MemoryTag) -> MemoryScope {
+    let previous = CURRENT_TAG
+        .try_with(|t| t.replace(tag as usize))
+        .unwrap_or(0);
+
+    MemoryScope { previous }
+}
+
+/// Check if the TrackingAllocator is installed as global allocator
+pub fn is_tracking() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Allocations made since the start by every subsystem, always 0 without the TrackingAllocator.
+/// Two calls tell how many allocations happened in between (e.g. in a frame)
+pub fn allocation_count() -> usize {
+    ALLOCATIONS
+        .iter()
+        .map(|count| count.load(Ordering::Relaxed))
+        .sum()
+}
+
+/// Memory statistics of a single subsystem
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryUsage {
+    pub tag: MemoryTag,
+    pub current_bytes: usize,
+    pub peak_bytes: usize,
+    pub allocations: usize,
+}
+
+/// Per-subsystem memory statistics at the time of the call
+#[derive(Clone, Debug)]
+pub struct MemoryReport {
+    pub usages: Vec<MemoryUsage>,
+}
+
+impl MemoryReport {
+    pub fn total_bytes(&self) -> usize {
+        self.usages.iter().map(|u| u.current_bytes).sum()
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !is_tracking() {
+            return writeln!(f, "Memory tracking disabled (TrackingAllocator not installed)");
+        }
+
+        for usage in &self.usages {
+            writeln!(
+                f,
+                "{:<10} {:>10.1} KiB (peak {:>10.1} KiB, {} allocations)",
+                format!("{:?}", usage.tag),
+                usage.current_bytes as f32 / 1024.,
+                usage.peak_bytes as f32 / 1024.,
+                usage.allocations
+            )?;
+        }
+        writeln!(f, "{:<10} {:>10.1} KiB", "Total", self.total_bytes() as f32 / 1024.)
+    }
+}
+
+/// Collect the current memory usage of every subsystem
+pub fn report() -> MemoryReport {
+    let usages = (0..TAG_COUNT)
+        .map(|i| MemoryUsage {
+            tag: MemoryTag::from_index(i),
+            current_bytes: CURRENT_BYTES[i].load(Ordering::Relaxed),
+            peak_bytes: PEAK_BYTES[i].load(Ordering::Relaxed),
+            allocations: ALLOCATIONS[i].load(Ordering::Relaxed),
+        })
+        .collect();
+
+    MemoryReport { usages }
+}
@@ -1,4 +1,145 @@
 mod main_engine;
 mod ctx;
 
-pub use main_engine::Engine;
+pub mod config;
+
+pub mod animation;
+pub mod assets;
+pub mod atlas;
+pub mod bench;
+pub mod bitmap_font;
+pub mod camera;
+pub mod capture;
+pub mod character;
+pub mod collision;
+pub mod color;
+pub mod console;
+pub mod crash;
+pub mod debug_draw;
+pub mod debug_server;
+pub mod ecs;
+pub mod error;
+pub mod events;
+pub mod exit;
+pub mod game;
+pub mod geometry;
+pub mod health;
+pub mod hitbox;
+pub mod hot_reload;
+#[cfg(feature = "serialization")]
+pub mod inspector;
+#[cfg(feature = "ipc")]
+pub mod instance;
+pub mod ipc;
+pub mod jobs;
+pub mod kinematic;
+pub mod leaks;
+pub mod lighting;
+pub mod loading;
+pub mod lod;
+pub mod math;
+pub mod memory;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod pack;
+pub mod paths;
+#[cfg(feature = "physics2d")]
+pub mod physics2d;
+#[cfg(feature = "physics3d")]
+pub mod physics3d;
+pub mod platform;
+pub mod plugin;
+#[cfg(feature = "serialization")]
+pub mod prefab;
+pub mod profiler;
+pub mod projectile;
+pub mod random;
+pub mod replay;
+#[cfg(feature = "serialization")]
+pub mod save;
+pub mod scene;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod stats;
+pub mod testing;
+#[cfg(feature = "xml")]
+pub mod tilemap;
+pub mod time;
+pub mod timer;
+pub mod transform;
+pub mod tween;
+pub mod vehicle;
+#[cfg(feature = "video")]
+pub mod video_player;
+pub mod warmup;
+pub mod water;
+
+pub use ctx::draw_objects::{
+    GraphicObject, NineSliceObject, ParallaxObject, ParallaxRepeat, PrimitiveObject, SliceBorders,
+    SpriteObject,
+};
+pub use ctx::gamepad::{Axis, Button, GamepadHandler};
+pub use ctx::render_thread::RenderHandle;
+pub use ctx::vulkan::{
+    CameraView, DeviceRecovery, FrameError, GpuPassTime, RenderCounters, StreamingTexture,
+};
+pub use ctx::{
+    AudioHandler, AudioStream, DisplayInfo, DisplayMode, FullscreenMode, SoundEffect,
+    SwapInterval, SystemCursor, VideoHandler,
+};
+pub use config::{
+    AudioConfig, BackgroundBehavior, BackgroundPolicy, EngineConfig, FrameErrorPolicy,
+    InputConfig, LoopMode, Subsystems, TouchConfig, WindowConfig, WindowPlacement,
+};
+pub use animation::{SpriteAnimation, SpriteAnimator, SpriteFrame};
+pub use camera::{Camera2D, CameraShake, CameraTarget};
+pub use capture::{CaptureFormat, CapturePlugin, ClipRecorder};
+pub use collision::{
+    Collider, CollisionQuery, Contact, QueryFilter, RayHit, Shape, ShapeHit, TileCollider,
+};
+pub use color::Color;
+pub use console::{CVarValue, Console, ConsolePlugin};
+pub use crash::CrashReports;
+pub use debug_draw::{DebugLines, DebugLinesObject};
+pub use assets::{Assets, Handle};
+pub use atlas::{AtlasBuilder, TextureAtlas};
+pub use bitmap_font::{BitmapFont, BitmapTextObject, TextAlign};
+pub use ecs::{Entity, Schedule, World, WorldSnapshot};
+pub use error::MagmaError;
+pub use events::{EngineEvent, InputEvent, LifecycleEvent};
+pub use exit::ExitReason;
+pub use game::{Context, Frame, Game};
+pub use geometry::{Aabb, Circle, Rect};
+#[cfg(feature = "serialization")]
+pub use inspector::{Inspector, InspectorPlugin};
+pub use kinematic::KinematicController;
+pub use lighting::{Light, LightKind, LightLayerObject, Occluder};
+pub use lod::{LodLevel, LodMesh, LodMeshObject};
+pub use main_engine::{Engine, EngineBuilder, EngineSnapshot};
+pub use math::{Mat3, Mat4, Quat, Vec2, Vec3, Vec4};
+pub use pack::PackArchive;
+pub use paths::{DirKind, Paths};
+#[cfg(feature = "physics2d")]
+pub use physics2d::{BodyType, Collider2D, ColliderShape, Physics2D, PhysicsEvent, RigidBody2D};
+#[cfg(feature = "physics3d")]
+pub use physics3d::{Collider3D, ColliderShape3D, Physics3D, RigidBody3D, Transform3D};
+pub use plugin::EnginePlugin;
+#[cfg(feature = "serialization")]
+pub use prefab::{Prefab, PrefabInstance, Prefabs};
+pub use profiler::ProfilerPlugin;
+pub use random::Rng;
+pub use replay::{Replay, ReplayFrame};
+#[cfg(feature = "serialization")]
+pub use save::{SaveManager, SlotInfo};
+pub use scene::{Scene, SceneManager, SceneTransition};
+#[cfg(feature = "scripting")]
+pub use scripting::{Script, ScriptEngine};
+pub use stats::FrameStats;
+#[cfg(feature = "xml")]
+pub use tilemap::{TileMap, TileMapObject};
+pub use time::Time;
+pub use timer::{Stopwatch, Timer, TimerMode};
+pub use transform::{GlobalTransform, Parent, Transform};
+pub use tween::{Easing, Repeat, Tween, TweenId, TweenManager, TweenSnapshot};
+#[cfg(feature = "video")]
+pub use video_player::VideoPlayer;
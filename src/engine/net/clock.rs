@@ -0,0 +1,229 @@
+//! Round trip times and clocks of the other sides, measured by pings
+//!
+//! Every side periodically pings its connections, which answer right away with their own
+//! clock. The round trip time gives the latency, and the remote clock plus half of it gives
+//! the offset between the two clocks. Clients use the clock of the server to place the
+//! snapshots in time, servers use the latency of the clients for lag compensation.
+
+// standard imports
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+// import the engine modules
+use super::message::{MessageReader, MessageWriter};
+use super::{ChannelId, ConnectionId, NetEvent, Transport, RESERVED_CHANNELS};
+
+/// Channel of the pings
+pub const CLOCK_CHANNEL: ChannelId = RESERVED_CHANNELS + 2;
+
+const MESSAGE_PING: u8 = 0;
+const MESSAGE_PONG: u8 = 1;
+
+/// Recent measures kept to pick the clock offset from
+const SAMPLE_COUNT: usize = 8;
+/// Weight of a new measure in the smoothed round trip time (like TCP)
+const RTT_SMOOTHING: f64 = 0.125;
+const JITTER_SMOOTHING: f64 = 0.25;
+/// Part of the error corrected by every new measure, so the remote clock never jumps
+const OFFSET_CORRECTION: f64 = 0.1;
+
+/// Latency and clock of the other side of a connection
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionClock {
+    rtt: f64,
+    jitter: f64,
+    offset: f64,
+    /// Round trip time and clock offset of the last measures
+    samples: VecDeque<(f64, f64)>,
+}
+
+impl ConnectionClock {
+    /// Smoothed round trip time, in seconds
+    pub fn rtt(&self) -> f64 {
+        self.rtt
+    }
+
+    /// Average variation of the round trip time, in seconds
+    pub fn jitter(&self) -> f64 {
+        self.jitter
+    }
+
+    /// Seconds to add to the local clock to get the remote one
+    pub fn offset(&self) -> f64 {
+        self.offset
+    }
+
+    /// Whether a pong was received, the values are 0 before
+    pub fn is_synchronized(&self) -> bool {
+        !self.samples.is_empty()
+    }
+
+    fn add_sample(&mut self, rtt: f64, offset: f64) {
+        if self.samples.is_empty() {
+            self.rtt = rtt;
+            self.jitter = rtt / 2.0;
+            self.offset = offset;
+        } else {
+            self.jitter += ((rtt - self.rtt).abs() - self.jitter) * JITTER_SMOOTHING;
+            self.rtt += (rtt - self.rtt) * RTT_SMOOTHING;
+        }
+
+        if self.samples.len() == SAMPLE_COUNT {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((rtt, offset));
+
+        // The fastest round trip was the least delayed, its offset is the most accurate
+        let (_, best) = self
+            .samples
+            .iter()
+            .fold((f64::MAX, offset), |best, sample| {
+                if sample.0 < best.0 {
+                    *sample
+                } else {
+                    best
+                }
+            });
+        self.offset += (best - self.offset) * OFFSET_CORRECTION;
+    }
+}
+
+/// Pings the connections of a transport and keeps their clocks
+pub struct NetClock {
+    start: Instant,
+    /// Seconds between the pings of a connection
+    pub ping_interval: f64,
+    next_ping: f64,
+    connections: HashMap<ConnectionId, ConnectionClock>,
+}
+
+impl Default for NetClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            ping_interval: 0.5,
+            next_ping: 0.0,
+            connections: HashMap::new(),
+        }
+    }
+}
+
+impl NetClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_ping_interval(mut self, seconds: f64) -> Self {
+        self.ping_interval = seconds;
+        self
+    }
+
+    /// Seconds since the clock was created, the time sent to the other sides
+    pub fn local_time(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    /// Ping the connections when it's time, once per frame
+    pub fn update(&mut self, transport: &mut dyn Transport) {
+        let now = self.local_time();
+        if now < self.next_ping {
+            return;
+        }
+        self.next_ping = now + self.ping_interval;
+
+        for connection in transport.connections() {
+            self.ping(connection, transport);
+        }
+    }
+
+    /// Answer and measure the pings, returns false for the events left to the game
+    pub fn handle_event(&mut self, event: &NetEvent, transport: &mut dyn Transport) -> bool {
+        match event {
+            NetEvent::Connected(connection) => {
+                // Synchronize as soon as possible
+                self.connections
+                    .insert(*connection, ConnectionClock::default());
+                self.ping(*connection, transport);
+                false
+            }
+            NetEvent::Disconnected { connection, .. } => {
+                self.connections.remove(connection);
+                false
+            }
+            NetEvent::Message {
+                connection,
+                channel: CLOCK_CHANNEL,
+                data,
+            } => {
+                if let Err(e) = self.on_message(*connection, data, transport) {
+                    eprintln!("Invalid ping from {:?}: {}", connection, e);
+                }
+                true
+            }
+            NetEvent::Message { .. } => false,
+        }
+    }
+
+    fn on_message(
+        &mut self,
+        connection: ConnectionId,
+        data: &[u8],
+        transport: &mut dyn Transport,
+    ) -> Result<(), String> {
+        let mut reader = MessageReader::new(data);
+
+        match reader.read_u8()? {
+            MESSAGE_PING => {
+                let sent = reader.read_f64()?;
+
+                let mut writer = MessageWriter::new();
+                writer
+                    .write_u8(MESSAGE_PONG)
+                    .write_f64(sent)
+                    .write_f64(self.local_time());
+                transport.send(connection, CLOCK_CHANNEL, &writer.into_bytes())
+            }
+            MESSAGE_PONG => {
+                let sent = reader.read_f64()?;
+                let remote_time = reader.read_f64()?;
+
+                let now = self.local_time();
+                let rtt = (now - sent).max(0.0);
+                // The remote clock was read about halfway through the round trip
+                let offset = remote_time + rtt / 2.0 - now;
+
+                self.connections
+                    .entry(connection)
+                    .or_default()
+                    .add_sample(rtt, offset);
+                Ok(())
+            }
+            kind => Err(format!("Unknown message {}", kind)),
+        }
+    }
+
+    fn ping(&self, connection: ConnectionId, transport: &mut dyn Transport) {
+        let mut writer = MessageWriter::new();
+        writer.write_u8(MESSAGE_PING).write_f64(self.local_time());
+
+        // Failures close the connection, reported by the next poll
+        let _ = transport.send(connection, CLOCK_CHANNEL, &writer.into_bytes());
+    }
+
+    pub fn connection(&self, connection: ConnectionId) -> Option<&ConnectionClock> {
+        self.connections
+            .get(&connection)
+            .filter(|clock| clock.is_synchronized())
+    }
+
+    /// Smoothed round trip time of a connection, in seconds
+    pub fn rtt(&self, connection: ConnectionId) -> Option<f64> {
+        self.connection(connection).map(|clock| clock.rtt())
+    }
+
+    /// Current time of the clock at the other side of a connection (e.g. the server one)
+    pub fn remote_time(&self, connection: ConnectionId) -> Option<f64> {
+        self.connection(connection)
+            .map(|clock| self.local_time() + clock.offset())
+    }
+}
@@ -0,0 +1,625 @@
+//! Lobby of a multiplayer session: players gathering before the game starts
+//!
+//! A player hosts the lobby and the others join it by address. The host owns the state
+//! (players, ready flags, settings) and relays it to everyone, so all the lobbies agree.
+//! After `start` the lobby stays the session: its transport carries the game messages.
+
+// standard imports
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+// import the engine modules
+use super::message::{MessageReader, MessageWriter};
+use super::{
+    ChannelId, ConnectionId, NetEvent, StreamClient, StreamProtocol, StreamServer, Transport,
+    RESERVED_CHANNELS,
+};
+
+/// Channel of the lobby messages
+pub const LOBBY_CHANNEL: ChannelId = RESERVED_CHANNELS + 1;
+
+/// Longest player name, longer ones are cut
+const MAX_NAME_LENGTH: usize = 32;
+
+// Messages of the joining players
+const MESSAGE_HELLO: u8 = 0;
+const MESSAGE_SET_READY: u8 = 1;
+const MESSAGE_SEND_CHAT: u8 = 2;
+
+// Messages of the host
+const MESSAGE_WELCOME: u8 = 10;
+const MESSAGE_REFUSED: u8 = 11;
+const MESSAGE_JOINED: u8 = 12;
+const MESSAGE_LEFT: u8 = 13;
+const MESSAGE_READY: u8 = 14;
+const MESSAGE_CHAT: u8 = 15;
+const MESSAGE_SETTING: u8 = 16;
+const MESSAGE_START: u8 = 17;
+
+/// Player of a lobby, the same on every side
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PlayerId(pub u32);
+
+impl PlayerId {
+    pub const HOST: PlayerId = PlayerId(0);
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Player {
+    pub id: PlayerId,
+    pub name: String,
+    pub ready: bool,
+}
+
+/// What happened in the lobby since the last poll
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LobbyEvent {
+    /// A player is in the lobby, also reported for the players already there when joining
+    Joined(PlayerId),
+    Left(PlayerId),
+    ReadyChanged {
+        player: PlayerId,
+        ready: bool,
+    },
+    Chat {
+        player: PlayerId,
+        text: String,
+    },
+    SettingChanged {
+        key: String,
+        value: String,
+    },
+    /// The host started the game
+    Started,
+    /// Message of the game, on a channel other than the lobby one
+    Message {
+        player: PlayerId,
+        channel: ChannelId,
+        data: Vec<u8>,
+    },
+    /// The connection to the host was lost or refused, the lobby is over
+    Closed(String),
+}
+
+enum Role {
+    Host {
+        max_players: usize,
+    },
+    Guest {
+        /// Name sent to the host once connected
+        name: String,
+        welcomed: bool,
+        /// Reason the host gave before closing the connection
+        refusal: Option<String>,
+    },
+}
+
+/// Lobby hosted locally or joined on another device
+pub struct Lobby {
+    transport: Box<dyn Transport>,
+    role: Role,
+    local: PlayerId,
+    players: Vec<Player>,
+    settings: BTreeMap<String, String>,
+    started: bool,
+    closed: bool,
+    /// Events of the local changes, reported at the next poll like the remote ones
+    pending: Vec<LobbyEvent>,
+}
+
+impl Lobby {
+    /// Host a lobby listening on `address`, with the local player in it
+    pub fn host(address: SocketAddr, protocol: StreamProtocol, name: &str) -> io::Result<Self> {
+        let server = StreamServer::bind(address, protocol)?;
+
+        Ok(Self {
+            transport: Box::new(server),
+            role: Role::Host { max_players: 8 },
+            local: PlayerId::HOST,
+            players: vec![Player {
+                id: PlayerId::HOST,
+                name: clean_name(name, PlayerId::HOST),
+                ready: false,
+            }],
+            settings: BTreeMap::new(),
+            started: false,
+            closed: false,
+            pending: vec![LobbyEvent::Joined(PlayerId::HOST)],
+        })
+    }
+
+    /// Join the lobby hosted at `address`, the players are known after the `Joined` events
+    pub fn join(
+        address: SocketAddr,
+        protocol: StreamProtocol,
+        name: &str,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        let client = StreamClient::connect(address, protocol, timeout)?;
+
+        Ok(Self {
+            transport: Box::new(client),
+            role: Role::Guest {
+                name: name.to_string(),
+                welcomed: false,
+                refusal: None,
+            },
+            local: PlayerId::HOST,
+            players: Vec::new(),
+            settings: BTreeMap::new(),
+            started: false,
+            closed: false,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Most players in the lobby (host included), the others are refused
+    pub fn with_max_players(mut self, count: usize) -> Self {
+        if let Role::Host { max_players } = &mut self.role {
+            *max_players = count.max(1);
+        }
+        self
+    }
+
+    pub fn is_host(&self) -> bool {
+        matches!(self.role, Role::Host { .. })
+    }
+
+    /// Player of this side (only valid once joined for the guests)
+    pub fn local_player(&self) -> PlayerId {
+        self.local
+    }
+
+    pub fn players(&self) -> &[Player] {
+        &self.players
+    }
+
+    pub fn player(&self, id: PlayerId) -> Option<&Player> {
+        self.players.iter().find(|player| player.id == id)
+    }
+
+    /// The guests are in once the host welcomed them
+    pub fn is_joined(&self) -> bool {
+        match &self.role {
+            Role::Host { .. } => true,
+            Role::Guest { welcomed, .. } => *welcomed && !self.closed,
+        }
+    }
+
+    pub fn all_ready(&self) -> bool {
+        !self.players.is_empty() && self.players.iter().all(|player| player.ready)
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn setting(&self, key: &str) -> Option<&str> {
+        self.settings.get(key).map(|value| value.as_str())
+    }
+
+    pub fn settings(&self) -> &BTreeMap<String, String> {
+        &self.settings
+    }
+
+    /// Transport of the session, to send the game messages (or replicate entities) on it
+    pub fn transport_mut(&mut self) -> &mut dyn Transport {
+        self.transport.as_mut()
+    }
+
+    /// Connection leading to a player: the host for the guests, the player's one for the host
+    pub fn connection(&self, player: PlayerId) -> Option<ConnectionId> {
+        match self.role {
+            Role::Host { .. } if player != PlayerId::HOST && self.player(player).is_some() => {
+                Some(ConnectionId(player.0))
+            }
+            Role::Guest { .. } if player == PlayerId::HOST => Some(StreamClient::SERVER),
+            _ => None,
+        }
+    }
+
+    /// Change the ready state of the local player, reported by a `ReadyChanged` event once
+    /// the host knows it
+    pub fn set_ready(&mut self, ready: bool) {
+        if self.is_host() {
+            self.update_ready(PlayerId::HOST, ready);
+        } else {
+            let mut writer = MessageWriter::new();
+            writer.write_u8(MESSAGE_SET_READY).write_bool(ready);
+            self.send_to_host(writer);
+        }
+    }
+
+    /// Send a chat line to every player, reported back as a `Chat` event
+    pub fn send_chat(&mut self, text: &str) {
+        if self.is_host() {
+            self.relay_chat(PlayerId::HOST, text);
+        } else {
+            let mut writer = MessageWriter::new();
+            writer.write_u8(MESSAGE_SEND_CHAT).write_str(text);
+            self.send_to_host(writer);
+        }
+    }
+
+    /// Change a setting of the game for everyone (map, mode...), host only
+    pub fn set_setting(&mut self, key: &str, value: &str) -> Result<(), String> {
+        if !self.is_host() {
+            return Err("Only the host changes the settings".to_string());
+        }
+
+        self.settings.insert(key.to_string(), value.to_string());
+        self.pending.push(LobbyEvent::SettingChanged {
+            key: key.to_string(),
+            value: value.to_string(),
+        });
+
+        let mut writer = MessageWriter::new();
+        writer
+            .write_u8(MESSAGE_SETTING)
+            .write_str(key)
+            .write_str(value);
+        self.broadcast(writer);
+        Ok(())
+    }
+
+    /// Remove a player from the lobby, host only
+    pub fn kick(&mut self, player: PlayerId) -> Result<(), String> {
+        if !self.is_host() {
+            return Err("Only the host kicks players".to_string());
+        }
+
+        let connection = self
+            .connection(player)
+            .ok_or_else(|| format!("No player {:?} to kick", player))?;
+        self.refuse(connection, "Kicked by the host");
+        Ok(())
+    }
+
+    /// Start the game for every player, host only. Later joins are refused
+    pub fn start(&mut self) -> Result<(), String> {
+        if !self.is_host() {
+            return Err("Only the host starts the game".to_string());
+        }
+
+        self.started = true;
+        self.pending.push(LobbyEvent::Started);
+
+        let mut writer = MessageWriter::new();
+        writer.write_u8(MESSAGE_START);
+        self.broadcast(writer);
+        Ok(())
+    }
+
+    /// Leave the lobby, closing every connection
+    pub fn leave(&mut self) {
+        for connection in self.transport.connections() {
+            self.transport.disconnect(connection);
+        }
+        self.closed = true;
+    }
+
+    /// Exchange the messages and get what happened, once per frame
+    pub fn poll(&mut self) -> Vec<LobbyEvent> {
+        let mut events = std::mem::take(&mut self.pending);
+
+        for event in self.transport.poll() {
+            match event {
+                NetEvent::Connected(connection) => self.on_connected(connection),
+                NetEvent::Disconnected { connection, reason } => {
+                    self.on_disconnected(connection, reason, &mut events)
+                }
+                NetEvent::Message {
+                    connection,
+                    channel: LOBBY_CHANNEL,
+                    data,
+                } => {
+                    if let Err(e) = self.on_message(connection, &data, &mut events) {
+                        eprintln!("Invalid lobby message from {:?}: {}", connection, e);
+                        self.transport.disconnect(connection);
+                    }
+                }
+                NetEvent::Message {
+                    connection,
+                    channel,
+                    data,
+                } => {
+                    if let Some(player) = self.sender(connection) {
+                        events.push(LobbyEvent::Message {
+                            player,
+                            channel,
+                            data,
+                        });
+                    }
+                }
+            }
+        }
+
+        // The lobby events of the local changes made while handling the messages
+        events.append(&mut self.pending);
+        events
+    }
+
+    fn on_connected(&mut self, connection: ConnectionId) {
+        // The host waits for the hello of the guest before adding the player
+        if let Role::Guest { name, .. } = &self.role {
+            let mut writer = MessageWriter::new();
+            writer.write_u8(MESSAGE_HELLO).write_str(name);
+            let _ = self
+                .transport
+                .send(connection, LOBBY_CHANNEL, &writer.into_bytes());
+        }
+    }
+
+    fn on_disconnected(
+        &mut self,
+        connection: ConnectionId,
+        reason: String,
+        events: &mut Vec<LobbyEvent>,
+    ) {
+        match &mut self.role {
+            Role::Host { .. } => {
+                if let Some(player) = self.sender(connection) {
+                    self.players.retain(|p| p.id != player);
+
+                    let mut writer = MessageWriter::new();
+                    writer.write_u8(MESSAGE_LEFT).write_u32(player.0);
+                    self.broadcast(writer);
+                    events.push(LobbyEvent::Left(player));
+                }
+            }
+            Role::Guest { refusal, .. } => {
+                self.closed = true;
+                events.push(LobbyEvent::Closed(refusal.take().unwrap_or(reason)));
+            }
+        }
+    }
+
+    fn on_message(
+        &mut self,
+        connection: ConnectionId,
+        data: &[u8],
+        events: &mut Vec<LobbyEvent>,
+    ) -> Result<(), String> {
+        let mut reader = MessageReader::new(data);
+        let kind = reader.read_u8()?;
+
+        if self.is_host() {
+            if kind == MESSAGE_HELLO {
+                return self.on_hello(connection, reader.read_str()?, events);
+            }
+
+            let player = self.sender(connection).ok_or("Message before the hello")?;
+            match kind {
+                MESSAGE_SET_READY => self.update_ready(player, reader.read_bool()?),
+                MESSAGE_SEND_CHAT => self.relay_chat(player, reader.read_str()?),
+                _ => return Err(format!("Unknown message {}", kind)),
+            }
+            return Ok(());
+        }
+
+        match kind {
+            MESSAGE_WELCOME => {
+                self.local = PlayerId(reader.read_u32()?);
+                self.players.clear();
+                for _ in 0..reader.read_u32()? {
+                    self.players.push(Player {
+                        id: PlayerId(reader.read_u32()?),
+                        name: reader.read_str()?.to_string(),
+                        ready: reader.read_bool()?,
+                    });
+                }
+                self.settings.clear();
+                for _ in 0..reader.read_u32()? {
+                    let key = reader.read_str()?.to_string();
+                    self.settings.insert(key, reader.read_str()?.to_string());
+                }
+
+                if let Role::Guest { welcomed, .. } = &mut self.role {
+                    *welcomed = true;
+                }
+                events.extend(self.players.iter().map(|p| LobbyEvent::Joined(p.id)));
+                events.extend(self.settings.iter().map(|(key, value)| {
+                    LobbyEvent::SettingChanged {
+                        key: key.clone(),
+                        value: value.clone(),
+                    }
+                }));
+            }
+            MESSAGE_REFUSED => {
+                if let Role::Guest { refusal, .. } = &mut self.role {
+                    *refusal = Some(reader.read_str()?.to_string());
+                }
+            }
+            MESSAGE_JOINED => {
+                let player = Player {
+                    id: PlayerId(reader.read_u32()?),
+                    name: reader.read_str()?.to_string(),
+                    ready: false,
+                };
+                events.push(LobbyEvent::Joined(player.id));
+                self.players.push(player);
+            }
+            MESSAGE_LEFT => {
+                let player = PlayerId(reader.read_u32()?);
+                self.players.retain(|p| p.id != player);
+                events.push(LobbyEvent::Left(player));
+            }
+            MESSAGE_READY => {
+                let player = PlayerId(reader.read_u32()?);
+                let ready = reader.read_bool()?;
+                if let Some(p) = self.players.iter_mut().find(|p| p.id == player) {
+                    p.ready = ready;
+                }
+                events.push(LobbyEvent::ReadyChanged { player, ready });
+            }
+            MESSAGE_CHAT => events.push(LobbyEvent::Chat {
+                player: PlayerId(reader.read_u32()?),
+                text: reader.read_str()?.to_string(),
+            }),
+            MESSAGE_SETTING => {
+                let key = reader.read_str()?.to_string();
+                let value = reader.read_str()?.to_string();
+                self.settings.insert(key.clone(), value.clone());
+                events.push(LobbyEvent::SettingChanged { key, value });
+            }
+            MESSAGE_START => {
+                self.started = true;
+                events.push(LobbyEvent::Started);
+            }
+            _ => return Err(format!("Unknown message {}", kind)),
+        }
+
+        Ok(())
+    }
+
+    fn on_hello(
+        &mut self,
+        connection: ConnectionId,
+        name: &str,
+        events: &mut Vec<LobbyEvent>,
+    ) -> Result<(), String> {
+        if self.sender(connection).is_some() {
+            return Err("Hello sent twice".to_string());
+        }
+
+        let max_players = match self.role {
+            Role::Host { max_players } => max_players,
+            Role::Guest { .. } => return Err("Hello sent to a guest".to_string()),
+        };
+        if self.started {
+            self.refuse(connection, "The game already started");
+            return Ok(());
+        }
+        if self.players.len() >= max_players {
+            self.refuse(connection, "The lobby is full");
+            return Ok(());
+        }
+
+        let player = Player {
+            id: PlayerId(connection.0),
+            name: clean_name(name, PlayerId(connection.0)),
+            ready: false,
+        };
+
+        let mut writer = MessageWriter::new();
+        writer
+            .write_u8(MESSAGE_JOINED)
+            .write_u32(player.id.0)
+            .write_str(&player.name);
+        self.broadcast(writer);
+
+        events.push(LobbyEvent::Joined(player.id));
+        self.players.push(player);
+
+        let mut writer = MessageWriter::new();
+        writer
+            .write_u8(MESSAGE_WELCOME)
+            .write_u32(connection.0)
+            .write_u32(self.players.len() as u32);
+        for player in self.players.iter() {
+            writer
+                .write_u32(player.id.0)
+                .write_str(&player.name)
+                .write_bool(player.ready);
+        }
+        writer.write_u32(self.settings.len() as u32);
+        for (key, value) in self.settings.iter() {
+            writer.write_str(key).write_str(value);
+        }
+        let _ = self
+            .transport
+            .send(connection, LOBBY_CHANNEL, &writer.into_bytes());
+
+        Ok(())
+    }
+
+    fn update_ready(&mut self, player: PlayerId, ready: bool) {
+        match self.players.iter_mut().find(|p| p.id == player) {
+            Some(p) if p.ready != ready => p.ready = ready,
+            _ => return,
+        }
+
+        let mut writer = MessageWriter::new();
+        writer
+            .write_u8(MESSAGE_READY)
+            .write_u32(player.0)
+            .write_bool(ready);
+        self.broadcast(writer);
+        self.pending
+            .push(LobbyEvent::ReadyChanged { player, ready });
+    }
+
+    fn relay_chat(&mut self, player: PlayerId, text: &str) {
+        let mut writer = MessageWriter::new();
+        writer
+            .write_u8(MESSAGE_CHAT)
+            .write_u32(player.0)
+            .write_str(text);
+        self.broadcast(writer);
+        self.pending.push(LobbyEvent::Chat {
+            player,
+            text: text.to_string(),
+        });
+    }
+
+    /// Player who sent a message on a connection
+    fn sender(&self, connection: ConnectionId) -> Option<PlayerId> {
+        match self.role {
+            Role::Host { .. } => self
+                .player(PlayerId(connection.0))
+                .filter(|player| player.id != PlayerId::HOST)
+                .map(|player| player.id),
+            Role::Guest { .. } if connection == StreamClient::SERVER => Some(PlayerId::HOST),
+            Role::Guest { .. } => None,
+        }
+    }
+
+    /// Send a message of the host to every guest in the lobby
+    fn broadcast(&mut self, writer: MessageWriter) {
+        let data = writer.into_bytes();
+        for player in self.players.iter().filter(|p| p.id != PlayerId::HOST) {
+            // Failures close the connection, reported by the next poll
+            let _ = self
+                .transport
+                .send(ConnectionId(player.id.0), LOBBY_CHANNEL, &data);
+        }
+    }
+
+    fn send_to_host(&mut self, writer: MessageWriter) {
+        let _ = self
+            .transport
+            .send(StreamClient::SERVER, LOBBY_CHANNEL, &writer.into_bytes());
+    }
+
+    /// Tell a guest why it's sent away, then close its connection
+    fn refuse(&mut self, connection: ConnectionId, reason: &str) {
+        let mut writer = MessageWriter::new();
+        writer.write_u8(MESSAGE_REFUSED).write_str(reason);
+        let _ = self
+            .transport
+            .send(connection, LOBBY_CHANNEL, &writer.into_bytes());
+        self.transport.disconnect(connection);
+    }
+}
+
+/// Name without surrounding spaces nor control characters, never empty
+fn clean_name(name: &str, id: PlayerId) -> String {
+    let name: String = name
+        .trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_NAME_LENGTH)
+        .collect();
+
+    if name.is_empty() {
+        format!("Player {}", id.0 + 1)
+    } else {
+        name
+    }
+}
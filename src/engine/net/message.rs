@@ -0,0 +1,146 @@
+//! Compact binary encoding of the messages, little endian
+
+/// Builds the bytes of a message
+#[derive(Clone, Debug, Default)]
+pub struct MessageWriter {
+    data: Vec<u8>,
+}
+
+impl MessageWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_u8(&mut self, value: u8) -> &mut Self {
+        self.data.push(value);
+        self
+    }
+
+    pub fn write_bool(&mut self, value: bool) -> &mut Self {
+        self.write_u8(value as u8)
+    }
+
+    pub fn write_u16(&mut self, value: u16) -> &mut Self {
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn write_u32(&mut self, value: u32) -> &mut Self {
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn write_u64(&mut self, value: u64) -> &mut Self {
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn write_f32(&mut self, value: f32) -> &mut Self {
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn write_f64(&mut self, value: f64) -> &mut Self {
+        self.data.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    /// Bytes prefixed by their length
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.write_u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn write_str(&mut self, text: &str) -> &mut Self {
+        self.write_bytes(text.as_bytes())
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Reads the values of a message in the order they were written
+#[derive(Clone, Debug)]
+pub struct MessageReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> MessageReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if self.remaining() < length {
+            return Err(format!(
+                "Message too short: {} bytes needed at {} of {}",
+                length,
+                self.position,
+                self.data.len()
+            ));
+        }
+
+        let bytes = &self.data[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], String> {
+        let length = self.read_u32()? as usize;
+        self.take(length)
+    }
+
+    pub fn read_str(&mut self) -> Result<&'a str, String> {
+        std::str::from_utf8(self.read_bytes()?).map_err(|e| format!("Invalid text: {}", e))
+    }
+
+    /// Bytes not read yet
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+}
@@ -0,0 +1,84 @@
+//! Networking for multiplayer games: connections exchanging messages on numbered channels
+//!
+//! Every transport implements `Transport`, so the game code and the layers built on top
+//! don't depend on how the bytes travel. Transports are non-blocking: `poll` them once per
+//! frame to send the queued messages and get the `NetEvent`s of the connections.
+
+pub mod clock;
+pub mod lobby;
+mod message;
+pub mod replication;
+mod stream;
+pub(crate) mod websocket;
+
+pub use clock::{ConnectionClock, NetClock};
+pub use lobby::{Lobby, LobbyEvent, Player, PlayerId};
+pub use message::{MessageReader, MessageWriter};
+pub use replication::{
+    Networked, RemoteEntity, Replicated, Replication, ReplicationClient, ReplicationServer,
+};
+pub use stream::{StreamClient, StreamProtocol, StreamServer};
+
+// standard imports
+use std::net::SocketAddr;
+
+/// Messages bigger than this close the connection
+pub const MAX_MESSAGE_SIZE: usize = 1 << 24;
+
+/// Identifier of a connection, unique for the lifetime of a transport
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConnectionId(pub u32);
+
+/// Channel of a message, letting the receiver route it without parsing it
+/// (the higher layers use the top ones, see `RESERVED_CHANNELS`)
+pub type ChannelId = u8;
+
+/// Channels from this one up are used by the engine layers
+pub const RESERVED_CHANNELS: ChannelId = 240;
+
+/// What happened to the connections of a transport since the last poll
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetEvent {
+    /// A connection is ready to exchange messages
+    Connected(ConnectionId),
+    /// A connection was closed, by either side or because of an error
+    Disconnected {
+        connection: ConnectionId,
+        reason: String,
+    },
+    Message {
+        connection: ConnectionId,
+        channel: ChannelId,
+        data: Vec<u8>,
+    },
+}
+
+/// Way of exchanging messages with the other side(s)
+pub trait Transport {
+    /// Queue a message to a connection, sent at the next poll
+    fn send(
+        &mut self,
+        connection: ConnectionId,
+        channel: ChannelId,
+        data: &[u8],
+    ) -> Result<(), String>;
+
+    /// Queue a message to every connection
+    fn broadcast(&mut self, channel: ChannelId, data: &[u8]) {
+        for connection in self.connections() {
+            // Failures close the connection, reported by the next poll
+            let _ = self.send(connection, channel, data);
+        }
+    }
+
+    /// Send the queued messages, receive the new ones and report the connection changes
+    fn poll(&mut self) -> Vec<NetEvent>;
+
+    /// Connections ready to exchange messages
+    fn connections(&self) -> Vec<ConnectionId>;
+
+    fn remote_address(&self, connection: ConnectionId) -> Option<SocketAddr>;
+
+    /// Close a connection after sending its queued messages
+    fn disconnect(&mut self, connection: ConnectionId);
+}
@@ -0,0 +1,447 @@
+//! Replication of entities from the server to the clients
+//!
+//! The server marks the entities to share with `Replicated` and periodically sends snapshots
+//! of their networked components. Every connection only gets what changed since the last
+//! snapshot sent to it (the transports are reliable and ordered, so that one was received).
+//! Clients rebuild the entities in their own world and show them a little in the past,
+//! interpolating between the two snapshots around that time to hide the network jitter.
+//! Snapshots are stamped with the clock of the server, which the clients follow with a
+//! `NetClock`.
+
+// standard imports
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::f32::consts::PI;
+
+// import the engine modules
+use super::super::ecs::{Entity, World};
+use super::super::math::Rad;
+use super::super::transform::Transform;
+use super::message::{MessageReader, MessageWriter};
+use super::{ChannelId, ConnectionId, NetEvent, Transport, RESERVED_CHANNELS};
+
+// other imports
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Channel of the snapshots
+pub const SNAPSHOT_CHANNEL: ChannelId = RESERVED_CHANNELS;
+
+/// Component sent from the server to the clients
+pub trait Networked: Serialize + DeserializeOwned + Clone + 'static {
+    /// Value between two snapshots at `t` (0.0 to 1.0), jumps to the newest one by default
+    fn interpolate(&self, to: &Self, _t: f32) -> Self {
+        to.clone()
+    }
+}
+
+impl Networked for Transform {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        // Turn the shortest way around
+        let mut turn = (to.rotation.0 - self.rotation.0) % (2.0 * PI);
+        if turn > PI {
+            turn -= 2.0 * PI;
+        } else if turn < -PI {
+            turn += 2.0 * PI;
+        }
+
+        Transform {
+            translation: self.translation + (to.translation - self.translation) * t,
+            rotation: Rad(self.rotation.0 + turn * t),
+            scale: self.scale + (to.scale - self.scale) * t,
+        }
+    }
+}
+
+/// Marks the entities of the server sent to the clients
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Replicated;
+
+/// Entity of the server a client entity replicates (as `Entity::to_bits`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RemoteEntity(pub u64);
+
+/// Component bytes of an entity, by component index
+type EntityState = BTreeMap<u16, Vec<u8>>;
+/// Replicated entities, by server entity
+type WorldState = BTreeMap<u64, EntityState>;
+
+/// Type-erased access to a networked component type
+struct ComponentType {
+    name: &'static str,
+    read: fn(&World, Entity) -> Option<Vec<u8>>,
+    write: fn(&mut World, Entity, &[u8]) -> Result<(), String>,
+    blend: fn(&mut World, Entity, &[u8], &[u8], f32) -> Result<(), String>,
+    remove: fn(&mut World, Entity),
+}
+
+fn read_component<T: Networked>(world: &World, entity: Entity) -> Option<Vec<u8>> {
+    serde_json::to_vec(&*world.get::<T>(entity)?).ok()
+}
+
+fn decode_component<T: Networked>(bytes: &[u8]) -> Result<T, String> {
+    serde_json::from_slice(bytes)
+        .map_err(|e| format!("Invalid {} in snapshot: {}", std::any::type_name::<T>(), e))
+}
+
+fn write_component<T: Networked>(
+    world: &mut World,
+    entity: Entity,
+    bytes: &[u8],
+) -> Result<(), String> {
+    world.insert(entity, decode_component::<T>(bytes)?);
+    Ok(())
+}
+
+fn blend_component<T: Networked>(
+    world: &mut World,
+    entity: Entity,
+    from: &[u8],
+    to: &[u8],
+    t: f32,
+) -> Result<(), String> {
+    let from = decode_component::<T>(from)?;
+    let to = decode_component::<T>(to)?;
+    world.insert(entity, from.interpolate(&to, t));
+    Ok(())
+}
+
+fn remove_component<T: Networked>(world: &mut World, entity: Entity) {
+    world.remove::<T>(entity);
+}
+
+/// Networked component types, registered in the same order on the server and the clients
+#[derive(Default)]
+pub struct Replication {
+    components: Vec<ComponentType>,
+}
+
+impl Replication {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_component<T: Networked>(mut self) -> Self {
+        assert!(
+            self.components.len() < u16::MAX as usize,
+            "Too many networked components"
+        );
+
+        self.components.push(ComponentType {
+            name: std::any::type_name::<T>(),
+            read: read_component::<T>,
+            write: write_component::<T>,
+            blend: blend_component::<T>,
+            remove: remove_component::<T>,
+        });
+        self
+    }
+
+    /// Names of the registered types, to check both sides agree
+    pub fn component_names(&self) -> Vec<&'static str> {
+        self.components.iter().map(|c| c.name).collect()
+    }
+
+    fn capture(&self, world: &World) -> WorldState {
+        let entities: Vec<Entity> = match world.borrow::<Replicated>() {
+            Some(replicated) => replicated.iter().map(|(entity, _)| entity).collect(),
+            None => return WorldState::new(),
+        };
+
+        entities
+            .into_iter()
+            .map(|entity| {
+                let components = self
+                    .components
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, component)| {
+                        Some((index as u16, (component.read)(world, entity)?))
+                    })
+                    .collect();
+                (entity.to_bits(), components)
+            })
+            .collect()
+    }
+}
+
+/// Changes from `base` to `state`, as a snapshot message
+fn encode_delta(tick: u32, time: f64, base: &WorldState, state: &WorldState) -> Vec<u8> {
+    let mut writer = MessageWriter::new();
+    writer.write_u32(tick).write_f64(time);
+
+    let empty = EntityState::new();
+    let changed: Vec<(&u64, Vec<(u16, Option<&Vec<u8>>)>)> = state
+        .iter()
+        .filter_map(|(id, components)| {
+            let old = base.get(id).unwrap_or(&empty);
+
+            let mut changes: Vec<(u16, Option<&Vec<u8>>)> = components
+                .iter()
+                .filter(|(index, bytes)| old.get(index) != Some(bytes))
+                .map(|(index, bytes)| (*index, Some(bytes)))
+                .collect();
+            changes.extend(
+                old.keys()
+                    .filter(|index| !components.contains_key(index))
+                    .map(|index| (*index, None)),
+            );
+
+            // New entities are sent even without components, to spawn them
+            if changes.is_empty() && base.contains_key(id) {
+                None
+            } else {
+                Some((id, changes))
+            }
+        })
+        .collect();
+
+    writer.write_u32(changed.len() as u32);
+    for (id, changes) in changed {
+        writer.write_u64(*id).write_u16(changes.len() as u16);
+        for (index, bytes) in changes {
+            writer.write_u16(index);
+            match bytes {
+                Some(bytes) => writer.write_bool(true).write_bytes(bytes),
+                None => writer.write_bool(false),
+            };
+        }
+    }
+
+    let removed: Vec<&u64> = base.keys().filter(|id| !state.contains_key(id)).collect();
+    writer.write_u32(removed.len() as u32);
+    for id in removed {
+        writer.write_u64(*id);
+    }
+
+    writer.into_bytes()
+}
+
+/// Apply a snapshot message to the previous state, returns its tick and time
+fn decode_delta(data: &[u8], state: &mut WorldState) -> Result<(u32, f64), String> {
+    let mut reader = MessageReader::new(data);
+    let tick = reader.read_u32()?;
+    let time = reader.read_f64()?;
+
+    for _ in 0..reader.read_u32()? {
+        let id = reader.read_u64()?;
+        let components = state.entry(id).or_default();
+
+        for _ in 0..reader.read_u16()? {
+            let index = reader.read_u16()?;
+            if reader.read_bool()? {
+                components.insert(index, reader.read_bytes()?.to_vec());
+            } else {
+                components.remove(&index);
+            }
+        }
+    }
+
+    for _ in 0..reader.read_u32()? {
+        state.remove(&reader.read_u64()?);
+    }
+
+    Ok((tick, time))
+}
+
+/// Sends the replicated entities of the server world to the clients
+pub struct ReplicationServer {
+    replication: Replication,
+    tick: u32,
+    /// Last state sent to each connection, base of its next delta
+    sent: HashMap<ConnectionId, WorldState>,
+}
+
+impl ReplicationServer {
+    pub fn new(replication: Replication) -> Self {
+        Self {
+            replication,
+            tick: 0,
+            sent: HashMap::new(),
+        }
+    }
+
+    /// Number of the last snapshot sent
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    /// Track the connections of the transport, new ones get a full snapshot
+    pub fn handle_event(&mut self, event: &NetEvent) {
+        match event {
+            NetEvent::Connected(connection) => {
+                self.sent.insert(*connection, WorldState::new());
+            }
+            NetEvent::Disconnected { connection, .. } => {
+                self.sent.remove(connection);
+            }
+            NetEvent::Message { .. } => (),
+        }
+    }
+
+    /// Send the changes of the replicated entities to every connection
+    /// (call it at the network rate, 10 to 30 times per second is usually enough).
+    /// `time` is the clock of the server, `NetClock::local_time`
+    pub fn send_snapshot(&mut self, world: &World, transport: &mut dyn Transport, time: f64) {
+        self.tick = self.tick.wrapping_add(1);
+        let state = self.replication.capture(world);
+
+        for connection in transport.connections() {
+            let base = self.sent.entry(connection).or_default();
+            let message = encode_delta(self.tick, time, base, &state);
+
+            if transport
+                .send(connection, SNAPSHOT_CHANNEL, &message)
+                .is_ok()
+            {
+                *base = state.clone();
+            }
+        }
+    }
+}
+
+/// Snapshot rebuilt by a client
+struct Snapshot {
+    tick: u32,
+    /// Clock of the server when it was captured, in seconds
+    time: f64,
+    state: WorldState,
+}
+
+/// Rebuilds the entities of the server in the client world
+pub struct ReplicationClient {
+    replication: Replication,
+    /// Seconds the entities are shown in the past, about two snapshot intervals so there is
+    /// always a newer snapshot to interpolate to
+    pub interpolation_delay: f64,
+    /// State after the newest snapshot
+    state: WorldState,
+    snapshots: VecDeque<Snapshot>,
+    render_time: f64,
+    entities: HashMap<u64, Entity>,
+}
+
+impl ReplicationClient {
+    pub fn new(replication: Replication, interpolation_delay: f64) -> Self {
+        Self {
+            replication,
+            interpolation_delay,
+            state: WorldState::new(),
+            snapshots: VecDeque::new(),
+            render_time: 0.0,
+            entities: HashMap::new(),
+        }
+    }
+
+    /// Take the snapshot messages, returns false for the events left to the game
+    pub fn handle_event(&mut self, event: &NetEvent) -> Result<bool, String> {
+        match event {
+            NetEvent::Message {
+                channel: SNAPSHOT_CHANNEL,
+                data,
+                ..
+            } => {
+                let (tick, time) = decode_delta(data, &mut self.state)?;
+                self.snapshots.push_back(Snapshot {
+                    tick,
+                    time,
+                    state: self.state.clone(),
+                });
+                Ok(true)
+            }
+            NetEvent::Disconnected { .. } => {
+                // The server starts again from scratch on the next connection
+                self.state.clear();
+                Ok(false)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Tick of the newest snapshot received
+    pub fn latest_tick(&self) -> Option<u32> {
+        self.snapshots.back().map(|snapshot| snapshot.tick)
+    }
+
+    /// Server time the entities are shown at, to send with the inputs for lag compensation
+    pub fn render_time(&self) -> f64 {
+        self.render_time
+    }
+
+    /// Local entity replicating an entity of the server
+    pub fn local_entity(&self, remote: u64) -> Option<Entity> {
+        self.entities.get(&remote).copied()
+    }
+
+    /// Place the replicated entities, once per frame. `server_time` is the clock of the
+    /// server estimated by `NetClock::remote_time`
+    pub fn update(&mut self, world: &mut World, server_time: f64) -> Result<(), String> {
+        let render_time = server_time - self.interpolation_delay;
+        self.render_time = render_time;
+
+        // Keep a single snapshot older than the render time
+        while self.snapshots.len() > 1 && self.snapshots[1].time <= render_time {
+            self.snapshots.pop_front();
+        }
+
+        let from = match self.snapshots.front() {
+            Some(snapshot) => snapshot,
+            None => return Ok(()),
+        };
+        let (to, t) = match self.snapshots.get(1) {
+            Some(to) if render_time > from.time => {
+                let t = (render_time - from.time) / (to.time - from.time).max(1e-6);
+                (Some(to), t.min(1.0) as f32)
+            }
+            _ => (None, 0.0),
+        };
+
+        // Entities of the server that went away
+        let gone: Vec<u64> = self
+            .entities
+            .keys()
+            .filter(|id| !from.state.contains_key(id))
+            .copied()
+            .collect();
+        for id in gone {
+            if let Some(entity) = self.entities.remove(&id) {
+                world.despawn(entity);
+            }
+        }
+
+        for (id, components) in from.state.iter() {
+            let entity = match self.entities.get(id) {
+                Some(entity) if world.is_alive(*entity) => *entity,
+                _ => {
+                    let entity = world.spawn();
+                    world.insert(entity, RemoteEntity(*id));
+                    self.entities.insert(*id, entity);
+                    entity
+                }
+            };
+            let target = to.and_then(|to| to.state.get(id));
+
+            for (index, component) in self.replication.components.iter().enumerate() {
+                let index = index as u16;
+
+                match (components.get(&index), target.and_then(|c| c.get(&index))) {
+                    (Some(bytes), Some(next)) if bytes != next => {
+                        (component.blend)(world, entity, bytes, next, t)?
+                    }
+                    (Some(bytes), _) => (component.write)(world, entity, bytes)?,
+                    (None, _) => (component.remove)(world, entity),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Despawn every replicated entity, when leaving the server
+    pub fn clear(&mut self, world: &mut World) {
+        for (_, entity) in self.entities.drain() {
+            world.despawn(entity);
+        }
+        self.snapshots.clear();
+        self.state.clear();
+    }
+}
@@ -0,0 +1,502 @@
+//! Transports over TCP streams, reliable and ordered: raw TCP with length prefixed
+//! messages, or WebSocket so browser builds can connect
+//!
+//! Messages are `[length: u32 LE][channel][data]` over TCP, and binary frames holding
+//! `[channel][data]` over WebSocket.
+
+// standard imports
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+// import the engine modules
+use super::super::memory::{self, MemoryTag};
+use super::super::random::Rng;
+use super::websocket::{self, OPCODE_BINARY, OPCODE_CLOSE, OPCODE_CONTINUATION, OPCODE_PING};
+use super::websocket::{OPCODE_PONG, OPCODE_TEXT};
+use super::{ChannelId, ConnectionId, NetEvent, Transport, MAX_MESSAGE_SIZE};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StreamProtocol {
+    Tcp,
+    /// Path requested by the clients, e.g. "/" or "/game"
+    WebSocket {
+        path: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// WebSocket upgrade in progress
+    Handshaking,
+    Open,
+    /// Sending the last queued messages before closing
+    Closing,
+    Closed,
+}
+
+struct StreamConnection {
+    stream: TcpStream,
+    address: SocketAddr,
+    protocol: StreamProtocol,
+    /// Client side of the connection, WebSocket clients mask their frames
+    client: bool,
+    state: State,
+    /// Key of the WebSocket request of a client, to check the response
+    websocket_key: Option<String>,
+    read_buffer: Vec<u8>,
+    write_buffer: Vec<u8>,
+    /// Message split in WebSocket frames, until the last one
+    fragments: Vec<u8>,
+    connected_reported: bool,
+    close_reason: Option<String>,
+    rng: Rng,
+}
+
+impl StreamConnection {
+    fn new(
+        stream: TcpStream,
+        address: SocketAddr,
+        protocol: StreamProtocol,
+        client: bool,
+    ) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+
+        let mut connection = Self {
+            stream,
+            address,
+            state: match protocol {
+                StreamProtocol::Tcp => State::Open,
+                StreamProtocol::WebSocket { .. } => State::Handshaking,
+            },
+            protocol,
+            client,
+            websocket_key: None,
+            read_buffer: Vec::new(),
+            write_buffer: Vec::new(),
+            fragments: Vec::new(),
+            connected_reported: false,
+            close_reason: None,
+            rng: Rng::from_time(),
+        };
+
+        if let (true, StreamProtocol::WebSocket { path }) = (client, &connection.protocol) {
+            let rng = &mut connection.rng;
+            let key_bytes: Vec<u8> = (0..16).map(|_| rng.next_u32() as u8).collect();
+            let key = base64::encode(&key_bytes);
+            let request = websocket::client_request(&address.to_string(), path, &key);
+
+            connection
+                .write_buffer
+                .extend_from_slice(request.as_bytes());
+            connection.websocket_key = Some(key);
+        }
+
+        Ok(connection)
+    }
+
+    fn queue(&mut self, channel: ChannelId, data: &[u8]) -> Result<(), String> {
+        let _scope = memory::scope(MemoryTag::Network);
+
+        if self.state != State::Open {
+            return Err(format!("Connection to {} isn't open", self.address));
+        }
+        if data.len() + 1 > MAX_MESSAGE_SIZE {
+            return Err(format!("Message too big ({} bytes)", data.len()));
+        }
+
+        match self.protocol {
+            StreamProtocol::Tcp => {
+                let length = (data.len() + 1) as u32;
+                self.write_buffer.extend_from_slice(&length.to_le_bytes());
+                self.write_buffer.push(channel);
+                self.write_buffer.extend_from_slice(data);
+            }
+            StreamProtocol::WebSocket { .. } => {
+                let mut payload = Vec::with_capacity(data.len() + 1);
+                payload.push(channel);
+                payload.extend_from_slice(data);
+                self.queue_frame(OPCODE_BINARY, &payload);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn queue_frame(&mut self, opcode: u8, payload: &[u8]) {
+        let mask = if self.client {
+            Some(self.rng.next_u32().to_le_bytes())
+        } else {
+            None
+        };
+        let frame = websocket::encode_frame(opcode, payload, mask);
+        self.write_buffer.extend_from_slice(&frame);
+    }
+
+    fn close(&mut self, reason: &str) {
+        if self.state == State::Closed {
+            return;
+        }
+
+        let _ = self.stream.shutdown(Shutdown::Both);
+        self.state = State::Closed;
+        self.close_reason.get_or_insert_with(|| reason.to_string());
+    }
+
+    /// Close after sending the queued messages
+    fn start_closing(&mut self, reason: &str) {
+        if let State::Open | State::Handshaking = self.state {
+            if let (State::Open, StreamProtocol::WebSocket { .. }) = (self.state, &self.protocol) {
+                self.queue_frame(OPCODE_CLOSE, &[]);
+            }
+            self.state = State::Closing;
+            self.close_reason = Some(reason.to_string());
+        }
+    }
+
+    /// Read, handle and send everything possible without blocking
+    fn poll(&mut self, id: ConnectionId, events: &mut Vec<NetEvent>) {
+        self.read();
+        if let Err(e) = self.process(id, events) {
+            self.close(&e);
+        }
+        self.flush();
+
+        if self.state == State::Closing && self.write_buffer.is_empty() {
+            self.close("Closed");
+        }
+        if self.state == State::Closed && self.connected_reported {
+            events.push(NetEvent::Disconnected {
+                connection: id,
+                reason: self.close_reason.clone().unwrap_or_default(),
+            });
+            self.connected_reported = false;
+        }
+    }
+
+    fn read(&mut self) {
+        let mut chunk = [0; 4096];
+
+        while self.state != State::Closed {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => self.close("Closed by the other side"),
+                Ok(read) => self.read_buffer.extend_from_slice(&chunk[..read]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => self.close(&e.to_string()),
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        while self.state != State::Closed && !self.write_buffer.is_empty() {
+            match self.stream.write(&self.write_buffer) {
+                Ok(0) => self.close("Couldn't write to the stream"),
+                Ok(written) => {
+                    self.write_buffer.drain(..written);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => self.close(&e.to_string()),
+            }
+        }
+    }
+
+    fn process(&mut self, id: ConnectionId, events: &mut Vec<NetEvent>) -> Result<(), String> {
+        if self.state == State::Handshaking && !self.handshake()? {
+            return Ok(());
+        }
+
+        if !self.connected_reported && self.state != State::Closed {
+            events.push(NetEvent::Connected(id));
+            self.connected_reported = true;
+        }
+
+        loop {
+            let message = match self.protocol {
+                StreamProtocol::Tcp => self.next_tcp_message()?,
+                StreamProtocol::WebSocket { .. } => self.next_websocket_message()?,
+            };
+
+            match message {
+                Some((channel, data)) => events.push(NetEvent::Message {
+                    connection: id,
+                    channel,
+                    data,
+                }),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Go on with the WebSocket upgrade, true once done
+    fn handshake(&mut self) -> Result<bool, String> {
+        let length = match websocket::head_length(&self.read_buffer) {
+            Some(length) => length,
+            None if self.read_buffer.len() > 16 * 1024 => {
+                return Err("WebSocket handshake too long".to_string())
+            }
+            None => return Ok(false),
+        };
+        let head = String::from_utf8_lossy(&self.read_buffer[..length]).into_owned();
+        self.read_buffer.drain(..length);
+
+        match &self.websocket_key {
+            Some(key) => websocket::check_response(&head, key)?,
+            None => match websocket::server_response(&head) {
+                Ok(response) => self.write_buffer.extend_from_slice(response.as_bytes()),
+                Err(e) => {
+                    self.write_buffer.extend_from_slice(
+                        b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n",
+                    );
+                    self.state = State::Closing;
+                    self.close_reason = Some(e);
+                    return Ok(false);
+                }
+            },
+        }
+
+        self.state = State::Open;
+        Ok(true)
+    }
+
+    fn next_tcp_message(&mut self) -> Result<Option<(ChannelId, Vec<u8>)>, String> {
+        if self.read_buffer.len() < 4 {
+            return Ok(None);
+        }
+
+        let mut length = [0; 4];
+        length.copy_from_slice(&self.read_buffer[..4]);
+        let length = u32::from_le_bytes(length) as usize;
+        if length == 0 || length > MAX_MESSAGE_SIZE {
+            return Err(format!("Invalid message length {}", length));
+        }
+        if self.read_buffer.len() < 4 + length {
+            return Ok(None);
+        }
+
+        let channel = self.read_buffer[4];
+        let data = self.read_buffer[5..4 + length].to_vec();
+        self.read_buffer.drain(..4 + length);
+
+        Ok(Some((channel, data)))
+    }
+
+    fn next_websocket_message(&mut self) -> Result<Option<(ChannelId, Vec<u8>)>, String> {
+        loop {
+            // The frames come from the other side of the connection
+            let frame =
+                match websocket::decode_frame(&self.read_buffer, MAX_MESSAGE_SIZE, !self.client)? {
+                    Some(frame) => frame,
+                    None => return Ok(None),
+                };
+            self.read_buffer.drain(..frame.length);
+
+            match frame.opcode {
+                OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                    self.fragments.extend_from_slice(&frame.payload);
+                    if self.fragments.len() > MAX_MESSAGE_SIZE {
+                        return Err("WebSocket message too big".to_string());
+                    }
+                    if !frame.fin {
+                        continue;
+                    }
+
+                    let message = std::mem::take(&mut self.fragments);
+                    match message.split_first() {
+                        Some((&channel, data)) => return Ok(Some((channel, data.to_vec()))),
+                        None => return Err("Empty WebSocket message".to_string()),
+                    }
+                }
+                OPCODE_PING => self.queue_frame(OPCODE_PONG, &frame.payload),
+                OPCODE_PONG => {}
+                OPCODE_CLOSE => {
+                    if self.state == State::Open {
+                        self.queue_frame(OPCODE_CLOSE, &[]);
+                        self.state = State::Closing;
+                        self.close_reason = Some("Closed by the other side".to_string());
+                    }
+                    return Ok(None);
+                }
+                opcode => return Err(format!("Unknown WebSocket opcode {}", opcode)),
+            }
+        }
+    }
+}
+
+/// Server accepting the connections of the clients
+pub struct StreamServer {
+    listener: TcpListener,
+    protocol: StreamProtocol,
+    connections: BTreeMap<ConnectionId, StreamConnection>,
+    next_id: u32,
+}
+
+impl StreamServer {
+    /// Start listening on an address, e.g. `0.0.0.0:7777` to be reachable from other devices
+    pub fn bind(address: SocketAddr, protocol: StreamProtocol) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            protocol,
+            connections: BTreeMap::new(),
+            next_id: 1,
+        })
+    }
+
+    /// Address the server is actually listening on (e.g. the port picked for port 0)
+    pub fn local_address(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    fn accept_connections(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, address)) => {
+                    match StreamConnection::new(stream, address, self.protocol.clone(), false) {
+                        Ok(connection) => {
+                            self.connections
+                                .insert(ConnectionId(self.next_id), connection);
+                            self.next_id += 1;
+                        }
+                        Err(e) => eprintln!("Couldn't set up connection from {}: {}", address, e),
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    eprintln!("Couldn't accept connection: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl Transport for StreamServer {
+    fn send(
+        &mut self,
+        connection: ConnectionId,
+        channel: ChannelId,
+        data: &[u8],
+    ) -> Result<(), String> {
+        self.connections
+            .get_mut(&connection)
+            .ok_or_else(|| format!("Unknown connection {:?}", connection))?
+            .queue(channel, data)
+    }
+
+    fn poll(&mut self) -> Vec<NetEvent> {
+        let _scope = memory::scope(MemoryTag::Network);
+
+        self.accept_connections();
+
+        let mut events = Vec::new();
+        for (&id, connection) in self.connections.iter_mut() {
+            connection.poll(id, &mut events);
+        }
+        self.connections
+            .retain(|_, connection| connection.state != State::Closed);
+
+        events
+    }
+
+    fn connections(&self) -> Vec<ConnectionId> {
+        self.connections
+            .iter()
+            .filter(|(_, connection)| connection.state == State::Open)
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    fn remote_address(&self, connection: ConnectionId) -> Option<SocketAddr> {
+        self.connections
+            .get(&connection)
+            .map(|connection| connection.address)
+    }
+
+    fn disconnect(&mut self, connection: ConnectionId) {
+        if let Some(connection) = self.connections.get_mut(&connection) {
+            connection.start_closing("Disconnected by the server");
+        }
+    }
+}
+
+/// Client connected to a server, which is the connection `StreamClient::SERVER`
+pub struct StreamClient {
+    connection: StreamConnection,
+}
+
+impl StreamClient {
+    pub const SERVER: ConnectionId = ConnectionId(0);
+
+    /// Connect to a server, blocking for at most `timeout`.
+    /// The connection is ready after the `Connected` event
+    pub fn connect(
+        address: SocketAddr,
+        protocol: StreamProtocol,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        let stream = TcpStream::connect_timeout(&address, timeout)?;
+
+        Ok(Self {
+            connection: StreamConnection::new(stream, address, protocol, true)?,
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.state == State::Open
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.connection.state == State::Closed
+    }
+}
+
+impl Transport for StreamClient {
+    fn send(
+        &mut self,
+        connection: ConnectionId,
+        channel: ChannelId,
+        data: &[u8],
+    ) -> Result<(), String> {
+        if connection != Self::SERVER {
+            return Err(format!("Unknown connection {:?}", connection));
+        }
+
+        self.connection.queue(channel, data)
+    }
+
+    fn poll(&mut self) -> Vec<NetEvent> {
+        let _scope = memory::scope(MemoryTag::Network);
+
+        let mut events = Vec::new();
+        self.connection.poll(Self::SERVER, &mut events);
+        events
+    }
+
+    fn connections(&self) -> Vec<ConnectionId> {
+        if self.is_connected() {
+            vec![Self::SERVER]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn remote_address(&self, connection: ConnectionId) -> Option<SocketAddr> {
+        if connection == Self::SERVER {
+            Some(self.connection.address)
+        } else {
+            None
+        }
+    }
+
+    fn disconnect(&mut self, connection: ConnectionId) {
+        if connection == Self::SERVER {
+            self.connection.start_closing("Disconnected by the client");
+        }
+    }
+}
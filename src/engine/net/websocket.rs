@@ -0,0 +1,223 @@
+//! WebSocket (RFC 6455) handshakes and frames, enough for binary messages with browsers
+
+// other imports
+use sha1::{Digest, Sha1};
+
+/// Appended to the key of the client to prove the server speaks WebSocket
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+pub(crate) const OPCODE_CONTINUATION: u8 = 0x0;
+pub(crate) const OPCODE_TEXT: u8 = 0x1;
+pub(crate) const OPCODE_BINARY: u8 = 0x2;
+pub(crate) const OPCODE_CLOSE: u8 = 0x8;
+pub(crate) const OPCODE_PING: u8 = 0x9;
+pub(crate) const OPCODE_PONG: u8 = 0xA;
+
+/// Frame read from a stream
+pub(crate) struct Frame {
+    pub fin: bool,
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+    /// Bytes of the stream taken by the frame
+    pub length: usize,
+}
+
+/// Request opening the connection, `key` is 16 random bytes in base64
+pub(crate) fn client_request(host: &str, path: &str, key: &str) -> String {
+    format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        path, host, key
+    )
+}
+
+/// Response accepting the request of a client
+pub(crate) fn server_response(request: &str) -> Result<String, String> {
+    let (first_line, headers) = parse_head(request);
+    if !first_line.starts_with("GET ") {
+        return Err(format!("Invalid WebSocket request '{}'", first_line));
+    }
+
+    let upgrade = header(&headers, "upgrade").unwrap_or("");
+    if !upgrade.eq_ignore_ascii_case("websocket") {
+        return Err("Missing WebSocket upgrade header".to_string());
+    }
+    let key = header(&headers, "sec-websocket-key").ok_or("Missing WebSocket key")?;
+
+    Ok(format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    ))
+}
+
+/// Check the response of the server to a request sent with `key`
+pub(crate) fn check_response(response: &str, key: &str) -> Result<(), String> {
+    let (first_line, headers) = parse_head(response);
+    if first_line.split_whitespace().nth(1) != Some("101") {
+        return Err(format!("WebSocket upgrade refused: '{}'", first_line));
+    }
+
+    match header(&headers, "sec-websocket-accept") {
+        Some(accept) if accept == accept_key(key) => Ok(()),
+        _ => Err("Invalid WebSocket accept key".to_string()),
+    }
+}
+
+/// End of the HTTP head at the start of a buffer, after the empty line
+pub(crate) fn head_length(buffer: &[u8]) -> Option<usize> {
+    buffer
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|position| position + 4)
+}
+
+/// First line and headers (names in lowercase) of an HTTP head
+fn parse_head(head: &str) -> (&str, Vec<(String, &str)>) {
+    let mut lines = head.lines();
+    let first_line = lines.next().unwrap_or("").trim();
+
+    let headers = lines
+        .filter_map(|line| {
+            let colon = line.find(':')?;
+            Some((
+                line[..colon].trim().to_ascii_lowercase(),
+                line[colon + 1..].trim(),
+            ))
+        })
+        .collect();
+
+    (first_line, headers)
+}
+
+fn header<'a>(headers: &[(String, &'a str)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header == name)
+        .map(|(_, value)| *value)
+}
+
+fn accept_key(key: &str) -> String {
+    base64::encode(Sha1::digest(
+        format!("{}{}", key, HANDSHAKE_GUID).as_bytes(),
+    ))
+}
+
+/// Frame with a whole message, clients must give a mask
+pub(crate) fn encode_frame(opcode: u8, payload: &[u8], mask: Option<[u8; 4]>) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 14);
+    frame.push(0x80 | opcode);
+
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    match payload.len() {
+        length if length < 126 => frame.push(mask_bit | length as u8),
+        length if length <= u16::MAX as usize => {
+            frame.push(mask_bit | 126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(mask_bit | 127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+
+    match mask {
+        Some(mask) => {
+            frame.extend_from_slice(&mask);
+            frame.extend(
+                payload
+                    .iter()
+                    .enumerate()
+                    .map(|(i, byte)| byte ^ mask[i % 4]),
+            );
+        }
+        None => frame.extend_from_slice(payload),
+    }
+
+    frame
+}
+
+/// Frame at the start of a buffer, None until it's complete.
+/// Frames of clients must be masked and frames of servers must not, the others are refused
+pub(crate) fn decode_frame(
+    buffer: &[u8],
+    max_size: usize,
+    from_client: bool,
+) -> Result<Option<Frame>, String> {
+    if buffer.len() < 2 {
+        return Ok(None);
+    }
+
+    let fin = buffer[0] & 0x80 != 0;
+    let opcode = buffer[0] & 0x0F;
+    let masked = buffer[1] & 0x80 != 0;
+    if masked != from_client {
+        return Err(if from_client {
+            "Unmasked WebSocket frame from a client".to_string()
+        } else {
+            "Masked WebSocket frame from a server".to_string()
+        });
+    }
+
+    let (payload_length, mut offset) = match buffer[1] & 0x7F {
+        126 => {
+            if buffer.len() < 4 {
+                return Ok(None);
+            }
+            (u16::from_be_bytes([buffer[2], buffer[3]]) as u64, 4)
+        }
+        127 => {
+            if buffer.len() < 10 {
+                return Ok(None);
+            }
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&buffer[2..10]);
+            (u64::from_be_bytes(bytes), 10)
+        }
+        length => (length as u64, 2),
+    };
+    if payload_length > max_size as u64 {
+        return Err(format!(
+            "WebSocket frame too big ({} bytes)",
+            payload_length
+        ));
+    }
+
+    let mask = if masked {
+        if buffer.len() < offset + 4 {
+            return Ok(None);
+        }
+        let mask = [
+            buffer[offset],
+            buffer[offset + 1],
+            buffer[offset + 2],
+            buffer[offset + 3],
+        ];
+        offset += 4;
+        Some(mask)
+    } else {
+        None
+    };
+
+    let length = offset + payload_length as usize;
+    if buffer.len() < length {
+        return Ok(None);
+    }
+
+    let payload = &buffer[offset..length];
+    let payload = match mask {
+        Some(mask) => payload
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ mask[i % 4])
+            .collect(),
+        None => payload.to_vec(),
+    };
+
+    Ok(Some(Frame {
+        fin,
+        opcode,
+        payload,
+        length,
+    }))
+}
@@ -0,0 +1,389 @@
+//! Packed asset archives: a whole asset directory baked into a single compressed file
+//!
+//! Archives are made by the `magma-pack` tool and mounted on a directory (usually the asset
+//! root, see `EngineConfig::asset_archive`). The asset manager then reads the files of that
+//! directory from the archives first, and from the disk when they aren't packed.
+//!
+//! An archive starts with the magic bytes, the format version and the length of its manifest
+//! (little endian u32 and u64), followed by the manifest in JSON and the data of the files.
+//! Every file is compressed with deflate unless that doesn't make it smaller, and its content
+//! hash is checked when it's read. Files with the same content are stored once.
+
+// standard imports
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// other imports
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+#[cfg(feature = "serialization")]
+use serde::{Deserialize, Serialize};
+
+// import the engine modules
+use super::platform;
+
+pub const PACK_MAGIC: [u8; 4] = *b"MGPK";
+pub const PACK_VERSION: u32 = 1;
+/// Magic, version and manifest length
+const HEADER_SIZE: u64 = 16;
+
+/// Archives mounted on a directory, the last mounted searched first
+static MOUNTS: Mutex<Vec<(PathBuf, Arc<PackArchive>)>> = Mutex::new(Vec::new());
+
+/// File stored in an archive
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct PackEntry {
+    /// Relative to the packed directory, with '/' separators
+    pub path: String,
+    /// Start of the data, from the end of the manifest
+    pub offset: u64,
+    /// Bytes stored in the archive
+    pub packed_size: u64,
+    /// Bytes of the original file
+    pub size: u64,
+    pub compressed: bool,
+    /// `content_hash` of the original file, in hexadecimal
+    pub hash: String,
+}
+
+/// Table of contents of an archive
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct PackManifest {
+    pub entries: Vec<PackEntry>,
+}
+
+impl PackManifest {
+    #[cfg(feature = "serialization")]
+    fn parse(bytes: &[u8]) -> Result<Self, String> {
+        serde_json::from_slice(bytes).map_err(|e| e.to_string())
+    }
+
+    /// The manifest is in JSON, archives can't be read without the `serialization` feature
+    #[cfg(not(feature = "serialization"))]
+    fn parse(_bytes: &[u8]) -> Result<Self, String> {
+        Err(String::from("archives need the serialization feature"))
+    }
+
+    #[cfg(feature = "serialization")]
+    fn encode(&self) -> Result<Vec<u8>, String> {
+        serde_json::to_vec(self).map_err(|e| e.to_string())
+    }
+
+    #[cfg(not(feature = "serialization"))]
+    fn encode(&self) -> Result<Vec<u8>, String> {
+        Err(String::from("archives need the serialization feature"))
+    }
+}
+
+/// Hash of the content of a file (64-bit FNV-1a), stable between runs and platforms
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Path inside an archive of a path relative to the packed directory
+fn entry_path(relative: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// Archive opened for reading, the file data is read when needed
+pub struct PackArchive {
+    path: PathBuf,
+    file: Mutex<File>,
+    data_start: u64,
+    entries: HashMap<String, PackEntry>,
+}
+
+impl PackArchive {
+    pub fn open(path: &Path) -> Result<PackArchive, String> {
+        let error = |e: &dyn std::fmt::Display| {
+            format!("Couldn't open archive '{}': {}", path.display(), e)
+        };
+
+        let mut file = File::open(path).map_err(|e| error(&e))?;
+        let file_size = file.metadata().map_err(|e| error(&e))?.len();
+        let mut header = [0; HEADER_SIZE as usize];
+        file.read_exact(&mut header).map_err(|e| error(&e))?;
+
+        if header[..4] != PACK_MAGIC {
+            return Err(error(&"not an asset archive"));
+        }
+        let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if version != PACK_VERSION {
+            return Err(error(&format!("unsupported version {}", version)));
+        }
+        let mut length = [0; 8];
+        length.copy_from_slice(&header[8..]);
+        let length = u64::from_le_bytes(length);
+        // Checked before allocating, a corrupted length would ask for any amount of memory
+        if length > file_size.saturating_sub(HEADER_SIZE) {
+            return Err(error(&"manifest longer than the archive"));
+        }
+
+        let mut manifest = vec![0; length as usize];
+        file.read_exact(&mut manifest).map_err(|e| error(&e))?;
+        let manifest = PackManifest::parse(&manifest).map_err(|e| error(&e))?;
+
+        let data_start = HEADER_SIZE + length;
+        for entry in &manifest.entries {
+            let end = entry.offset.checked_add(entry.packed_size);
+            if end.map_or(true, |end| end > file_size - data_start) {
+                return Err(error(&format!(
+                    "'{}' is outside of the archive",
+                    entry.path
+                )));
+            }
+        }
+
+        Ok(PackArchive {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+            data_start,
+            entries: manifest
+                .entries
+                .into_iter()
+                .map(|entry| (entry.path.clone(), entry))
+                .collect(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Check if a file is packed, by its path inside the archive ("sprites/ferris.png")
+    pub fn contains(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    pub fn entry(&self, path: &str) -> Option<&PackEntry> {
+        self.entries.get(path)
+    }
+
+    /// Every packed file
+    pub fn entries(&self) -> impl Iterator<Item = &PackEntry> {
+        self.entries.values()
+    }
+
+    /// Content of a packed file, checked against its hash
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, String> {
+        let error = |e: &dyn std::fmt::Display| {
+            format!(
+                "Couldn't read '{}' from archive '{}': {}",
+                path,
+                self.path.display(),
+                e
+            )
+        };
+        let entry = self.entries.get(path).ok_or_else(|| error(&"not packed"))?;
+
+        let mut packed = vec![0; entry.packed_size as usize];
+        {
+            let mut file = self.file.lock().map_err(|_| error(&"archive poisoned"))?;
+            file.seek(SeekFrom::Start(self.data_start + entry.offset))
+                .and_then(|_| file.read_exact(&mut packed))
+                .map_err(|e| error(&e))?;
+        }
+
+        let bytes = if entry.compressed {
+            // The size isn't trusted for the allocation, but nothing bigger is decompressed
+            let mut bytes = Vec::new();
+            DeflateDecoder::new(&packed[..])
+                .take(entry.size)
+                .read_to_end(&mut bytes)
+                .map_err(|e| error(&e))?;
+            bytes
+        } else {
+            packed
+        };
+
+        if format!("{:016x}", content_hash(&bytes)) != entry.hash {
+            return Err(error(&"corrupted data"));
+        }
+        Ok(bytes)
+    }
+}
+
+/// Summary of a baked archive
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PackReport {
+    pub files: usize,
+    /// Files stored once for several paths
+    pub duplicates: usize,
+    /// Bytes of the original files
+    pub size: u64,
+    /// Bytes of the archive
+    pub packed_size: u64,
+}
+
+/// Files of a directory and its subdirectories, in a stable order
+fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<_>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Pack every file of a directory in an archive, compressed with a level from 0 (none) to 9
+pub fn bake(directory: &Path, output: &Path, level: u32) -> Result<PackReport, String> {
+    let mut files = Vec::new();
+    collect_files(directory, &mut files)
+        .map_err(|e| format!("Couldn't list '{}': {}", directory.display(), e))?;
+    // The archive may be written in the packed directory
+    let output_path = output.canonicalize().ok();
+
+    let mut report = PackReport::default();
+    let mut manifest = PackManifest::default();
+    let mut data = Vec::new();
+    // Data already stored by hash and size, with the first file storing it
+    let mut stored: HashMap<(u64, u64), Vec<(PathBuf, (u64, u64, bool))>> = HashMap::new();
+
+    for file in files {
+        if output_path.is_some() && file.canonicalize().ok() == output_path {
+            continue;
+        }
+        let error =
+            |e: &dyn std::fmt::Display| format!("Couldn't pack '{}': {}", file.display(), e);
+
+        let relative = file.strip_prefix(directory).map_err(|e| error(&e))?;
+        let path = entry_path(relative).ok_or_else(|| error(&"path isn't valid UTF-8"))?;
+        let bytes = fs::read(&file).map_err(|e| error(&e))?;
+        let hash = content_hash(&bytes);
+        let size = bytes.len() as u64;
+
+        // Files with the same hash are only shared when their bytes are the same
+        let duplicate = stored.get(&(hash, size)).and_then(|locations| {
+            locations
+                .iter()
+                .find(|(first, _)| fs::read(first).map_or(false, |first| first == bytes))
+        });
+        let (offset, packed_size, compressed) = match duplicate {
+            Some((_, location)) => {
+                report.duplicates += 1;
+                *location
+            }
+            None => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::new(level.min(9)));
+                encoder.write_all(&bytes).map_err(|e| error(&e))?;
+                let deflated = encoder.finish().map_err(|e| error(&e))?;
+                // Already compressed formats (PNG, OGG...) are kept as they are
+                let compressed = deflated.len() < bytes.len();
+                let packed = if compressed { deflated } else { bytes };
+
+                let location = (data.len() as u64, packed.len() as u64, compressed);
+                data.extend_from_slice(&packed);
+                stored
+                    .entry((hash, size))
+                    .or_default()
+                    .push((file.clone(), location));
+                location
+            }
+        };
+
+        report.files += 1;
+        report.size += size;
+        manifest.entries.push(PackEntry {
+            path,
+            offset,
+            packed_size,
+            size,
+            compressed,
+            hash: format!("{:016x}", hash),
+        });
+    }
+
+    let manifest = manifest.encode()?;
+    let mut archive = Vec::with_capacity(HEADER_SIZE as usize + manifest.len() + data.len());
+    archive.extend_from_slice(&PACK_MAGIC);
+    archive.extend_from_slice(&PACK_VERSION.to_le_bytes());
+    archive.extend_from_slice(&(manifest.len() as u64).to_le_bytes());
+    archive.extend_from_slice(&manifest);
+    archive.extend_from_slice(&data);
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    fs::write(output, &archive)
+        .map_err(|e| format!("Couldn't write archive '{}': {}", output.display(), e))?;
+
+    report.packed_size = archive.len() as u64;
+    Ok(report)
+}
+
+/// Read the files of a directory from an archive, before the ones on disk
+pub fn mount(archive: &Path, directory: &Path) -> Result<(), String> {
+    let archive = Arc::new(PackArchive::open(archive)?);
+    let mut mounts = MOUNTS.lock().map_err(|_| "Archive mounts poisoned")?;
+    mounts.push((directory.to_path_buf(), archive));
+    Ok(())
+}
+
+/// Stop reading from an archive, returns false if it wasn't mounted
+pub fn unmount(archive: &Path) -> bool {
+    match MOUNTS.lock() {
+        Ok(mut mounts) => {
+            let count = mounts.len();
+            mounts.retain(|(_, mounted)| mounted.path() != archive);
+            mounts.len() != count
+        }
+        Err(_) => false,
+    }
+}
+
+/// Archive holding a file and the path of the file inside it
+fn find(path: &Path) -> Option<(Arc<PackArchive>, String)> {
+    let mounts = MOUNTS.lock().ok()?;
+
+    mounts.iter().rev().find_map(|(directory, archive)| {
+        let inner = entry_path(path.strip_prefix(directory).ok()?)?;
+        if archive.contains(&inner) {
+            Some((archive.clone(), inner))
+        } else {
+            None
+        }
+    })
+}
+
+/// Check if a file is read from a mounted archive
+pub fn is_packed(path: &Path) -> bool {
+    find(path).is_some()
+}
+
+/// Content of a file, from the mounted archives or from the disk (the APK on Android)
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    match find(path) {
+        Some((archive, inner)) => archive
+            .read(&inner)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        None => platform::read_file(path),
+    }
+}
+
+/// Content of a text file, from the mounted archives or from the disk
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    String::from_utf8(read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,225 @@
+//! Platform directories of the game: config, saves, cache and logs
+//!
+//! Every directory is namespaced by the project name and created the first time it's requested.
+//!
+//! - Linux: the XDG directories (`~/.config`, `~/.local/share`, `~/.cache`, `~/.local/state`)
+//! - Windows: `%APPDATA%` for config and saves, `%LOCALAPPDATA%` for cache and logs
+//! - macOS: `~/Library/Application Support`, `~/Library/Caches` and `~/Library/Logs`
+//! - Android and iOS: the private storage of the app, as given by SDL2
+
+// standard imports
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Kind of directory managed by `Paths`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DirKind {
+    Config,
+    Saves,
+    Cache,
+    Logs,
+}
+
+/// Resolves (and creates) the directories of a project
+#[derive(Clone, Debug)]
+pub struct Paths {
+    project_name: String,
+    /// Overrides every platform directory, e.g. for portable installs and tests
+    root: Option<PathBuf>,
+}
+
+fn env_path(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+fn home() -> PathBuf {
+    env_path("HOME")
+        .or_else(|| env_path("USERPROFILE"))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Platform directory holding the directories of every project
+#[cfg(target_os = "windows")]
+fn platform_base(kind: DirKind) -> (PathBuf, Option<&'static str>) {
+    let roaming = || env_path("APPDATA").unwrap_or_else(|| home().join("AppData").join("Roaming"));
+    let local = || env_path("LOCALAPPDATA").unwrap_or_else(|| home().join("AppData").join("Local"));
+
+    match kind {
+        DirKind::Config => (roaming(), Some("config")),
+        DirKind::Saves => (roaming(), Some("saves")),
+        DirKind::Cache => (local(), Some("cache")),
+        DirKind::Logs => (local(), Some("logs")),
+    }
+}
+
+/// Platform directory holding the directories of every project
+#[cfg(target_os = "macos")]
+fn platform_base(kind: DirKind) -> (PathBuf, Option<&'static str>) {
+    let library = home().join("Library");
+
+    match kind {
+        DirKind::Config => (library.join("Application Support"), None),
+        DirKind::Saves => (library.join("Application Support"), Some("saves")),
+        DirKind::Cache => (library.join("Caches"), None),
+        DirKind::Logs => (library.join("Logs"), None),
+    }
+}
+
+/// Platform directory holding the directories of every project
+#[cfg(any(target_os = "android", target_os = "ios"))]
+fn platform_base(kind: DirKind) -> (PathBuf, Option<&'static str>) {
+    // The sandbox of the app only holds this game
+    let storage = sdl2::filesystem::pref_path("", "magma")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("."));
+
+    match kind {
+        DirKind::Config => (storage, Some("config")),
+        DirKind::Saves => (storage, Some("saves")),
+        DirKind::Cache => (storage, Some("cache")),
+        DirKind::Logs => (storage, Some("logs")),
+    }
+}
+
+/// Platform directory holding the directories of every project
+#[cfg(not(any(
+    target_os = "windows",
+    target_os = "macos",
+    target_os = "android",
+    target_os = "ios"
+)))]
+fn platform_base(kind: DirKind) -> (PathBuf, Option<&'static str>) {
+    let xdg = |var: &str, default: &[&str]| {
+        env_path(var)
+            .filter(|path| path.is_absolute())
+            .unwrap_or_else(|| default.iter().fold(home(), |path, part| path.join(part)))
+    };
+
+    match kind {
+        DirKind::Config => (xdg("XDG_CONFIG_HOME", &[".config"]), None),
+        DirKind::Saves => (xdg("XDG_DATA_HOME", &[".local", "share"]), Some("saves")),
+        DirKind::Cache => (xdg("XDG_CACHE_HOME", &[".cache"]), None),
+        DirKind::Logs => (xdg("XDG_STATE_HOME", &[".local", "state"]), Some("logs")),
+    }
+}
+
+/// Write the whole file or nothing: the data goes to a temporary file next to it, synced to disk,
+/// then renamed over the old file
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+    }
+
+    fs::rename(&temp_path, path)
+}
+
+/// Turn the project name into a single, portable path component
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    match name.trim_matches('.') {
+        "" => String::from("magma"),
+        _ => name,
+    }
+}
+
+impl Paths {
+    pub fn new(project_name: &str) -> Self {
+        Self {
+            project_name: sanitize(project_name),
+            root: None,
+        }
+    }
+
+    /// Keep every directory inside `root` instead of the platform ones
+    pub fn with_root(project_name: &str, root: PathBuf) -> Self {
+        Self {
+            project_name: sanitize(project_name),
+            root: Some(root),
+        }
+    }
+
+    pub fn project_name(&self) -> &str {
+        &self.project_name
+    }
+
+    /// Path of the directory, without creating it
+    pub fn resolve(&self, kind: DirKind) -> PathBuf {
+        if let Some(root) = &self.root {
+            let name = match kind {
+                DirKind::Config => "config",
+                DirKind::Saves => "saves",
+                DirKind::Cache => "cache",
+                DirKind::Logs => "logs",
+            };
+            return root.join(&self.project_name).join(name);
+        }
+
+        let (base, subdir) = platform_base(kind);
+        let path = base.join(&self.project_name);
+
+        match subdir {
+            Some(subdir) => path.join(subdir),
+            None => path,
+        }
+    }
+
+    /// Path of the directory, created if missing
+    pub fn dir(&self, kind: DirKind) -> Result<PathBuf, String> {
+        let path = self.resolve(kind);
+        fs::create_dir_all(&path)
+            .map_err(|e| format!("Couldn't create directory '{}': {}", path.display(), e))?;
+
+        Ok(path)
+    }
+
+    pub fn config(&self) -> Result<PathBuf, String> {
+        self.dir(DirKind::Config)
+    }
+
+    pub fn saves(&self) -> Result<PathBuf, String> {
+        self.dir(DirKind::Saves)
+    }
+
+    pub fn cache(&self) -> Result<PathBuf, String> {
+        self.dir(DirKind::Cache)
+    }
+
+    pub fn logs(&self) -> Result<PathBuf, String> {
+        self.dir(DirKind::Logs)
+    }
+
+    /// Path of a file in the directory, created with its parents if missing.
+    /// Absolute paths are returned as they are
+    pub fn file(&self, kind: DirKind, name: &Path) -> Result<PathBuf, String> {
+        if name.is_absolute() {
+            return Ok(name.to_path_buf());
+        }
+
+        let path = self.dir(kind)?.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Couldn't create directory '{}': {}", parent.display(), e))?;
+        }
+
+        Ok(path)
+    }
+}
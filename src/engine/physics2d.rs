@@ -0,0 +1,907 @@
+//! 2D rigid body physics with rapier2d (https://rapier.rs), behind the `physics2d` feature
+//!
+//! Entities get a `RigidBody2D` and/or a `Collider2D` next to their `Transform`, and a
+//! `Physics2D` resource in the world enables the simulation. The engine steps it at every
+//! update (the fixed step with `LoopMode::Fixed`), before the systems run: dynamic bodies
+//! write their placement to the transforms, kinematic and static ones follow them.
+//! Bodies are placed with their local `Transform`, keep them out of hierarchies.
+//! Contacts starting and ending are reported as `EngineEvent::Physics` events.
+
+// standard imports
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::sync::Mutex;
+
+// import the engine modules
+use super::collision::{CollisionQuery, QueryFilter, RayHit, Shape, ShapeHit};
+use super::color::Color;
+use super::ctx::VideoHandler;
+use super::debug_draw::{DebugLines, DebugLinesObject};
+use super::ecs::{Entity, World};
+use super::events::{EngineEvent, EventBus};
+use super::math::{Rad, Vec2};
+use super::transform::Transform;
+
+// other imports
+use cgmath::InnerSpace;
+use rapier2d::prelude::{
+    ActiveEvents, Ball, BroadPhase, CCDSolver, Collider, ColliderBuilder, ColliderHandle,
+    ColliderSet, ContactEvent, ContactPair, Cuboid, EventHandler, IntegrationParameters,
+    InteractionGroups, IntersectionEvent, IslandManager, Isometry, JointSet, NarrowPhase,
+    PhysicsPipeline, Point, QueryPipeline, Ray, Real, RigidBody, RigidBodyBuilder, RigidBodyHandle,
+    RigidBodySet, RigidBodyType, SharedShape, Vector,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BodyType {
+    /// Moved by the simulation
+    Dynamic,
+    /// Never moves (ground, walls)
+    Static,
+    /// Moved by its `Transform`, pushing the dynamic bodies
+    Kinematic,
+}
+
+/// Rigid body of an entity, read when the simulation creates it
+/// (use `Physics2D::body_mut` to change it later)
+#[derive(Clone, Debug, PartialEq)]
+pub struct RigidBody2D {
+    pub body_type: BodyType,
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+    pub gravity_scale: f32,
+    pub lock_rotation: bool,
+    /// Continuous collision detection, so fast bodies don't go through thin ones
+    pub ccd: bool,
+    handle: Option<RigidBodyHandle>,
+}
+
+impl RigidBody2D {
+    pub fn new(body_type: BodyType) -> Self {
+        Self {
+            body_type,
+            linear_damping: 0.0,
+            angular_damping: 0.0,
+            gravity_scale: 1.0,
+            lock_rotation: false,
+            ccd: false,
+            handle: None,
+        }
+    }
+
+    pub fn dynamic() -> Self {
+        Self::new(BodyType::Dynamic)
+    }
+
+    pub fn fixed() -> Self {
+        Self::new(BodyType::Static)
+    }
+
+    pub fn kinematic() -> Self {
+        Self::new(BodyType::Kinematic)
+    }
+
+    pub fn with_damping(mut self, linear: f32, angular: f32) -> Self {
+        self.linear_damping = linear;
+        self.angular_damping = angular;
+        self
+    }
+
+    pub fn with_gravity_scale(mut self, gravity_scale: f32) -> Self {
+        self.gravity_scale = gravity_scale;
+        self
+    }
+
+    pub fn with_locked_rotation(mut self) -> Self {
+        self.lock_rotation = true;
+        self
+    }
+
+    pub fn with_ccd(mut self) -> Self {
+        self.ccd = true;
+        self
+    }
+
+    /// Body in the simulation, None until the next step
+    pub fn handle(&self) -> Option<RigidBodyHandle> {
+        self.handle
+    }
+}
+
+/// Shape of a collider, in world units
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColliderShape {
+    Box {
+        half_extents: Vec2,
+    },
+    Circle {
+        radius: f32,
+    },
+    /// Vertical capsule
+    Capsule {
+        half_height: f32,
+        radius: f32,
+    },
+    /// Convex hull of the points
+    ConvexPolygon(Vec<Vec2>),
+}
+
+/// Collider of an entity, attached to its `RigidBody2D` or static without one.
+/// Read when the simulation creates it (use `Physics2D::colliders_mut` to change it later)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Collider2D {
+    pub shape: ColliderShape,
+    /// Position relative to the entity
+    pub offset: Vec2,
+    pub friction: f32,
+    pub restitution: f32,
+    pub density: f32,
+    /// Detects overlaps without colliding (triggers)
+    pub sensor: bool,
+    /// Bits of the layers of the collider, matched with the masks of the queries
+    pub layers: u32,
+    handle: Option<ColliderHandle>,
+}
+
+impl Collider2D {
+    pub fn new(shape: ColliderShape) -> Self {
+        Self {
+            shape,
+            offset: Vec2::new(0.0, 0.0),
+            friction: 0.5,
+            restitution: 0.0,
+            density: 1.0,
+            sensor: false,
+            layers: 1,
+            handle: None,
+        }
+    }
+
+    pub fn rectangle(half_extents: Vec2) -> Self {
+        Self::new(ColliderShape::Box { half_extents })
+    }
+
+    pub fn circle(radius: f32) -> Self {
+        Self::new(ColliderShape::Circle { radius })
+    }
+
+    pub fn capsule(half_height: f32, radius: f32) -> Self {
+        Self::new(ColliderShape::Capsule {
+            half_height,
+            radius,
+        })
+    }
+
+    pub fn with_offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_friction(mut self, friction: f32) -> Self {
+        self.friction = friction;
+        self
+    }
+
+    pub fn with_restitution(mut self, restitution: f32) -> Self {
+        self.restitution = restitution;
+        self
+    }
+
+    pub fn with_density(mut self, density: f32) -> Self {
+        self.density = density;
+        self
+    }
+
+    pub fn as_sensor(mut self) -> Self {
+        self.sensor = true;
+        self
+    }
+
+    pub fn with_layers(mut self, layers: u32) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    /// Collider in the simulation, None until the next step
+    pub fn handle(&self) -> Option<ColliderHandle> {
+        self.handle
+    }
+}
+
+/// Contacts of the colliders, reported once when they change
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhysicsEvent {
+    /// Two solid colliders started touching
+    CollisionStarted(Entity, Entity),
+    CollisionEnded(Entity, Entity),
+    /// A collider started overlapping a sensor
+    TriggerEntered {
+        trigger: Entity,
+        other: Entity,
+    },
+    TriggerExited {
+        trigger: Entity,
+        other: Entity,
+    },
+}
+
+impl PhysicsEvent {
+    /// Whether an entity is one of the two involved
+    pub fn involves(&self, entity: Entity) -> bool {
+        match *self {
+            PhysicsEvent::CollisionStarted(a, b) | PhysicsEvent::CollisionEnded(a, b) => {
+                a == entity || b == entity
+            }
+            PhysicsEvent::TriggerEntered { trigger, other }
+            | PhysicsEvent::TriggerExited { trigger, other } => {
+                trigger == entity || other == entity
+            }
+        }
+    }
+}
+
+/// Events of a step as rapier reports them, the handler is shared with its threads
+#[derive(Default)]
+struct EventCollector {
+    contacts: Mutex<Vec<ContactEvent>>,
+    intersections: Mutex<Vec<IntersectionEvent>>,
+}
+
+impl EventHandler for EventCollector {
+    fn handle_intersection_event(&self, event: IntersectionEvent) {
+        self.intersections.lock().unwrap().push(event);
+    }
+
+    fn handle_contact_event(&self, event: ContactEvent, _contact_pair: &ContactPair) {
+        self.contacts.lock().unwrap().push(event);
+    }
+}
+
+/// Simulation of the bodies and colliders of a world, added as a world resource
+pub struct Physics2D {
+    /// In world units per second squared, Y going down
+    pub gravity: Vec2,
+    /// World units in a meter of the simulation, which is tuned for objects of about a meter
+    pub units_per_meter: f32,
+    pub paused: bool,
+
+    pipeline: PhysicsPipeline,
+    parameters: IntegrationParameters,
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    joints: JointSet,
+    ccd_solver: CCDSolver,
+    query_pipeline: QueryPipeline,
+
+    body_entities: HashMap<RigidBodyHandle, Entity>,
+    collider_entities: HashMap<ColliderHandle, Entity>,
+    entity_bodies: HashMap<Entity, RigidBodyHandle>,
+
+    debug_draw: Option<DebugLinesObject>,
+}
+
+impl Physics2D {
+    pub fn new() -> Self {
+        Self {
+            gravity: Vec2::new(0.0, 980.0),
+            units_per_meter: 100.0,
+            paused: false,
+            pipeline: PhysicsPipeline::new(),
+            parameters: IntegrationParameters::default(),
+            islands: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            joints: JointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            query_pipeline: QueryPipeline::new(),
+            body_entities: HashMap::new(),
+            collider_entities: HashMap::new(),
+            entity_bodies: HashMap::new(),
+            debug_draw: None,
+        }
+    }
+
+    /// Body of an entity
+    pub fn body(&self, entity: Entity) -> Option<&RigidBody> {
+        self.bodies.get(*self.entity_bodies.get(&entity)?)
+    }
+
+    pub fn body_mut(&mut self, entity: Entity) -> Option<&mut RigidBody> {
+        self.bodies.get_mut(*self.entity_bodies.get(&entity)?)
+    }
+
+    pub fn colliders(&self) -> &ColliderSet {
+        &self.colliders
+    }
+
+    pub fn colliders_mut(&mut self) -> &mut ColliderSet {
+        &mut self.colliders
+    }
+
+    /// Entity owning a body
+    pub fn body_entity(&self, handle: RigidBodyHandle) -> Option<Entity> {
+        self.body_entities.get(&handle).copied()
+    }
+
+    /// Entity owning a collider
+    pub fn collider_entity(&self, handle: ColliderHandle) -> Option<Entity> {
+        self.collider_entities.get(&handle).copied()
+    }
+
+    /// In world units per second
+    pub fn linear_velocity(&self, entity: Entity) -> Option<Vec2> {
+        let velocity = self.body(entity)?.linvel();
+        Some(self.to_world(Vec2::new(velocity.x, velocity.y)))
+    }
+
+    pub fn set_linear_velocity(&mut self, entity: Entity, velocity: Vec2) {
+        let velocity = self.to_physics(velocity);
+        if let Some(body) = self.body_mut(entity) {
+            body.set_linvel(velocity, true);
+        }
+    }
+
+    /// In radians per second
+    pub fn angular_velocity(&self, entity: Entity) -> Option<f32> {
+        Some(self.body(entity)?.angvel())
+    }
+
+    pub fn set_angular_velocity(&mut self, entity: Entity, velocity: f32) {
+        if let Some(body) = self.body_mut(entity) {
+            body.set_angvel(velocity, true);
+        }
+    }
+
+    /// Change the velocity at once (jumps, explosions), in world units
+    pub fn apply_impulse(&mut self, entity: Entity, impulse: Vec2) {
+        let impulse = self.to_physics(impulse);
+        if let Some(body) = self.body_mut(entity) {
+            body.apply_impulse(impulse, true);
+        }
+    }
+
+    /// Push during the next step (thrusters, wind), in world units
+    pub fn apply_force(&mut self, entity: Entity, force: Vec2) {
+        let force = self.to_physics(force);
+        if let Some(body) = self.body_mut(entity) {
+            body.apply_force(force, true);
+        }
+    }
+
+    /// Draw the collider outlines at every step, over the objects with lower z indices
+    pub fn enable_debug_draw(&mut self, video: &mut VideoHandler, z_index: u8) {
+        self.debug_draw = Some(DebugLines::create(video, z_index));
+    }
+
+    pub fn disable_debug_draw(&mut self) {
+        self.debug_draw = None;
+    }
+
+    /// First collider hit by a ray going from `origin` in `direction` for at most
+    /// `max_distance`, as placed by the last step (the tiles of the filter are ignored)
+    pub fn raycast(
+        &self,
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+        filter: &QueryFilter,
+    ) -> Option<RayHit> {
+        let length = direction.magnitude();
+        if length <= 0.0 {
+            return None;
+        }
+
+        let direction = direction / length;
+        let ray = Ray::new(
+            Point::from(self.to_physics(origin)),
+            Vector::new(direction.x, direction.y),
+        );
+        let predicate = |handle: ColliderHandle| self.query_accepts(handle, filter);
+
+        let (handle, intersection) = self.query_pipeline.cast_ray_and_get_normal(
+            &self.colliders,
+            &ray,
+            max_distance / self.units_per_meter,
+            true,
+            InteractionGroups::new(u32::MAX, filter.mask),
+            Some(&predicate),
+        )?;
+
+        let point = ray.point_at(intersection.toi);
+        Some(RayHit {
+            entity: self.collider_entity(handle),
+            point: self.to_world(Vec2::new(point.x, point.y)),
+            normal: Vec2::new(intersection.normal.x, intersection.normal.y),
+            distance: intersection.toi * self.units_per_meter,
+        })
+    }
+
+    /// First collider hit by a shape moved by `motion`, as placed by the last step
+    pub fn shape_cast(
+        &self,
+        shape: &Shape,
+        motion: Vec2,
+        filter: &QueryFilter,
+    ) -> Option<ShapeHit> {
+        let center = shape.center();
+        let position = Isometry::new(self.to_physics(center), 0.0);
+        let velocity = self.to_physics(motion);
+        let groups = InteractionGroups::new(u32::MAX, filter.mask);
+        let predicate = |handle: ColliderHandle| self.query_accepts(handle, filter);
+
+        // Time of impact over one unit of time, so the fraction of the motion
+        let (handle, toi) = match shape {
+            Shape::Aabb(aabb) => self.query_pipeline.cast_shape(
+                &self.colliders,
+                &position,
+                &velocity,
+                &Cuboid::new(self.to_physics(aabb.half_extents())),
+                1.0,
+                groups,
+                Some(&predicate),
+            ),
+            Shape::Circle(circle) => self.query_pipeline.cast_shape(
+                &self.colliders,
+                &position,
+                &velocity,
+                &Ball::new(circle.radius / self.units_per_meter),
+                1.0,
+                groups,
+                Some(&predicate),
+            ),
+        }?;
+
+        // Witness and normal of the collider hit are in its local space
+        let collider_position = self.colliders.get(handle)?.position();
+        let point = collider_position * toi.witness1;
+        let normal = collider_position.rotation * *toi.normal1;
+
+        Some(ShapeHit {
+            entity: self.collider_entity(handle),
+            time: toi.toi,
+            point: self.to_world(Vec2::new(point.x, point.y)),
+            normal: Vec2::new(normal.x, normal.y),
+            center: center + motion * toi.toi,
+        })
+    }
+
+    fn query_accepts(&self, handle: ColliderHandle, filter: &QueryFilter) -> bool {
+        self.collider_entity(handle)
+            .map_or(true, |entity| !filter.exclude.contains(&entity))
+    }
+
+    /// Create and remove the bodies and colliders of the world, move the simulation forward
+    /// by `delta` seconds, then update the transforms and report the contacts
+    fn step(&mut self, world: &World, delta: f32, events: &mut EventBus) {
+        self.sync_bodies(world);
+        self.sync_colliders(world);
+        self.push_transforms(world);
+
+        if !self.paused && delta > 0.0 {
+            self.parameters.dt = delta;
+            let gravity = self.to_physics(self.gravity);
+            let collector = EventCollector::default();
+
+            self.pipeline.step(
+                &gravity,
+                &self.parameters,
+                &mut self.islands,
+                &mut self.broad_phase,
+                &mut self.narrow_phase,
+                &mut self.bodies,
+                &mut self.colliders,
+                &mut self.joints,
+                &mut self.ccd_solver,
+                &(),
+                &collector,
+            );
+
+            for event in self.physics_events(collector) {
+                events.push(EngineEvent::Physics(event));
+            }
+        }
+
+        self.pull_transforms(world);
+        self.query_pipeline
+            .update(&self.islands, &self.bodies, &self.colliders);
+        self.draw_debug();
+    }
+
+    /// Events of the entities still known, the contacts of removed colliders are lost
+    fn physics_events(&self, collector: EventCollector) -> Vec<PhysicsEvent> {
+        let entities = |a, b| Some((self.collider_entity(a)?, self.collider_entity(b)?));
+
+        let contacts = collector.contacts.into_inner().unwrap();
+        let collisions = contacts.into_iter().filter_map(|event| match event {
+            ContactEvent::Started(a, b) => {
+                let (a, b) = entities(a, b)?;
+                Some(PhysicsEvent::CollisionStarted(a, b))
+            }
+            ContactEvent::Stopped(a, b) => {
+                let (a, b) = entities(a, b)?;
+                Some(PhysicsEvent::CollisionEnded(a, b))
+            }
+        });
+
+        let intersections = collector.intersections.into_inner().unwrap();
+        let triggers = intersections.into_iter().filter_map(|event| {
+            // The first sensor is the trigger
+            let first_sensor = self
+                .colliders
+                .get(event.collider1)
+                .map_or(false, Collider::is_sensor);
+            let (trigger, other) = if first_sensor {
+                entities(event.collider1, event.collider2)?
+            } else {
+                entities(event.collider2, event.collider1)?
+            };
+
+            Some(if event.intersecting {
+                PhysicsEvent::TriggerEntered { trigger, other }
+            } else {
+                PhysicsEvent::TriggerExited { trigger, other }
+            })
+        });
+
+        collisions.chain(triggers).collect()
+    }
+
+    fn sync_bodies(&mut self, world: &World) {
+        let mut rigid_bodies = match world.borrow_mut::<RigidBody2D>() {
+            Some(rigid_bodies) => rigid_bodies,
+            None => {
+                self.remove_bodies(|_| true);
+                return;
+            }
+        };
+        let transforms = world.borrow::<Transform>();
+
+        // Components removed or entities despawned
+        let alive: Vec<(Entity, RigidBodyHandle)> = rigid_bodies
+            .iter()
+            .filter_map(|(entity, body)| Some((entity, body.handle?)))
+            .collect();
+        self.remove_bodies(|(entity, handle)| !alive.contains(&(entity, handle)));
+
+        for (entity, component) in rigid_bodies.iter_mut() {
+            if component.handle.is_some() {
+                continue;
+            }
+
+            let transform = transforms
+                .as_ref()
+                .and_then(|transforms| transforms.get(entity).copied())
+                .unwrap_or_default();
+
+            let body_type = match component.body_type {
+                BodyType::Dynamic => RigidBodyType::Dynamic,
+                BodyType::Static => RigidBodyType::Static,
+                BodyType::Kinematic => RigidBodyType::KinematicPositionBased,
+            };
+            let mut builder = RigidBodyBuilder::new(body_type)
+                .position(self.to_isometry(transform.translation, transform.rotation))
+                .linear_damping(component.linear_damping)
+                .angular_damping(component.angular_damping)
+                .gravity_scale(component.gravity_scale)
+                .ccd_enabled(component.ccd);
+            if component.lock_rotation {
+                builder = builder.lock_rotations();
+            }
+
+            let handle = self.bodies.insert(builder.build());
+            component.handle = Some(handle);
+            self.body_entities.insert(handle, entity);
+            self.entity_bodies.insert(entity, handle);
+        }
+    }
+
+    fn sync_colliders(&mut self, world: &World) {
+        let mut colliders = match world.borrow_mut::<Collider2D>() {
+            Some(colliders) => colliders,
+            None => {
+                self.remove_colliders(|_| true);
+                return;
+            }
+        };
+        let transforms = world.borrow::<Transform>();
+
+        let alive: Vec<(Entity, ColliderHandle)> = colliders
+            .iter()
+            .filter_map(|(entity, collider)| Some((entity, collider.handle?)))
+            .collect();
+        self.remove_colliders(|(entity, handle)| !alive.contains(&(entity, handle)));
+
+        for (entity, component) in colliders.iter_mut() {
+            if component.handle.is_some() {
+                continue;
+            }
+
+            let offset = self.to_physics(component.offset);
+            let collider = self
+                .collider_builder(&component.shape)
+                .translation(offset)
+                .friction(component.friction)
+                .restitution(component.restitution)
+                .density(component.density)
+                .sensor(component.sensor)
+                .collision_groups(InteractionGroups::new(component.layers, u32::MAX))
+                .active_events(ActiveEvents::CONTACT_EVENTS | ActiveEvents::INTERSECTION_EVENTS)
+                .build();
+
+            let handle = match self.entity_bodies.get(&entity) {
+                Some(&body) => self
+                    .colliders
+                    .insert_with_parent(collider, body, &mut self.bodies),
+                None => {
+                    // Static collider placed once, like static bodies
+                    let transform = transforms
+                        .as_ref()
+                        .and_then(|transforms| transforms.get(entity).copied())
+                        .unwrap_or_default();
+                    let mut collider = collider;
+                    collider.set_position(
+                        self.to_isometry(transform.translation, transform.rotation)
+                            * Isometry::translation(offset.x, offset.y),
+                    );
+                    self.colliders.insert(collider)
+                }
+            };
+
+            component.handle = Some(handle);
+            self.collider_entities.insert(handle, entity);
+        }
+    }
+
+    fn collider_builder(&self, shape: &ColliderShape) -> ColliderBuilder {
+        let scale = 1.0 / self.units_per_meter;
+
+        match shape {
+            ColliderShape::Box { half_extents } => {
+                ColliderBuilder::cuboid(half_extents.x * scale, half_extents.y * scale)
+            }
+            ColliderShape::Circle { radius } => ColliderBuilder::ball(radius * scale),
+            ColliderShape::Capsule {
+                half_height,
+                radius,
+            } => ColliderBuilder::capsule_y(half_height * scale, radius * scale),
+            ColliderShape::ConvexPolygon(points) => {
+                let points: Vec<Point<Real>> = points
+                    .iter()
+                    .map(|point| Point::new(point.x * scale, point.y * scale))
+                    .collect();
+
+                // Degenerate hulls fall back to a point-sized ball
+                match SharedShape::convex_hull(&points) {
+                    Some(shape) => ColliderBuilder::new(shape),
+                    None => ColliderBuilder::ball(0.0),
+                }
+            }
+        }
+    }
+
+    fn remove_bodies(&mut self, mut remove: impl FnMut((Entity, RigidBodyHandle)) -> bool) {
+        let removed: Vec<(RigidBodyHandle, Entity)> = self
+            .body_entities
+            .iter()
+            .map(|(&handle, &entity)| (handle, entity))
+            .filter(|&(handle, entity)| remove((entity, handle)))
+            .collect();
+
+        for (handle, entity) in removed {
+            // The colliders attached go with the body
+            if let Some(body) = self.bodies.get(handle) {
+                for collider in body.colliders() {
+                    self.collider_entities.remove(collider);
+                }
+            }
+
+            self.bodies.remove(
+                handle,
+                &mut self.islands,
+                &mut self.colliders,
+                &mut self.joints,
+            );
+            self.body_entities.remove(&handle);
+            self.entity_bodies.remove(&entity);
+        }
+    }
+
+    fn remove_colliders(&mut self, mut remove: impl FnMut((Entity, ColliderHandle)) -> bool) {
+        let removed: Vec<ColliderHandle> = self
+            .collider_entities
+            .iter()
+            .filter(|&(&handle, &entity)| remove((entity, handle)))
+            .map(|(&handle, _)| handle)
+            .collect();
+
+        for handle in removed {
+            self.colliders
+                .remove(handle, &mut self.islands, &mut self.bodies, true);
+            self.collider_entities.remove(&handle);
+        }
+    }
+
+    /// Kinematic and static bodies follow their transforms
+    fn push_transforms(&mut self, world: &World) {
+        let transforms = match world.borrow::<Transform>() {
+            Some(transforms) => transforms,
+            None => return,
+        };
+
+        for (&handle, &entity) in &self.body_entities {
+            let transform = match transforms.get(entity) {
+                Some(transform) => transform,
+                None => continue,
+            };
+            let position = self.to_isometry(transform.translation, transform.rotation);
+            let body = match self.bodies.get_mut(handle) {
+                Some(body) => body,
+                None => continue,
+            };
+
+            match body.body_type() {
+                RigidBodyType::KinematicPositionBased => body.set_next_kinematic_position(position),
+                RigidBodyType::Static if *body.position() != position => {
+                    body.set_position(position, true)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Dynamic bodies move their transforms
+    fn pull_transforms(&self, world: &World) {
+        let mut transforms = match world.borrow_mut::<Transform>() {
+            Some(transforms) => transforms,
+            None => return,
+        };
+
+        for (&handle, &entity) in &self.body_entities {
+            let body = match self.bodies.get(handle) {
+                Some(body) if body.is_dynamic() => body,
+                _ => continue,
+            };
+
+            if let Some(transform) = transforms.get_mut(entity) {
+                let position = body.position();
+                let translation = position.translation.vector;
+                transform.translation = self.to_world(Vec2::new(translation.x, translation.y));
+                transform.rotation = Rad(position.rotation.angle());
+            }
+        }
+    }
+
+    fn draw_debug(&self) {
+        let debug_draw = match &self.debug_draw {
+            Some(debug_draw) => debug_draw,
+            None => return,
+        };
+        let mut lines = debug_draw.get_mut();
+        lines.clear();
+
+        for (_, collider) in self.colliders.iter() {
+            let color = if collider.is_sensor() {
+                Color::YELLOW
+            } else {
+                match collider.parent().and_then(|body| self.bodies.get(body)) {
+                    Some(body) if body.is_dynamic() => {
+                        if body.is_sleeping() {
+                            Color::BLUE
+                        } else {
+                            Color::GREEN
+                        }
+                    }
+                    _ => Color::GRAY,
+                }
+            };
+
+            let outline = self.collider_outline(collider);
+            lines.polyline(&outline, true, color);
+        }
+    }
+
+    /// Points around a collider shape, in world units
+    fn collider_outline(&self, collider: &Collider) -> Vec<Vec2> {
+        let shape = collider.shape();
+        let local: Vec<Point<Real>> = if let Some(cuboid) = shape.as_cuboid() {
+            let half = cuboid.half_extents;
+            vec![
+                Point::new(-half.x, -half.y),
+                Point::new(half.x, -half.y),
+                Point::new(half.x, half.y),
+                Point::new(-half.x, half.y),
+            ]
+        } else if let Some(ball) = shape.as_ball() {
+            circle_points(Point::origin(), ball.radius, 0.0, 2.0 * PI)
+        } else if let Some(capsule) = shape.as_capsule() {
+            // Bottom half circle, then the top one
+            let (a, b) = (capsule.segment.a, capsule.segment.b);
+            let mut points = circle_points(a, capsule.radius, PI, 2.0 * PI);
+            points.extend(circle_points(b, capsule.radius, 0.0, PI));
+            points
+        } else if let Some(polygon) = shape.as_convex_polygon() {
+            polygon.points().to_vec()
+        } else {
+            let aabb = shape.compute_local_aabb();
+            vec![
+                aabb.mins,
+                Point::new(aabb.maxs.x, aabb.mins.y),
+                aabb.maxs,
+                Point::new(aabb.mins.x, aabb.maxs.y),
+            ]
+        };
+
+        let position = collider.position();
+        local
+            .into_iter()
+            .map(|point| {
+                let point = position * point;
+                self.to_world(Vec2::new(point.x, point.y))
+            })
+            .collect()
+    }
+
+    fn to_physics(&self, vector: Vec2) -> Vector<Real> {
+        Vector::new(
+            vector.x / self.units_per_meter,
+            vector.y / self.units_per_meter,
+        )
+    }
+
+    fn to_world(&self, vector: Vec2) -> Vec2 {
+        vector * self.units_per_meter
+    }
+
+    fn to_isometry(&self, translation: Vec2, rotation: Rad<f32>) -> Isometry<Real> {
+        Isometry::new(self.to_physics(translation), rotation.0)
+    }
+}
+
+impl CollisionQuery for Physics2D {
+    fn raycast(
+        &self,
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+        filter: &QueryFilter,
+    ) -> Option<RayHit> {
+        Physics2D::raycast(self, origin, direction, max_distance, filter)
+    }
+
+    fn shape_cast(&self, shape: &Shape, motion: Vec2, filter: &QueryFilter) -> Option<ShapeHit> {
+        Physics2D::shape_cast(self, shape, motion, filter)
+    }
+}
+
+impl Default for Physics2D {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Points of an arc, from angle `start` to `end`
+fn circle_points(center: Point<Real>, radius: f32, start: f32, end: f32) -> Vec<Point<Real>> {
+    const SEGMENTS: usize = 12;
+
+    (0..=SEGMENTS)
+        .map(|i| {
+            let angle = start + (end - start) * i as f32 / SEGMENTS as f32;
+            Point::new(
+                center.x + radius * angle.cos(),
+                center.y + radius * angle.sin(),
+            )
+        })
+        .collect()
+}
+
+/// Step the `Physics2D` resource of the world, if any (called by the engine at every update)
+pub(crate) fn step_world(world: &World, delta: f32, events: &mut EventBus) {
+    if let Some(mut physics) = world.resource_mut::<Physics2D>() {
+        physics.step(world, delta, events);
+    }
+}
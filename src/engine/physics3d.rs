@@ -0,0 +1,692 @@
+//! 3D rigid body physics with rapier3d (https://rapier.rs), behind the `physics3d` feature
+//!
+//! Mirrors the 2D integration: entities get a `RigidBody3D` and/or a `Collider3D` next to
+//! their `Transform3D`, and a `Physics3D` resource in the world enables the simulation,
+//! stepped by the engine at every update before the systems run.
+//! The engine renders in 2D only, so `Transform3D` is a plain component without hierarchy
+//! for the games drawing their 3D scenes themselves. Units are meters, with the Y axis up.
+
+// standard imports
+use std::collections::{HashMap, HashSet};
+
+// import the engine modules
+use super::crash;
+use super::ecs::{Entity, World};
+use super::math::{Quat, Vec3};
+
+// other imports
+use cgmath::{InnerSpace, One};
+use rapier3d::na::{Quaternion, UnitQuaternion};
+use rapier3d::prelude::{
+    BroadPhase, CCDSolver, ColliderBuilder, ColliderHandle, ColliderSet, IntegrationParameters,
+    InteractionGroups, IslandManager, Isometry, JointSet, NarrowPhase, PhysicsPipeline, Point,
+    QueryPipeline, Ray, Real, RigidBody, RigidBodyBuilder, RigidBodyHandle, RigidBodySet,
+    RigidBodyType, Translation, Vector,
+};
+
+/// Placement of an entity in the 3D world
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform3D {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Transform3D {
+    pub fn new(translation: Vec3) -> Self {
+        Self {
+            translation,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for Transform3D {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::new(0.0, 0.0, 0.0),
+            rotation: Quat::one(),
+            scale: Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BodyType {
+    /// Moved by the simulation
+    Dynamic,
+    /// Never moves (ground, walls)
+    Static,
+    /// Moved by its `Transform3D`, pushing the dynamic bodies
+    Kinematic,
+}
+
+/// Rigid body of an entity, read when the simulation creates it
+/// (use `Physics3D::body_mut` to change it later)
+#[derive(Clone, Debug, PartialEq)]
+pub struct RigidBody3D {
+    pub body_type: BodyType,
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+    pub gravity_scale: f32,
+    pub lock_rotation: bool,
+    /// Continuous collision detection, so fast bodies don't go through thin ones
+    pub ccd: bool,
+    handle: Option<RigidBodyHandle>,
+}
+
+impl RigidBody3D {
+    pub fn new(body_type: BodyType) -> Self {
+        Self {
+            body_type,
+            linear_damping: 0.0,
+            angular_damping: 0.0,
+            gravity_scale: 1.0,
+            lock_rotation: false,
+            ccd: false,
+            handle: None,
+        }
+    }
+
+    pub fn dynamic() -> Self {
+        Self::new(BodyType::Dynamic)
+    }
+
+    pub fn fixed() -> Self {
+        Self::new(BodyType::Static)
+    }
+
+    pub fn kinematic() -> Self {
+        Self::new(BodyType::Kinematic)
+    }
+
+    pub fn with_damping(mut self, linear: f32, angular: f32) -> Self {
+        self.linear_damping = linear;
+        self.angular_damping = angular;
+        self
+    }
+
+    pub fn with_gravity_scale(mut self, gravity_scale: f32) -> Self {
+        self.gravity_scale = gravity_scale;
+        self
+    }
+
+    pub fn with_locked_rotation(mut self) -> Self {
+        self.lock_rotation = true;
+        self
+    }
+
+    pub fn with_ccd(mut self) -> Self {
+        self.ccd = true;
+        self
+    }
+
+    /// Body in the simulation, None until the next step
+    pub fn handle(&self) -> Option<RigidBodyHandle> {
+        self.handle
+    }
+}
+
+/// Shape of a collider, in meters
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColliderShape3D {
+    Box {
+        half_extents: Vec3,
+    },
+    Sphere {
+        radius: f32,
+    },
+    /// Along the Y axis
+    Capsule {
+        half_height: f32,
+        radius: f32,
+    },
+    /// Along the Y axis
+    Cylinder {
+        half_height: f32,
+        radius: f32,
+    },
+    /// Convex hull of the points
+    ConvexHull(Vec<Vec3>),
+    /// Triangles of a mesh, best for static level geometry
+    TriMesh {
+        vertices: Vec<Vec3>,
+        indices: Vec<[u32; 3]>,
+    },
+}
+
+/// Collider of an entity, attached to its `RigidBody3D` or static without one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Collider3D {
+    pub shape: ColliderShape3D,
+    /// Position relative to the entity
+    pub offset: Vec3,
+    pub friction: f32,
+    pub restitution: f32,
+    pub density: f32,
+    /// Detects overlaps without colliding (triggers)
+    pub sensor: bool,
+    /// Bits of the layers of the collider, matched with the masks of the queries
+    pub layers: u32,
+    handle: Option<ColliderHandle>,
+}
+
+impl Collider3D {
+    pub fn new(shape: ColliderShape3D) -> Self {
+        Self {
+            shape,
+            offset: Vec3::new(0.0, 0.0, 0.0),
+            friction: 0.5,
+            restitution: 0.0,
+            density: 1.0,
+            sensor: false,
+            layers: 1,
+            handle: None,
+        }
+    }
+
+    pub fn cuboid(half_extents: Vec3) -> Self {
+        Self::new(ColliderShape3D::Box { half_extents })
+    }
+
+    pub fn sphere(radius: f32) -> Self {
+        Self::new(ColliderShape3D::Sphere { radius })
+    }
+
+    pub fn capsule(half_height: f32, radius: f32) -> Self {
+        Self::new(ColliderShape3D::Capsule {
+            half_height,
+            radius,
+        })
+    }
+
+    /// Triangles of a mesh, e.g. from the vertex and index buffers of a model
+    pub fn trimesh(vertices: Vec<Vec3>, indices: Vec<[u32; 3]>) -> Self {
+        Self::new(ColliderShape3D::TriMesh { vertices, indices })
+    }
+
+    pub fn with_offset(mut self, offset: Vec3) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_friction(mut self, friction: f32) -> Self {
+        self.friction = friction;
+        self
+    }
+
+    pub fn with_restitution(mut self, restitution: f32) -> Self {
+        self.restitution = restitution;
+        self
+    }
+
+    pub fn with_density(mut self, density: f32) -> Self {
+        self.density = density;
+        self
+    }
+
+    pub fn as_sensor(mut self) -> Self {
+        self.sensor = true;
+        self
+    }
+
+    pub fn with_layers(mut self, layers: u32) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    /// Collider in the simulation, None until the next step
+    pub fn handle(&self) -> Option<ColliderHandle> {
+        self.handle
+    }
+}
+
+/// First hit of a ray in the 3D world
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit3D {
+    pub entity: Option<Entity>,
+    pub point: Vec3,
+    /// Normal of the surface hit, facing the origin
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+/// Simulation of the 3D bodies and colliders of a world, added as a world resource
+pub struct Physics3D {
+    /// In meters per second squared
+    pub gravity: Vec3,
+    pub paused: bool,
+
+    pipeline: PhysicsPipeline,
+    parameters: IntegrationParameters,
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    joints: JointSet,
+    ccd_solver: CCDSolver,
+    query_pipeline: QueryPipeline,
+
+    body_entities: HashMap<RigidBodyHandle, Entity>,
+    collider_entities: HashMap<ColliderHandle, Entity>,
+    entity_bodies: HashMap<Entity, RigidBodyHandle>,
+    /// Entities whose collider shape is invalid, reported once
+    invalid_colliders: HashSet<Entity>,
+}
+
+impl Physics3D {
+    pub fn new() -> Self {
+        Self {
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            paused: false,
+            pipeline: PhysicsPipeline::new(),
+            parameters: IntegrationParameters::default(),
+            islands: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            joints: JointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            query_pipeline: QueryPipeline::new(),
+            body_entities: HashMap::new(),
+            collider_entities: HashMap::new(),
+            entity_bodies: HashMap::new(),
+            invalid_colliders: HashSet::new(),
+        }
+    }
+
+    /// Body of an entity
+    pub fn body(&self, entity: Entity) -> Option<&RigidBody> {
+        self.bodies.get(*self.entity_bodies.get(&entity)?)
+    }
+
+    pub fn body_mut(&mut self, entity: Entity) -> Option<&mut RigidBody> {
+        self.bodies.get_mut(*self.entity_bodies.get(&entity)?)
+    }
+
+    /// Entity owning a collider
+    pub fn collider_entity(&self, handle: ColliderHandle) -> Option<Entity> {
+        self.collider_entities.get(&handle).copied()
+    }
+
+    pub fn linear_velocity(&self, entity: Entity) -> Option<Vec3> {
+        Some(from_vector(self.body(entity)?.linvel()))
+    }
+
+    pub fn set_linear_velocity(&mut self, entity: Entity, velocity: Vec3) {
+        if let Some(body) = self.body_mut(entity) {
+            body.set_linvel(to_vector(velocity), true);
+        }
+    }
+
+    pub fn apply_impulse(&mut self, entity: Entity, impulse: Vec3) {
+        if let Some(body) = self.body_mut(entity) {
+            body.apply_impulse(to_vector(impulse), true);
+        }
+    }
+
+    pub fn apply_force(&mut self, entity: Entity, force: Vec3) {
+        if let Some(body) = self.body_mut(entity) {
+            body.apply_force(to_vector(force), true);
+        }
+    }
+
+    /// First collider hit by a ray, of the layers in `mask`, as placed by the last step
+    pub fn raycast(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        mask: u32,
+    ) -> Option<RayHit3D> {
+        let length = direction.magnitude();
+        if length <= 0.0 {
+            return None;
+        }
+
+        let ray = Ray::new(
+            Point::from(to_vector(origin)),
+            to_vector(direction / length),
+        );
+        let (handle, intersection) = self.query_pipeline.cast_ray_and_get_normal(
+            &self.colliders,
+            &ray,
+            max_distance,
+            true,
+            InteractionGroups::new(u32::MAX, mask),
+            None,
+        )?;
+
+        Some(RayHit3D {
+            entity: self.collider_entity(handle),
+            point: from_vector(&ray.point_at(intersection.toi).coords),
+            normal: from_vector(&intersection.normal),
+            distance: intersection.toi,
+        })
+    }
+
+    /// Create and remove the bodies and colliders of the world, move the simulation forward
+    /// by `delta` seconds, then update the transforms
+    fn step(&mut self, world: &World, delta: f32) {
+        self.sync_bodies(world);
+        self.sync_colliders(world);
+        self.push_transforms(world);
+
+        if !self.paused && delta > 0.0 {
+            self.parameters.dt = delta;
+
+            self.pipeline.step(
+                &to_vector(self.gravity),
+                &self.parameters,
+                &mut self.islands,
+                &mut self.broad_phase,
+                &mut self.narrow_phase,
+                &mut self.bodies,
+                &mut self.colliders,
+                &mut self.joints,
+                &mut self.ccd_solver,
+                &(),
+                &(),
+            );
+        }
+
+        self.pull_transforms(world);
+        self.query_pipeline
+            .update(&self.islands, &self.bodies, &self.colliders);
+    }
+
+    fn sync_bodies(&mut self, world: &World) {
+        let mut rigid_bodies = match world.borrow_mut::<RigidBody3D>() {
+            Some(rigid_bodies) => rigid_bodies,
+            None => {
+                self.remove_bodies(|_| true);
+                return;
+            }
+        };
+        let transforms = world.borrow::<Transform3D>();
+
+        // Components removed or entities despawned
+        let alive: Vec<(Entity, RigidBodyHandle)> = rigid_bodies
+            .iter()
+            .filter_map(|(entity, body)| Some((entity, body.handle?)))
+            .collect();
+        self.remove_bodies(|(entity, handle)| !alive.contains(&(entity, handle)));
+
+        for (entity, component) in rigid_bodies.iter_mut() {
+            if component.handle.is_some() {
+                continue;
+            }
+
+            let transform = transforms
+                .as_ref()
+                .and_then(|transforms| transforms.get(entity).copied())
+                .unwrap_or_default();
+
+            let body_type = match component.body_type {
+                BodyType::Dynamic => RigidBodyType::Dynamic,
+                BodyType::Static => RigidBodyType::Static,
+                BodyType::Kinematic => RigidBodyType::KinematicPositionBased,
+            };
+            let mut builder = RigidBodyBuilder::new(body_type)
+                .position(to_isometry(&transform))
+                .linear_damping(component.linear_damping)
+                .angular_damping(component.angular_damping)
+                .gravity_scale(component.gravity_scale)
+                .ccd_enabled(component.ccd);
+            if component.lock_rotation {
+                builder = builder.lock_rotations();
+            }
+
+            let handle = self.bodies.insert(builder.build());
+            component.handle = Some(handle);
+            self.body_entities.insert(handle, entity);
+            self.entity_bodies.insert(entity, handle);
+        }
+    }
+
+    fn sync_colliders(&mut self, world: &World) {
+        let mut colliders = match world.borrow_mut::<Collider3D>() {
+            Some(colliders) => colliders,
+            None => {
+                self.remove_colliders(|_| true);
+                return;
+            }
+        };
+        let transforms = world.borrow::<Transform3D>();
+
+        let alive: Vec<(Entity, ColliderHandle)> = colliders
+            .iter()
+            .filter_map(|(entity, collider)| Some((entity, collider.handle?)))
+            .collect();
+        self.remove_colliders(|(entity, handle)| !alive.contains(&(entity, handle)));
+
+        for (entity, component) in colliders.iter_mut() {
+            if component.handle.is_some() {
+                continue;
+            }
+
+            // Invalid shapes are skipped until they're fixed
+            let builder = match collider_builder(&component.shape) {
+                Ok(builder) => builder,
+                Err(e) => {
+                    if self.invalid_colliders.insert(entity) {
+                        crash::log(&format!(
+                            "Collider of entity {} skipped: {}",
+                            entity.index(),
+                            e
+                        ));
+                    }
+                    continue;
+                }
+            };
+            self.invalid_colliders.remove(&entity);
+
+            let offset = to_vector(component.offset);
+            let collider = builder
+                .translation(offset)
+                .friction(component.friction)
+                .restitution(component.restitution)
+                .density(component.density)
+                .sensor(component.sensor)
+                .collision_groups(InteractionGroups::new(component.layers, u32::MAX))
+                .build();
+
+            let handle = match self.entity_bodies.get(&entity) {
+                Some(&body) => self
+                    .colliders
+                    .insert_with_parent(collider, body, &mut self.bodies),
+                None => {
+                    // Static collider placed once, like static bodies
+                    let transform = transforms
+                        .as_ref()
+                        .and_then(|transforms| transforms.get(entity).copied())
+                        .unwrap_or_default();
+                    let mut collider = collider;
+                    collider.set_position(to_isometry(&transform) * Translation::from(offset));
+                    self.colliders.insert(collider)
+                }
+            };
+
+            component.handle = Some(handle);
+            self.collider_entities.insert(handle, entity);
+        }
+    }
+
+    fn remove_bodies(&mut self, mut remove: impl FnMut((Entity, RigidBodyHandle)) -> bool) {
+        let removed: Vec<(RigidBodyHandle, Entity)> = self
+            .body_entities
+            .iter()
+            .map(|(&handle, &entity)| (handle, entity))
+            .filter(|&(handle, entity)| remove((entity, handle)))
+            .collect();
+
+        for (handle, entity) in removed {
+            // The colliders attached go with the body
+            if let Some(body) = self.bodies.get(handle) {
+                for collider in body.colliders() {
+                    self.collider_entities.remove(collider);
+                }
+            }
+
+            self.bodies.remove(
+                handle,
+                &mut self.islands,
+                &mut self.colliders,
+                &mut self.joints,
+            );
+            self.body_entities.remove(&handle);
+            self.entity_bodies.remove(&entity);
+        }
+    }
+
+    fn remove_colliders(&mut self, mut remove: impl FnMut((Entity, ColliderHandle)) -> bool) {
+        let removed: Vec<ColliderHandle> = self
+            .collider_entities
+            .iter()
+            .filter(|&(&handle, &entity)| remove((entity, handle)))
+            .map(|(&handle, _)| handle)
+            .collect();
+
+        for handle in removed {
+            self.colliders
+                .remove(handle, &mut self.islands, &mut self.bodies, true);
+            self.collider_entities.remove(&handle);
+        }
+    }
+
+    /// Kinematic and static bodies follow their transforms
+    fn push_transforms(&mut self, world: &World) {
+        let transforms = match world.borrow::<Transform3D>() {
+            Some(transforms) => transforms,
+            None => return,
+        };
+
+        for (&handle, &entity) in &self.body_entities {
+            let position = match transforms.get(entity) {
+                Some(transform) => to_isometry(transform),
+                None => continue,
+            };
+            let body = match self.bodies.get_mut(handle) {
+                Some(body) => body,
+                None => continue,
+            };
+
+            match body.body_type() {
+                RigidBodyType::KinematicPositionBased => body.set_next_kinematic_position(position),
+                RigidBodyType::Static if *body.position() != position => {
+                    body.set_position(position, true)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Dynamic bodies move their transforms
+    fn pull_transforms(&self, world: &World) {
+        let mut transforms = match world.borrow_mut::<Transform3D>() {
+            Some(transforms) => transforms,
+            None => return,
+        };
+
+        for (&handle, &entity) in &self.body_entities {
+            let body = match self.bodies.get(handle) {
+                Some(body) if body.is_dynamic() => body,
+                _ => continue,
+            };
+
+            if let Some(transform) = transforms.get_mut(entity) {
+                let position = body.position();
+                let rotation = position.rotation;
+                transform.translation = from_vector(&position.translation.vector);
+                transform.rotation = Quat::new(rotation.w, rotation.i, rotation.j, rotation.k);
+            }
+        }
+    }
+}
+
+impl Default for Physics3D {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn collider_builder(shape: &ColliderShape3D) -> Result<ColliderBuilder, String> {
+    let point = |vertex: &Vec3| Point::new(vertex.x, vertex.y, vertex.z);
+
+    let builder = match shape {
+        ColliderShape3D::Box { half_extents } => {
+            ColliderBuilder::cuboid(half_extents.x, half_extents.y, half_extents.z)
+        }
+        ColliderShape3D::Sphere { radius } => ColliderBuilder::ball(*radius),
+        ColliderShape3D::Capsule {
+            half_height,
+            radius,
+        } => ColliderBuilder::capsule_y(*half_height, *radius),
+        ColliderShape3D::Cylinder {
+            half_height,
+            radius,
+        } => ColliderBuilder::cylinder(*half_height, *radius),
+        ColliderShape3D::ConvexHull(points) => {
+            let hull: Vec<Point<Real>> = points.iter().map(point).collect();
+
+            ColliderBuilder::convex_hull(&hull).ok_or_else(|| {
+                format!(
+                    "Degenerate convex hull of {} points (they must not be on a plane)",
+                    points.len()
+                )
+            })?
+        }
+        ColliderShape3D::TriMesh { vertices, indices } => {
+            // rapier panics on out of range indices
+            if indices.is_empty() {
+                return Err("Triangle mesh without triangles".to_string());
+            }
+            if let Some(index) = indices
+                .iter()
+                .flatten()
+                .find(|&&index| index as usize >= vertices.len())
+            {
+                return Err(format!(
+                    "Triangle mesh index {} out of range ({} vertices)",
+                    index,
+                    vertices.len()
+                ));
+            }
+
+            ColliderBuilder::trimesh(vertices.iter().map(point).collect(), indices.clone())
+        }
+    };
+
+    Ok(builder)
+}
+
+fn to_vector(vector: Vec3) -> Vector<Real> {
+    Vector::new(vector.x, vector.y, vector.z)
+}
+
+fn from_vector(vector: &Vector<Real>) -> Vec3 {
+    Vec3::new(vector.x, vector.y, vector.z)
+}
+
+/// Position of a transform, the scale can't be simulated
+fn to_isometry(transform: &Transform3D) -> Isometry<Real> {
+    let rotation = transform.rotation.normalize();
+    Isometry::from_parts(
+        Translation::from(to_vector(transform.translation)),
+        UnitQuaternion::from_quaternion(Quaternion::new(
+            rotation.s,
+            rotation.v.x,
+            rotation.v.y,
+            rotation.v.z,
+        )),
+    )
+}
+
+/// Step the `Physics3D` resource of the world, if any (called by the engine at every update)
+pub(crate) fn step_world(world: &World, delta: f32) {
+    if let Some(mut physics) = world.resource_mut::<Physics3D>() {
+        physics.step(world, delta);
+    }
+}
@@ -0,0 +1,51 @@
+//! Mobile platforms: where the assets shipped with the game are, and how to read them
+//!
+//! On Android the assets are stored in the APK, out of reach of `std::fs`: the loose files
+//! missing from the file system are read through SDL2, which looks for them in the APK.
+//! On iOS they're in the app bundle, where relative asset roots are found instead of the
+//! working directory (see `EngineConfig::asset_root`).
+
+// standard imports
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(target_os = "android")]
+use std::io::Read;
+
+// SDL2 imports
+#[cfg(target_os = "android")]
+use sdl2::rwops::RWops;
+
+/// The engine runs on a phone or tablet, with a touch screen and an app lifecycle
+pub const IS_MOBILE: bool = cfg!(any(target_os = "android", target_os = "ios"));
+
+/// Content of a file not packed in an archive, from the APK if it isn't on the file system
+#[cfg(target_os = "android")]
+pub fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    match fs::read(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let mut file = RWops::from_file(path, "rb")
+                .map_err(|e| io::Error::new(io::ErrorKind::NotFound, e))?;
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            Ok(bytes)
+        }
+        result => result,
+    }
+}
+
+/// Content of a file not packed in an archive
+#[cfg(not(target_os = "android"))]
+pub fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    fs::read(path)
+}
+
+/// Directory holding the relative asset roots, None for the working directory
+pub fn bundle_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "ios") {
+        sdl2::filesystem::base_path().ok().map(PathBuf::from)
+    } else {
+        None
+    }
+}
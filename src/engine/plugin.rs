@@ -0,0 +1,87 @@
+//! Extensions of the engine shipped as separate crates (physics, networking...)
+//!
+//! A plugin is registered with `Engine::add_plugin` and driven by the engine loop next to the
+//! game: it gets the events and updates before the game, and renders after it.
+
+// import the engine modules
+use super::events::EngineEvent;
+use super::game::{Context, Frame};
+use super::time::Time;
+
+/// Lifecycle hooks of a plugin, every one is optional
+pub trait EnginePlugin {
+    /// Name used to find the plugin and in error messages
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Called once when the plugin is added, to register systems and load its resources
+    fn build(&mut self, _ctx: &mut Context) {}
+
+    /// Called before every game update, with the same timing
+    fn on_update(&mut self, _time: &Time, _ctx: &mut Context) {}
+
+    /// Called for every event, before the game gets it
+    fn on_event(&mut self, _event: &EngineEvent, _ctx: &mut Context) {}
+
+    /// Called after the game prepared the frame, never in headless mode
+    fn on_render(&mut self, _frame: &mut Frame) {}
+
+    /// Called once after the game shut down, in reverse order of registration
+    fn shutdown(&mut self, _ctx: &mut Context) {}
+}
+
+/// Plugins registered on an engine, in order of registration
+#[derive(Default)]
+pub(crate) struct PluginSet {
+    plugins: Vec<Box<dyn EnginePlugin>>,
+}
+
+impl PluginSet {
+    pub(crate) fn add(
+        &mut self,
+        mut plugin: Box<dyn EnginePlugin>,
+        ctx: &mut Context,
+    ) -> Result<(), String> {
+        if self.contains(plugin.name()) {
+            return Err(format!("Plugin '{}' is already registered", plugin.name()));
+        }
+
+        plugin.build(ctx);
+        self.plugins.push(plugin);
+
+        Ok(())
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.plugins.iter().any(|plugin| plugin.name() == name)
+    }
+
+    pub(crate) fn names(&self) -> Vec<&str> {
+        self.plugins.iter().map(|plugin| plugin.name()).collect()
+    }
+
+    pub(crate) fn update(&mut self, time: &Time, ctx: &mut Context) {
+        for plugin in self.plugins.iter_mut() {
+            plugin.on_update(time, ctx);
+        }
+    }
+
+    pub(crate) fn event(&mut self, event: &EngineEvent, ctx: &mut Context) {
+        for plugin in self.plugins.iter_mut() {
+            plugin.on_event(event, ctx);
+        }
+    }
+
+    pub(crate) fn render(&mut self, frame: &mut Frame) {
+        for plugin in self.plugins.iter_mut() {
+            plugin.on_render(frame);
+        }
+    }
+
+    pub(crate) fn shutdown(&mut self, ctx: &mut Context) {
+        for plugin in self.plugins.iter_mut().rev() {
+            plugin.shutdown(ctx);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Data-driven entity definitions (prefabs), read from JSON or RON files
+//!
+//! A prefab file maps component names to their values, e.g. in JSON:
+//!
+//! ```json
+//! {
+//!     "Transform": { "translation": [0.0, 0.0], "rotation": 0.0, "scale": [1.0, 1.0] },
+//!     "Health": { "current": 3, "max": 3 }
+//! }
+//! ```
+//!
+//! Every component name is registered on the `Prefabs` with the type it's deserialized to,
+//! or with a loader for the components holding assets. With hot-reloading enabled, the
+//! entities spawned from a prefab follow its file: the components whose value changed are
+//! replaced, the ones removed from the file are removed, the others keep their state.
+
+// standard imports
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+// import the engine modules
+use super::assets::Handle;
+use super::crash;
+use super::ecs::{Entity, System, World};
+use super::game::Context;
+use super::time::Time;
+
+// other imports
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// Components of a prefab file, by name
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Prefab {
+    pub components: BTreeMap<String, Value>,
+}
+
+impl Prefab {
+    pub fn parse_json(source: &str) -> Result<Prefab, String> {
+        serde_json::from_str(source)
+            .map(|components| Prefab { components })
+            .map_err(|e| e.to_string())
+    }
+
+    /// Needs the `ron-prefabs` feature
+    #[cfg(feature = "ron-prefabs")]
+    pub fn parse_ron(source: &str) -> Result<Prefab, String> {
+        ron::de::from_str(source)
+            .map(|components| Prefab { components })
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Entity spawned from a prefab, followed when the prefab is reloaded
+#[derive(Clone, Debug)]
+pub struct PrefabInstance {
+    pub source: Handle<Prefab>,
+}
+
+type InsertFn = Rc<dyn Fn(&mut Context, Entity, &Value) -> Result<(), String>>;
+
+/// Type-erased access to a component type of the prefabs
+#[derive(Clone)]
+struct ComponentType {
+    insert: InsertFn,
+    remove: fn(&mut World, Entity),
+}
+
+fn remove_component<T: 'static>(world: &mut World, entity: Entity) {
+    world.remove::<T>(entity);
+}
+
+/// Component types the prefabs can use, by name. Spawns the prefabs and, added to the
+/// systems of the engine, updates their entities when they're reloaded:
+///
+/// ```ignore
+/// let prefabs = Prefabs::new()
+///     .with_component::<Transform>("Transform")
+///     .with_component_loader("Script", |value, ctx| {
+///         let path: PathBuf = serde_json::from_value(value.clone()).map_err(|e| e.to_string())?;
+///         let path = ctx.asset_path(&path);
+///         Ok(Script::new(ctx.assets.load_script(&path)?))
+///     });
+/// ctx.systems.add_system("prefabs", prefabs.clone());
+///
+/// let goblin = ctx.load_prefab(Path::new("prefabs/goblin.json"))?;
+/// let entity = prefabs.spawn(&goblin, ctx)?;
+/// ```
+#[derive(Clone, Default)]
+pub struct Prefabs {
+    components: BTreeMap<String, ComponentType>,
+}
+
+impl Prefabs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Component deserialized from its value in the file
+    pub fn with_component<T: DeserializeOwned + 'static>(self, name: &str) -> Self {
+        self.with_component_loader(name, |value, _| {
+            serde_json::from_value::<T>(value.clone()).map_err(|e| e.to_string())
+        })
+    }
+
+    /// Component built from its value in the file by a function, e.g. to load an asset
+    pub fn with_component_loader<T, F>(mut self, name: &str, loader: F) -> Self
+    where
+        T: 'static,
+        F: Fn(&Value, &mut Context) -> Result<T, String> + 'static,
+    {
+        let insert: InsertFn = Rc::new(move |ctx, entity, value| {
+            let component = loader(value, ctx)?;
+            ctx.world.insert(entity, component);
+            Ok(())
+        });
+
+        self.components.insert(
+            name.to_string(),
+            ComponentType {
+                insert,
+                remove: remove_component::<T>,
+            },
+        );
+        self
+    }
+
+    /// Names of the registered components
+    pub fn component_names(&self) -> impl Iterator<Item = &str> {
+        self.components.keys().map(|name| name.as_str())
+    }
+
+    fn insert(
+        &self,
+        ctx: &mut Context,
+        entity: Entity,
+        name: &str,
+        value: &Value,
+    ) -> Result<(), String> {
+        let component = self
+            .components
+            .get(name)
+            .ok_or_else(|| format!("Unknown component '{}'", name))?;
+        (component.insert)(ctx, entity, value).map_err(|e| format!("Invalid {}: {}", name, e))
+    }
+
+    /// New entity with the components of a prefab, nothing is spawned if one is invalid
+    pub fn spawn(&self, prefab: &Handle<Prefab>, ctx: &mut Context) -> Result<Entity, String> {
+        let entity = ctx.world.spawn();
+
+        for (name, value) in prefab.components.iter() {
+            if let Err(e) = self.insert(ctx, entity, name, value) {
+                ctx.world.despawn(entity);
+                return Err(format!(
+                    "Couldn't spawn prefab '{}': {}",
+                    prefab.path().display(),
+                    e
+                ));
+            }
+        }
+
+        ctx.world.insert(
+            entity,
+            PrefabInstance {
+                source: prefab.clone(),
+            },
+        );
+        Ok(entity)
+    }
+
+    /// Apply the changes between two versions of a prefab to one of its entities
+    fn rebind(&self, ctx: &mut Context, entity: Entity, old: &Prefab, new: &Prefab) {
+        for (name, value) in new.components.iter() {
+            if old.components.get(name) == Some(value) {
+                continue;
+            }
+
+            if let Err(e) = self.insert(ctx, entity, name, value) {
+                crash::log(&format!("Couldn't update an entity of a prefab: {}", e));
+            }
+        }
+
+        for name in old.components.keys() {
+            if new.components.contains_key(name) {
+                continue;
+            }
+            if let Some(component) = self.components.get(name) {
+                (component.remove)(&mut ctx.world, entity);
+            }
+        }
+    }
+}
+
+impl System for Prefabs {
+    fn run(&mut self, _time: &Time, ctx: &mut Context) {
+        let stale: Vec<(Entity, Handle<Prefab>)> = match ctx.world.borrow::<PrefabInstance>() {
+            Some(instances) => instances
+                .iter()
+                .filter(|(_, instance)| instance.source.is_stale())
+                .map(|(entity, instance)| (entity, instance.source.clone()))
+                .collect(),
+            None => return,
+        };
+
+        for (entity, old) in stale {
+            let mut new = old.clone();
+            new.refresh();
+
+            self.rebind(ctx, entity, &old, &new);
+            if let Some(instance) = ctx.world.get_mut::<PrefabInstance>(entity) {
+                instance.source = new;
+            }
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! On-screen profiler, toggled by a key: frame times, CPU time of the systems, GPU time of the
+//! render passes and memory usage, with a graph of the last frame times
+//!
+//! The numbers are refreshed a few times per second to stay readable, the graph every frame.
+//! GPU times need timestamp queries, missing on some devices. The memory usage needs the
+//! `TrackingAllocator` (see `memory`).
+
+// standard imports
+use std::fmt::Write;
+use std::sync::Arc;
+
+// SDL2 imports
+use sdl2::keyboard::Keycode;
+
+// import the engine modules
+use super::bitmap_font::{BitmapFont, BitmapTextObject};
+use super::color::Color;
+use super::crash;
+use super::ctx::draw_objects::{Draw, PrimitiveObject, WORLD_UNITS_PER_PIXEL};
+use super::debug_draw::{DebugLines, DebugLinesObject};
+use super::events::{EngineEvent, InputEvent};
+use super::game::{Context, Frame};
+use super::math::Vec2;
+use super::memory;
+use super::plugin::EnginePlugin;
+use super::stats::FRAME_STATS_WINDOW;
+use super::time::Time;
+
+/// Over the game, under the console
+const PROFILER_Z_INDEX: u8 = 250;
+/// Sizes in pixels
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 6.0;
+const PANEL_WIDTH: f32 = 320.0;
+const GRAPH_HEIGHT: f32 = 60.0;
+/// Frame time at the top of the graph, in seconds
+const GRAPH_MAX_TIME: f32 = 1.0 / 20.0;
+const TARGET_60: f32 = 1.0 / 60.0;
+const TARGET_30: f32 = 1.0 / 30.0;
+
+fn milliseconds(seconds: f32) -> f32 {
+    seconds * 1000.0
+}
+
+fn frame_time_color(time: f32) -> Color {
+    if time <= TARGET_60 * 1.05 {
+        Color::GREEN
+    } else if time <= TARGET_30 * 1.05 {
+        Color::YELLOW
+    } else {
+        Color::RED
+    }
+}
+
+/// Profiler overlay, added with `Engine::add_plugin`
+pub struct ProfilerPlugin {
+    font: Arc<BitmapFont>,
+    toggle_key: Keycode,
+    visible: bool,
+    /// Seconds between two updates of the numbers
+    refresh_interval: f32,
+    since_refresh: f32,
+    /// New numbers measured on the CPU, shown with the GPU times at the next render
+    report: Option<String>,
+    frame_times: Vec<f32>,
+
+    background: Option<PrimitiveObject>,
+    text: Option<BitmapTextObject>,
+    graph: Option<DebugLinesObject>,
+}
+
+impl ProfilerPlugin {
+    /// Profiler drawn with a bitmap font, shown with F3
+    pub fn new(font: Arc<BitmapFont>) -> Self {
+        Self {
+            font,
+            toggle_key: Keycode::F3,
+            visible: false,
+            refresh_interval: 0.25,
+            since_refresh: 0.0,
+            report: None,
+            frame_times: Vec::with_capacity(FRAME_STATS_WINDOW),
+            background: None,
+            text: None,
+            graph: None,
+        }
+    }
+
+    pub fn with_toggle_key(mut self, key: Keycode) -> Self {
+        self.toggle_key = key;
+        self
+    }
+
+    /// Seconds between two updates of the numbers
+    pub fn with_refresh_interval(mut self, seconds: f32) -> Self {
+        self.refresh_interval = seconds;
+        self
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+        // Shown right away with fresh numbers
+        self.since_refresh = self.refresh_interval;
+    }
+
+    /// Frame statistics, system times and memory usage
+    fn cpu_report(ctx: &Context) -> String {
+        let mut report = format!("{}\n", ctx.frame_stats);
+
+        report += "CPU (systems):";
+        let mut systems = ctx.systems.system_times().peekable();
+        if systems.peek().is_none() {
+            report += " none";
+        }
+        for (name, time) in systems {
+            let _ = write!(report, "\n  {:<20} {:>6.2}ms", name, milliseconds(time));
+        }
+
+        if memory::is_tracking() {
+            let usage = memory::report();
+            let _ = write!(
+                report,
+                "\nMemory: {:.1} MiB",
+                usage.total_bytes() as f32 / (1024.0 * 1024.0)
+            );
+            for tag in usage.usages.iter().filter(|u| u.peak_bytes > 0) {
+                let _ = write!(
+                    report,
+                    "\n  {:<20} {:>8.1} KiB (peak {:.1} KiB)",
+                    format!("{:?}", tag.tag),
+                    tag.current_bytes as f32 / 1024.0,
+                    tag.peak_bytes as f32 / 1024.0
+                );
+            }
+        } else {
+            report += "\nMemory: not tracked (TrackingAllocator not installed)";
+        }
+
+        report
+    }
+
+    fn hide(&self) {
+        if let Some(background) = &self.background {
+            background.get_mut().set_visible(false);
+        }
+        if let Some(text) = &self.text {
+            text.get_mut().set_visible(false);
+        }
+        if let Some(graph) = &self.graph {
+            graph.get_mut().set_visible(false);
+        }
+    }
+}
+
+impl EnginePlugin for ProfilerPlugin {
+    fn name(&self) -> &str {
+        "profiler"
+    }
+
+    fn on_update(&mut self, time: &Time, ctx: &mut Context) {
+        if !self.visible {
+            return;
+        }
+
+        self.frame_times.clear();
+        self.frame_times.extend(ctx.frame_stats.frame_times());
+
+        self.since_refresh += time.delta();
+        if self.since_refresh >= self.refresh_interval {
+            self.since_refresh = 0.0;
+            self.report = Some(Self::cpu_report(ctx));
+        }
+    }
+
+    fn on_event(&mut self, event: &EngineEvent, _ctx: &mut Context) {
+        if let EngineEvent::Input(InputEvent::KeyDown {
+            key, repeat: false, ..
+        }) = event
+        {
+            if *key == self.toggle_key {
+                self.set_visible(!self.visible);
+            }
+        }
+    }
+
+    fn on_render(&mut self, frame: &mut Frame) {
+        let video = &mut *frame.video;
+
+        if !self.visible {
+            self.hide();
+            return;
+        }
+
+        if self.background.is_none() {
+            match video.new_rectangle(
+                Vec2::new(1.0, 1.0),
+                Color::rgba(0.0, 0.0, 0.0, 0.7),
+                Vec2::new(0.0, 0.0),
+                PROFILER_Z_INDEX,
+            ) {
+                Ok(background) => self.background = Some(background),
+                Err(e) => crash::log(&format!("Couldn't create the profiler background: {}", e)),
+            }
+        }
+        if self.graph.is_none() {
+            self.graph = Some(DebugLines::create(video, PROFILER_Z_INDEX + 1));
+        }
+        if self.text.is_none() {
+            match self.font.new_text(video, "", PROFILER_Z_INDEX + 1) {
+                Ok(text) => self.text = Some(text),
+                Err(e) => {
+                    crash::log(&format!("Couldn't create the profiler text: {}", e));
+                    self.visible = false;
+                    return;
+                }
+            }
+        }
+
+        let text = self.text.as_ref().unwrap();
+        if let Some(mut report) = self.report.take() {
+            let passes = video.gl_handler.get_gpu_pass_times();
+            report += "\nGPU:";
+            if passes.is_empty() {
+                report += " not measured";
+            }
+            for pass in passes {
+                let _ = write!(
+                    report,
+                    "\n  {:<20} {:>6.2}ms",
+                    pass.name,
+                    milliseconds(pass.time)
+                );
+            }
+            text.get_mut().set_text(&report);
+        }
+
+        // Sizes in pixels whatever the camera zoom
+        let view = video.gl_handler.view_bounds();
+        let view_size = view.max - view.min;
+        let (window_width, _) = video.get_window_size();
+        let pixel = view_size.x / window_width.max(1) as f32;
+
+        let text_size = self.font.measure(text.get_mut().get_text()) * pixel;
+        let panel_width = (PANEL_WIDTH * pixel).max(text_size.x + 2.0 * PADDING * pixel);
+        let graph_height = GRAPH_HEIGHT * pixel;
+        let panel_height = text_size.y + graph_height + 3.0 * PADDING * pixel;
+
+        let left = view.min.x + MARGIN * pixel;
+        let top = view.min.y + MARGIN * pixel;
+        let inner_left = left + PADDING * pixel;
+        let inner_width = panel_width - 2.0 * PADDING * pixel;
+
+        {
+            let mut background = self.background.as_ref().unwrap().get_mut();
+            background.global_position =
+                Vec2::new(left + panel_width / 2.0, top + panel_height / 2.0);
+            background.scale = Vec2::new(panel_width / 2.0, panel_height / 2.0);
+            background.set_visible(true);
+        }
+
+        {
+            let mut text = text.get_mut();
+            text.global_position = Vec2::new(inner_left, top + PADDING * pixel);
+            text.scale = Vec2::new(pixel, pixel) / WORLD_UNITS_PER_PIXEL;
+            text.set_visible(true);
+        }
+
+        // Frame times from left (oldest) to right, the world Y goes down
+        let mut graph = self.graph.as_ref().unwrap().get_mut();
+        graph.clear();
+        graph.thickness = pixel;
+        let bottom = top + panel_height - PADDING * pixel;
+        let height_of = |time: f32| bottom - (time / GRAPH_MAX_TIME).min(1.0) * graph_height;
+
+        for target in [TARGET_60, TARGET_30].iter() {
+            let y = height_of(*target);
+            graph.line(
+                Vec2::new(inner_left, y),
+                Vec2::new(inner_left + inner_width, y),
+                Color::GRAY,
+            );
+        }
+
+        let step = inner_width / (FRAME_STATS_WINDOW - 1) as f32;
+        let points: Vec<(Vec2, f32)> = self
+            .frame_times
+            .iter()
+            .enumerate()
+            .map(|(i, &time)| {
+                (
+                    Vec2::new(inner_left + i as f32 * step, height_of(time)),
+                    time,
+                )
+            })
+            .collect();
+        for pair in points.windows(2) {
+            graph.line(pair[0].0, pair[1].0, frame_time_color(pair[1].1));
+        }
+        graph.set_visible(true);
+    }
+}
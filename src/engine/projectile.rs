@@ -0,0 +1,373 @@
+//! Pooled projectiles with continuous collision against hurtboxes
+//!
+//! Projectiles are simulated deterministically from their spawn parameters, so a replication
+//! layer only has to send spawns: remote peers fast-forward them by the latency with
+//! `spawn_predicted` and end up in the same place.
+
+// import the event bus
+use super::events::{EngineEvent, EventBus};
+use super::hitbox::{DamagePayload, HitEvent, Hurtbox};
+
+// other imports
+use cgmath::{InnerSpace, Vector2};
+
+/// Step used to fast-forward the predicted projectiles
+const MAX_PROJECTILE_STEP: f32 = 1.0 / 60.0;
+
+/// Handle to a projectile of a pool, invalid once the projectile is despawned
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProjectileId {
+    index: u32,
+    generation: u32,
+}
+
+/// Steering towards the hurtbox of an entity
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Homing {
+    pub target: u64,
+    /// Maximum rotation of the velocity, in radians per second
+    pub turn_rate: f32,
+}
+
+/// Spawn parameters of a projectile, everything needed to simulate it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProjectileDesc {
+    pub owner: u64,
+    pub team: u32,
+    pub position: Vector2<f32>,
+    pub velocity: Vector2<f32>,
+    /// Acceleration applied every second (gravity, wind...)
+    pub gravity: Vector2<f32>,
+    pub homing: Option<Homing>,
+    pub radius: f32,
+    /// Seconds before the projectile expires
+    pub lifetime: f32,
+    pub payload: DamagePayload,
+    /// Keep going after a hit, hitting every target once
+    pub piercing: bool,
+}
+
+/// Why a projectile left the pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DespawnReason {
+    Hit,
+    Expired,
+    Removed,
+}
+
+/// Hooks called by the pool, used by the replication layer to mirror projectiles on other peers
+pub trait ProjectileHooks {
+    fn on_spawn(&mut self, _id: ProjectileId, _desc: &ProjectileDesc) {}
+    fn on_hit(&mut self, _id: ProjectileId, _hit: &HitEvent) {}
+    fn on_despawn(&mut self, _id: ProjectileId, _reason: DespawnReason) {}
+}
+
+/// Live projectile
+#[derive(Clone, Debug)]
+pub struct Projectile {
+    pub desc: ProjectileDesc,
+    pub position: Vector2<f32>,
+    pub velocity: Vector2<f32>,
+    /// Seconds since the spawn
+    pub age: f32,
+    hit_targets: Vec<u64>,
+}
+
+impl Projectile {
+    fn new(desc: ProjectileDesc) -> Self {
+        Self {
+            desc,
+            position: desc.position,
+            velocity: desc.velocity,
+            age: 0.0,
+            hit_targets: Vec::new(),
+        }
+    }
+
+    fn steer(&mut self, target: Vector2<f32>, turn_rate: f32, dt: f32) {
+        let to_target = target - self.position;
+        let speed = self.velocity.magnitude();
+        if speed == 0.0 || to_target.magnitude2() == 0.0 {
+            return;
+        }
+
+        let current = self.velocity.y.atan2(self.velocity.x);
+        let wanted = to_target.y.atan2(to_target.x);
+
+        // Shortest rotation, wrapped in [-PI, PI]
+        let mut difference = wanted - current;
+        while difference > std::f32::consts::PI {
+            difference -= 2.0 * std::f32::consts::PI;
+        }
+        while difference < -std::f32::consts::PI {
+            difference += 2.0 * std::f32::consts::PI;
+        }
+
+        let max_turn = turn_rate * dt;
+        let angle = current + difference.max(-max_turn).min(max_turn);
+        self.velocity = Vector2::new(angle.cos(), angle.sin()) * speed;
+    }
+
+    /// Steer and accelerate for a step, returning where the projectile goes without hitting anything
+    fn step(&mut self, dt: f32, hurtboxes: &[Hurtbox]) -> Vector2<f32> {
+        if let Some(homing) = self.desc.homing {
+            let target = hurtboxes.iter().find(|h| h.owner == homing.target);
+            if let Some(target) = target {
+                self.steer(target.rect.center, homing.turn_rate, dt);
+            }
+        }
+
+        self.velocity += self.desc.gravity * dt;
+        self.position + self.velocity * dt
+    }
+
+    fn can_hit(&self, hurtbox: &Hurtbox) -> bool {
+        hurtbox.owner != self.desc.owner
+            && hurtbox.team != self.desc.team
+            && !hurtbox.invulnerable
+            && !self.hit_targets.contains(&hurtbox.owner)
+    }
+}
+
+/// Fraction (0.0 to 1.0) of the movement `from` -> `to` of a circle when it first touches the hurtbox
+fn sweep(from: Vector2<f32>, to: Vector2<f32>, radius: f32, hurtbox: &Hurtbox) -> Option<f32> {
+    // Circle against box is approximated by a point against the box grown by the radius
+    let min = hurtbox.rect.center - hurtbox.rect.half_extents - Vector2::new(radius, radius);
+    let max = hurtbox.rect.center + hurtbox.rect.half_extents + Vector2::new(radius, radius);
+    let movement = to - from;
+
+    let mut enter: f32 = 0.0;
+    let mut exit: f32 = 1.0;
+    for &(start, delta, low, high) in &[
+        (from.x, movement.x, min.x, max.x),
+        (from.y, movement.y, min.y, max.y),
+    ] {
+        if delta == 0.0 {
+            if start < low || start > high {
+                return None;
+            }
+        } else {
+            let t1 = (low - start) / delta;
+            let t2 = (high - start) / delta;
+            enter = enter.max(t1.min(t2));
+            exit = exit.min(t1.max(t2));
+        }
+    }
+
+    if enter <= exit {
+        Some(enter)
+    } else {
+        None
+    }
+}
+
+/// Pool of projectiles, reusing the slots of the despawned ones
+#[derive(Default)]
+pub struct ProjectilePool {
+    slots: Vec<(u32, Option<Projectile>)>,
+    free: Vec<usize>,
+    hooks: Option<Box<dyn ProjectileHooks>>,
+}
+
+impl ProjectilePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reserve room for the given amount of projectiles
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut pool = Self::new();
+        pool.slots.reserve(capacity);
+        pool.free.reserve(capacity);
+        pool
+    }
+
+    pub fn set_hooks(&mut self, hooks: Box<dyn ProjectileHooks>) {
+        self.hooks = Some(hooks);
+    }
+
+    pub fn spawn(&mut self, desc: ProjectileDesc) -> ProjectileId {
+        let projectile = Projectile::new(desc);
+
+        let index = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.0 = slot.0.wrapping_add(1);
+                slot.1 = Some(projectile);
+                index
+            }
+            None => {
+                self.slots.push((0, Some(projectile)));
+                self.slots.len() - 1
+            }
+        };
+
+        let id = ProjectileId {
+            index: index as u32,
+            generation: self.slots[index].0,
+        };
+        if let Some(hooks) = self.hooks.as_mut() {
+            hooks.on_spawn(id, &desc);
+        }
+
+        id
+    }
+
+    /// Spawn a projectile fired `elapsed` seconds ago (e.g. on a remote peer), already moved to where it is now
+    /// with the steps of `update`: homing projectiles follow their target as placed in `hurtboxes`.
+    /// Hits are only checked from the current position on, a projectile already expired is despawned
+    pub fn spawn_predicted(
+        &mut self,
+        desc: ProjectileDesc,
+        elapsed: f32,
+        hurtboxes: &[Hurtbox],
+    ) -> ProjectileId {
+        let id = self.spawn(desc);
+
+        let expired = match self.get_mut(id) {
+            Some(projectile) => {
+                let mut remaining = elapsed;
+                while remaining > 0.0 {
+                    let dt = remaining.min(MAX_PROJECTILE_STEP);
+                    projectile.position = projectile.step(dt, hurtboxes);
+                    projectile.age += dt;
+                    remaining -= dt;
+                }
+                projectile.age >= projectile.desc.lifetime
+            }
+            None => false,
+        };
+        if expired {
+            self.despawn_with(id, DespawnReason::Expired);
+        }
+
+        id
+    }
+
+    pub fn despawn(&mut self, id: ProjectileId) -> bool {
+        self.despawn_with(id, DespawnReason::Removed)
+    }
+
+    fn despawn_with(&mut self, id: ProjectileId, reason: DespawnReason) -> bool {
+        if self.get(id).is_none() {
+            return false;
+        }
+
+        self.slots[id.index as usize].1 = None;
+        self.free.push(id.index as usize);
+
+        if let Some(hooks) = self.hooks.as_mut() {
+            hooks.on_despawn(id, reason);
+        }
+
+        true
+    }
+
+    pub fn get(&self, id: ProjectileId) -> Option<&Projectile> {
+        match self.slots.get(id.index as usize) {
+            Some((generation, Some(projectile))) if *generation == id.generation => Some(projectile),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, id: ProjectileId) -> Option<&mut Projectile> {
+        match self.slots.get_mut(id.index as usize) {
+            Some((generation, Some(projectile))) if *generation == id.generation => Some(projectile),
+            _ => None,
+        }
+    }
+
+    /// Every live projectile
+    pub fn iter(&self) -> impl Iterator<Item = (ProjectileId, &Projectile)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (generation, projectile))| {
+                projectile.as_ref().map(|p| {
+                    (
+                        ProjectileId {
+                            index: index as u32,
+                            generation: *generation,
+                        },
+                        p,
+                    )
+                })
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Move every projectile, hitting the hurtboxes crossed on the way (even at high speed).
+    /// Hits are pushed on the event bus and returned.
+    pub fn update(&mut self, dt: f32, hurtboxes: &[Hurtbox], bus: &mut EventBus) -> Vec<HitEvent> {
+        let mut hits = Vec::new();
+        let mut despawned = Vec::new();
+
+        for index in 0..self.slots.len() {
+            let generation = self.slots[index].0;
+            let projectile = match self.slots[index].1.as_mut() {
+                Some(p) => p,
+                None => continue,
+            };
+            let id = ProjectileId {
+                index: index as u32,
+                generation,
+            };
+
+            let from = projectile.position;
+            let to = projectile.step(dt, hurtboxes);
+
+            // Targets in order of contact along the movement
+            let mut touched: Vec<(f32, &Hurtbox)> = hurtboxes
+                .iter()
+                .filter(|h| projectile.can_hit(h))
+                .filter_map(|h| sweep(from, to, projectile.desc.radius, h).map(|t| (t, h)))
+                .collect();
+            touched.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+            let mut stopped_at = None;
+            for (t, hurtbox) in touched {
+                let point = from + (to - from) * t;
+                let hit = HitEvent {
+                    attacker: projectile.desc.owner,
+                    target: hurtbox.owner,
+                    payload: projectile.desc.payload,
+                    point,
+                };
+
+                projectile.hit_targets.push(hurtbox.owner);
+                if let Some(hooks) = self.hooks.as_mut() {
+                    hooks.on_hit(id, &hit);
+                }
+                bus.push(EngineEvent::Hit(hit));
+                hits.push(hit);
+
+                if !projectile.desc.piercing {
+                    stopped_at = Some(point);
+                    break;
+                }
+            }
+
+            projectile.position = stopped_at.unwrap_or(to);
+            projectile.age += dt;
+
+            if stopped_at.is_some() {
+                despawned.push((id, DespawnReason::Hit));
+            } else if projectile.age >= projectile.desc.lifetime {
+                despawned.push((id, DespawnReason::Expired));
+            }
+        }
+
+        for (id, reason) in despawned {
+            self.despawn_with(id, reason);
+        }
+
+        hits
+    }
+}
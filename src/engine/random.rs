@@ -0,0 +1,152 @@
+//! Seeded random number generator, giving the same sequence on every platform for a given seed
+//!
+//! Gameplay should draw its random numbers from `ctx.rng`: replays reseed it, so the
+//! recorded session plays out the same way. Systems and procedural generators can take
+//! their own stream with `stream`, unaffected by how many numbers the others draw.
+
+// standard imports
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// import the engine modules
+use super::math::Vec2;
+
+/// SplitMix64 generator, small and fast, not suitable for cryptography
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rng {
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, state: seed }
+    }
+
+    /// Generator seeded with the current time
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+
+        Self::new(nanos)
+    }
+
+    /// Seed the generator was started with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restart the sequence from a new seed
+    pub fn reseed(&mut self, seed: u64) {
+        *self = Self::new(seed);
+    }
+
+    /// Independent generator seeded from this one, advancing it by one number
+    pub fn fork(&mut self) -> Rng {
+        Rng::new(self.next_u64())
+    }
+
+    /// Generator of a named stream (e.g. a system or a level), always the same for
+    /// a given seed and name whatever was drawn from this generator
+    pub fn stream(&self, name: &str) -> Rng {
+        // FNV-1a of the name, mixed with the seed
+        let hash = name.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+
+        let mut mixer = Rng::new(self.seed ^ hash);
+        Rng::new(mixer.next_u64())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Number in range 0.0..1.0
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Number in range min..max
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    /// Integer in range min..max (min if the range is empty)
+    pub fn range_int(&mut self, min: i32, max: i32) -> i32 {
+        if max <= min {
+            return min;
+        }
+
+        let span = (max as i64 - min as i64) as u64;
+        (min as i64 + (self.next_u64() % span) as i64) as i32
+    }
+
+    /// True with the given probability (0.0 to 1.0)
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+
+    /// Index picked with a probability proportional to its weight, None if every weight is zero
+    pub fn weighted_index(&mut self, weights: &[f32]) -> Option<usize> {
+        let total: f32 = weights.iter().map(|weight| weight.max(0.0)).sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let mut target = self.next_f32() * total;
+        for (index, weight) in weights.iter().enumerate() {
+            let weight = weight.max(0.0);
+            if target < weight {
+                return Some(index);
+            }
+            target -= weight;
+        }
+
+        // Rounding errors, the last non-zero weight
+        weights.iter().rposition(|weight| *weight > 0.0)
+    }
+
+    /// Shuffle a slice in place (Fisher-Yates)
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for index in (1..items.len()).rev() {
+            let other = (self.next_u64() % (index as u64 + 1)) as usize;
+            items.swap(index, other);
+        }
+    }
+
+    /// Vector of length 1 in a random direction
+    pub fn unit_vector(&mut self) -> Vec2 {
+        let angle = self.next_f32() * std::f32::consts::TAU;
+        Vec2::new(angle.cos(), angle.sin())
+    }
+
+    /// Point uniformly distributed in a circle of the given radius, centered on the origin
+    pub fn in_circle(&mut self, radius: f32) -> Vec2 {
+        self.unit_vector() * radius * self.next_f32().sqrt()
+    }
+
+    /// Random element of a slice, None if it's empty
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            None
+        } else {
+            items.get((self.next_u64() % items.len() as u64) as usize)
+        }
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::from_time()
+    }
+}
@@ -0,0 +1,483 @@
+//! Deterministic replays: the input and frame times of a session, played back exactly later
+//!
+//! A recording keeps the seed of `ctx.rng`, the loop mode and, for every frame, its delta time
+//! and the `InputEvent`s received. Playing it back reseeds the generator, feeds the recorded
+//! input instead of the live one and steps the same delta times, so a game drawing its random
+//! numbers from `ctx.rng` and reading its input from the events goes through the same states.
+//!
+//! Start recording and playback from the same game state (e.g. right after `Game::init`).
+//! The live controller state read with `GamepadHandler::button` and `axis` isn't replayed.
+
+// standard imports
+use std::fs;
+use std::path::Path;
+
+// SDL2 imports
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+
+// import the engine modules
+use super::config::LoopMode;
+use super::ctx::gamepad::{Axis, Button};
+use super::events::{EngineEvent, InputEvent, WindowId};
+
+/// First bytes of a replay file
+const REPLAY_MAGIC: &[u8; 4] = b"MGRP";
+/// Version of the replay file format
+const REPLAY_FORMAT: u8 = 2;
+
+/// Delta time and input of a recorded frame
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReplayFrame {
+    pub delta: f32,
+    pub inputs: Vec<InputEvent>,
+}
+
+/// Recorded session
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    /// Seed of `ctx.rng` when the recording started
+    pub seed: u64,
+    pub loop_mode: LoopMode,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, loop_mode: LoopMode) -> Self {
+        Self {
+            seed,
+            loop_mode,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Seconds of game time covered by the replay
+    pub fn duration(&self) -> f32 {
+        self.frames.iter().map(|frame| frame.delta).sum()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path)
+            .map_err(|e| format!("Couldn't read replay '{}': {}", path.display(), e))?;
+
+        Self::decode(&bytes).map_err(|e| format!("Invalid replay '{}': {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.encode())
+            .map_err(|e| format!("Couldn't write replay '{}': {}", path.display(), e))
+    }
+
+    /// Compact binary form, written by `save`
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer(Vec::new());
+
+        writer.0.extend_from_slice(REPLAY_MAGIC);
+        writer.u8(REPLAY_FORMAT);
+        writer.u64(self.seed);
+        writer.f32(match self.loop_mode {
+            LoopMode::Variable => 0.0,
+            LoopMode::Fixed(rate) => rate,
+        });
+        writer.varint(self.frames.len() as u64);
+
+        for frame in &self.frames {
+            writer.f32(frame.delta);
+            writer.varint(frame.inputs.len() as u64);
+            for input in &frame.inputs {
+                writer.input(input);
+            }
+        }
+
+        writer.0
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes, offset: 0 };
+
+        if reader.take(4)? != REPLAY_MAGIC {
+            return Err(String::from("not a replay file"));
+        }
+        let format = reader.u8()?;
+        if format != REPLAY_FORMAT {
+            return Err(format!("unsupported format version {}", format));
+        }
+
+        let seed = reader.u64()?;
+        let loop_mode = match reader.f32()? {
+            rate if rate > 0.0 => LoopMode::Fixed(rate),
+            _ => LoopMode::Variable,
+        };
+
+        let frame_count = reader.varint()?;
+        let mut frames = Vec::new();
+        for _ in 0..frame_count {
+            let delta = reader.f32()?;
+            let input_count = reader.varint()?;
+            let inputs = (0..input_count)
+                .map(|_| reader.input())
+                .collect::<Result<_, _>>()?;
+
+            frames.push(ReplayFrame { delta, inputs });
+        }
+
+        Ok(Self {
+            seed,
+            loop_mode,
+            frames,
+        })
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.0.extend_from_slice(&value.to_bits().to_le_bytes());
+    }
+
+    /// LEB128, most counts and coordinates fit in one or two bytes
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    /// Zigzag encoding, small negative numbers stay small
+    fn signed(&mut self, value: i32) {
+        self.varint(((value << 1) ^ (value >> 31)) as u32 as u64);
+    }
+
+    fn name(&mut self, name: &str) {
+        self.varint(name.len() as u64);
+        self.0.extend_from_slice(name.as_bytes());
+    }
+
+    fn touch(&mut self, tag: u8, window: WindowId, finger: i64, x: f32, y: f32) {
+        self.u8(tag);
+        self.varint(window as u64);
+        self.varint(finger as u64);
+        self.f32(x);
+        self.f32(y);
+    }
+
+    fn input(&mut self, input: &InputEvent) {
+        match *input {
+            InputEvent::KeyDown {
+                window,
+                key,
+                repeat,
+            } => {
+                self.u8(if repeat { 1 } else { 0 });
+                self.varint(window as u64);
+                self.signed(key as i32);
+            }
+            InputEvent::KeyUp { window, key } => {
+                self.u8(2);
+                self.varint(window as u64);
+                self.signed(key as i32);
+            }
+            InputEvent::MouseMoved { window, x, y } => {
+                self.u8(3);
+                self.varint(window as u64);
+                self.signed(x);
+                self.signed(y);
+            }
+            InputEvent::MouseButtonDown {
+                window,
+                button,
+                x,
+                y,
+            } => {
+                self.u8(4);
+                self.varint(window as u64);
+                self.u8(button.to_ll());
+                self.signed(x);
+                self.signed(y);
+            }
+            InputEvent::MouseButtonUp {
+                window,
+                button,
+                x,
+                y,
+            } => {
+                self.u8(5);
+                self.varint(window as u64);
+                self.u8(button.to_ll());
+                self.signed(x);
+                self.signed(y);
+            }
+            InputEvent::MouseWheel { window, x, y } => {
+                self.u8(6);
+                self.varint(window as u64);
+                self.signed(x);
+                self.signed(y);
+            }
+            InputEvent::GamepadButton {
+                player,
+                button,
+                pressed,
+            } => {
+                self.u8(if pressed { 7 } else { 8 });
+                self.varint(player as u64);
+                self.name(&button.string());
+            }
+            InputEvent::GamepadAxis {
+                player,
+                axis,
+                value,
+            } => {
+                self.u8(9);
+                self.varint(player as u64);
+                self.name(&axis.string());
+                self.f32(value);
+            }
+            InputEvent::TouchDown {
+                window,
+                finger,
+                x,
+                y,
+            } => self.touch(10, window, finger, x, y),
+            InputEvent::TouchMoved {
+                window,
+                finger,
+                x,
+                y,
+            } => self.touch(11, window, finger, x, y),
+            InputEvent::TouchUp {
+                window,
+                finger,
+                x,
+                y,
+            } => self.touch(12, window, finger, x, y),
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.offset + count;
+        let bytes = self
+            .bytes
+            .get(self.offset..end)
+            .ok_or_else(|| String::from("unexpected end of file"))?;
+        self.offset = end;
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(f32::from_bits(u32::from_le_bytes(bytes)))
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(String::from("invalid number"))
+    }
+
+    fn signed(&mut self) -> Result<i32, String> {
+        let value = self.varint()? as u32;
+        Ok(((value >> 1) as i32) ^ -((value & 1) as i32))
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let length = self.varint()? as usize;
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn window(&mut self) -> Result<WindowId, String> {
+        Ok(self.varint()? as WindowId)
+    }
+
+    fn key(&mut self) -> Result<Keycode, String> {
+        let code = self.signed()?;
+        Keycode::from_i32(code).ok_or_else(|| format!("unknown key {}", code))
+    }
+
+    fn input(&mut self) -> Result<InputEvent, String> {
+        let input = match self.u8()? {
+            tag @ 0..=1 => InputEvent::KeyDown {
+                window: self.window()?,
+                key: self.key()?,
+                repeat: tag == 1,
+            },
+            2 => InputEvent::KeyUp {
+                window: self.window()?,
+                key: self.key()?,
+            },
+            3 => InputEvent::MouseMoved {
+                window: self.window()?,
+                x: self.signed()?,
+                y: self.signed()?,
+            },
+            4 => InputEvent::MouseButtonDown {
+                window: self.window()?,
+                button: MouseButton::from_ll(self.u8()?),
+                x: self.signed()?,
+                y: self.signed()?,
+            },
+            5 => InputEvent::MouseButtonUp {
+                window: self.window()?,
+                button: MouseButton::from_ll(self.u8()?),
+                x: self.signed()?,
+                y: self.signed()?,
+            },
+            6 => InputEvent::MouseWheel {
+                window: self.window()?,
+                x: self.signed()?,
+                y: self.signed()?,
+            },
+            tag @ 7..=8 => {
+                let player = self.varint()? as usize;
+                let name = self.name()?;
+                InputEvent::GamepadButton {
+                    player,
+                    button: Button::from_string(&name)
+                        .ok_or_else(|| format!("unknown button '{}'", name))?,
+                    pressed: tag == 7,
+                }
+            }
+            9 => {
+                let player = self.varint()? as usize;
+                let name = self.name()?;
+                InputEvent::GamepadAxis {
+                    player,
+                    axis: Axis::from_string(&name)
+                        .ok_or_else(|| format!("unknown axis '{}'", name))?,
+                    value: self.f32()?,
+                }
+            }
+            tag @ 10..=12 => {
+                let window = self.window()?;
+                let finger = self.varint()? as i64;
+                let (x, y) = (self.f32()?, self.f32()?);
+                match tag {
+                    10 => InputEvent::TouchDown {
+                        window,
+                        finger,
+                        x,
+                        y,
+                    },
+                    11 => InputEvent::TouchMoved {
+                        window,
+                        finger,
+                        x,
+                        y,
+                    },
+                    _ => InputEvent::TouchUp {
+                        window,
+                        finger,
+                        x,
+                        y,
+                    },
+                }
+            }
+            tag => return Err(format!("unknown input tag {}", tag)),
+        };
+
+        Ok(input)
+    }
+}
+
+/// Replay being recorded or played back by the engine
+pub(crate) enum ReplayState {
+    Idle,
+    Recording(Replay),
+    Playing { replay: Replay, frame: usize },
+}
+
+impl Default for ReplayState {
+    fn default() -> Self {
+        ReplayState::Idle
+    }
+}
+
+impl ReplayState {
+    /// Record the frame, or swap in the recorded input. Returns the delta time of the frame
+    pub(crate) fn process_frame(&mut self, delta: f32, events: &mut Vec<EngineEvent>) -> f32 {
+        match self {
+            ReplayState::Idle => delta,
+            ReplayState::Recording(replay) => {
+                let inputs = events
+                    .iter()
+                    .filter_map(|event| match event {
+                        EngineEvent::Input(input) => Some(*input),
+                        _ => None,
+                    })
+                    .collect();
+                replay.frames.push(ReplayFrame { delta, inputs });
+
+                delta
+            }
+            ReplayState::Playing { replay, frame } => {
+                let recorded = match replay.frames.get(*frame) {
+                    Some(recorded) => recorded,
+                    None => {
+                        // Finished, the live input takes over
+                        *self = ReplayState::Idle;
+                        return delta;
+                    }
+                };
+                *frame += 1;
+
+                // The live input is dropped while playing
+                events.retain(|event| !matches!(event, EngineEvent::Input(_)));
+                events.splice(
+                    0..0,
+                    recorded
+                        .inputs
+                        .iter()
+                        .map(|input| EngineEvent::Input(*input)),
+                );
+
+                recorded.delta
+            }
+        }
+    }
+
+    pub(crate) fn is_recording(&self) -> bool {
+        matches!(self, ReplayState::Recording(_))
+    }
+
+    pub(crate) fn is_playing(&self) -> bool {
+        match self {
+            ReplayState::Playing { replay, frame } => *frame < replay.frames.len(),
+            _ => false,
+        }
+    }
+}
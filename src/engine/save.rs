@@ -0,0 +1,189 @@
+//! Versioned save files of the game state, stored by slot in the platform saves directory
+//!
+//! Every save is written to a temporary file then renamed over the old one, which is kept
+//! as a `.bak` backup: a crash while saving can't leave a corrupt save behind.
+
+// standard imports
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// import the engine modules
+use super::paths::{self, Paths};
+
+// other imports
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+pub use serde_json::Value as SaveValue;
+
+/// Extension of the save files
+const SAVE_EXTENSION: &str = "sav";
+
+/// What gets written on disk, the game data with its format version
+#[derive(Serialize, Deserialize)]
+struct SaveFile<T> {
+    version: u32,
+    /// Seconds since the Unix epoch
+    saved_at: u64,
+    data: T,
+}
+
+/// Information about a save slot, read without loading the data
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlotInfo {
+    pub name: String,
+    pub version: u32,
+    pub saved_at: SystemTime,
+}
+
+/// Reads and writes the save slots of the game
+#[derive(Clone, Debug)]
+pub struct SaveManager {
+    paths: Paths,
+}
+
+fn check_slot_name(slot: &str) -> Result<(), String> {
+    let valid = !slot.is_empty()
+        && slot
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == ' ');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid save slot name '{}'", slot))
+    }
+}
+
+fn read_file<T: DeserializeOwned>(path: &Path) -> Result<SaveFile<T>, String> {
+    let bytes =
+        fs::read(path).map_err(|e| format!("Couldn't read save '{}': {}", path.display(), e))?;
+
+    serde_json::from_slice(&bytes).map_err(|e| format!("Corrupt save '{}': {}", path.display(), e))
+}
+
+impl SaveManager {
+    pub fn new(paths: Paths) -> Self {
+        Self { paths }
+    }
+
+    /// File of a save slot
+    pub fn slot_path(&self, slot: &str) -> Result<PathBuf, String> {
+        check_slot_name(slot)?;
+
+        Ok(self
+            .paths
+            .saves()?
+            .join(slot)
+            .with_extension(SAVE_EXTENSION))
+    }
+
+    /// Write the data in a slot, tagged with the format version of the game
+    pub fn save<T: Serialize>(&self, slot: &str, version: u32, data: &T) -> Result<(), String> {
+        let path = self.slot_path(slot)?;
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let bytes = serde_json::to_vec_pretty(&SaveFile {
+            version,
+            saved_at,
+            data,
+        })
+        .map_err(|e| format!("Couldn't serialize save '{}': {}", slot, e))?;
+
+        write_atomic(&path, &bytes)
+    }
+
+    /// Read the data of a slot saved with the same format version, or an older one it's compatible with
+    pub fn load<T: DeserializeOwned>(&self, slot: &str, version: u32) -> Result<T, String> {
+        self.load_migrating(slot, version, |_, data| {
+            serde_json::from_value(data).map_err(|e| e.to_string())
+        })
+    }
+
+    /// Read the data of a slot, converting the data of older format versions with `migrate`
+    /// (it gets the version of the save and its raw data)
+    pub fn load_migrating<T, F>(&self, slot: &str, version: u32, migrate: F) -> Result<T, String>
+    where
+        F: FnOnce(u32, SaveValue) -> Result<T, String>,
+        T: DeserializeOwned,
+    {
+        let path = self.slot_path(slot)?;
+
+        // Fall back to the previous save if the last one is unreadable
+        let file: SaveFile<SaveValue> = match read_file(&path) {
+            Ok(file) => file,
+            Err(e) => read_file(&backup_path(&path)).map_err(|_| e)?,
+        };
+
+        if file.version > version {
+            return Err(format!(
+                "Save '{}' has version {}, newer than the supported {}",
+                slot, file.version, version
+            ));
+        }
+
+        if file.version == version {
+            serde_json::from_value(file.data).map_err(|e| format!("Corrupt save '{}': {}", slot, e))
+        } else {
+            migrate(file.version, file.data).map_err(|e| {
+                format!(
+                    "Couldn't migrate save '{}' from version {}: {}",
+                    slot, file.version, e
+                )
+            })
+        }
+    }
+
+    pub fn exists(&self, slot: &str) -> bool {
+        self.slot_path(slot).map_or(false, |path| path.exists())
+    }
+
+    /// Delete a slot and its backup
+    pub fn delete(&self, slot: &str) -> Result<(), String> {
+        let path = self.slot_path(slot)?;
+
+        let _ = fs::remove_file(backup_path(&path));
+        fs::remove_file(&path).map_err(|e| format!("Couldn't delete save '{}': {}", slot, e))
+    }
+
+    /// Every readable save slot, the most recent first
+    pub fn list_slots(&self) -> Result<Vec<SlotInfo>, String> {
+        let dir = self.paths.saves()?;
+        let entries = fs::read_dir(&dir).map_err(|e| format!("Couldn't list saves: {}", e))?;
+
+        let mut slots: Vec<SlotInfo> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == SAVE_EXTENSION))
+            .filter_map(|path| {
+                let file: SaveFile<SaveValue> = read_file(&path).ok()?;
+                Some(SlotInfo {
+                    name: path.file_stem()?.to_string_lossy().into_owned(),
+                    version: file.version,
+                    saved_at: UNIX_EPOCH + Duration::from_secs(file.saved_at),
+                })
+            })
+            .collect();
+
+        slots.sort_by(|a, b| b.saved_at.cmp(&a.saved_at));
+        Ok(slots)
+    }
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    path.with_extension(format!("{}.bak", SAVE_EXTENSION))
+}
+
+/// Write the whole file or nothing (see `paths::write_atomic`), the old file is kept as backup
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let error = |e: std::io::Error| format!("Couldn't write save '{}': {}", path.display(), e);
+
+    if path.exists() {
+        fs::copy(path, backup_path(path)).map_err(error)?;
+    }
+    paths::write_atomic(path, bytes).map_err(error)
+}
@@ -0,0 +1,166 @@
+//! Stack of scenes (menus, gameplay, pause screens...), itself usable as the `Game` run by the engine
+
+// import the engine modules
+use super::events::EngineEvent;
+use super::game::{Context, Frame, Game};
+use super::time::Time;
+
+/// Change of the scene stack requested by a scene update
+pub enum SceneTransition {
+    None,
+    /// Open a scene over the current one, which is paused
+    Push(Box<dyn Scene>),
+    /// Close the current scene, resuming the one below (quits the game if it was the last)
+    Pop,
+    /// Close the current scene and open another in its place
+    Replace(Box<dyn Scene>),
+    /// Close every scene
+    Quit,
+}
+
+/// Part of the game with its own logic, handled by the `SceneManager`
+pub trait Scene {
+    /// Called when the scene enters the stack
+    fn enter(&mut self, _ctx: &mut Context) {}
+
+    /// Called when the scene leaves the stack
+    fn exit(&mut self, _ctx: &mut Context) {}
+
+    /// Called when another scene is pushed over this one
+    fn pause(&mut self, _ctx: &mut Context) {}
+
+    /// Called when the scene is back on top of the stack
+    fn resume(&mut self, _ctx: &mut Context) {}
+
+    /// Advance the scene, only called on the top scene
+    fn update(&mut self, time: &Time, ctx: &mut Context) -> SceneTransition;
+
+    fn render(&mut self, _frame: &mut Frame) {}
+
+    /// Called for every engine event, only on the top scene
+    fn on_event(&mut self, _event: &EngineEvent, _ctx: &mut Context) {}
+
+    /// Keep rendering the scenes below this one (e.g. a pause menu over the gameplay)
+    fn is_overlay(&self) -> bool {
+        false
+    }
+}
+
+/// Stack of scenes, the top one is the active scene
+pub struct SceneManager {
+    stack: Vec<Box<dyn Scene>>,
+    /// The first scene, entered on init
+    pending: Option<Box<dyn Scene>>,
+}
+
+impl SceneManager {
+    pub fn new(initial: Box<dyn Scene>) -> Self {
+        Self {
+            stack: Vec::new(),
+            pending: Some(initial),
+        }
+    }
+
+    pub fn push(&mut self, mut scene: Box<dyn Scene>, ctx: &mut Context) {
+        if let Some(top) = self.stack.last_mut() {
+            top.pause(ctx);
+        }
+
+        scene.enter(ctx);
+        self.stack.push(scene);
+    }
+
+    pub fn pop(&mut self, ctx: &mut Context) -> Option<Box<dyn Scene>> {
+        let mut scene = self.stack.pop()?;
+        scene.exit(ctx);
+
+        match self.stack.last_mut() {
+            Some(top) => top.resume(ctx),
+            None => ctx.set_break_signal(),
+        }
+
+        Some(scene)
+    }
+
+    pub fn replace(&mut self, mut scene: Box<dyn Scene>, ctx: &mut Context) -> Option<Box<dyn Scene>> {
+        let old = self.stack.pop().map(|mut old| {
+            old.exit(ctx);
+            old
+        });
+
+        scene.enter(ctx);
+        self.stack.push(scene);
+
+        old
+    }
+
+    /// Pop every scene from the top, quitting the game. The scenes below aren't resumed
+    pub fn clear(&mut self, ctx: &mut Context) {
+        while let Some(mut scene) = self.stack.pop() {
+            scene.exit(ctx);
+        }
+        ctx.set_break_signal();
+    }
+
+    pub fn apply(&mut self, transition: SceneTransition, ctx: &mut Context) {
+        match transition {
+            SceneTransition::None => {}
+            SceneTransition::Push(scene) => self.push(scene, ctx),
+            SceneTransition::Pop => {
+                self.pop(ctx);
+            }
+            SceneTransition::Replace(scene) => {
+                self.replace(scene, ctx);
+            }
+            SceneTransition::Quit => self.clear(ctx),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+}
+
+impl Game for SceneManager {
+    fn init(&mut self, ctx: &mut Context) {
+        if let Some(scene) = self.pending.take() {
+            self.push(scene, ctx);
+        }
+    }
+
+    fn update(&mut self, time: &Time, ctx: &mut Context) {
+        if let Some(top) = self.stack.last_mut() {
+            let transition = top.update(time, ctx);
+            self.apply(transition, ctx);
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        // Render from the lowest scene visible through the overlays
+        let first_visible = self
+            .stack
+            .iter()
+            .rposition(|scene| !scene.is_overlay())
+            .unwrap_or(0);
+
+        for scene in self.stack[first_visible..].iter_mut() {
+            scene.render(frame);
+        }
+    }
+
+    fn on_event(&mut self, event: &EngineEvent, ctx: &mut Context) {
+        if let Some(top) = self.stack.last_mut() {
+            top.on_event(event, ctx);
+        }
+    }
+
+    fn shutdown(&mut self, ctx: &mut Context) {
+        while let Some(mut scene) = self.stack.pop() {
+            scene.exit(ctx);
+        }
+    }
+}
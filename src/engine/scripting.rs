@@ -0,0 +1,514 @@
+//! Lua scripting of the gameplay, with the `scripting` feature
+//!
+//! A script is a Lua module returning a table of callbacks. Scripts are attached to entities
+//! with the `Script` component, or to the whole scene with `ScriptEngine::add_scene_script`.
+//! Every entity gets its own instance of the table (its `self`), with `self.entity` set to
+//! its id (nil for the scene scripts):
+//!
+//! ```lua
+//! local Player = {}
+//!
+//! function Player:init()
+//!     self.speed = 200
+//! end
+//!
+//! function Player:update(dt)
+//!     local x, y = engine.position(self.entity)
+//!     if engine.action_down("right") then
+//!         engine.set_position(self.entity, x + self.speed * dt, y)
+//!     end
+//! end
+//!
+//! return Player
+//! ```
+//!
+//! The callbacks reach the engine through the global `engine` table: `spawn()`,
+//! `despawn(id)`, `is_alive(id)`, `position(id)`, `set_position(id, x, y)`,
+//! `play_sound(path)`, `draw_sprite(path, x, y [, z])` (drawn for the current frame only),
+//! `key_down(name)`, `action_down(action)` and `log(text)`. Errors of a script are logged
+//! and stop its instance, the rest of the game keeps running.
+//!
+//! With hot-reloading enabled, an edited script is loaded again and its running instances
+//! switch to the new callbacks, keeping their fields (`init` isn't called again).
+
+// standard imports
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// SDL2 imports
+use sdl2::keyboard::Keycode;
+
+// import the engine modules
+use super::assets::{Handle, ScriptSource};
+use super::crash;
+use super::ctx::draw_objects::{Draw, SpriteObject};
+use super::ecs::{Entity, System};
+use super::game::Context;
+use super::math::Vec2;
+use super::memory::{self, MemoryTag};
+use super::time::Time;
+use super::transform::Transform;
+
+// other imports
+use mlua::{Function, Lua, MultiValue, RegistryKey, Scope, Table, Value};
+
+/// Lua script attached to an entity, run by the `ScriptEngine`
+#[derive(Clone, Debug)]
+pub struct Script {
+    pub source: Handle<ScriptSource>,
+}
+
+impl Script {
+    pub fn new(source: Handle<ScriptSource>) -> Self {
+        Self { source }
+    }
+}
+
+/// Table of a script owned by an entity (or the scene)
+struct Instance {
+    source: Handle<ScriptSource>,
+    table: RegistryKey,
+    initialized: bool,
+    /// Stopped after an error
+    failed: bool,
+}
+
+/// Sprites of `engine.draw_sprite`, reused from frame to frame
+#[derive(Default)]
+struct SpritePool {
+    sprites: HashMap<(PathBuf, u8), (Vec<SpriteObject>, usize)>,
+}
+
+impl SpritePool {
+    /// Hide the sprites of the previous frame
+    fn begin_frame(&mut self) {
+        for (sprites, used) in self.sprites.values_mut() {
+            for sprite in sprites.iter() {
+                sprite.get_mut().set_visible(false);
+            }
+            *used = 0;
+        }
+    }
+
+    fn draw(
+        &mut self,
+        ctx: &mut Context,
+        path: &Path,
+        position: Vec2,
+        z_index: u8,
+    ) -> Result<(), String> {
+        // Nothing is drawn by dedicated servers
+        if ctx.is_headless() {
+            return Ok(());
+        }
+
+        let (sprites, used) = self
+            .sprites
+            .entry((path.to_path_buf(), z_index))
+            .or_default();
+        if *used == sprites.len() {
+            let texture = ctx.load_texture(path)?;
+            sprites.push(ctx.video().new_sprite_from_asset(&texture, z_index)?);
+        }
+
+        let mut sprite = sprites[*used].get_mut();
+        sprite.global_position = position;
+        sprite.set_visible(true);
+        *used += 1;
+
+        Ok(())
+    }
+}
+
+/// Runs the scripts of the entities and of the scene, added to the systems of the engine:
+///
+/// ```ignore
+/// ctx.systems.add_system("scripts", ScriptEngine::new());
+/// ```
+pub struct ScriptEngine {
+    lua: Lua,
+    /// Module table of every script, by path
+    modules: HashMap<PathBuf, RegistryKey>,
+    entities: HashMap<Entity, Instance>,
+    scene: Vec<Instance>,
+    sprites: SpritePool,
+}
+
+impl Default for ScriptEngine {
+    fn default() -> Self {
+        let _scope = memory::scope(MemoryTag::Scripting);
+
+        Self {
+            lua: Lua::new(),
+            modules: HashMap::new(),
+            entities: HashMap::new(),
+            scene: Vec::new(),
+            sprites: SpritePool::default(),
+        }
+    }
+}
+
+impl ScriptEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run a script for the whole scene, after the ones already added
+    pub fn add_scene_script(&mut self, source: Handle<ScriptSource>) -> Result<(), String> {
+        let _scope = memory::scope(MemoryTag::Scripting);
+
+        let instance = self.instantiate(source, None)?;
+        self.scene.push(instance);
+        Ok(())
+    }
+
+    pub fn clear_scene_scripts(&mut self) {
+        self.scene.clear();
+    }
+
+    /// Run a piece of Lua code with the engine API, returns its results as text
+    pub fn eval(&mut self, code: &str, ctx: &mut Context) -> Result<String, String> {
+        let _scope = memory::scope(MemoryTag::Scripting);
+
+        let lua = &self.lua;
+        let ctx = RefCell::new(ctx);
+        let sprites = RefCell::new(&mut self.sprites);
+
+        lua.scope(|scope| {
+            register_api(lua, scope, &ctx, &sprites)?;
+
+            let values: MultiValue = lua.load(code).set_name("eval")?.eval()?;
+            let tostring: Function = lua.globals().get("tostring")?;
+            let texts = values
+                .into_iter()
+                .map(|value| tostring.call::<_, String>(value))
+                .collect::<mlua::Result<Vec<String>>>()?;
+
+            Ok(texts.join("\t"))
+        })
+        .map_err(|e| e.to_string())
+    }
+
+    /// Module table of a script, with the instances looking their callbacks up in it
+    fn compile_module(&self, source: &ScriptSource, path: &Path) -> Result<RegistryKey, String> {
+        let name = path.display().to_string();
+        let module: Table = self
+            .lua
+            .load(&source.code)
+            .set_name(&name)
+            .and_then(|chunk| chunk.eval())
+            .map_err(|e| format!("Couldn't load script '{}': {}", name, e))?;
+        module
+            .set("__index", module.clone())
+            .map_err(|e| e.to_string())?;
+
+        self.lua
+            .create_registry_value(module)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Load the module table of a script the first time it's used
+    fn load_module(&mut self, source: &Handle<ScriptSource>) -> Result<(), String> {
+        let path = source.path();
+        if self.modules.contains_key(path) {
+            return Ok(());
+        }
+
+        let key = self.compile_module(source, path)?;
+        self.modules.insert(path.to_path_buf(), key);
+        Ok(())
+    }
+
+    /// Load again the scripts changed on disk (see `hot_reload`) and re-bind their instances to
+    /// the new callbacks. The instances keep their fields and aren't initialized again, the
+    /// ones stopped by an error run again. A script failing to load keeps its old version
+    fn reload_modules(&mut self) {
+        let mut stale: Vec<Handle<ScriptSource>> = Vec::new();
+        for instance in self.scene.iter_mut().chain(self.entities.values_mut()) {
+            if instance.source.refresh() && !stale.iter().any(|s| s.ptr_eq(&instance.source)) {
+                stale.push(instance.source.clone());
+            }
+        }
+
+        for source in stale {
+            let path = source.path();
+            let key = match self.compile_module(&source, path) {
+                Ok(key) => key,
+                Err(e) => {
+                    crash::log(&e);
+                    continue;
+                }
+            };
+            self.modules.insert(path.to_path_buf(), key);
+
+            let lua = &self.lua;
+            let module = &self.modules[path];
+            for instance in self.scene.iter_mut().chain(self.entities.values_mut()) {
+                if !instance.source.ptr_eq(&source) {
+                    continue;
+                }
+
+                let result = lua.registry_value::<Table>(module).and_then(|module| {
+                    let table: Table = lua.registry_value(&instance.table)?;
+                    table.set_metatable(Some(module));
+                    Ok(())
+                });
+                match result {
+                    Ok(()) => instance.failed = false,
+                    Err(e) => crash::log(&format!(
+                        "Couldn't reload script '{}': {}",
+                        path.display(),
+                        e
+                    )),
+                }
+            }
+        }
+
+        // Free the tables of the replaced modules
+        self.lua.expire_registry_values();
+    }
+
+    fn instantiate(
+        &mut self,
+        mut source: Handle<ScriptSource>,
+        entity: Option<Entity>,
+    ) -> Result<Instance, String> {
+        // The module follows the latest version, the instance starts bound to it
+        source.refresh();
+        self.load_module(&source)?;
+
+        let lua = &self.lua;
+        let table = lua
+            .registry_value::<Table>(&self.modules[source.path()])
+            .and_then(|module| {
+                let table = lua.create_table()?;
+                table.set_metatable(Some(module));
+                table.set("entity", entity.map(entity_id))?;
+                lua.create_registry_value(table)
+            })
+            .map_err(|e| e.to_string())?;
+
+        Ok(Instance {
+            source,
+            table,
+            initialized: false,
+            failed: false,
+        })
+    }
+
+    /// Follow the `Script` components added, replaced and removed since the last update
+    fn sync_entities(&mut self, ctx: &mut Context) {
+        let scripts: Vec<(Entity, Handle<ScriptSource>)> = match ctx.world.borrow::<Script>() {
+            Some(scripts) => scripts
+                .iter()
+                .map(|(entity, script)| (entity, script.source.clone()))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let current: HashMap<Entity, Handle<ScriptSource>> = scripts.into_iter().collect();
+        self.entities
+            .retain(|entity, instance| match current.get(entity) {
+                Some(source) => source.ptr_eq(&instance.source),
+                None => false,
+            });
+
+        for (entity, source) in current {
+            if self.entities.contains_key(&entity) {
+                continue;
+            }
+
+            match self.instantiate(source, Some(entity)) {
+                Ok(instance) => {
+                    self.entities.insert(entity, instance);
+                }
+                Err(e) => {
+                    // Not retried every frame, the component has to be added again
+                    crash::log(&e);
+                    ctx.world.remove::<Script>(entity);
+                }
+            }
+        }
+    }
+}
+
+impl System for ScriptEngine {
+    fn run(&mut self, time: &Time, ctx: &mut Context) {
+        let _scope = memory::scope(MemoryTag::Scripting);
+
+        self.sprites.begin_frame();
+        self.reload_modules();
+        self.sync_entities(ctx);
+
+        let lua = &self.lua;
+        let scene = &mut self.scene;
+        let entities = &mut self.entities;
+        let ctx = RefCell::new(ctx);
+        let sprites = RefCell::new(&mut self.sprites);
+        let delta = time.delta();
+
+        let result = lua.scope(|scope| {
+            register_api(lua, scope, &ctx, &sprites)?;
+
+            for instance in scene.iter_mut().chain(entities.values_mut()) {
+                update_instance(lua, instance, delta);
+            }
+            Ok(())
+        });
+
+        if let Err(e) = result {
+            crash::log(&format!("Couldn't run the scripts: {}", e));
+        }
+    }
+}
+
+/// Call `init` the first time, then `update`
+fn update_instance(lua: &Lua, instance: &mut Instance, delta: f32) {
+    if instance.failed {
+        return;
+    }
+
+    let result = lua
+        .registry_value::<Table>(&instance.table)
+        .and_then(|table| {
+            if !instance.initialized {
+                instance.initialized = true;
+                if let Some(init) = table.get::<_, Option<Function>>("init")? {
+                    init.call::<_, ()>(table.clone())?;
+                }
+            }
+
+            match table.get::<_, Option<Function>>("update")? {
+                Some(update) => update.call::<_, ()>((table, delta)),
+                None => Ok(()),
+            }
+        });
+
+    if let Err(e) = result {
+        crash::log(&format!(
+            "Script '{}' stopped: {}",
+            instance.source.path().display(),
+            e
+        ));
+        instance.failed = true;
+    }
+}
+
+fn entity_id(entity: Entity) -> i64 {
+    entity.to_bits() as i64
+}
+
+fn entity(id: i64) -> Entity {
+    Entity::from_bits(id as u64)
+}
+
+fn runtime_error(message: String) -> mlua::Error {
+    mlua::Error::RuntimeError(message)
+}
+
+/// Fill the global `engine` table, valid until the end of the scope
+fn register_api<'lua, 'scope, 'ctx>(
+    lua: &'lua Lua,
+    scope: &Scope<'lua, 'scope>,
+    ctx: &'scope RefCell<&'ctx mut Context>,
+    sprites: &'scope RefCell<&'ctx mut SpritePool>,
+) -> mlua::Result<()>
+where
+    'ctx: 'scope,
+{
+    let api = lua.create_table()?;
+
+    api.set(
+        "spawn",
+        scope.create_function(move |_, ()| Ok(entity_id(ctx.borrow_mut().world.spawn())))?,
+    )?;
+    api.set(
+        "despawn",
+        scope.create_function(move |_, id: i64| Ok(ctx.borrow_mut().world.despawn(entity(id))))?,
+    )?;
+    api.set(
+        "is_alive",
+        scope.create_function(move |_, id: i64| Ok(ctx.borrow().world.is_alive(entity(id))))?,
+    )?;
+    api.set(
+        "position",
+        scope.create_function(move |_, id: i64| {
+            let ctx = ctx.borrow();
+            let position = ctx
+                .world
+                .get::<Transform>(entity(id))
+                .map(|transform| transform.translation);
+            Ok((position.map(|p| p.x), position.map(|p| p.y)))
+        })?,
+    )?;
+    api.set(
+        "set_position",
+        scope.create_function(move |_, (id, x, y): (i64, f32, f32)| {
+            let mut ctx = ctx.borrow_mut();
+            let world = &mut ctx.world;
+            match world.get_mut::<Transform>(entity(id)) {
+                Some(transform) => transform.translation = Vec2::new(x, y),
+                None => {
+                    world.insert(entity(id), Transform::new(Vec2::new(x, y)));
+                }
+            }
+            Ok(())
+        })?,
+    )?;
+    api.set(
+        "play_sound",
+        scope.create_function(move |_, path: String| {
+            let mut ctx = ctx.borrow_mut();
+            let sound = ctx.load_sound(Path::new(&path)).map_err(runtime_error)?;
+            ctx.audio.sfx_play(&sound);
+            Ok(())
+        })?,
+    )?;
+    api.set(
+        "draw_sprite",
+        scope.create_function(move |_, (path, x, y, z): (String, f32, f32, Option<u8>)| {
+            sprites
+                .borrow_mut()
+                .draw(
+                    &mut ctx.borrow_mut(),
+                    Path::new(&path),
+                    Vec2::new(x, y),
+                    z.unwrap_or(0),
+                )
+                .map_err(runtime_error)
+        })?,
+    )?;
+    api.set(
+        "key_down",
+        scope.create_function(move |_, name: String| {
+            let key = Keycode::from_name(&name)
+                .ok_or_else(|| runtime_error(format!("Unknown key '{}'", name)))?;
+            Ok(ctx.borrow().is_key_down(key))
+        })?,
+    )?;
+    api.set(
+        "action_down",
+        scope.create_function(move |_, action: String| Ok(ctx.borrow().is_action_down(&action)))?,
+    )?;
+    api.set(
+        "log",
+        scope.create_function(|_, values: MultiValue| {
+            let texts: Vec<String> = values
+                .into_iter()
+                .map(|value| match value {
+                    Value::String(text) => text.to_string_lossy().into_owned(),
+                    Value::Nil => "nil".to_string(),
+                    Value::Boolean(value) => value.to_string(),
+                    Value::Integer(value) => value.to_string(),
+                    Value::Number(value) => value.to_string(),
+                    other => format!("<{}>", other.type_name()),
+                })
+                .collect();
+            crash::log(&texts.join("\t"));
+            Ok(())
+        })?,
+    )?;
+
+    lua.globals().set("engine", api)
+}
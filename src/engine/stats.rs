@@ -0,0 +1,236 @@
+//! Frame statistics collected by the engine: frame times over the last frames and renderer counters
+
+// standard imports
+use std::collections::VecDeque;
+use std::fmt;
+
+// import the ctx module
+use super::ctx::vulkan::RenderCounters;
+use super::memory;
+
+/// Frames kept to compute the statistics
+pub const FRAME_STATS_WINDOW: usize = 240;
+
+/// Rolling statistics of the last frames, updated by the engine after every rendered frame
+#[derive(Clone, Debug)]
+pub struct FrameStats {
+    /// Frame times in seconds, the newest last
+    frame_times: VecDeque<f32>,
+    /// Frames kept, the oldest are forgotten
+    window: usize,
+    /// Seconds spent by the engine in the last frame, without waiting for the framerate limit
+    work_time: f32,
+    counters: RenderCounters,
+    frame_count: u64,
+    /// Allocations counted by the TrackingAllocator at the end of the last frame, and during it
+    allocation_total: usize,
+    frame_allocations: usize,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self::with_window(FRAME_STATS_WINDOW)
+    }
+
+    /// Statistics of the last `window` frames
+    pub fn with_window(window: usize) -> Self {
+        Self {
+            frame_times: VecDeque::with_capacity(window.min(FRAME_STATS_WINDOW)),
+            window: window.max(1),
+            work_time: 0.0,
+            counters: RenderCounters::default(),
+            frame_count: 0,
+            allocation_total: memory::allocation_count(),
+            frame_allocations: 0,
+        }
+    }
+
+    /// Add a rendered frame
+    pub(crate) fn record(&mut self, frame_time: f32, counters: RenderCounters) {
+        if self.frame_times.len() >= self.window {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+        self.counters = counters;
+        self.frame_count += 1;
+
+        let allocation_total = memory::allocation_count();
+        self.frame_allocations = allocation_total.saturating_sub(self.allocation_total);
+        self.allocation_total = allocation_total;
+    }
+
+    /// Time spent updating and rendering the last frame, measured by the engine
+    pub(crate) fn set_work_time(&mut self, seconds: f32) {
+        self.work_time = seconds;
+    }
+
+    /// Seconds spent by the engine in the last frame (updates, rendering and the wait for the
+    /// previous frame), without the wait for the framerate limit
+    pub fn work_time(&self) -> f32 {
+        self.work_time
+    }
+
+    /// Forget the collected frames, e.g. after loading a level
+    pub fn reset(&mut self) {
+        self.frame_times.clear();
+    }
+
+    /// Frames counted since the start
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Frame times in seconds of the last frames, the oldest first
+    pub fn frame_times(&self) -> impl Iterator<Item = f32> + '_ {
+        self.frame_times.iter().copied()
+    }
+
+    /// Average frame time in seconds
+    pub fn average(&self) -> f32 {
+        if self.frame_times.is_empty() {
+            0.0
+        } else {
+            self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32
+        }
+    }
+
+    pub fn min(&self) -> f32 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+
+        self.frame_times.iter().copied().fold(f32::INFINITY, f32::min)
+    }
+
+    pub fn max(&self) -> f32 {
+        self.frame_times.iter().copied().fold(0.0, f32::max)
+    }
+
+    /// Frames per second from the average frame time
+    pub fn fps(&self) -> f32 {
+        let average = self.average();
+
+        if average > 0.0 {
+            1.0 / average
+        } else {
+            0.0
+        }
+    }
+
+    /// Frame time below which `fraction` of the frames are (0.99 for the 99th percentile)
+    pub fn percentile(&self, fraction: f32) -> f32 {
+        let mut times: Vec<f32> = self.frame_times.iter().copied().collect();
+        if times.is_empty() {
+            return 0.0;
+        }
+        times.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let index = (fraction.max(0.0).min(1.0) * (times.len() - 1) as f32).round() as usize;
+        times[index]
+    }
+
+    /// Frames per second of the slowest 1% of the frames
+    pub fn one_percent_low(&self) -> f32 {
+        let mut times: Vec<f32> = self.frame_times.iter().copied().collect();
+        if times.is_empty() {
+            return 0.0;
+        }
+        times.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+
+        let slowest = &times[..(times.len() / 100).max(1)];
+        let average = slowest.iter().sum::<f32>() / slowest.len() as f32;
+
+        if average > 0.0 {
+            1.0 / average
+        } else {
+            0.0
+        }
+    }
+
+    /// Draw calls recorded for the last frame
+    pub fn draw_calls(&self) -> u32 {
+        self.counters.draw_calls
+    }
+
+    /// Vertices drawn in the last frame
+    pub fn vertices(&self) -> u32 {
+        self.counters.vertices
+    }
+
+    /// Batched draw calls recorded for the last frame
+    pub fn batches(&self) -> u32 {
+        self.counters.batches
+    }
+
+    /// Objects merged in the batches of the last frame
+    pub fn batched_objects(&self) -> u32 {
+        self.counters.batched_objects
+    }
+
+    /// Heap allocations made during the last frame, None without the TrackingAllocator (see `memory`).
+    /// A steady frame (nothing loaded or spawned) should allocate nothing in the engine itself
+    pub fn allocations(&self) -> Option<usize> {
+        if memory::is_tracking() {
+            Some(self.frame_allocations)
+        } else {
+            None
+        }
+    }
+
+    /// Visible objects recorded for the last frame
+    pub fn drawn_objects(&self) -> u32 {
+        self.counters.drawn_objects
+    }
+
+    /// Visible objects skipped in the last frame because they were outside of the view
+    pub fn culled_objects(&self) -> u32 {
+        self.counters.culled_objects
+    }
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Multiline summary, meant for debug overlays and logs
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "FPS: {:.0} (1% low {:.0})",
+            self.fps(),
+            self.one_percent_low()
+        )?;
+        writeln!(
+            f,
+            "Frame: {:.2}ms avg, {:.2}ms min, {:.2}ms max",
+            self.average() * 1000.0,
+            self.min() * 1000.0,
+            self.max() * 1000.0
+        )?;
+        writeln!(
+            f,
+            "Draw calls: {}, vertices: {}",
+            self.draw_calls(),
+            self.vertices()
+        )?;
+        writeln!(
+            f,
+            "Batches: {} ({} objects)",
+            self.batches(),
+            self.batched_objects()
+        )?;
+        write!(
+            f,
+            "Objects: {} drawn, {} culled",
+            self.drawn_objects(),
+            self.culled_objects()
+        )?;
+        match self.allocations() {
+            Some(allocations) => write!(f, "\nAllocations: {}", allocations),
+            None => Ok(()),
+        }
+    }
+}
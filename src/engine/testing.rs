@@ -0,0 +1,275 @@
+//! Automated UI testing: simulated input, deterministic frames and golden image checks
+//!
+//! Set the `MAGMA_UPDATE_GOLDEN` environment variable to write (or rewrite) the golden images
+//! instead of comparing against them.
+
+// standard imports
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+// SDL2 imports
+use sdl2::event::Event;
+use sdl2::keyboard::Mod;
+use sdl2::mouse::MouseState;
+
+pub use sdl2::keyboard::Keycode;
+pub use sdl2::mouse::MouseButton;
+
+// import the engine modules
+use super::ctx::{decode_png_rgba, encode_png_rgba};
+use super::game::Game;
+use super::main_engine::Engine;
+use super::replay::Replay;
+
+/// Delta time of every simulated frame, so runs are repeatable
+pub const TEST_FRAME_TIME: f32 = 1.0 / 60.0;
+
+/// Captured frame, RGBA8 pixels
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub pixels: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Image {
+    pub fn load(path: &Path) -> Result<Image, String> {
+        let file = File::open(path).map_err(|e| format!("Couldn't open '{}': {}", path.display(), e))?;
+        let (pixels, width, height) = decode_png_rgba(file)?;
+
+        Ok(Image {
+            pixels,
+            width,
+            height,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let file = File::create(path).map_err(|e| format!("Couldn't create '{}': {}", path.display(), e))?;
+
+        encode_png_rgba(BufWriter::new(file), &self.pixels, self.width, self.height)
+    }
+
+    /// Count the pixels with a channel differing by more than `tolerance`
+    pub fn count_differences(&self, other: &Image, tolerance: u8) -> Option<usize> {
+        if self.width != other.width || self.height != other.height {
+            return None;
+        }
+
+        let count = self
+            .pixels
+            .chunks_exact(4)
+            .zip(other.pixels.chunks_exact(4))
+            .filter(|(a, b)| a.iter().zip(b.iter()).any(|(x, y)| x.max(y) - x.min(y) > tolerance))
+            .count();
+
+        Some(count)
+    }
+}
+
+/// Drives a game frame by frame with simulated input
+pub struct UiTestHarness<G: Game> {
+    pub engine: Engine,
+    pub game: G,
+    /// Largest difference of a channel still considered equal in the golden checks
+    pub tolerance: u8,
+    mouse_position: (i32, i32),
+}
+
+impl<G: Game> UiTestHarness<G> {
+    /// Start the game, calling its `init`
+    pub fn new(mut engine: Engine, mut game: G) -> Self {
+        game.init(engine.get_context());
+        // Every frame can be captured
+        if let Some(video) = engine.get_context().try_video() {
+            video.keep_frames = true;
+        }
+
+        Self {
+            engine,
+            game,
+            tolerance: 2,
+            mouse_position: (0, 0),
+        }
+    }
+
+    /// Run the given amount of frames, returns false if the game stopped
+    pub fn advance(&mut self, frames: u32) -> bool {
+        (0..frames).all(|_| self.engine.step(&mut self.game, TEST_FRAME_TIME))
+    }
+
+    fn send(&mut self, event: Event) {
+        self.engine
+            .get_context()
+            .simulate_event(event)
+            .expect("Couldn't simulate input event");
+    }
+
+    pub fn key_down(&mut self, key: Keycode) {
+        let window_id = self.engine.get_context().main_window_id();
+        self.send(Event::KeyDown {
+            timestamp: 0,
+            window_id,
+            keycode: Some(key),
+            scancode: None,
+            keymod: Mod::NOMOD,
+            repeat: false,
+        });
+    }
+
+    pub fn key_up(&mut self, key: Keycode) {
+        let window_id = self.engine.get_context().main_window_id();
+        self.send(Event::KeyUp {
+            timestamp: 0,
+            window_id,
+            keycode: Some(key),
+            scancode: None,
+            keymod: Mod::NOMOD,
+            repeat: false,
+        });
+    }
+
+    /// Press and release a key, a frame apart
+    pub fn press_key(&mut self, key: Keycode) {
+        self.key_down(key);
+        self.advance(1);
+        self.key_up(key);
+        self.advance(1);
+    }
+
+    pub fn mouse_move(&mut self, x: i32, y: i32) {
+        let window_id = self.engine.get_context().main_window_id();
+        let (old_x, old_y) = self.mouse_position;
+        self.mouse_position = (x, y);
+
+        self.send(Event::MouseMotion {
+            timestamp: 0,
+            window_id,
+            which: 0,
+            mousestate: MouseState::from_sdl_state(0),
+            x,
+            y,
+            xrel: x - old_x,
+            yrel: y - old_y,
+        });
+    }
+
+    /// Move the mouse and click with the left button, a frame apart
+    pub fn click(&mut self, x: i32, y: i32) {
+        let window_id = self.engine.get_context().main_window_id();
+
+        self.mouse_move(x, y);
+        self.send(Event::MouseButtonDown {
+            timestamp: 0,
+            window_id,
+            which: 0,
+            mouse_btn: MouseButton::Left,
+            clicks: 1,
+            x,
+            y,
+        });
+        self.advance(1);
+        self.send(Event::MouseButtonUp {
+            timestamp: 0,
+            window_id,
+            which: 0,
+            mouse_btn: MouseButton::Left,
+            clicks: 1,
+            x,
+            y,
+        });
+        self.advance(1);
+    }
+
+    /// Play a recorded session until its last frame, returns false if the game stopped
+    pub fn run_replay(&mut self, replay: Replay) -> bool {
+        self.engine.play_replay(replay);
+
+        while self.engine.is_replaying() {
+            if !self.advance(1) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Check the state of the game, returning the message as error if it doesn't hold
+    pub fn check<F: FnOnce(&G) -> bool>(&self, message: &str, condition: F) -> Result<(), String> {
+        if condition(&self.game) {
+            Ok(())
+        } else {
+            Err(message.to_string())
+        }
+    }
+
+    /// Capture the last rendered frame
+    pub fn capture(&mut self) -> Result<Image, String> {
+        let (pixels, width, height) = self
+            .engine
+            .get_context()
+            .try_video()
+            .ok_or("Can't capture frames in headless mode")?
+            .gl_handler
+            .capture_last_frame()
+            .map_err(|e| e.to_string())?
+            .ok_or("No frame was drawn yet")?;
+
+        Ok(Image {
+            pixels,
+            width,
+            height,
+        })
+    }
+
+    /// Compare the last rendered frame against a golden image, missing ones are an error unless
+    /// `MAGMA_UPDATE_GOLDEN` is set. On mismatch the capture is saved next to it with the
+    /// `.actual.png` extension
+    pub fn check_golden(&mut self, golden_path: &Path) -> Result<(), String> {
+        let actual = self.capture()?;
+
+        if env::var_os("MAGMA_UPDATE_GOLDEN").is_some() {
+            return actual.save(golden_path);
+        }
+        if !golden_path.exists() {
+            return Err(format!(
+                "Missing golden image '{}', set MAGMA_UPDATE_GOLDEN to create it",
+                golden_path.display()
+            ));
+        }
+
+        let golden = Image::load(golden_path)?;
+        let result = match actual.count_differences(&golden, self.tolerance) {
+            Some(0) => return Ok(()),
+            Some(count) => Err(format!(
+                "{} pixels differ from golden image '{}'",
+                count,
+                golden_path.display()
+            )),
+            None => Err(format!(
+                "Frame is {}x{}, golden image '{}' is {}x{}",
+                actual.width,
+                actual.height,
+                golden_path.display(),
+                golden.width,
+                golden.height
+            )),
+        };
+
+        actual.save(&golden_path.with_extension("actual.png"))?;
+
+        result
+    }
+
+    /// Stop the game, calling its `shutdown`
+    pub fn finish(mut self) -> G {
+        self.game.shutdown(self.engine.get_context());
+
+        self.game
+    }
+}
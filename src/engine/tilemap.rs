@@ -0,0 +1,1119 @@
+//! Tile maps made with Tiled (https://www.mapeditor.org), loaded from `.tmx` files
+//!
+//! Orthogonal maps are supported with their tile layers, object layers, tilesets (embedded or
+//! `.tsx`), properties and animated tiles. Layer data can be XML, CSV or uncompressed base64.
+//! Tile layers are drawn through the sprite batch, only the tiles seen by the camera.
+
+// standard imports
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// vulkan imports
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+
+// import the engine modules
+use super::color::Color;
+use super::ctx::draw_objects::{
+    BatchQuad, Draw, DrawFlags, DrawState, GraphicObject, WORLD_UNITS_PER_PIXEL,
+};
+use super::ctx::vulkan::{DeviceRecovery, FrameError, GraphicsHandler, Texture};
+use super::ctx::VideoHandler;
+use super::geometry::{Aabb, Rect};
+use super::leaks::{ResourceKind, ResourceTracker};
+use super::math::{Vec2, Vec4};
+use super::pack;
+
+// other imports
+use roxmltree::{Document, Node};
+
+/// Bits of a global tile id flipping the tile
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+/// Hexagonal maps rotation bit, unused by orthogonal maps but cleared from the ids
+const ROTATED_HEXAGONAL: u32 = 0x1000_0000;
+
+/// Custom property of a map, layer, object or tile
+#[derive(Clone, Debug, PartialEq)]
+pub enum Property {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Color(Color),
+    /// Strings, files and multiline texts
+    String(String),
+}
+
+impl Property {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Property::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Property::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Value of float and int properties
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Property::Float(value) => Some(*value),
+            Property::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Property::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+pub type Properties = HashMap<String, Property>;
+
+/// Tile placed in a layer, with its flips
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    /// Global id, unique across the tilesets of the map
+    pub gid: u32,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    /// Swap of the X and Y axes, combined with the other flips to rotate tiles by 90 degrees
+    pub flip_diagonal: bool,
+}
+
+impl Tile {
+    /// Tile from a global id with its flip bits, None for empty cells
+    fn from_raw(raw: u32) -> Option<Tile> {
+        let gid = raw
+            & !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL);
+
+        if gid == 0 {
+            return None;
+        }
+
+        Some(Tile {
+            gid,
+            flip_horizontal: raw & FLIPPED_HORIZONTALLY != 0,
+            flip_vertical: raw & FLIPPED_VERTICALLY != 0,
+            flip_diagonal: raw & FLIPPED_DIAGONALLY != 0,
+        })
+    }
+}
+
+/// Frame of an animated tile
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnimationFrame {
+    /// Id of the tile shown, local to the tileset
+    pub tile_id: u32,
+    pub duration: Duration,
+}
+
+/// Tile of a tileset with custom data
+#[derive(Clone, Debug, Default)]
+pub struct TileData {
+    /// Type (or class) given in Tiled
+    pub kind: String,
+    pub properties: Properties,
+    /// Empty if the tile isn't animated
+    pub animation: Vec<AnimationFrame>,
+}
+
+/// Image split in tiles
+#[derive(Clone, Debug)]
+pub struct Tileset {
+    /// Global id of the first tile
+    pub first_gid: u32,
+    pub name: String,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub spacing: u32,
+    pub margin: u32,
+    pub columns: u32,
+    pub tile_count: u32,
+    /// Path of the image, ready to be loaded
+    pub image: PathBuf,
+    pub image_width: u32,
+    pub image_height: u32,
+    /// Tiles with properties or animations, by local id
+    pub tiles: HashMap<u32, TileData>,
+}
+
+impl Tileset {
+    pub fn contains(&self, gid: u32) -> bool {
+        gid >= self.first_gid && gid < self.first_gid + self.tile_count
+    }
+
+    /// Region of a tile in the image, in texture coordinates
+    fn tex_region(&self, tile_id: u32) -> ([f32; 2], [f32; 2]) {
+        let columns = self.columns.max(1);
+        let x = self.margin + (tile_id % columns) * (self.tile_width + self.spacing);
+        let y = self.margin + (tile_id / columns) * (self.tile_height + self.spacing);
+
+        let (width, height) = (
+            self.image_width.max(1) as f32,
+            self.image_height.max(1) as f32,
+        );
+        (
+            [x as f32 / width, y as f32 / height],
+            [
+                (x + self.tile_width) as f32 / width,
+                (y + self.tile_height) as f32 / height,
+            ],
+        )
+    }
+}
+
+/// Grid of tiles
+#[derive(Clone, Debug)]
+pub struct TileLayer {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub visible: bool,
+    pub opacity: f32,
+    /// Offset in pixels, groups included
+    pub offset: Vec2,
+    pub properties: Properties,
+    /// Row by row, None for empty cells
+    tiles: Vec<Option<Tile>>,
+}
+
+impl TileLayer {
+    /// Tile of a cell, None if empty or outside of the layer
+    pub fn tile(&self, x: i32, y: i32) -> Option<Tile> {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return None;
+        }
+
+        self.tiles[(y as u32 * self.width + x as u32) as usize]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ObjectShape {
+    Rectangle,
+    Ellipse,
+    Point,
+    /// Points relative to the object position
+    Polygon(Vec<Vec2>),
+    Polyline(Vec<Vec2>),
+}
+
+/// Object placed in an object layer (spawn points, triggers, collision shapes...)
+#[derive(Clone, Debug)]
+pub struct MapObject {
+    pub id: u32,
+    pub name: String,
+    /// Type (or class) given in Tiled
+    pub kind: String,
+    /// Top-left corner in pixels (bottom-left for tile objects), layer offset included
+    pub position: Vec2,
+    pub size: Vec2,
+    /// Clockwise, in degrees
+    pub rotation: f32,
+    pub shape: ObjectShape,
+    /// Tile shown by tile objects
+    pub tile: Option<Tile>,
+    pub visible: bool,
+    pub properties: Properties,
+}
+
+/// Layer of objects
+#[derive(Clone, Debug)]
+pub struct ObjectLayer {
+    pub name: String,
+    pub visible: bool,
+    pub objects: Vec<MapObject>,
+    pub properties: Properties,
+}
+
+impl ObjectLayer {
+    pub fn object(&self, name: &str) -> Option<&MapObject> {
+        self.objects.iter().find(|object| object.name == name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Layer {
+    Tiles(TileLayer),
+    Objects(ObjectLayer),
+}
+
+impl Layer {
+    pub fn name(&self) -> &str {
+        match self {
+            Layer::Tiles(layer) => &layer.name,
+            Layer::Objects(layer) => &layer.name,
+        }
+    }
+}
+
+/// Orthogonal map loaded from Tiled, group layers are flattened
+#[derive(Clone, Debug)]
+pub struct TileMap {
+    /// Size in tiles
+    pub width: u32,
+    pub height: u32,
+    /// Size of the grid cells, in pixels
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub background: Option<Color>,
+    pub properties: Properties,
+    /// Sorted by first global id
+    pub tilesets: Vec<Tileset>,
+    /// From the bottom one to the top one
+    pub layers: Vec<Layer>,
+}
+
+impl TileMap {
+    /// Load a `.tmx` map, tilesets and images are relative to its directory
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = pack::read_to_string(path)
+            .map_err(|e| format!("Couldn't read map '{}': {}", path.display(), e))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        Self::parse(&source, base_dir)
+            .map_err(|e| format!("Couldn't load map '{}': {}", path.display(), e))
+    }
+
+    /// Parse the content of a `.tmx` file, tilesets and images are relative to `base_dir`
+    pub fn parse(source: &str, base_dir: &Path) -> Result<Self, String> {
+        let document = Document::parse(source).map_err(|e| e.to_string())?;
+        let root = document.root_element();
+        if root.tag_name().name() != "map" {
+            return Err("Not a Tiled map".to_string());
+        }
+
+        let orientation = root.attribute("orientation").unwrap_or("orthogonal");
+        if orientation != "orthogonal" {
+            return Err(format!("{} maps aren't supported", orientation));
+        }
+        if root.attribute("infinite") == Some("1") {
+            return Err("Infinite maps aren't supported".to_string());
+        }
+
+        let mut map = TileMap {
+            width: attr(root, "width")?,
+            height: attr(root, "height")?,
+            tile_width: attr(root, "tilewidth")?,
+            tile_height: attr(root, "tileheight")?,
+            background: root
+                .attribute("backgroundcolor")
+                .map(parse_color)
+                .transpose()?,
+            properties: parse_properties(root)?,
+            tilesets: Vec::new(),
+            layers: Vec::new(),
+        };
+
+        for tileset in root.children().filter(|n| n.has_tag_name("tileset")) {
+            map.tilesets.push(parse_tileset(tileset, base_dir)?);
+        }
+        map.tilesets.sort_by_key(|tileset| tileset.first_gid);
+
+        parse_layers(root, Vec2::new(0.0, 0.0), true, 1.0, &mut map.layers)?;
+
+        Ok(map)
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.name() == name)
+    }
+
+    pub fn tile_layer(&self, name: &str) -> Option<&TileLayer> {
+        self.layers.iter().find_map(|layer| match layer {
+            Layer::Tiles(layer) if layer.name == name => Some(layer),
+            _ => None,
+        })
+    }
+
+    pub fn object_layer(&self, name: &str) -> Option<&ObjectLayer> {
+        self.layers.iter().find_map(|layer| match layer {
+            Layer::Objects(layer) if layer.name == name => Some(layer),
+            _ => None,
+        })
+    }
+
+    /// Tileset a global id belongs to
+    pub fn tileset_of(&self, gid: u32) -> Option<&Tileset> {
+        self.tilesets
+            .iter()
+            .rev()
+            .find(|tileset| tileset.contains(gid))
+    }
+
+    /// Custom data of a tile, None if it has none
+    pub fn tile_data(&self, gid: u32) -> Option<&TileData> {
+        let tileset = self.tileset_of(gid)?;
+        tileset.tiles.get(&(gid - tileset.first_gid))
+    }
+
+    /// Properties of the tile in a cell of a layer
+    pub fn tile_properties(&self, layer: &str, x: i32, y: i32) -> Option<&Properties> {
+        let tile = self.tile_layer(layer)?.tile(x, y)?;
+        self.tile_data(tile.gid).map(|data| &data.properties)
+    }
+
+    /// Property of the tile in a cell of a layer (e.g. `solid` for collisions)
+    pub fn tile_property(&self, layer: &str, x: i32, y: i32, name: &str) -> Option<&Property> {
+        self.tile_properties(layer, x, y)?.get(name)
+    }
+
+    /// Cells of a layer whose tile has the property, unless it's set to false
+    pub fn tiles_with_property(&self, layer: &str, name: &str) -> Vec<(u32, u32)> {
+        let layer = match self.tile_layer(layer) {
+            Some(layer) => layer,
+            None => return Vec::new(),
+        };
+
+        let mut cells = Vec::new();
+        for y in 0..layer.height {
+            for x in 0..layer.width {
+                let property = layer
+                    .tile(x as i32, y as i32)
+                    .and_then(|tile| self.tile_data(tile.gid))
+                    .and_then(|data| data.properties.get(name));
+
+                match property {
+                    None | Some(Property::Bool(false)) => {}
+                    Some(_) => cells.push((x, y)),
+                }
+            }
+        }
+        cells
+    }
+
+    /// Area of a cell, in map pixels
+    pub fn tile_rect(&self, x: i32, y: i32) -> Rect {
+        Rect::new(
+            (x * self.tile_width as i32) as f32,
+            (y * self.tile_height as i32) as f32,
+            self.tile_width as f32,
+            self.tile_height as f32,
+        )
+    }
+
+    /// Cell holding a point in map pixels
+    pub fn pixel_to_tile(&self, point: Vec2) -> (i32, i32) {
+        (
+            (point.x / self.tile_width as f32).floor() as i32,
+            (point.y / self.tile_height as f32).floor() as i32,
+        )
+    }
+
+    /// Create the draw objects of the tile layers, the map top-left corner at the world origin
+    pub fn instantiate(
+        &self,
+        video: &mut VideoHandler,
+        z_index: u8,
+    ) -> Result<TileMapObject, String> {
+        let mut tilesets = Vec::with_capacity(self.tilesets.len());
+        for tileset in &self.tilesets {
+            let image = tileset.image.to_string_lossy();
+            let (texture, dimensions) = video.gl_handler.load_texture(&image)?;
+
+            let mut tileset = tileset.clone();
+            tileset.image_width = dimensions.x;
+            tileset.image_height = dimensions.y;
+            tilesets.push((tileset, texture));
+        }
+        let tilesets = Arc::new(tilesets);
+
+        let mut layers = Vec::new();
+        for layer in &self.layers {
+            let layer = match layer {
+                Layer::Tiles(layer) => layer,
+                Layer::Objects(_) => continue,
+            };
+
+            let draw = TileLayerDraw::new(self, layer, tilesets.clone(), z_index);
+            layers.push((layer.name.clone(), video.gl_handler.add_draw_object(draw)));
+        }
+
+        Ok(TileMapObject {
+            layers,
+            tile_size: Vec2::new(self.tile_width as f32, self.tile_height as f32),
+            position: Vec2::new(0.0, 0.0),
+            scale: Vec2::new(1.0, 1.0),
+        })
+    }
+}
+
+pub type TileLayerObject = GraphicObject<TileLayerDraw>;
+
+/// Tile layers of a map on screen
+pub struct TileMapObject {
+    layers: Vec<(String, TileLayerObject)>,
+    /// Size of the grid cells, in pixels
+    tile_size: Vec2,
+    position: Vec2,
+    scale: Vec2,
+}
+
+impl TileMapObject {
+    pub fn layer(&self, name: &str) -> Option<&TileLayerObject> {
+        self.layers
+            .iter()
+            .find(|(layer, _)| layer == name)
+            .map(|(_, object)| object)
+    }
+
+    /// Move the map top-left corner, in world units
+    pub fn set_position(&mut self, position: Vec2) {
+        self.position = position;
+        self.refresh_layers();
+    }
+
+    pub fn get_position(&self) -> Vec2 {
+        self.position
+    }
+
+    pub fn set_scale(&mut self, scale: Vec2) {
+        self.scale = scale;
+        self.refresh_layers();
+    }
+
+    pub fn get_scale(&self) -> Vec2 {
+        self.scale
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        for (_, layer) in &self.layers {
+            layer.get_mut().set_visible(visible);
+        }
+    }
+
+    /// Play the animated tiles for `delta` seconds.
+    /// Animations stay frozen while the renderer reuses its Command Buffers
+    pub fn advance(&mut self, delta: f32) {
+        for (_, layer) in &self.layers {
+            layer.get_mut().animation_time += delta;
+        }
+    }
+
+    /// World position of a point in map pixels
+    pub fn map_to_world(&self, point: Vec2) -> Vec2 {
+        self.position
+            + Vec2::new(point.x * self.scale.x, point.y * self.scale.y) * WORLD_UNITS_PER_PIXEL
+    }
+
+    /// Point in map pixels at a world position
+    pub fn world_to_map(&self, point: Vec2) -> Vec2 {
+        let offset = (point - self.position) / WORLD_UNITS_PER_PIXEL;
+        Vec2::new(offset.x / self.scale.x, offset.y / self.scale.y)
+    }
+
+    /// Cell at a world position (e.g. under the mouse, through `screen_to_world`)
+    pub fn world_to_tile(&self, point: Vec2) -> (i32, i32) {
+        let point = self.world_to_map(point);
+        (
+            (point.x / self.tile_size.x).floor() as i32,
+            (point.y / self.tile_size.y).floor() as i32,
+        )
+    }
+
+    /// World area of a cell, for collisions with the tiles
+    pub fn tile_bounds(&self, x: i32, y: i32) -> Aabb {
+        let corner = Vec2::new(x as f32 * self.tile_size.x, y as f32 * self.tile_size.y);
+
+        Aabb::new(
+            self.map_to_world(corner),
+            self.map_to_world(corner + self.tile_size),
+        )
+    }
+
+    fn refresh_layers(&mut self) {
+        for (_, layer) in &self.layers {
+            let mut layer = layer.get_mut();
+            layer.global_position = self.position
+                + Vec2::new(layer.offset.x * self.scale.x, layer.offset.y * self.scale.y)
+                    * WORLD_UNITS_PER_PIXEL;
+            layer.scale = self.scale;
+        }
+    }
+}
+
+/// Tile layer drawn as batched quads
+pub struct TileLayerDraw {
+    width: u32,
+    height: u32,
+    /// Tileset index and tile, row by row
+    tiles: Vec<Option<(usize, Tile)>>,
+    tilesets: Arc<Vec<(Tileset, Texture)>>,
+    /// Size of the grid cells, in pixels
+    cell_size: Vec2,
+    /// Offset of the layer, in pixels
+    offset: Vec2,
+    /// Largest tile of the tilesets in cells, bigger tiles overflow their cell
+    max_tile_cells: (i32, i32),
+    animation_time: f32,
+
+    z_index: u8,
+    draw_flags: DrawFlags,
+
+    pub color: Vec4,
+    /// Top-left corner of the layer in world units
+    pub global_position: Vec2,
+    pub scale: Vec2,
+
+    _tracker: ResourceTracker,
+}
+
+impl TileLayerDraw {
+    fn new(
+        map: &TileMap,
+        layer: &TileLayer,
+        tilesets: Arc<Vec<(Tileset, Texture)>>,
+        z_index: u8,
+    ) -> Self {
+        let tiles = layer
+            .tiles
+            .iter()
+            .map(|tile| {
+                let tile = (*tile)?;
+                let index = tilesets
+                    .iter()
+                    .rposition(|(tileset, _)| tileset.contains(tile.gid))?;
+                Some((index, tile))
+            })
+            .collect();
+
+        let cell_size = Vec2::new(map.tile_width as f32, map.tile_height as f32);
+        let max_tile_cells = tilesets.iter().fold((1, 1), |(x, y), (tileset, _)| {
+            (
+                x.max((tileset.tile_width as f32 / cell_size.x).ceil() as i32),
+                y.max((tileset.tile_height as f32 / cell_size.y).ceil() as i32),
+            )
+        });
+
+        let mut draw_flags = DrawFlags::USED;
+        draw_flags.set(DrawFlags::VISIBLE, layer.visible);
+
+        Self {
+            width: layer.width,
+            height: layer.height,
+            tiles,
+            tilesets,
+            cell_size,
+            offset: layer.offset,
+            max_tile_cells,
+            animation_time: 0.0,
+            z_index,
+            draw_flags,
+            color: Color::WHITE.with_alpha(layer.opacity).into(),
+            global_position: layer.offset * WORLD_UNITS_PER_PIXEL,
+            scale: Vec2::new(1.0, 1.0),
+            _tracker: ResourceTracker::new(
+                ResourceKind::DrawObject,
+                &format!("Tile layer '{}'", layer.name),
+            ),
+        }
+    }
+
+    /// Tile shown at the current animation time
+    fn animated_tile_id(&self, tileset: &Tileset, tile_id: u32) -> u32 {
+        let frames = match tileset.tiles.get(&tile_id) {
+            Some(data) if !data.animation.is_empty() => &data.animation,
+            _ => return tile_id,
+        };
+
+        let total: f32 = frames.iter().map(|f| f.duration.as_secs_f32()).sum();
+        if total <= 0.0 {
+            return tile_id;
+        }
+
+        let mut time = self.animation_time.rem_euclid(total);
+        for frame in frames {
+            time -= frame.duration.as_secs_f32();
+            if time < 0.0 {
+                return frame.tile_id;
+            }
+        }
+        frames[frames.len() - 1].tile_id
+    }
+
+    /// Quads of the tiles intersecting `view`, every tile without a view
+    fn push_quads(&self, view: Option<&Aabb>, quads: &mut Vec<BatchQuad>) {
+        let pixel = Vec2::new(self.scale.x, self.scale.y) * WORLD_UNITS_PER_PIXEL;
+        let cell = Vec2::new(self.cell_size.x * pixel.x, self.cell_size.y * pixel.y);
+
+        // Cells seen, with a margin for the tiles overflowing their cell up and right
+        let (mut x_range, mut y_range) = ((0, self.width as i32), (0, self.height as i32));
+        if let Some(view) = view {
+            let min = view.min - self.global_position;
+            let max = view.max - self.global_position;
+            let (cells_x, cells_y) = self.max_tile_cells;
+
+            x_range.0 = x_range.0.max((min.x / cell.x).floor() as i32 - cells_x);
+            x_range.1 = x_range.1.min((max.x / cell.x).ceil() as i32 + 1);
+            y_range.0 = y_range.0.max((min.y / cell.y).floor() as i32 - 1);
+            y_range.1 = y_range.1.min((max.y / cell.y).ceil() as i32 + cells_y);
+        }
+
+        for y in y_range.0..y_range.1 {
+            for x in x_range.0..x_range.1 {
+                let (tileset_index, tile) =
+                    match self.tiles[(y as u32 * self.width + x as u32) as usize] {
+                        Some(tile) => tile,
+                        None => continue,
+                    };
+                let (tileset, texture) = &self.tilesets[tileset_index];
+
+                // Tiles are anchored to the bottom-left corner of their cell
+                let size = Vec2::new(
+                    tileset.tile_width as f32 * pixel.x,
+                    tileset.tile_height as f32 * pixel.y,
+                );
+                let bottom_left =
+                    self.global_position + Vec2::new(x as f32 * cell.x, (y + 1) as f32 * cell.y);
+                let center = bottom_left + Vec2::new(size.x, -size.y) / 2.0;
+
+                let tile_id = self.animated_tile_id(tileset, tile.gid - tileset.first_gid);
+                let (mut min, mut max) = tileset.tex_region(tile_id);
+                if tile.flip_horizontal {
+                    std::mem::swap(&mut min[0], &mut max[0]);
+                }
+                if tile.flip_vertical {
+                    std::mem::swap(&mut min[1], &mut max[1]);
+                }
+
+                let mut quad =
+                    BatchQuad::new(Some(texture.clone()), center, size / 2.0, self.color);
+                if tile.flip_diagonal {
+                    for vertex in quad.vertices.iter_mut() {
+                        vertex.tex_coords.swap(0, 1);
+                    }
+                }
+                quads.push(quad.with_tex_region(min, max));
+            }
+        }
+    }
+}
+
+impl Draw for TileLayerDraw {
+    fn draw(
+        &self,
+        gl_handler: &mut GraphicsHandler,
+        command_buffer: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> Result<(), FrameError> {
+        // Recorded Command Buffers may be resubmitted after the camera moves, keep every tile
+        let mut quads = Vec::new();
+        self.push_quads(None, &mut quads);
+
+        gl_handler.draw_quads(quads, command_buffer)
+    }
+
+    fn get_z_index(&self) -> u8 {
+        self.z_index
+    }
+
+    fn flush_data(&self) {}
+
+    fn write_flags(&mut self) -> &mut DrawFlags {
+        &mut self.draw_flags
+    }
+
+    fn read_flags(&self) -> DrawFlags {
+        self.draw_flags
+    }
+
+    fn set_dead(&mut self) {
+        self.draw_flags.remove(DrawFlags::USED);
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        self.draw_flags.set(DrawFlags::VISIBLE, visible);
+    }
+
+    fn get_state(&self) -> DrawState {
+        DrawState {
+            color: self.color,
+            global_position: self.global_position,
+            scale: self.scale,
+            flags: self.draw_flags,
+        }
+    }
+
+    fn set_state(&mut self, state: &DrawState) {
+        self.color = state.color;
+        self.global_position = state.global_position;
+        self.scale = state.scale;
+        self.draw_flags = state.flags;
+    }
+
+    fn recreate(
+        &mut self,
+        _gl_handler: &GraphicsHandler,
+        recovery: &DeviceRecovery,
+    ) -> Result<(), String> {
+        let tilesets = self
+            .tilesets
+            .iter()
+            .map(|(tileset, texture)| Ok((tileset.clone(), recovery.texture(texture)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        self.tilesets = Arc::new(tilesets);
+
+        Ok(())
+    }
+
+    fn batch_quads(&self, view: &Aabb, quads: &mut Vec<BatchQuad>) -> bool {
+        self.push_quads(Some(view), quads);
+        true
+    }
+
+    fn batch_key(&self) -> usize {
+        self.tilesets
+            .first()
+            .map_or(0, |(_, texture)| Arc::as_ptr(texture) as usize)
+    }
+}
+
+/// Required attribute of a node
+fn attr<T: FromStr>(node: Node, name: &str) -> Result<T, String> {
+    let value = node.attribute(name).ok_or_else(|| {
+        format!(
+            "Missing attribute '{}' of <{}>",
+            name,
+            node.tag_name().name()
+        )
+    })?;
+
+    value.parse().map_err(|_| {
+        format!(
+            "Invalid attribute {}=\"{}\" of <{}>",
+            name,
+            value,
+            node.tag_name().name()
+        )
+    })
+}
+
+/// Optional attribute of a node, `default` when missing
+fn attr_or<T: FromStr>(node: Node, name: &str, default: T) -> Result<T, String> {
+    match node.attribute(name) {
+        Some(_) => attr(node, name),
+        None => Ok(default),
+    }
+}
+
+/// Tiled color, `#rrggbb` or `#aarrggbb`
+fn parse_color(value: &str) -> Result<Color, String> {
+    let digits = value.trim_start_matches('#');
+
+    if digits.len() == 8 {
+        Color::from_hex(&format!("{}{}", &digits[2..], &digits[..2]))
+    } else {
+        Color::from_hex(digits)
+    }
+}
+
+fn parse_properties(node: Node) -> Result<Properties, String> {
+    let mut properties = Properties::new();
+
+    let list = match node.children().find(|n| n.has_tag_name("properties")) {
+        Some(list) => list,
+        None => return Ok(properties),
+    };
+
+    for property in list.children().filter(|n| n.has_tag_name("property")) {
+        let name: String = attr(property, "name")?;
+        // Multiline strings are stored as text
+        let value = property
+            .attribute("value")
+            .or_else(|| property.text())
+            .unwrap_or("");
+
+        let value = match property.attribute("type").unwrap_or("string") {
+            "bool" => Property::Bool(value == "true"),
+            "int" | "object" => Property::Int(attr(property, "value")?),
+            "float" => Property::Float(attr(property, "value")?),
+            "color" if !value.is_empty() => Property::Color(parse_color(value)?),
+            _ => Property::String(value.to_string()),
+        };
+        properties.insert(name, value);
+    }
+
+    Ok(properties)
+}
+
+fn parse_tileset(node: Node, base_dir: &Path) -> Result<Tileset, String> {
+    let first_gid = attr(node, "firstgid")?;
+
+    // External tileset, its paths are relative to its own file
+    if let Some(source) = node.attribute("source") {
+        let path = base_dir.join(source);
+        let content = pack::read_to_string(&path)
+            .map_err(|e| format!("Couldn't read tileset '{}': {}", path.display(), e))?;
+        let document = Document::parse(&content)
+            .map_err(|e| format!("Couldn't parse tileset '{}': {}", path.display(), e))?;
+
+        let tileset_dir = path.parent().unwrap_or(base_dir);
+        return parse_tileset_content(document.root_element(), first_gid, tileset_dir);
+    }
+
+    parse_tileset_content(node, first_gid, base_dir)
+}
+
+fn parse_tileset_content(node: Node, first_gid: u32, base_dir: &Path) -> Result<Tileset, String> {
+    let name: String = attr_or(node, "name", String::new())?;
+    let image = node
+        .children()
+        .find(|n| n.has_tag_name("image"))
+        .ok_or_else(|| {
+            format!(
+                "Tileset '{}' isn't a single image, image collections aren't supported",
+                name
+            )
+        })?;
+
+    let mut tiles = HashMap::new();
+    for tile in node.children().filter(|n| n.has_tag_name("tile")) {
+        let animation = match tile.children().find(|n| n.has_tag_name("animation")) {
+            Some(animation) => animation
+                .children()
+                .filter(|n| n.has_tag_name("frame"))
+                .map(|frame| {
+                    Ok(AnimationFrame {
+                        tile_id: attr(frame, "tileid")?,
+                        duration: Duration::from_millis(attr(frame, "duration")?),
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?,
+            None => Vec::new(),
+        };
+
+        let kind = tile
+            .attribute("type")
+            .or_else(|| tile.attribute("class"))
+            .unwrap_or("")
+            .to_string();
+
+        tiles.insert(
+            attr(tile, "id")?,
+            TileData {
+                kind,
+                properties: parse_properties(tile)?,
+                animation,
+            },
+        );
+    }
+
+    let image_width = attr_or(image, "width", 0)?;
+    let tile_width = attr(node, "tilewidth")?;
+    let margin = attr_or(node, "margin", 0)?;
+    let spacing = attr_or(node, "spacing", 0)?;
+    // Old maps may lack the columns
+    let columns = match node.attribute("columns") {
+        Some(_) => attr(node, "columns")?,
+        None => (image_width + spacing).saturating_sub(2 * margin) / (tile_width + spacing).max(1),
+    };
+
+    Ok(Tileset {
+        first_gid,
+        name,
+        tile_width,
+        tile_height: attr(node, "tileheight")?,
+        spacing,
+        margin,
+        columns,
+        tile_count: attr(node, "tilecount")?,
+        image: base_dir.join(attr::<String>(image, "source")?),
+        image_width,
+        image_height: attr_or(image, "height", 0)?,
+        tiles,
+    })
+}
+
+/// Add the layers of a map or group, the group offset, visibility and opacity applied
+fn parse_layers(
+    node: Node,
+    offset: Vec2,
+    visible: bool,
+    opacity: f32,
+    layers: &mut Vec<Layer>,
+) -> Result<(), String> {
+    for child in node.children().filter(|n| n.is_element()) {
+        let name: String = attr_or(child, "name", String::new())?;
+        let offset = offset
+            + Vec2::new(
+                attr_or(child, "offsetx", 0.0)?,
+                attr_or(child, "offsety", 0.0)?,
+            );
+        let visible = visible && attr_or(child, "visible", 1)? != 0;
+        let opacity = opacity * attr_or(child, "opacity", 1.0)?;
+
+        match child.tag_name().name() {
+            "layer" => {
+                let width = attr(child, "width")?;
+                let height = attr(child, "height")?;
+                let data = child
+                    .children()
+                    .find(|n| n.has_tag_name("data"))
+                    .ok_or_else(|| format!("Layer '{}' has no data", name))?;
+
+                let tiles =
+                    parse_layer_data(data).map_err(|e| format!("Layer '{}': {}", name, e))?;
+                if tiles.len() != (width * height) as usize {
+                    return Err(format!(
+                        "Layer '{}' has {} tiles instead of {}",
+                        name,
+                        tiles.len(),
+                        width * height
+                    ));
+                }
+
+                layers.push(Layer::Tiles(TileLayer {
+                    properties: parse_properties(child)?,
+                    name,
+                    width,
+                    height,
+                    visible,
+                    opacity,
+                    offset,
+                    tiles,
+                }));
+            }
+            "objectgroup" => {
+                let objects = child
+                    .children()
+                    .filter(|n| n.has_tag_name("object"))
+                    .map(|object| parse_object(object, offset))
+                    .collect::<Result<Vec<_>, String>>()?;
+
+                layers.push(Layer::Objects(ObjectLayer {
+                    properties: parse_properties(child)?,
+                    name,
+                    visible,
+                    objects,
+                }));
+            }
+            "group" => parse_layers(child, offset, visible, opacity, layers)?,
+            // Image layers and the map data
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_layer_data(data: Node) -> Result<Vec<Option<Tile>>, String> {
+    if let Some(compression) = data.attribute("compression") {
+        return Err(format!(
+            "{} compressed layers aren't supported",
+            compression
+        ));
+    }
+
+    let raw: Vec<u32> = match data.attribute("encoding") {
+        None => data
+            .children()
+            .filter(|n| n.has_tag_name("tile"))
+            .map(|tile| attr_or(tile, "gid", 0))
+            .collect::<Result<_, String>>()?,
+        Some("csv") => data
+            .text()
+            .unwrap_or("")
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("Invalid tile '{}'", value))
+            })
+            .collect::<Result<_, String>>()?,
+        Some("base64") => decode_base64(data.text().unwrap_or("").trim())?
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect(),
+        Some(encoding) => return Err(format!("Unknown encoding '{}'", encoding)),
+    };
+
+    Ok(raw.into_iter().map(Tile::from_raw).collect())
+}
+
+fn parse_object(node: Node, offset: Vec2) -> Result<MapObject, String> {
+    let points = |shape: Node| -> Result<Vec<Vec2>, String> {
+        attr::<String>(shape, "points")?
+            .split_whitespace()
+            .map(|point| {
+                let mut coords = point.split(',').map(str::parse::<f32>);
+                match (coords.next(), coords.next()) {
+                    (Some(Ok(x)), Some(Ok(y))) => Ok(Vec2::new(x, y)),
+                    _ => Err(format!("Invalid point '{}'", point)),
+                }
+            })
+            .collect()
+    };
+
+    let mut shape = ObjectShape::Rectangle;
+    for child in node.children().filter(|n| n.is_element()) {
+        shape = match child.tag_name().name() {
+            "ellipse" => ObjectShape::Ellipse,
+            "point" => ObjectShape::Point,
+            "polygon" => ObjectShape::Polygon(points(child)?),
+            "polyline" => ObjectShape::Polyline(points(child)?),
+            _ => continue,
+        };
+    }
+
+    let kind = node
+        .attribute("type")
+        .or_else(|| node.attribute("class"))
+        .unwrap_or("")
+        .to_string();
+
+    Ok(MapObject {
+        id: attr_or(node, "id", 0)?,
+        name: attr_or(node, "name", String::new())?,
+        kind,
+        position: offset + Vec2::new(attr(node, "x")?, attr(node, "y")?),
+        size: Vec2::new(attr_or(node, "width", 0.0)?, attr_or(node, "height", 0.0)?),
+        rotation: attr_or(node, "rotation", 0.0)?,
+        shape,
+        tile: Tile::from_raw(attr_or(node, "gid", 0)?),
+        visible: attr_or(node, "visible", 1)? != 0,
+        properties: parse_properties(node)?,
+    })
+}
+
+/// Standard base64, padding optional
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in text
+        .bytes()
+        .filter(|c| !c.is_ascii_whitespace() && *c != b'=')
+    {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(format!("Invalid base64 character '{}'", c as char)),
+        };
+
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+
+    Ok(bytes)
+}
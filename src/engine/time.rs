@@ -0,0 +1,65 @@
+//! Frame timing exposed to the game, so movement isn't tied to the frame rate
+
+/// Weight of the newest frame in the smoothed frame time
+const FPS_SMOOTHING: f32 = 0.1;
+
+/// Timing of the current frame, updated by the engine before every update
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Time {
+    delta: f32,
+    elapsed: f64,
+    frame_count: u64,
+    smoothed_delta: f32,
+}
+
+impl Time {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seconds since the previous update
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    /// Seconds since the start of the game loop
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Frames rendered before the current one
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Frames per second averaged over the last frames, steadier than `1 / delta`
+    pub fn fps(&self) -> f32 {
+        if self.smoothed_delta > 0.0 {
+            1.0 / self.smoothed_delta
+        } else {
+            0.0
+        }
+    }
+
+    /// Start a new frame that took `delta` seconds
+    pub(crate) fn advance(&mut self, delta: f32) {
+        self.delta = delta;
+        self.elapsed += delta as f64;
+
+        self.smoothed_delta = if self.smoothed_delta > 0.0 {
+            self.smoothed_delta + (delta - self.smoothed_delta) * FPS_SMOOTHING
+        } else {
+            delta
+        };
+    }
+
+    /// Count the frame as rendered
+    pub(crate) fn end_frame(&mut self) {
+        self.frame_count += 1;
+    }
+
+    /// Same timing seen by a fixed update step
+    pub(crate) fn with_delta(&self, delta: f32) -> Time {
+        Time { delta, ..*self }
+    }
+}
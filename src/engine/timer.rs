@@ -0,0 +1,211 @@
+//! Timers and stopwatches for cooldowns, spawn intervals and durations
+//!
+//! They are ticked with the delta of the updates, e.g. `timer.tick(time.delta())`.
+//! Added as components to entities of `ctx.world`, the engine ticks them before
+//! running the systems, at every update step.
+
+// import the engine modules
+use super::ecs::World;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerMode {
+    /// Finishes once and stays finished until reset
+    Once,
+    /// Starts over every time it finishes
+    Repeating,
+}
+
+/// Countdown of a duration in seconds
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timer {
+    duration: f32,
+    elapsed: f32,
+    mode: TimerMode,
+    paused: bool,
+    finished: bool,
+    /// Times the timer finished during the last tick
+    times_finished: u32,
+}
+
+impl Timer {
+    pub fn new(duration: f32, mode: TimerMode) -> Self {
+        Self {
+            duration,
+            elapsed: 0.0,
+            mode,
+            paused: false,
+            finished: false,
+            times_finished: 0,
+        }
+    }
+
+    pub fn once(duration: f32) -> Self {
+        Self::new(duration, TimerMode::Once)
+    }
+
+    pub fn repeating(duration: f32) -> Self {
+        Self::new(duration, TimerMode::Repeating)
+    }
+
+    /// Advance the timer by `delta` seconds
+    pub fn tick(&mut self, delta: f32) -> &Self {
+        self.times_finished = 0;
+        if self.paused {
+            if self.mode == TimerMode::Repeating {
+                self.finished = false;
+            }
+            return self;
+        }
+
+        match self.mode {
+            TimerMode::Once => {
+                if self.finished {
+                    return self;
+                }
+
+                self.elapsed = (self.elapsed + delta).min(self.duration);
+                if self.elapsed >= self.duration {
+                    self.finished = true;
+                    self.times_finished = 1;
+                }
+            }
+            TimerMode::Repeating => {
+                self.elapsed += delta;
+                if self.duration <= 0.0 {
+                    // Would finish endlessly, once per tick is enough
+                    self.elapsed = 0.0;
+                    self.times_finished = 1;
+                } else {
+                    while self.elapsed >= self.duration {
+                        self.elapsed -= self.duration;
+                        self.times_finished += 1;
+                    }
+                }
+                self.finished = self.times_finished > 0;
+            }
+        }
+
+        self
+    }
+
+    /// True once a one-shot timer ran out, or on the ticks a repeating timer finished
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// True only on the tick the timer finished
+    pub fn just_finished(&self) -> bool {
+        self.times_finished > 0
+    }
+
+    /// Times the timer finished during the last tick, more than one with long frames
+    pub fn times_finished(&self) -> u32 {
+        self.times_finished
+    }
+
+    /// Start the countdown again
+    pub fn reset(&mut self) {
+        self.elapsed = 0.0;
+        self.finished = false;
+        self.times_finished = 0;
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    /// Change the duration, keeping the time elapsed
+    pub fn set_duration(&mut self, duration: f32) {
+        self.duration = duration;
+    }
+
+    pub fn mode(&self) -> TimerMode {
+        self.mode
+    }
+
+    /// Seconds since the start of the countdown
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+
+    /// Seconds left before the timer finishes
+    pub fn remaining(&self) -> f32 {
+        (self.duration - self.elapsed).max(0.0)
+    }
+
+    /// Progress from 0.0 to 1.0 (e.g. for cooldown bars)
+    pub fn fraction(&self) -> f32 {
+        if self.duration > 0.0 {
+            (self.elapsed / self.duration).min(1.0)
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Measure of the time passed, in seconds
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stopwatch {
+    elapsed: f64,
+    paused: bool,
+}
+
+impl Stopwatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advance the stopwatch by `delta` seconds
+    pub fn tick(&mut self, delta: f32) -> &Self {
+        if !self.paused {
+            self.elapsed += delta as f64;
+        }
+        self
+    }
+
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    pub fn reset(&mut self) {
+        self.elapsed = 0.0;
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+}
+
+/// Tick the `Timer` and `Stopwatch` components of the world (called by the engine at every update)
+pub(crate) fn tick_components(world: &World, delta: f32) {
+    if let Some(mut timers) = world.borrow_mut::<Timer>() {
+        for (_, timer) in timers.iter_mut() {
+            timer.tick(delta);
+        }
+    }
+    if let Some(mut stopwatches) = world.borrow_mut::<Stopwatch>() {
+        for (_, stopwatch) in stopwatches.iter_mut() {
+            stopwatch.tick(delta);
+        }
+    }
+}
@@ -0,0 +1,323 @@
+//! Placement of the entities: local transforms, parent/child hierarchy and world transforms
+//!
+//! Entities get a `Transform` relative to their `Parent` (or to the world without one).
+//! The engine computes their `GlobalTransform` after the updates of every frame, so the
+//! world placement is ready when rendering.
+
+// standard imports
+use std::collections::{HashMap, HashSet};
+
+// import the engine modules
+use super::ecs::{Entity, World};
+use super::math::{Mat3, Rad, Vec2};
+
+// other imports
+use cgmath::{InnerSpace, SquareMatrix};
+#[cfg(feature = "serialization")]
+use serde::{Deserialize, Serialize};
+
+/// Deepest parent chain followed, longer chains (or cycles) are cut there
+const MAX_HIERARCHY_DEPTH: usize = 64;
+
+/// Translation, rotation and scale of an entity, relative to its parent
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct Transform {
+    pub translation: Vec2,
+    pub rotation: Rad<f32>,
+    pub scale: Vec2,
+}
+
+impl Transform {
+    pub fn new(translation: Vec2) -> Self {
+        Self {
+            translation,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: Rad<f32>) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec2) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn translate(&mut self, offset: Vec2) {
+        self.translation += offset;
+    }
+
+    pub fn rotate(&mut self, angle: Rad<f32>) {
+        self.rotation += angle;
+    }
+
+    /// Unit vector of the local X axis
+    pub fn right(&self) -> Vec2 {
+        let (sin, cos) = self.rotation.0.sin_cos();
+        Vec2::new(cos, sin)
+    }
+
+    /// Unit vector of the local Y axis
+    pub fn up(&self) -> Vec2 {
+        let (sin, cos) = self.rotation.0.sin_cos();
+        Vec2::new(-sin, cos)
+    }
+
+    /// Homogeneous matrix applying scale, then rotation, then translation
+    pub fn to_matrix(&self) -> Mat3 {
+        let right = self.right() * self.scale.x;
+        let up = self.up() * self.scale.y;
+
+        Mat3::from_cols(
+            right.extend(0.0),
+            up.extend(0.0),
+            self.translation.extend(1.0),
+        )
+    }
+
+    /// Point relative to the transform moved to the parent space
+    pub fn transform_point(&self, point: Vec2) -> Vec2 {
+        (self.to_matrix() * point.extend(1.0)).truncate()
+    }
+
+    /// Point in the parent space moved relative to the transform
+    pub fn inverse_transform_point(&self, point: Vec2) -> Vec2 {
+        let offset = point - self.translation;
+        let local = Vec2::new(offset.dot(self.right()), offset.dot(self.up()));
+
+        Vec2::new(local.x / self.scale.x, local.y / self.scale.y)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec2::new(0.0, 0.0),
+            rotation: Rad(0.0),
+            scale: Vec2::new(1.0, 1.0),
+        }
+    }
+}
+
+/// Placement of an entity in the world, computed by the engine from the hierarchy
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlobalTransform {
+    matrix: Mat3,
+}
+
+impl GlobalTransform {
+    pub fn matrix(&self) -> Mat3 {
+        self.matrix
+    }
+
+    pub fn translation(&self) -> Vec2 {
+        self.matrix.z.truncate()
+    }
+
+    pub fn rotation(&self) -> Rad<f32> {
+        Rad(self.matrix.x.y.atan2(self.matrix.x.x))
+    }
+
+    pub fn scale(&self) -> Vec2 {
+        Vec2::new(
+            self.matrix.x.truncate().magnitude(),
+            self.matrix.y.truncate().magnitude(),
+        )
+    }
+
+    /// Local point of the entity moved to world space
+    pub fn transform_point(&self, point: Vec2) -> Vec2 {
+        (self.matrix * point.extend(1.0)).truncate()
+    }
+
+    /// World point moved to the local space of the entity, None if a scale is zero
+    pub fn inverse_transform_point(&self, point: Vec2) -> Option<Vec2> {
+        let inverse = self.matrix.invert()?;
+        Some((inverse * point.extend(1.0)).truncate())
+    }
+
+    /// Translation, rotation and scale of the world placement (skew from non-uniform scales is lost)
+    pub fn to_transform(&self) -> Transform {
+        Transform {
+            translation: self.translation(),
+            rotation: self.rotation(),
+            scale: self.scale(),
+        }
+    }
+}
+
+impl From<Transform> for GlobalTransform {
+    fn from(transform: Transform) -> Self {
+        Self {
+            matrix: transform.to_matrix(),
+        }
+    }
+}
+
+/// Entity the transform of this one is relative to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Parent(pub Entity);
+
+/// Attach an entity to a parent, keeping its local transform.
+/// Returns false if the parent is dead or a descendant of the child, which would make a cycle
+pub fn set_parent(world: &mut World, child: Entity, parent: Entity) -> bool {
+    if !world.is_alive(parent) || is_ancestor(world, child, parent) {
+        return false;
+    }
+
+    world.insert(child, Parent(parent));
+    true
+}
+
+/// Check if `ancestor` is `entity` or one of its parents
+pub fn is_ancestor(world: &World, ancestor: Entity, entity: Entity) -> bool {
+    let mut current = entity;
+    for _ in 0..=MAX_HIERARCHY_DEPTH {
+        if current == ancestor {
+            return true;
+        }
+        current = match world.get::<Parent>(current) {
+            Some(parent) => parent.0,
+            None => return false,
+        };
+    }
+
+    // Too deep to be attached anyway
+    true
+}
+
+/// Detach an entity from its parent, its transform becomes relative to the world
+pub fn remove_parent(world: &mut World, child: Entity) -> Option<Entity> {
+    world.remove::<Parent>(child).map(|parent| parent.0)
+}
+
+/// Direct children of an entity
+pub fn children(world: &World, parent: Entity) -> Vec<Entity> {
+    world.borrow::<Parent>().map_or_else(Vec::new, |parents| {
+        parents
+            .iter()
+            .filter(|(_, p)| p.0 == parent)
+            .map(|(child, _)| child)
+            .collect()
+    })
+}
+
+/// Despawn an entity with all of its descendants
+pub fn despawn_recursive(world: &mut World, entity: Entity) {
+    // Every entity is despawned once, even if the hierarchy has a cycle
+    let mut visited = HashSet::new();
+    let mut pending = vec![entity];
+    while let Some(entity) = pending.pop() {
+        if !visited.insert(entity) {
+            continue;
+        }
+
+        pending.extend(children(world, entity));
+        world.despawn(entity);
+    }
+}
+
+/// Include the hierarchy in the snapshots of the world (done by the engine at startup)
+pub(crate) fn register_snapshot(world: &mut World) {
+    world.register_snapshot::<Transform>();
+    world.register_snapshot::<GlobalTransform>();
+    world.register_snapshot::<Parent>();
+}
+
+/// Compute the `GlobalTransform` of every entity with a `Transform`.
+/// Allocates its maps every call, the engine keeps a `TransformPropagation` instead
+pub fn propagate(world: &mut World) {
+    TransformPropagation::default().run(world);
+}
+
+/// Maps used to compute the `GlobalTransform`s, kept between the frames to not allocate them again
+#[derive(Default)]
+pub struct TransformPropagation {
+    locals: HashMap<Entity, Transform>,
+    parents: HashMap<Entity, Entity>,
+    globals: HashMap<Entity, Mat3>,
+}
+
+impl TransformPropagation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compute the `GlobalTransform` of every entity with a `Transform` (called by the engine every frame)
+    pub fn run(&mut self, world: &mut World) {
+        self.locals.clear();
+        self.parents.clear();
+        self.globals.clear();
+
+        match world.borrow::<Transform>() {
+            Some(transforms) if !transforms.is_empty() => self
+                .locals
+                .extend(transforms.iter().map(|(entity, t)| (entity, *t))),
+            _ => return,
+        }
+        if let Some(parents) = world.borrow::<Parent>() {
+            self.parents
+                .extend(parents.iter().map(|(entity, p)| (entity, p.0)));
+        }
+
+        for entity in self.locals.keys() {
+            global_matrix(*entity, &self.locals, &self.parents, &mut self.globals, 0);
+        }
+
+        for (entity, matrix) in self.globals.drain() {
+            match world.get_mut::<GlobalTransform>(entity) {
+                Some(global) => global.matrix = matrix,
+                None => {
+                    world.insert(entity, GlobalTransform { matrix });
+                }
+            }
+        }
+    }
+}
+
+/// World matrix of an entity, parents without `Transform` count as the identity
+fn global_matrix(
+    entity: Entity,
+    locals: &HashMap<Entity, Transform>,
+    parents: &HashMap<Entity, Entity>,
+    globals: &mut HashMap<Entity, Mat3>,
+    depth: usize,
+) -> Mat3 {
+    if let Some(matrix) = globals.get(&entity) {
+        return *matrix;
+    }
+
+    let local = locals
+        .get(&entity)
+        .map_or_else(Mat3::identity, Transform::to_matrix);
+    let matrix = match parents.get(&entity) {
+        Some(parent) if depth < MAX_HIERARCHY_DEPTH => {
+            global_matrix(*parent, locals, parents, globals, depth + 1) * local
+        }
+        _ => local,
+    };
+
+    // Only the entities with a Transform get a GlobalTransform
+    if locals.contains_key(&entity) {
+        globals.insert(entity, matrix);
+    }
+
+    matrix
+}
+
+/// World position of a point local to an entity, as of the last propagation
+pub fn local_to_world(world: &World, entity: Entity, point: Vec2) -> Option<Vec2> {
+    world
+        .get::<GlobalTransform>(entity)
+        .map(|global| global.transform_point(point))
+}
+
+/// Position of a world point relative to an entity, as of the last propagation
+pub fn world_to_local(world: &World, entity: Entity, point: Vec2) -> Option<Vec2> {
+    world
+        .get::<GlobalTransform>(entity)
+        .and_then(|global| global.inverse_transform_point(point))
+}
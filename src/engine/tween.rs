@@ -0,0 +1,398 @@
+//! Easing functions and tweens, interpolating values over time
+//!
+//! A `Tween` can be advanced by hand, or handed to `ctx.tweens` with a function applying
+//! its value: the engine advances it after every update, until it finishes or is cancelled.
+
+// standard imports
+use std::f32::consts::PI;
+
+// import the engine modules
+use super::color::Color;
+use super::game::Context;
+use super::math::{Vec2, Vec3, Vec4};
+
+/// Shape of the progression of a tween, from 0.0 to 1.0
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineIn,
+    SineOut,
+    SineInOut,
+    ExpoIn,
+    ExpoOut,
+    ExpoInOut,
+    /// Goes slightly back before starting
+    BackIn,
+    /// Overshoots the end before settling
+    BackOut,
+    BackInOut,
+    ElasticOut,
+    BounceOut,
+}
+
+/// Overshoot of the Back easings
+const BACK_OVERSHOOT: f32 = 1.70158;
+
+fn bounce_out(t: f32) -> f32 {
+    const N: f32 = 7.5625;
+    const D: f32 = 2.75;
+
+    if t < 1.0 / D {
+        N * t * t
+    } else if t < 2.0 / D {
+        let t = t - 1.5 / D;
+        N * t * t + 0.75
+    } else if t < 2.5 / D {
+        let t = t - 2.25 / D;
+        N * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D;
+        N * t * t + 0.984375
+    }
+}
+
+impl Easing {
+    /// Eased progress of a linear progress `t` (clamped to 0.0..=1.0)
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        let in_out = |ease_in: fn(f32) -> f32| {
+            if t < 0.5 {
+                ease_in(t * 2.0) / 2.0
+            } else {
+                1.0 - ease_in((1.0 - t) * 2.0) / 2.0
+            }
+        };
+
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::QuadInOut => in_out(|t| t * t),
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => in_out(|t| t * t * t),
+            Easing::SineIn => 1.0 - (t * PI / 2.0).cos(),
+            Easing::SineOut => (t * PI / 2.0).sin(),
+            Easing::SineInOut => (1.0 - (t * PI).cos()) / 2.0,
+            Easing::ExpoIn => expo_in(t),
+            Easing::ExpoOut => 1.0 - expo_in(1.0 - t),
+            Easing::ExpoInOut => in_out(expo_in),
+            Easing::BackIn => back_in(t),
+            Easing::BackOut => 1.0 - back_in(1.0 - t),
+            Easing::BackInOut => in_out(back_in),
+            Easing::ElasticOut => {
+                if t == 0.0 || t == 1.0 {
+                    t
+                } else {
+                    2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+                }
+            }
+            Easing::BounceOut => bounce_out(t),
+        }
+    }
+}
+
+fn expo_in(t: f32) -> f32 {
+    if t == 0.0 {
+        0.0
+    } else {
+        2f32.powf(10.0 * t - 10.0)
+    }
+}
+
+fn back_in(t: f32) -> f32 {
+    (BACK_OVERSHOOT + 1.0) * t * t * t - BACK_OVERSHOOT * t * t
+}
+
+impl Default for Easing {
+    fn default() -> Self {
+        Easing::Linear
+    }
+}
+
+/// Value which can be interpolated by a tween
+pub trait Tweenable: Copy {
+    /// Value between `from` (t = 0.0) and `to` (t = 1.0), `t` may go past them with some easings
+    fn interpolate(from: Self, to: Self, t: f32) -> Self;
+}
+
+impl Tweenable for f32 {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from + (to - from) * t
+    }
+}
+
+impl Tweenable for Vec2 {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from + (to - from) * t
+    }
+}
+
+impl Tweenable for Vec3 {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from + (to - from) * t
+    }
+}
+
+impl Tweenable for Vec4 {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from + (to - from) * t
+    }
+}
+
+impl Tweenable for Color {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from.lerp(to, t)
+    }
+}
+
+/// What a tween does once it reaches the end
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Repeat {
+    Once,
+    /// Start over from the beginning, forever
+    Loop,
+    /// Go back and forth, forever
+    PingPong,
+}
+
+/// Interpolation of a value over a duration
+pub struct Tween<T: Tweenable> {
+    from: T,
+    to: T,
+    /// Seconds
+    duration: f32,
+    elapsed: f32,
+    easing: Easing,
+    repeat: Repeat,
+    on_complete: Option<Box<dyn FnMut(&mut Context)>>,
+}
+
+impl<T: Tweenable> Tween<T> {
+    /// Tween from `from` to `to` in `duration` seconds
+    pub fn new(from: T, to: T, duration: f32) -> Self {
+        Self {
+            from,
+            to,
+            duration,
+            elapsed: 0.0,
+            easing: Easing::Linear,
+            repeat: Repeat::Once,
+            on_complete: None,
+        }
+    }
+
+    pub fn easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    pub fn repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    /// Called once the tween finished, when driven by `ctx.tweens`
+    pub fn on_complete<F: FnMut(&mut Context) + 'static>(mut self, callback: F) -> Self {
+        self.on_complete = Some(Box::new(callback));
+        self
+    }
+
+    /// Advance the tween by `delta` seconds, returning the new value
+    pub fn update(&mut self, delta: f32) -> T {
+        self.elapsed += delta;
+
+        if self.repeat != Repeat::Once && self.duration > 0.0 {
+            let period = match self.repeat {
+                Repeat::PingPong => self.duration * 2.0,
+                _ => self.duration,
+            };
+            self.elapsed %= period;
+        }
+
+        self.value()
+    }
+
+    /// Progress from 0.0 to 1.0, before easing
+    pub fn progress(&self) -> f32 {
+        if self.duration <= 0.0 {
+            return 1.0;
+        }
+
+        let t = self.elapsed / self.duration;
+        match self.repeat {
+            Repeat::PingPong if t > 1.0 => 2.0 - t,
+            _ => t.min(1.0),
+        }
+    }
+
+    pub fn value(&self) -> T {
+        T::interpolate(self.from, self.to, self.easing.apply(self.progress()))
+    }
+
+    /// Only tweens played once finish
+    pub fn is_finished(&self) -> bool {
+        self.repeat == Repeat::Once && self.elapsed >= self.duration
+    }
+
+    /// Start again from the beginning
+    pub fn reset(&mut self) {
+        self.elapsed = 0.0;
+    }
+}
+
+/// Identifier of a tween driven by `ctx.tweens`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TweenId(u64);
+
+/// Tween with the function applying its value
+trait ActiveTween {
+    /// Advance and apply the tween, returns true once it finished
+    fn advance(&mut self, delta: f32, ctx: &mut Context) -> bool;
+    fn elapsed(&self) -> f32;
+    fn set_elapsed(&mut self, elapsed: f32);
+}
+
+struct AppliedTween<T: Tweenable, F: FnMut(T, &mut Context)> {
+    tween: Tween<T>,
+    apply: F,
+}
+
+impl<T: Tweenable, F: FnMut(T, &mut Context)> ActiveTween for AppliedTween<T, F> {
+    fn advance(&mut self, delta: f32, ctx: &mut Context) -> bool {
+        let value = self.tween.update(delta);
+        (self.apply)(value, ctx);
+
+        if !self.tween.is_finished() {
+            return false;
+        }
+        if let Some(mut callback) = self.tween.on_complete.take() {
+            callback(ctx);
+        }
+        true
+    }
+
+    fn elapsed(&self) -> f32 {
+        self.tween.elapsed
+    }
+
+    fn set_elapsed(&mut self, elapsed: f32) {
+        self.tween.elapsed = elapsed;
+    }
+}
+
+/// Progress of the tweens driven by `ctx.tweens`
+#[derive(Clone, Debug)]
+pub struct TweenSnapshot {
+    elapsed: Vec<(TweenId, f32)>,
+}
+
+/// Tweens advanced by the engine after every update
+#[derive(Default)]
+pub struct TweenManager {
+    active: Vec<(TweenId, Box<dyn ActiveTween>)>,
+    next_id: u64,
+    /// Cancelled while the tweens were advancing
+    cancelled: Vec<TweenId>,
+}
+
+impl TweenManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drive a tween, `apply` gets its value every frame (e.g. to move a sprite or fade a volume)
+    pub fn add<T, F>(&mut self, tween: Tween<T>, apply: F) -> TweenId
+    where
+        T: Tweenable + 'static,
+        F: FnMut(T, &mut Context) + 'static,
+    {
+        let id = TweenId(self.next_id);
+        self.next_id += 1;
+
+        self.active
+            .push((id, Box::new(AppliedTween { tween, apply })));
+        id
+    }
+
+    /// Stop a tween where it is, without calling its completion callback
+    pub fn cancel(&mut self, id: TweenId) {
+        self.active.retain(|(active, _)| *active != id);
+        self.cancelled.push(id);
+    }
+
+    pub fn is_active(&self, id: TweenId) -> bool {
+        self.active.iter().any(|(active, _)| *active == id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.active.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+
+    /// Cancel every tween
+    pub fn clear(&mut self) {
+        let ids: Vec<TweenId> = self.active.iter().map(|(id, _)| *id).collect();
+        for id in ids {
+            self.cancel(id);
+        }
+    }
+
+    /// Copy the progress of the active tweens
+    pub fn snapshot(&self) -> TweenSnapshot {
+        TweenSnapshot {
+            elapsed: self
+                .active
+                .iter()
+                .map(|(id, tween)| (*id, tween.elapsed()))
+                .collect(),
+        }
+    }
+
+    /// Rewind the tweens to a snapshot, the ones added after it are dropped.
+    /// Tweens finished or cancelled since the snapshot can't come back, their functions are gone
+    pub fn restore(&mut self, snapshot: &TweenSnapshot) {
+        self.active
+            .retain(|(id, _)| snapshot.elapsed.iter().any(|(kept, _)| kept == id));
+
+        for (id, tween) in &mut self.active {
+            if let Some((_, elapsed)) = snapshot.elapsed.iter().find(|(kept, _)| kept == id) {
+                tween.set_elapsed(*elapsed);
+            }
+        }
+    }
+}
+
+/// Advance every tween of the context (called by the engine after the updates)
+pub(crate) fn update_tweens(ctx: &mut Context, delta: f32) {
+    // Taken out, the tweens get the whole context while applying their values
+    let mut tweens = std::mem::take(&mut ctx.tweens.active);
+    ctx.tweens.cancelled.clear();
+
+    let mut index = 0;
+    while index < tweens.len() {
+        let (id, tween) = &mut tweens[index];
+        let finished = ctx.tweens.cancelled.contains(id) || tween.advance(delta, ctx);
+
+        if finished {
+            tweens.remove(index);
+        } else {
+            index += 1;
+        }
+    }
+
+    // Keep the tweens added while advancing, drop the ones cancelled meanwhile
+    let cancelled = std::mem::take(&mut ctx.tweens.cancelled);
+    tweens.retain(|(id, _)| !cancelled.contains(id));
+    tweens.append(&mut ctx.tweens.active);
+    ctx.tweens.active = tweens;
+}
@@ -0,0 +1,241 @@
+//! Arcade vehicle controller for side-view games, with raycast wheels and spring suspensions
+//!
+//! The vehicle doesn't depend on a physics engine: the ground is found through the
+//! `GroundQuery` trait, implemented by whatever holds the level collision.
+//! Coordinates follow the screen, with the y axis pointing down.
+
+// other imports
+use cgmath::{InnerSpace, Vector2};
+
+/// Ground found by a wheel raycast
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GroundHit {
+    pub distance: f32,
+    /// Surface normal, pointing away from the ground
+    pub normal: Vector2<f32>,
+    /// Grip of the surface (1.0 for asphalt, lower for ice or mud)
+    pub friction: f32,
+}
+
+/// Level collision seen by the vehicle
+pub trait GroundQuery {
+    /// First ground hit along a ray, up to `max_distance`
+    fn raycast(&self, origin: Vector2<f32>, direction: Vector2<f32>, max_distance: f32) -> Option<GroundHit>;
+}
+
+/// Inputs given to the vehicle every update, each in -1.0..=1.0 (0.0..=1.0 for brake)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VehicleInput {
+    pub throttle: f32,
+    pub brake: f32,
+    /// Leaning of the vehicle, backward (-1.0) or forward (1.0)
+    pub steer: f32,
+}
+
+/// Wheel mounted on the chassis
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wheel {
+    /// Suspension mount relative to the chassis center, for an unrotated chassis
+    pub mount: Vector2<f32>,
+    pub radius: f32,
+    /// Length of the suspension at rest
+    pub rest_length: f32,
+    pub stiffness: f32,
+    pub damping: f32,
+    /// Receives the engine force
+    pub driven: bool,
+}
+
+/// Tuning of the vehicle feel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VehicleParams {
+    pub mass: f32,
+    pub inertia: f32,
+    pub gravity: Vector2<f32>,
+    pub engine_force: f32,
+    pub brake_force: f32,
+    pub max_speed: f32,
+    /// Torque applied by `steer` on the ground
+    pub lean_torque: f32,
+    /// Torque applied by `steer` in the air
+    pub air_control_torque: f32,
+    /// Fraction of the grip kept while the wheels slip, lower values drift longer
+    pub slide_grip: f32,
+    /// Traction needed before the wheels start to slip, as a multiplier of the surface friction
+    pub drift_threshold: f32,
+    pub linear_drag: f32,
+    pub angular_drag: f32,
+}
+
+impl Default for VehicleParams {
+    fn default() -> Self {
+        Self {
+            mass: 1.0,
+            inertia: 1.0,
+            gravity: Vector2::new(0.0, 980.0),
+            engine_force: 1500.0,
+            brake_force: 2000.0,
+            max_speed: 800.0,
+            lean_torque: 2.0,
+            air_control_torque: 6.0,
+            slide_grip: 0.6,
+            drift_threshold: 1.0,
+            linear_drag: 0.1,
+            angular_drag: 2.0,
+        }
+    }
+}
+
+/// State of a wheel after the last update
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WheelState {
+    pub grounded: bool,
+    /// How much the suspension is compressed
+    pub compression: f32,
+    pub slipping: bool,
+}
+
+/// Vehicle body driven by the wheel suspensions
+#[derive(Clone, Debug)]
+pub struct Vehicle {
+    pub params: VehicleParams,
+    pub wheels: Vec<Wheel>,
+    pub position: Vector2<f32>,
+    pub velocity: Vector2<f32>,
+    /// Rotation of the chassis in radians, clockwise
+    pub angle: f32,
+    pub angular_velocity: f32,
+    wheel_states: Vec<WheelState>,
+}
+
+impl Vehicle {
+    pub fn new(params: VehicleParams, wheels: Vec<Wheel>, position: Vector2<f32>) -> Self {
+        let wheel_states = vec![WheelState::default(); wheels.len()];
+
+        Self {
+            params,
+            wheels,
+            position,
+            velocity: Vector2::new(0.0, 0.0),
+            angle: 0.0,
+            angular_velocity: 0.0,
+            wheel_states,
+        }
+    }
+
+    /// Common two-wheel setup, both wheels driven
+    pub fn two_wheeled(params: VehicleParams, position: Vector2<f32>, wheelbase: f32, radius: f32) -> Self {
+        let wheel = |x: f32| Wheel {
+            mount: Vector2::new(x, 0.0),
+            radius,
+            rest_length: radius,
+            stiffness: 40.0 * params.mass,
+            damping: 4.0 * params.mass,
+            driven: true,
+        };
+
+        Self::new(params, vec![wheel(-wheelbase / 2.0), wheel(wheelbase / 2.0)], position)
+    }
+
+    pub fn wheel_states(&self) -> &[WheelState] {
+        &self.wheel_states
+    }
+
+    pub fn is_grounded(&self) -> bool {
+        self.wheel_states.iter().any(|w| w.grounded)
+    }
+
+    /// Speed along the chassis, positive going forward
+    pub fn forward_speed(&self) -> f32 {
+        self.velocity.dot(self.rotate(Vector2::new(1.0, 0.0)))
+    }
+
+    fn rotate(&self, v: Vector2<f32>) -> Vector2<f32> {
+        let (sin, cos) = self.angle.sin_cos();
+        Vector2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos)
+    }
+
+    /// Advance the simulation by `dt` seconds
+    pub fn update(&mut self, dt: f32, input: VehicleInput, ground: &dyn GroundQuery) {
+        if dt <= 0.0 {
+            return;
+        }
+
+        let params = self.params;
+        let down = self.rotate(Vector2::new(0.0, 1.0));
+        let driven_wheels = self.wheels.iter().filter(|w| w.driven).count().max(1) as f32;
+
+        let mut force = params.gravity * params.mass;
+        let mut torque = 0.0;
+
+        for (index, wheel) in self.wheels.iter().enumerate() {
+            let offset = self.rotate(wheel.mount);
+            let origin = self.position + offset;
+            let reach = wheel.rest_length + wheel.radius;
+            let previous = self.wheel_states[index];
+
+            let hit = match ground.raycast(origin, down, reach) {
+                Some(hit) => hit,
+                None => {
+                    self.wheel_states[index] = WheelState::default();
+                    continue;
+                }
+            };
+
+            // Spring and damper along the suspension
+            let compression = reach - hit.distance;
+            let compression_speed = (compression - previous.compression) / dt;
+            let suspension = (wheel.stiffness * compression + wheel.damping * compression_speed).max(0.0);
+            let mut wheel_force = -down * suspension;
+
+            // Drive and brake along the ground, limited by the traction
+            let tangent = Vector2::new(-hit.normal.y, hit.normal.x);
+            let tangent = if tangent.dot(self.rotate(Vector2::new(1.0, 0.0))) < 0.0 {
+                -tangent
+            } else {
+                tangent
+            };
+            let ground_speed = self.velocity.dot(tangent);
+
+            let mut traction = 0.0;
+            if wheel.driven && ground_speed.abs() < params.max_speed {
+                traction += input.throttle * params.engine_force / driven_wheels;
+            }
+            if input.brake > 0.0 {
+                traction -= ground_speed.signum() * input.brake * params.brake_force / self.wheels.len() as f32;
+            }
+
+            let grip = hit.friction * suspension * params.drift_threshold;
+            let slipping = traction.abs() > grip;
+            if slipping {
+                traction = traction.signum() * grip * params.slide_grip;
+            }
+            wheel_force += tangent * traction;
+
+            force += wheel_force;
+            torque += offset.x * wheel_force.y - offset.y * wheel_force.x;
+
+            self.wheel_states[index] = WheelState {
+                grounded: true,
+                compression,
+                slipping,
+            };
+        }
+
+        torque += input.steer
+            * if self.is_grounded() {
+                params.lean_torque
+            } else {
+                params.air_control_torque
+            }
+            * params.inertia;
+
+        self.velocity += force / params.mass * dt;
+        self.velocity *= 1.0 / (1.0 + params.linear_drag * dt);
+        self.angular_velocity += torque / params.inertia * dt;
+        self.angular_velocity *= 1.0 / (1.0 + params.angular_drag * dt);
+
+        self.position += self.velocity * dt;
+        self.angle += self.angular_velocity * dt;
+    }
+}
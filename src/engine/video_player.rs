@@ -0,0 +1,580 @@
+//! Video playback for intros and cutscenes, decoded by FFmpeg (WebM with VP8/VP9, OGV with
+//! Theora...)
+//!
+//! A thread decodes the file ahead of the playback, converting the frames to RGBA and the
+//! soundtrack to 16-bit stereo. The game calls `update` every frame: it advances the playback
+//! clock, uploads the last frame due to the sprite of the player and queues the soundtrack on
+//! an `AudioStream`, a little ahead of the clock.
+
+// standard imports
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, SendError, Sender, SyncSender, TryRecvError};
+use std::thread;
+
+// import the engine modules
+use super::crash;
+use super::ctx::draw_objects::SpriteObject;
+use super::ctx::vulkan::StreamingTexture;
+use super::ctx::AudioStream;
+use super::game::Context;
+
+// other imports
+use ffmpeg_next as ffmpeg;
+
+/// Decoded frames waiting for their time, how far the decoder reads ahead
+const FRAME_QUEUE: usize = 8;
+/// Soundtrack queued ahead of the playback clock, in seconds
+const AUDIO_LEAD: f64 = 0.1;
+/// Sample rate the soundtrack is resampled to
+const AUDIO_RATE: i32 = 44100;
+/// Ticks per second of the FFmpeg timestamps not bound to a stream (AV_TIME_BASE)
+const TIME_BASE: f64 = 1_000_000.0;
+
+/// Size and length of a video, sent by the decoder once the file is open
+struct VideoInfo {
+    width: u32,
+    height: u32,
+    duration: Option<f64>,
+}
+
+/// Frame converted to tightly packed RGBA8, with its time in seconds
+struct VideoFrame {
+    time: f64,
+    pixels: Vec<u8>,
+}
+
+/// Messages of the decoding thread, tagged with the seek they follow
+enum Decoded {
+    Info(VideoInfo),
+    Frame(u64, VideoFrame),
+    End(u64),
+    Error(String),
+}
+
+/// Chunk of interleaved stereo samples, with the seek it follows and its time in seconds
+type AudioChunk = (u64, f64, Vec<i16>);
+
+enum Command {
+    Seek { time: f64, generation: u64 },
+}
+
+/// Streams of the file, owned by the decoding thread
+struct Decoder {
+    input: ffmpeg::format::context::Input,
+    video_stream: usize,
+    video_time_base: f64,
+    video: ffmpeg::decoder::Video,
+    scaler: ffmpeg::software::scaling::Context,
+    width: u32,
+    height: u32,
+    audio: Option<AudioDecoder>,
+}
+
+struct AudioDecoder {
+    stream: usize,
+    time_base: f64,
+    decoder: ffmpeg::decoder::Audio,
+    resampler: ffmpeg::software::resampling::Context,
+}
+
+impl Decoder {
+    fn open(path: &Path) -> Result<(Decoder, VideoInfo), ffmpeg::Error> {
+        let input = ffmpeg::format::input(&path)?;
+
+        let (video_stream, video_time_base, video) = {
+            let stream = input
+                .streams()
+                .best(ffmpeg::media::Type::Video)
+                .ok_or(ffmpeg::Error::StreamNotFound)?;
+            let context = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?;
+            (
+                stream.index(),
+                f64::from(stream.time_base()),
+                context.decoder().video()?,
+            )
+        };
+        let (width, height) = (video.width(), video.height());
+        let scaler = ffmpeg::software::scaling::Context::get(
+            video.format(),
+            width,
+            height,
+            ffmpeg::format::Pixel::RGBA,
+            width,
+            height,
+            ffmpeg::software::scaling::Flags::BILINEAR,
+        )?;
+
+        // A soundtrack FFmpeg can't decode leaves the video silent
+        let audio = match input.streams().best(ffmpeg::media::Type::Audio) {
+            Some(stream) => AudioDecoder::open(&stream)
+                .map_err(|e| crash::log(&format!("Couldn't decode the video soundtrack: {}", e)))
+                .ok(),
+            None => None,
+        };
+
+        let duration = if input.duration() > 0 {
+            Some(input.duration() as f64 / TIME_BASE)
+        } else {
+            None
+        };
+
+        let decoder = Decoder {
+            input,
+            video_stream,
+            video_time_base,
+            video,
+            scaler,
+            width,
+            height,
+            audio,
+        };
+
+        Ok((
+            decoder,
+            VideoInfo {
+                width,
+                height,
+                duration,
+            },
+        ))
+    }
+
+    /// Decode the file until the player is dropped, waiting for a seek after the end
+    fn run(
+        mut self,
+        commands: Receiver<Command>,
+        frames: SyncSender<Decoded>,
+        audio: Sender<AudioChunk>,
+    ) {
+        let mut generation = 0;
+        let mut ended = false;
+
+        loop {
+            let command = if ended {
+                match commands.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => return,
+                }
+            } else {
+                match commands.try_recv() {
+                    Ok(command) => Some(command),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            };
+
+            if let Some(Command::Seek {
+                time,
+                generation: seek,
+            }) = command
+            {
+                generation = seek;
+                ended = false;
+                if let Err(e) = self.seek(time) {
+                    crash::log(&format!("Couldn't seek the video to {:.2}s: {}", time, e));
+                }
+                continue;
+            }
+
+            let mut packet = ffmpeg::Packet::empty();
+            let sent = match packet.read(&mut self.input) {
+                Ok(()) => self.decode(&packet, generation, &frames, &audio),
+                Err(ffmpeg::Error::Eof) => {
+                    ended = true;
+                    self.finish(generation, &frames, &audio)
+                }
+                // Damaged packets are skipped, the next key frame brings the picture back
+                Err(_) => Ok(()),
+            };
+
+            if sent.is_err() {
+                return;
+            }
+        }
+    }
+
+    fn decode(
+        &mut self,
+        packet: &ffmpeg::Packet,
+        generation: u64,
+        frames: &SyncSender<Decoded>,
+        audio: &Sender<AudioChunk>,
+    ) -> Result<(), SendError<Decoded>> {
+        if packet.stream() == self.video_stream {
+            if self.video.send_packet(packet).is_ok() {
+                self.receive_frames(generation, frames)?;
+            }
+        } else if let Some(decoder) = self.audio.as_mut() {
+            if packet.stream() == decoder.stream && decoder.decoder.send_packet(packet).is_ok() {
+                decoder.receive(generation, audio);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn receive_frames(
+        &mut self,
+        generation: u64,
+        frames: &SyncSender<Decoded>,
+    ) -> Result<(), SendError<Decoded>> {
+        let mut decoded = ffmpeg::frame::Video::empty();
+        while self.video.receive_frame(&mut decoded).is_ok() {
+            let mut rgba = ffmpeg::frame::Video::empty();
+            if self.scaler.run(&decoded, &mut rgba).is_err() {
+                continue;
+            }
+
+            let frame = VideoFrame {
+                time: decoded.timestamp().unwrap_or(0) as f64 * self.video_time_base,
+                pixels: packed_rgba(&rgba, self.width, self.height),
+            };
+            frames.send(Decoded::Frame(generation, frame))?;
+        }
+
+        Ok(())
+    }
+
+    /// Flush the frames held by the decoders and tell the player the end was reached
+    fn finish(
+        &mut self,
+        generation: u64,
+        frames: &SyncSender<Decoded>,
+        audio: &Sender<AudioChunk>,
+    ) -> Result<(), SendError<Decoded>> {
+        if self.video.send_eof().is_ok() {
+            self.receive_frames(generation, frames)?;
+        }
+        if let Some(decoder) = self.audio.as_mut() {
+            if decoder.decoder.send_eof().is_ok() {
+                decoder.receive(generation, audio);
+            }
+        }
+
+        frames.send(Decoded::End(generation))
+    }
+
+    fn seek(&mut self, time: f64) -> Result<(), ffmpeg::Error> {
+        // Lands on the key frame before the time, the player drops the frames until it
+        let timestamp = (time * TIME_BASE) as i64;
+        self.input.seek(timestamp, ..timestamp)?;
+
+        self.video.flush();
+        if let Some(decoder) = self.audio.as_mut() {
+            decoder.decoder.flush();
+        }
+
+        Ok(())
+    }
+}
+
+impl AudioDecoder {
+    fn open(stream: &ffmpeg::format::stream::Stream) -> Result<AudioDecoder, ffmpeg::Error> {
+        let context = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?;
+        let decoder = context.decoder().audio()?;
+
+        let mut layout = decoder.channel_layout();
+        if layout.is_empty() {
+            layout = ffmpeg::ChannelLayout::default(i32::from(decoder.channels()));
+        }
+        let resampler = ffmpeg::software::resampling::Context::get(
+            decoder.format(),
+            layout,
+            decoder.rate(),
+            ffmpeg::format::Sample::I16(ffmpeg::format::sample::Type::Packed),
+            ffmpeg::ChannelLayout::STEREO,
+            AUDIO_RATE as u32,
+        )?;
+
+        Ok(AudioDecoder {
+            stream: stream.index(),
+            time_base: f64::from(stream.time_base()),
+            decoder,
+            resampler,
+        })
+    }
+
+    /// Resample the decoded samples, dropped if the player is gone
+    fn receive(&mut self, generation: u64, audio: &Sender<AudioChunk>) {
+        let mut decoded = ffmpeg::frame::Audio::empty();
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            let mut resampled = ffmpeg::frame::Audio::empty();
+            if self.resampler.run(&decoded, &mut resampled).is_err() {
+                continue;
+            }
+
+            // Two channels of two bytes per sample
+            let bytes = &resampled.data(0)[..resampled.samples() * 4];
+            let samples = bytes
+                .chunks_exact(2)
+                .map(|sample| i16::from_ne_bytes([sample[0], sample[1]]))
+                .collect();
+            let time = decoded.timestamp().unwrap_or(0) as f64 * self.time_base;
+
+            let _ = audio.send((generation, time, samples));
+        }
+    }
+}
+
+/// Copy the rows of a scaled frame without the padding FFmpeg leaves at their end
+fn packed_rgba(frame: &ffmpeg::frame::Video, width: u32, height: u32) -> Vec<u8> {
+    let row = width as usize * 4;
+    let stride = frame.stride(0);
+    let data = frame.data(0);
+
+    let mut pixels = Vec::with_capacity(row * height as usize);
+    for y in 0..height as usize {
+        pixels.extend_from_slice(&data[y * stride..y * stride + row]);
+    }
+
+    pixels
+}
+
+/// Video shown on a sprite, with its soundtrack played on a stream of the audio subsystem
+pub struct VideoPlayer {
+    sprite: SpriteObject,
+    /// Texture of the sprite, updated with every frame shown
+    frame: StreamingTexture,
+    duration: Option<f64>,
+
+    commands: Sender<Command>,
+    frames: Receiver<Decoded>,
+    audio: Receiver<AudioChunk>,
+    stream: Option<AudioStream>,
+
+    /// Incremented by every seek, what was decoded before it is dropped
+    generation: u64,
+    time: f64,
+    playing: bool,
+    /// The decoder sent the last frame of the file
+    decoded_all: bool,
+    next_frame: Option<VideoFrame>,
+    pending_audio: VecDeque<(f64, Vec<i16>)>,
+
+    /// Start over at the end of the video
+    pub looping: bool,
+}
+
+impl VideoPlayer {
+    /// Open a video in the assets, paused on a black sprite until the first update
+    pub fn open(ctx: &mut Context, path: &Path, z_index: u8) -> Result<VideoPlayer, String> {
+        if ctx.is_headless() {
+            return Err(String::from("Can't play videos in headless mode"));
+        }
+        ffmpeg::init().map_err(|e| format!("Couldn't initialize FFmpeg: {}", e))?;
+
+        let path = ctx.asset_path(path);
+        let (commands, pending_commands) = mpsc::channel();
+        let (frames_sender, frames) = mpsc::sync_channel(FRAME_QUEUE);
+        let (audio_sender, audio) = mpsc::channel();
+
+        let decoded_path = path.clone();
+        thread::Builder::new()
+            .name(String::from("video decoder"))
+            .spawn(move || match Decoder::open(&decoded_path) {
+                Ok((decoder, info)) => {
+                    if frames_sender.send(Decoded::Info(info)).is_ok() {
+                        decoder.run(pending_commands, frames_sender, audio_sender);
+                    }
+                }
+                Err(e) => {
+                    let _ = frames_sender.send(Decoded::Error(format!(
+                        "Couldn't open video '{}': {}",
+                        decoded_path.display(),
+                        e
+                    )));
+                }
+            })
+            .map_err(|e| format!("Couldn't start the video decoder: {}", e))?;
+
+        let info = match frames.recv() {
+            Ok(Decoded::Info(info)) => info,
+            Ok(Decoded::Error(e)) => return Err(e),
+            _ => return Err(String::from("The video decoder stopped")),
+        };
+
+        let stream = if ctx.audio.is_enabled() {
+            ctx.audio
+                .open_stream(AUDIO_RATE, 2)
+                .map_err(|e| crash::log(&format!("Couldn't open the video soundtrack: {}", e)))
+                .ok()
+        } else {
+            None
+        };
+
+        let black = vec![0; info.width as usize * info.height as usize * 4];
+        let video = ctx
+            .try_video()
+            .ok_or("Can't play videos in headless mode")?;
+        let frame = video
+            .gl_handler
+            .create_streaming_texture(info.width, info.height)?;
+        video.gl_handler.update_streaming_texture(&frame, &black)?;
+        let label = format!("video {}", path.display());
+        let sprite = video.gl_handler.new_sprite_from_texture(
+            frame.texture(),
+            frame.dimensions(),
+            &label,
+            z_index,
+        )?;
+
+        Ok(VideoPlayer {
+            sprite,
+            frame,
+            duration: info.duration,
+            commands,
+            frames,
+            audio,
+            stream,
+            generation: 0,
+            time: 0.0,
+            playing: false,
+            decoded_all: false,
+            next_frame: None,
+            pending_audio: VecDeque::new(),
+            looping: false,
+        })
+    }
+
+    /// Sprite showing the video, to place and scale like any other
+    pub fn sprite(&self) -> &SpriteObject {
+        &self.sprite
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+        if let Some(stream) = &self.stream {
+            stream.play();
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+        if let Some(stream) = &self.stream {
+            stream.pause();
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Check if the last frame was shown, never true while looping
+    pub fn is_finished(&self) -> bool {
+        self.decoded_all && self.next_frame.is_none() && !self.looping
+    }
+
+    /// Playback position, in seconds
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Length of the video in seconds, None if the container doesn't tell
+    pub fn duration(&self) -> Option<f64> {
+        self.duration
+    }
+
+    /// Jump to a position in seconds, the picture changes at the next update
+    pub fn seek(&mut self, seconds: f64) {
+        self.generation += 1;
+        self.time = match self.duration {
+            Some(duration) => seconds.max(0.0).min(duration),
+            None => seconds.max(0.0),
+        };
+        self.decoded_all = false;
+        self.next_frame = None;
+        self.pending_audio.clear();
+        if let Some(stream) = &self.stream {
+            stream.clear();
+        }
+
+        // Make room for the decoder if it waits on a full queue, so it gets to the command
+        while self.frames.try_recv().is_ok() {}
+
+        let _ = self.commands.send(Command::Seek {
+            time: self.time,
+            generation: self.generation,
+        });
+    }
+
+    /// Advance the playback by the frame time, showing the frame due and queuing the soundtrack
+    pub fn update(&mut self, dt: f32, ctx: &mut Context) {
+        if self.playing {
+            self.time += f64::from(dt);
+        }
+
+        // Frames late by a whole update are skipped, only the last one due is uploaded
+        let mut due = None;
+        loop {
+            if let Some(frame) = self.next_frame.take() {
+                if frame.time > self.time {
+                    self.next_frame = Some(frame);
+                    break;
+                }
+                due = Some(frame);
+            }
+
+            match self.frames.try_recv() {
+                Ok(Decoded::Frame(generation, frame)) if generation == self.generation => {
+                    self.next_frame = Some(frame);
+                }
+                Ok(Decoded::End(generation)) if generation == self.generation => {
+                    self.decoded_all = true;
+                }
+                Ok(Decoded::Error(e)) => {
+                    crash::log(&e);
+                    self.decoded_all = true;
+                }
+                // Decoded before the last seek
+                Ok(_) => {}
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.decoded_all = true;
+                    break;
+                }
+            }
+        }
+
+        if let (Some(frame), Some(video)) = (due, ctx.try_video()) {
+            if let Err(e) = video
+                .gl_handler
+                .update_streaming_texture(&self.frame, &frame.pixels)
+            {
+                crash::log(&format!("Couldn't show the video frame: {}", e));
+            }
+        }
+
+        self.queue_audio(ctx);
+
+        if self.looping && self.decoded_all && self.next_frame.is_none() {
+            self.seek(0.0);
+        }
+    }
+
+    fn queue_audio(&mut self, ctx: &Context) {
+        while let Ok((generation, time, samples)) = self.audio.try_recv() {
+            // Chunks decoded before the last seek, or from the key frame before its time
+            if generation == self.generation && time + AUDIO_LEAD >= self.time {
+                self.pending_audio.push_back((time, samples));
+            }
+        }
+
+        let stream = match &self.stream {
+            Some(stream) if self.playing => stream,
+            _ => return,
+        };
+
+        let volume = ctx.audio.music_get_volume();
+        while let Some((time, _)) = self.pending_audio.front() {
+            if *time > self.time + AUDIO_LEAD {
+                break;
+            }
+            if let Some((_, samples)) = self.pending_audio.pop_front() {
+                if let Err(e) = stream.queue(&samples, volume) {
+                    crash::log(&format!("Couldn't queue the video soundtrack: {}", e));
+                }
+            }
+        }
+    }
+}
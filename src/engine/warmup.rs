@@ -0,0 +1,97 @@
+//! Startup warmup driven by a usage profile recorded in previous runs
+//!
+//! Every asset loaded during a run is recorded, the list is saved at the end and used
+//! by the next startup to pre-touch the same files before the first frame.
+
+// standard imports
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Kind of the assets recorded in a profile
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetKind {
+    Texture,
+    Sound,
+    Music,
+}
+
+impl AssetKind {
+    fn name(self) -> &'static str {
+        match self {
+            AssetKind::Texture => "texture",
+            AssetKind::Sound => "sound",
+            AssetKind::Music => "music",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<AssetKind> {
+        match name {
+            "texture" => Some(AssetKind::Texture),
+            "sound" => Some(AssetKind::Sound),
+            "music" => Some(AssetKind::Music),
+            _ => None,
+        }
+    }
+}
+
+/// Assets used during a run, in order of first use
+#[derive(Clone, Debug, Default)]
+pub struct UsageProfile {
+    pub assets: Vec<(AssetKind, PathBuf)>,
+}
+
+impl UsageProfile {
+    /// Read a profile saved with `save`, one `<kind> <path>` entry per line
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+
+        let assets = content
+            .lines()
+            .filter_map(|line| {
+                let (kind, asset_path) = line.split_at(line.find(' ')?);
+                Some((AssetKind::from_name(kind)?, PathBuf::from(asset_path.trim())))
+            })
+            .collect();
+
+        Ok(Self { assets })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let content: String = self
+            .assets
+            .iter()
+            .map(|(kind, asset_path)| format!("{} {}\n", kind.name(), asset_path.display()))
+            .collect();
+
+        fs::write(path, content)
+    }
+
+    /// Read every asset once so the first real load hits the OS file cache, returns the bytes touched
+    pub fn pre_touch(&self) -> usize {
+        self.assets
+            .iter()
+            .filter_map(|(_, asset_path)| fs::read(asset_path).ok())
+            .map(|bytes| bytes.len())
+            .sum()
+    }
+}
+
+static RECORDED: Mutex<Vec<(AssetKind, PathBuf)>> = Mutex::new(Vec::new());
+
+/// Record the use of an asset for the next warmup (called by the loaders)
+pub fn record(kind: AssetKind, path: &Path) {
+    if let Ok(mut recorded) = RECORDED.lock() {
+        if !recorded.iter().any(|(k, p)| *k == kind && p == path) {
+            recorded.push((kind, path.to_path_buf()));
+        }
+    }
+}
+
+/// Profile of the assets used so far in this run
+pub fn recorded_profile() -> UsageProfile {
+    let assets = RECORDED.lock().map(|r| r.clone()).unwrap_or_default();
+
+    UsageProfile { assets }
+}
@@ -0,0 +1,161 @@
+//! Water volumes switching the characters to swimming
+//!
+//! `water_system` checks every `CharacterController` of the world against the `WaterVolume`s,
+//! changes their movement model and reports the transitions as `EngineEvent::Water`, used by
+//! audio and rendering to switch to their underwater effects.
+
+// import the engine modules
+use super::character::{CharacterController, Medium};
+use super::ecs::Entity;
+use super::events::EngineEvent;
+use super::game::Context;
+use super::hitbox::HitRect;
+use super::time::Time;
+
+// other imports
+use cgmath::Vector2;
+
+/// Area filled with water (or any fluid)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WaterVolume {
+    pub rect: HitRect,
+    pub buoyancy: f32,
+    pub drag: f32,
+    pub current: Vector2<f32>,
+}
+
+impl WaterVolume {
+    pub fn new(rect: HitRect) -> Self {
+        Self {
+            rect,
+            buoyancy: 0.9,
+            drag: 3.0,
+            current: Vector2::new(0.0, 0.0),
+        }
+    }
+
+    /// Height of the water surface
+    pub fn surface(&self) -> f32 {
+        self.rect.center.y - self.rect.half_extents.y
+    }
+
+    pub fn contains(&self, point: Vector2<f32>) -> bool {
+        (point.x - self.rect.center.x).abs() <= self.rect.half_extents.x
+            && (point.y - self.rect.center.y).abs() <= self.rect.half_extents.y
+    }
+
+    fn medium(&self) -> Medium {
+        Medium {
+            buoyancy: self.buoyancy,
+            drag: self.drag,
+            current: self.current,
+            surface: self.surface(),
+        }
+    }
+}
+
+/// Water transitions of a character
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaterEvent {
+    /// The character started swimming
+    Entered(Entity),
+    Exited(Entity),
+    /// The head of the character went under the surface
+    Submerged(Entity),
+    Surfaced(Entity),
+}
+
+/// Water state of a character, stored next to its controller
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Submersion {
+    pub in_water: bool,
+    pub head_under: bool,
+}
+
+/// Music muffling while the listener (usually the player) is under water
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnderwaterAudio {
+    pub listener: Entity,
+    pub muffled_volume: i32,
+    /// Music volume restored when surfacing
+    pub normal_volume: i32,
+}
+
+/// ECS system applying the water volumes to the characters (add it with `Schedule::add_system`)
+pub fn water_system(_time: &Time, ctx: &mut Context) {
+    let volumes: Vec<WaterVolume> = match ctx.world.borrow::<WaterVolume>() {
+        Some(volumes) => volumes.iter().map(|(_, v)| *v).collect(),
+        None => Vec::new(),
+    };
+
+    let entities: Vec<Entity> = match ctx.world.borrow::<CharacterController>() {
+        Some(characters) => characters.iter().map(|(e, _)| e).collect(),
+        None => return,
+    };
+
+    let mut events = Vec::new();
+    for entity in entities {
+        let (medium, head_under) = {
+            let character = match ctx.world.get::<CharacterController>(entity) {
+                Some(c) => c,
+                None => continue,
+            };
+
+            let volume = volumes.iter().find(|v| v.contains(character.position));
+            let head = Vector2::new(character.position.x, character.head());
+
+            (
+                volume.map(WaterVolume::medium),
+                volumes.iter().any(|v| v.contains(head)),
+            )
+        };
+
+        let previous = ctx.world.get::<Submersion>(entity).map(|s| *s).unwrap_or_default();
+        let current = Submersion {
+            in_water: medium.is_some(),
+            head_under,
+        };
+
+        if let Some(character) = ctx.world.get_mut::<CharacterController>(entity) {
+            if character.get_medium() != medium {
+                character.set_medium(medium);
+            }
+        }
+
+        if previous != current {
+            if current.in_water != previous.in_water {
+                events.push(if current.in_water {
+                    WaterEvent::Entered(entity)
+                } else {
+                    WaterEvent::Exited(entity)
+                });
+            }
+            if current.head_under != previous.head_under {
+                events.push(if current.head_under {
+                    WaterEvent::Submerged(entity)
+                } else {
+                    WaterEvent::Surfaced(entity)
+                });
+            }
+
+            ctx.world.insert(entity, current);
+        }
+    }
+
+    let underwater_audio = ctx.world.resource::<UnderwaterAudio>().map(|a| *a);
+    for event in events {
+        if let Some(audio) = underwater_audio {
+            match event {
+                WaterEvent::Submerged(e) if e == audio.listener => {
+                    ctx.audio.music_set_volume(audio.muffled_volume)
+                }
+                WaterEvent::Surfaced(e) if e == audio.listener => {
+                    ctx.audio.music_set_volume(audio.normal_volume)
+                }
+                _ => {}
+            }
+        }
+
+        ctx.events.push(EngineEvent::Water(event));
+    }
+}
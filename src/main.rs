@@ -1,6 +1,90 @@
-use pholidota::Engine;
+use std::path::Path;
+
+use pholidota::engine::events::{EngineEvent, WindowEvent};
+use pholidota::engine::{PrimitiveObject, SpriteObject};
+use pholidota::{Context, Engine, EngineConfig, Frame, Game, Time, WindowConfig};
+
+// track the memory usage of every subsystem while developing
+#[cfg(debug_assertions)]
+#[global_allocator]
+static ALLOCATOR: pholidota::engine::memory::TrackingAllocator =
+    pholidota::engine::memory::TrackingAllocator;
+
+/// Small scene used to try the engine features
+#[derive(Default)]
+struct TestingGrounds {
+    _ferris: Option<SpriteObject>,
+    python: Option<SpriteObject>,
+    _rect: Option<PrimitiveObject>,
+    i: f32,
+}
+
+impl Game for TestingGrounds {
+    fn init(&mut self, ctx: &mut Context) {
+        if ctx.audio.music_from_file(Path::new("assets/example.ogg")).is_ok() {
+            println!("Music was loaded fine!");
+            match ctx.audio.music_play(-1) {
+                Ok(_) => println!("Music played fine!"),
+                Err(_) => println!("Music couldn't play..."),
+            }
+        } else {
+            println!("Music couldn't be loaded...");
+        }
+
+        // before, z index wasn't sorted and depth depended on the order in the vector
+        // now the order isn't important but the z index must be specified
+        self._ferris = ctx.video().new_sprite("assets/rust.png", 1).map_err(|e| println!("{}", e)).ok();
+        self.python = ctx.video().new_sprite("assets/python.png", 1).map_err(|e| println!("{}", e)).ok();
+
+        self._rect = ctx.video().new_rectangle((100.0, 100.0).into(), (0.0, 0.0, 1.0, 1.0).into(), (200.0, 200.0).into(), 2).map_err(|e| println!("{}", e)).ok();
+    }
+
+    fn update(&mut self, _time: &Time, ctx: &mut Context) {
+        self.i += 2.0;
+        ctx.camera.stretch.y = 1.0 - (self.i / 1000.0);
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        if let Some(python) = &self.python {
+            let mut sprite = python.get_mut();
+            sprite.global_position.x = self.i;
+            sprite.color = cgmath::Vector4::new(1.0, 1.0, 1.0, 1.0 - (self.i / 255.0));
+        }
+    }
+
+    fn on_event(&mut self, event: &EngineEvent, ctx: &mut Context) {
+        match event {
+            EngineEvent::FrameError(e) => eprintln!("Frame dropped: {}", e),
+            EngineEvent::Window(id, WindowEvent::CloseRequested) => {
+                ctx.close_window(*id);
+            }
+            _ => {}
+        }
+    }
+}
 
 fn main() {
-    let mut main_engine = Engine::new(); // create the Engine instance
-    main_engine.run();                   // run the engine main function
+    let config = EngineConfig::new().window(WindowConfig::new().title("Rust Testing Grounds"));
+
+    // create the Engine instance
+    let mut main_engine = match Engine::new(config) {
+        Ok(engine) => engine,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut game = TestingGrounds::default();
+    // run the game until the window is closed
+    if let Err(e) = main_engine.run(&mut game) {
+        eprintln!("{}", e);
+    }
+
+    // every GPU resource should be released together with the engine
+    drop(game);
+    drop(main_engine);
+    let leaks = pholidota::engine::leaks::report();
+    if !leaks.leaks.is_empty() {
+        eprint!("{}", leaks);
+    }
 }